@@ -0,0 +1,105 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use roxmltree::Document;
+use tokio::process::Command;
+
+/// The suffix of the fragments holding the text extracted from attachments
+pub const SUFFIX: &str = ".text.txt";
+
+/// Tool extracting the text of attached PDFs
+const PDFTOTEXT: &str = "pdftotext";
+
+/// Returns the fragment name of the text extracted from an attachment.
+pub fn text(name: &str) -> String {
+    return format!("{}{}", name, SUFFIX);
+}
+
+/// Returns true if the fragment holds the text extracted from an attachment.
+pub fn is_text(name: &str) -> bool {
+    return name.len() > SUFFIX.len() && name.ends_with(SUFFIX);
+}
+
+/// Collects the text contents of an XML document, i.e. of an e-invoice, leaving out the markup.
+fn strip_markup(xml: &str) -> Result<String> {
+    let document = Document::parse(xml)?;
+
+    return Ok(document.descendants()
+        .filter(|node| node.is_text())
+        .filter_map(|node| node.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"));
+}
+
+/// Extracts the text of a file attached to a document, so it is found by search along the document.
+///
+/// Plain text is taken as is, XML is stripped of its markup and PDFs are run through `pdftotext`. Returns `None` for
+/// all other formats and for files without any text.
+pub async fn extract(path: &Path, mimetype: &str) -> Result<Option<String>> {
+    let text = match mimetype.split(';').next().unwrap_or_default().trim() {
+        "text/plain" => {
+            String::from_utf8_lossy(&tokio::fs::read(path).await?).into_owned()
+        }
+
+        "application/xml" | "text/xml" => {
+            let xml = String::from_utf8_lossy(&tokio::fs::read(path).await?).into_owned();
+            strip_markup(&xml).context("Invalid XML attachment")?
+        }
+
+        "application/pdf" => {
+            debug!("Extracting text of {:?}", path);
+
+            let output = Command::new(PDFTOTEXT)
+                .arg(path)
+                .arg("-")
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .output().await
+                .with_context(|| format!("Error executing {}", PDFTOTEXT))?;
+            if !output.status.success() {
+                bail!("{} failed: {}", PDFTOTEXT, output.status);
+            }
+
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+
+        _ => return Ok(None),
+    };
+
+    return Ok(Some(text).filter(|text| !text.trim().is_empty()));
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_is_text() {
+        assert_that!(is_text(&text("invoice.xml"))).is_true();
+        assert_that!(is_text("invoice.xml")).is_false();
+        assert_that!(is_text(SUFFIX)).is_false();
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let xml = dir.path().join("invoice.xml");
+        tokio::fs::write(&xml, br#"<?xml version="1.0"?><Invoice><ID>RE-4711</ID><Note>Hosting  </Note><Empty/></Invoice>"#).await.unwrap();
+        assert_that!(extract(&xml, "application/xml").await.unwrap()).is_equal_to(Some(String::from("RE-4711\nHosting")));
+
+        let txt = dir.path().join("notes.txt");
+        tokio::fs::write(&txt, b"Signed in person").await.unwrap();
+        assert_that!(extract(&txt, "text/plain; charset=utf-8").await.unwrap()).is_equal_to(Some(String::from("Signed in person")));
+
+        let png = dir.path().join("photo.png");
+        tokio::fs::write(&png, b"\x89PNG\r\n\x1a\n").await.unwrap();
+        assert_that!(extract(&png, "image/png").await.unwrap()).is_none();
+    }
+}
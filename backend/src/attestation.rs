@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use futures::TryStreamExt;
+use log::{error, info, warn};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Attestation as Config;
+use crate::proto::api::attestations::AttestationInfo;
+use crate::repository::{Checksums, Repository};
+use crate::status::Status;
+
+/// Format of the names of the individual manifests
+const FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Manifest of the checksums of all inboxed and archived bundles at a point in time.
+///
+/// The manifest is signed as serialized, so the signature can be verified with any ed25519 implementation against the
+/// file as stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created: DateTime<Utc>,
+
+    /// Hex encoded public key the manifest is signed with
+    pub key: String,
+
+    pub bundles: BTreeMap<String, Checksums>,
+}
+
+/// Returns the directory the signed manifests are stored in.
+pub fn directory(repository: &Repository) -> PathBuf {
+    return repository.path().join("attestations");
+}
+
+/// Loads the signing key from a file, generating a new one if the file does not exist.
+async fn load_key(path: impl AsRef<Path>) -> Result<Keypair> {
+    let path = path.as_ref();
+
+    let secret = match tokio::fs::read_to_string(path).await {
+        Ok(secret) => SecretKey::from_bytes(&hex::decode(secret.trim())?)
+            .map_err(|err| anyhow!("Invalid signing key in {:?}: {}", path, err))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            info!("Generating signing key for attestations: {:?}", path);
+            let secret = Keypair::generate(&mut OsRng).secret;
+            tokio::fs::write(path, hex::encode(secret.as_bytes())).await
+                .with_context(|| format!("Failed to write signing key: {:?}", path))?;
+            secret
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let public = PublicKey::from(&secret);
+
+    return Ok(Keypair { secret, public });
+}
+
+/// Checks the hex encoded signature of a manifest against the key the manifest names.
+pub fn verify(manifest: &[u8], signature: &str) -> Result<bool> {
+    let key = serde_json::from_slice::<Manifest>(manifest)?.key;
+    let key = PublicKey::from_bytes(&hex::decode(key)?)
+        .map_err(|err| anyhow!("Invalid public key: {}", err))?;
+
+    let signature = Signature::try_from(hex::decode(signature)?.as_slice())
+        .map_err(|err| anyhow!("Invalid signature: {}", err))?;
+
+    return Ok(key.verify(manifest, &signature).is_ok());
+}
+
+/// Lists the signed manifests ordered by time.
+pub async fn list(repository: &Repository) -> Result<Vec<AttestationInfo>> {
+    let path = directory(repository);
+
+    let names = match tokio::fs::read_dir(&path).await {
+        Ok(entries) => entries
+            .map_ok(|entry| entry.file_name().to_string_lossy().into_owned())
+            .try_collect::<Vec<_>>().await?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut attestations = Vec::new();
+    for name in names {
+        let name = match name.strip_suffix(".json") {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let created = match NaiveDateTime::parse_from_str(&name, FORMAT) {
+            Ok(created) => Utc.from_utc_datetime(&created),
+            Err(_) => continue,
+        };
+
+        let (manifest, signature) = read(repository, &name).await?;
+
+        attestations.push(AttestationInfo {
+            key: serde_json::from_slice::<Manifest>(&manifest)?.key,
+            digest: hex::encode(Sha256::digest(&manifest)),
+            name,
+            created,
+            signature,
+        });
+    }
+
+    attestations.sort_by_key(|attestation| attestation.created);
+
+    return Ok(attestations);
+}
+
+/// Reads a manifest as signed and its hex encoded signature.
+pub async fn read(repository: &Repository, name: &str) -> Result<(Vec<u8>, String)> {
+    let path = directory(repository);
+
+    let manifest = tokio::fs::read(path.join(format!("{}.json", name))).await?;
+    let signature = tokio::fs::read_to_string(path.join(format!("{}.json.sig", name))).await?;
+
+    return Ok((manifest, signature.trim().to_string()));
+}
+
+/// Periodically signs a manifest of all bundle checksums.
+///
+/// The manifests are kept in the repository, so users can prove later that a document existed unmodified at the time
+/// of a manifest, i.e. for warranty claims. Publishing the digest of each manifest to a third party, like a
+/// timestamping service, proves the manifest has not been created after the fact.
+pub struct Attestor {
+    config: Config,
+
+    key: Keypair,
+
+    repository: Repository,
+
+    client: reqwest::Client,
+
+    status: Arc<Status>,
+}
+
+impl Attestor {
+    pub async fn from_config(config: Config, repository: Repository, status: Arc<Status>) -> Result<Self> {
+        let key = load_key(&config.key).await?;
+        let client = reqwest::Client::builder().build()?;
+
+        tokio::fs::create_dir_all(directory(&repository)).await?;
+
+        return Ok(Self { config, key, repository, client, status });
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval));
+        loop {
+            interval.tick().await;
+
+            match self.attest().await {
+                Ok(attestation) => info!("Signed manifest {} ({})", attestation.name, attestation.digest),
+                Err(err) => {
+                    error!("Failed to sign manifest: {:#}", err);
+                    self.status.failed("attestation", &err);
+                }
+            }
+        }
+    }
+
+    /// Signs and stores a manifest of the current checksums and publishes its digest.
+    pub async fn attest(&self) -> Result<AttestationInfo> {
+        let mut bundles = BTreeMap::new();
+
+        for bundle in self.repository.inbox().list().await? {
+            match bundle.read_checksums().await? {
+                Some(checksums) => { bundles.insert(bundle.id().to_string(), checksums); }
+                None => warn!("Bundle without checksums is not attested: {}", bundle.id()),
+            }
+        }
+
+        for bundle in self.repository.archive().list().await? {
+            match bundle.read_checksums().await? {
+                Some(checksums) => { bundles.insert(bundle.id().to_string(), checksums); }
+                None => warn!("Bundle without checksums is not attested: {}", bundle.id()),
+            }
+        }
+
+        let created = Utc::now();
+        let manifest = serde_json::to_vec_pretty(&Manifest {
+            created,
+            key: hex::encode(self.key.public.as_bytes()),
+            bundles,
+        })?;
+
+        let signature = hex::encode(self.key.sign(&manifest).to_bytes());
+
+        let name = created.format(FORMAT).to_string();
+        let path = directory(&self.repository);
+        tokio::fs::write(path.join(format!("{}.json", name)), &manifest).await?;
+        tokio::fs::write(path.join(format!("{}.json.sig", name)), &signature).await?;
+
+        let attestation = AttestationInfo {
+            name,
+            created,
+            key: hex::encode(self.key.public.as_bytes()),
+            digest: hex::encode(Sha256::digest(&manifest)),
+            signature,
+        };
+
+        if let Some(webhook) = &self.config.webhook {
+            self.client.post(webhook)
+                .json(&attestation)
+                .send().await?
+                .error_for_status()?;
+        }
+
+        return Ok(attestation);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::proto::model::Kind;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_attest() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let staging = repository.stage().await.unwrap();
+        crate::meta::Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        let id = *staging.create().await.unwrap().archive().await.unwrap().id();
+
+        let attestor = Attestor::from_config(Config {
+            key: repository.path().join("attestation.key").to_string_lossy().into_owned(),
+            interval: 60,
+            webhook: None,
+        }, repository.clone(), Arc::new(Status::new())).await.unwrap();
+
+        let attestation = attestor.attest().await.unwrap();
+
+        let (manifest, signature) = read(&repository, &attestation.name).await.unwrap();
+        assert_that!(signature).is_equal_to(&attestation.signature);
+        assert_that!(verify(&manifest, &signature).unwrap()).is_true();
+        assert_that!(serde_json::from_slice::<Manifest>(&manifest).unwrap().bundles.contains_key(&id.to_string())).is_true();
+
+        // Any change to the manifest invalidates the signature
+        let mut forged = manifest.clone();
+        forged.extend_from_slice(b"\n");
+        assert_that!(verify(&forged, &signature).unwrap()).is_false();
+
+        assert_that!(list(&repository).await.unwrap().len()).is_equal_to(1);
+
+        // The signing key is reused
+        let attestor = Attestor::from_config(attestor.config.clone(), repository.clone(), Arc::new(Status::new())).await.unwrap();
+        assert_that!(attestor.attest().await.unwrap().key).is_equal_to(attestation.key);
+    }
+}
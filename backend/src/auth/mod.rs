@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::config::{Auth, Defaults};
+use crate::proto::api::auth::Scope;
+use crate::utils::StrExt;
+
+pub use self::sessions::{Device, Session, Sessions};
+pub use self::throttle::Throttle;
+pub use self::totp::{Pending, TwoFactor};
+pub use self::webauthn::{Passkey, Passkeys};
+
+mod sessions;
+mod throttle;
+mod totp;
+mod webauthn;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    pub exp: u64,
+    pub sub: String,
+
+    #[serde(default)]
+    pub sid: Option<String>,
+}
+
+impl Claims {
+    pub fn new(timeout: Duration, subject: String, session: Option<String>) -> Self {
+        return Self {
+            exp: (SystemTime::now() + timeout)
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("System time before epoch")
+                .as_secs(),
+            sub: subject,
+            sid: session,
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    subject: String,
+
+    session: Option<String>,
+
+    /// The scopes granted to the token, unrestricted if unset
+    scopes: Option<HashSet<Scope>>,
+}
+
+impl Token {
+    /// The name of the authenticated user or API key.
+    pub fn subject(&self) -> &str { &self.subject }
+
+    /// The ID of the login session, if authenticated by a session token.
+    pub fn session(&self) -> Option<&str> { self.session.as_deref() }
+
+    /// Checks if the token grants the given scope.
+    pub fn permits(&self, scope: Scope) -> bool {
+        return self.scopes.as_ref().map_or(true, |scopes| {
+            scopes.contains(&Scope::Admin) || scopes.contains(&scope)
+        });
+    }
+}
+
+/// An API token issued for scanners and scripts.
+///
+/// Only a hash of the secret is persisted - the token itself is handed out once on creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub created: DateTime<Utc>,
+
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+
+    /// Tokens issued before scopes were introduced are unrestricted
+    #[serde(default = "ApiToken::default_scopes")]
+    pub scopes: HashSet<Scope>,
+
+    hash: String,
+}
+
+impl ApiToken {
+    /// Prefix used to distinguish API tokens from session tokens
+    pub const PREFIX: &'static str = "adt_";
+
+    fn default_scopes() -> HashSet<Scope> { std::iter::once(Scope::Admin).collect() }
+
+    fn hash(secret: &str) -> String {
+        return hex::encode(Sha256::digest(secret.as_bytes()));
+    }
+}
+
+/// Persistent store of issued API tokens.
+struct ApiTokens {
+    path: PathBuf,
+    tokens: RwLock<HashMap<String, ApiToken>>,
+}
+
+impl ApiTokens {
+    async fn load(path: PathBuf) -> Result<Self> {
+        let tokens = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice::<Vec<ApiToken>>(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let tokens = tokens.into_iter()
+            .map(|token| (token.id.clone(), token))
+            .collect();
+
+        return Ok(Self { path, tokens: RwLock::new(tokens) });
+    }
+
+    async fn save(&self, tokens: &HashMap<String, ApiToken>) -> Result<()> {
+        let tokens = tokens.values().collect::<Vec<_>>();
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(&tokens)?).await?;
+        return Ok(());
+    }
+}
+
+pub struct Authenticator {
+    username: String,
+    passhash: String,
+
+    jwt_decoding_key: DecodingKey<'static>,
+    jwt_encoding_key: EncodingKey,
+
+    jwt_token_duration: Duration,
+
+    api_keys: HashMap<String, String>,
+
+    /// API key credentials verified before, as verifying a hash on each request is too expensive
+    verified: Mutex<HashSet<String>>,
+
+    api_tokens: ApiTokens,
+
+    defaults: HashMap<String, Defaults>,
+
+    two_factor: TwoFactor,
+
+    passkeys: Passkeys,
+
+    sessions: Sessions,
+
+    throttle: Throttle,
+}
+
+/// Outcome of a login attempt.
+pub enum Login {
+    Success(Token),
+
+    /// The password was correct, but a second factor is required, along with a challenge for the passkeys if any
+    CodeRequired(Option<serde_json::Value>),
+
+    Failed,
+
+    /// Too many failed attempts, login is locked for the given time
+    Locked(Duration),
+}
+
+impl Authenticator {
+    /// Creates the authenticator, storing issued tokens and enrollments in the given directory.
+    pub async fn from_config(config: Auth, path: PathBuf) -> Result<Self> {
+        // TODO: Add some sanity checks (empty values, ...)
+
+        let jwt_token_duration = Duration::from_secs(60 * 60); // TODO: Make configurable
+
+        Ok(Self {
+            username: config.username,
+            passhash: config.passhash,
+
+            jwt_decoding_key: DecodingKey::from_secret(config.secret.as_bytes()).into_static(),
+            jwt_encoding_key: EncodingKey::from_secret(config.secret.as_bytes()),
+
+            jwt_token_duration,
+
+            api_keys: config.api_keys,
+
+            verified: Mutex::new(HashSet::new()),
+
+            api_tokens: ApiTokens::load(path.join("tokens.json")).await?,
+
+            defaults: config.defaults,
+
+            two_factor: TwoFactor::load(path.join("twofactor.json")).await?,
+
+            passkeys: Passkeys::load(path.join("passkeys.json"), config.webauthn).await?,
+
+            sessions: Sessions::load(path.join("sessions.json"), jwt_token_duration).await?,
+
+            throttle: Throttle::new(config.max_attempts, Duration::from_secs(config.lockout)),
+        })
+    }
+
+    /// Returns the metadata applied to documents uploaded with the given token.
+    ///
+    /// Defaults are configured by the name of API keys and tokens, so documents uploaded in a login session get none.
+    pub fn defaults(&self, token: &Token) -> Defaults {
+        if token.session().is_some() {
+            return Defaults::default();
+        }
+
+        return self.defaults.get(token.subject()).cloned().unwrap_or_default();
+    }
+
+    pub async fn verify_token(&self, bearer: &str) -> Result<Token> {
+        let token = jsonwebtoken::decode::<Claims>(
+            bearer,
+            &self.jwt_decoding_key,
+            &jsonwebtoken::Validation::default(),
+        )?;
+
+        // Only tokens of sessions which have not been revoked are accepted
+        let session = token.claims.sid
+            .ok_or_else(|| anyhow::anyhow!("Token without session"))?;
+        if !self.sessions.touch(&session).await {
+            anyhow::bail!("Session expired or revoked: {}", session);
+        }
+
+        Ok(Token { subject: token.claims.sub, session: Some(session), scopes: None })
+    }
+
+    pub async fn sign_token(&self, token: &Token) -> Result<String> {
+        let bearer = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &Claims::new(self.jwt_token_duration, token.subject.clone(), token.session.clone()),
+            &self.jwt_encoding_key,
+        )?;
+
+        Ok(bearer)
+    }
+
+    pub async fn login(&self, password: &str, code: Option<&str>, assertion: Option<&serde_json::Value>, device: Device) -> Result<Login> {
+        // TODO: Verify passhash is valid on config load
+
+        // Attempts are throttled per user and per remote address
+        let keys = std::iter::once(format!("user:{}", self.username))
+            .chain(device.address.as_ref().map(|address| format!("address:{}", address)))
+            .collect::<Vec<_>>();
+
+        if let Some(remaining) = keys.iter().filter_map(|key| self.throttle.locked(key)).max() {
+            return Ok(Login::Locked(remaining));
+        }
+
+        let failed = || {
+            keys.iter().for_each(|key| self.throttle.failed(key));
+            return Login::Failed;
+        };
+
+        if !bcrypt::verify(password.as_bytes(), &self.passhash).unwrap_or(false) {
+            return Ok(failed());
+        }
+
+        // Passkeys are accepted instead of a code, as they can only be registered with two-factor authentication enabled
+        if self.two_factor.is_enabled(&self.username).await {
+            let verified = match (code, assertion) {
+                (_, Some(assertion)) => self.passkeys.verify(&self.username, assertion).await?,
+                (Some(code), None) => self.two_factor.verify(&self.username, code).await?,
+                (None, None) => return Ok(Login::CodeRequired(self.passkeys.challenge(&self.username).await?)),
+            };
+
+            if !verified {
+                return Ok(failed());
+            }
+        }
+
+        keys.iter().for_each(|key| self.throttle.succeeded(key));
+
+        let session = self.sessions.create(self.username.clone(), device).await?;
+
+        return Ok(Login::Success(Token { subject: self.username.clone(), session: Some(session.id), scopes: None }));
+    }
+
+    pub fn two_factor(&self) -> &TwoFactor { &self.two_factor }
+
+    pub fn passkeys(&self) -> &Passkeys { &self.passkeys }
+
+    pub fn sessions(&self) -> &Sessions { &self.sessions }
+
+    /// Authorizes a request by the value of its `Authorization` header.
+    ///
+    /// Accepts API tokens and session tokens as bearer and API keys by basic authorization. This is shared by all
+    /// protocols served, so API tokens are limited to their scopes and carry their defaults everywhere.
+    pub async fn authorize(&self, authorization: &str) -> Option<Token> {
+        let (kind, payload) = authorization.split2(' ')?;
+
+        return match kind {
+            "Bearer" if payload.starts_with(ApiToken::PREFIX) => self.verify_api_token(payload).await,
+            "Bearer" => self.verify_token(payload).await.ok(),
+            "Basic" => {
+                let payload = String::from_utf8(base64::decode(payload).ok()?).ok()?;
+                let (username, password) = payload.split2(':')?;
+
+                self.verify_key(username, password).await
+            }
+            _ => None,
+        };
+    }
+
+    pub async fn verify_key(&self, username: &str, password: &str) -> Option<Token> {
+        let hash = self.api_keys.get(username)?;
+
+        let key = hex::encode(Sha256::digest(format!("{}:{}", username, password).as_bytes()));
+        let verified = self.verified.lock().expect("Credentials poisoned").contains(&key);
+
+        if verified || bcrypt::verify(password, hash).ok()? {
+            self.verified.lock().expect("Credentials poisoned").insert(key);
+            return Some(Token { subject: username.to_string(), session: None, scopes: None });
+        } else {
+            return None;
+        }
+    }
+
+    pub async fn verify_api_token(&self, token: &str) -> Option<Token> {
+        let (id, secret) = token.strip_prefix(ApiToken::PREFIX)?.split2('_')?;
+
+        let mut tokens = self.api_tokens.tokens.write().await;
+        let token = tokens.get_mut(id)?;
+
+        if token.hash == ApiToken::hash(secret) {
+            // Only kept in memory until the tokens are saved the next time
+            token.last_used = Some(Utc::now());
+
+            return Some(Token { subject: token.name.clone(), session: None, scopes: Some(token.scopes.clone()) });
+        } else {
+            return None;
+        }
+    }
+
+    pub async fn api_tokens(&self) -> Vec<ApiToken> {
+        let mut tokens = self.api_tokens.tokens.read().await.values().cloned().collect::<Vec<_>>();
+        tokens.sort_by_key(|token| token.created);
+        return tokens;
+    }
+
+    /// Issues a new API token and returns it together with the token string.
+    pub async fn create_api_token(&self, name: String, scopes: HashSet<Scope>) -> Result<(ApiToken, String)> {
+        let mut rng = rand::thread_rng();
+        let id = hex::encode(rng.gen::<[u8; 8]>());
+        let secret = hex::encode(rng.gen::<[u8; 32]>());
+
+        let token = ApiToken {
+            id: id.clone(),
+            name,
+            created: Utc::now(),
+            last_used: None,
+            scopes,
+            hash: ApiToken::hash(&secret),
+        };
+
+        let mut tokens = self.api_tokens.tokens.write().await;
+        tokens.insert(id.clone(), token.clone());
+        self.api_tokens.save(&tokens).await?;
+
+        return Ok((token, format!("{}{}_{}", ApiToken::PREFIX, id, secret)));
+    }
+
+    /// Revokes an API token and returns whether it existed.
+    pub async fn revoke_api_token(&self, id: &str) -> Result<bool> {
+        let mut tokens = self.api_tokens.tokens.write().await;
+        if tokens.remove(id).is_none() {
+            return Ok(false);
+        }
+
+        self.api_tokens.save(&tokens).await?;
+
+        return Ok(true);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+/// Information about the device a session was started from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Device {
+    pub user_agent: Option<String>,
+    pub address: Option<String>,
+}
+
+/// A login session of the UI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub subject: String,
+
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+
+    pub device: Device,
+}
+
+/// Server-side record of all active sessions.
+///
+/// Session tokens are only accepted as long as the session is known, which allows to revoke them remotely. Sessions
+/// expire if they have not been used for longer than the token lifetime.
+pub struct Sessions {
+    path: PathBuf,
+    timeout: Duration,
+
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    pub async fn load(path: PathBuf, timeout: std::time::Duration) -> Result<Self> {
+        let sessions = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        return Ok(Self {
+            path,
+            timeout: Duration::from_std(timeout)?,
+            sessions: RwLock::new(sessions),
+        });
+    }
+
+    async fn save(&self, sessions: &HashMap<String, Session>) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(sessions)?).await?;
+        return Ok(());
+    }
+
+    fn expire(&self, sessions: &mut HashMap<String, Session>) {
+        let deadline = Utc::now() - self.timeout;
+        sessions.retain(|_, session| session.last_seen > deadline);
+    }
+
+    pub async fn create(&self, subject: String, device: Device) -> Result<Session> {
+        let now = Utc::now();
+        let session = Session {
+            id: hex::encode(rand::thread_rng().gen::<[u8; 16]>()),
+            subject,
+            created: now,
+            last_seen: now,
+            device,
+        };
+
+        let mut sessions = self.sessions.write().await;
+        self.expire(&mut sessions);
+        sessions.insert(session.id.clone(), session.clone());
+        self.save(&sessions).await?;
+
+        return Ok(session);
+    }
+
+    /// Records activity of a session and returns whether it is still active.
+    pub async fn touch(&self, id: &str) -> bool {
+        let mut sessions = self.sessions.write().await;
+        self.expire(&mut sessions);
+
+        return match sessions.get_mut(id) {
+            Some(session) => {
+                session.last_seen = Utc::now();
+                true
+            }
+            None => false,
+        };
+    }
+
+    /// Lists the active sessions of a user.
+    pub async fn list(&self, subject: &str) -> Vec<Session> {
+        let mut sessions = self.sessions.write().await;
+        self.expire(&mut sessions);
+
+        let mut sessions = sessions.values()
+            .filter(|session| session.subject == subject)
+            .cloned()
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| session.created);
+
+        return sessions;
+    }
+
+    /// Revokes a session of a user and returns whether it existed.
+    pub async fn revoke(&self, subject: &str, id: &str) -> Result<bool> {
+        let mut sessions = self.sessions.write().await;
+        if sessions.get(id).map_or(true, |session| session.subject != subject) {
+            return Ok(false);
+        }
+
+        sessions.remove(id);
+        self.save(&sessions).await?;
+
+        return Ok(true);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// Throttles login attempts by locking keys (i.e. the user or the remote address) out for some time after too many
+/// consecutive failures.
+pub struct Throttle {
+    attempts: u32,
+    lockout: Duration,
+
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl Throttle {
+    pub fn new(attempts: u32, lockout: Duration) -> Self {
+        return Self {
+            attempts,
+            lockout,
+            failures: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Returns the time remaining until the key is unlocked or `None` if the key is not locked.
+    pub fn locked(&self, key: &str) -> Option<Duration> {
+        let failures = self.failures.lock().expect("Throttle poisoned");
+        let failures = failures.get(key)?;
+
+        if failures.count < self.attempts {
+            return None;
+        }
+
+        return self.lockout.checked_sub(failures.last.elapsed())
+            .filter(|remaining| *remaining > Duration::from_secs(0));
+    }
+
+    /// Records a failed attempt for the key.
+    pub fn failed(&self, key: &str) {
+        let mut failures = self.failures.lock().expect("Throttle poisoned");
+
+        // Failures older than the lockout period are forgotten
+        let lockout = self.lockout;
+        failures.retain(|_, failures| failures.last.elapsed() < lockout);
+
+        let failures = failures.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            last: Instant::now(),
+        });
+        failures.count += 1;
+        failures.last = Instant::now();
+    }
+
+    /// Resets the failures of the key after a successful attempt.
+    pub fn succeeded(&self, key: &str) {
+        self.failures.lock().expect("Throttle poisoned").remove(key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_lockout() {
+        let throttle = Throttle::new(3, Duration::from_secs(60));
+
+        throttle.failed("admin");
+        throttle.failed("admin");
+        assert_that!(throttle.locked("admin")).is_none();
+
+        throttle.failed("admin");
+        assert_that!(throttle.locked("admin")).is_some();
+        assert_that!(throttle.locked("other")).is_none();
+
+        throttle.succeeded("admin");
+        assert_that!(throttle.locked("admin")).is_none();
+    }
+
+    #[test]
+    fn test_expired() {
+        let throttle = Throttle::new(1, Duration::from_millis(0));
+
+        throttle.failed("admin");
+        assert_that!(throttle.locked("admin")).is_none();
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+/// Length of a time step in seconds
+const STEP: u64 = 30;
+
+/// Number of digits of a generated code
+const DIGITS: u32 = 6;
+
+/// Number of recovery codes issued on enrollment
+const RECOVERY_CODES: usize = 10;
+
+/// Calculates the HOTP value for a counter (RFC 4226).
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_varkey(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+
+    return value % 10u32.pow(DIGITS);
+}
+
+/// Verifies a TOTP code (RFC 6238) allowing for one step of clock drift.
+///
+/// Returns the time step the code has been generated for, if valid. Only steps after the given last accepted one are
+/// considered, so a code can not be replayed while it is still within the window.
+fn verify(secret: &[u8], code: &str, time: u64, last: Option<u64>) -> Option<u64> {
+    let code = code.trim().parse::<u32>().ok()?;
+
+    let counter = time / STEP;
+    return [counter.saturating_sub(1), counter, counter + 1].iter()
+        .copied()
+        .filter(|&counter| last.map_or(true, |last| counter > last))
+        .find(|&counter| hotp(secret, counter) == code);
+}
+
+fn now() -> u64 {
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("System time before epoch")
+        .as_secs();
+}
+
+fn hash(code: &str) -> String {
+    return hex::encode(Sha256::digest(code.as_bytes()));
+}
+
+/// Second factor enrolled by a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Enrollment {
+    /// The base32 encoded shared secret
+    secret: String,
+
+    /// Enrollment is only enforced after the user has proven to own the secret
+    confirmed: bool,
+
+    /// Hashes of the unused recovery codes
+    recovery: Vec<String>,
+
+    /// The time step of the last accepted code
+    #[serde(default)]
+    last_step: Option<u64>,
+}
+
+/// A pending enrollment to be set up in an authenticator app.
+pub struct Pending {
+    pub secret: String,
+    pub uri: String,
+}
+
+/// Persistent store of TOTP enrollments by user.
+pub struct TwoFactor {
+    path: PathBuf,
+    enrollments: Mutex<HashMap<String, Enrollment>>,
+}
+
+impl TwoFactor {
+    const ISSUER: &'static str = "Adacta";
+
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let enrollments = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        return Ok(Self { path, enrollments: Mutex::new(enrollments) });
+    }
+
+    async fn save(&self, enrollments: &HashMap<String, Enrollment>) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(enrollments)?).await?;
+        return Ok(());
+    }
+
+    /// Checks if the user must provide a second factor on login.
+    pub async fn is_enabled(&self, user: &str) -> bool {
+        return self.enrollments.lock().await.get(user)
+            .map_or(false, |enrollment| enrollment.confirmed);
+    }
+
+    /// Starts a new enrollment, replacing any unconfirmed one.
+    pub async fn enroll(&self, user: &str) -> Result<Pending> {
+        let mut enrollments = self.enrollments.lock().await;
+        if enrollments.get(user).map_or(false, |enrollment| enrollment.confirmed) {
+            return Err(anyhow!("Two-factor authentication already enabled"));
+        }
+
+        let secret = base32::encode(base32::Alphabet::RFC4648 { padding: false },
+                                    &rand::thread_rng().gen::<[u8; 20]>());
+
+        enrollments.insert(user.to_string(), Enrollment {
+            secret: secret.clone(),
+            confirmed: false,
+            recovery: Vec::new(),
+            last_step: None,
+        });
+        self.save(&enrollments).await?;
+
+        let uri = format!("otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}",
+                          issuer = Self::ISSUER, user = user, secret = secret);
+
+        return Ok(Pending { secret, uri });
+    }
+
+    /// Confirms a pending enrollment and returns the recovery codes if the code is valid.
+    pub async fn confirm(&self, user: &str, code: &str) -> Result<Option<Vec<String>>> {
+        let mut enrollments = self.enrollments.lock().await;
+        let enrollment = enrollments.get_mut(user)
+            .ok_or_else(|| anyhow!("No pending enrollment"))?;
+
+        let step = match verify(&Self::decode(&enrollment.secret)?, code, now(), enrollment.last_step) {
+            Some(step) => step,
+            None => return Ok(None),
+        };
+
+        let codes = (0..RECOVERY_CODES)
+            .map(|_| hex::encode(rand::thread_rng().gen::<[u8; 5]>()))
+            .collect::<Vec<_>>();
+
+        enrollment.confirmed = true;
+        enrollment.last_step = Some(step);
+        enrollment.recovery = codes.iter().map(|code| hash(code)).collect();
+        self.save(&enrollments).await?;
+
+        return Ok(Some(codes));
+    }
+
+    /// Verifies a TOTP or recovery code. Each code can only be used once.
+    pub async fn verify(&self, user: &str, code: &str) -> Result<bool> {
+        let mut enrollments = self.enrollments.lock().await;
+        let enrollment = match enrollments.get_mut(user) {
+            Some(enrollment) if enrollment.confirmed => enrollment,
+            _ => return Ok(false),
+        };
+
+        if let Some(step) = verify(&Self::decode(&enrollment.secret)?, code, now(), enrollment.last_step) {
+            enrollment.last_step = Some(step);
+            self.save(&enrollments).await?;
+            return Ok(true);
+        }
+
+        let hashed = hash(code.trim());
+        if let Some(index) = enrollment.recovery.iter().position(|recovery| *recovery == hashed) {
+            enrollment.recovery.remove(index);
+            self.save(&enrollments).await?;
+            return Ok(true);
+        }
+
+        return Ok(false);
+    }
+
+    /// Removes the enrollment of the user.
+    pub async fn disable(&self, user: &str) -> Result<()> {
+        let mut enrollments = self.enrollments.lock().await;
+        enrollments.remove(user);
+        return self.save(&enrollments).await;
+    }
+
+    fn decode(secret: &str) -> Result<Vec<u8>> {
+        return base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret)
+            .ok_or_else(|| anyhow!("Invalid TOTP secret"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // Test vectors from RFC 6238 for SHA1, truncated to 6 digits
+        let secret = b"12345678901234567890";
+        assert_that!(hotp(secret, 59 / STEP)).is_equal_to(287082);
+        assert_that!(hotp(secret, 1111111109 / STEP)).is_equal_to(81804);
+        assert_that!(hotp(secret, 1234567890 / STEP)).is_equal_to(5924);
+
+        assert_that!(verify(secret, "081804", 1111111109, None)).is_equal_to(Some(1111111109 / STEP));
+        assert_that!(verify(secret, "081804", 1111111109 + STEP, None)).is_equal_to(Some(1111111109 / STEP));
+        assert_that!(verify(secret, "081804", 1111111109 + 3 * STEP, None)).is_none();
+    }
+
+    #[test]
+    fn test_replay() {
+        let secret = b"12345678901234567890";
+        let step = 1111111109 / STEP;
+
+        assert_that!(verify(secret, "081804", 1111111109, Some(step - 1))).is_equal_to(Some(step));
+        assert_that!(verify(secret, "081804", 1111111109, Some(step))).is_none();
+        assert_that!(verify(secret, "081804", 1111111109 + STEP, Some(step))).is_none();
+    }
+
+    #[tokio::test]
+    async fn test_verify_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let two_factor = TwoFactor::load(dir.path().join("twofactor.json")).await.unwrap();
+
+        let pending = two_factor.enroll("user").await.unwrap();
+        let secret = TwoFactor::decode(&pending.secret).unwrap();
+
+        // Confirm with the code of the previous step to leave the current one for the login
+        let previous = format!("{:06}", hotp(&secret, now() / STEP - 1));
+        assert_that!(two_factor.confirm("user", &previous).await.unwrap()).is_some();
+
+        let code = format!("{:06}", hotp(&secret, now() / STEP));
+        assert_that!(two_factor.verify("user", &code).await.unwrap()).is_true();
+        assert_that!(two_factor.verify("user", &code).await.unwrap()).is_false();
+        assert_that!(two_factor.verify("user", &previous).await.unwrap()).is_false();
+    }
+}
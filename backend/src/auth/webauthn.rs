@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use webauthn_rs::{AuthenticationState, RegistrationState, Webauthn};
+use webauthn_rs::ephemeral::WebauthnEphemeralConfig;
+use webauthn_rs::proto::{Credential, CredentialID, PublicKeyCredential, RegisterPublicKeyCredential};
+
+use crate::config::Webauthn as Config;
+
+/// A passkey registered by a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Passkey {
+    pub name: String,
+    pub created: DateTime<Utc>,
+
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+
+    credential: Credential,
+}
+
+impl Passkey {
+    /// The ID of the credential, encoded like by the browsers.
+    pub fn id(&self) -> String {
+        return base64::encode_config(&self.credential.cred_id, base64::URL_SAFE_NO_PAD);
+    }
+}
+
+/// Persistent store of passkeys (WebAuthn) by user.
+///
+/// Passkeys are accepted instead of a TOTP code, so the recovery codes of the enrollment stay available if a passkey
+/// gets lost. Pending ceremonies are kept in memory only, a new one replacing any pending one of the same user.
+pub struct Passkeys {
+    path: PathBuf,
+
+    /// The relying party, passkeys can not be registered if not configured
+    webauthn: Option<Webauthn<WebauthnEphemeralConfig>>,
+
+    passkeys: Mutex<HashMap<String, Vec<Passkey>>>,
+
+    registrations: Mutex<HashMap<String, RegistrationState>>,
+    authentications: Mutex<HashMap<String, AuthenticationState>>,
+}
+
+impl Passkeys {
+    const NAME: &'static str = "Adacta";
+
+    pub async fn load(path: PathBuf, config: Option<Config>) -> Result<Self> {
+        let passkeys = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let webauthn = match config {
+            Some(config) => {
+                let id = match config.id {
+                    Some(id) => id,
+                    None => url::Url::parse(&config.origin)?.host_str()
+                        .ok_or_else(|| anyhow!("Origin without host: {}", config.origin))?
+                        .to_string(),
+                };
+
+                Some(Webauthn::new(WebauthnEphemeralConfig::new(Self::NAME, &config.origin, &id, None)))
+            }
+            None => None,
+        };
+
+        return Ok(Self {
+            path,
+            webauthn,
+            passkeys: Mutex::new(passkeys),
+            registrations: Mutex::new(HashMap::new()),
+            authentications: Mutex::new(HashMap::new()),
+        });
+    }
+
+    async fn save(&self, passkeys: &HashMap<String, Vec<Passkey>>) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(passkeys)?).await?;
+        return Ok(());
+    }
+
+    /// Checks if a relying party is configured.
+    pub fn is_configured(&self) -> bool {
+        return self.webauthn.is_some();
+    }
+
+    /// Returns the passkeys registered by the user.
+    pub async fn list(&self, user: &str) -> Vec<Passkey> {
+        return self.passkeys.lock().await.get(user).cloned().unwrap_or_default();
+    }
+
+    /// Starts registering a passkey and returns the options to create the credential with.
+    pub async fn register(&self, user: &str) -> Result<serde_json::Value> {
+        let webauthn = self.webauthn.as_ref()
+            .ok_or_else(|| anyhow!("Passkeys not configured"))?;
+
+        let (options, state) = webauthn.generate_challenge_register(user, false)
+            .map_err(|err| anyhow!("Failed to create registration challenge: {:?}", err))?;
+
+        self.registrations.lock().await.insert(user.to_string(), state);
+
+        return Ok(serde_json::to_value(options)?);
+    }
+
+    /// Completes the pending registration, returns `false` if the credential is not valid.
+    pub async fn confirm(&self, user: &str, name: String, credential: serde_json::Value) -> Result<bool> {
+        let webauthn = self.webauthn.as_ref()
+            .ok_or_else(|| anyhow!("Passkeys not configured"))?;
+
+        let state = self.registrations.lock().await.remove(user)
+            .ok_or_else(|| anyhow!("No pending registration"))?;
+
+        let credential = match serde_json::from_value::<RegisterPublicKeyCredential>(credential) {
+            Ok(credential) => credential,
+            Err(_) => return Ok(false),
+        };
+
+        let mut passkeys = self.passkeys.lock().await;
+
+        // A credential can only be registered once, no matter by which user
+        let registered = |id: &CredentialID| Ok(passkeys.values().flatten()
+            .any(|passkey| passkey.credential.cred_id == *id));
+
+        let credential = match webauthn.register_credential(&credential, &state, registered) {
+            Ok((credential, _)) => credential,
+            Err(err) => {
+                debug!("Passkey registration for {} rejected: {:?}", user, err);
+                return Ok(false);
+            }
+        };
+
+        passkeys.entry(user.to_string()).or_default().push(Passkey {
+            name,
+            created: Utc::now(),
+            last_used: None,
+            credential,
+        });
+        self.save(&passkeys).await?;
+
+        return Ok(true);
+    }
+
+    /// Creates a challenge to be signed by one of the passkeys of the user, if any is registered.
+    pub async fn challenge(&self, user: &str) -> Result<Option<serde_json::Value>> {
+        let webauthn = match self.webauthn.as_ref() {
+            Some(webauthn) => webauthn,
+            None => return Ok(None),
+        };
+
+        let credentials = self.list(user).await.into_iter()
+            .map(|passkey| passkey.credential)
+            .collect::<Vec<_>>();
+        if credentials.is_empty() {
+            return Ok(None);
+        }
+
+        let (challenge, state) = webauthn.generate_challenge_authenticate(credentials)
+            .map_err(|err| anyhow!("Failed to create authentication challenge: {:?}", err))?;
+
+        self.authentications.lock().await.insert(user.to_string(), state);
+
+        return Ok(Some(serde_json::to_value(challenge)?));
+    }
+
+    /// Verifies an assertion signing the pending challenge. Each challenge can only be used once.
+    pub async fn verify(&self, user: &str, assertion: &serde_json::Value) -> Result<bool> {
+        let webauthn = match self.webauthn.as_ref() {
+            Some(webauthn) => webauthn,
+            None => return Ok(false),
+        };
+
+        let state = match self.authentications.lock().await.remove(user) {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        let assertion = match serde_json::from_value::<PublicKeyCredential>(assertion.clone()) {
+            Ok(assertion) => assertion,
+            Err(_) => return Ok(false),
+        };
+
+        let (id, data) = match webauthn.authenticate_credential(&assertion, &state) {
+            Ok(result) => result,
+            Err(err) => {
+                debug!("Passkey assertion for {} rejected: {:?}", user, err);
+                return Ok(false);
+            }
+        };
+
+        let mut passkeys = self.passkeys.lock().await;
+        let passkey = passkeys.get_mut(user)
+            .and_then(|passkeys| passkeys.iter_mut().find(|passkey| passkey.credential.cred_id.as_slice() == id.as_slice()))
+            .ok_or_else(|| anyhow!("Passkey vanished"))?;
+
+        // The signature counter is kept to detect cloned authenticators on the next use
+        passkey.credential.counter = data.counter;
+        passkey.last_used = Some(Utc::now());
+        self.save(&passkeys).await?;
+
+        return Ok(true);
+    }
+
+    /// Removes a passkey of the user, returns `false` if not found.
+    pub async fn remove(&self, user: &str, id: &str) -> Result<bool> {
+        let mut passkeys = self.passkeys.lock().await;
+        let registered = match passkeys.get_mut(user) {
+            Some(registered) => registered,
+            None => return Ok(false),
+        };
+
+        let count = registered.len();
+        registered.retain(|passkey| passkey.id() != id);
+        if registered.len() == count {
+            return Ok(false);
+        }
+
+        self.save(&passkeys).await?;
+        return Ok(true);
+    }
+
+    /// Removes all passkeys of the user.
+    pub async fn clear(&self, user: &str) -> Result<()> {
+        let mut passkeys = self.passkeys.lock().await;
+        passkeys.remove(user);
+        return self.save(&passkeys).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn config() -> Config {
+        return Config {
+            origin: String::from("https://adacta.example.com"),
+            id: None,
+        };
+    }
+
+    #[tokio::test]
+    async fn test_not_configured() {
+        let dir = tempfile::tempdir().unwrap();
+        let passkeys = Passkeys::load(dir.path().join("passkeys.json"), None).await.unwrap();
+
+        assert_that!(passkeys.is_configured()).is_false();
+        assert_that!(passkeys.register("user").await).is_err();
+        assert_that!(passkeys.challenge("user").await.unwrap()).is_none();
+        assert_that!(passkeys.verify("user", &serde_json::json!({})).await.unwrap()).is_false();
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let dir = tempfile::tempdir().unwrap();
+        let passkeys = Passkeys::load(dir.path().join("passkeys.json"), Some(config())).await.unwrap();
+
+        let options = passkeys.register("user").await.unwrap();
+        assert_that!(options["publicKey"]["rp"]["id"].as_str()).is_equal_to(Some("adacta.example.com"));
+
+        // The pending registration is consumed by an invalid credential
+        assert_that!(passkeys.confirm("user", String::from("key"), serde_json::json!({})).await.unwrap()).is_false();
+        assert_that!(passkeys.confirm("user", String::from("key"), serde_json::json!({})).await).is_err();
+
+        assert_that!(passkeys.list("user").await).is_empty();
+    }
+
+    #[tokio::test]
+    async fn test_challenge_without_passkeys() {
+        let dir = tempfile::tempdir().unwrap();
+        let passkeys = Passkeys::load(dir.path().join("passkeys.json"), Some(config())).await.unwrap();
+
+        assert_that!(passkeys.challenge("user").await.unwrap()).is_none();
+        assert_that!(passkeys.verify("user", &serde_json::json!({})).await.unwrap()).is_false();
+        assert_that!(passkeys.remove("user", "unknown").await.unwrap()).is_false();
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Component, Path};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use s3::bucket::Bucket;
+use s3::creds::Credentials;
+use s3::region::Region;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::BackupS3 as Config;
+use crate::repository::Repository;
+
+/// A backup of the repository, referring to the contents of its files by checksum.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// SHA-256 checksums of the files by path relative to the repository
+    files: BTreeMap<String, String>,
+}
+
+/// Stores backups in an S3 bucket.
+///
+/// The contents of all files are stored once as objects named by their checksum, so each backup only uploads the files
+/// changed since the previous one. A backup itself is a snapshot listing the files of the repository.
+pub struct Store {
+    bucket: Bucket,
+
+    prefix: String,
+}
+
+fn check(action: &str, key: &str, (data, code): (Vec<u8>, u16)) -> Result<Vec<u8>> {
+    if !(200..300).contains(&code) {
+        bail!("Failed to {} {}: {}: {}", action, key, code, String::from_utf8_lossy(&data));
+    }
+
+    return Ok(data);
+}
+
+impl Store {
+    pub fn from_config(config: Config) -> Result<Self> {
+        let credentials = Credentials::new(Some(&config.access_key), Some(&config.secret_key), None, None, None)?;
+
+        let bucket = match config.endpoint {
+            Some(endpoint) => Bucket::new_with_path_style(&config.bucket, Region::Custom { region: config.region, endpoint }, credentials)?,
+            None => Bucket::new(&config.bucket, config.region.parse()?, credentials)?,
+        };
+
+        let prefix = match config.prefix.as_str() {
+            "" => String::new(),
+            prefix => format!("{}/", prefix.trim_end_matches('/')),
+        };
+
+        return Ok(Self { bucket, prefix });
+    }
+
+    fn object(&self, checksum: &str) -> String {
+        return format!("{}objects/{}", self.prefix, checksum);
+    }
+
+    fn snapshot(&self, name: &str) -> String {
+        return format!("{}snapshots/{}.json", self.prefix, name);
+    }
+
+    /// Lists the keys of all objects below a prefix, relative to the prefix.
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let prefix = format!("{}{}", self.prefix, prefix);
+
+        let results = self.bucket.list(prefix.clone(), None).await
+            .with_context(|| format!("Failed to list {}", prefix))?;
+
+        return Ok(results.into_iter()
+            .flat_map(|result| result.contents)
+            .filter_map(|object| object.key.strip_prefix(&prefix).map(String::from))
+            .collect());
+    }
+
+    /// Lists the names of all backups.
+    pub async fn list(&self) -> Result<Vec<String>> {
+        return Ok(self.keys("snapshots/").await?.into_iter()
+            .filter_map(|key| key.strip_suffix(".json").map(String::from))
+            .collect());
+    }
+
+    async fn load(&self, name: &str) -> Result<Manifest> {
+        let key = self.snapshot(name);
+        let data = check("download", &key, self.bucket.get_object(&key).await?)?;
+        return Ok(serde_json::from_slice(&data)?);
+    }
+
+    /// Backs up the bundles in the inbox, archive and trash along with the given files of the repository root.
+    ///
+    /// The bundles are backed up from a snapshot, so bundles changed during the backup are consistent. Bundles being
+    /// staged are not backed up. Returns the number of uploaded files.
+    pub async fn backup(&self, repository: &Repository, name: &str, files: &[&str]) -> Result<usize> {
+        let snapshot = repository.snapshot().await?;
+
+        let mut existing = self.keys("objects/").await?.into_iter().collect::<HashSet<_>>();
+
+        let mut manifest = Manifest::default();
+        let mut uploaded = 0;
+        for bundle in snapshot.bundles() {
+            for fragment in bundle.fragment_names() {
+                let path = bundle.path().join(fragment);
+                let checksum = bundle.checksum(fragment).expect("Fragment in snapshot");
+
+                if !existing.contains(&checksum) {
+                    let data = snapshot.load(bundle, fragment).await?;
+                    self.upload(&path, &checksum, &data).await?;
+
+                    existing.insert(checksum.clone());
+                    uploaded += 1;
+                }
+
+                manifest.files.insert(path.to_string_lossy().into_owned(), checksum);
+            }
+        }
+
+        for file in files {
+            let path = repository.path().join(file);
+            if !path.exists() {
+                continue;
+            }
+
+            let data = tokio::fs::read(&path).await
+                .with_context(|| format!("Failed to read {:?}", path))?;
+            let checksum = hex::encode(Sha256::digest(&data));
+
+            if !existing.contains(&checksum) {
+                self.upload(Path::new(file), &checksum, &data).await?;
+
+                existing.insert(checksum.clone());
+                uploaded += 1;
+            }
+
+            manifest.files.insert(file.to_string(), checksum);
+        }
+
+        let key = self.snapshot(name);
+        check("upload", &key, self.bucket.put_object(&key, &serde_json::to_vec(&manifest)?).await?)?;
+
+        info!("Uploaded {} of {} files to {}", uploaded, manifest.files.len(), key);
+
+        return Ok(uploaded);
+    }
+
+    async fn upload(&self, path: &Path, checksum: &str, data: &[u8]) -> Result<()> {
+        let key = self.object(checksum);
+        debug!("Uploading {:?} to {}", path, key);
+
+        check("upload", &key, self.bucket.put_object(&key, data).await?)?;
+
+        return Ok(());
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        let key = self.snapshot(name);
+        check("delete", &key, self.bucket.delete_object(&key).await?)?;
+
+        return Ok(());
+    }
+
+    /// Deletes all objects which are not referred to by any backup.
+    pub async fn collect(&self) -> Result<usize> {
+        let mut referenced = HashSet::new();
+        for name in self.list().await? {
+            referenced.extend(self.load(&name).await?.files.into_iter().map(|(_, checksum)| checksum));
+        }
+
+        let mut deleted = 0;
+        for checksum in self.keys("objects/").await? {
+            if referenced.contains(&checksum) {
+                continue;
+            }
+
+            let key = self.object(&checksum);
+            check("delete", &key, self.bucket.delete_object(&key).await?)?;
+            deleted += 1;
+        }
+
+        return Ok(deleted);
+    }
+
+    /// Downloads a backup into an empty directory, verifying the checksums of all files.
+    pub async fn restore(&self, name: &str, path: &Path) -> Result<usize> {
+        let snapshot = self.load(name).await?;
+
+        for (file, checksum) in &snapshot.files {
+            let relative = Path::new(file);
+            if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+                bail!("Invalid path in backup: {}", file);
+            }
+
+            let key = self.object(checksum);
+            let data = check("download", &key, self.bucket.get_object(&key).await?)?;
+
+            if hex::encode(Sha256::digest(&data)) != *checksum {
+                bail!("Checksum mismatch of {} in backup {}", file, name);
+            }
+
+            let target = path.join(relative);
+            tokio::fs::create_dir_all(target.parent().expect("No parent directory")).await?;
+            tokio::fs::write(&target, &data).await
+                .with_context(|| format!("Failed to write {:?}", target))?;
+        }
+
+        return Ok(snapshot.files.len());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{Datelike, DateTime, NaiveDateTime, TimeZone, Utc};
+use futures::TryStreamExt;
+use log::{error, info, warn};
+
+use crate::config::Backup as Config;
+use crate::maintenance::Task;
+use crate::repository::Repository;
+use crate::status::Status;
+
+use self::bucket::Store;
+
+mod bucket;
+
+/// Format of the names of the individual backups
+const FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Files in the repository root which are backed up in addition to the bundles
+const FILES: &[&str] = &["format", "journal.jsonl", "quarantine.jsonl", "tokens.json", "twofactor.json", "passkeys.json", "satellite.json", "requests.json", "correspondents.json", "persons.json", "checklists.json", "reminders.json", "settings/labels.json", "settings/rules.json", "settings/asn.json", "settings/retention.json"];
+
+/// Where the backups are stored.
+enum Target {
+    /// Each backup is a fork of the repository in a directory
+    Directory(PathBuf),
+
+    S3(Store),
+}
+
+impl Target {
+    async fn from_config(config: &Config) -> Result<Self> {
+        return match (&config.path, &config.s3) {
+            (Some(path), None) => {
+                tokio::fs::create_dir_all(path).await
+                    .with_context(|| format!("Failed to create backup directory: {}", path))?;
+                Ok(Self::Directory(PathBuf::from(path)))
+            }
+            (None, Some(s3)) => Ok(Self::S3(Store::from_config(s3.clone())?)),
+            _ => Err(anyhow!("Either a backup directory or an S3 bucket must be configured")),
+        };
+    }
+
+    /// Lists all backups ordered by time.
+    async fn list(&self) -> Result<Vec<(DateTime<Utc>, String)>> {
+        let names = match self {
+            Self::Directory(path) => tokio::fs::read_dir(path).await?
+                .map_ok(|entry| entry.file_name().to_string_lossy().into_owned())
+                .try_collect::<Vec<_>>().await?,
+            Self::S3(store) => store.list().await?,
+        };
+
+        let mut backups = names.into_iter()
+            .filter_map(|name| {
+                let time = NaiveDateTime::parse_from_str(&name, FORMAT).ok()?;
+                return Some((Utc.from_utc_datetime(&time), name));
+            })
+            .collect::<Vec<_>>();
+        backups.sort();
+
+        return Ok(backups);
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        return match self {
+            Self::Directory(path) => Ok(tokio::fs::remove_dir_all(path.join(name)).await?),
+            Self::S3(store) => store.delete(name).await,
+        };
+    }
+}
+
+/// Periodically backs up the repository into a target directory or an S3 bucket.
+///
+/// Backups are incremental: in a directory, each backup is a fork of the repository sharing unchanged fragments with
+/// the repository or the previous backup. In a bucket, only files changed since the previous backup are uploaded.
+/// Bundles being staged are not backed up. Old backups are rotated, keeping the latest backup of the last days and
+/// weeks.
+pub struct Backup {
+    config: Config,
+
+    target: Target,
+
+    repository: Repository,
+
+    status: Arc<Status>,
+}
+
+impl Backup {
+    pub async fn from_config(config: Config, repository: Repository, status: Arc<Status>) -> Result<Self> {
+        let target = Target::from_config(&config).await?;
+
+        return Ok(Self { config, target, repository, status });
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval));
+        loop {
+            interval.tick().await;
+
+            match self.backup().await {
+                Ok(name) => {
+                    info!("Backed up repository as {}", name);
+                    self.status.backed_up();
+                }
+                Err(err) => {
+                    error!("Failed to back up repository: {:#}", err);
+                    self.status.failed("backup", &err);
+                }
+            }
+
+            if let Err(err) = self.rotate().await {
+                error!("Failed to rotate backups: {:#}", err);
+                self.status.failed("backup", &err);
+            }
+        }
+    }
+
+    /// Creates a new backup and returns its name.
+    pub async fn backup(&self) -> Result<String> {
+        let name = Utc::now().format(FORMAT).to_string();
+
+        match &self.target {
+            Target::Directory(path) => {
+                let base = self.target.list().await?.pop()
+                    .map(|(_, name)| path.join(name));
+
+                let fork = self.repository.fork_from(path.join(&name), base).await?;
+
+                for file in FILES {
+                    let source = self.repository.path().join(file);
+                    if source.exists() {
+                        tokio::fs::create_dir_all(fork.path().join(file).parent().expect("No parent directory")).await?;
+                        tokio::fs::copy(&source, fork.path().join(file)).await
+                            .with_context(|| format!("Failed to copy {:?}", source))?;
+                    }
+                }
+            }
+
+            Target::S3(store) => {
+                store.backup(&self.repository, &name, FILES).await?;
+            }
+        }
+
+        return Ok(name);
+    }
+
+    /// Deletes all backups which are not retained by the rotation.
+    pub async fn rotate(&self) -> Result<usize> {
+        let backups = self.target.list().await?;
+
+        let keep = retained(backups.iter().map(|(time, _)| *time), self.config.daily, self.config.weekly);
+
+        let mut deleted = 0;
+        for (time, name) in backups {
+            if keep.contains(&time) {
+                continue;
+            }
+
+            info!("Deleting expired backup {}", name);
+            if let Err(err) = self.target.delete(&name).await {
+                warn!("Failed to delete backup {}: {:#}", name, err);
+                continue;
+            }
+
+            deleted += 1;
+        }
+
+        // Contents are shared between the backups in the bucket and are only deleted once no backup refers to them
+        if let Target::S3(store) = &self.target {
+            if deleted != 0 {
+                let collected = store.collect().await?;
+                info!("Deleted {} unreferenced objects", collected);
+            }
+        }
+
+        return Ok(deleted);
+    }
+}
+
+#[async_trait]
+impl Task for Backup {
+    async fn run(&self) -> Result<String> {
+        let name = self.backup().await?;
+        self.status.backed_up();
+
+        let deleted = self.rotate().await?;
+
+        return Ok(format!("Backed up repository as {}, deleted {} expired backups", name, deleted));
+    }
+}
+
+/// Restores a backup into an empty repository directory, verifying the checksums of all files.
+///
+/// The backup is given by its name or `latest`. Returns the name of the restored backup.
+pub async fn restore(config: Config, name: &str, path: impl AsRef<Path>) -> Result<String> {
+    let path = path.as_ref();
+
+    if path.exists() && path.read_dir()?.next().is_some() {
+        bail!("Restore target is not empty: {:?}", path);
+    }
+
+    let target = Target::from_config(&config).await?;
+
+    let backups = target.list().await?;
+    let name = match name {
+        "latest" => backups.last().map(|(_, name)| name.clone())
+            .ok_or_else(|| anyhow!("No backups found"))?,
+        name => backups.iter().find(|(_, backup)| backup == name).map(|(_, name)| name.clone())
+            .ok_or_else(|| anyhow!("Backup not found: {}", name))?,
+    };
+
+    info!("Restoring backup {} to {:?}", name, path);
+
+    match &target {
+        Target::Directory(backups) => {
+            let backup = Repository::with_path(backups.join(&name)).await?;
+
+            let report = backup.verify().await?;
+            if !report.is_ok() {
+                for (id, problem) in &report.problems {
+                    error!("{}: {}", id, problem);
+                }
+                bail!("Backup {} is damaged: {} problems found", name, report.problems.len());
+            }
+
+            backup.fork(path.to_path_buf()).await?;
+
+            for file in FILES {
+                let source = backup.path().join(file);
+                if source.exists() {
+                    tokio::fs::create_dir_all(path.join(file).parent().expect("No parent directory")).await?;
+                    tokio::fs::copy(&source, path.join(file)).await
+                        .with_context(|| format!("Failed to copy {:?}", source))?;
+                }
+            }
+        }
+
+        Target::S3(store) => {
+            let files = store.restore(&name, path).await?;
+            info!("Restored {} files", files);
+        }
+    }
+
+    return Ok(name);
+}
+
+/// Selects the backups to keep: the latest one of each of the last `daily` days and of the last `weekly` weeks.
+fn retained(backups: impl IntoIterator<Item=DateTime<Utc>>, daily: usize, weekly: usize) -> HashSet<DateTime<Utc>> {
+    let mut backups = backups.into_iter().collect::<Vec<_>>();
+    backups.sort_by(|a, b| b.cmp(a));
+
+    let mut keep = HashSet::new();
+
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for backup in backups {
+        if days.len() < daily && days.insert(backup.date()) {
+            keep.insert(backup);
+        }
+
+        let week = backup.iso_week();
+        if weeks.len() < weekly && weeks.insert((week.year(), week.week())) {
+            keep.insert(backup);
+        }
+    }
+
+    return keep;
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_retained() {
+        // One backup per day for 60 days, starting on a monday
+        let start = Utc.ymd(2020, 6, 1).and_hms(3, 0, 0);
+        let backups = (0..60).map(|day| start + chrono::Duration::days(day)).collect::<Vec<_>>();
+
+        let keep = retained(backups.iter().copied(), 7, 4);
+
+        // The last 7 days, which cover the last two weeks, plus the sundays of the 2 weeks before
+        assert_that!(keep.len()).is_equal_to(9);
+        for backup in &backups[53..] {
+            assert_that!(keep.contains(backup)).is_true();
+        }
+        for week in 1..=2 {
+            assert_that!(keep.contains(&backups[55 - 7 * week])).is_true();
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info};
+use tokio::process::Command;
+
+use crate::config::Barcodes as Config;
+use crate::filing::Filing;
+use crate::meta::Metadata;
+use crate::proto::model::{Kind, Label, PropertyValue};
+use crate::repository::{Bundle, Staging};
+
+/// Prefix of the properties holding the detected barcodes, numbered in the order they were found
+pub const PROPERTY: &str = "barcode";
+
+/// Returns the property holding the n-th detected barcode, counting from one.
+fn property(n: usize) -> String {
+    return format!("{}.{}", PROPERTY, n);
+}
+
+/// Parses an archive serial number from a barcode with the given prefix, like `ASN 000042`.
+fn asn(prefix: &str, code: &str) -> Option<i64> {
+    let number = code.trim().strip_prefix(prefix)?.trim();
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    return number.parse().ok();
+}
+
+/// Records the detected barcodes in the metadata and applies the ASN and routes found among them.
+///
+/// An ASN or barcodes already assigned, i.e. by an upload, are kept. Returns true if the metadata has been changed.
+fn apply(config: &Config, metadata: &mut Metadata, codes: &[String]) -> bool {
+    let mut changed = false;
+
+    if !metadata.properties.keys().any(|key| key.starts_with(PROPERTY) && key[PROPERTY.len()..].starts_with('.')) {
+        for (i, code) in codes.iter().enumerate() {
+            metadata.properties.insert(property(i + 1), PropertyValue::String(code.clone()));
+            changed = true;
+        }
+    }
+
+    if !metadata.properties.contains_key(Filing::PROPERTY) {
+        if let Some(asn) = codes.iter().find_map(|code| asn(&config.asn_prefix, code)) {
+            metadata.properties.insert(String::from(Filing::PROPERTY), PropertyValue::Integer(asn));
+            changed = true;
+        }
+    }
+
+    for code in codes {
+        for label in config.routes.get(code).into_iter().flatten() {
+            changed |= metadata.labels.insert(Label::from(label.as_str()));
+        }
+    }
+
+    return changed;
+}
+
+/// Detects barcodes and QR codes on the pages of juiced documents, i.e. of scans carrying pre-printed labels.
+///
+/// The codes are recorded as properties. A code carrying an archive serial number assigns it to the document, so the
+/// paper original keeps the number of its label, and codes configured as routes pre-select labels.
+pub struct Detector {
+    config: Config,
+}
+
+impl Detector {
+    pub fn from_config(config: Config) -> Self {
+        return Self { config };
+    }
+
+    /// Scans a page preview for barcodes.
+    async fn scan(&self, page: &Path) -> Result<Vec<String>> {
+        debug!("Scanning {:?} for barcodes", page);
+
+        let output = Command::new(&self.config.zbarimg)
+            .args(&["--raw", "--quiet"])
+            .arg(page)
+            .stdin(Stdio::null())
+            .output().await
+            .with_context(|| format!("Error executing {}", self.config.zbarimg))?;
+
+        // zbarimg exits with status 4 if the page does not contain any barcode
+        return match output.status.code() {
+            Some(0) => Ok(String::from_utf8_lossy(&output.stdout).lines()
+                .map(str::trim)
+                .filter(|code| !code.is_empty())
+                .map(String::from)
+                .collect()),
+            Some(4) => Ok(Vec::new()),
+            _ => Err(anyhow!("{} failed: {}: {}", self.config.zbarimg, output.status, String::from_utf8_lossy(&output.stderr))),
+        };
+    }
+
+    /// Scans the page previews of a juiced bundle and records the barcodes found in its metadata.
+    ///
+    /// Returns true if the metadata has been changed.
+    pub async fn detect(&self, bundle: &Bundle<'_, Staging>) -> Result<bool> {
+        let mut metadata = bundle.read_metadata().await?;
+
+        let mut codes = Vec::new();
+        for page in 1..=metadata.pages {
+            let path = bundle.path_of(Kind::Page(page));
+            if !path.exists() {
+                continue;
+            }
+
+            for code in self.scan(&path).await? {
+                if !codes.contains(&code) {
+                    codes.push(code);
+                }
+            }
+        }
+
+        if !apply(&self.config, &mut metadata, &codes) {
+            return Ok(false);
+        }
+
+        info!("Found barcodes on bundle {}: {}", bundle.id(), codes.join(", "));
+
+        metadata.save(bundle.write(Kind::Metadata).await?).await?;
+
+        return Ok(true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_asn() {
+        assert_that!(asn("ASN", "ASN 000042")).is_equal_to(Some(42));
+        assert_that!(asn("ASN", "ASN000042")).is_equal_to(Some(42));
+        assert_that!(asn("ASN", "000042")).is_none();
+        assert_that!(asn("ASN", "ASN 42a")).is_none();
+        assert_that!(asn("ASN", "ASN")).is_none();
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut config = Config::default();
+        config.routes.insert(String::from("ROUTE-TAX"), vec![String::from("tax"), String::from("finance")]);
+
+        let codes = vec![String::from("ROUTE-TAX"), String::from("ASN 000042"), String::from("ASN 000043")];
+
+        let mut metadata = Metadata::new();
+        assert_that!(apply(&config, &mut metadata, &codes)).is_true();
+        assert_that!(metadata.properties.get("barcode.1")).is_equal_to(Some(&PropertyValue::String(String::from("ROUTE-TAX"))));
+        assert_that!(metadata.properties.get("barcode.3")).is_equal_to(Some(&PropertyValue::String(String::from("ASN 000043"))));
+        assert_that!(metadata.properties.get(Filing::PROPERTY)).is_equal_to(Some(&PropertyValue::Integer(42)));
+        assert_that!(metadata.labels.contains(&Label::from("tax"))).is_true();
+        assert_that!(metadata.labels.contains(&Label::from("finance"))).is_true();
+
+        // Applying again changes nothing
+        assert_that!(apply(&config, &mut metadata, &codes)).is_false();
+
+        // An ASN assigned before is kept
+        let mut metadata = Metadata::new();
+        metadata.properties.insert(String::from(Filing::PROPERTY), PropertyValue::Integer(7));
+        apply(&config, &mut metadata, &codes);
+        assert_that!(metadata.properties.get(Filing::PROPERTY)).is_equal_to(Some(&PropertyValue::Integer(7)));
+
+        assert_that!(apply(&config, &mut Metadata::new(), &[])).is_false();
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::Datelike;
+use tokio::sync::RwLock;
+
+use crate::meta::Metadata;
+use crate::proto::api::checklists::ItemProgress;
+use crate::proto::model::{Checklist, DocId, PropertyValue};
+use crate::proto::query::Query;
+
+/// Repository-wide registry of checklists of documents expected every year.
+pub struct Checklists {
+    path: PathBuf,
+    checklists: RwLock<BTreeMap<String, Checklist>>,
+}
+
+impl Checklists {
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let checklists = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        return Ok(Self { path, checklists: RwLock::new(checklists) });
+    }
+
+    async fn save(&self, checklists: &BTreeMap<String, Checklist>) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(checklists)?).await?;
+        return Ok(());
+    }
+
+    pub async fn list(&self) -> Vec<Checklist> {
+        return self.checklists.read().await.values().cloned().collect();
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Checklist> {
+        return self.checklists.read().await.get(name).cloned();
+    }
+
+    /// Defines a checklist or replaces the existing one with the same name.
+    pub async fn define(&self, checklist: Checklist) -> Result<()> {
+        let mut checklists = self.checklists.write().await;
+        checklists.insert(checklist.name.clone(), checklist);
+        return self.save(&checklists).await;
+    }
+
+    /// Removes a checklist and returns whether it was defined.
+    pub async fn remove(&self, name: &str) -> Result<bool> {
+        let mut checklists = self.checklists.write().await;
+        if checklists.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&checklists).await?;
+
+        return Ok(true);
+    }
+}
+
+/// Finds the documents of the given year matching the expected documents of a checklist.
+///
+/// The year of a document is taken from the date property of the checklist and falls back to the upload date if the
+/// property is missing, like the statistics do.
+pub fn progress<'m>(checklist: &Checklist, year: i32, docs: impl IntoIterator<Item=&'m (DocId, Metadata)>) -> Result<Vec<ItemProgress>> {
+    let queries = checklist.items.iter()
+        .map(|item| Query::from_str(&item.query))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut items = checklist.items.iter()
+        .map(|item| ItemProgress {
+            name: item.name.clone(),
+            documents: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    for (id, metadata) in docs {
+        let date = match checklist.date.as_deref().and_then(|date| metadata.properties.get(date)) {
+            Some(PropertyValue::Date(date)) => date.year(),
+            _ => metadata.uploaded.year(),
+        };
+
+        if date != year {
+            continue;
+        }
+
+        for (item, query) in items.iter_mut().zip(&queries) {
+            if metadata.matches(query) {
+                item.documents.push(*id);
+            }
+        }
+    }
+
+    return Ok(items);
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use spectral::prelude::*;
+
+    use crate::proto::model::{ExpectedDocument, Label};
+
+    use super::*;
+
+    fn doc(labels: &[&str], uploaded: i32, date: Option<NaiveDate>) -> (DocId, Metadata) {
+        let mut metadata = Metadata {
+            labels: labels.iter().map(|label| Label::from(*label)).collect(),
+            uploaded: Utc.ymd(uploaded, 6, 1).and_hms(0, 0, 0),
+            ..Metadata::new()
+        };
+
+        if let Some(date) = date {
+            metadata.properties.insert(String::from("date"), PropertyValue::Date(date));
+        }
+
+        return (DocId::random(), metadata);
+    }
+
+    #[test]
+    fn test_progress() {
+        let checklist = Checklist {
+            name: String::from("Taxes"),
+            items: vec![
+                ExpectedDocument { name: String::from("Payslip"), query: String::from("label:payslip") },
+                ExpectedDocument { name: String::from("Donations"), query: String::from("label:donation") },
+            ],
+            date: Some(String::from("date")),
+        };
+
+        let docs = vec![
+            doc(&["payslip"], 2021, Some(NaiveDate::from_ymd(2020, 12, 31))),
+            doc(&["payslip"], 2021, None),
+            doc(&["donation"], 2020, None),
+            doc(&["other"], 2020, None),
+        ];
+
+        let items = progress(&checklist, 2020, &docs).unwrap();
+        assert_that!(items[0].documents).is_equal_to(vec![docs[0].0]);
+        assert_that!(items[1].documents).is_equal_to(vec![docs[2].0]);
+
+        let items = progress(&checklist, 2021, &docs).unwrap();
+        assert_that!(items[0].documents).is_equal_to(vec![docs[1].0]);
+        assert_that!(items[1].documents).is_empty();
+    }
+}
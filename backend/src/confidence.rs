@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+/// Fragment holding the words recognized by tesseract along with their confidence, as written by its `tsv` output
+pub const FRAGMENT: &str = "document.tsv";
+
+/// Confidence in percent below which documents are listed for review, unless requested otherwise
+pub const DEFAULT_THRESHOLD: u8 = 60;
+
+/// The confidence of the OCR of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Confidence {
+    /// Mean confidence of all recognized words in percent
+    pub mean: u8,
+
+    /// Mean confidence per page in percent, unset for pages without recognized words
+    pub pages: Vec<Option<u8>>,
+}
+
+/// Calculates the OCR confidence from the `tsv` output of tesseract.
+///
+/// Each recognized word is reported on a line of level 5 with its page number and its confidence. Lines of other levels
+/// or without confidence describe the layout and are skipped. Returns `None` if no words have been recognized at all.
+pub fn parse(tsv: &str) -> Option<Confidence> {
+    let mut pages = BTreeMap::<usize, (f64, usize)>::new();
+
+    for line in tsv.lines().skip(1) {
+        let columns = line.split('\t').collect::<Vec<_>>();
+        if columns.len() < 12 || columns[0] != "5" || columns[11].trim().is_empty() {
+            continue;
+        }
+
+        let page = match columns[1].parse::<usize>() {
+            Ok(page) if page > 0 => page,
+            _ => continue,
+        };
+
+        let confidence = match columns[10].parse::<f64>() {
+            Ok(confidence) if confidence >= 0.0 => confidence.min(100.0),
+            _ => continue,
+        };
+
+        let (sum, count) = pages.entry(page).or_default();
+        *sum += confidence;
+        *count += 1;
+    }
+
+    let (sum, count) = pages.values()
+        .fold((0.0, 0), |(sum, count), (page_sum, page_count)| (sum + page_sum, count + page_count));
+    if count == 0 {
+        return None;
+    }
+
+    let last = pages.keys().next_back().copied().unwrap_or_default();
+    let pages = (1..=last)
+        .map(|page| pages.get(&page).map(|(sum, count)| (sum / *count as f64).round() as u8))
+        .collect();
+
+    return Some(Confidence {
+        mean: (sum / count as f64).round() as u8,
+        pages,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    const HEADER: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext";
+
+    #[test]
+    fn test_parse() {
+        let tsv = [
+            HEADER,
+            "1\t1\t0\t0\t0\t0\t0\t0\t2480\t3508\t-1\t",
+            "5\t1\t1\t1\t1\t1\t100\t100\t200\t40\t96.5\tInvoice",
+            "5\t1\t1\t1\t1\t2\t320\t100\t80\t40\t89.5\t42",
+            "5\t1\t1\t1\t1\t3\t420\t100\t80\t40\t-1\t",
+            "1\t2\t0\t0\t0\t0\t0\t0\t2480\t3508\t-1\t",
+            "5\t3\t1\t1\t1\t1\t100\t100\t200\t40\t30\tsmudge",
+        ].join("\n");
+
+        assert_that!(parse(&tsv)).is_equal_to(Some(Confidence {
+            mean: 72,
+            pages: vec![Some(93), None, Some(30)],
+        }));
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_that!(parse(HEADER)).is_none();
+        assert_that!(parse("")).is_none();
+    }
+}
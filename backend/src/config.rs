@@ -1,23 +1,177 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
 use tokio::fs::OpenOptions;
 use tokio::io::AsyncReadExt;
 
+use crate::proto::model::{Label, PropertyValue};
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Auth {
+    #[serde(default = "Auth::default_username")]
+    pub username: String,
+
     pub passhash: String,
 
     pub secret: String,
 
     pub api_keys: HashMap<String, String>,
+
+    /// Metadata applied to documents uploaded with an API key or token, by the name of the key or token
+    #[serde(default)]
+    pub defaults: HashMap<String, Defaults>,
+
+    /// Number of failed logins after which further attempts are locked
+    #[serde(default = "Auth::default_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Seconds for which logins are locked after too many failed attempts
+    #[serde(default = "Auth::default_lockout")]
+    pub lockout: u64,
+
+    /// Relying party for passkeys, which can only be registered if configured
+    #[serde(default)]
+    pub webauthn: Option<Webauthn>,
+}
+
+impl Auth {
+    fn default_username() -> String { String::from("admin") }
+
+    fn default_max_attempts() -> u32 { 5 }
+
+    fn default_lockout() -> u64 { 15 * 60 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Webauthn {
+    /// Origin the web interface is served at, i.e. `https://adacta.example.com`
+    pub origin: String,
+
+    /// The relying party ID passkeys are bound to, defaults to the host of the origin
+    #[serde(default)]
+    pub id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Repository {
     pub path: String,
+
+    /// Days after which trashed bundles are purged automatically, taken over into the repository settings unless
+    /// these define a retention already
+    #[serde(default)]
+    pub trash_retention: Option<u32>,
+
+    /// Overwrite fragment contents before purging bundles (best-effort)
+    #[serde(default)]
+    pub shred: bool,
+
+    /// Additionally hold advisory lock files while changing bundles, for multiple processes sharing the repository
+    #[serde(default)]
+    pub lock_files: bool,
+
+    /// Compress fragments transparently, disabled if missing
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Number of bundles read concurrently while listing, which hides the latency of network filesystems
+    #[serde(default = "Repository::default_concurrency")]
+    pub concurrency: usize,
+
+    /// Rules retaining archived documents from deletion
+    #[serde(default)]
+    pub retention: Vec<RetentionRule>,
+
+    /// Refuse to replace existing fragments of archived documents
+    #[serde(default)]
+    pub write_once: bool,
+
+    /// Keep local copies of recently read documents and previews, for repositories on network filesystems
+    #[serde(default)]
+    pub cache: Option<Cache>,
+
+    /// How the IDs of new documents are generated
+    #[serde(default)]
+    pub ids: IdScheme,
+}
+
+impl Repository {
+    pub fn default_concurrency() -> usize { 16 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cache {
+    /// Local directory holding the copies, which must not be shared with other repositories
+    pub path: String,
+
+    /// Size of all copies in MiB, beyond which the least recently used copies are evicted
+    #[serde(default = "Cache::default_size")]
+    pub size: u64,
+}
+
+impl Cache {
+    fn default_size() -> u64 { 1024 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NamedRepository {
+    #[serde(flatten)]
+    pub repository: Repository,
+
+    /// Juicer processing the documents uploaded to the repository, the default juicer if unset
+    #[serde(default)]
+    pub juicer: Option<Juicer>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdScheme {
+    /// Random IDs without any order
+    Random,
+
+    /// IDs starting with their creation time, so listings sort by creation time
+    TimeOrdered,
+}
+
+impl Default for IdScheme {
+    fn default() -> Self { Self::Random }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetentionRule {
+    /// Documents with this label or any label nested below are retained
+    pub label: String,
+
+    /// Years after archiving the documents can not be deleted
+    pub years: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Compression {
+    /// Fragments to compress, which may be `plaintext` and `metadata` as all others are read from disk directly
+    #[serde(default = "Compression::default_fragments")]
+    pub fragments: Vec<String>,
+
+    /// The zstd compression level
+    #[serde(default = "Compression::default_level")]
+    pub level: i32,
+}
+
+impl Compression {
+    fn default_fragments() -> Vec<String> { vec![String::from("plaintext")] }
+
+    fn default_level() -> i32 { 3 }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        return Self {
+            fragments: Self::default_fragments(),
+            level: Self::default_level(),
+        };
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -33,9 +187,286 @@ pub enum Index {
     Elasticsearch(ElasticsearchIndex),
 }
 
+impl Index {
+    /// Returns the index of a named repository, which is kept next to the index of the default repository.
+    pub fn named(&self, name: &str) -> Self {
+        return match self {
+            Self::Elasticsearch(config) => Self::Elasticsearch(ElasticsearchIndex {
+                index: format!("{}-{}", config.index, name),
+                ..config.clone()
+            }),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DockerTls {
+    /// Directory containing `ca.pem`, `cert.pem` and `key.pem`
+    pub cert_path: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct DockerJuicer {
     pub image: Option<String>,
+
+    /// URL of a remote docker daemon, i.e. `tcp://builder:2376`
+    #[serde(default)]
+    pub host: Option<String>,
+
+    /// Path of a local docker-compatible API socket, i.e. `/run/podman/podman.sock`
+    #[serde(default)]
+    pub socket: Option<String>,
+
+    #[serde(default)]
+    pub tls: Option<DockerTls>,
+
+    /// Seconds after which a running extraction is aborted
+    #[serde(default = "DockerJuicer::default_timeout")]
+    pub timeout: u64,
+
+    /// Restrictions of the container, as it processes untrusted files
+    #[serde(default)]
+    pub sandbox: DockerSandbox,
+
+    /// Restrictions of the images the juicer may run
+    #[serde(default)]
+    pub policy: ImagePolicy,
+
+    /// When the image is pulled on startup
+    #[serde(default)]
+    pub pull: PullPolicy,
+
+    /// Seconds between pulls of the image to pick up updates of its tag, the image is only pulled on startup if unset
+    #[serde(default)]
+    pub pull_interval: Option<u64>,
+
+    /// Tesseract languages used for OCR, unless requested otherwise on upload
+    #[serde(default = "DockerJuicer::default_languages")]
+    pub languages: String,
+}
+
+impl DockerJuicer {
+    fn default_timeout() -> u64 { 10 * 60 }
+
+    fn default_languages() -> String { String::from("eng+deu") }
+}
+
+/// Strategy used to pull the juicer image on startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PullPolicy {
+    /// Pull the image only if it does not exist locally
+    Missing,
+
+    /// Always pull the image, i.e. to update a tag like `develop`
+    Always,
+
+    /// Never pull the image, it must be provided by other means
+    Never,
+}
+
+impl Default for PullPolicy {
+    fn default() -> Self { Self::Missing }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DockerSandbox {
+    /// Mount the root filesystem read-only, leaving only `/juicer` and `/tmp` writable
+    #[serde(default = "DockerSandbox::default_read_only")]
+    pub read_only: bool,
+
+    /// Prevent processes from gaining privileges, i.e. by executing setuid binaries
+    #[serde(default = "DockerSandbox::default_no_new_privileges")]
+    pub no_new_privileges: bool,
+
+    /// Path of a seccomp profile on the docker host, the daemon's default profile applies if unset
+    #[serde(default)]
+    pub seccomp: Option<String>,
+
+    /// Capabilities dropped from the container, i.e. `ALL`
+    #[serde(default = "DockerSandbox::default_cap_drop")]
+    pub cap_drop: Vec<String>,
+
+    /// Size of the tmpfs mounted to `/tmp`, i.e. `512m`, or no tmpfs if unset
+    #[serde(default = "DockerSandbox::default_tmpfs")]
+    pub tmpfs: Option<String>,
+
+    /// Memory limit of the container in MiB, the container gets no additional swap
+    #[serde(default)]
+    pub memory: Option<u64>,
+
+    /// Number of CPUs the container may use, i.e. `1.5`
+    #[serde(default)]
+    pub cpus: Option<f64>,
+
+    /// Maximum number of processes in the container, protecting the host from fork bombs
+    #[serde(default = "DockerSandbox::default_pids_limit")]
+    pub pids_limit: Option<u64>,
+
+    /// User and group the container runs as, i.e. `1000:1000`, the user of the image if unset
+    #[serde(default)]
+    pub user: Option<String>,
+
+    /// User namespace mode of the container, i.e. `host` to opt out of the daemon's user namespace remapping
+    #[serde(default)]
+    pub userns: Option<String>,
+
+    /// Allow the container to access the network, which the juicer does not need
+    #[serde(default)]
+    pub network: bool,
+}
+
+impl DockerSandbox {
+    fn default_read_only() -> bool { true }
+    fn default_no_new_privileges() -> bool { true }
+    fn default_cap_drop() -> Vec<String> { vec![String::from("ALL")] }
+    fn default_tmpfs() -> Option<String> { Some(String::from("512m")) }
+    fn default_pids_limit() -> Option<u64> { Some(512) }
+}
+
+impl Default for DockerSandbox {
+    fn default() -> Self {
+        return Self {
+            read_only: Self::default_read_only(),
+            no_new_privileges: Self::default_no_new_privileges(),
+            seccomp: None,
+            cap_drop: Self::default_cap_drop(),
+            tmpfs: Self::default_tmpfs(),
+            memory: None,
+            cpus: None,
+            pids_limit: Self::default_pids_limit(),
+            user: None,
+            userns: None,
+            network: false,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImagePolicy {
+    /// Registries or repositories images are allowed from, i.e. `ghcr.io/adacta-io` or `adacta10/juicer`, all images
+    /// are allowed if empty
+    #[serde(default)]
+    pub allowed: Vec<String>,
+
+    /// Require the image to be pinned by digest, i.e. `adacta10/juicer@sha256:...`
+    #[serde(default)]
+    pub require_digest: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NativeJuicer {
+    #[serde(default = "NativeJuicer::default_pdftotext")]
+    pub pdftotext: String,
+
+    #[serde(default = "NativeJuicer::default_pdftoppm")]
+    pub pdftoppm: String,
+
+    #[serde(default = "NativeJuicer::default_pdfinfo")]
+    pub pdfinfo: String,
+
+    #[serde(default = "NativeJuicer::default_tesseract")]
+    pub tesseract: String,
+
+    /// LibreOffice used to convert office documents to PDF
+    #[serde(default = "NativeJuicer::default_soffice")]
+    pub soffice: String,
+
+    /// img2pdf used to wrap scanned images into a PDF
+    #[serde(default = "NativeJuicer::default_img2pdf")]
+    pub img2pdf: String,
+
+    /// Tesseract languages used for OCR, unless requested otherwise on upload
+    #[serde(default = "NativeJuicer::default_languages")]
+    pub languages: String,
+}
+
+impl NativeJuicer {
+    fn default_pdftotext() -> String { String::from("pdftotext") }
+
+    fn default_pdftoppm() -> String { String::from("pdftoppm") }
+
+    fn default_pdfinfo() -> String { String::from("pdfinfo") }
+
+    fn default_tesseract() -> String { String::from("tesseract") }
+
+    fn default_soffice() -> String { String::from("soffice") }
+
+    fn default_img2pdf() -> String { String::from("img2pdf") }
+
+    fn default_languages() -> String { String::from("eng+deu") }
+}
+
+impl Default for NativeJuicer {
+    fn default() -> Self {
+        return Self {
+            pdftotext: Self::default_pdftotext(),
+            pdftoppm: Self::default_pdftoppm(),
+            pdfinfo: Self::default_pdfinfo(),
+            tesseract: Self::default_tesseract(),
+            soffice: Self::default_soffice(),
+            img2pdf: Self::default_img2pdf(),
+            languages: Self::default_languages(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineJuicer {
+    /// Tools used by the builtin steps
+    #[serde(default)]
+    pub native: NativeJuicer,
+
+    /// Steps run in order by the MIME type of the uploaded document, like `application/pdf` or `image/*`, documents of
+    /// other types run the steps configured for `*`
+    pub pipelines: HashMap<String, Vec<PipelineStep>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineStep {
+    /// Name of the step, like `convert`, `ocr`, `preview` or `classify`
+    pub name: String,
+
+    #[serde(flatten)]
+    pub runner: StepRunner,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+pub enum StepRunner {
+    /// Runs the step of the native juicer with the same name
+    Builtin,
+
+    /// Runs a command in the bundle directory, `{languages}` in the arguments is replaced by the OCR languages
+    Command {
+        program: String,
+
+        #[serde(default)]
+        args: Vec<String>,
+    },
+
+    /// Runs a docker image over all fragments of the bundle
+    Docker(DockerJuicer),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainJuicer {
+    /// Juicers tried in order until one succeeds, i.e. a fast native juicer before an OCR container
+    pub juicers: Vec<ChainedJuicer>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainedJuicer {
+    /// Name of the juicer recorded in the metadata of the documents it extracted
+    pub name: String,
+
+    /// Falls back to the next juicer if the extracted document contains no text, i.e. for scans without text layer
+    #[serde(default)]
+    pub require_text: bool,
+
+    #[serde(flatten)]
+    pub juicer: Juicer,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -43,6 +474,9 @@ pub struct DockerJuicer {
 #[serde(rename_all = "lowercase")]
 pub enum Juicer {
     Docker(DockerJuicer),
+    Native(NativeJuicer),
+    Pipeline(PipelineJuicer),
+    Chain(ChainJuicer),
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -65,31 +499,1300 @@ pub enum Suggester {
 }
 
 #[derive(Debug, Clone, Deserialize)]
-pub struct Web {
-    pub address: String,
-    pub port: u16,
+pub struct Warmup {
+    /// Render missing thumbnails and first page previews of archived documents
+    #[serde(default = "Warmup::default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "Warmup::default_pdftoppm")]
+    pub pdftoppm: String,
+}
+
+impl Warmup {
+    fn default_enabled() -> bool { true }
+
+    fn default_pdftoppm() -> String { String::from("pdftoppm") }
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        return Self {
+            enabled: Self::default_enabled(),
+            pdftoppm: Self::default_pdftoppm(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanAction {
+    /// Move orphaned bundles to the quarantine for review
+    Quarantine,
+
+    /// Delete orphaned bundles
+    Delete,
 }
 
 #[derive(Debug, Clone, Deserialize)]
-pub struct Config {
-    pub auth: Auth,
+pub struct Orphans {
+    /// Collect bundles left behind in the staging area, i.e. by a crash while ingesting
+    #[serde(default = "Orphans::default_enabled")]
+    pub enabled: bool,
 
-    pub repository: Repository,
+    /// Seconds since the last change after which a staged bundle is considered orphaned
+    #[serde(default = "Orphans::default_age")]
+    pub age: u64,
 
-    pub index: Index,
-    pub juicer: Juicer,
-    pub suggester: Suggester,
+    /// Seconds between two collections, the first one runs on startup
+    #[serde(default = "Orphans::default_interval")]
+    pub interval: u64,
 
-    pub web: Web,
+    #[serde(default = "Orphans::default_action")]
+    pub action: OrphanAction,
 }
 
-impl Config {
-    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
-        let mut file = OpenOptions::new().read(true).open(path).await?;
+impl Orphans {
+    fn default_enabled() -> bool { true }
 
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).await?;
+    fn default_age() -> u64 { 24 * 60 * 60 }
+
+    fn default_interval() -> u64 { 60 * 60 }
+
+    fn default_action() -> OrphanAction { OrphanAction::Quarantine }
+}
+
+impl Default for Orphans {
+    fn default() -> Self {
+        return Self {
+            enabled: Self::default_enabled(),
+            age: Self::default_age(),
+            interval: Self::default_interval(),
+            action: Self::default_action(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Previews {
+    /// Directory the rendered previews are cached in, defaults to `cache/previews` in the repository
+    #[serde(default)]
+    pub path: Option<String>,
+
+    /// Largest size of the longer side of a preview in pixels
+    #[serde(default = "Previews::default_max_size")]
+    pub max_size: u32,
+
+    /// Number of previews rendered concurrently
+    #[serde(default = "Previews::default_concurrency")]
+    pub concurrency: usize,
+
+    #[serde(default = "Previews::default_pdftoppm")]
+    pub pdftoppm: String,
+}
+
+impl Previews {
+    fn default_max_size() -> u32 { 2048 }
+
+    fn default_concurrency() -> usize { 2 }
+
+    fn default_pdftoppm() -> String { String::from("pdftoppm") }
+}
+
+impl Default for Previews {
+    fn default() -> Self {
+        return Self {
+            path: None,
+            max_size: Self::default_max_size(),
+            concurrency: Self::default_concurrency(),
+            pdftoppm: Self::default_pdftoppm(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Processor {
+    /// Name of the processor as shown in logs
+    pub name: String,
+
+    /// Filename of the processed fragments, `*.gpx` matches all fragments by extension
+    pub fragment: String,
+
+    /// Command and arguments, the path of the fragment is appended as last argument
+    pub command: Vec<String>,
+
+    /// Seconds after which the command is aborted
+    #[serde(default = "Processor::default_timeout")]
+    pub timeout: u64,
+}
+
+impl Processor {
+    fn default_timeout() -> u64 { 5 * 60 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Transcription {
+    /// Command and arguments, the path of the audio fragment is appended as last argument and the transcript is
+    /// expected on stdout
+    pub command: Vec<String>,
+
+    /// Seconds after which the command is aborted
+    #[serde(default = "Transcription::default_timeout")]
+    pub timeout: u64,
+}
+
+impl Transcription {
+    fn default_timeout() -> u64 { 10 * 60 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Queue {
+    /// Maximum number of concurrently running juicers
+    #[serde(default = "Queue::default_concurrency")]
+    pub concurrency: usize,
+
+    /// Number of retries before a job is considered failed
+    #[serde(default = "Queue::default_retries")]
+    pub retries: u32,
+
+    /// Initial delay between retries in seconds, doubled with every attempt
+    #[serde(default = "Queue::default_backoff")]
+    pub backoff: u64,
+
+    /// Split scanned stacks of documents at separator pages before juicing
+    #[serde(default)]
+    pub split: Option<Split>,
+
+    /// Detect barcodes on the pages of juiced documents
+    #[serde(default)]
+    pub barcodes: Option<Barcodes>,
+
+    /// Propose the metadata found by the rules instead of applying it, so it must be accepted before archiving
+    #[serde(default)]
+    pub review: bool,
+
+    /// Alternative juicers failed jobs can be retried with, by name
+    #[serde(default)]
+    pub juicers: HashMap<String, Juicer>,
+}
+
+impl Queue {
+    fn default_concurrency() -> usize { 2 }
+
+    fn default_retries() -> u32 { 3 }
+
+    fn default_backoff() -> u64 { 30 }
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        return Self {
+            concurrency: Self::default_concurrency(),
+            retries: Self::default_retries(),
+            backoff: Self::default_backoff(),
+            split: None,
+            barcodes: None,
+            review: false,
+            juicers: HashMap::new(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Separator {
+    /// Empty pages separate the documents - this does not work for duplex scans
+    Blank,
+
+    /// Sheets carrying the configured barcode separate the documents
+    Barcode,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Split {
+    #[serde(default = "Split::default_separator")]
+    pub separator: Separator,
+
+    /// Content of the barcode printed on separator sheets
+    #[serde(default = "Split::default_barcode")]
+    pub barcode: String,
+
+    /// Maximum share of dark pixels of a page considered blank
+    #[serde(default = "Split::default_blank_threshold")]
+    pub blank_threshold: f64,
+
+    #[serde(default = "Split::default_pdftoppm")]
+    pub pdftoppm: String,
+
+    #[serde(default = "Split::default_zbarimg")]
+    pub zbarimg: String,
+
+    #[serde(default = "Split::default_qpdf")]
+    pub qpdf: String,
+}
+
+impl Split {
+    fn default_separator() -> Separator { Separator::Barcode }
+
+    fn default_barcode() -> String { String::from("ADACTA-SEPARATOR") }
+
+    fn default_blank_threshold() -> f64 { 0.002 }
+
+    fn default_pdftoppm() -> String { String::from("pdftoppm") }
+
+    fn default_zbarimg() -> String { String::from("zbarimg") }
+
+    fn default_qpdf() -> String { String::from("qpdf") }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Barcodes {
+    /// Prefix of barcodes carrying an archive serial number, like `ASN 000042` on pre-printed label sheets
+    #[serde(default = "Barcodes::default_asn_prefix")]
+    pub asn_prefix: String,
+
+    /// Labels assigned to documents carrying a barcode, by the content of the barcode
+    #[serde(default)]
+    pub routes: HashMap<String, Vec<String>>,
+
+    #[serde(default = "Barcodes::default_zbarimg")]
+    pub zbarimg: String,
+}
+
+impl Barcodes {
+    fn default_asn_prefix() -> String { String::from("ASN") }
+
+    fn default_zbarimg() -> String { String::from("zbarimg") }
+}
+
+impl Default for Barcodes {
+    fn default() -> Self {
+        return Self {
+            asn_prefix: Self::default_asn_prefix(),
+            routes: HashMap::new(),
+            zbarimg: Self::default_zbarimg(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Merge {
+    #[serde(default = "Merge::default_qpdf")]
+    pub qpdf: String,
+}
+
+impl Merge {
+    fn default_qpdf() -> String { String::from("qpdf") }
+}
+
+impl Default for Merge {
+    fn default() -> Self {
+        return Self {
+            qpdf: Self::default_qpdf(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Editing {
+    #[serde(default = "Editing::default_qpdf")]
+    pub qpdf: String,
+}
+
+impl Editing {
+    fn default_qpdf() -> String { String::from("qpdf") }
+}
+
+impl Default for Editing {
+    fn default() -> Self {
+        return Self {
+            qpdf: Self::default_qpdf(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Quota {
+    /// Bytes the documents of each repository may use, unlimited if unset
+    #[serde(default)]
+    pub repository: Option<u64>,
+
+    /// Bytes the documents owned by each user may use, unlimited if unset
+    #[serde(default)]
+    pub user: Option<u64>,
+
+    /// Bytes the documents owned by the given users may use, overriding the limit for all users
+    #[serde(default)]
+    pub users: HashMap<String, u64>,
+}
+
+/// Metadata applied to all documents of an ingestion source when they are staged.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Defaults {
+    /// Labels added to the documents, which is also how the type of the documents is given
+    #[serde(default)]
+    pub labels: HashSet<Label>,
+
+    /// Properties set unless the document has them already
+    #[serde(default)]
+    pub properties: HashMap<String, PropertyValue>,
+
+    /// User owning the documents instead of the uploader
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Consume {
+    /// Directory watched for dropped documents
+    pub path: String,
+
+    #[serde(default)]
+    pub defaults: Defaults,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Imap {
+    pub host: String,
+
+    #[serde(default = "Imap::default_port")]
+    pub port: u16,
+
+    pub username: String,
+    pub password: String,
+
+    #[serde(default = "Imap::default_mailbox")]
+    pub mailbox: String,
+
+    /// Polling interval in seconds
+    #[serde(default = "Imap::default_interval")]
+    pub interval: u64,
+
+    /// Ingest the plain text body of mails as a document on its own
+    #[serde(default)]
+    pub include_body: bool,
+
+    /// Delete processed mails instead of marking them as seen
+    #[serde(default)]
+    pub delete: bool,
+
+    #[serde(default)]
+    pub defaults: Defaults,
+}
+
+impl Imap {
+    fn default_port() -> u16 { 993 }
+
+    fn default_mailbox() -> String { String::from("INBOX") }
+
+    fn default_interval() -> u64 { 5 * 60 }
+}
+
+/// Strategy used to resolve metadata changed on both sides of a sync.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    Upstream,
+    Local,
+    Merge,
+}
+
+impl Default for Resolution {
+    fn default() -> Self { Self::Merge }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Satellite {
+    /// Base URL of the upstream instance
+    pub upstream: String,
+
+    /// API key used to authenticate against upstream
+    pub username: String,
+    pub password: String,
+
+    /// Only documents with at least one of these labels are synced
+    pub labels: Vec<String>,
+
+    /// Sync interval in seconds
+    #[serde(default = "Satellite::default_interval")]
+    pub interval: u64,
+
+    #[serde(default)]
+    pub resolution: Resolution,
+}
+
+impl Satellite {
+    fn default_interval() -> u64 { 15 * 60 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Backup {
+    /// Directory the backups are stored in
+    #[serde(default)]
+    pub path: Option<String>,
+
+    /// Bucket the backups are stored in, instead of a directory
+    #[serde(default)]
+    pub s3: Option<BackupS3>,
+
+    /// Backup interval in seconds
+    #[serde(default = "Backup::default_interval")]
+    pub interval: u64,
+
+    /// Number of days to keep the latest backup of
+    #[serde(default = "Backup::default_daily")]
+    pub daily: usize,
+
+    /// Number of weeks to keep the latest backup of
+    #[serde(default = "Backup::default_weekly")]
+    pub weekly: usize,
+}
+
+impl Backup {
+    fn default_interval() -> u64 { 24 * 60 * 60 }
+
+    fn default_daily() -> usize { 7 }
+
+    fn default_weekly() -> usize { 4 }
+}
+
+/// A maintenance task run by the scheduler.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaintenanceTask {
+    /// Index all archived documents again, i.e. after the index has been lost
+    Reindex,
+
+    /// Collect bundles left behind in the staging area
+    Orphans,
+
+    /// Purge expired bundles from the trash
+    Trash,
+
+    /// Back up the repository as configured
+    Backup,
+
+    /// Report documents stored more than once
+    Duplicates,
+}
+
+impl MaintenanceTask {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Self::Reindex => "reindex",
+            Self::Orphans => "orphans",
+            Self::Trash => "trash",
+            Self::Backup => "backup",
+            Self::Duplicates => "duplicates",
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Maintenance {
+    pub task: MaintenanceTask,
+
+    /// Seconds between two runs, the first one runs after the first interval
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackupS3 {
+    pub bucket: String,
+
+    /// Region of the bucket, i.e. `eu-central-1`
+    pub region: String,
+
+    /// Endpoint of an S3-compatible service, i.e. `https://minio.example.com`
+    #[serde(default)]
+    pub endpoint: Option<String>,
+
+    pub access_key: String,
+    pub secret_key: String,
+
+    /// Prefix of all objects stored in the bucket, i.e. `adacta/`
+    #[serde(default)]
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reminders {
+    /// Number of days before the due date to remind of a document
+    #[serde(default = "Reminders::default_lead")]
+    pub lead: u32,
+
+    /// Check interval in seconds
+    #[serde(default = "Reminders::default_interval")]
+    pub interval: u64,
+
+    /// URL the due documents are posted to as JSON
+    #[serde(default)]
+    pub webhook: Option<String>,
+
+    /// Mail the due documents to the configured recipients
+    #[serde(default)]
+    pub email: Option<Email>,
+}
+
+impl Reminders {
+    fn default_lead() -> u32 { 7 }
+
+    fn default_interval() -> u64 { 60 * 60 }
+}
+
+/// Events announced to chat channels.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationEvent {
+    /// New documents arrived in the inbox
+    Inbox,
+
+    /// Juicing a document failed permanently
+    Failures,
+
+    /// Documents have been moved to the quarantine
+    Quarantine,
+
+    /// Documents are approaching their due date, if reminders are configured
+    Due,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+pub enum ChatChannel {
+    Matrix(MatrixChannel),
+    Slack(SlackChannel),
+    Telegram(TelegramChannel),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatrixChannel {
+    /// Base URL of the homeserver, i.e. `https://matrix.org`
+    pub homeserver: String,
+
+    /// ID of the room to post to, i.e. `!abcdef:matrix.org`
+    pub room: String,
+
+    /// Access token of the user posting the messages
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlackChannel {
+    /// URL of the incoming webhook of the channel
+    pub webhook: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramChannel {
+    /// Token of the bot posting the messages
+    pub token: String,
+
+    /// ID of the chat to post to
+    pub chat: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Channel {
+    #[serde(flatten)]
+    pub channel: ChatChannel,
+
+    /// The events announced in the channel, all if unset
+    #[serde(default = "Channel::default_events")]
+    pub events: HashSet<NotificationEvent>,
+}
+
+impl Channel {
+    fn default_events() -> HashSet<NotificationEvent> {
+        return vec![NotificationEvent::Inbox, NotificationEvent::Failures, NotificationEvent::Quarantine, NotificationEvent::Due]
+            .into_iter()
+            .collect();
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Notifications {
+    /// Seconds events are collected for before they are announced at once
+    #[serde(default = "Notifications::default_interval")]
+    pub interval: u64,
+
+    pub channels: Vec<Channel>,
+}
+
+impl Notifications {
+    fn default_interval() -> u64 { 5 * 60 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Attestation {
+    /// File holding the hex encoded ed25519 signing key, which is generated if missing
+    pub key: String,
+
+    /// Attestation interval in seconds
+    #[serde(default = "Attestation::default_interval")]
+    pub interval: u64,
+
+    /// URL the digest and signature of each manifest are posted to as JSON, i.e. a timestamping service
+    #[serde(default)]
+    pub webhook: Option<String>,
+}
+
+impl Attestation {
+    fn default_interval() -> u64 { 24 * 60 * 60 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Timestamping {
+    /// URL of the RFC 3161 time-stamping authority, i.e. `https://freetsa.org/tsr`
+    pub url: String,
+
+    /// Credentials for authorities requiring basic authentication
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Email {
+    /// SMTP server used with STARTTLS on the submission port
+    pub host: String,
+
+    pub username: String,
+    pub password: String,
+
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mail {
+    /// SMTP server used with STARTTLS on the submission port
+    pub host: String,
+
+    pub username: String,
+    pub password: String,
+
+    pub from: String,
+
+    /// Maximal size of documents sent in bytes
+    #[serde(default = "Mail::default_max_size")]
+    pub max_size: u64,
+}
+
+impl Mail {
+    fn default_max_size() -> u64 { 10 * 1024 * 1024 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Filing {
+    /// Link encoded in the QR code, `{id}` and `{asn}` are replaced with the document ID and archive serial number
+    pub link: String,
+
+    /// Width of the label in millimeters
+    #[serde(default = "Filing::default_width")]
+    pub width: f64,
+
+    /// Height of the label in millimeters
+    #[serde(default = "Filing::default_height")]
+    pub height: f64,
+
+    /// Layout of label sheets used for printing many labels at once
+    #[serde(default)]
+    pub sheet: Sheet,
+}
+
+impl Filing {
+    fn default_width() -> f64 { 62.0 }
+
+    fn default_height() -> f64 { 29.0 }
+}
+
+/// Layout of a sheet of labels, all sizes in millimeters.
+///
+/// Defaults to an A4 sheet with 3 by 8 labels of 70 by 37 millimeters.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sheet {
+    #[serde(default = "Sheet::default_page_width")]
+    pub page_width: f64,
+
+    #[serde(default = "Sheet::default_page_height")]
+    pub page_height: f64,
+
+    #[serde(default = "Sheet::default_columns")]
+    pub columns: usize,
+
+    #[serde(default = "Sheet::default_rows")]
+    pub rows: usize,
+
+    #[serde(default = "Sheet::default_width")]
+    pub width: f64,
+
+    #[serde(default = "Sheet::default_height")]
+    pub height: f64,
+
+    /// Offset of the first label from the left edge of the page
+    #[serde(default)]
+    pub margin_left: f64,
+
+    /// Offset of the first label from the top edge of the page
+    #[serde(default = "Sheet::default_margin_top")]
+    pub margin_top: f64,
+
+    /// Horizontal space between adjacent labels
+    #[serde(default)]
+    pub gap_x: f64,
+
+    /// Vertical space between adjacent labels
+    #[serde(default)]
+    pub gap_y: f64,
+}
+
+impl Sheet {
+    fn default_page_width() -> f64 { 210.0 }
+
+    fn default_page_height() -> f64 { 297.0 }
+
+    fn default_columns() -> usize { 3 }
+
+    fn default_rows() -> usize { 8 }
+
+    fn default_width() -> f64 { 70.0 }
+
+    fn default_height() -> f64 { 37.0 }
+
+    fn default_margin_top() -> f64 { 0.5 }
+}
+
+impl Default for Sheet {
+    fn default() -> Self {
+        return Self {
+            page_width: Self::default_page_width(),
+            page_height: Self::default_page_height(),
+            columns: Self::default_columns(),
+            rows: Self::default_rows(),
+            width: Self::default_width(),
+            height: Self::default_height(),
+            margin_left: 0.0,
+            margin_top: Self::default_margin_top(),
+            gap_x: 0.0,
+            gap_y: 0.0,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Domain {
+    pub name: String,
+
+    /// Documents with any of these labels are encrypted with the domain key
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Web {
+    pub address: String,
+    pub port: u16,
+
+    /// Seconds during which deleting and archiving documents can be reverted
+    #[serde(default = "Web::default_undo_window")]
+    pub undo_window: u64,
+
+    #[serde(default)]
+    pub frontend: Frontend,
+
+    #[serde(default)]
+    pub cors: Cors,
+
+    /// Addresses of reverse proxies whose `Forwarded` and `X-Forwarded-*` headers are trusted
+    #[serde(default)]
+    pub proxies: Vec<String>,
+
+    /// Terminate TLS instead of serving plain HTTP, if not done by a reverse proxy
+    #[serde(default)]
+    pub tls: Option<Tls>,
+
+    /// Listen on a unix domain socket instead of the TCP address and port
+    #[serde(default)]
+    pub socket: Option<Socket>,
+
+    /// Namespace qualifying the IDs of documents of this instance, i.e. if multiple instances are synced together
+    #[serde(default)]
+    pub namespace: Option<String>,
+
+    /// Metadata applied to scans by the profile selected by the scanner, i.e. by the number of the button pressed
+    #[serde(default)]
+    pub scanner: HashMap<String, Defaults>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dav {
+    pub address: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Grpc {
+    pub address: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Socket {
+    pub path: String,
+
+    /// Permissions of the socket as octal mode
+    #[serde(default = "Socket::default_mode")]
+    pub mode: String,
+}
+
+impl Socket {
+    fn default_mode() -> String { String::from("0660") }
+}
+
+impl Web {
+    fn default_undo_window() -> u64 { 30 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tls {
+    Files(TlsFiles),
+    Acme(Acme),
+}
+
+impl Tls {
+    /// Path of the PEM encoded certificate chain.
+    pub fn certs(&self) -> String {
+        return match self {
+            Self::Files(files) => files.certs.clone(),
+            Self::Acme(acme) => format!("{}/certs.pem", acme.path),
+        };
+    }
+
+    /// Path of the PEM encoded private key.
+    pub fn key(&self) -> String {
+        return match self {
+            Self::Files(files) => files.key.clone(),
+            Self::Acme(acme) => format!("{}/key.pem", acme.path),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsFiles {
+    /// PEM encoded certificate chain
+    pub certs: String,
+
+    /// PEM encoded private key
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Acme {
+    /// Domains the certificate is issued for, the first one is used as the common name
+    pub domains: Vec<String>,
+
+    /// Contact address of the ACME account
+    pub email: String,
+
+    /// Directory URL of the ACME server
+    #[serde(default = "Acme::default_directory")]
+    pub directory: String,
+
+    /// Directory to store the account, the certificate and the private key in
+    pub path: String,
+
+    /// Port to answer HTTP-01 challenges on, which must be reachable as port 80 of the domains
+    #[serde(default = "Acme::default_http_port")]
+    pub http_port: u16,
+
+    /// Days before expiry to renew the certificate
+    #[serde(default = "Acme::default_renew_days")]
+    pub renew_days: i64,
+}
+
+impl Acme {
+    fn default_directory() -> String { String::from("https://acme-v02.api.letsencrypt.org/directory") }
+
+    fn default_http_port() -> u16 { 80 }
+
+    fn default_renew_days() -> i64 { 30 }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Frontend {
+    /// Serve the frontend alongside the API, disable if it is served separately
+    #[serde(default = "Frontend::default_enabled")]
+    pub enabled: bool,
+
+    /// Directory containing the compiled frontend, the frontend embedded at build time is served if not set
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl Frontend {
+    fn default_enabled() -> bool { true }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cors {
+    /// Origins allowed to access the API from browsers, `*` allows all origins
+    #[serde(default)]
+    pub origins: Vec<String>,
+
+    /// Seconds browsers may cache the answer to preflight requests
+    #[serde(default = "Cors::default_max_age")]
+    pub max_age: u64,
+}
+
+impl Cors {
+    fn default_max_age() -> u64 { 60 * 60 }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        return Self {
+            origins: Vec::new(),
+            max_age: Self::default_max_age(),
+        };
+    }
+}
+
+impl Default for Frontend {
+    fn default() -> Self {
+        return Self {
+            enabled: Self::default_enabled(),
+            path: None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tracing {
+    /// OTLP collector the spans are exported to
+    #[serde(default = "Tracing::default_endpoint")]
+    pub endpoint: String,
+
+    /// Name of the service the spans are reported for
+    #[serde(default = "Tracing::default_service")]
+    pub service: String,
+}
+
+impl Tracing {
+    fn default_endpoint() -> String { String::from("localhost:4317") }
+
+    fn default_service() -> String { String::from("adacta") }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub auth: Auth,
+
+    pub repository: Repository,
+
+    /// Repositories served in addition to the default one, by the name scoping the API paths
+    #[serde(default)]
+    pub repositories: BTreeMap<String, NamedRepository>,
+
+    pub index: Index,
+    pub juicer: Juicer,
+    pub suggester: Suggester,
+
+    #[serde(default)]
+    pub queue: Queue,
+
+    /// Collect staged bundles which are never going to be processed
+    #[serde(default)]
+    pub orphans: Orphans,
+
+    /// Prepare the renditions shown first for archived documents
+    #[serde(default)]
+    pub warmup: Warmup,
+
+    /// Render previews of pages in the requested size on demand
+    #[serde(default)]
+    pub previews: Previews,
+
+    /// Merge documents arriving as separate scans
+    #[serde(default)]
+    pub merge: Merge,
+
+    /// Rotate, reorder and drop the pages of archived documents
+    #[serde(default)]
+    pub editing: Editing,
+
+    /// Limit the storage used by the documents of repositories and users
+    #[serde(default)]
+    pub quota: Quota,
+
+    /// Enrich the metadata of archived documents from their fragments
+    #[serde(default)]
+    pub processors: Vec<Processor>,
+
+    /// Transcribe audio attached to documents
+    #[serde(default)]
+    pub transcription: Option<Transcription>,
+
+    #[serde(default)]
+    pub consume: Option<Consume>,
+
+    #[serde(default)]
+    pub imap: Option<Imap>,
+
+    #[serde(default)]
+    pub satellite: Option<Satellite>,
+
+    #[serde(default)]
+    pub backup: Option<Backup>,
+
+    /// Run maintenance tasks at fixed intervals
+    #[serde(default)]
+    pub maintenance: Vec<Maintenance>,
+
+    /// Notify about documents approaching their due date
+    #[serde(default)]
+    pub reminders: Option<Reminders>,
+
+    /// Announce new documents and failures in chat channels
+    #[serde(default)]
+    pub notifications: Option<Notifications>,
+
+    /// Sign manifests of all bundle checksums to prove documents existed unmodified
+    #[serde(default)]
+    pub attestation: Option<Attestation>,
+
+    /// Obtain trusted timestamps for archived documents
+    #[serde(default)]
+    pub timestamping: Option<Timestamping>,
+
+    /// Render labels for filing the paper originals of archived documents
+    #[serde(default)]
+    pub filing: Option<Filing>,
+
+    /// Send archived documents by mail
+    #[serde(default)]
+    pub mail: Option<Mail>,
+
+    /// Encryption domains for sensitive documents
+    #[serde(default)]
+    pub domains: Vec<Domain>,
+
+    /// Export spans of the ingest pipeline via OpenTelemetry
+    #[serde(default)]
+    pub tracing: Option<Tracing>,
+
+    /// Serve a read-only view of the archive via WebDAV
+    #[serde(default)]
+    pub dav: Option<Dav>,
+
+    /// Serve the core operations via gRPC for integrations of other services
+    #[serde(default)]
+    pub grpc: Option<Grpc>,
+
+    pub web: Web,
+}
+
+/// Prefix of the environment variables overriding the configuration file
+const ENV_PREFIX: &str = "ADACTA_";
+
+/// Overrides settings of the configuration by environment variables like `ADACTA_WEB__PORT=8080`.
+///
+/// The name following the prefix is the path of the setting, its segments separated by double underscores. Segments
+/// match existing keys regardless of case, while new keys are taken in lowercase unless written in mixed case, so
+/// entries of maps like `ADACTA_AUTH__API_KEYS__Scanner` keep their case. Numeric segments index lists, missing
+/// sections are created.
+///
+/// Values replacing a string are taken as they are, other values are parsed as YAML, so quoting forces a string.
+fn overlay(config: &mut Value, vars: impl IntoIterator<Item=(String, String)>) -> Result<()> {
+    for (name, value) in vars {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            Some(path) if !path.is_empty() => path,
+            _ => continue,
+        };
+
+        let mut target = &mut *config;
+        for segment in path.split("__") {
+            target = match target {
+                Value::Sequence(items) => {
+                    let index = segment.parse::<usize>()
+                        .with_context(|| format!("Invalid list index in {}: {}", name, segment))?;
+                    items.get_mut(index)
+                        .ok_or_else(|| anyhow!("No such list item in {}: {}", name, index))?
+                }
+                other => {
+                    if !other.is_mapping() {
+                        *other = Value::Mapping(Mapping::new());
+                    }
+
+                    let mapping = match other {
+                        Value::Mapping(mapping) => mapping,
+                        _ => unreachable!(),
+                    };
+
+                    let key = overlay_key(mapping, segment)
+                        .with_context(|| format!("Ambiguous key in {}: {}", name, segment))?;
+                    if !mapping.contains_key(&key) {
+                        mapping.insert(key.clone(), Value::Null);
+                    }
+
+                    mapping.get_mut(&key).expect("Key inserted")
+                }
+            };
+        }
+
+        *target = overlay_value(target, &value)
+            .with_context(|| format!("Invalid value of {}", name))?;
+    }
+
+    return Ok(());
+}
+
+/// Resolves a path segment to the key of a mapping, preferring an exact match over one ignoring case.
+fn overlay_key(mapping: &Mapping, segment: &str) -> Result<Value> {
+    let key = Value::String(segment.to_string());
+    if mapping.contains_key(&key) {
+        return Ok(key);
+    }
+
+    let mut matching = mapping.iter()
+        .map(|(key, _)| key)
+        .filter(|key| key.as_str().map_or(false, |key| key.eq_ignore_ascii_case(segment)));
+
+    return match (matching.next(), matching.next()) {
+        (Some(key), None) => Ok(key.clone()),
+        (Some(_), Some(_)) => Err(anyhow!("Multiple keys match ignoring case")),
+        (None, _) if segment.chars().any(char::is_lowercase) => Ok(key),
+        (None, _) => Ok(Value::String(segment.to_lowercase())),
+    };
+}
+
+/// Converts the value of a variable for the setting it replaces.
+fn overlay_value(target: &Value, value: &str) -> Result<Value> {
+    let parsed: Value = match serde_yaml::from_str(value) {
+        Ok(parsed) => parsed,
+        Err(_) if target.is_string() => return Ok(Value::String(value.to_string())),
+        Err(err) => return Err(err.into()),
+    };
+
+    return Ok(match parsed {
+        // Strings stay strings, even if they look like numbers or are not valid YAML
+        parsed if target.is_string() && !parsed.is_string() => Value::String(value.to_string()),
+
+        // Numbers not written in their canonical form, i.e. with leading zeros, would lose digits
+        Value::Number(number) if !number.is_f64() && number.to_string() != value.trim() => Value::String(value.to_string()),
+
+        parsed => parsed,
+    });
+}
+
+impl Config {
+    /// Loads the configuration file and applies the overrides from the environment.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = OpenOptions::new().read(true).open(path).await?;
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).await?;
+
+        let mut config: Value = serde_yaml::from_slice(&buffer)?;
+        overlay(&mut config, std::env::vars())?;
+
+        Ok(serde_yaml::from_value(config)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        return vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    }
+
+    #[test]
+    fn test_overlay() {
+        let mut config: Value = serde_yaml::from_str("
+            web:
+              port: 8080
+            ingest:
+              - path: /scans
+              - path: /mail
+        ").unwrap();
+
+        overlay(&mut config, vars(&[
+            ("ADACTA_WEB__PORT", "9090"),
+            ("ADACTA_WEB__ADDRESS", "'::1'"),
+            ("ADACTA_JUICER__IMAGE", "juicer:latest"),
+            ("ADACTA_WEB__UNDO_WINDOW", "60"),
+            ("ADACTA_INGEST__1__PATH", "/post"),
+            ("HOME", "/root"),
+        ])).unwrap();
+
+        assert_that!(config["web"]["port"].as_u64()).is_equal_to(Some(9090));
+        assert_that!(config["web"]["address"].as_str()).is_equal_to(Some("::1"));
+        assert_that!(config["juicer"]["image"].as_str()).is_equal_to(Some("juicer:latest"));
+        assert_that!(config["web"]["undo_window"].as_u64()).is_equal_to(Some(60));
+        assert_that!(config["ingest"][0]["path"].as_str()).is_equal_to(Some("/scans"));
+        assert_that!(config["ingest"][1]["path"].as_str()).is_equal_to(Some("/post"));
+        assert_that!(config.get("home")).is_none();
+
+        assert_that!(overlay(&mut config, vars(&[("ADACTA_INGEST__2__PATH", "/fax")]))).is_err();
+        assert_that!(overlay(&mut config, vars(&[("ADACTA_INGEST__FIRST", "/fax")]))).is_err();
+    }
+
+    #[test]
+    fn test_overlay_strings() {
+        let mut config: Value = serde_yaml::from_str("
+            auth:
+              secret: my secret
+              passhash: hash
+              max_attempts: 5
+        ").unwrap();
+
+        overlay(&mut config, vars(&[
+            ("ADACTA_AUTH__SECRET", "123456"),
+            ("ADACTA_AUTH__PASSHASH", "'quoted'"),
+            ("ADACTA_AUTH__MAX_ATTEMPTS", "3"),
+            ("ADACTA_AUTH__USERNAME", "007"),
+        ])).unwrap();
+
+        assert_that!(config["auth"]["secret"].as_str()).is_equal_to(Some("123456"));
+        assert_that!(config["auth"]["passhash"].as_str()).is_equal_to(Some("quoted"));
+        assert_that!(config["auth"]["max_attempts"].as_u64()).is_equal_to(Some(3));
+        assert_that!(config["auth"]["username"].as_str()).is_equal_to(Some("007"));
+    }
+
+    #[test]
+    fn test_overlay_key_case() {
+        let mut config: Value = serde_yaml::from_str("
+            auth:
+              api_keys:
+                Scanner: old
+            repositories:
+              Family:
+                path: /family
+        ").unwrap();
+
+        overlay(&mut config, vars(&[
+            ("ADACTA_AUTH__API_KEYS__Scanner", "new"),
+            ("ADACTA_AUTH__API_KEYS__Phone", "phone"),
+            ("ADACTA_REPOSITORIES__FAMILY__PATH", "/shared"),
+        ])).unwrap();
+
+        assert_that!(config["auth"]["api_keys"]["Scanner"].as_str()).is_equal_to(Some("new"));
+        assert_that!(config["auth"]["api_keys"]["Phone"].as_str()).is_equal_to(Some("phone"));
+        assert_that!(config["repositories"]["Family"]["path"].as_str()).is_equal_to(Some("/shared"));
+        assert_that!(config["repositories"].get("family")).is_none();
+
+        let mut config: Value = serde_yaml::from_str("
+            auth:
+              api_keys:
+                scanner: lower
+                Scanner: upper
+        ").unwrap();
 
-        Ok(serde_yaml::from_slice(&buffer)?)
+        assert_that!(overlay(&mut config, vars(&[("ADACTA_AUTH__API_KEYS__SCANNER", "new")]))).is_err();
     }
 }
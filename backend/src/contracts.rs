@@ -0,0 +1,68 @@
+use chrono::NaiveDate;
+
+use crate::meta::Metadata;
+use crate::period::Period;
+use crate::proto::model::PropertyValue;
+
+/// Property holding the day a contract ends unless cancelled, which makes a document a contract
+pub const TERM_END: &str = "term-end";
+
+/// Property holding the notice period of a contract, i.e. `3 months`, a plain number is taken as months
+pub const NOTICE_PERIOD: &str = "notice-period";
+
+/// Returns the term end and notice period of a document typed as contract.
+///
+/// Documents without a term end are not contracts. A contract without a notice period can be cancelled up to the
+/// term end.
+pub fn terms(metadata: &Metadata) -> Option<(NaiveDate, Period)> {
+    let end = match metadata.properties.get(TERM_END)? {
+        PropertyValue::Date(end) => *end,
+        _ => return None,
+    };
+
+    let notice = match metadata.properties.get(NOTICE_PERIOD) {
+        Some(PropertyValue::Integer(months)) if *months >= 0 => Period::Months(*months as u32),
+        Some(PropertyValue::String(notice)) => notice.parse().ok()?,
+        Some(_) => return None,
+        None => Period::Days(0),
+    };
+
+    return Some((end, notice));
+}
+
+/// Returns the last day a contract can be cancelled before it ends or renews.
+pub fn cancel_by(metadata: &Metadata) -> Option<NaiveDate> {
+    return terms(metadata).map(|(end, notice)| notice.before(end));
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn contract(end: NaiveDate, notice: Option<PropertyValue>) -> Metadata {
+        let mut metadata = Metadata::new();
+        metadata.properties.insert(TERM_END.to_string(), PropertyValue::Date(end));
+        if let Some(notice) = notice {
+            metadata.properties.insert(NOTICE_PERIOD.to_string(), notice);
+        }
+
+        return metadata;
+    }
+
+    #[test]
+    fn test_cancel_by() {
+        let end = NaiveDate::from_ymd(2021, 5, 31);
+
+        assert_that!(cancel_by(&contract(end, Some(PropertyValue::String(String::from("3 months"))))))
+            .is_equal_to(Some(NaiveDate::from_ymd(2021, 2, 28)));
+        assert_that!(cancel_by(&contract(end, Some(PropertyValue::Integer(6)))))
+            .is_equal_to(Some(NaiveDate::from_ymd(2020, 11, 30)));
+        assert_that!(cancel_by(&contract(end, Some(PropertyValue::String(String::from("4 weeks"))))))
+            .is_equal_to(Some(NaiveDate::from_ymd(2021, 5, 3)));
+        assert_that!(cancel_by(&contract(end, None))).is_equal_to(Some(end));
+
+        assert_that!(cancel_by(&Metadata::new())).is_none();
+    }
+}
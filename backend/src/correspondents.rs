@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+
+use crate::meta::Metadata;
+use crate::proto::model::Correspondent;
+
+/// Repository-wide registry of correspondents.
+///
+/// Correspondents are identified by their unique name, which is referenced by the metadata of their documents.
+pub struct Correspondents {
+    path: PathBuf,
+    correspondents: RwLock<BTreeMap<String, Correspondent>>,
+}
+
+impl Correspondents {
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let correspondents = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        return Ok(Self { path, correspondents: RwLock::new(correspondents) });
+    }
+
+    async fn save(&self, correspondents: &BTreeMap<String, Correspondent>) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(correspondents)?).await?;
+        return Ok(());
+    }
+
+    pub async fn list(&self) -> Vec<Correspondent> {
+        return self.correspondents.read().await.values().cloned().collect();
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Correspondent> {
+        return self.correspondents.read().await.get(name).cloned();
+    }
+
+    /// Registers a correspondent or replaces the existing one with the same name.
+    pub async fn define(&self, correspondent: Correspondent) -> Result<()> {
+        let mut correspondents = self.correspondents.write().await;
+        correspondents.insert(correspondent.name.clone(), correspondent);
+        return self.save(&correspondents).await;
+    }
+
+    /// Removes a correspondent from the registry and returns whether it was registered.
+    ///
+    /// Documents referring to the correspondent are not changed.
+    pub async fn remove(&self, name: &str) -> Result<bool> {
+        let mut correspondents = self.correspondents.write().await;
+        if correspondents.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&correspondents).await?;
+
+        return Ok(true);
+    }
+
+    /// Finds the correspondent of a document by its text.
+    ///
+    /// If multiple correspondents are mentioned, the one mentioned first wins as the sender is usually printed on top.
+    pub async fn identify(&self, text: &str) -> Option<Correspondent> {
+        return self.correspondents.read().await.values()
+            .filter_map(|correspondent| correspondent.find_in(text).map(|position| (position, correspondent)))
+            .min_by_key(|(position, _)| *position)
+            .map(|(_, correspondent)| correspondent.clone());
+    }
+
+    /// Assigns the correspondent identified by the text to the document and adds the default labels of the
+    /// correspondent, unless the document already has a correspondent.
+    ///
+    /// Returns whether the metadata has been changed.
+    pub async fn assign(&self, metadata: &mut Metadata, text: &str) -> bool {
+        if metadata.correspondent.is_some() {
+            return false;
+        }
+
+        let correspondent = match self.identify(text).await {
+            Some(correspondent) => correspondent,
+            None => return false,
+        };
+
+        metadata.correspondent = Some(correspondent.name);
+        metadata.labels.extend(correspondent.labels);
+
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use spectral::prelude::*;
+
+    use crate::proto::model::Label;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_assign() {
+        let correspondents = Correspondents::load(tempfile::tempdir().unwrap().into_path().join("correspondents.json")).await.unwrap();
+
+        correspondents.define(Correspondent {
+            name: String::from("Stadtwerke"),
+            aliases: vec![String::from("SWM GmbH")],
+            labels: vec![Label::from("utilities")].into_iter().collect(),
+        }).await.unwrap();
+
+        correspondents.define(Correspondent {
+            name: String::from("ACME"),
+            aliases: vec![],
+            labels: HashSet::new(),
+        }).await.unwrap();
+
+        let mut metadata = Metadata::new();
+        assert_that!(correspondents.assign(&mut metadata, "swm gmbh\nInvoice\nYour order at Acme").await).is_true();
+        assert_that!(metadata.correspondent.as_deref()).is_equal_to(Some("Stadtwerke"));
+        assert_that!(metadata.labels.contains(&Label::from("utilities"))).is_true();
+
+        // Assigned correspondents are kept
+        assert_that!(correspondents.assign(&mut metadata, "ACME").await).is_false();
+
+        // Names are only matched as whole words
+        let mut metadata = Metadata::new();
+        assert_that!(correspondents.assign(&mut metadata, "acmeville").await).is_false();
+        assert_that!(metadata.correspondent).is_none();
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, NewAead};
+use hmac::Hmac;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::RwLock;
+
+use crate::config::Domain;
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+
+/// Number of PBKDF2 rounds used to derive domain keys from passphrases
+const ROUNDS: u32 = 100_000;
+
+/// Length of the nonce prepended to each encrypted fragment
+const NONCE_LEN: usize = 12;
+
+/// Plaintext encrypted with the domain key to verify passphrases
+const CHECK: &[u8] = b"adacta";
+
+/// Persistent parameters of an encryption domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Params {
+    salt: String,
+    check: String,
+}
+
+/// Checks if the fragment holds contents of the document and is encrypted for documents of encryption domains.
+pub fn is_sensitive(kind: &Kind) -> bool {
+    return matches!(kind, Kind::Document | Kind::Preview | Kind::Page(_) | Kind::Thumbnail | Kind::Plaintext);
+}
+
+/// Lists all fragments of a document which are encrypted for documents of encryption domains.
+///
+/// Fragments which have not been rendered for the document are included and must be skipped by the caller.
+pub fn sensitive(metadata: &Metadata) -> Vec<Kind> {
+    let mut kinds = vec![Kind::Document, Kind::Preview, Kind::Thumbnail, Kind::Plaintext];
+    kinds.extend((1..=metadata.pages).map(Kind::Page));
+
+    return kinds;
+}
+
+/// Encrypts data with the given key.
+///
+/// The random nonce is prepended to the ciphertext.
+pub fn encrypt(key: &Key, data: &[u8]) -> Result<Vec<u8>> {
+    let nonce = rand::thread_rng().gen::<[u8; NONCE_LEN]>();
+
+    let ciphertext = ChaCha20Poly1305::new(key)
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+
+    let mut result = nonce.to_vec();
+    result.extend(ciphertext);
+
+    return Ok(result);
+}
+
+pub fn decrypt(key: &Key, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        return Err(anyhow!("Encrypted data truncated"));
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+
+    return ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Decryption failed"));
+}
+
+/// Keys of the encryption domains unlocked by the users.
+///
+/// Documents carrying a label assigned to a domain are encrypted with the domain key when archived. The key is derived
+/// from a passphrase which must be provided per user and expires after a period of inactivity.
+pub struct Keyring {
+    domains: Vec<Domain>,
+
+    path: PathBuf,
+
+    /// Unlocked keys by user and domain with the time of last use
+    keys: RwLock<HashMap<(String, String), (Key, Instant)>>,
+}
+
+impl Keyring {
+    /// Time after which an unused key gets locked again
+    const TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+    pub async fn new(domains: Vec<Domain>, path: PathBuf) -> Result<Self> {
+        tokio::fs::create_dir_all(&path).await?;
+
+        return Ok(Self {
+            domains,
+            path,
+            keys: RwLock::new(HashMap::new()),
+        });
+    }
+
+    pub fn domains(&self) -> &[Domain] { &self.domains }
+
+    /// Returns the domain the document belongs to, if any.
+    pub fn domain_of(&self, metadata: &Metadata) -> Option<&str> {
+        return self.domains.iter()
+            .find(|domain| domain.labels.iter().any(|label| metadata.labels.contains(label.as_str())))
+            .map(|domain| domain.name.as_str());
+    }
+
+    fn derive(passphrase: &str, salt: &[u8]) -> Key {
+        let mut key = Key::default();
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, ROUNDS, &mut key);
+        return key;
+    }
+
+    /// Unlocks a domain for the user and returns whether the passphrase was correct.
+    ///
+    /// The first unlock of a domain sets its passphrase.
+    pub async fn unlock(&self, user: &str, domain: &str, passphrase: &str) -> Result<bool> {
+        if !self.domains.iter().any(|d| d.name == domain) {
+            return Err(anyhow!("Unknown encryption domain: {}", domain));
+        }
+
+        let path = self.path.join(format!("{}.json", hex::encode(domain)));
+
+        let key = match tokio::fs::read(&path).await {
+            Ok(data) => {
+                let params = serde_json::from_slice::<Params>(&data)?;
+
+                let key = Self::derive(passphrase, &hex::decode(&params.salt)?);
+                if decrypt(&key, &hex::decode(&params.check)?).is_err() {
+                    return Ok(false);
+                }
+
+                key
+            }
+
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let salt = rand::thread_rng().gen::<[u8; 16]>();
+
+                let key = Self::derive(passphrase, &salt);
+                let params = Params {
+                    salt: hex::encode(salt),
+                    check: hex::encode(encrypt(&key, CHECK)?),
+                };
+
+                tokio::fs::write(&path, serde_json::to_vec_pretty(&params)?).await?;
+
+                key
+            }
+
+            Err(err) => return Err(err.into()),
+        };
+
+        self.keys.write().await.insert((user.to_string(), domain.to_string()), (key, Instant::now()));
+
+        return Ok(true);
+    }
+
+    pub async fn lock(&self, user: &str, domain: &str) {
+        self.keys.write().await.remove(&(user.to_string(), domain.to_string()));
+    }
+
+    /// Returns the key of an unlocked domain.
+    pub async fn key(&self, user: &str, domain: &str) -> Option<Key> {
+        let mut keys = self.keys.write().await;
+
+        let entry = (user.to_string(), domain.to_string());
+        let (key, used) = keys.get_mut(&entry)?;
+
+        if used.elapsed() > Self::TIMEOUT {
+            keys.remove(&entry);
+            return None;
+        }
+
+        *used = Instant::now();
+
+        return Some(*key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = Keyring::derive("secret", b"salt");
+
+        let encrypted = encrypt(&key, b"my document").unwrap();
+        assert_that!(decrypt(&key, &encrypted).unwrap()).is_equal_to(b"my document".to_vec());
+
+        let other = Keyring::derive("other", b"salt");
+        assert_that!(decrypt(&other, &encrypted).is_err()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_unlock() {
+        let path = tempfile::tempdir().unwrap();
+        let domains = vec![Domain { name: String::from("medical"), labels: vec![String::from("medical")] }];
+        let keyring = Keyring::new(domains, path.path().to_path_buf()).await.unwrap();
+
+        assert_that!(keyring.unlock("admin", "medical", "secret").await.unwrap()).is_true();
+        assert_that!(keyring.key("admin", "medical").await).is_some();
+        assert_that!(keyring.key("other", "medical").await).is_none();
+
+        keyring.lock("admin", "medical").await;
+        assert_that!(keyring.key("admin", "medical").await).is_none();
+
+        assert_that!(keyring.unlock("admin", "medical", "wrong").await.unwrap()).is_false();
+        assert_that!(keyring.unlock("admin", "medical", "secret").await.unwrap()).is_true();
+    }
+}
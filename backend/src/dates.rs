@@ -0,0 +1,166 @@
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+
+use crate::meta::Metadata;
+
+/// Earliest year accepted as the date of a document, older years are most likely part of other numbers
+const MIN_YEAR: i32 = 1900;
+
+/// Wording which marks the date of a document, like `Rechnungsdatum: 01.02.2021` or `Date: March 3, 2021`
+const MARKERS: &[&str] = &["datum", "date", "dated", "vom", "issued"];
+
+/// Wording which marks other dates, like the due date of an invoice, which are never taken as date of the document
+const OTHER: &[&str] = &["due", "fällig", "zahlbar", "bis", "until", "valid"];
+
+/// Number of characters before a date on the same line which are searched for the markers
+const CONTEXT: usize = 24;
+
+/// Parses english and german month names and their abbreviations.
+fn month(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let name = name.trim_end_matches('.');
+
+    let months: [&[&str]; 12] = [
+        &["january", "januar", "jan", "jänner"],
+        &["february", "februar", "feb"],
+        &["march", "märz", "mar", "mär"],
+        &["april", "apr"],
+        &["may", "mai"],
+        &["june", "juni", "jun"],
+        &["july", "juli", "jul"],
+        &["august", "aug"],
+        &["september", "sept", "sep"],
+        &["october", "oktober", "oct", "okt"],
+        &["november", "nov"],
+        &["december", "dezember", "dec", "dez"],
+    ];
+
+    return months.iter()
+        .position(|names| names.contains(&name))
+        .map(|month| month as u32 + 1);
+}
+
+/// Expands two-digit years to this century.
+fn year(year: &str) -> Option<i32> {
+    let value = year.parse::<i32>().ok()?;
+    return Some(if year.len() == 2 { 2000 + value } else { value });
+}
+
+/// Finds all dates in the text along with their position.
+///
+/// Recognizes ISO dates like `2021-02-01`, day-first numeric dates like `01.02.2021` or `1/2/21` and dates with the
+/// month spelled out like `1. Februar 2021` or `February 1, 2021`.
+fn candidates(text: &str) -> Vec<(usize, NaiveDate)> {
+    let name = r"(\p{L}{3,9}\.?)";
+
+    let patterns: [(&str, fn(&regex::Captures) -> Option<NaiveDate>); 4] = [
+        (r"\b(\d{4})-(\d{2})-(\d{2})\b",
+         |c| NaiveDate::from_ymd_opt(year(&c[1])?, c[2].parse().ok()?, c[3].parse().ok()?)),
+        (r"\b(\d{1,2})[./](\d{1,2})[./](\d{4}|\d{2})\b",
+         |c| NaiveDate::from_ymd_opt(year(&c[3])?, c[2].parse().ok()?, c[1].parse().ok()?)),
+        (r"\b(\d{1,2})\.?\s+MONTH\s+(\d{4})\b",
+         |c| NaiveDate::from_ymd_opt(year(&c[3])?, self::month(&c[2])?, c[1].parse().ok()?)),
+        (r"\bMONTH\s+(\d{1,2}),?\s+(\d{4})\b",
+         |c| NaiveDate::from_ymd_opt(year(&c[3])?, self::month(&c[1])?, c[2].parse().ok()?)),
+    ];
+
+    let mut candidates = patterns.iter()
+        .flat_map(|(pattern, parse)| {
+            let pattern = Regex::new(&pattern.replace("MONTH", name)).expect("Invalid date pattern");
+            pattern.captures_iter(text)
+                .filter_map(|captures| Some((captures.get(0)?.start(), parse(&captures)?)))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by_key(|(position, _)| *position);
+
+    return candidates;
+}
+
+/// Finds the most plausible date of the document in its text, like the date of an invoice or a letter.
+///
+/// Only dates between 1900 and the given day are considered. Dates marked as date of the document by the wording before
+/// them are preferred, otherwise the first date not marked as other date is taken, as letters and invoices usually
+/// start with their date.
+pub fn detect(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let candidates = candidates(text).into_iter()
+        .filter(|(_, date)| date.year() >= MIN_YEAR && *date <= today)
+        .map(|(position, date)| {
+            let line = &text[..position];
+            let line = &line[line.rfind('\n').map_or(0, |i| i + 1)..];
+
+            let context = line.chars().rev().take(CONTEXT).collect::<String>()
+                .chars().rev().collect::<String>()
+                .to_lowercase();
+
+            let other = OTHER.iter().any(|other| context.contains(other));
+            let marked = !other && MARKERS.iter().any(|marker| context.contains(marker));
+
+            (date, marked, other)
+        })
+        .collect::<Vec<_>>();
+
+    return candidates.iter()
+        .find(|(_, marked, _)| *marked)
+        .or_else(|| candidates.iter().find(|(_, _, other)| !*other))
+        .map(|(date, _, _)| *date);
+}
+
+/// Proposes the date found in the text unless the metadata has one already.
+///
+/// Returns true if the date has been proposed.
+pub fn suggest(metadata: &mut Metadata, text: &str, today: NaiveDate) -> bool {
+    if metadata.date.is_some() {
+        return false;
+    }
+
+    metadata.date = detect(text, today);
+    return metadata.date.is_some();
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn today() -> NaiveDate { NaiveDate::from_ymd(2021, 6, 1) }
+
+    #[test]
+    fn test_detect_formats() {
+        assert_that!(detect("2021-02-01", today())).is_equal_to(Some(NaiveDate::from_ymd(2021, 2, 1)));
+        assert_that!(detect("01.02.2021", today())).is_equal_to(Some(NaiveDate::from_ymd(2021, 2, 1)));
+        assert_that!(detect("1/2/21", today())).is_equal_to(Some(NaiveDate::from_ymd(2021, 2, 1)));
+        assert_that!(detect("Berlin, 1. Februar 2021", today())).is_equal_to(Some(NaiveDate::from_ymd(2021, 2, 1)));
+        assert_that!(detect("London, February 1, 2021", today())).is_equal_to(Some(NaiveDate::from_ymd(2021, 2, 1)));
+        assert_that!(detect("Total 24.99 EUR", today())).is_none();
+        assert_that!(detect("32.01.2021", today())).is_none();
+    }
+
+    #[test]
+    fn test_detect_plausible() {
+        // Dates in the future and from long ago are no dates of the document
+        assert_that!(detect("Valid until 2030-01-01", today())).is_none();
+        assert_that!(detect("Founded 01.01.1850", today())).is_none();
+
+        // Marked dates are preferred over the first date
+        let text = "Customer since 01.03.2015\nRechnungsdatum: 15.04.2021\nZahlbar bis 30.04.2021";
+        assert_that!(detect(text, today())).is_equal_to(Some(NaiveDate::from_ymd(2021, 4, 15)));
+
+        // Due dates are skipped even without a marked date
+        let text = "Due: 2021-05-01\nOrder 2021-04-20";
+        assert_that!(detect(text, today())).is_equal_to(Some(NaiveDate::from_ymd(2021, 4, 20)));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let mut metadata = Metadata::new();
+        assert_that!(suggest(&mut metadata, "Datum: 15.04.2021", today())).is_true();
+        assert_that!(metadata.date).is_equal_to(Some(NaiveDate::from_ymd(2021, 4, 15)));
+
+        // Dates set already are kept
+        assert_that!(suggest(&mut metadata, "Datum: 16.04.2021", today())).is_false();
+        assert_that!(metadata.date).is_equal_to(Some(NaiveDate::from_ymd(2021, 4, 15)));
+    }
+}
@@ -0,0 +1,239 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::Editing as Config;
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+use crate::queue::Queue;
+use crate::repository::{Archived, Bundle, Repository, Staging, Version};
+
+/// Renditions regenerated from the edited document, besides the page previews.
+const RENDITIONS: &[Kind] = &[Kind::Plaintext, Kind::Preview, Kind::Thumbnail];
+
+/// A page of the edited document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    /// The page of the current document, counting from one
+    pub page: u32,
+
+    /// Clockwise rotation in degrees, a multiple of 90
+    pub rotate: i32,
+}
+
+/// Checks that the pages select at least one existing page and rotate by right angles only.
+pub fn validate(pages: &[Page], count: u32) -> Result<()> {
+    if pages.is_empty() {
+        bail!("At least one page is required");
+    }
+
+    for page in pages {
+        if page.page == 0 || page.page > count {
+            bail!("No such page: {} (of {})", page.page, count);
+        }
+
+        if page.rotate % 90 != 0 {
+            bail!("Invalid rotation of page {}: {}", page.page, page.rotate);
+        }
+    }
+
+    return Ok(());
+}
+
+/// Builds the `qpdf` arguments assembling the pages from the input into the output.
+///
+/// Pages are selected in the given order and rotated afterwards, so the rotations refer to the pages of the output.
+fn arguments(input: &Path, pages: &[Page], output: &Path) -> Vec<String> {
+    let mut args = pages.iter().enumerate()
+        .filter(|(_, page)| page.rotate.rem_euclid(360) != 0)
+        .map(|(i, page)| format!("--rotate=+{}:{}", page.rotate.rem_euclid(360), i + 1))
+        .collect::<Vec<_>>();
+
+    let selection = pages.iter()
+        .map(|page| page.page.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    args.extend(vec![
+        String::from("--empty"),
+        String::from("--pages"),
+        input.to_string_lossy().into_owned(),
+        selection,
+        String::from("--"),
+        output.to_string_lossy().into_owned(),
+    ]);
+
+    return args;
+}
+
+/// The document and its renditions as juiced in a staging bundle.
+struct Juiced {
+    document: Vec<u8>,
+    pages: u32,
+    renditions: Vec<(Kind, Vec<u8>)>,
+}
+
+/// Replaces the documents of archived bundles, i.e. by a signed version of a contract or by rotating, reordering and
+/// dropping the pages of scans which came in upside down or with blank backsides.
+///
+/// The new document is juiced again to regenerate the text and the previews. The current document is kept as prior
+/// version.
+pub struct Editor {
+    config: Config,
+
+    queue: Queue,
+}
+
+impl Editor {
+    pub fn new(config: Config, queue: Queue) -> Self {
+        return Self { config, queue };
+    }
+
+    async fn assemble(&self, input: &Path, pages: &[Page], output: &Path) -> Result<()> {
+        let args = arguments(input, pages, output);
+
+        debug!("Running {} {:?}", self.config.qpdf, args);
+
+        let output = Command::new(&self.config.qpdf)
+            .args(&args)
+            .stdin(Stdio::null())
+            .output().await
+            .with_context(|| format!("Error executing {}", self.config.qpdf))?;
+
+        if !output.status.success() {
+            bail!("{} failed: {}: {}", self.config.qpdf, output.status, String::from_utf8_lossy(&output.stderr));
+        }
+
+        return Ok(());
+    }
+
+    /// Juices the original written to the staging bundle and collects the results.
+    async fn juice(&self, staging: &Bundle<'_, Staging>, bundle: &Bundle<'_, Archived>) -> Result<Juiced> {
+        bundle.read_metadata().await?.save(staging.write(Kind::Metadata).await?).await?;
+
+        self.queue.juice(staging).await?;
+
+        let document = tokio::fs::read(staging.path_of(Kind::Document)).await?;
+        let pages = staging.read_metadata().await?.pages;
+
+        let mut renditions = Vec::new();
+        for kind in RENDITIONS.iter().cloned().chain((1..=pages).map(Kind::Page)) {
+            match tokio::fs::read(staging.path_of(&kind)).await {
+                Ok(data) => renditions.push((kind, data)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        return Ok(Juiced { document, pages, renditions });
+    }
+
+    /// Takes over the juiced document and its renditions, removing the page previews beyond the new pages.
+    async fn take_over(&self, bundle: &Bundle<'_, Archived>, juiced: Juiced, reason: &str) -> Result<Version> {
+        let metadata = bundle.read_metadata().await?;
+
+        let version = bundle.replace_document(&juiced.document, reason).await?;
+        for (kind, data) in juiced.renditions {
+            bundle.replace(kind, &data).await?;
+        }
+
+        for page in juiced.pages + 1..=metadata.pages {
+            bundle.remove(Kind::Page(page)).await?;
+        }
+
+        bundle.write_metadata(&Metadata { pages: juiced.pages, ..metadata }).await?;
+
+        return Ok(version);
+    }
+
+    /// Replaces the document of an archived bundle by the given pages of it.
+    ///
+    /// The caller must ensure the bundle is not encrypted, as the pages are assembled from the document as read.
+    pub async fn edit<'r>(&self, repository: &'r Repository, bundle: &Bundle<'r, Archived>, pages: &[Page]) -> Result<Version> {
+        validate(pages, bundle.read_metadata().await?.pages)?;
+
+        let staging = repository.stage().await?;
+
+        info!("Editing pages of bundle {} in staging bundle {}", bundle.id(), staging.id());
+
+        let juiced = async {
+            // The document may be stored compressed, so it is assembled from a plain copy
+            let source = staging.path_of(Kind::other("source.pdf"));
+            let mut document = bundle.read(Kind::Document).await?
+                .ok_or_else(|| anyhow!("Document missing in bundle: {}", bundle.id()))?;
+            tokio::io::copy(&mut document, &mut tokio::fs::File::create(&source).await?).await?;
+
+            self.assemble(&source, pages, &staging.path_of(Kind::other("original.pdf"))).await?;
+            tokio::fs::remove_file(&source).await?;
+
+            self.juice(&staging, bundle).await
+        }.await;
+
+        staging.delete().await?;
+
+        return self.take_over(bundle, juiced?, "Edited pages").await;
+    }
+
+    /// Replaces the document of an archived bundle by a new PDF, i.e. by a signed version of a contract.
+    ///
+    /// The caller must ensure the bundle is not encrypted, as the new document is stored as is.
+    pub async fn replace<'r>(&self, repository: &'r Repository, bundle: &Bundle<'r, Archived>, data: &[u8], reason: &str) -> Result<Version> {
+        let staging = repository.stage().await?;
+
+        info!("Replacing document of bundle {} in staging bundle {}", bundle.id(), staging.id());
+
+        let juiced = async {
+            let mut original = staging.write(Kind::other("original.pdf")).await?;
+            original.write_all(data).await?;
+            original.commit().await?;
+
+            self.juice(&staging, bundle).await
+        }.await;
+
+        staging.delete().await?;
+
+        return self.take_over(bundle, juiced?, reason).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let page = |page, rotate| Page { page, rotate };
+
+        assert_that!(validate(&[page(2, 90), page(1, -90)], 2).is_ok()).is_true();
+        assert_that!(validate(&[], 2).is_err()).is_true();
+        assert_that!(validate(&[page(0, 0)], 2).is_err()).is_true();
+        assert_that!(validate(&[page(3, 0)], 2).is_err()).is_true();
+        assert_that!(validate(&[page(1, 45)], 2).is_err()).is_true();
+    }
+
+    #[test]
+    fn test_arguments() {
+        let pages = [
+            Page { page: 3, rotate: 0 },
+            Page { page: 1, rotate: 180 },
+            Page { page: 2, rotate: -90 },
+        ];
+
+        assert_that!(arguments(Path::new("document.pdf"), &pages, Path::new("original.pdf"))).is_equal_to(vec![
+            String::from("--rotate=+180:2"),
+            String::from("--rotate=+270:3"),
+            String::from("--empty"),
+            String::from("--pages"),
+            String::from("document.pdf"),
+            String::from("3,1,2"),
+            String::from("--"),
+            String::from("original.pdf"),
+        ]);
+    }
+}
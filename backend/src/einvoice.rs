@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+
+use crate::proto::model::{Decimal, PropertyValue};
+use crate::render::render_text;
+
+/// Structured content of an electronic invoice (XRechnung / ZUGFeRD).
+///
+/// Both syntaxes allowed by EN 16931 are supported: OASIS UBL (`Invoice` / `CreditNote`) and UN/CEFACT CII
+/// (`CrossIndustryInvoice`).
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Invoice {
+    pub number: Option<String>,
+    pub issued: Option<String>,
+    pub due: Option<String>,
+
+    pub seller: Option<String>,
+    pub buyer: Option<String>,
+
+    pub currency: Option<String>,
+    pub total: Option<String>,
+
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Line {
+    pub name: Option<String>,
+    pub quantity: Option<String>,
+    pub amount: Option<String>,
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    return node.children().find(|n| n.is_element() && n.tag_name().name() == name);
+}
+
+fn path<'a, 'i>(node: Node<'a, 'i>, path: &[&str]) -> Option<Node<'a, 'i>> {
+    return path.iter().try_fold(node, |node, name| child(node, name));
+}
+
+fn text(node: Node<'_, '_>, p: &[&str]) -> Option<String> {
+    return path(node, p)
+        .and_then(|node| node.text())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+}
+
+/// Converts the CII date format `102` (`YYYYMMDD`) to ISO 8601.
+fn cii_date(s: String) -> String {
+    if s.len() == 8 && s.chars().all(|c| c.is_ascii_digit()) {
+        return format!("{}-{}-{}", &s[0..4], &s[4..6], &s[6..8]);
+    } else {
+        return s;
+    }
+}
+
+impl Invoice {
+    pub fn parse(xml: &str) -> Result<Self> {
+        let doc = Document::parse(xml)?;
+        let root = doc.root_element();
+
+        return match root.tag_name().name() {
+            "Invoice" | "CreditNote" => Ok(Self::parse_ubl(root)),
+            "CrossIndustryInvoice" => Ok(Self::parse_cii(root)),
+            name => Err(anyhow!("Unsupported e-invoice format: {}", name)),
+        };
+    }
+
+    fn parse_ubl(root: Node<'_, '_>) -> Self {
+        let party = |role: &str| {
+            text(root, &[role, "Party", "PartyName", "Name"])
+                .or_else(|| text(root, &[role, "Party", "PartyLegalEntity", "RegistrationName"]))
+        };
+
+        let lines = root.children()
+            .filter(|n| n.is_element() && matches!(n.tag_name().name(), "InvoiceLine" | "CreditNoteLine"))
+            .map(|line| Line {
+                name: text(line, &["Item", "Name"]),
+                quantity: text(line, &["InvoicedQuantity"]).or_else(|| text(line, &["CreditedQuantity"])),
+                amount: text(line, &["LineExtensionAmount"]),
+            })
+            .collect();
+
+        return Self {
+            number: text(root, &["ID"]),
+            issued: text(root, &["IssueDate"]),
+            due: text(root, &["DueDate"])
+                .or_else(|| text(root, &["PaymentMeans", "PaymentDueDate"])),
+            seller: party("AccountingSupplierParty"),
+            buyer: party("AccountingCustomerParty"),
+            currency: text(root, &["DocumentCurrencyCode"]),
+            total: text(root, &["LegalMonetaryTotal", "PayableAmount"]),
+            lines,
+        };
+    }
+
+    fn parse_cii(root: Node<'_, '_>) -> Self {
+        let transaction = child(root, "SupplyChainTradeTransaction");
+        let agreement = transaction.and_then(|n| child(n, "ApplicableHeaderTradeAgreement"));
+        let settlement = transaction.and_then(|n| child(n, "ApplicableHeaderTradeSettlement"));
+
+        let lines = transaction.into_iter()
+            .flat_map(|n| n.children())
+            .filter(|n| n.is_element() && n.tag_name().name() == "IncludedSupplyChainTradeLineItem")
+            .map(|line| Line {
+                name: text(line, &["SpecifiedTradeProduct", "Name"]),
+                quantity: text(line, &["SpecifiedLineTradeDelivery", "BilledQuantity"]),
+                amount: text(line, &["SpecifiedLineTradeSettlement", "SpecifiedTradeSettlementLineMonetarySummation", "LineTotalAmount"]),
+            })
+            .collect();
+
+        return Self {
+            number: text(root, &["ExchangedDocument", "ID"]),
+            issued: text(root, &["ExchangedDocument", "IssueDateTime", "DateTimeString"]).map(cii_date),
+            due: settlement
+                .and_then(|n| text(n, &["SpecifiedTradePaymentTerms", "DueDateDateTime", "DateTimeString"]))
+                .map(cii_date),
+            seller: agreement.and_then(|n| text(n, &["SellerTradeParty", "Name"])),
+            buyer: agreement.and_then(|n| text(n, &["BuyerTradeParty", "Name"])),
+            currency: settlement.and_then(|n| text(n, &["InvoiceCurrencyCode"])),
+            total: settlement.and_then(|n| text(n, &["SpecifiedTradeSettlementHeaderMonetarySummation", "DuePayableAmount"])),
+            lines,
+        };
+    }
+
+    /// The payment deadline, if given as valid date.
+    pub fn due_date(&self) -> Option<NaiveDate> {
+        return self.due.as_deref()
+            .and_then(|due| NaiveDate::parse_from_str(due, "%Y-%m-%d").ok());
+    }
+
+    pub fn title(&self) -> String {
+        return match (&self.number, &self.seller) {
+            (Some(number), Some(seller)) => format!("Invoice {} from {}", number, seller),
+            (Some(number), None) => format!("Invoice {}", number),
+            (None, Some(seller)) => format!("Invoice from {}", seller),
+            (None, None) => String::from("Invoice"),
+        };
+    }
+
+    /// Maps the invoice fields to metadata properties.
+    ///
+    /// Dates and the total amount are typed if they can be parsed, otherwise they are kept as text.
+    pub fn properties(&self) -> HashMap<String, PropertyValue> {
+        let date = |value: &String| match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => PropertyValue::Date(date),
+            Err(_) => PropertyValue::from(value.clone()),
+        };
+
+        let amount = |value: &String| match Decimal::from_str(value) {
+            Ok(amount) => PropertyValue::amount(amount, self.currency.clone())
+                .unwrap_or(PropertyValue::Decimal { amount, currency: None }),
+            Err(_) => PropertyValue::from(value.clone()),
+        };
+
+        let fields = vec![
+            ("invoice.number", self.number.clone().map(PropertyValue::from)),
+            ("invoice.issued", self.issued.as_ref().map(date)),
+            ("invoice.due", self.due.as_ref().map(date)),
+            ("invoice.seller", self.seller.clone().map(PropertyValue::from)),
+            ("invoice.buyer", self.buyer.clone().map(PropertyValue::from)),
+            ("invoice.currency", self.currency.clone().map(PropertyValue::from)),
+            ("invoice.total", self.total.as_ref().map(amount)),
+        ];
+
+        return fields.into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key.to_string(), value)))
+            .collect();
+    }
+
+    /// Renders a human readable PDF representation of the invoice.
+    pub fn render(&self) -> Result<Vec<u8>> {
+        let mut text = Vec::new();
+        text.push(self.title());
+        text.push(String::new());
+
+        let field = |name: &str, value: &Option<String>| format!("{:<12} {}", name, value.as_deref().unwrap_or("-"));
+        text.push(field("Number:", &self.number));
+        text.push(field("Issued:", &self.issued));
+        text.push(field("Due:", &self.due));
+        text.push(field("Seller:", &self.seller));
+        text.push(field("Buyer:", &self.buyer));
+        text.push(String::new());
+
+        for line in &self.lines {
+            text.push(format!("{:>8}  {:<60} {:>12}",
+                              line.quantity.as_deref().unwrap_or(""),
+                              line.name.as_deref().unwrap_or(""),
+                              line.amount.as_deref().unwrap_or("")));
+        }
+
+        text.push(String::new());
+        text.push(format!("Total: {} {}",
+                          self.total.as_deref().unwrap_or("-"),
+                          self.currency.as_deref().unwrap_or("")));
+
+        return render_text(&self.title(), &text);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_ubl() {
+        let invoice = Invoice::parse(r#"<?xml version="1.0" encoding="UTF-8"?>
+            <ubl:Invoice xmlns:ubl="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2"
+                         xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2"
+                         xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+                <cbc:ID>R-2020-0815</cbc:ID>
+                <cbc:IssueDate>2020-11-01</cbc:IssueDate>
+                <cbc:DueDate>2020-11-15</cbc:DueDate>
+                <cbc:DocumentCurrencyCode>EUR</cbc:DocumentCurrencyCode>
+                <cac:AccountingSupplierParty><cac:Party><cac:PartyName><cbc:Name>ACME GmbH</cbc:Name></cac:PartyName></cac:Party></cac:AccountingSupplierParty>
+                <cac:AccountingCustomerParty><cac:Party><cac:PartyLegalEntity><cbc:RegistrationName>Jane Doe</cbc:RegistrationName></cac:PartyLegalEntity></cac:Party></cac:AccountingCustomerParty>
+                <cac:LegalMonetaryTotal><cbc:PayableAmount currencyID="EUR">119.00</cbc:PayableAmount></cac:LegalMonetaryTotal>
+                <cac:InvoiceLine>
+                    <cbc:InvoicedQuantity unitCode="H87">1</cbc:InvoicedQuantity>
+                    <cbc:LineExtensionAmount currencyID="EUR">100.00</cbc:LineExtensionAmount>
+                    <cac:Item><cbc:Name>Anvil</cbc:Name></cac:Item>
+                </cac:InvoiceLine>
+            </ubl:Invoice>"#).unwrap();
+
+        assert_that!(invoice.number).is_equal_to(Some(String::from("R-2020-0815")));
+        assert_that!(invoice.issued).is_equal_to(Some(String::from("2020-11-01")));
+        assert_that!(invoice.due).is_equal_to(Some(String::from("2020-11-15")));
+        assert_that!(invoice.seller).is_equal_to(Some(String::from("ACME GmbH")));
+        assert_that!(invoice.buyer).is_equal_to(Some(String::from("Jane Doe")));
+        assert_that!(invoice.total).is_equal_to(Some(String::from("119.00")));
+        assert_that!(invoice.lines).has_length(1);
+        assert_that!(invoice.title()).is_equal_to(String::from("Invoice R-2020-0815 from ACME GmbH"));
+    }
+
+    #[test]
+    fn test_parse_cii() {
+        let invoice = Invoice::parse(r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rsm:CrossIndustryInvoice xmlns:rsm="urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100"
+                                      xmlns:ram="urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100"
+                                      xmlns:udt="urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100">
+                <rsm:ExchangedDocument>
+                    <ram:ID>471102</ram:ID>
+                    <ram:IssueDateTime><udt:DateTimeString format="102">20201101</udt:DateTimeString></ram:IssueDateTime>
+                </rsm:ExchangedDocument>
+                <rsm:SupplyChainTradeTransaction>
+                    <ram:ApplicableHeaderTradeAgreement>
+                        <ram:SellerTradeParty><ram:Name>Lieferant GmbH</ram:Name></ram:SellerTradeParty>
+                        <ram:BuyerTradeParty><ram:Name>Kunden AG</ram:Name></ram:BuyerTradeParty>
+                    </ram:ApplicableHeaderTradeAgreement>
+                    <ram:ApplicableHeaderTradeSettlement>
+                        <ram:InvoiceCurrencyCode>EUR</ram:InvoiceCurrencyCode>
+                        <ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+                            <ram:DuePayableAmount>529.87</ram:DuePayableAmount>
+                        </ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+                    </ram:ApplicableHeaderTradeSettlement>
+                </rsm:SupplyChainTradeTransaction>
+            </rsm:CrossIndustryInvoice>"#).unwrap();
+
+        assert_that!(invoice.number).is_equal_to(Some(String::from("471102")));
+        assert_that!(invoice.issued).is_equal_to(Some(String::from("2020-11-01")));
+        assert_that!(invoice.seller).is_equal_to(Some(String::from("Lieferant GmbH")));
+        assert_that!(invoice.currency).is_equal_to(Some(String::from("EUR")));
+        assert_that!(invoice.total).is_equal_to(Some(String::from("529.87")));
+
+        let properties = invoice.properties();
+        assert_that!(properties.get("invoice.issued")).is_equal_to(Some(&PropertyValue::Date(NaiveDate::from_ymd(2020, 11, 1))));
+        assert_that!(properties.get("invoice.total")).is_equal_to(Some(&PropertyValue::parse("529.87 EUR")));
+        assert_that!(properties.get("invoice.seller")).is_equal_to(Some(&PropertyValue::from("Lieferant GmbH")));
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        assert_that!(Invoice::parse("<html></html>")).is_err();
+    }
+}
@@ -0,0 +1,631 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufWriter, Read, Write};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, Stream, StreamExt};
+use futures::channel::mpsc;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, DocInfo, Kind};
+use crate::repository::{Checksums, diff, Location, Repository, Snapshot};
+
+/// Name of the manifest, which is the first entry of an export
+pub const MANIFEST: &str = "manifest.json";
+
+/// Version of the export format
+const VERSION: u32 = 1;
+
+/// Number of chunks buffered between the archive and the repository
+const BUFFER: usize = 16;
+
+/// Size of the chunks an export is streamed in
+const CHUNK: usize = 64 * 1024;
+
+/// Filename of the document fragment, which identifies the content of a bundle
+const DOCUMENT: &str = "document.pdf";
+
+/// Describes the bundles contained in an export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub exported: DateTime<Utc>,
+    pub docs: Vec<ExportedDoc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedDoc {
+    #[serde(flatten)]
+    pub doc: DocInfo,
+
+    /// SHA-256 checksums of the exported fragments by filename
+    pub fragments: BTreeMap<String, String>,
+}
+
+/// Bundles exported into a portable tar archive, i.e. for long-term offline storage or to move them to another instance.
+///
+/// The archive starts with the manifest, followed by the fragments of each bundle in a directory named by its ID.
+/// Fragments are exported as stored, so bundles of encryption domains stay encrypted and can only be read by an
+/// instance holding the keys of the domain. Bundles are exported from a snapshot, so they are consistent even if they
+/// are changed while the export is written.
+#[derive(Debug)]
+pub struct Export {
+    snapshot: Snapshot,
+    docs: Vec<ExportedDoc>,
+}
+
+/// Passes written data on to a stream, blocking while the stream is not consumed.
+struct ChannelWriter(mpsc::Sender<std::io::Result<Bytes>>);
+
+fn header(size: usize, time: DateTime<Utc>) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size as u64);
+    header.set_mode(0o644);
+    header.set_mtime(time.timestamp() as u64);
+    header.set_cksum();
+
+    return header;
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        futures::executor::block_on(self.0.send(Ok(Bytes::copy_from_slice(buf))))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Export aborted"))?;
+
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl Export {
+    pub fn new(snapshot: Snapshot) -> Self {
+        return Self { snapshot, docs: Vec::new() };
+    }
+
+    /// Creates an export of all archived bundles.
+    pub async fn archive(repository: &Repository) -> Result<Self> {
+        let mut export = Self::new(repository.snapshot().await?);
+
+        let ids = export.snapshot.bundles().iter()
+            .filter(|bundle| bundle.location() == Location::Archive)
+            .map(|bundle| *bundle.id())
+            .collect::<Vec<_>>();
+        for id in ids {
+            export.add(id)?;
+        }
+
+        return Ok(export);
+    }
+
+    pub fn snapshot(&self) -> &Snapshot {
+        return &self.snapshot;
+    }
+
+    /// Adds a bundle of the snapshot to the export, recording the checksums of its fragments.
+    pub fn add(&mut self, id: DocId) -> Result<()> {
+        let bundle = self.snapshot.get(id)
+            .ok_or_else(|| anyhow!("Bundle not in snapshot: {}", id))?;
+
+        let fragments = bundle.fragment_names()
+            .filter(|name| Checksums::covers(name))
+            .map(|name| (name.to_string(), bundle.checksum(name).expect("Fragment in snapshot")))
+            .collect();
+
+        self.docs.push(ExportedDoc {
+            doc: (id, bundle.metadata()?).into(),
+            fragments,
+        });
+
+        return Ok(());
+    }
+
+    pub fn len(&self) -> usize {
+        return self.docs.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.docs.is_empty();
+    }
+
+    /// Writes the archive, blocking until all fragments are written.
+    pub fn write(self, writer: impl Write) -> Result<usize> {
+        let exported = Utc::now();
+
+        let docs = self.docs;
+        let manifest = serde_json::to_vec_pretty(&Manifest { version: VERSION, exported, docs: docs.clone() })?;
+
+        let mut archive = tar::Builder::new(writer);
+        archive.append_data(&mut header(manifest.len(), exported), MANIFEST, manifest.as_slice())?;
+
+        for doc in &docs {
+            let bundle = self.snapshot.get(doc.doc.id).expect("Bundle in snapshot");
+            for name in doc.fragments.keys() {
+                let data = self.snapshot.read(bundle, name)?;
+                archive.append_data(&mut header(data.len(), exported), format!("{}/{}", doc.doc.id, name), data.as_slice())
+                    .with_context(|| format!("Failed to export fragment {} of bundle {}", name, doc.doc.id))?;
+            }
+        }
+
+        archive.into_inner()?.flush()?;
+
+        info!("Exported {} bundles", docs.len());
+
+        return Ok(docs.len());
+    }
+
+    /// Streams the archive while it is written in the background.
+    pub fn stream(self) -> impl Stream<Item=std::io::Result<Bytes>> {
+        let (sender, receiver) = mpsc::channel(BUFFER);
+
+        tokio::task::spawn_blocking(move || {
+            let mut errors = sender.clone();
+            if let Err(err) = self.write(BufWriter::with_capacity(CHUNK, ChannelWriter(sender))) {
+                error!("Export failed: {:#}", err);
+                let err = std::io::Error::new(std::io::ErrorKind::Other, format!("{:#}", err));
+                futures::executor::block_on(errors.send(Err(err))).ok();
+            }
+        });
+
+        return receiver;
+    }
+}
+
+/// The outcome of importing a single bundle.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The bundle has been created
+    Imported,
+
+    /// A bundle with the same ID, document and metadata exists already
+    Identical,
+
+    /// The metadata of the existing bundle has been replaced by the newer metadata of the export
+    Updated { fields: Vec<String> },
+
+    /// The metadata of the existing bundle has been changed since the export and is kept
+    Kept { fields: Vec<String> },
+
+    /// Another bundle holds the same document
+    Duplicate { of: DocId },
+
+    /// A bundle with the same ID but another document exists
+    Conflict,
+}
+
+/// Summary of an import.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Imported {
+    pub bundles: Vec<DocId>,
+
+    /// Bundles not created as they exist in the repository already
+    pub skipped: usize,
+
+    /// How each bundle of the export has been reconciled with the repository
+    pub report: Vec<(DocId, Outcome)>,
+}
+
+impl Imported {
+    /// Returns the bundles created or updated by the import.
+    pub fn changed(&self) -> impl Iterator<Item=DocId> + '_ {
+        return self.report.iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Imported | Outcome::Updated { .. }))
+            .map(|(id, _)| *id);
+    }
+}
+
+/// An entry read from an export.
+enum Entry {
+    Manifest(Vec<u8>),
+    Fragment(DocId, String, Vec<u8>),
+}
+
+/// Reads the entries of an export and passes them on, blocking while they are not consumed.
+fn read(reader: impl Read, sender: &mut mpsc::Sender<Result<Entry>>) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path()?.to_string_lossy().into_owned();
+
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+
+        let entry = if path == MANIFEST {
+            Entry::Manifest(data)
+        } else {
+            let (id, name) = match path.find('/') {
+                Some(index) => (&path[..index], &path[index + 1..]),
+                None => bail!("Unexpected entry in export: {}", path),
+            };
+
+            if name.is_empty() || name.contains('/') || name.starts_with('.') {
+                bail!("Invalid fragment in export: {}", path);
+            }
+
+            let id = DocId::from_str(id)
+                .with_context(|| format!("Invalid bundle in export: {}", path))?;
+
+            Entry::Fragment(id, name.to_string(), data)
+        };
+
+        if futures::executor::block_on(sender.send(Ok(entry))).is_err() {
+            // The import has been aborted
+            return Ok(());
+        }
+    }
+
+    return Ok(());
+}
+
+/// Collects the bundles in the inbox and archive by the checksum of their document, to detect duplicates.
+async fn documents(repository: &Repository) -> Result<HashMap<String, DocId>> {
+    let mut documents = HashMap::new();
+
+    for bundle in repository.inbox().list().await? {
+        if let Some(checksum) = bundle.read_checksums().await?.as_ref().and_then(|checksums| checksums.get(DOCUMENT)) {
+            documents.insert(checksum.to_string(), *bundle.id());
+        }
+    }
+
+    for bundle in repository.archive().list().await? {
+        if let Some(checksum) = bundle.read_checksums().await?.as_ref().and_then(|checksums| checksums.get(DOCUMENT)) {
+            documents.insert(checksum.to_string(), *bundle.id());
+        }
+    }
+
+    return Ok(documents);
+}
+
+/// Reconciles an exported bundle with the existing bundle of the same ID.
+///
+/// Bundles holding another document are left untouched. Differing metadata replaces the existing one, unless the
+/// bundle has been changed since the export was created or has been trashed.
+async fn reconcile(repository: &Repository,
+                   doc: &ExportedDoc,
+                   exported: DateTime<Utc>,
+                   location: Location,
+                   checksums: Option<Checksums>,
+                   existing: Metadata) -> Result<Outcome> {
+    let id = doc.doc.id;
+
+    if checksums.as_ref().and_then(|checksums| checksums.get(DOCUMENT)) != doc.fragments.get(DOCUMENT).map(String::as_str) {
+        info!("Skipping bundle {} conflicting with existing document", id);
+        return Ok(Outcome::Conflict);
+    }
+
+    let mut metadata = Metadata::from(doc.doc.metadata.clone());
+    metadata.carry_over(&existing);
+
+    let fields = diff(&existing, &metadata)?.into_iter()
+        .map(|(field, _)| field)
+        .collect::<Vec<_>>();
+    if fields.is_empty() {
+        info!("Skipping identical bundle {}", id);
+        return Ok(Outcome::Identical);
+    }
+
+    let modified = repository.journal().history(id).await?.last().map(|entry| entry.time);
+    if location == Location::Trash || modified.map_or(false, |modified| modified >= exported) {
+        info!("Keeping metadata of bundle {} changed since the export", id);
+        return Ok(Outcome::Kept { fields });
+    }
+
+    match location {
+        Location::Inbox => repository.inbox().get(id).await
+            .with_context(|| format!("Bundle vanished: {}", id))?
+            .write_metadata(&metadata).await?,
+        Location::Archive => repository.archive().get(id).await
+            .with_context(|| format!("Bundle vanished: {}", id))?
+            .write_metadata(&metadata).await?,
+        Location::Trash => unreachable!(),
+    }
+
+    info!("Updated metadata of bundle {}: {}", id, fields.join(", "));
+    return Ok(Outcome::Updated { fields });
+}
+
+/// Imports a single bundle, reconciling it with existing bundles of the same ID or document.
+async fn import_bundle(repository: &Repository,
+                       doc: &ExportedDoc,
+                       fragments: Vec<(String, Vec<u8>)>,
+                       exported: DateTime<Utc>,
+                       documents: &mut HashMap<String, DocId>) -> Result<Outcome> {
+    let id = doc.doc.id;
+
+    for (name, data) in &fragments {
+        let checksum = hex::encode(Sha256::digest(data));
+        if doc.fragments.get(name) != Some(&checksum) {
+            bail!("Checksum mismatch of fragment {} in bundle {}", name, id);
+        }
+    }
+
+    if fragments.len() != doc.fragments.len() {
+        bail!("Fragments missing in bundle {}", id);
+    }
+
+    if let Some(bundle) = repository.inbox().get(id).await {
+        let (checksums, metadata) = (bundle.read_checksums().await?, bundle.read_metadata().await?);
+        return reconcile(repository, doc, exported, Location::Inbox, checksums, metadata).await;
+    }
+
+    if let Some(bundle) = repository.archive().get(id).await {
+        let (checksums, metadata) = (bundle.read_checksums().await?, bundle.read_metadata().await?);
+        return reconcile(repository, doc, exported, Location::Archive, checksums, metadata).await;
+    }
+
+    if let Some(bundle) = repository.trash().get(id).await {
+        let (checksums, metadata) = (bundle.read_checksums().await?, bundle.read_metadata().await?);
+        return reconcile(repository, doc, exported, Location::Trash, checksums, metadata).await;
+    }
+
+    let document = doc.fragments.get(DOCUMENT);
+    if let Some(of) = document.and_then(|checksum| documents.get(checksum)) {
+        info!("Skipping bundle {} duplicating {}", id, of);
+        return Ok(Outcome::Duplicate { of: *of });
+    }
+
+    let staging = repository.stage_with_id(id).await?;
+
+    let result: Result<()> = async {
+        for (name, data) in &fragments {
+            let mut file = staging.write(Kind::other(name.as_str())).await?;
+            file.write_all(data).await
+                .with_context(|| format!("Writing {}", name))?;
+            file.commit().await?;
+        }
+
+        return Ok(());
+    }.await;
+
+    if let Err(err) = result {
+        staging.delete().await?;
+        return Err(err);
+    }
+
+    let bundle = staging.create().await?;
+    if doc.doc.metadata.archived.is_some() {
+        bundle.archive().await?;
+    }
+
+    if let Some(document) = document {
+        documents.insert(document.clone(), id);
+    }
+
+    return Ok(Outcome::Imported);
+}
+
+/// Imports the bundles of an export into the repository.
+///
+/// All fragments are verified against the checksums of the manifest before a bundle is created. Bundles which have
+/// been archived when exported are archived again, all others land in the inbox.
+///
+/// Importing into a non-empty repository, i.e. to restore a backup, does not create duplicates: bundles which exist
+/// with the same ID are reconciled and bundles holding a document which exists already are skipped. The outcome for
+/// each bundle is reported.
+pub async fn import(repository: &Repository, reader: impl Read + Send + 'static) -> Result<Imported> {
+    let (sender, mut receiver) = mpsc::channel(BUFFER);
+    let reading = tokio::task::spawn_blocking(move || {
+        let mut sender = sender;
+        if let Err(err) = read(reader, &mut sender) {
+            futures::executor::block_on(sender.send(Err(err))).ok();
+        }
+    });
+
+    let manifest: Manifest = match receiver.next().await {
+        Some(Ok(Entry::Manifest(data))) => serde_json::from_slice(&data)
+            .context("Invalid manifest")?,
+        Some(Err(err)) => return Err(err),
+        _ => bail!("Export does not start with a manifest"),
+    };
+
+    if manifest.version != VERSION {
+        bail!("Unsupported export version: {}", manifest.version);
+    }
+
+    let docs = manifest.docs.iter()
+        .map(|doc| (doc.doc.id, doc))
+        .collect::<HashMap<_, _>>();
+
+    let mut documents = documents(repository).await?;
+
+    let mut imported = Imported::default();
+    let mut done = HashSet::new();
+
+    let mut pending: Option<(DocId, Vec<(String, Vec<u8>)>)> = None;
+    loop {
+        // Fragments of a bundle are exported in sequence, so the pending bundle is complete once another one starts
+        let next = match receiver.next().await.transpose()? {
+            Some(Entry::Fragment(id, name, data)) => match &mut pending {
+                Some((pending_id, fragments)) if *pending_id == id => {
+                    fragments.push((name, data));
+                    continue;
+                }
+                _ => Some((id, vec![(name, data)])),
+            },
+            Some(Entry::Manifest(_)) => bail!("Duplicate manifest in export"),
+            None => None,
+        };
+
+        if let Some((id, fragments)) = std::mem::replace(&mut pending, next) {
+            let doc = docs.get(&id)
+                .with_context(|| format!("Bundle missing in manifest: {}", id))?;
+
+            if !done.insert(id) {
+                bail!("Fragments of bundle {} are not in sequence", id);
+            }
+
+            let outcome = import_bundle(repository, doc, fragments, manifest.exported, &mut documents).await?;
+            if outcome == Outcome::Imported {
+                imported.bundles.push(id);
+            } else {
+                imported.skipped += 1;
+            }
+
+            imported.report.push((id, outcome));
+        }
+
+        if pending.is_none() {
+            break;
+        }
+    }
+
+    reading.await?;
+
+    if let Some(id) = docs.keys().find(|id| !done.contains(id)) {
+        bail!("Fragments missing in bundle {}", id);
+    }
+
+    info!("Imported {} bundles, skipped {} existing ones, updated {}",
+          imported.bundles.len(),
+          imported.skipped,
+          imported.report.iter().filter(|(_, outcome)| matches!(outcome, Outcome::Updated { .. })).count());
+
+    return Ok(imported);
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    async fn bundle(repository: &Repository, archived: bool) -> DocId {
+        let staging = repository.stage().await.unwrap();
+
+        let document: &[u8] = if archived { b"my archived document" } else { b"my inboxed document" };
+        let mut fragment = staging.write(Kind::Document).await.unwrap();
+        fragment.write_all(document).await.unwrap();
+        fragment.commit().await.unwrap();
+
+        let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+        plaintext.write_all(b"my document plaintext").await.unwrap();
+        plaintext.commit().await.unwrap();
+
+        let mut metadata = Metadata::new();
+        if archived {
+            metadata.archived = Some(Utc::now());
+        }
+        metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        let bundle = staging.create().await.unwrap();
+        if archived {
+            return *bundle.archive().await.unwrap().id();
+        }
+
+        return *bundle.id();
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let source = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let archived = bundle(&source, true).await;
+        let inboxed = bundle(&source, false).await;
+
+        let mut export = Export::archive(&source).await.unwrap();
+        export.add(inboxed).unwrap();
+
+        let mut data = Vec::new();
+        assert_that!(export.write(&mut data).unwrap()).is_equal_to(2);
+
+        let target = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let imported = import(&target, std::io::Cursor::new(data.clone())).await.unwrap();
+        assert_that!(imported.bundles).has_length(2);
+        assert_that!(imported.skipped).is_equal_to(0);
+
+        let bundle = target.archive().get(archived).await.unwrap();
+        assert_that!(bundle.read_plaintext().await.unwrap()).is_equal_to(String::from("my document plaintext"));
+        assert_that!(target.inbox().get(inboxed).await.is_some()).is_true();
+
+        let imported = import(&target, std::io::Cursor::new(data)).await.unwrap();
+        assert_that!(imported.bundles).is_empty();
+        assert_that!(imported.skipped).is_equal_to(2);
+        assert_that!(imported.report).contains_all_of(&vec![
+            &(archived, Outcome::Identical),
+            &(inboxed, Outcome::Identical),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_import_reconcile() {
+        let source = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let id = bundle(&source, true).await;
+
+        let mut data = Vec::new();
+        Export::archive(&source).await.unwrap().write(&mut data).unwrap();
+
+        let target = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        import(&target, std::io::Cursor::new(data)).await.unwrap();
+
+        // Change the metadata in the source after the first import
+        let original = source.archive().get(id).await.unwrap();
+        let mut metadata = original.read_metadata().await.unwrap();
+        metadata.title = Some(String::from("Renamed"));
+        original.write_metadata(&metadata).await.unwrap();
+
+        let mut data = Vec::new();
+        Export::archive(&source).await.unwrap().write(&mut data).unwrap();
+
+        let imported = import(&target, std::io::Cursor::new(data.clone())).await.unwrap();
+        assert_that!(imported.report).is_equal_to(vec![
+            (id, Outcome::Updated { fields: vec![String::from("title")] }),
+        ]);
+        assert_that!(imported.changed().collect::<Vec<_>>()).is_equal_to(vec![id]);
+
+        let restored = target.archive().get(id).await.unwrap();
+        assert_that!(restored.read_metadata().await.unwrap().title).is_equal_to(Some(String::from("Renamed")));
+
+        // Changes made in the target after the export are kept
+        let mut metadata = restored.read_metadata().await.unwrap();
+        metadata.title = Some(String::from("Changed locally"));
+        restored.write_metadata(&metadata).await.unwrap();
+
+        let imported = import(&target, std::io::Cursor::new(data)).await.unwrap();
+        assert_that!(imported.report).is_equal_to(vec![
+            (id, Outcome::Kept { fields: vec![String::from("title")] }),
+        ]);
+        assert_that!(restored.read_metadata().await.unwrap().title).is_equal_to(Some(String::from("Changed locally")));
+
+        // The same document under another ID is a duplicate
+        let other = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let copy = bundle(&other, true).await;
+
+        let mut data = Vec::new();
+        Export::archive(&other).await.unwrap().write(&mut data).unwrap();
+
+        let imported = import(&target, std::io::Cursor::new(data)).await.unwrap();
+        assert_that!(imported.report).is_equal_to(vec![(copy, Outcome::Duplicate { of: id })]);
+        assert_that!(target.archive().get(copy).await.is_none()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_import_corrupted() {
+        let source = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let id = bundle(&source, true).await;
+
+        let mut data = Vec::new();
+        Export::archive(&source).await.unwrap().write(&mut data).unwrap();
+
+        // Flip a byte of the plaintext, which is stored uncompressed
+        let offset = data.windows(21).position(|window| window == b"my document plaintext").unwrap();
+        data[offset] = b'M';
+
+        let target = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        assert_that!(import(&target, std::io::Cursor::new(data)).await).is_err();
+        assert_that!(target.archive().get(id).await.is_none()).is_true();
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{anyhow, bail, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config::Filing as Config;
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind, PropertyValue};
+use crate::render::{render_label, render_sheet};
+use crate::repository::{Bundle, Inboxed, Repository, Settings};
+
+/// Labels for filing the paper originals of archived documents.
+///
+/// Each archived document gets a sequential archive serial number (ASN) stored as property. The label shows the ASN and
+/// a QR code linking to the document, so the paper original can be found again via the archive.
+pub struct Filing {
+    config: Option<Config>,
+
+    /// The repository holding the last assigned ASN
+    repository: Repository,
+
+    lock: Mutex<()>,
+}
+
+/// The last assigned ASN.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Counter {
+    last: i64,
+}
+
+impl Settings for Counter {
+    const NAME: &'static str = "asn";
+    const VERSION: u32 = 1;
+
+    fn validate(&self) -> Result<()> {
+        if self.last < 0 {
+            bail!("Negative ASN: {}", self.last);
+        }
+
+        return Ok(());
+    }
+}
+
+/// Error returned if a document should be archived with an ASN already held by another document.
+#[derive(Debug, thiserror::Error)]
+#[error("ASN {asn} is already assigned to {other}")]
+pub struct Collision {
+    pub asn: i64,
+    pub other: DocId,
+}
+
+/// A document found by a code printed on paper.
+#[derive(Debug)]
+pub struct Found {
+    pub id: DocId,
+    pub metadata: Metadata,
+    pub archived: bool,
+}
+
+/// A code printed on paper referring to a document.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Code {
+    Asn(i64),
+
+    /// A full or abbreviated document ID
+    Id(String),
+}
+
+impl Code {
+    fn parse(code: &str) -> Option<Self> {
+        let code = code.trim();
+
+        let asn = code.strip_prefix("ASN").or_else(|| code.strip_prefix("asn")).unwrap_or(code).trim();
+        if !asn.is_empty() && asn.chars().all(|c| c.is_ascii_digit()) {
+            return asn.parse().ok().map(Self::Asn);
+        }
+
+        if code.len() >= Filing::MIN_SHORT_ID && code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Some(Self::Id(code.to_string()));
+        }
+
+        return None;
+    }
+
+    fn matches(&self, id: &DocId, metadata: &Metadata) -> bool {
+        return match self {
+            Self::Asn(asn) => metadata.properties.get(Filing::PROPERTY) == Some(&PropertyValue::Integer(*asn)),
+            Self::Id(prefix) => id.to_string().starts_with(prefix.as_str()),
+        };
+    }
+}
+
+impl Filing {
+    /// Minimum length of abbreviated document IDs
+    pub const MIN_SHORT_ID: usize = 6;
+
+    /// The property holding the ASN
+    pub const PROPERTY: &'static str = "asn";
+
+    /// The fragment holding the rendered label
+    pub const FRAGMENT: &'static str = "label.pdf";
+
+    pub fn new(config: Option<Config>, repository: Repository) -> Self {
+        return Self {
+            config,
+            repository,
+            lock: Mutex::new(()),
+        };
+    }
+
+    fn caption(asn: i64) -> String {
+        return format!("ASN {:06}", asn);
+    }
+
+    fn link(config: &Config, id: &DocId, asn: i64) -> String {
+        return config.link
+            .replace("{id}", &id.to_string())
+            .replace("{asn}", &asn.to_string());
+    }
+
+    /// Assigns the next ASN.
+    async fn next(&self) -> Result<i64> {
+        let _lock = self.lock.lock().await;
+
+        let mut counter = self.repository.load_settings::<Counter>().await?;
+        counter.last += 1;
+        self.repository.save_settings(&counter).await?;
+
+        return Ok(counter.last);
+    }
+
+    /// Reserves an ASN given to a document before archiving, so it is never assigned again.
+    ///
+    /// Fails with `Collision` if another document holds the ASN already. As the counter only ever increases, this can
+    /// only be the case for ASNs not beyond the last assigned one.
+    async fn reserve(&self, id: &DocId, asn: i64) -> Result<()> {
+        let _lock = self.lock.lock().await;
+
+        let mut counter = self.repository.load_settings::<Counter>().await?;
+        if asn > counter.last {
+            counter.last = asn;
+            return self.repository.save_settings(&counter).await;
+        }
+
+        let holders = resolve(&self.repository, &asn.to_string()).await?.unwrap_or_default();
+        if let Some(other) = holders.iter().find(|found| found.id != *id) {
+            return Err(Collision { asn, other: other.id }.into());
+        }
+
+        return Ok(());
+    }
+
+    /// Assigns an ASN to a document about to be archived and renders its label, if filing is enabled.
+    ///
+    /// Documents which already have an ASN keep it, i.e. one entered from a pre-printed label, and the ASN is reserved
+    /// instead.
+    pub async fn label(&self, bundle: &Bundle<'_, Inboxed>, metadata: &mut Metadata) -> Result<()> {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+
+        let asn = match asn(metadata) {
+            Some(asn) => {
+                self.reserve(bundle.id(), asn).await?;
+                asn
+            }
+            None => {
+                let asn = self.next().await?;
+                metadata.properties.insert(String::from(Self::PROPERTY), PropertyValue::Integer(asn));
+                asn
+            }
+        };
+
+        let label = render_label(&Self::caption(asn), &Self::link(config, bundle.id(), asn), config.width, config.height)?;
+        bundle.replace(Kind::other(Self::FRAGMENT), &label).await?;
+
+        info!("Assigned ASN {} to {}", asn, bundle.id());
+
+        return Ok(());
+    }
+
+    /// Renders the labels of the given documents and their ASNs onto sheets for printing them at once.
+    ///
+    /// The first `skip` positions of the first sheet are left empty.
+    pub fn sheet(&self, docs: &[(DocId, i64)], skip: usize) -> Result<Vec<u8>> {
+        let config = self.config.as_ref()
+            .ok_or_else(|| anyhow!("Filing is disabled"))?;
+
+        let labels = docs.iter()
+            .map(|(id, asn)| (Self::caption(*asn), Self::link(config, id, *asn)))
+            .collect::<Vec<_>>();
+
+        return render_sheet(&labels, &config.sheet, skip);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.config.is_some();
+    }
+}
+
+/// Returns the ASN of a document, if assigned.
+pub fn asn(metadata: &Metadata) -> Option<i64> {
+    return match metadata.properties.get(Filing::PROPERTY) {
+        Some(PropertyValue::Integer(asn)) => Some(*asn),
+        _ => None,
+    };
+}
+
+/// Resolves an ASN or a full or abbreviated document ID to the inboxed and archived documents it refers to.
+///
+/// Returns `None` if the code is neither.
+pub async fn resolve(repository: &Repository, code: &str) -> Result<Option<Vec<Found>>> {
+    let code = match Code::parse(code) {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+
+    let mut found = Vec::new();
+
+    for bundle in repository.inbox().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if code.matches(bundle.id(), &metadata) {
+            found.push(Found { id: *bundle.id(), metadata, archived: false });
+        }
+    }
+
+    for bundle in repository.archive().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if code.matches(bundle.id(), &metadata) {
+            found.push(Found { id: *bundle.id(), metadata, archived: true });
+        }
+    }
+
+    return Ok(Some(found));
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::config::Sheet;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_label() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let filing = Filing::new(Some(Config {
+            link: String::from("https://adacta.example.com/search?query=property.asn:{asn}"),
+            width: 62.0,
+            height: 29.0,
+            sheet: Sheet::default(),
+        }), repository.clone());
+
+        let mut bundles = Vec::new();
+        for _ in 0..2 {
+            let staging = repository.stage().await.unwrap();
+            Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            bundles.push(staging.create().await.unwrap());
+        }
+
+        let mut metadata = Metadata::new();
+        filing.label(&bundles[0], &mut metadata).await.unwrap();
+        assert_that!(metadata.properties.get(Filing::PROPERTY)).is_equal_to(Some(&PropertyValue::Integer(1)));
+        assert_that!(bundles[0].path_of(Kind::other(Filing::FRAGMENT)).exists()).is_true();
+
+        // Labelling again keeps the number
+        filing.label(&bundles[0], &mut metadata).await.unwrap();
+        assert_that!(metadata.properties.get(Filing::PROPERTY)).is_equal_to(Some(&PropertyValue::Integer(1)));
+
+        let mut metadata = Metadata::new();
+        filing.label(&bundles[1], &mut metadata).await.unwrap();
+        assert_that!(metadata.properties.get(Filing::PROPERTY)).is_equal_to(Some(&PropertyValue::Integer(2)));
+    }
+
+    #[tokio::test]
+    async fn test_reserve() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let filing = Filing::new(Some(Config {
+            link: String::from("https://adacta.example.com/d/{asn}"),
+            width: 62.0,
+            height: 29.0,
+            sheet: Sheet::default(),
+        }), repository.clone());
+
+        let mut bundles = Vec::new();
+        for _ in 0..3 {
+            let staging = repository.stage().await.unwrap();
+            Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            bundles.push(staging.create().await.unwrap());
+        }
+
+        // An ASN from a pre-printed label is kept and never assigned again
+        let mut metadata = Metadata::new();
+        metadata.properties.insert(String::from(Filing::PROPERTY), PropertyValue::Integer(7));
+        filing.label(&bundles[0], &mut metadata).await.unwrap();
+        bundles[0].write_metadata(&metadata).await.unwrap();
+
+        let mut metadata = Metadata::new();
+        filing.label(&bundles[1], &mut metadata).await.unwrap();
+        assert_that!(metadata.properties.get(Filing::PROPERTY)).is_equal_to(Some(&PropertyValue::Integer(8)));
+
+        // Labelling the holder again keeps its ASN, whereas others can not take it
+        let mut metadata = bundles[0].read_metadata().await.unwrap();
+        assert_that!(filing.label(&bundles[0], &mut metadata).await).is_ok();
+
+        let mut metadata = Metadata::new();
+        metadata.properties.insert(String::from(Filing::PROPERTY), PropertyValue::Integer(7));
+        let err = filing.label(&bundles[2], &mut metadata).await.unwrap_err();
+        assert_that!(err.downcast_ref::<Collision>().map(|collision| collision.other)).is_equal_to(Some(*bundles[0].id()));
+    }
+
+    #[tokio::test]
+    async fn test_sheet() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let filing = Filing::new(Some(Config {
+            link: String::from("https://adacta.example.com/d/{asn}"),
+            width: 62.0,
+            height: 29.0,
+            sheet: Sheet::default(),
+        }), repository.clone());
+
+        let docs = (1..=30).map(|asn| (DocId::random(), asn)).collect::<Vec<_>>();
+
+        let sheet = filing.sheet(&docs, 5).unwrap();
+        assert_that!(sheet.starts_with(b"%PDF")).is_true();
+
+        let disabled = Filing::new(None, repository.clone());
+        assert_that!(disabled.sheet(&docs, 0)).is_err();
+    }
+
+    #[test]
+    fn test_parse_code() {
+        assert_that!(Code::parse("42")).is_equal_to(Some(Code::Asn(42)));
+        assert_that!(Code::parse("ASN 000042")).is_equal_to(Some(Code::Asn(42)));
+        assert_that!(Code::parse("7xKq3bZ")).is_equal_to(Some(Code::Id(String::from("7xKq3bZ"))));
+        assert_that!(Code::parse("7xK")).is_none();
+        assert_that!(Code::parse("../etc")).is_none();
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let filing = Filing::new(None, repository.clone());
+
+        let staging = repository.stage().await.unwrap();
+        Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        let bundle = staging.create().await.unwrap();
+
+        let mut metadata = Metadata::new();
+        filing.label(&bundle, &mut metadata).await.unwrap();
+        assert_that!(metadata.properties).is_empty();
+        assert_that!(bundle.path_of(Kind::other(Filing::FRAGMENT)).exists()).is_false();
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::Cursor;
+use std::str::FromStr;
+
+use anyhow::Result;
+use exif::{In, Reader, Tag, Value};
+use log::{debug, info};
+
+use crate::juicer::image_original;
+use crate::proto::model::{Decimal, Kind, PropertyValue};
+use crate::repository::{Bundle, Staging};
+
+/// The property holding the location a photographed document has been taken at
+pub const PROPERTY: &str = "photo.location";
+
+/// Reads a coordinate given as degrees, minutes and seconds, which is negative for the given reference.
+fn coordinate(exif: &exif::Exif, tag: Tag, reference: Tag, negative: u8) -> Option<f64> {
+    let parts = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(parts) if parts.len() == 3 => parts.iter()
+            .map(|part| part.to_f64())
+            .collect::<Vec<_>>(),
+        _ => return None,
+    };
+
+    let value = parts[0] + parts[1] / 60.0 + parts[2] / 3600.0;
+
+    let negated = match &exif.get_field(reference, In::PRIMARY)?.value {
+        Value::Ascii(values) => values.first().and_then(|value| value.first()) == Some(&negative),
+        _ => return None,
+    };
+
+    return Some(if negated { -value } else { value });
+}
+
+/// Extracts the location from the GPS data embedded in the EXIF data of an image.
+///
+/// Returns `None` if the image has no or invalid GPS data, as most scans don't carry any.
+pub fn location(image: &[u8]) -> Option<PropertyValue> {
+    let exif = match Reader::new().read_from_container(&mut Cursor::new(image)) {
+        Ok(exif) => exif,
+        Err(err) => {
+            debug!("No EXIF data found: {}", err);
+            return None;
+        }
+    };
+
+    let lat = coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?;
+    let lon = coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?;
+
+    // Six fractional digits are precise to about ten centimeters
+    let decimal = |value: f64| Decimal::from_str(&format!("{:.6}", value)).ok();
+    return PropertyValue::location(decimal(lat)?, decimal(lon)?).ok();
+}
+
+/// Tags a staged bundle ingested from a photographed document with the location the photo has been taken at.
+///
+/// Locations already assigned, i.e. by an upload, are kept. Returns true if the location has been assigned.
+pub async fn geotag(bundle: &Bundle<'_, Staging>) -> Result<bool> {
+    let extension = match image_original(bundle).await {
+        Some(extension) => extension,
+        None => return Ok(false),
+    };
+
+    let mut metadata = bundle.read_metadata().await?;
+    if metadata.properties.contains_key(PROPERTY) {
+        return Ok(false);
+    }
+
+    let image = tokio::fs::read(bundle.path_of(Kind::other(format!("original.{}", extension)))).await?;
+    let location = match location(&image) {
+        Some(location) => location,
+        None => return Ok(false),
+    };
+
+    info!("Tagging bundle {} with location {}", bundle.id(), location);
+
+    metadata.properties.insert(String::from(PROPERTY), location);
+    metadata.save(bundle.write(Kind::Metadata).await?).await?;
+
+    return Ok(true);
+}
+
+#[cfg(test)]
+mod test {
+    use exif::{Field, Rational};
+    use exif::experimental::Writer;
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn image(lat: [u32; 3], lat_ref: &[u8], lon: [u32; 3], lon_ref: &[u8]) -> Vec<u8> {
+        let rational = |parts: [u32; 3]| Value::Rational(parts.iter()
+            .map(|part| Rational { num: *part, denom: 1 })
+            .collect());
+
+        let fields = vec![
+            Field { tag: Tag::GPSLatitude, ifd_num: In::PRIMARY, value: rational(lat) },
+            Field { tag: Tag::GPSLatitudeRef, ifd_num: In::PRIMARY, value: Value::Ascii(vec![lat_ref.to_vec()]) },
+            Field { tag: Tag::GPSLongitude, ifd_num: In::PRIMARY, value: rational(lon) },
+            Field { tag: Tag::GPSLongitudeRef, ifd_num: In::PRIMARY, value: Value::Ascii(vec![lon_ref.to_vec()]) },
+        ];
+
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+
+        let mut image = Cursor::new(Vec::new());
+        writer.write(&mut image, false).unwrap();
+
+        return image.into_inner();
+    }
+
+    #[test]
+    fn test_location() {
+        assert_that!(location(&image([48, 8, 18], b"N", [11, 34, 30], b"E")))
+            .is_equal_to(Some(PropertyValue::parse("geo:48.138333,11.575000")));
+
+        assert_that!(location(&image([33, 52, 4], b"S", [151, 12, 26], b"E")))
+            .is_equal_to(Some(PropertyValue::parse("geo:-33.867778,151.207222")));
+
+        assert_that!(location(b"not an image")).is_none();
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use log::info;
+use rocket::http::Header;
+use rocket::local::asynchronous::Client;
+
+use crate::auth::Authenticator;
+use crate::checklists::Checklists;
+use crate::config;
+use crate::correspondents::Correspondents;
+use crate::crypto::Keyring;
+use crate::editing::Editor;
+use crate::filing::Filing;
+use crate::index::memory::Index;
+use crate::juicer::stub::Juicer;
+use crate::labels::Labels;
+use crate::mailer::Mailer;
+use crate::merge::Merger;
+use crate::persons::Persons;
+use crate::preferences::Preferences;
+use crate::previews::Previews;
+use crate::proto::model::DocId;
+use crate::queue::Queue;
+use crate::quota::Quotas;
+use crate::reload::Reloader;
+use crate::repository::Repository;
+use crate::requests::Requests;
+use crate::rules::Rules;
+use crate::shares::Shares;
+use crate::status::Status;
+use crate::transcription::Transcriber;
+use crate::web::Repositories;
+
+pub use crate::juicer::stub::sample;
+
+/// Name and key of the API key accepted by the harness
+pub const API_KEY: (&str, &str) = ("harness", "testkey");
+
+/// Hash of the API key
+const API_KEY_HASH: &str = "$2y$12$8X8eghlzYFEKYhcOdqIU6OqaC5oACEYpfLzJChXPPIHBO6aRmzXaC";
+
+/// Runs the backend against a temporary repository for end-to-end tests.
+///
+/// Documents are juiced by the stub juicer and searched in an in-memory index, so whole flows from uploading to
+/// searching archived documents can be tested without docker or Elasticsearch. Requests are dispatched to the server
+/// in-process by the client. Beside the tests of the backend, this is available with the `harness` feature.
+pub struct Harness {
+    pub repository: Repository,
+    pub index: Arc<Index>,
+    pub queue: Queue,
+    pub status: Arc<Status>,
+
+    client: Client,
+}
+
+impl Harness {
+    pub async fn new() -> Result<Self> {
+        let (repository, index, queue, status, rocket) = Self::build(0).await?;
+
+        let client = Client::untracked(rocket).await?;
+
+        return Ok(Self { repository, index, queue, status, client });
+    }
+
+    /// Serves the backend against a temporary repository on the given port until shut down.
+    ///
+    /// This allows to run tests of the frontend or other clients against a backend without any external services.
+    pub async fn serve(port: u16) -> Result<()> {
+        let (repository, _, _, _, rocket) = Self::build(port).await?;
+
+        info!("Serving harness for repository at {:?} on port {}", repository.path(), port);
+        rocket.launch().await?;
+
+        return Ok(());
+    }
+
+    async fn build(port: u16) -> Result<(Repository, Arc<Index>, Queue, Arc<Status>, rocket::Rocket)> {
+        let repository = Repository::with_path(tempfile::tempdir()?).await?;
+
+        let mut api_keys = HashMap::new();
+        api_keys.insert(API_KEY.0.to_string(), API_KEY_HASH.to_string());
+
+        let auth = Authenticator::from_config(config::Auth {
+            username: String::from("admin"),
+            passhash: String::from(API_KEY_HASH),
+            secret: String::from("harness"),
+            api_keys,
+            defaults: HashMap::new(),
+            max_attempts: 3,
+            lockout: 60,
+            webauthn: None,
+        }, repository.path().to_path_buf()).await?;
+
+        let status = Arc::new(Status::new());
+
+        let index = Arc::new(Index::new());
+        tokio::spawn(crate::index::follow(index.clone(), repository.clone(), status.clone()));
+
+        let correspondents = Arc::new(Correspondents::load(repository.path().join("correspondents.json")).await?);
+        let rules = Arc::new(Rules::load(repository.clone()).await?);
+
+        let queue = Queue::new(config::Queue { retries: 0, ..config::Queue::default() },
+                               repository.clone(),
+                               Arc::new(Juicer {}),
+                               HashMap::new(),
+                               rules.clone(),
+                               correspondents.clone(),
+                               status.clone());
+
+        let suggester = crate::suggester::dumb::Suggester::from_config(config::DumbSuggester {
+            path: repository.path().join("suggester").to_string_lossy().into_owned(),
+        }).await?;
+
+        let rocket = crate::web::server(
+            config::Web {
+                address: String::from("127.0.0.1"),
+                port,
+                undo_window: 30,
+                frontend: config::Frontend::default(),
+                cors: config::Cors::default(),
+                proxies: vec![],
+                tls: None,
+                socket: None,
+                namespace: None,
+                scanner: HashMap::new(),
+            },
+            crate::web::Services {
+                auth: Arc::new(auth),
+                repository: repository.clone(),
+                index: index.clone(),
+                queue: queue.clone(),
+                suggester: Box::new(suggester),
+                preferences: Preferences::with_path(repository.path().join("preferences")).await?,
+                keyring: Keyring::new(vec![], repository.path().join("domains")).await?,
+                filing: Filing::new(None, repository.clone()),
+                mailer: Mailer::new(None),
+                previews: Previews::from_config(config::Previews::default(), &repository),
+                transcriber: Transcriber::new(None),
+                merger: Merger::new(config::Merge::default(), queue.clone()),
+                editor: Editor::new(config::Editing::default(), queue.clone()),
+                quotas: Quotas::new(config::Quota::default()),
+                labels: Labels::load(repository.clone()).await?,
+                correspondents,
+                persons: Persons::load(repository.path().join("persons.json")).await?,
+                checklists: Checklists::load(repository.path().join("checklists.json")).await?,
+                rules: rules.clone(),
+                requests: Requests::load(repository.path().join("requests.json")).await?,
+                shares: Shares::load(repository.path().join("shares.json"), repository.path().join("shares.key")).await?,
+                repositories: Repositories::default(),
+                reloader: Arc::new(Reloader::new(None, queue.clone(), rules, Vec::new(), None, status.clone())),
+            },
+            status.clone(),
+        )?;
+
+        return Ok((repository, index, queue, status, rocket));
+    }
+
+    /// The client dispatching requests to the server.
+    pub fn client(&self) -> &Client {
+        return &self.client;
+    }
+
+    /// The header authorizing requests by the API key of the harness.
+    pub fn authorization() -> Header<'static> {
+        let basic = base64::encode(format!("{}:{}", API_KEY.0, API_KEY.1));
+        return Header::new("Authorization", format!("Basic {}", basic));
+    }
+
+    /// Waits for a document to land in the inbox after uploading it.
+    pub async fn juiced(&self, id: DocId) -> Result<()> {
+        return self.wait(|| async move { self.repository.inbox().get(id).await.is_some() }).await;
+    }
+
+    /// Waits for an archived document to be indexed.
+    pub async fn indexed(&self, id: DocId) -> Result<()> {
+        return self.wait(|| async move { self.index.contains(id).await }).await;
+    }
+
+    async fn wait<F, Fut>(&self, condition: F) -> Result<()>
+        where F: Fn() -> Fut,
+              Fut: std::future::Future<Output=bool> {
+        for _ in 0..500 {
+            if condition().await {
+                return Ok(());
+            }
+
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+
+        bail!("Timed out waiting for the backend");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rocket::http::{ContentType, Status};
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upload_archive_search() {
+        let harness = Harness::new().await.unwrap();
+
+        let response = harness.client().post("/api/upload")
+            .header(ContentType::PDF)
+            .header(Harness::authorization())
+            .body(sample(&["Invoice from ACME", "Total: 42 EUR"]))
+            .dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::Ok);
+
+        let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+        let id = response["id"].as_str().unwrap().parse::<DocId>().unwrap();
+
+        harness.juiced(id).await.unwrap();
+
+        let bundle = harness.repository.inbox().get(id).await.unwrap();
+        assert_that!(bundle.read_plaintext().await.unwrap()).is_equal_to(String::from("Invoice from ACME\nTotal: 42 EUR"));
+        assert_that!(bundle.read_metadata().await.unwrap().pages).is_equal_to(1);
+
+        let response = harness.client().post(format!("/api/inbox/{}", id))
+            .header(ContentType::JSON)
+            .header(Harness::authorization())
+            .body(r#"{"labels": ["invoice"], "properties": {}}"#)
+            .dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::Ok);
+
+        harness.indexed(id).await.unwrap();
+
+        let response = harness.client().get("/api/archive?query=acme")
+            .header(Harness::authorization())
+            .dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::Ok);
+
+        let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+        assert_that!(response["count"].as_u64()).is_equal_to(Some(1));
+        assert_that!(response["docs"][0]["id"].as_str()).is_equal_to(Some(id.to_string().as_str()));
+    }
+}
@@ -3,33 +3,60 @@ use std::str::FromStr;
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
-use elasticsearch::{Elasticsearch, IndexParts, SearchParts};
+use chrono::{DateTime, NaiveDate, Utc};
+use elasticsearch::{BulkParts, DeleteByQueryParts, DeleteParts, Elasticsearch, IndexParts, SearchParts};
+use elasticsearch::http::request::JsonBody;
 use elasticsearch::http::transport::Transport;
+use elasticsearch::indices::{IndicesCreateParts, IndicesDeleteParts, IndicesExistsParts, IndicesGetMappingParts, IndicesPutMappingParts};
+use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::value::{RawValue, Value};
 
+use crate::attachments;
 use crate::config::ElasticsearchIndex as Config;
 use crate::index::SearchResponse;
-use crate::proto::model::{DocId, Label};
-use crate::repository::{Archived, Bundle};
+use crate::proto::model::{DocId, Kind, Label, PropertyValue, Snippet};
+use crate::proto::query::{Comparison, Filter, Query, SortKey};
+use crate::repository::{Archived, Bundle, Listing};
+use crate::snippets::{self, MAX_SNIPPETS, POST_MARK, PRE_MARK};
+use crate::transcription::Transcriber;
 
-const DOCUMENT_TYPE: &str = "document";
+/// Number of pages sent to the index in a single bulk request
+const CHUNK_BATCH: usize = 32;
 
+/// Number of characters in a highlighted snippet
+const SNIPPET_SIZE: usize = 150;
+
+/// Number of leading pages of a document compared when looking for similar documents
+const SIMILAR_PAGES: u64 = 5;
+
+/// The document text is indexed as one child document per page, joined to the parent document holding the metadata.
+/// This keeps the memory required for indexing bounded and allows to score the best matching page of a document
+/// instead of diluting a single match over the whole text of huge documents.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct Source {
-    text: String,
+    relation: String,
+    title: Option<String>,
     uploaded: DateTime<Utc>,
     archived: Option<DateTime<Utc>>,
+    pages: u32,
     labels: HashSet<Label>,
-    properties: HashMap<String, String>,
+    properties: HashMap<String, Value>,
+    correspondent: Option<String>,
+    belongs_to: Option<String>,
+    date: Option<NaiveDate>,
+    due: Option<NaiveDate>,
+    confidence: Option<u8>,
 }
 
 pub struct Index {
     client: Elasticsearch,
 
     index: String,
+
+    /// Whether the index has been created on connect
+    created: bool,
 }
 
 impl Index {
@@ -43,7 +70,247 @@ impl Index {
 
         client.ping().send().await?;
 
-        Ok(Self { client, index })
+        let exists = client.indices()
+            .exists(IndicesExistsParts::Index(&[&index]))
+            .send().await?;
+        let created = if !exists.status_code().is_success() {
+            info!("Creating index {}", index);
+            Self::create(&client, &index).await?;
+            true
+        } else if !Self::is_chunked(&client, &index).await? {
+            // The join between documents and their page chunks can not be added to the documents indexed before
+            info!("Recreating index {} to index the text of documents by page", index);
+            Self::delete(&client, &index).await?;
+            Self::create(&client, &index).await?;
+            true
+        } else {
+            Self::update(&client, &index).await?;
+            false
+        };
+
+        Ok(Self { client, index, created })
+    }
+
+    /// Returns true if the index has been created on connect, so all archived documents must be indexed.
+    pub fn is_created(&self) -> bool {
+        return self.created;
+    }
+
+    /// Checks if the mapping of an existing index joins documents and their page chunks.
+    async fn is_chunked(client: &Elasticsearch, index: &str) -> Result<bool> {
+        let response = client.indices()
+            .get_mapping(IndicesGetMappingParts::Index(&[index]))
+            .send().await?;
+
+        if !response.status_code().is_success() {
+            return Err(anyhow!(
+                "ElasticSearch mapping retrieval error: {}",
+                response.read_body::<Box<RawValue>>().await?
+            ));
+        }
+
+        let mappings = response.read_body::<Value>().await?;
+        return Ok(mappings[index]["mappings"]["properties"]["relation"]["type"] == "join");
+    }
+
+    async fn delete(client: &Elasticsearch, index: &str) -> Result<()> {
+        let response = client.indices()
+            .delete(IndicesDeleteParts::Index(&[index]))
+            .send().await?;
+
+        if !response.status_code().is_success() {
+            return Err(anyhow!(
+                "ElasticSearch index deletion error: {}",
+                response.read_body::<Box<RawValue>>().await?
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn create(client: &Elasticsearch, index: &str) -> Result<()> {
+        let response = client.indices()
+            .create(IndicesCreateParts::Index(index))
+            .body(json!({
+                "settings": {
+                    "analysis": {
+                        "normalizer": {
+                            "lowercase": { "type": "custom", "filter": ["lowercase"] }
+                        }
+                    }
+                },
+                "mappings": {
+                    "dynamic_templates": Self::templates(),
+                    "properties": {
+                        "relation": { "type": "join", "relations": { "document": "chunk" } },
+                        "text": { "type": "text" },
+                        "page": { "type": "integer" },
+                        "pages": { "type": "integer" },
+                        "title": { "type": "text", "fields": { "keyword": { "type": "keyword", "normalizer": "lowercase" } } },
+                        "uploaded": { "type": "date" },
+                        "archived": { "type": "date" },
+                        "labels": { "type": "text", "fields": { "keyword": { "type": "keyword" } } },
+                        "correspondent": { "type": "keyword", "normalizer": "lowercase" },
+                        "belongs_to": { "type": "keyword", "normalizer": "lowercase" },
+                        "date": { "type": "date" },
+                        "due": { "type": "date" },
+                        "confidence": { "type": "byte" },
+                    }
+                }
+            }))
+            .send().await?;
+
+        if !response.status_code().is_success() {
+            return Err(anyhow!(
+                "ElasticSearch index creation error: {}",
+                response.read_body::<Box<RawValue>>().await?
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Mappings of properties by their type.
+    ///
+    /// String properties are mapped as keywords to be sortable, the keyword subfield is kept for compatibility with
+    /// existing queries. Locations are the only properties indexed as objects and are mapped as geo points.
+    fn templates() -> Value {
+        return json!([
+            {
+                "properties": {
+                    "path_match": "properties.*",
+                    "match_mapping_type": "string",
+                    "mapping": { "type": "keyword", "fields": { "keyword": { "type": "keyword" } } },
+                }
+            },
+            {
+                "locations": {
+                    "path_match": "properties.*",
+                    "match_mapping_type": "object",
+                    "mapping": { "type": "geo_point" },
+                }
+            },
+        ]);
+    }
+
+    /// Updates the dynamic templates of an existing index, which apply to properties not indexed before.
+    async fn update(client: &Elasticsearch, index: &str) -> Result<()> {
+        let response = client.indices()
+            .put_mapping(IndicesPutMappingParts::Index(&[index]))
+            .body(json!({
+                "dynamic_templates": Self::templates(),
+                "properties": {
+                    "belongs_to": { "type": "keyword", "normalizer": "lowercase" },
+                    "confidence": { "type": "byte" },
+                    "date": { "type": "date" },
+                },
+            }))
+            .send().await?;
+
+        if !response.status_code().is_success() {
+            return Err(anyhow!(
+                "ElasticSearch mapping update error: {}",
+                response.read_body::<Box<RawValue>>().await?
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Removes the page chunks of a document.
+    async fn remove_chunks(&self, id: &str) -> Result<()> {
+        let response = self.client
+            .delete_by_query(DeleteByQueryParts::Index(&[&self.index]))
+            .body(json!({
+                "query": { "parent_id": { "type": "chunk", "id": id } }
+            }))
+            .send().await?;
+
+        if !response.status_code().is_success() {
+            return Err(anyhow!(
+                "ElasticSearch chunk removal error: {}",
+                response.read_body::<Box<RawValue>>().await?
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the page chunks of a document.
+    async fn index_chunks(&self, id: &str, bundle: &Bundle<'_, Archived>) -> Result<()> {
+        // Remove chunks of a previous indexing run as the page count may have changed
+        self.remove_chunks(id).await?;
+
+        let mut pages = bundle.read_pages().await?;
+        let mut page = 0u64;
+        let mut done = false;
+
+        while !done {
+            let mut body: Vec<JsonBody<Value>> = Vec::with_capacity(2 * CHUNK_BATCH);
+            while body.len() < 2 * CHUNK_BATCH {
+                let text = match pages.next_segment().await? {
+                    Some(text) => text,
+                    None => {
+                        done = true;
+                        break;
+                    }
+                };
+
+                page += 1;
+
+                let text = String::from_utf8_lossy(&text);
+                if text.trim().is_empty() {
+                    continue;
+                }
+
+                body.push(json!({ "index": { "_id": format!("{}-{}", id, page), "routing": id } }).into());
+                body.push(json!({
+                    "relation": { "name": "chunk", "parent": id },
+                    "page": page,
+                    "text": text,
+                }).into());
+            }
+
+            self.bulk(id, body).await?;
+        }
+
+        // Transcripts of attached audio and the text of other attachments are indexed as chunks apart from the pages
+        let mut body: Vec<JsonBody<Value>> = Vec::new();
+        for name in bundle.fragment_names().await?.into_iter().filter(|name| Transcriber::is_transcript(name) || attachments::is_text(name)) {
+            let text = tokio::fs::read_to_string(bundle.path_of(Kind::other(&name))).await?;
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            body.push(json!({ "index": { "_id": format!("{}-{}", id, name), "routing": id } }).into());
+            body.push(json!({
+                "relation": { "name": "chunk", "parent": id },
+                "text": text,
+            }).into());
+        }
+
+        self.bulk(id, body).await?;
+
+        Ok(())
+    }
+
+    async fn bulk(&self, id: &str, body: Vec<JsonBody<Value>>) -> Result<()> {
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        debug!("Indexing {} chunks of {}", body.len() / 2, id);
+        let response = self.client
+            .bulk(BulkParts::Index(&self.index))
+            .body(body)
+            .send().await?;
+
+        let response = response.read_body::<Value>().await?;
+        if response["errors"].as_bool().unwrap_or(true) {
+            return Err(anyhow!("ElasticSearch bulk error: {}", response));
+        }
+
+        return Ok(());
     }
 
     async fn query(&self, mut query: Value) -> Result<SearchResponse> {
@@ -52,7 +319,7 @@ impl Index {
 
         // Execute the query
         let response = self.client
-            .search(SearchParts::IndexType(&[&self.index], &[DOCUMENT_TYPE]))
+            .search(SearchParts::Index(&[&self.index]))
             .body(query)
             .send().await?;
 
@@ -75,7 +342,141 @@ impl Index {
             .map(DocId::from_str)
             .collect::<Result<Vec<_>>>()?;
 
-        Ok(SearchResponse { count, docs })
+        let snippets = response["hits"]["hits"].as_array()
+            .expect("no array")
+            .iter()
+            .map(|hit| Ok((DocId::from_str(hit["_id"].as_str().expect("no atr"))?, Self::snippets(hit))))
+            .filter(|result| result.as_ref().map_or(true, |(_, snippets)| !snippets.is_empty()))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(SearchResponse { count, docs, snippets })
+    }
+
+    /// Requests the best matching page of a text clause along with the highlighted matches.
+    fn highlight(name: String) -> Value {
+        return json!({
+            "name": name,
+            "size": 1,
+            "_source": ["page"],
+            "highlight": {
+                "fields": {
+                    "text": {
+                        "pre_tags": [PRE_MARK.to_string()],
+                        "post_tags": [POST_MARK.to_string()],
+                        "fragment_size": SNIPPET_SIZE,
+                        "number_of_fragments": MAX_SNIPPETS,
+                    }
+                }
+            },
+        });
+    }
+
+    /// Collects the highlighted snippets of the pages matching the text clauses of a document.
+    fn snippets(hit: &Value) -> Vec<Snippet> {
+        let inner = match hit["inner_hits"].as_object() {
+            Some(inner) => inner,
+            None => return Vec::new(),
+        };
+
+        return inner.values()
+            .filter_map(|inner| inner["hits"]["hits"].as_array())
+            .flatten()
+            .flat_map(|chunk| {
+                let page = chunk["_source"]["page"].as_u64().map(|page| page as u32);
+                chunk["highlight"]["text"].as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(move |marked| snippets::parse_marked(marked, page))
+            })
+            .take(MAX_SNIPPETS)
+            .collect();
+    }
+
+    fn range(field: &str, comparison: Comparison, value: Value) -> Value {
+        let range = match comparison {
+            Comparison::Eq => json!({ "gte": value, "lte": value }),
+            Comparison::Lt => json!({ "lt": value }),
+            Comparison::Le => json!({ "lte": value }),
+            Comparison::Gt => json!({ "gt": value }),
+            Comparison::Ge => json!({ "gte": value }),
+        };
+
+        return json!({ "range": { field: range } });
+    }
+
+    fn date(date: &NaiveDate) -> Value {
+        return json!(date.format("%Y-%m-%d").to_string());
+    }
+
+    /// Converts a property value to the indexed representation.
+    ///
+    /// Properties are mapped dynamically, so dates are picked up by date detection and numbers are mapped as numbers.
+    /// Amounts are indexed as plain numbers to allow range queries, the currency is not searchable. Locations are
+    /// indexed as geo points.
+    fn property(value: &PropertyValue) -> Value {
+        return match value {
+            PropertyValue::Boolean(value) => json!(value),
+            PropertyValue::Integer(value) => json!(value),
+            PropertyValue::Decimal { amount, .. } => json!(amount.to_f64()),
+            PropertyValue::Date(date) => Self::date(date),
+            PropertyValue::Location { lat, lon } => json!({ "lat": lat.to_f64(), "lon": lon.to_f64() }),
+            PropertyValue::String(value) => json!(value),
+        };
+    }
+
+    /// Scores a document by its best matching page
+    fn chunks(query: Value) -> Value {
+        return json!({
+            "has_child": {
+                "type": "chunk",
+                "score_mode": "max",
+                "query": query,
+            }
+        });
+    }
+
+    /// Translates a filter term to the elasticsearch query DSL
+    fn clause(filter: &Filter) -> Value {
+        return match filter {
+            Filter::Text(text) => Self::chunks(json!({ "simple_query_string": { "query": text, "fields": ["text"] } })),
+            Filter::Phrase(phrase) => Self::chunks(json!({ "match_phrase": { "text": phrase } })),
+            Filter::Label(label) => json!({
+                "bool": {
+                    "should": [
+                        { "term": { "labels.keyword": label.to_string() } },
+                        { "prefix": { "labels.keyword": format!("{}{}", label, Label::SEPARATOR) } },
+                    ],
+                    "minimum_should_match": 1,
+                }
+            }),
+            Filter::Title(title) => json!({ "match": { "title": title } }),
+            Filter::Correspondent(name) => json!({ "term": { "correspondent": name } }),
+            Filter::BelongsTo(name) => json!({ "term": { "belongs_to": name } }),
+            Filter::Property { key, comparison, value } => {
+                // Strings are matched against the exact keyword instead of the analyzed text
+                let field = match value {
+                    PropertyValue::String(_) => format!("properties.{}.keyword", key),
+                    _ => format!("properties.{}", key),
+                };
+
+                match comparison {
+                    Comparison::Eq => json!({ "term": { field: Self::property(value) } }),
+                    comparison => Self::range(&field, *comparison, Self::property(value)),
+                }
+            }
+            Filter::Near { key, center, radius } => json!({
+                "geo_distance": {
+                    "distance": format!("{}m", radius),
+                    format!("properties.{}", key): Self::property(center),
+                }
+            }),
+            Filter::Uploaded(comparison, date) => Self::range("uploaded", *comparison, Self::date(date)),
+            Filter::Archived(comparison, date) => Self::range("archived", *comparison, Self::date(date)),
+            Filter::Dated(comparison, date) => Self::range("date", *comparison, Self::date(date)),
+            Filter::Due(comparison, date) => Self::range("due", *comparison, Self::date(date)),
+            Filter::Confidence(comparison, confidence) => Self::range("confidence", *comparison, json!(confidence)),
+        };
     }
 }
 
@@ -84,34 +485,139 @@ impl super::Index for Index {
     async fn index<'r>(&self, bundle: &Bundle<'r, Archived>) -> Result<()> {
         let id = bundle.id().to_string();
 
-        let text = bundle.read_plaintext().await?;
         let meta = bundle.read_metadata().await?;
+        let encrypted = meta.domain.is_some();
 
         self.client
-            .index(IndexParts::IndexTypeId(&self.index, DOCUMENT_TYPE, &id))
+            .index(IndexParts::IndexId(&self.index, &id))
             .body(Source {
-                text,
+                relation: String::from("document"),
+                title: meta.title,
                 uploaded: meta.uploaded,
                 archived: meta.archived,
+                pages: meta.pages,
                 labels: meta.labels,
-                properties: meta.properties,
+                properties: meta.properties.iter()
+                    .map(|(key, value)| (key.clone(), Self::property(value)))
+                    .collect(),
+                correspondent: meta.correspondent,
+                belongs_to: meta.belongs_to,
+                date: meta.date,
+                due: meta.due,
+                confidence: meta.confidence,
             })
             .send().await?;
 
+        // The plaintext of encrypted documents must not leak into the index
+        if !encrypted {
+            self.index_chunks(&id, bundle).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&self, id: &DocId) -> Result<()> {
+        let id = id.to_string();
+
+        self.remove_chunks(&id).await?;
+
+        self.client
+            .delete(DeleteParts::IndexId(&self.index, &id))
+            .send().await?;
+
         Ok(())
     }
 
-    async fn search(&self, query: &str) -> Result<SearchResponse> {
+    async fn search(&self, query: &Query, listing: &Listing) -> Result<SearchResponse> {
+        let (must_not, must): (Vec<_>, Vec<_>) = query.terms.iter()
+            .partition(|term| term.negated);
+
+        let mut must = must.into_iter()
+            .enumerate()
+            .map(|(i, term)| {
+                let mut clause = Self::clause(&term.filter);
+                if let Filter::Text(_) | Filter::Phrase(_) = term.filter {
+                    clause["has_child"]["inner_hits"] = Self::highlight(format!("text-{}", i));
+                }
+                clause
+            })
+            .collect::<Vec<_>>();
+        must.push(json!({ "term": { "relation": "document" } }));
+
+        let must_not = must_not.into_iter().map(|term| Self::clause(&term.filter)).collect::<Vec<_>>();
+
+        // Without explicit sort order, documents are ordered by relevance. Fields missing in indices created by older
+        // versions are treated as unmapped to allow sorting before a reindex.
+        let sort = match &listing.sort {
+            Some(sort) => {
+                let (field, unmapped) = match &sort.key {
+                    SortKey::Uploaded => (String::from("uploaded"), "date"),
+                    SortKey::Title => (String::from("title.keyword"), "keyword"),
+                    SortKey::Pages => (String::from("pages"), "integer"),
+                    SortKey::Date => (String::from("date"), "date"),
+                    SortKey::Due => (String::from("due"), "date"),
+                    SortKey::Property(key) => (format!("properties.{}", key), "keyword"),
+                };
+
+                json!([{ field: {
+                    "order": if sort.descending { "desc" } else { "asc" },
+                    "unmapped_type": unmapped,
+                } }])
+            }
+            None => json!(["_score"]),
+        };
+
         self.query(json!({
             "query": {
                 "bool" : {
-                    "must" : {
-                        "simple_query_string" : {
-                            "query" : query
-                        }
-                    }
+                    "must" : must,
+                    "must_not" : must_not,
                 }
-            }
+            },
+            "sort": sort,
+            "from": listing.offset,
+            "size": listing.limit,
+        })).await
+    }
+
+    async fn similar(&self, id: &DocId, listing: &Listing) -> Result<SearchResponse> {
+        let id = id.to_string();
+
+        // The leading pages are compared by the term vectors of their chunks, pages missing in the index are skipped.
+        // Labels and title are compared on the document itself.
+        let pages = (1..=SIMILAR_PAGES)
+            .map(|page| json!({ "_index": self.index, "_id": format!("{}-{}", id, page), "routing": id }))
+            .collect::<Vec<_>>();
+
+        self.query(json!({
+            "query": {
+                "bool": {
+                    "should": [
+                        Self::chunks(json!({
+                            "more_like_this": {
+                                "fields": ["text"],
+                                "like": pages,
+                                "min_term_freq": 1,
+                                "max_query_terms": 25,
+                            }
+                        })),
+                        {
+                            "more_like_this": {
+                                "fields": ["title", "labels"],
+                                "like": [{ "_index": self.index, "_id": id }],
+                                "min_term_freq": 1,
+                                "min_doc_freq": 1,
+                            }
+                        },
+                    ],
+                    "minimum_should_match": 1,
+                    "must": [{ "term": { "relation": "document" } }],
+                    "must_not": [{ "ids": { "values": [id] } }],
+                }
+            },
+            "sort": ["_score"],
+            "from": listing.offset,
+            "size": listing.limit,
         })).await
     }
 }
@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::meta::Metadata;
+use crate::proto::model::DocId;
+use crate::proto::query::{Filter, Query, Term};
+use crate::repository::{Archived, Bundle, Listing};
+use crate::snippets;
+
+use super::SearchResponse;
+
+/// Index keeping the archived documents in memory, standing in for Elasticsearch in tests.
+///
+/// Free text terms are matched against the plaintext besides the metadata, all other terms against the metadata only.
+/// There is no ranking, so unsorted results are listed in no particular order.
+#[derive(Default)]
+pub struct Index {
+    docs: RwLock<HashMap<DocId, (Metadata, String)>>,
+}
+
+impl Index {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Checks if a document has been indexed.
+    pub async fn contains(&self, id: DocId) -> bool {
+        return self.docs.read().await.contains_key(&id);
+    }
+}
+
+/// Evaluates a query against a document.
+fn matches(query: &Query, metadata: &Metadata, plaintext: &str) -> bool {
+    return query.terms.iter().all(|term| {
+        let matched = match &term.filter {
+            Filter::Text(text) | Filter::Phrase(text) if plaintext.to_lowercase().contains(&text.to_lowercase()) => true,
+            _ => metadata.matches(&Query { terms: vec![Term { negated: false, filter: term.filter.clone() }] }),
+        };
+
+        return matched != term.negated;
+    });
+}
+
+/// Splits a text into the set of its lowercase words, skipping short words which are mostly stop words and numbers.
+fn words(text: &str) -> HashSet<String> {
+    return text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect();
+}
+
+/// Jaccard similarity of two sets.
+fn overlap<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+
+    return a.intersection(b).count() as f64 / union as f64;
+}
+
+#[async_trait]
+impl super::Index for Index {
+    async fn index<'r>(&self, bundle: &Bundle<'r, Archived>) -> Result<()> {
+        let metadata = bundle.read_metadata().await?;
+        let plaintext = bundle.read_plaintext().await?;
+
+        self.docs.write().await.insert(*bundle.id(), (metadata, plaintext));
+
+        return Ok(());
+    }
+
+    async fn remove(&self, id: &DocId) -> Result<()> {
+        self.docs.write().await.remove(id);
+        return Ok(());
+    }
+
+    async fn search(&self, query: &Query, listing: &Listing) -> Result<SearchResponse> {
+        let docs = self.docs.read().await;
+
+        let page = listing.apply(docs.iter()
+            .filter(|(_, (metadata, plaintext))| matches(query, metadata, plaintext))
+            .map(|(id, (metadata, _))| (*id, metadata.clone()))
+            .collect());
+
+        let terms = query.text()
+            .filter_map(|filter| match filter {
+                Filter::Text(text) | Filter::Phrase(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let ids = page.items.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        let snippets = ids.iter()
+            .filter_map(|id| docs.get(id).map(|(_, plaintext)| (*id, snippets::find(plaintext, &terms))))
+            .filter(|(_, snippets)| !snippets.is_empty())
+            .collect();
+
+        return Ok(SearchResponse {
+            count: page.total as u64,
+            docs: ids,
+            snippets,
+        });
+    }
+
+    async fn similar(&self, id: &DocId, listing: &Listing) -> Result<SearchResponse> {
+        let docs = self.docs.read().await;
+
+        let (metadata, plaintext) = match docs.get(id) {
+            Some(doc) => doc,
+            None => return Ok(SearchResponse { count: 0, docs: Vec::new(), snippets: HashMap::new() }),
+        };
+        let words = words(plaintext);
+
+        // Scores by the shared words of the text and the shared labels instead of term vectors
+        let mut similar = docs.iter()
+            .filter(|(other, _)| *other != id)
+            .map(|(other, (other_metadata, other_plaintext))| {
+                let score = overlap(&words, &self::words(other_plaintext)) + overlap(&metadata.labels, &other_metadata.labels);
+                (score, *other, other_metadata.clone())
+            })
+            .filter(|(score, _, _)| *score > 0.0)
+            .collect::<Vec<_>>();
+        similar.sort_by(|(a, _, _), (b, _, _)| b.partial_cmp(a).expect("Invalid score"));
+
+        let page = Listing { sort: None, group: None, ..listing.clone() }.apply(similar.into_iter()
+            .map(|(_, id, metadata)| (id, metadata))
+            .collect());
+
+        return Ok(SearchResponse {
+            count: page.total as u64,
+            docs: page.items.into_iter().map(|(id, _)| id).collect(),
+            snippets: HashMap::new(),
+        });
+    }
+}
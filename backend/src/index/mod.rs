@@ -1,22 +1,75 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use anyhow::Result;
 use async_trait::async_trait;
+use log::{error, warn};
 #[cfg(test)]
 use mockall::automock;
+use tokio::sync::broadcast::RecvError;
 
-use crate::proto::model::DocId;
-use crate::repository::{Archived, Bundle};
+use crate::proto::model::{DocId, Snippet};
+use crate::proto::query::Query;
+use crate::repository::{Archived, Bundle, Event, Listing, Repository};
+use crate::status::Status;
 
 pub mod elasticsearch;
+#[cfg(any(test, feature = "testing"))]
+pub mod memory;
 
 #[derive(Debug, Clone)]
 pub struct SearchResponse {
     pub count: u64,
     pub docs: Vec<DocId>,
+
+    /// Text snippets showing the matched terms, by document
+    pub snippets: HashMap<DocId, Vec<Snippet>>,
 }
 
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait Index {
     async fn index<'r>(&self, bundle: &Bundle<'r, Archived>) -> Result<()>;
-    async fn search(&self, query: &str) -> Result<SearchResponse>;
+    async fn remove(&self, id: &DocId) -> Result<()>;
+    async fn search(&self, query: &Query, listing: &Listing) -> Result<SearchResponse>;
+
+    /// Finds documents with content and labels similar to the given document, ordered by similarity.
+    async fn similar(&self, id: &DocId, listing: &Listing) -> Result<SearchResponse>;
+}
+
+/// Keeps the index in sync with the archive by following the repository events.
+pub async fn follow(index: Arc<dyn Index + Send + Sync>, repository: Repository, status: Arc<Status>) {
+    let mut events = repository.subscribe();
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Indexer missed {} repository events", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        let result = match event {
+            Event::Archived(id) | Event::Restored(id) | Event::MetadataUpdated(id) => {
+                match repository.archive().get(id).await {
+                    Some(bundle) => index.index(&bundle).await,
+                    None => Ok(()),
+                }
+            }
+
+            Event::Unarchived(id) | Event::Trashed(id) | Event::Purged(id) | Event::Erased(id) => index.remove(&id).await,
+
+            Event::Staged(_) | Event::Inboxed(_) | Event::Quarantined(_) => Ok(()),
+        };
+
+        match result {
+            Ok(()) => status.indexed(),
+            Err(err) => {
+                error!("Failed to update index for bundle {}: {:#}", event.id(), err);
+                status.failed("index", &err);
+            }
+        }
+    }
 }
@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use log::{error, info, warn};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::config::{Consume as Config, Defaults};
+use crate::juicer::converted_format;
+use crate::meta::Metadata;
+use crate::queue::Queue;
+use crate::status::Status;
+
+/// Name of the directory below the consume directory where files which failed to ingest are moved to
+const FAILED: &str = "failed";
+
+/// Watches a directory and ingests every PDF, office document or mail dropped into it.
+pub struct Consumer {
+    path: PathBuf,
+
+    /// Metadata applied to all consumed documents
+    defaults: Defaults,
+
+    queue: Queue,
+
+    status: Arc<Status>,
+}
+
+impl Consumer {
+    pub async fn from_config(config: Config,
+                             queue: Queue,
+                             status: Arc<Status>) -> Result<Self> {
+        let path = PathBuf::from(config.path);
+
+        tokio::fs::create_dir_all(path.join(FAILED)).await
+            .with_context(|| format!("Creating consume directory {:?}", path))?;
+
+        return Ok(Self { path, defaults: config.defaults, queue, status });
+    }
+
+    /// Consumes all existing files and then keeps watching for new ones.
+    pub async fn run(self) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // The watcher is blocking, so it lives on its own thread and forwards the changed paths
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let (events_tx, events_rx) = std::sync::mpsc::channel();
+
+            let mut watcher = notify::watcher(events_tx, Duration::from_secs(2))?;
+            watcher.watch(&path, RecursiveMode::NonRecursive)?;
+
+            for event in events_rx {
+                match event {
+                    DebouncedEvent::Create(path) |
+                    DebouncedEvent::Write(path) |
+                    DebouncedEvent::Rename(_, path) => {
+                        if tx.send(path).is_err() {
+                            break;
+                        }
+                    }
+
+                    DebouncedEvent::Error(err, path) => {
+                        warn!("Error watching consume directory {:?}: {}", path, err);
+                    }
+
+                    _ => {}
+                }
+            }
+
+            return Ok(());
+        });
+
+        // Pick up files dropped while we were not running
+        let existing = tokio::fs::read_dir(&self.path).await?
+            .map_ok(|entry| entry.path())
+            .try_collect::<Vec<_>>().await?;
+        for path in existing {
+            self.consume(&path).await;
+        }
+
+        while let Some(path) = rx.recv().await {
+            self.consume(&path).await;
+        }
+
+        return Ok(());
+    }
+
+    async fn consume(&self, path: &Path) {
+        // Only consume regular PDF, office, image or mail files which still exist - a file may have been consumed already
+        let extension = if path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("pdf")) {
+            "pdf"
+        } else if path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("eml")) {
+            "eml"
+        } else {
+            match converted_format(None, path.file_name().and_then(|name| name.to_str())) {
+                Some(extension) => extension,
+                None => return,
+            }
+        };
+
+        match tokio::fs::metadata(path).await {
+            Ok(metadata) if metadata.is_file() => {}
+            _ => return,
+        }
+
+        info!("Consuming {:?}", path);
+
+        let result: Result<()> = async {
+            if extension == "eml" {
+                // Mails are split into the body and the attachments
+                let raw = tokio::fs::read(path).await?;
+
+                let docs = super::mail::ingest(&self.queue, &raw, true, None, &self.defaults).await?;
+                info!("Consumed {:?} as {} bundles", path, docs.len());
+            } else {
+                let file = tokio::fs::File::open(path).await?;
+
+                let metadata = Metadata {
+                    filename: path.file_name().map(|name| name.to_string_lossy().into_owned()),
+                    ..Metadata::new()
+                }.with_defaults(&self.defaults);
+
+                let id = super::ingest(&self.queue, file, extension, metadata).await?;
+                info!("Consumed {:?} as bundle {}", path, id);
+            }
+
+            tokio::fs::remove_file(path).await?;
+
+            return Ok(());
+        }.await;
+
+        if let Err(err) = result {
+            error!("Failed to consume {:?}: {:#}", path, err);
+            self.status.failed("consume", &err);
+
+            // Move the file aside to avoid consuming it over and over again
+            let failed = self.path.join(FAILED).join(path.file_name().expect("No filename"));
+            if let Err(err) = tokio::fs::rename(path, &failed).await {
+                error!("Failed to move {:?} to {:?}: {}", path, failed, err);
+            }
+        }
+    }
+}
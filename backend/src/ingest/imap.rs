@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{error, info};
+
+use crate::config::Imap as Config;
+use crate::queue::Queue;
+use crate::status::Status;
+
+/// Periodically polls an IMAP mailbox and ingests the PDF and office document attachments of all unseen mails.
+pub struct Mailbox {
+    config: Arc<Config>,
+
+    queue: Queue,
+
+    status: Arc<Status>,
+}
+
+impl Mailbox {
+    pub async fn from_config(config: Config,
+                             queue: Queue,
+                             status: Arc<Status>) -> Result<Self> {
+        return Ok(Self {
+            config: Arc::new(config),
+            queue,
+            status,
+        });
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval));
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.poll().await {
+                error!("Failed to poll mailbox {}@{}: {:#}", self.config.username, self.config.host, err);
+                self.status.failed("imap", &err);
+            }
+        }
+    }
+
+    async fn poll(&self) -> Result<()> {
+        let config = self.config.clone();
+        let mails = tokio::task::spawn_blocking(move || fetch(&config)).await??;
+
+        info!("Fetched {} unseen mails from {}", mails.len(), self.config.host);
+
+        let mut processed = Vec::with_capacity(mails.len());
+        for (uid, raw) in mails {
+            match super::mail::ingest(&self.queue, &raw, self.config.include_body, None, &self.config.defaults).await {
+                Ok(_) => processed.push(uid),
+                Err(err) => {
+                    error!("Failed to ingest mail {}: {:#}", uid, err);
+                    self.status.failed("imap", &err);
+                }
+            }
+        }
+
+        if !processed.is_empty() {
+            let config = self.config.clone();
+            tokio::task::spawn_blocking(move || mark(&config, &processed)).await??;
+        }
+
+        return Ok(());
+    }
+}
+
+type Session = imap::Session<native_tls::TlsStream<std::net::TcpStream>>;
+
+fn connect(config: &Config) -> Result<Session> {
+    let tls = native_tls::TlsConnector::builder().build()?;
+    let client = imap::connect((config.host.as_str(), config.port), &config.host, &tls)?;
+
+    let mut session = client.login(&config.username, &config.password)
+        .map_err(|(err, _)| anyhow!("Login failed: {}", err))?;
+    session.select(&config.mailbox)?;
+
+    return Ok(session);
+}
+
+/// Fetches all unseen mails without marking them as seen.
+fn fetch(config: &Config) -> Result<Vec<(u32, Vec<u8>)>> {
+    let mut session = connect(config)?;
+
+    let mut mails = Vec::new();
+    for uid in session.uid_search("UNSEEN")? {
+        for message in session.uid_fetch(uid.to_string(), "BODY.PEEK[]")?.iter() {
+            if let Some(body) = message.body() {
+                mails.push((uid, body.to_vec()));
+            }
+        }
+    }
+
+    session.logout()?;
+
+    return Ok(mails);
+}
+
+/// Marks the given mails as processed by flagging them as seen or deleting them.
+fn mark(config: &Config, uids: &[u32]) -> Result<()> {
+    let mut session = connect(config)?;
+
+    let set = uids.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+    if config.delete {
+        session.uid_store(&set, "+FLAGS (\\Deleted)")?;
+        session.expunge()?;
+    } else {
+        session.uid_store(&set, "+FLAGS (\\Seen)")?;
+    }
+
+    session.logout()?;
+
+    return Ok(());
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use log::{info, warn};
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+
+use crate::config::Defaults;
+use crate::juicer::converted_format;
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, PropertyValue, Relation, RelationKind};
+use crate::queue::Queue;
+use crate::render::render_text;
+
+/// A document extracted from a mail.
+struct Document {
+    title: String,
+    filename: Option<String>,
+
+    /// File extension of the original, either `pdf` or a format converted by the juicer
+    extension: &'static str,
+
+    data: Vec<u8>,
+}
+
+/// Ingests a mail given in its raw RFC 822 form.
+///
+/// If `include_body` is set, the plain text body is rendered and ingested as a document on its own. Every PDF, office
+/// document and scan attached is ingested as a separate bundle linked to the body. Sender, subject and date of the mail
+/// are kept as properties of all bundles, which are owned by the given user, if any, and get the defaults of the source
+/// applied.
+///
+/// Returns the IDs and initial metadata of the ingested bundles, the body first.
+pub async fn ingest(queue: &Queue, raw: &[u8], include_body: bool, owner: Option<&str>, defaults: &Defaults) -> Result<Vec<(DocId, Metadata)>> {
+    let mail = mailparse::parse_mail(raw)?;
+
+    let subject = mail.headers.get_first_value("Subject");
+    let properties = properties(&mail);
+
+    let body = match body(&mail)? {
+        Some(body) if include_body => {
+            let title = subject.clone().unwrap_or_else(|| String::from("Mail"));
+            let text = body.lines().map(str::to_string).collect::<Vec<_>>();
+
+            Some(Document {
+                data: render_text(&title, &text)?,
+                title,
+                filename: None,
+                extension: "pdf",
+            })
+        }
+        _ => None,
+    };
+
+    let attachments = attachments(&mail)?;
+
+    if body.is_none() && attachments.is_empty() {
+        warn!("Mail without documents: {:?}", subject);
+    }
+
+    let mut docs = Vec::new();
+
+    let mut parent = None;
+    if let Some(document) = body {
+        let metadata = metadata(document.title, None, owner, &properties, defaults);
+
+        let id = super::ingest(queue, &document.data[..], document.extension, metadata.clone()).await?;
+        info!("Ingested mail body as bundle {}", id);
+
+        parent = Some(id);
+        docs.push((id, metadata));
+    }
+
+    for document in attachments {
+        let mut metadata = metadata(document.title, document.filename, owner, &properties, defaults);
+        if let Some(parent) = parent {
+            metadata.relations.insert(Relation { kind: RelationKind::AttachmentOf, target: parent });
+        }
+
+        let id = super::ingest(queue, &document.data[..], document.extension, metadata.clone()).await?;
+        info!("Ingested mail attachment as bundle {}", id);
+
+        docs.push((id, metadata));
+    }
+
+    return Ok(docs);
+}
+
+fn metadata(title: String,
+            filename: Option<String>,
+            owner: Option<&str>,
+            properties: &HashMap<String, PropertyValue>,
+            defaults: &Defaults) -> Metadata {
+    return Metadata {
+        title: Some(title),
+        filename,
+        owner: owner.map(String::from),
+        properties: properties.clone(),
+        ..Metadata::new()
+    }.with_defaults(defaults);
+}
+
+/// Extracts sender, subject and date of a mail as properties.
+fn properties(mail: &ParsedMail) -> HashMap<String, PropertyValue> {
+    let mut properties = HashMap::new();
+
+    if let Some(from) = mail.headers.get_first_value("From") {
+        properties.insert(String::from("mail.from"), from.into());
+    }
+    if let Some(subject) = mail.headers.get_first_value("Subject") {
+        properties.insert(String::from("mail.subject"), subject.into());
+    }
+    if let Some(date) = mail.headers.get_first_value("Date").and_then(|date| mailparse::dateparse(&date).ok()) {
+        properties.insert(String::from("mail.date"), PropertyValue::Date(NaiveDateTime::from_timestamp(date, 0).date()));
+    }
+
+    return properties;
+}
+
+/// Extracts all PDF, office document and image attachments of a mail.
+fn attachments(mail: &ParsedMail) -> Result<Vec<Document>> {
+    let mut documents = Vec::new();
+
+    for part in mail.parts() {
+        let filename = part.get_content_disposition().params.get("filename").cloned()
+            .or_else(|| part.ctype.params.get("name").cloned());
+
+        let is_pdf = part.ctype.mimetype.eq_ignore_ascii_case("application/pdf")
+            || filename.as_ref().map_or(false, |name| name.to_lowercase().ends_with(".pdf"));
+
+        let extension = if is_pdf {
+            "pdf"
+        } else {
+            match converted_format(Some(&part.ctype.mimetype), filename.as_deref()) {
+                Some(extension) => extension,
+                None => continue,
+            }
+        };
+
+        documents.push(Document {
+            title: filename.clone().unwrap_or_else(|| String::from("Attachment")),
+            filename,
+            extension,
+            data: part.get_body_raw()?,
+        });
+    }
+
+    return Ok(documents);
+}
+
+/// Returns the plain text body of a mail, if any.
+fn body(mail: &ParsedMail) -> Result<Option<String>> {
+    for part in mail.parts() {
+        if part.ctype.mimetype.eq_ignore_ascii_case("text/plain")
+            && part.get_content_disposition().disposition != DispositionType::Attachment {
+            return Ok(Some(part.get_body()?));
+        }
+    }
+
+    return Ok(None);
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+    use spectral::prelude::*;
+
+    use crate::proto::model::Label;
+
+    use super::*;
+
+    const MAIL: &[u8] = b"From: Biller <billing@example.com>\r
+Subject: Your invoice\r
+Date: Tue, 1 Jul 2003 10:52:37 +0200\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"XXX\"\r
+\r
+--XXX\r
+Content-Type: text/plain; charset=utf-8\r
+\r
+Please find your invoice attached.\r
+--XXX\r
+Content-Type: application/pdf; name=\"invoice.pdf\"\r
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r
+Content-Transfer-Encoding: base64\r
+\r
+JVBERi0xLjQK\r
+--XXX\r
+Content-Type: application/vnd.oasis.opendocument.text; name=\"letter.odt\"\r
+Content-Disposition: attachment; filename=\"letter.odt\"\r
+Content-Transfer-Encoding: base64\r
+\r
+UEsDBA==\r
+--XXX\r
+Content-Type: image/png; name=\"logo.png\"\r
+Content-Disposition: attachment; filename=\"logo.png\"\r
+Content-Transfer-Encoding: base64\r
+\r
+iVBORw==\r
+--XXX--\r
+";
+
+    #[test]
+    fn test_attachments() {
+        let mail = mailparse::parse_mail(MAIL).unwrap();
+
+        let documents = attachments(&mail).unwrap();
+        assert_that!(documents).has_length(3);
+        assert_that!(documents[0].title.as_str()).is_equal_to("invoice.pdf");
+        assert_that!(documents[0].extension).is_equal_to("pdf");
+        assert_that!(documents[0].data.as_slice()).is_equal_to(&b"%PDF-1.4\n"[..]);
+        assert_that!(documents[1].title.as_str()).is_equal_to("letter.odt");
+        assert_that!(documents[1].extension).is_equal_to("odt");
+        assert_that!(documents[2].title.as_str()).is_equal_to("logo.png");
+        assert_that!(documents[2].extension).is_equal_to("png");
+    }
+
+    #[test]
+    fn test_body() {
+        let mail = mailparse::parse_mail(MAIL).unwrap();
+
+        let body = body(&mail).unwrap();
+        assert_that!(body.as_deref().map(str::trim)).is_equal_to(Some("Please find your invoice attached."));
+    }
+
+    #[test]
+    fn test_properties() {
+        let mail = mailparse::parse_mail(MAIL).unwrap();
+
+        let properties = properties(&mail);
+        assert_that!(properties.get("mail.from")).is_equal_to(Some(&PropertyValue::from("Biller <billing@example.com>")));
+        assert_that!(properties.get("mail.subject")).is_equal_to(Some(&PropertyValue::from("Your invoice")));
+        assert_that!(properties.get("mail.date")).is_equal_to(Some(&PropertyValue::Date(NaiveDate::from_ymd(2003, 7, 1))));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let mail = mailparse::parse_mail(MAIL).unwrap();
+
+        let defaults = Defaults {
+            labels: vec![Label::from("kitchen")].into_iter().collect(),
+            properties: vec![
+                (String::from("mail.subject"), PropertyValue::from("Overridden")),
+                (String::from("room"), PropertyValue::from("Kitchen")),
+            ].into_iter().collect(),
+            owner: Some(String::from("household")),
+        };
+
+        let metadata = metadata(String::from("Mail"), None, Some("scanner"), &properties(&mail), &defaults);
+        assert_that!(metadata.labels.contains(&Label::from("kitchen"))).is_true();
+        assert_that!(metadata.properties.get("mail.subject")).is_equal_to(Some(&PropertyValue::from("Your invoice")));
+        assert_that!(metadata.properties.get("room")).is_equal_to(Some(&PropertyValue::from("Kitchen")));
+        assert_that!(metadata.owner.as_deref()).is_equal_to(Some("household"));
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use log::{info, trace};
+use tokio::io::AsyncRead;
+
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind};
+use crate::queue::Queue;
+use crate::quota::{Quotas, Reservation};
+
+pub mod consume;
+pub mod imap;
+pub mod mail;
+pub mod paperless;
+
+#[cfg(test)]
+mod test;
+
+/// Stages a document and queues it for juicing, after which it is moved into the inbox.
+///
+/// The document is stored as the original fragment with the given file extension, which must either be `pdf` or one of
+/// the office formats converted by the juicer. The staging bundle is removed if any of the steps fail.
+pub async fn ingest(queue: &Queue,
+                    document: impl AsyncRead + Unpin,
+                    extension: &str,
+                    metadata: Metadata) -> Result<DocId> {
+    return stage(queue, None, document, extension, metadata).await;
+}
+
+/// Ingests a document uploaded by a user, like `ingest`.
+///
+/// The bytes are accounted to the owner of the document. The document is rejected with `quota::Exceeded` as soon as
+/// storing it exceeds the quotas of the repository or the owner.
+pub async fn ingest_within(queue: &Queue,
+                           quotas: &Quotas,
+                           document: impl AsyncRead + Unpin,
+                           extension: &str,
+                           metadata: Metadata) -> Result<DocId> {
+    let reservation = quotas.reserve(queue.repository(), metadata.owner.as_deref(), 0).await?;
+
+    let id = stage(queue, Some(&reservation), document, extension, metadata).await?;
+    reservation.commit();
+
+    return Ok(id);
+}
+
+async fn stage(queue: &Queue,
+               reservation: Option<&Reservation>,
+               mut document: impl AsyncRead + Unpin,
+               extension: &str,
+               metadata: Metadata) -> Result<DocId> {
+    // Create a new staging area
+    let staging = queue.repository().stage().await?;
+
+    info!("Ingesting to staging bundle {}", staging.id());
+
+    let result: Result<()> = async {
+        let original = format!("original.{}", extension);
+
+        let mut original_fragment = staging.write(Kind::other(&original)).await?;
+        let copied = match reservation {
+            Some(reservation) => reservation.copy(&mut document, &mut original_fragment).await,
+            None => tokio::io::copy(&mut document, &mut original_fragment).await.map_err(anyhow::Error::from),
+        };
+        copied.with_context(|| format!("Writing {} to staging", original))?;
+        original_fragment.commit().await?;
+
+        trace!("Original fragment written");
+
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
+
+        trace!("Metadata fragment written");
+
+        return Ok(());
+    }.await;
+
+    match result {
+        Ok(()) => {
+            return queue.enqueue(staging).await;
+        }
+        Err(err) => {
+            staging.delete().await?;
+            return Err(err);
+        }
+    }
+}
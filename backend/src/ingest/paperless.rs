@@ -0,0 +1,451 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+use crate::correspondents::Correspondents;
+use crate::juicer::converted_format;
+use crate::labels::{Definition, Labels};
+use crate::meta::Metadata;
+use crate::proto::model::{Correspondent, DocId, Kind, Label, PropertyValue};
+use crate::queue::Queue;
+use crate::repository::{Bundle, Staging};
+
+/// Property recording the ID of a document in paperless-ngx, which prevents importing it twice
+pub const PROPERTY: &str = "paperless";
+
+/// Property the date a document has been created on is stored as
+const CREATED: &str = "created";
+
+/// An object of the manifest of a paperless-ngx export.
+#[derive(Debug, Deserialize)]
+struct Object {
+    model: String,
+    pk: i64,
+    fields: Value,
+
+    /// Path of the original file relative to the export
+    #[serde(default, rename = "__exported_file_name__")]
+    file: Option<String>,
+
+    /// Path of the archived PDF relative to the export, which carries the text layer created by paperless-ngx
+    #[serde(default, rename = "__exported_archive_name__")]
+    archive: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tag {
+    name: String,
+
+    #[serde(default)]
+    color: Option<String>,
+
+    #[serde(default)]
+    is_inbox_tag: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Named {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FieldInstance {
+    document: i64,
+    field: i64,
+
+    #[serde(flatten)]
+    values: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Document {
+    title: String,
+
+    #[serde(default)]
+    content: String,
+
+    created: String,
+
+    #[serde(default)]
+    added: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    correspondent: Option<i64>,
+
+    #[serde(default)]
+    document_type: Option<i64>,
+
+    #[serde(default)]
+    tags: Vec<i64>,
+
+    mime_type: String,
+
+    #[serde(default)]
+    original_filename: Option<String>,
+}
+
+/// A document to import with its files.
+#[derive(Debug)]
+struct Entry {
+    pk: i64,
+    document: Document,
+    file: Option<String>,
+    archive: Option<String>,
+}
+
+/// The contents of a paperless-ngx export, as created by its `document_exporter`.
+#[derive(Debug, Default)]
+struct Export {
+    tags: HashMap<i64, Tag>,
+    correspondents: HashMap<i64, String>,
+    types: HashMap<i64, String>,
+    fields: HashMap<i64, String>,
+    values: HashMap<i64, Vec<(i64, PropertyValue)>>,
+    documents: Vec<Entry>,
+}
+
+/// Summary of an import.
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub bundles: Vec<DocId>,
+
+    /// Documents imported by a previous run
+    pub skipped: usize,
+
+    pub failed: usize,
+}
+
+/// Converts the value of a custom field, skipping empty ones and those referring to other objects.
+fn value(name: &str, value: &Value) -> Option<PropertyValue> {
+    return match (name, value) {
+        (_, Value::Null) => None,
+        (_, Value::Bool(value)) => Some(PropertyValue::Boolean(*value)),
+        (_, Value::Number(value)) => Some(PropertyValue::parse(&value.to_string())),
+        (_, Value::String(value)) if value.is_empty() => None,
+
+        // Monetary values are prefixed by their currency, i.e. `EUR12.50`
+        ("value_monetary", Value::String(value)) => {
+            let (currency, amount) = value.split_at(value.find(|c: char| !c.is_ascii_uppercase()).unwrap_or(0));
+            if currency.is_empty() {
+                Some(PropertyValue::parse(amount))
+            } else {
+                Some(PropertyValue::parse(&format!("{} {}", amount, currency)))
+            }
+        }
+
+        ("value_date", Value::String(value)) => Some(PropertyValue::parse(value)),
+        (_, Value::String(value)) => Some(PropertyValue::from(value.as_str())),
+
+        _ => None,
+    };
+}
+
+/// Turns a name into one usable as label or correspondent, as paperless-ngx uses slashes freely.
+fn sanitize(name: &str) -> String {
+    return name.trim().replace('/', "-");
+}
+
+impl Export {
+    fn parse(objects: Vec<Object>) -> Result<Self> {
+        let mut export = Self::default();
+
+        let mut instances = Vec::new();
+        for object in objects {
+            let fields = object.fields;
+            match object.model.as_str() {
+                "documents.tag" => { export.tags.insert(object.pk, serde_json::from_value(fields)?); }
+                "documents.correspondent" => { export.correspondents.insert(object.pk, serde_json::from_value::<Named>(fields)?.name); }
+                "documents.documenttype" => { export.types.insert(object.pk, serde_json::from_value::<Named>(fields)?.name); }
+                "documents.customfield" => { export.fields.insert(object.pk, serde_json::from_value::<Named>(fields)?.name); }
+                "documents.customfieldinstance" => { instances.push(serde_json::from_value::<FieldInstance>(fields)?); }
+                "documents.document" => {
+                    export.documents.push(Entry {
+                        pk: object.pk,
+                        document: serde_json::from_value(fields)
+                            .with_context(|| format!("Invalid document: {}", object.pk))?,
+                        file: object.file,
+                        archive: object.archive,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        for instance in instances {
+            let value = instance.values.iter()
+                .filter(|(name, _)| name.starts_with("value_"))
+                .find_map(|(name, v)| value(name, v));
+
+            if let Some(value) = value {
+                export.values.entry(instance.document).or_default().push((instance.field, value));
+            }
+        }
+
+        return Ok(export);
+    }
+
+    /// Builds the metadata of a document.
+    ///
+    /// Tags become labels, except for inbox tags, which leave the document in the inbox. The document type becomes a
+    /// label as well, as adacta has no notion of types. Custom fields become properties.
+    fn metadata(&self, entry: &Entry) -> Metadata {
+        let document = &entry.document;
+
+        let mut metadata = Metadata::new();
+        metadata.title = Some(document.title.clone()).filter(|title| !title.is_empty());
+        metadata.uploaded = document.added.unwrap_or(metadata.uploaded);
+        metadata.filename = document.original_filename.clone();
+
+        let mut inbox = false;
+        for tag in document.tags.iter().filter_map(|pk| self.tags.get(pk)) {
+            if tag.is_inbox_tag {
+                inbox = true;
+            } else {
+                metadata.labels.insert(Label::from(sanitize(&tag.name)));
+            }
+        }
+
+        if let Some(name) = document.document_type.and_then(|pk| self.types.get(&pk)) {
+            metadata.labels.insert(Label::from(sanitize(name)));
+        }
+
+        metadata.labels.retain(Label::is_valid);
+
+        metadata.correspondent = document.correspondent
+            .and_then(|pk| self.correspondents.get(&pk))
+            .map(|name| sanitize(name))
+            .filter(|name| !name.is_empty());
+
+        // Older versions export the creation as timestamp
+        if let Some(created) = document.created.get(..10).and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()) {
+            metadata.properties.insert(String::from(CREATED), PropertyValue::Date(created));
+            metadata.date = Some(created);
+        }
+
+        for (field, value) in self.values.get(&entry.pk).into_iter().flatten() {
+            if let Some(name) = self.fields.get(field) {
+                metadata.properties.insert(name.clone(), value.clone());
+            }
+        }
+
+        metadata.properties.insert(String::from(PROPERTY), PropertyValue::Integer(entry.pk));
+
+        if !inbox {
+            metadata.archived = Some(Utc::now());
+        }
+
+        return metadata;
+    }
+}
+
+/// Imports the documents, tags, correspondents and custom fields of a paperless-ngx export.
+///
+/// The original files are juiced again to render the previews, but the text recognized by paperless-ngx is kept.
+/// Documents which have been imported before are skipped, so an interrupted import can be run again.
+pub struct Paperless<'a> {
+    path: PathBuf,
+
+    queue: &'a Queue,
+    labels: &'a Labels,
+    correspondents: &'a Correspondents,
+}
+
+impl<'a> Paperless<'a> {
+    pub fn new(path: impl Into<PathBuf>, queue: &'a Queue, labels: &'a Labels, correspondents: &'a Correspondents) -> Self {
+        return Self { path: path.into(), queue, labels, correspondents };
+    }
+
+    /// Collects the IDs of documents imported before.
+    async fn imported(&self) -> Result<HashSet<i64>> {
+        let repository = self.queue.repository();
+
+        let mut metadatas = Vec::new();
+        for bundle in repository.inbox().list().await? {
+            metadatas.push(bundle.read_metadata().await?);
+        }
+        for bundle in repository.archive().list().await? {
+            metadatas.push(bundle.read_metadata().await?);
+        }
+
+        return Ok(metadatas.into_iter()
+            .filter_map(|metadata| match metadata.properties.get(PROPERTY) {
+                Some(PropertyValue::Integer(pk)) => Some(*pk),
+                _ => None,
+            })
+            .collect());
+    }
+
+    /// Registers the tags and correspondents which are not known yet.
+    async fn define(&self, export: &Export) -> Result<()> {
+        let defined = self.labels.list().await;
+        for tag in export.tags.values().filter(|tag| !tag.is_inbox_tag) {
+            let label = Label::from(sanitize(&tag.name));
+            if !label.is_valid() || defined.contains_key(&label) {
+                continue;
+            }
+
+            let definition = Definition { color: tag.color.clone(), description: None };
+            if definition.is_valid() {
+                self.labels.define(label, definition).await?;
+            }
+        }
+
+        for name in export.correspondents.values() {
+            let correspondent = Correspondent {
+                name: sanitize(name),
+                aliases: vec![],
+                labels: HashSet::new(),
+            };
+
+            if correspondent.is_valid() && self.correspondents.get(&correspondent.name).await.is_none() {
+                self.correspondents.define(correspondent).await?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    async fn prepare(&self, staging: &Bundle<'_, Staging>, entry: &Entry, metadata: &Metadata) -> Result<()> {
+        let document = &entry.document;
+
+        // Formats not supported by the juicer are imported from the archived PDF
+        let extension = if document.mime_type == "application/pdf" {
+            Some("pdf")
+        } else {
+            converted_format(Some(&document.mime_type), document.original_filename.as_deref())
+        };
+
+        let (file, extension) = match (&entry.file, extension, &entry.archive) {
+            (Some(file), Some(extension), _) => (file, extension),
+            (_, _, Some(archive)) => (archive, "pdf"),
+            _ => bail!("No importable file for document {}", entry.pk),
+        };
+
+        tokio::fs::copy(self.path.join(file), staging.path_of(Kind::other(format!("original.{}", extension)))).await
+            .with_context(|| format!("Copying {}", file))?;
+
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
+
+        self.queue.juice(staging).await?;
+
+        if !document.content.is_empty() {
+            let mut plaintext = staging.write(Kind::Plaintext).await?;
+            plaintext.write_all(document.content.as_bytes()).await?;
+            plaintext.commit().await?;
+        }
+
+        return Ok(());
+    }
+
+    async fn import_document(&self, export: &Export, entry: &Entry) -> Result<DocId> {
+        let metadata = export.metadata(entry);
+
+        let staging = self.queue.repository().stage().await?;
+
+        if let Err(err) = self.prepare(&staging, entry, &metadata).await {
+            staging.delete().await?;
+            return Err(err);
+        }
+
+        let bundle = staging.create().await?;
+        let id = *bundle.id();
+
+        if metadata.archived.is_some() {
+            bundle.archive().await?;
+        }
+
+        return Ok(id);
+    }
+
+    pub async fn import(&self) -> Result<Imported> {
+        let manifest = tokio::fs::read(self.path.join("manifest.json")).await
+            .with_context(|| format!("Reading manifest of {:?}", self.path))?;
+        let export = Export::parse(serde_json::from_slice(&manifest).context("Invalid manifest")?)?;
+
+        info!("Importing {} documents from {:?}", export.documents.len(), self.path);
+
+        self.define(&export).await?;
+
+        let imported = self.imported().await?;
+
+        let mut result = Imported::default();
+        for entry in &export.documents {
+            if imported.contains(&entry.pk) {
+                result.skipped += 1;
+                continue;
+            }
+
+            match self.import_document(&export, entry).await {
+                Ok(id) => {
+                    info!("Imported document {} as bundle {}", entry.pk, id);
+                    result.bundles.push(id);
+                }
+                Err(err) => {
+                    error!("Failed to import document {}: {:#}", entry.pk, err);
+                    result.failed += 1;
+                }
+            }
+        }
+
+        if result.failed != 0 {
+            warn!("Failed to import {} documents, run the import again to retry", result.failed);
+        }
+
+        return Ok(result);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    const MANIFEST: &str = r##"[
+        {"model": "documents.correspondent", "pk": 1, "fields": {"name": "ACME Corp/Inc"}},
+        {"model": "documents.documenttype", "pk": 1, "fields": {"name": "Invoice"}},
+        {"model": "documents.tag", "pk": 1, "fields": {"name": "tax", "color": "#a6cee3", "is_inbox_tag": false}},
+        {"model": "documents.tag", "pk": 2, "fields": {"name": "Inbox", "color": "#000000", "is_inbox_tag": true}},
+        {"model": "documents.customfield", "pk": 1, "fields": {"name": "total", "data_type": "monetary"}},
+        {"model": "documents.customfield", "pk": 2, "fields": {"name": "paid", "data_type": "boolean"}},
+        {"model": "documents.customfieldinstance", "pk": 1, "fields": {"document": 1, "field": 1, "value_text": null, "value_monetary": "EUR12.50"}},
+        {"model": "documents.customfieldinstance", "pk": 2, "fields": {"document": 1, "field": 2, "value_bool": true}},
+        {"model": "documents.document", "pk": 1, "fields": {
+            "title": "My invoice", "content": "my invoice text", "created": "2023-04-01T00:00:00Z",
+            "added": "2023-04-02T10:00:00Z", "correspondent": 1, "document_type": 1, "tags": [1],
+            "mime_type": "application/pdf", "original_filename": "invoice.pdf", "checksum": "abc"
+        }, "__exported_file_name__": "0000001.pdf", "__exported_archive_name__": "archive/0000001.pdf"},
+        {"model": "documents.document", "pk": 2, "fields": {
+            "title": "My letter", "content": "", "created": "2023-05-01", "tags": [1, 2],
+            "mime_type": "text/plain", "checksum": "def"
+        }, "__exported_file_name__": "0000002.txt"}
+    ]"##;
+
+    #[test]
+    fn test_metadata() {
+        let export = Export::parse(serde_json::from_str(MANIFEST).unwrap()).unwrap();
+        assert_that!(export.documents).has_length(2);
+
+        let metadata = export.metadata(&export.documents[0]);
+        assert_that!(metadata.title.as_deref()).is_equal_to(Some("My invoice"));
+        assert_that!(metadata.correspondent.as_deref()).is_equal_to(Some("ACME Corp-Inc"));
+        assert_that!(metadata.labels).is_equal_to(vec![Label::from("tax"), Label::from("Invoice")].into_iter().collect::<HashSet<_>>());
+        assert_that!(metadata.properties.get(CREATED)).is_equal_to(Some(&PropertyValue::Date(NaiveDate::from_ymd(2023, 4, 1))));
+        assert_that!(metadata.date).is_equal_to(Some(NaiveDate::from_ymd(2023, 4, 1)));
+        assert_that!(metadata.properties.get("total")).is_equal_to(Some(&PropertyValue::parse("12.50 EUR")));
+        assert_that!(metadata.properties.get("paid")).is_equal_to(Some(&PropertyValue::Boolean(true)));
+        assert_that!(metadata.properties.get(PROPERTY)).is_equal_to(Some(&PropertyValue::Integer(1)));
+        assert_that!(metadata.archived).is_some();
+
+        let metadata = export.metadata(&export.documents[1]);
+        assert_that!(metadata.labels).is_equal_to(vec![Label::from("tax")].into_iter().collect::<HashSet<_>>());
+        assert_that!(metadata.properties.get(CREATED)).is_equal_to(Some(&PropertyValue::Date(NaiveDate::from_ymd(2023, 5, 1))));
+        assert_that!(metadata.archived).is_none();
+    }
+}
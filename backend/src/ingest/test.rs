@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use spectral::prelude::*;
+use tokio::io::AsyncReadExt;
+
+use crate::config::Queue as Config;
+use crate::correspondents::Correspondents;
+use crate::juicer::MockJuicer;
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+use crate::queue::{Job, Queue};
+use crate::repository::Repository;
+use crate::rules::Rules;
+use crate::status::Status;
+
+use super::ingest;
+
+async fn queue(repository: &Repository, juicer: MockJuicer) -> Queue {
+    return Queue::new(Config { retries: 0, ..Config::default() },
+                      repository.clone(),
+                      Arc::new(juicer),
+                      HashMap::new(),
+                      Arc::new(Rules::load(repository.clone()).await.unwrap()),
+                      Arc::new(Correspondents::load(repository.path().join("correspondents.json")).await.unwrap()),
+                      Arc::new(Status::new()));
+}
+
+#[tokio::test]
+async fn test_ingest() {
+    let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+    let mut juicer = MockJuicer::new();
+    juicer.expect_extract()
+        .returning(|_| Ok(()));
+
+    let id = ingest(&queue(&repository, juicer).await, &b"my document"[..], "pdf", Metadata::new()).await.unwrap();
+
+    let bundle = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(bundle) = repository.inbox().get(id).await {
+                return bundle;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap();
+
+    let mut original = String::new();
+    bundle.read(Kind::other("original.pdf")).await.unwrap().unwrap()
+        .read_to_string(&mut original).await.unwrap();
+    assert_that!(original.as_str()).is_equal_to("my document");
+
+    assert_that!(bundle.read(Kind::other(Job::FRAGMENT)).await.unwrap().is_none()).is_true();
+}
+
+#[tokio::test]
+async fn test_ingest_failed() {
+    let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+    let mut juicer = MockJuicer::new();
+    juicer.expect_extract()
+        .returning(|_| Err(anyhow!("juicer failed")));
+
+    let id = ingest(&queue(&repository, juicer).await, &b"my document"[..], "pdf", Metadata::new()).await.unwrap();
+
+    // The failed job is moved aside along with the original
+    let job = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(bundle) = repository.failed().get(id).await {
+                if let Ok(Some(job @ Job { failed: Some(_), .. })) = Job::load(&bundle).await {
+                    return job;
+                }
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap();
+
+    assert_that!(job.attempts).is_equal_to(1);
+    assert_that!(job.failed.map(|failure| failure.details)).is_equal_to(Some(String::from("juicer failed")));
+    assert_that!(repository.inbox().list().await.unwrap()).is_empty();
+    assert_that!(repository.staging().get(id).await.is_none()).is_true();
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+use crate::repository::{Bundle, Staging};
+
+use super::native::MIN_TEXT_LEN;
+
+/// A juicer of the chain along with the name recorded for the documents it extracted.
+pub struct Link {
+    pub name: String,
+
+    /// Fails the extraction if the document contains no text, so the next juicer is tried
+    pub require_text: bool,
+
+    pub juicer: Arc<dyn super::Juicer + Send + Sync>,
+}
+
+/// Juicer trying a chain of juicers in order until one succeeds.
+///
+/// This allows to run a fast juicer first and fall back to a heavyweight one, i.e. an OCR container, only if the
+/// first one fails or finds no text. The logs of all juicers tried are collected into a single `juicer.log` and the
+/// name of the succeeding juicer is recorded in the metadata.
+pub struct Juicer {
+    links: Vec<Link>,
+}
+
+impl Juicer {
+    pub fn new(links: Vec<Link>) -> Self {
+        return Self { links };
+    }
+}
+
+/// Checks if the extracted text of the document contains enough text to skip OCR.
+async fn has_text(bundle: &Bundle<'_, Staging>) -> Result<bool> {
+    let mut text = String::new();
+    if let Some(mut file) = bundle.read(Kind::other("document.txt")).await? {
+        file.read_to_string(&mut text).await?;
+    }
+
+    return Ok(text.trim().len() as u64 >= MIN_TEXT_LEN);
+}
+
+impl Juicer {
+    async fn write_log(bundle: &Bundle<'_, Staging>, log: &[u8]) -> Result<()> {
+        let mut logfile = bundle.write(Kind::other("juicer.log")).await
+            .with_context(|| "Failed to open juicer.log")?;
+        logfile.write_all(log).await?;
+        logfile.commit().await?;
+
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl super::Juicer for Juicer {
+    async fn extract<'r>(&self, bundle: &Bundle<'r, Staging>) -> Result<()> {
+        let mut log = Vec::new();
+        let mut error = None;
+
+        for link in &self.links {
+            debug!("Extracting {} using juicer {}", bundle.id(), link.name);
+            log.write_all(format!("== {} ==\n", link.name).as_bytes()).await?;
+
+            let result = link.juicer.extract(bundle).await;
+
+            // Each juicer replaces the log of the one before
+            if let Some(mut logfile) = bundle.read(Kind::other("juicer.log")).await? {
+                tokio::io::copy(&mut logfile, &mut log).await?;
+            }
+
+            let result = match result {
+                Ok(()) if link.require_text && !has_text(bundle).await? => Err(anyhow!("No text extracted")),
+                result => result,
+            };
+
+            match result {
+                Ok(()) => {
+                    log.write_all(format!("Extracted by {}\n", link.name).as_bytes()).await?;
+                    Self::write_log(bundle, &log).await?;
+
+                    let mut metadata = bundle.read_metadata().await?;
+                    metadata.juicer = Some(link.name.clone());
+                    Metadata::save(&metadata, bundle.write(Kind::Metadata).await?).await?;
+
+                    return Ok(());
+                }
+
+                Err(err) => {
+                    warn!("Juicer {} failed for {}: {:#}", link.name, bundle.id(), err);
+                    log.write_all(format!("Juicer {} failed: {:#}\n", link.name, err).as_bytes()).await?;
+                    error = Some(err);
+                }
+            }
+        }
+
+        Self::write_log(bundle, &log).await?;
+
+        return Err(error
+            .unwrap_or_else(|| anyhow!("No juicer configured"))
+            .context("All juicers failed"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::juicer::{Juicer as _, MockJuicer};
+    use crate::repository::Repository;
+
+    use super::*;
+
+    /// Creates a juicer writing the given text and log.
+    fn juicer(text: &'static str, log: &'static str) -> Arc<dyn crate::juicer::Juicer + Send + Sync> {
+        let mut juicer = MockJuicer::new();
+        juicer.expect_extract()
+            .times(1)
+            .returning(move |bundle| {
+                std::fs::write(bundle.path().join("juicer.log"), log)?;
+                std::fs::write(bundle.path().join("document.txt"), text)?;
+                Ok(())
+            });
+
+        return Arc::new(juicer);
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let staging = repository.stage().await.unwrap();
+        Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        let juicer = Juicer::new(vec![
+            Link { name: String::from("native"), require_text: true, juicer: juicer("", "no text layer\n") },
+            Link { name: String::from("ocr"), require_text: true, juicer: juicer("Some recognized text", "ocr done\n") },
+        ]);
+
+        juicer.extract(&staging).await.unwrap();
+
+        assert_that!(staging.read_metadata().await.unwrap().juicer).is_equal_to(Some(String::from("ocr")));
+
+        let log = tokio::fs::read_to_string(staging.path().join("juicer.log")).await.unwrap();
+        assert_that!(log.as_str()).is_equal_to("== native ==\nno text layer\nJuicer native failed: No text extracted\n\
+                                                == ocr ==\nocr done\nExtracted by ocr\n");
+    }
+
+    #[tokio::test]
+    async fn test_all_failed() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let staging = repository.stage().await.unwrap();
+        Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        let juicer = Juicer::new(vec![
+            Link { name: String::from("native"), require_text: true, juicer: juicer("", "") },
+        ]);
+
+        assert_that!(juicer.extract(&staging).await.is_err()).is_true();
+        assert_that!(staging.read_metadata().await.unwrap().juicer).is_none();
+    }
+}
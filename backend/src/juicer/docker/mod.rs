@@ -1,15 +1,20 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use futures::{StreamExt, TryStreamExt};
-use log::{debug, error, trace};
-use shiplift::{ContainerOptions, Docker, LogsOptions};
+use log::{debug, error, info, trace, warn};
+use shiplift::{Container, ContainerOptions, Docker, LogsOptions, PullOptions, RmContainerOptions};
 use tokio::io::AsyncWriteExt;
+use tracing::{field, info_span};
+use tracing_futures::Instrument;
 
-use crate::config::DockerJuicer as Config;
+use crate::config::{DockerJuicer as Config, DockerSandbox, ImagePolicy, PullPolicy};
 use crate::proto::model::Kind;
 use crate::repository::{Bundle, Staging};
 use std::path::Path;
 use std::io::Cursor;
+use std::time::Duration;
+
+use super::{image_original, JuicerError, office_original, scanned_pages};
 
 #[cfg(test)]
 mod test;
@@ -18,45 +23,248 @@ pub struct Juicer {
     docker: Docker,
 
     image: String,
+
+    timeout: Duration,
+
+    sandbox: DockerSandbox,
+
+    languages: String,
+
+    /// Uploads all fragments instead of the original only, as steps of a pipeline continue on the output of others
+    whole_bundle: bool,
 }
 
 impl Juicer {
     const DOCKER_IMAGE: &'static str = "adacta10/juicer:develop";
 
     pub async fn from_config(config: Config) -> Result<Self> {
-        let docker = Docker::new();
+        if let Some(tls) = &config.tls {
+            // The client only picks up certificates from the environment
+            std::env::set_var("DOCKER_CERT_PATH", &tls.cert_path);
+            std::env::set_var("DOCKER_TLS_VERIFY", "1");
+        }
+
+        let docker = match (&config.host, &config.socket) {
+            (Some(_), Some(_)) => anyhow::bail!("Docker host and socket are mutually exclusive"),
+            (Some(host), None) => Docker::host(host.parse()
+                .with_context(|| format!("Invalid docker host: {}", host))?),
+            (None, Some(socket)) => Docker::unix(socket),
+            (None, None) => Docker::new(),
+        };
         // docker.ping().await?; // TODO: Implement?
 
         let image = config.image
             .unwrap_or_else(|| Self::DOCKER_IMAGE.to_string());
+        verify_image(&config.policy, &image)?;
+
+        let timeout = Duration::from_secs(config.timeout);
+
+        let sandbox = config.sandbox;
+
+        let languages = config.languages;
 
-        Ok(Self { docker, image })
+        let juicer = Self { docker, image, timeout, sandbox, languages, whole_bundle: false };
+
+        // Pulling on startup reveals a missing image right away instead of failing the first upload
+        match config.pull {
+            PullPolicy::Missing if juicer.digest().await.is_some() => {}
+            PullPolicy::Missing | PullPolicy::Always => pull(&juicer.docker, &juicer.image).await?,
+            PullPolicy::Never => {}
+        }
+
+        if let Some(interval) = config.pull_interval {
+            let docker = juicer.docker.clone();
+            let image = juicer.image.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(interval.max(1)));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = pull(&docker, &image).await {
+                        warn!("Failed to pull juicer image {}: {:#}", image, err);
+                    }
+                }
+            });
+        }
+
+        Ok(juicer)
+    }
+
+    /// Creates a juicer running a single step of a pipeline.
+    pub async fn step(config: Config) -> Result<Self> {
+        return Ok(Self {
+            whole_bundle: true,
+            ..Self::from_config(config).await?
+        });
     }
 }
 
-#[async_trait]
-impl super::Juicer for Juicer {
-    async fn extract<'r>(&self, bundle: &Bundle<'r, Staging>) -> Result<()> {
+/// Pulls an image by its tag or digest.
+async fn pull(docker: &Docker, image: &str) -> Result<()> {
+    info!("Pulling juicer image {}", image);
+
+    let mut pull = docker.images().pull(&PullOptions::builder().image(image).build());
+    while let Some(status) = pull.next().await {
+        let status = status.with_context(|| format!("Error pulling image {}", image))?;
+        trace!("Pulling {}: {}", image, status);
+    }
+
+    return Ok(());
+}
+
+/// Splits an image reference into the repository, including the registry, and the digest if pinned.
+fn parse_image(image: &str) -> (&str, Option<&str>) {
+    let (name, digest) = match image.find('@') {
+        Some(index) => (&image[..index], Some(&image[index + 1..])),
+        None => (image, None),
+    };
+
+    // A colon after the last slash separates the tag, others separate the port of the registry
+    let repository = match name.rfind(':') {
+        Some(index) if !name[index..].contains('/') => &name[..index],
+        _ => name,
+    };
+
+    return (repository, digest);
+}
+
+/// Ensures the image is allowed by the policy, so the config can not silently point the juicer to an arbitrary image.
+fn verify_image(policy: &ImagePolicy, image: &str) -> Result<()> {
+    let (repository, digest) = parse_image(image);
+
+    if !policy.allowed.is_empty() && !policy.allowed.iter().any(|allowed| {
+        let allowed = allowed.trim_end_matches('/');
+        repository == allowed || repository.starts_with(&format!("{}/", allowed))
+    }) {
+        anyhow::bail!("Juicer image not allowed by policy: {}", image);
+    }
+
+    if policy.require_digest {
+        let pinned = digest
+            .and_then(|digest| digest.strip_prefix("sha256:"))
+            .map_or(false, |hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()));
+        if !pinned {
+            anyhow::bail!("Juicer image not pinned by digest: {}", image);
+        }
+    }
+
+    return Ok(());
+}
+
+/// Removes the container when dropped.
+///
+/// This guarantees cleanup if juicing fails, times out or the extraction gets cancelled by dropping its future.
+struct ContainerGuard {
+    docker: Docker,
+    id: String,
+
+    /// The span of the container, which ends once the container is removed
+    span: tracing::Span,
+}
+
+impl Drop for ContainerGuard {
+    fn drop(&mut self) {
+        let docker = self.docker.clone();
+        let id = std::mem::take(&mut self.id);
+
+        tokio::spawn(async move {
+            debug!("Deleting container (id={})", id);
+            if let Err(err) = docker.containers().get(&id)
+                .remove(RmContainerOptions::builder().force(true).volumes(true).build()).await {
+                error!("Error deleting container (id={}): {}", id, err);
+            }
+        }.instrument(std::mem::replace(&mut self.span, tracing::Span::none())));
+    }
+}
+
+impl Juicer {
+    /// Returns the digest of the image if it exists locally, falling back to its ID for images built locally.
+    async fn digest(&self) -> Option<String> {
+        let details = self.docker.images().get(&self.image).inspect().await.ok()?;
+
+        return Some(details.repo_digests
+            .and_then(|digests| digests.into_iter().next())
+            .unwrap_or(details.id));
+    }
+
+    /// Builds the options of the container, restricted by the sandbox as it processes untrusted files.
+    fn options(&self, name: &str) -> ContainerOptions {
+        let mut security_options = Vec::new();
+        if self.sandbox.no_new_privileges {
+            security_options.push(String::from("no-new-privileges"));
+        }
+        if let Some(seccomp) = &self.sandbox.seccomp {
+            security_options.push(format!("seccomp={}", seccomp));
+        }
+
+        // The languages requested on upload are read from the metadata by the container itself
+        let languages = format!("OCR_LANGUAGES={}", self.languages);
+
+        let mut create = ContainerOptions::builder(&self.image);
+        create
+            .name(name)
+            .env(vec![languages.as_str()])
+            .readonly_rootfs(self.sandbox.read_only)
+            .security_options(security_options.iter().map(String::as_str).collect())
+            .capabilities_drop(self.sandbox.cap_drop.iter().map(String::as_str).collect());
+        if !self.sandbox.network {
+            create.network_mode("none");
+        }
+        if let Some(size) = &self.sandbox.tmpfs {
+            create.tmpfs(vec![("/tmp", &format!("rw,noexec,nosuid,size={}", size))]);
+        }
+        if let Some(memory) = self.sandbox.memory {
+            // Limiting swap to the memory limit leaves no additional swap
+            create.memory(memory * 1024 * 1024);
+            create.memory_swap((memory * 1024 * 1024) as i64);
+        }
+        if let Some(cpus) = self.sandbox.cpus {
+            create.nano_cpus((cpus * 1e9) as u64);
+        }
+        if let Some(pids) = self.sandbox.pids_limit {
+            create.pids_limit(pids);
+        }
+        if let Some(user) = &self.sandbox.user {
+            create.user(user);
+        }
+        if let Some(userns) = &self.sandbox.userns {
+            create.userns_mode(userns);
+        }
+
+        return create.build();
+    }
+
+    async fn run<'r>(&self, container: &Container<'_>, bundle: &Bundle<'r, Staging>) -> Result<u64> {
         // Open the log file
         let mut logfile = bundle.write(Kind::other("juicer.log")).await
             .with_context(|| "Failed to open juicer.log")?;
 
-        let containers = self.docker.containers();
+        // Record the exact image, as tags like `develop` move over time
+        let digest = self.digest().await.unwrap_or_else(|| String::from("unknown"));
+        logfile.write_all(format!("Image: {} ({})\n", self.image, digest).as_bytes()).await?;
 
-        debug!("Creating container");
-        let create = ContainerOptions::builder(&self.image)
-            .name(&format!("juicer-{}", bundle.id()))
-            .network_mode("none")
-            .build();
-        let container = containers.create(&create).await
-            .with_context(|| format!("Error creating container (image={})", self.image))?;
-        let container = containers.get(&container.id);
+        // Office documents and scans are uploaded as is and converted to PDF in the container
+        let scans = scanned_pages(bundle).await?;
+        let originals = if self.whole_bundle {
+            bundle.fragment_names().await?.into_iter()
+                .filter(|name| name != "metadata.json" && name != "juicer.log")
+                .collect()
+        } else if !scans.is_empty() {
+            scans
+        } else {
+            match office_original(bundle).await.or(image_original(bundle).await) {
+                Some(extension) => vec![format!("original.{}", extension)],
+                None => vec![String::from("original.pdf")],
+            }
+        };
 
         debug!("Uploading bundle to container (id={})", container.id());
         let upload: Result<_> = try {
             let mut archive = tar::Builder::new(Vec::new());
             archive.append_path_with_name(bundle.path_of(Kind::Metadata), "metadata.json")?;
-            archive.append_path_with_name(bundle.path_of(Kind::other("original.pdf")), "original.pdf")?;
+            for original in &originals {
+                archive.append_path_with_name(bundle.path_of(Kind::other(original)), original)?;
+            }
             archive.into_inner()?
         };
         let upload = upload.context("Error creating upload archive")?;
@@ -72,14 +280,20 @@ impl super::Juicer for Juicer {
             .stdout(true)
             .stderr(true)
             .build());
-        while let Some(chunk) = logs.next().await {
-            let chunk = chunk.with_context(|| format!("Error running container (id={})", container.id()))?;
+        let logged: Result<()> = try {
+            while let Some(chunk) = logs.next().await {
+                let chunk = chunk.with_context(|| format!("Error running container (id={})", container.id()))?;
 
-            trace!("{}: {}", container.id(), String::from_utf8_lossy(&chunk));
+                trace!("{}: {}", container.id(), String::from_utf8_lossy(&chunk));
 
-            logfile.write_all(&chunk).await
-                .with_context(|| "Failed to write log")?;
-        }
+                logfile.write_all(&chunk).await
+                    .with_context(|| "Failed to write log")?;
+            }
+        };
+
+        // The log is kept even if the container fails, as it tells why
+        logfile.commit().await?;
+        logged?;
 
         debug!("Waiting for container to finish (id={})", container.id());
         let result = container.wait().await
@@ -102,14 +316,41 @@ impl super::Juicer for Juicer {
             entry.unpack(path)?;
         }
 
-        debug!("Deleting container (id={})", container.id());
-        container.delete().await
-            .with_context(|| format!("Error deleting container (id={})", container.id()))?;
+        return Ok(result.status_code);
+    }
+}
+
+#[async_trait]
+impl super::Juicer for Juicer {
+    async fn extract<'r>(&self, bundle: &Bundle<'r, Staging>) -> Result<()> {
+        let containers = self.docker.containers();
+
+        // Traces the container from its creation until it is removed
+        let span = info_span!("container", image = %self.image, id = field::Empty);
+
+        debug!("Creating container");
+        let create = self.options(&format!("juicer-{}", bundle.id()));
+        let container = containers.create(&create)
+            .instrument(span.clone())
+            .await
+            .with_context(|| format!("Error creating container (image={})", self.image))?;
+        span.record("id", &container.id.as_str());
+
+        let _guard = ContainerGuard {
+            docker: self.docker.clone(),
+            id: container.id.clone(),
+            span: span.clone(),
+        };
+
+        let container = containers.get(&container.id);
+
+        let status_code = tokio::time::timeout(self.timeout, self.run(&container, bundle).instrument(span)).await
+            .map_err(|_| JuicerError::Timeout(self.timeout))??;
 
         // Fail with error depending on status-code
-        if result.status_code != 0 {
-            error!("Container failed (id={}): {}", container.id(), result.status_code);
-            anyhow::bail!("Juicing failed (id={}): {}", container.id(), result.status_code);
+        if status_code != 0 {
+            error!("Container failed (id={}): {}", container.id(), status_code);
+            anyhow::bail!("Juicing failed (id={}): {}", container.id(), status_code);
         }
 
         return Ok(());
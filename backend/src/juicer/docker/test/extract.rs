@@ -23,6 +23,8 @@ async fn test_extract_pages() {
     juicer.extract(&bundle).await.unwrap();
 
     assert_that!(bundle.read_metadata().await.unwrap().pages).is_equal_to(1);
+    assert_that!(bundle.path_of(Kind::Page(1)).exists()).is_true();
+    assert_that!(bundle.path_of(Kind::Thumbnail).exists()).is_true();
 }
 
 #[tokio::test]
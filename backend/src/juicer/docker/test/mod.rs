@@ -1,5 +1,6 @@
 use rust_embed::RustEmbed;
 use shiplift::BuildOptions;
+use spectral::prelude::*;
 use tokio::io::AsyncWriteExt;
 
 use crate::meta::Metadata;
@@ -39,7 +40,7 @@ pub async fn juicer() -> Result<Juicer> {
         }
     };
 
-    let juicer = Juicer::from_config(Config { image: Some(id) }).await?;
+    let juicer = Juicer::from_config(Config { image: Some(id), host: None, socket: None, tls: None, timeout: 60, sandbox: Default::default(), policy: Default::default(), pull: PullPolicy::Never, pull_interval: None, languages: String::from("eng+deu") }).await?;
 
     return Ok(juicer);
 }
@@ -50,8 +51,52 @@ pub async fn upload<'r>(repository: &'r Repository, metadata: Metadata, original
     let metadata_fragment = bundle.write(Kind::Metadata).await?;
     metadata.save(metadata_fragment).await?;
 
-    bundle.write(Kind::other("original.pdf")).await?
-        .write_all(&Resources::get(original).unwrap()).await?;
+    let mut fragment = bundle.write(Kind::other("original.pdf")).await?;
+    fragment.write_all(&Resources::get(original).unwrap()).await?;
+    fragment.commit().await?;
 
     return Ok(bundle);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_verify_image() {
+    let digest = format!("sha256:{}", "a".repeat(64));
+
+    let policy = ImagePolicy { allowed: vec![String::from("ghcr.io/adacta-io"), String::from("adacta10/juicer")], require_digest: false };
+    assert_that!(verify_image(&policy, "adacta10/juicer:develop")).is_ok();
+    assert_that!(verify_image(&policy, "ghcr.io/adacta-io/juicer")).is_ok();
+    assert_that!(verify_image(&policy, &format!("ghcr.io/adacta-io/juicer:1.0@{}", digest))).is_ok();
+    assert_that!(verify_image(&policy, "adacta10/juicer-evil:develop")).is_err();
+    assert_that!(verify_image(&policy, "ghcr.io/adacta-io.evil/juicer")).is_err();
+    assert_that!(verify_image(&policy, "registry:5000/adacta10/juicer")).is_err();
+
+    let policy = ImagePolicy { allowed: vec![], require_digest: true };
+    assert_that!(verify_image(&policy, &format!("registry:5000/juicer@{}", digest))).is_ok();
+    assert_that!(verify_image(&policy, "adacta10/juicer:develop")).is_err();
+    assert_that!(verify_image(&policy, "adacta10/juicer@sha256:abc")).is_err();
+}
+
+#[test]
+fn test_options() {
+    let juicer = Juicer {
+        docker: Docker::new(),
+        image: String::from("adacta10/juicer:develop"),
+        timeout: Duration::from_secs(60),
+        sandbox: DockerSandbox {
+            memory: Some(1024),
+            cpus: Some(1.5),
+            user: Some(String::from("1000:1000")),
+            ..Default::default()
+        },
+        languages: String::from("eng"),
+        whole_bundle: false,
+    };
+
+    let options = serde_json::from_str::<serde_json::Value>(&juicer.options("juicer-test").serialize().unwrap()).unwrap();
+    assert_that!(options["User"].as_str()).is_equal_to(Some("1000:1000"));
+    assert_that!(options["HostConfig"]["NetworkMode"].as_str()).is_equal_to(Some("none"));
+    assert_that!(options["HostConfig"]["Memory"].as_u64()).is_equal_to(Some(1024 * 1024 * 1024));
+    assert_that!(options["HostConfig"]["MemorySwap"].as_u64()).is_equal_to(Some(1024 * 1024 * 1024));
+    assert_that!(options["HostConfig"]["NanoCpus"].as_u64()).is_equal_to(Some(1_500_000_000));
+    assert_that!(options["HostConfig"]["PidsLimit"].as_u64()).is_equal_to(Some(512));
+}
@@ -1,14 +1,194 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::future::{BoxFuture, FutureExt};
 #[cfg(test)]
 use mockall::automock;
 
+use crate::config::Juicer as Config;
+use crate::proto::model::Kind;
 use crate::repository::{Bundle, Staging};
 
+pub mod chain;
 pub mod docker;
+pub mod native;
+pub mod pipeline;
+pub mod report;
+#[cfg(any(test, feature = "testing"))]
+pub mod stub;
+
+/// Errors reported by juicers in addition to failures of the extraction itself.
+#[derive(Debug, thiserror::Error)]
+pub enum JuicerError {
+    #[error("Juicing timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+/// Office document formats converted to PDF before juicing, by file extension and MIME type.
+pub const OFFICE_FORMATS: &[(&str, &str)] = &[
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+];
+
+/// Scanned image formats converted to PDF and OCRed while juicing, by file extension and MIME type.
+pub const IMAGE_FORMATS: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+];
+
+fn find_format(formats: &[(&'static str, &str)], mimetype: Option<&str>, filename: Option<&str>) -> Option<&'static str> {
+    return formats.iter()
+        .find(|(extension, mime)| {
+            mimetype.map_or(false, |mimetype| mimetype.eq_ignore_ascii_case(mime))
+                || filename.map_or(false, |filename| filename.to_lowercase().ends_with(&format!(".{}", extension)))
+        })
+        .map(|(extension, _)| *extension);
+}
+
+async fn find_original(bundle: &Bundle<'_, Staging>, formats: &[(&'static str, &str)]) -> Option<&'static str> {
+    for (extension, _) in formats {
+        if tokio::fs::metadata(bundle.path_of(Kind::other(format!("original.{}", extension)))).await.is_ok() {
+            return Some(*extension);
+        }
+    }
+
+    return None;
+}
+
+/// Returns the file extension of a document which is converted to PDF while juicing by its MIME type or filename.
+///
+/// Returns `None` for PDFs and unsupported formats.
+pub fn converted_format(mimetype: Option<&str>, filename: Option<&str>) -> Option<&'static str> {
+    return find_format(OFFICE_FORMATS, mimetype, filename)
+        .or_else(|| find_format(IMAGE_FORMATS, mimetype, filename));
+}
+
+/// Returns the file extension of a scanned image by its MIME type or filename.
+///
+/// Returns `None` for all other formats.
+pub fn image_format(mimetype: Option<&str>, filename: Option<&str>) -> Option<&'static str> {
+    return find_format(IMAGE_FORMATS, mimetype, filename);
+}
+
+/// Returns the file extension of the office document a bundle has been ingested from, if the original is one.
+pub async fn office_original(bundle: &Bundle<'_, Staging>) -> Option<&'static str> {
+    return find_original(bundle, OFFICE_FORMATS).await;
+}
+
+/// Returns the file extension of the scanned image a bundle has been ingested from, if the original is one.
+pub async fn image_original(bundle: &Bundle<'_, Staging>) -> Option<&'static str> {
+    return find_original(bundle, IMAGE_FORMATS).await;
+}
+
+/// Returns the MIME type of the document a bundle has been ingested from, which is a PDF unless converted while juicing.
+pub async fn original_mimetype(bundle: &Bundle<'_, Staging>) -> Result<&'static str> {
+    let scans = scanned_pages(bundle).await?;
+    let extension = match scans.first() {
+        Some(page) => page.rsplit('.').next(),
+        None => office_original(bundle).await.or(image_original(bundle).await),
+    };
+
+    return Ok(extension
+        .and_then(|extension| OFFICE_FORMATS.iter().chain(IMAGE_FORMATS).find(|(format, _)| *format == extension))
+        .map_or("application/pdf", |(_, mimetype)| *mimetype));
+}
+
+/// Returns the fragments of the pages of a scan assembled page by page, in page order.
+///
+/// Pages are named like `scan-0001.png` by their zero-padded number, so they are ordered by name.
+pub async fn scanned_pages(bundle: &Bundle<'_, Staging>) -> Result<Vec<String>> {
+    let mut pages = Vec::new();
+
+    let mut entries = tokio::fs::read_dir(bundle.path()).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        let scanned = filename.strip_prefix("scan-")
+            .and_then(|page| page.find('.').map(|i| (&page[..i], &page[i + 1..])))
+            .map_or(false, |(number, extension)| {
+                !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+                    && IMAGE_FORMATS.iter().any(|(format, _)| *format == extension)
+            });
+
+        if scanned {
+            pages.push(filename);
+        }
+    }
+
+    pages.sort();
+
+    return Ok(pages);
+}
 
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait Juicer {
     async fn extract<'r>(&self, bundle: &Bundle<'r, Staging>) -> Result<()>;
 }
+
+/// Creates the configured juicer, which may consist of other juicers in turn.
+pub fn from_config(config: Config) -> BoxFuture<'static, Result<Arc<dyn Juicer + Send + Sync>>> {
+    return async move {
+        let juicer: Arc<dyn Juicer + Send + Sync> = match config {
+            Config::Docker(config) => {
+                Arc::new(docker::Juicer::from_config(config).await?)
+            }
+            Config::Native(config) => {
+                Arc::new(native::Juicer::from_config(config).await?)
+            }
+            Config::Pipeline(config) => {
+                Arc::new(pipeline::Juicer::from_config(config).await?)
+            }
+            Config::Chain(config) => {
+                let mut links = Vec::with_capacity(config.juicers.len());
+                for link in config.juicers {
+                    links.push(chain::Link {
+                        name: link.name,
+                        require_text: link.require_text,
+                        juicer: from_config(link.juicer).await?,
+                    });
+                }
+
+                Arc::new(chain::Juicer::new(links))
+            }
+        };
+
+        return Ok(juicer);
+    }.boxed();
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_converted_format() {
+        assert_that!(converted_format(Some("application/vnd.oasis.opendocument.text"), None)).is_equal_to(Some("odt"));
+        assert_that!(converted_format(Some("image/jpeg"), Some("scan.jpeg"))).is_equal_to(Some("jpg"));
+        assert_that!(converted_format(None, Some("Scan.TIFF"))).is_equal_to(Some("tiff"));
+        assert_that!(converted_format(Some("application/octet-stream"), Some("letter.docx"))).is_equal_to(Some("docx"));
+        assert_that!(converted_format(Some("application/pdf"), Some("letter.pdf"))).is_none();
+    }
+
+    #[tokio::test]
+    async fn test_scanned_pages() {
+        let repository = crate::repository::Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let staging = repository.stage().await.unwrap();
+
+        for name in &["scan-0002.png", "scan-0001.jpg", "scan-notes.txt", "scan-.png", "original.pdf"] {
+            staging.write(Kind::other(*name)).await.unwrap().commit().await.unwrap();
+        }
+
+        assert_that!(scanned_pages(&staging).await.unwrap()).is_equal_to(vec![
+            String::from("scan-0001.jpg"),
+            String::from("scan-0002.png"),
+        ]);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use log::debug;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::NativeJuicer as Config;
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+use crate::repository::{Bundle, Staging};
+
+use super::{image_original, office_original, scanned_pages};
+
+#[cfg(test)]
+mod test;
+
+/// Minimal amount of text a PDF must contain to skip OCR
+pub const MIN_TEXT_LEN: u64 = 10;
+
+/// Resolution of the page previews in DPI
+pub const PAGE_RESOLUTION: &str = "100";
+
+/// Size of the longer side of the thumbnail in pixels
+pub const THUMBNAIL_SIZE: &str = "256";
+
+/// Juicer running the extraction tools installed on the host directly.
+///
+/// This follows the same steps as the docker image but replaces `ocrmypdf` by rasterizing the pages using `pdftoppm`
+/// and running `tesseract` over them.
+pub struct Juicer {
+    config: Config,
+}
+
+impl Juicer {
+    pub async fn from_config(config: Config) -> Result<Self> {
+        return Ok(Self { config });
+    }
+}
+
+/// Runs a command in the bundle directory and appends its output to the log.
+pub(super) async fn run(logfile: &mut (impl AsyncWriteExt + Unpin), dir: &Path, program: &str, args: &[&str]) -> Result<()> {
+    debug!("Running {} {:?}", program, args);
+
+    logfile.write_all(format!("+ {} {}\n", program, args.join(" ")).as_bytes()).await?;
+
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output().await
+        .with_context(|| format!("Error executing {}", program))?;
+
+    logfile.write_all(&output.stdout).await?;
+    logfile.write_all(&output.stderr).await?;
+
+    if !output.status.success() {
+        bail!("{} failed: {}", program, output.status);
+    }
+
+    return Ok(());
+}
+
+/// Parses the page number from the file names `pdftoppm` emits for multiple pages, like `page-01.png`.
+fn page_number(filename: &str) -> Option<u32> {
+    return filename.strip_prefix("page-")?.strip_suffix(".png")?.parse().ok();
+}
+
+/// Parses the `key: value` lines emitted by `pdfinfo`.
+fn parse_pdfinfo(output: &str) -> HashMap<&str, &str> {
+    return output.lines()
+        .filter_map(|line| {
+            let i = line.find(':')?;
+            let (key, value) = (line[..i].trim(), line[i + 1..].trim());
+            (!value.is_empty()).then_some((key, value))
+        })
+        .collect();
+}
+
+/// The steps of the extraction, which are run in this order but can be run alone as steps of a pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Converts office documents and scanned images to PDF
+    Convert,
+
+    /// Extracts the text and OCRs documents without text
+    Ocr,
+
+    /// Renders the preview, the page previews and the thumbnail
+    Preview,
+
+    /// Extracts the title and page count into the metadata
+    Classify,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Convert, Stage::Ocr, Stage::Preview, Stage::Classify];
+
+    /// Returns the stage by the name of a pipeline step.
+    pub fn named(name: &str) -> Option<Self> {
+        return match name {
+            "convert" => Some(Self::Convert),
+            "ocr" => Some(Self::Ocr),
+            "preview" => Some(Self::Preview),
+            "classify" => Some(Self::Classify),
+            _ => None,
+        };
+    }
+}
+
+impl Juicer {
+    /// Runs a single stage of the extraction.
+    pub async fn run_stage(&self, stage: Stage, bundle: &Bundle<'_, Staging>, logfile: &mut (impl AsyncWriteExt + Unpin)) -> Result<()> {
+        return match stage {
+            Stage::Convert => self.convert(bundle, logfile).await,
+            Stage::Ocr => self.ocr(bundle, logfile).await,
+            Stage::Preview => self.preview(bundle, logfile).await,
+            Stage::Classify => self.classify(bundle).await,
+        };
+    }
+
+    async fn convert(&self, bundle: &Bundle<'_, Staging>, logfile: &mut (impl AsyncWriteExt + Unpin)) -> Result<()> {
+        let dir = bundle.path();
+
+        // Convert office documents to PDF, the original is kept as is
+        if let Some(extension) = office_original(bundle).await {
+            run(logfile, &dir, &self.config.soffice, &["--headless", "--convert-to", "pdf", &format!("original.{}", extension)]).await?;
+        }
+
+        // Wrap scanned images into a PDF without text, which is OCRed below - pages are not deskewed by this juicer
+        if let Some(extension) = image_original(bundle).await {
+            run(logfile, &dir, &self.config.img2pdf, &[&format!("original.{}", extension), "-o", "original.pdf"]).await?;
+        }
+
+        // Assemble the pages of scans received page by page in the same way
+        let scans = scanned_pages(bundle).await?;
+        if !scans.is_empty() {
+            let mut args = scans.iter().map(String::as_str).collect::<Vec<_>>();
+            args.extend(&["-o", "original.pdf"]);
+            run(logfile, &dir, &self.config.img2pdf, &args).await?;
+        }
+
+        return Ok(());
+    }
+
+    async fn ocr(&self, bundle: &Bundle<'_, Staging>, logfile: &mut (impl AsyncWriteExt + Unpin)) -> Result<()> {
+        let dir = bundle.path();
+
+        // Extract text from original PDF
+        run(logfile, &dir, &self.config.pdftotext, &["original.pdf", "original.txt"]).await?;
+
+        let text_len = tokio::fs::metadata(dir.join("original.txt")).await?.len();
+        if text_len < MIN_TEXT_LEN {
+            debug!("Document contains no text - enhancing");
+
+            // Rasterize all pages and OCR them into a searchable PDF, the text and the confidence of the words
+            let pages = dir.join("pages");
+            tokio::fs::create_dir_all(&pages).await?;
+
+            run(logfile, &dir, &self.config.pdftoppm, &["-r", "300", "-png", "original.pdf", "pages/page"]).await?;
+
+            let mut images = tokio::fs::read_dir(&pages).await?
+                .filter_map(|entry| async move { entry.ok().map(|entry| entry.file_name().to_string_lossy().into_owned()) })
+                .collect::<Vec<_>>().await;
+            images.sort();
+
+            let list = images.iter().map(|image| format!("pages/{}\n", image)).collect::<String>();
+            tokio::fs::write(pages.join("list.txt"), list).await?;
+
+            let languages = bundle.read_metadata().await?.languages
+                .unwrap_or_else(|| self.config.languages.clone());
+
+            let result = run(logfile, &dir, &self.config.tesseract,
+                             &["pages/list.txt", "document", "-l", &languages, "pdf", "txt", "tsv"]).await;
+
+            tokio::fs::remove_dir_all(&pages).await?;
+            result?;
+        } else {
+            debug!("Document already contains text");
+
+            tokio::fs::copy(dir.join("original.pdf"), dir.join("document.pdf")).await?;
+            tokio::fs::copy(dir.join("original.txt"), dir.join("document.txt")).await?;
+        }
+
+        return Ok(());
+    }
+
+    async fn preview(&self, bundle: &Bundle<'_, Staging>, logfile: &mut (impl AsyncWriteExt + Unpin)) -> Result<()> {
+        let dir = bundle.path();
+
+        // Extract preview
+        run(logfile, &dir, &self.config.pdftoppm, &["document.pdf", "preview", "-png", "-f", "1", "-singlefile"]).await?;
+
+        // Extract a preview per page and rename them by the zero-padded page number
+        run(logfile, &dir, &self.config.pdftoppm, &["document.pdf", "page", "-png", "-r", PAGE_RESOLUTION]).await?;
+
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let filename = entry.file_name();
+            if let Some(page) = filename.to_str().and_then(page_number) {
+                tokio::fs::rename(entry.path(), bundle.path_of(Kind::Page(page))).await?;
+            }
+        }
+
+        run(logfile, &dir, &self.config.pdftoppm, &["document.pdf", "thumbnail", "-png", "-f", "1", "-singlefile", "-scale-to", THUMBNAIL_SIZE]).await?;
+
+        return Ok(());
+    }
+
+    async fn classify(&self, bundle: &Bundle<'_, Staging>) -> Result<()> {
+        // Extract additional metadata
+        let info = Command::new(&self.config.pdfinfo)
+            .arg("document.pdf")
+            .current_dir(bundle.path())
+            .output().await
+            .with_context(|| format!("Error executing {}", self.config.pdfinfo))?;
+        if !info.status.success() {
+            bail!("{} failed: {}", self.config.pdfinfo, info.status);
+        }
+
+        let info = String::from_utf8_lossy(&info.stdout);
+        let info = parse_pdfinfo(&info);
+
+        // The title will only be set if missing, whereas the page count is always replaced
+        let mut metadata = bundle.read_metadata().await?;
+        if metadata.title.is_none() {
+            metadata.title = info.get("Title").map(|title| title.to_string());
+        }
+        metadata.pages = info.get("Pages")
+            .map(|pages| pages.parse())
+            .transpose()
+            .context("Invalid page count")?
+            .unwrap_or_default();
+
+        Metadata::save(&metadata, bundle.write(Kind::Metadata).await?).await?;
+
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl super::Juicer for Juicer {
+    async fn extract<'r>(&self, bundle: &Bundle<'r, Staging>) -> Result<()> {
+        let mut logfile = bundle.write(Kind::other("juicer.log")).await
+            .with_context(|| "Failed to open juicer.log")?;
+
+        // The log is kept even if a stage fails, as it tells why
+        let mut result = Ok(());
+        for stage in &Stage::ALL {
+            result = self.run_stage(*stage, bundle, &mut logfile).await;
+            if result.is_err() {
+                break;
+            }
+        }
+
+        logfile.commit().await?;
+
+        return result;
+    }
+}
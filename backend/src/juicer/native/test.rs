@@ -0,0 +1,24 @@
+use spectral::prelude::*;
+
+use super::*;
+
+#[test]
+fn test_parse_pdfinfo() {
+    let info = parse_pdfinfo("Title:          My Document\n\
+                              Author:         \n\
+                              Pages:          3\n\
+                              CreationDate:   Sat Jun 27 15:17:41 2020 CEST\n");
+
+    assert_that!(info.get("Title")).is_equal_to(Some(&"My Document"));
+    assert_that!(info.get("Pages")).is_equal_to(Some(&"3"));
+    assert_that!(info.get("CreationDate")).is_equal_to(Some(&"Sat Jun 27 15:17:41 2020 CEST"));
+    assert_that!(info.get("Author")).is_none();
+}
+
+#[test]
+fn test_page_number() {
+    assert_that!(page_number("page-1.png")).is_equal_to(Some(1));
+    assert_that!(page_number("page-012.png")).is_equal_to(Some(12));
+    assert_that!(page_number("preview.png")).is_none();
+    assert_that!(page_number("page-x.png")).is_none();
+}
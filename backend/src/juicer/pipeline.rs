@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use log::debug;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::{PipelineJuicer as Config, StepRunner};
+use crate::proto::model::Kind;
+use crate::repository::{Bundle, Staging};
+
+use super::{docker, native, original_mimetype};
+use super::Juicer as _;
+use super::native::Stage;
+
+/// Pipeline key matching documents of all MIME types without a pipeline of their own
+const ANY: &str = "*";
+
+enum Runner {
+    Builtin(Arc<native::Juicer>, Stage),
+    Command { program: String, args: Vec<String> },
+    Docker(docker::Juicer),
+}
+
+struct Step {
+    name: String,
+    runner: Runner,
+}
+
+/// Juicer running a pipeline of named steps selected by the MIME type of the uploaded document.
+///
+/// Each step runs over the bundle directory and continues on the fragments written by the steps before. The logs of
+/// all steps are collected into a single `juicer.log`.
+pub struct Juicer {
+    pipelines: HashMap<String, Vec<Step>>,
+
+    languages: String,
+}
+
+impl Juicer {
+    pub async fn from_config(config: Config) -> Result<Self> {
+        let languages = config.native.languages.clone();
+        let native = Arc::new(native::Juicer::from_config(config.native).await?);
+
+        let mut pipelines = HashMap::new();
+        for (mimetype, steps) in config.pipelines {
+            let mut pipeline = Vec::with_capacity(steps.len());
+            for step in steps {
+                let runner = match step.runner {
+                    StepRunner::Builtin => Runner::Builtin(native.clone(), Stage::named(&step.name)
+                        .ok_or_else(|| anyhow!("Unknown builtin step: {}", step.name))?),
+                    StepRunner::Command { program, args } => Runner::Command { program, args },
+                    StepRunner::Docker(config) => Runner::Docker(docker::Juicer::step(config).await?),
+                };
+
+                pipeline.push(Step { name: step.name, runner });
+            }
+
+            pipelines.insert(mimetype, pipeline);
+        }
+
+        return Ok(Self { pipelines, languages });
+    }
+}
+
+/// Selects the pipeline for a MIME type, falling back to the pipeline for all subtypes and the one for all types.
+fn select<'a, T>(pipelines: &'a HashMap<String, T>, mimetype: &str) -> Option<&'a T> {
+    let wildcard = mimetype.find('/').map(|i| format!("{}/*", &mimetype[..i]));
+
+    return pipelines.get(mimetype)
+        .or_else(|| wildcard.and_then(|wildcard| pipelines.get(&wildcard)))
+        .or_else(|| pipelines.get(ANY));
+}
+
+impl Juicer {
+    async fn run_step(&self, step: &Step, bundle: &Bundle<'_, Staging>, log: &mut Vec<u8>) -> Result<()> {
+        match &step.runner {
+            Runner::Builtin(juicer, stage) => {
+                juicer.run_stage(*stage, bundle, log).await?;
+            }
+
+            Runner::Command { program, args } => {
+                let languages = bundle.read_metadata().await?.languages
+                    .unwrap_or_else(|| self.languages.clone());
+
+                let args = args.iter()
+                    .map(|arg| arg.replace("{languages}", &languages))
+                    .collect::<Vec<_>>();
+
+                native::run(log, &bundle.path(), program, &args.iter().map(String::as_str).collect::<Vec<_>>()).await?;
+            }
+
+            Runner::Docker(juicer) => {
+                let result = juicer.extract(bundle).await;
+
+                // The container writes its own log, which is replaced by the next step
+                if let Some(mut container) = bundle.read(Kind::other("juicer.log")).await? {
+                    tokio::io::copy(&mut container, log).await?;
+                }
+
+                result?;
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl super::Juicer for Juicer {
+    async fn extract<'r>(&self, bundle: &Bundle<'r, Staging>) -> Result<()> {
+        let mimetype = original_mimetype(bundle).await?;
+
+        let steps = match select(&self.pipelines, mimetype) {
+            Some(steps) => steps,
+            None => bail!("No juicer pipeline for {}", mimetype),
+        };
+
+        let mut log = Vec::new();
+        let mut result = Ok(());
+        for step in steps {
+            debug!("Running step {} for {}", step.name, bundle.id());
+
+            log.write_all(format!("== {} ==\n", step.name).as_bytes()).await?;
+
+            result = self.run_step(step, bundle, &mut log).await
+                .with_context(|| format!("Juicer step {} failed", step.name));
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let mut logfile = bundle.write(Kind::other("juicer.log")).await
+            .with_context(|| "Failed to open juicer.log")?;
+        logfile.write_all(&log).await?;
+        logfile.commit().await?;
+
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::config::{NativeJuicer, PipelineStep};
+    use crate::juicer::Juicer as _;
+    use crate::repository::Repository;
+
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let pipelines = vec![
+            (String::from("application/pdf"), "pdf"),
+            (String::from("image/*"), "image"),
+            (String::from("*"), "any"),
+        ].into_iter().collect::<HashMap<_, _>>();
+
+        assert_that!(select(&pipelines, "application/pdf")).is_equal_to(Some(&"pdf"));
+        assert_that!(select(&pipelines, "image/png")).is_equal_to(Some(&"image"));
+        assert_that!(select(&pipelines, "application/vnd.oasis.opendocument.text")).is_equal_to(Some(&"any"));
+
+        let pipelines = vec![(String::from("application/pdf"), "pdf")].into_iter().collect::<HashMap<_, _>>();
+        assert_that!(select(&pipelines, "image/png")).is_none();
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let command = |name: &str, script: &str| PipelineStep {
+            name: String::from(name),
+            runner: StepRunner::Command {
+                program: String::from("sh"),
+                args: vec![String::from("-c"), String::from(script)],
+            },
+        };
+
+        let juicer = Juicer::from_config(Config {
+            native: NativeJuicer::default(),
+            pipelines: vec![
+                (String::from("application/pdf"), vec![
+                    command("ocr", "echo {languages} > document.txt"),
+                    command("fail", "echo failed; exit 1"),
+                    command("never", "touch never"),
+                ]),
+            ].into_iter().collect(),
+        }).await.unwrap();
+
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let staging = repository.stage().await.unwrap();
+        crate::meta::Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        let result = juicer.extract(&staging).await;
+        assert_that!(result.unwrap_err().to_string()).is_equal_to(String::from("Juicer step fail failed"));
+
+        let text = tokio::fs::read_to_string(staging.path().join("document.txt")).await.unwrap();
+        assert_that!(text.as_str()).is_equal_to("eng+deu\n");
+
+        let log = tokio::fs::read_to_string(staging.path().join("juicer.log")).await.unwrap();
+        assert_that!(log.as_str()).contains("== ocr ==");
+        assert_that!(log.as_str()).contains("failed");
+        assert_that!(log.contains("== never ==")).is_false();
+
+        assert_that!(staging.path().join("never").exists()).is_false();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_builtin() {
+        let result = Juicer::from_config(Config {
+            native: NativeJuicer::default(),
+            pipelines: vec![
+                (String::from("*"), vec![PipelineStep { name: String::from("deskew"), runner: StepRunner::Builtin }]),
+            ].into_iter().collect(),
+        }).await;
+
+        assert_that!(result.is_err()).is_true();
+    }
+}
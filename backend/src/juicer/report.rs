@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use super::JuicerError;
+
+/// Category of a juicer failure.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureKind {
+    Corrupted,
+    Encrypted,
+    Unsupported,
+    OcrFailed,
+    DiskFull,
+    Timeout,
+    Unknown,
+}
+
+impl FailureKind {
+    /// A message describing the failure to the user.
+    pub fn description(&self) -> &'static str {
+        return match self {
+            Self::Corrupted => "The PDF appears corrupted",
+            Self::Encrypted => "The PDF is encrypted",
+            Self::Unsupported => "The file is not a supported document",
+            Self::OcrFailed => "Text recognition failed",
+            Self::DiskFull => "There is not enough disk space left",
+            Self::Timeout => "Processing the document took too long",
+            Self::Unknown => "Processing the document failed",
+        };
+    }
+
+    /// The serialized name of the category.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Self::Corrupted => "corrupted",
+            Self::Encrypted => "encrypted",
+            Self::Unsupported => "unsupported",
+            Self::OcrFailed => "ocr-failed",
+            Self::DiskFull => "disk-full",
+            Self::Timeout => "timeout",
+            Self::Unknown => "unknown",
+        };
+    }
+}
+
+/// Log patterns identifying the failure categories, checked in order.
+const PATTERNS: &[(&str, FailureKind)] = &[
+    ("No space left on device", FailureKind::DiskFull),
+    ("EncryptedPdfError", FailureKind::Encrypted),
+    ("Incorrect password", FailureKind::Encrypted),
+    ("May not be a PDF file", FailureKind::Unsupported),
+    ("Couldn't read xref table", FailureKind::Corrupted),
+    ("Couldn't find trailer dictionary", FailureKind::Corrupted),
+    ("PDF file is damaged", FailureKind::Corrupted),
+    ("InputFileError", FailureKind::Corrupted),
+    ("Syntax Error", FailureKind::Corrupted),
+    ("SubprocessOutputError", FailureKind::OcrFailed),
+    ("Tesseract", FailureKind::OcrFailed),
+    ("tesseract", FailureKind::OcrFailed),
+];
+
+/// Structured report of a failed extraction.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    pub kind: FailureKind,
+
+    /// The raw error for diagnosis
+    pub details: String,
+}
+
+impl Failure {
+    /// Classifies a juicer error using the log written by the juicer.
+    pub fn classify(err: &anyhow::Error, log: &str) -> Self {
+        let details = format!("{:#}", err);
+
+        let kind = if let Some(JuicerError::Timeout(_)) = err.downcast_ref::<JuicerError>() {
+            FailureKind::Timeout
+        } else {
+            PATTERNS.iter()
+                .find(|(pattern, _)| log.contains(pattern) || details.contains(pattern))
+                .map_or(FailureKind::Unknown, |(_, kind)| *kind)
+        };
+
+        return Self { kind, details };
+    }
+
+    pub fn description(&self) -> &'static str { self.kind.description() }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let err = anyhow!("Juicing failed (id=abc): 1");
+
+        assert_that!(Failure::classify(&err, "Syntax Error: Couldn't read xref table\n").kind)
+            .is_equal_to(FailureKind::Corrupted);
+        assert_that!(Failure::classify(&err, "Syntax Warning: May not be a PDF file (continuing anyway)\n").kind)
+            .is_equal_to(FailureKind::Unsupported);
+        assert_that!(Failure::classify(&err, "write error: No space left on device\n").kind)
+            .is_equal_to(FailureKind::DiskFull);
+        assert_that!(Failure::classify(&err, "something else\n").kind)
+            .is_equal_to(FailureKind::Unknown);
+
+        let timeout = anyhow::Error::new(JuicerError::Timeout(Duration::from_secs(10)));
+        assert_that!(Failure::classify(&timeout, "").kind)
+            .is_equal_to(FailureKind::Timeout);
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use regex::bytes::Regex;
+use tokio::io::AsyncWriteExt;
+
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+use crate::repository::{Bundle, Staging};
+
+/// Extracts the text of uncompressed PDFs, which is shown by `(text) Tj` operators.
+fn text(pdf: &[u8]) -> String {
+    let operators = Regex::new(r"\(((?:[^()\\]|\\.)*)\)\s*Tj").expect("Invalid regex");
+
+    return operators.captures_iter(pdf)
+        .map(|captures| String::from_utf8_lossy(&captures[1]).replace("\\(", "(").replace("\\)", ")"))
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+/// Counts the pages of a PDF by their objects.
+fn pages(pdf: &[u8]) -> u32 {
+    let objects = Regex::new(r"/Type\s*/Page\b").expect("Invalid regex");
+    return objects.find_iter(pdf).count() as u32;
+}
+
+/// Juicer standing in for the real ones in tests, which neither requires docker nor any tools installed.
+///
+/// The original PDF is taken as document as is and the text is taken from the text operators of the PDF, so it only
+/// works for uncompressed PDFs like the ones written by tests. Previews are not rendered.
+pub struct Juicer {}
+
+#[async_trait]
+impl super::Juicer for Juicer {
+    async fn extract<'r>(&self, bundle: &Bundle<'r, Staging>) -> Result<()> {
+        let original = tokio::fs::read(bundle.path_of(Kind::other("original.pdf"))).await
+            .context("Missing original.pdf")?;
+
+        let mut document = bundle.write(Kind::Document).await?;
+        document.write_all(&original).await?;
+        document.commit().await?;
+
+        let mut plaintext = bundle.write(Kind::Plaintext).await?;
+        plaintext.write_all(text(&original).as_bytes()).await?;
+        plaintext.commit().await?;
+
+        let mut metadata = bundle.read_metadata().await?;
+        metadata.pages = pages(&original);
+        Metadata::save(&metadata, bundle.write(Kind::Metadata).await?).await?;
+
+        return Ok(());
+    }
+}
+
+/// Writes a single page PDF showing the given lines of text, which can be read by the stub juicer.
+pub fn sample(lines: &[&str]) -> Vec<u8> {
+    let content = lines.iter()
+        .map(|line| format!("({}) Tj T*", line.replace('(', "\\(").replace(')', "\\)")))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let content = format!("BT /F1 12 Tf 14 TL 72 720 Td\n{}\nET", content);
+
+    let objects = [
+        String::from("<< /Type /Catalog /Pages 2 0 R >>"),
+        String::from("<< /Type /Pages /Kids [3 0 R] /Count 1 >>"),
+        String::from("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> >> >>"),
+        format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+        String::from("<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>"),
+    ];
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", index + 1, object));
+    }
+
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref));
+
+    return pdf.into_bytes();
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let pdf = sample(&["Invoice (copy)", "Total: 42 EUR"]);
+
+        assert_that!(text(&pdf)).is_equal_to(String::from("Invoice (copy)\nTotal: 42 EUR"));
+        assert_that!(pages(&pdf)).is_equal_to(1);
+    }
+}
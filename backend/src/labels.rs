@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{bail, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::meta::Metadata;
+use crate::proto::model::Label;
+use crate::repository::{Repository, Settings};
+
+/// Presentation details of a label.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Definition {
+    /// Color as hex triplet like `#ff8800`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl Definition {
+    /// Checks if the color is a valid hex triplet.
+    pub fn is_valid(&self) -> bool {
+        return self.color.as_deref().map_or(true, |color| {
+            color.len() == 7
+                && color.starts_with('#')
+                && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+        });
+    }
+}
+
+impl Settings for BTreeMap<Label, Definition> {
+    const NAME: &'static str = "labels";
+    const VERSION: u32 = 1;
+
+    fn validate(&self) -> Result<()> {
+        for (label, definition) in self {
+            if !label.is_valid() {
+                bail!("Invalid label: {}", label);
+            }
+
+            if !definition.is_valid() {
+                bail!("Invalid color of label {}: {}", label, definition.color.as_deref().unwrap_or_default());
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+/// Repository-wide registry of labels.
+///
+/// Documents can use labels which are not registered, the registry only holds the details shown for a label.
+pub struct Labels {
+    repository: Repository,
+    labels: RwLock<BTreeMap<Label, Definition>>,
+}
+
+impl Labels {
+    pub async fn load(repository: Repository) -> Result<Self> {
+        let labels = repository.load_settings().await?;
+        return Ok(Self { repository, labels: RwLock::new(labels) });
+    }
+
+    async fn save(&self, labels: &BTreeMap<Label, Definition>) -> Result<()> {
+        return self.repository.save_settings(labels).await;
+    }
+
+    pub async fn list(&self) -> BTreeMap<Label, Definition> {
+        return self.labels.read().await.clone();
+    }
+
+    /// Registers a label or replaces its details.
+    pub async fn define(&self, label: Label, definition: Definition) -> Result<()> {
+        let mut labels = self.labels.write().await;
+        labels.insert(label, definition);
+        return self.save(&labels).await;
+    }
+
+    /// Removes a label from the registry and returns whether it was registered.
+    ///
+    /// Documents using the label are not changed.
+    pub async fn remove(&self, label: &Label) -> Result<bool> {
+        let mut labels = self.labels.write().await;
+        if labels.remove(label).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&labels).await?;
+
+        return Ok(true);
+    }
+
+    /// Renames a label and all labels below it, both in the registry and in all inboxed and archived documents.
+    ///
+    /// Returns the number of changed documents.
+    pub async fn rename(&self, repository: &Repository, from: &Label, to: &Label) -> Result<usize> {
+        // Hold the registry while documents are changed to serialize concurrent renames
+        let mut labels = self.labels.write().await;
+
+        let mut changed = 0;
+
+        for bundle in repository.inbox().list().await? {
+            let mut metadata = bundle.read_metadata().await?;
+            if relabel(&mut metadata, from, to) {
+                bundle.write_metadata(&metadata).await?;
+                changed += 1;
+            }
+        }
+
+        for bundle in repository.archive().list().await? {
+            let mut metadata = bundle.read_metadata().await?;
+            if relabel(&mut metadata, from, to) {
+                bundle.write_metadata(&metadata).await?;
+                changed += 1;
+            }
+        }
+
+        let moved = labels.keys()
+            .filter(|label| label.is_within(from))
+            .cloned()
+            .collect::<Vec<_>>();
+        for label in moved {
+            if let Some(definition) = labels.remove(&label) {
+                labels.insert(label.rebase(from, to).expect("Label within renamed label"), definition);
+            }
+        }
+
+        self.save(&labels).await?;
+
+        info!("Renamed label {} to {} in {} documents", from, to, changed);
+
+        return Ok(changed);
+    }
+}
+
+/// Replaces all labels within `from` by the same labels within `to` and returns whether anything changed.
+fn relabel(metadata: &mut Metadata, from: &Label, to: &Label) -> bool {
+    let labels = metadata.labels.iter()
+        .map(|label| label.rebase(from, to).unwrap_or_else(|| label.clone()))
+        .collect::<HashSet<_>>();
+
+    if labels == metadata.labels {
+        return false;
+    }
+
+    metadata.labels = labels;
+    return true;
+}
+
+/// Counts the inboxed and archived documents matching the predicate per label.
+///
+/// Documents are counted for the labels above their labels in the hierarchy, too.
+pub async fn usage(repository: &Repository, predicate: impl Fn(&Metadata) -> bool) -> Result<BTreeMap<Label, usize>> {
+    let mut metadata = Vec::new();
+    for bundle in repository.inbox().list().await? {
+        metadata.push(bundle.read_metadata().await?);
+    }
+    for bundle in repository.archive().list().await? {
+        metadata.push(bundle.read_metadata().await?);
+    }
+
+    let mut usage = BTreeMap::new();
+    for metadata in metadata.into_iter().filter(|metadata| predicate(metadata)) {
+        let labels = metadata.labels.iter()
+            .flat_map(|label| std::iter::once(label.clone()).chain(label.ancestors()))
+            .collect::<HashSet<_>>();
+
+        for label in labels {
+            *usage.entry(label).or_insert(0) += 1;
+        }
+    }
+
+    return Ok(usage);
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::proto::model::Kind;
+
+    use super::*;
+
+    async fn inboxed(repository: &Repository, labels: &[&str]) {
+        let staging = repository.stage().await.unwrap();
+
+        Metadata {
+            labels: labels.iter().map(|label| Label::from(*label)).collect(),
+            ..Metadata::new()
+        }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        staging.create().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_usage() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        inboxed(&repository, &["finance/tax/2023", "finance/tax/2022"]).await;
+        inboxed(&repository, &["finance/bank"]).await;
+
+        let usage = usage(&repository, |_| true).await.unwrap();
+        assert_that!(usage.get(&Label::from("finance"))).is_equal_to(Some(&2));
+        assert_that!(usage.get(&Label::from("finance/tax"))).is_equal_to(Some(&1));
+        assert_that!(usage.get(&Label::from("finance/tax/2023"))).is_equal_to(Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_rename() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        inboxed(&repository, &["finance/tax/2023", "private"]).await;
+        inboxed(&repository, &["finance/bank"]).await;
+
+        let labels = Labels::load(repository.clone()).await.unwrap();
+        labels.define(Label::from("finance/tax"), Definition {
+            color: Some(String::from("#ff0000")),
+            description: None,
+        }).await.unwrap();
+
+        let changed = labels.rename(&repository, &Label::from("finance/tax"), &Label::from("taxes")).await.unwrap();
+        assert_that!(changed).is_equal_to(1);
+
+        let usage = usage(&repository, |_| true).await.unwrap();
+        assert_that!(usage.get(&Label::from("taxes/2023"))).is_equal_to(Some(&1));
+        assert_that!(usage.get(&Label::from("finance/tax"))).is_none();
+        assert_that!(usage.get(&Label::from("finance/bank"))).is_equal_to(Some(&1));
+
+        let registry = Labels::load(repository.clone()).await.unwrap().list().await;
+        assert_that!(registry.keys().collect::<Vec<_>>()).is_equal_to(vec![&Label::from("taxes")]);
+    }
+}
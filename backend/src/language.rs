@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+/// Common words of the supported languages by their ISO 639-3 code, which is also the name of the tesseract language
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("deu", &["der", "die", "das", "und", "ist", "nicht", "mit", "den", "von", "zu", "für", "auf", "ein", "eine", "im", "sie", "wir", "ihre", "bitte", "vom"]),
+    ("eng", &["the", "and", "is", "of", "to", "for", "with", "on", "this", "that", "you", "your", "are", "be", "by", "from", "please", "we", "our", "will"]),
+    ("fra", &["le", "la", "les", "et", "est", "des", "du", "pour", "avec", "une", "dans", "vous", "votre", "nous", "sur", "pas", "par", "au", "aux", "ce"]),
+    ("spa", &["el", "los", "las", "y", "es", "del", "para", "con", "una", "por", "su", "que", "en", "usted", "al", "como", "este", "esta", "nuestro"]),
+    ("ita", &["il", "gli", "e", "di", "che", "per", "con", "una", "del", "della", "sono", "non", "si", "lo", "le", "al", "alla", "questo", "nostro", "vostro"]),
+    ("nld", &["de", "het", "een", "en", "van", "is", "niet", "met", "voor", "op", "dat", "u", "uw", "wij", "ons", "zijn", "aan", "bij", "deze", "graag"]),
+];
+
+/// Minimal number of common words to tell the language, shorter texts are not detected
+const MIN_MATCHES: usize = 5;
+
+/// Detects the language of a text by counting the common words of each supported language.
+///
+/// Returns the ISO 639-3 code of the language having the most common words, or `None` if the text is too short or
+/// the language is not supported.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let words = text.split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+
+    return STOPWORDS.iter()
+        .map(|(language, stopwords)| {
+            let stopwords = stopwords.iter().copied().collect::<HashSet<_>>();
+            let matches = words.iter().filter(|word| stopwords.contains(word.as_str())).count();
+            (*language, matches)
+        })
+        .filter(|(_, matches)| *matches >= MIN_MATCHES)
+        .max_by_key(|(_, matches)| *matches)
+        .map(|(language, _)| language);
+}
+
+/// Checks if the OCR languages are given like `deu+eng`.
+pub fn is_valid(languages: &str) -> bool {
+    return languages.split('+')
+        .all(|language| !language.is_empty() && language.chars().all(|c| c.is_ascii_lowercase() || c == '_'));
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_that!(detect("Sehr geehrte Damen und Herren, die Rechnung für den Monat ist bitte bis zum Ende des Monats auf das Konto zu überweisen."))
+            .is_equal_to(Some("deu"));
+        assert_that!(detect("Dear customer, please find the invoice for this month attached. The total is due within 14 days of the date on your statement."))
+            .is_equal_to(Some("eng"));
+        assert_that!(detect("Total: 42 EUR")).is_none();
+        assert_that!(detect("")).is_none();
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert_that!(is_valid("deu")).is_true();
+        assert_that!(is_valid("deu+eng+chi_sim")).is_true();
+        assert_that!(is_valid("deu+")).is_false();
+        assert_that!(is_valid("deu; rm -rf /")).is_false();
+    }
+}
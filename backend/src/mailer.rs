@@ -0,0 +1,154 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, Result};
+use lettre::{SmtpClient, Transport};
+use lettre::smtp::authentication::Credentials;
+use lettre_email::{Email, EmailBuilder};
+
+use crate::config::Mail as Config;
+use crate::meta::Metadata;
+use crate::proto::model::DocId;
+
+/// Delivers a mail using STARTTLS on the submission port of the SMTP server.
+pub async fn deliver(host: &str, username: &str, password: &str, email: Email) -> Result<()> {
+    let (host, username, password) = (host.to_string(), username.to_string(), password.to_string());
+
+    return tokio::task::spawn_blocking(move || -> Result<()> {
+        SmtpClient::new_simple(&host)
+            .map_err(|err| anyhow!("Failed to connect to {}: {}", host, err))?
+            .credentials(Credentials::new(username, password))
+            .transport()
+            .send(email.into())
+            .map_err(|err| anyhow!("Failed to send mail: {}", err))?;
+
+        return Ok(());
+    }).await?;
+}
+
+/// Summarizes the metadata of a document for the body of a mail.
+pub fn summary(id: DocId, metadata: &Metadata) -> String {
+    let mut text = String::new();
+
+    let _ = writeln!(text, "Title: {}", metadata.title.as_deref().unwrap_or("Untitled"));
+    let _ = writeln!(text, "Document: {}", id);
+    let _ = writeln!(text, "Uploaded: {}", metadata.uploaded.format("%Y-%m-%d"));
+
+    if let Some(correspondent) = &metadata.correspondent {
+        let _ = writeln!(text, "Correspondent: {}", correspondent);
+    }
+
+    if let Some(due) = metadata.due {
+        let _ = writeln!(text, "Due: {}", due.format("%Y-%m-%d"));
+    }
+
+    if !metadata.labels.is_empty() {
+        let mut labels = metadata.labels.iter().map(ToString::to_string).collect::<Vec<_>>();
+        labels.sort();
+        let _ = writeln!(text, "Labels: {}", labels.join(", "));
+    }
+
+    let mut properties = metadata.properties.iter().collect::<Vec<_>>();
+    properties.sort_by_key(|(name, _)| name.as_str());
+    for (name, value) in properties {
+        let _ = writeln!(text, "{}: {}", name, serde_json::to_string(value).unwrap_or_default());
+    }
+
+    return text;
+}
+
+/// Sends archived documents by mail, i.e. to forward an invoice to an accountant.
+pub struct Mailer {
+    config: Option<Config>,
+}
+
+impl Mailer {
+    pub fn new(config: Option<Config>) -> Self {
+        return Self { config };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.config.is_some();
+    }
+
+    /// Sends a document as PDF attachment along with the summary of its metadata.
+    ///
+    /// Documents larger than the configured limit are refused, as most mail servers reject large mails anyway.
+    pub async fn send(&self,
+                      to: &str,
+                      message: Option<&str>,
+                      id: DocId,
+                      metadata: &Metadata,
+                      document: &[u8]) -> Result<()> {
+        let config = match &self.config {
+            Some(config) => config,
+            None => bail!("Sending mails is disabled"),
+        };
+
+        if document.len() as u64 > config.max_size {
+            bail!(TooLarge(config.max_size));
+        }
+
+        let mut body = String::new();
+        if let Some(message) = message.filter(|message| !message.trim().is_empty()) {
+            let _ = writeln!(body, "{}\n", message.trim());
+        }
+        body.push_str(&summary(id, metadata));
+
+        let title = metadata.title.as_deref().unwrap_or("Untitled");
+        let filename = format!("{}.pdf", title.replace(|c: char| c == '/' || c == '\\' || c.is_control(), "_"));
+
+        let email = EmailBuilder::new()
+            .from(config.from.as_str())
+            .to(to)
+            .subject(title)
+            .text(body)
+            .attachment(document, &filename, &mime::APPLICATION_PDF)
+            .map_err(|err| anyhow!("Failed to attach document: {}", err))?
+            .build()
+            .map_err(|err| anyhow!("Failed to build mail: {}", err))?;
+
+        return deliver(&config.host, &config.username, &config.password, email).await;
+    }
+}
+
+/// The document exceeds the size limit of mails.
+#[derive(Debug, thiserror::Error)]
+#[error("Document exceeds the mail size limit of {0} bytes")]
+pub struct TooLarge(pub u64);
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::proto::model::{Label, PropertyValue};
+
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let id = DocId::random();
+
+        let mut metadata = Metadata::new();
+        metadata.title = Some(String::from("Invoice 42"));
+        metadata.correspondent = Some(String::from("ACME"));
+        metadata.labels.insert(Label::from("tax"));
+        metadata.labels.insert(Label::from("invoice"));
+        metadata.properties.insert(String::from("amount"), PropertyValue::Integer(42));
+
+        let summary = summary(id, &metadata);
+
+        assert_that!(summary).starts_with("Title: Invoice 42\n");
+        assert_that!(summary).contains(format!("Document: {}\n", id).as_str());
+        assert_that!(summary).contains("Correspondent: ACME\n");
+        assert_that!(summary).contains("Labels: invoice, tax\n");
+        assert_that!(summary).contains("amount: 42\n");
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let mailer = Mailer::new(None);
+
+        assert_that!(mailer.is_enabled()).is_false();
+        assert_that!(mailer.send("accountant@example.com", None, DocId::random(), &Metadata::new(), b"%PDF").await).is_err();
+    }
+}
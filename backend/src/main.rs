@@ -1,30 +1,162 @@
 #![feature(bool_to_option)]
 #![feature(try_blocks)]
 
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
 pub use adacta_proto as proto;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::{App, Arg};
+use log::{error, info};
 
+use crate::attestation::Attestor;
 use crate::auth::Authenticator;
-use crate::config::{Config, Index as IndexConfig, Juicer as JuicerConfig, Suggester as SuggesterConfig};
+use crate::backup::Backup;
+use crate::checklists::Checklists;
+use crate::config::{Config, Index as IndexConfig, MaintenanceTask, NotificationEvent, Queue as QueueConfig, Suggester as SuggesterConfig, Tls as TlsConfig};
+use crate::correspondents::Correspondents;
+use crate::crypto::Keyring;
+use crate::editing::Editor;
+use crate::export::Export;
+use crate::filing::Filing;
 use crate::index::Index;
+use crate::ingest::consume::Consumer;
+use crate::ingest::imap::Mailbox;
+use crate::ingest::paperless::Paperless;
 use crate::juicer::Juicer;
-use crate::repository::Repository;
+use crate::labels::Labels;
+use crate::mailer::Mailer;
+use crate::maintenance::{self, Scheduler, Task};
+use crate::merge::Merger;
+use crate::notifications::Notifications;
+use crate::orphans::Collector;
+use crate::persons::Persons;
+use crate::preferences::Preferences;
+use crate::previews::Previews;
+use crate::processors::Processors;
+use crate::quarantine::Reviews;
+use crate::queue::Queue;
+use crate::quota::Quotas;
+use crate::reload::Reloader;
+use crate::reminders::Reminders;
+use crate::repository::{Repository, Retention};
+use crate::requests::Requests;
+use crate::rules::Rules;
+use crate::satellite::Satellite;
+use crate::shares::Shares;
+use crate::snooze::Snoozer;
+use crate::status::Status;
 use crate::suggester::Suggester;
+use crate::suggestions::Suggestions;
+use crate::telemetry::Telemetry;
+use crate::timestamping::Timestamper;
+use crate::transcription::Transcriber;
+use crate::undo::Undo;
+use crate::warmup::Warmup;
+use crate::web::{Acme, Dav, Grpc, Named, Repositories};
 
+pub mod attachments;
+pub mod attestation;
 pub mod auth;
+pub mod backup;
+pub mod barcodes;
+pub mod checklists;
+pub mod confidence;
 pub mod config;
+pub mod contracts;
+pub mod correspondents;
+pub mod crypto;
+pub mod dates;
+pub mod editing;
+pub mod einvoice;
+pub mod export;
+pub mod filing;
+pub mod geotag;
+#[cfg(any(test, feature = "harness"))]
+pub mod harness;
 pub mod index;
+pub mod ingest;
 pub mod juicer;
+pub mod labels;
+pub mod language;
+pub mod mailer;
+pub mod maintenance;
+pub mod merge;
 pub mod meta;
+pub mod mimetype;
+pub mod notifications;
+pub mod orphans;
+pub mod period;
+pub mod plugins;
+pub mod persons;
+pub mod preferences;
+pub mod previews;
+pub mod processors;
+pub mod quarantine;
+pub mod queue;
+pub mod quota;
+pub mod reminders;
+pub mod render;
 pub mod suggester;
 pub mod repository;
+pub mod requests;
+pub mod reload;
+pub mod rules;
+pub mod satellite;
+pub mod shares;
+pub mod snippets;
+pub mod snooze;
+pub mod split;
+pub mod stats;
+pub mod status;
+pub mod suggestions;
+pub mod telemetry;
+pub mod timestamping;
+pub mod transcription;
+pub mod undo;
+pub mod uploads;
 pub mod utils;
+pub mod warmup;
+pub mod warranties;
 pub mod web;
 
+/// Connects to the index and tells whether it has been created empty.
+async fn connect(config: IndexConfig) -> Result<(Arc<dyn Index + Send + Sync>, bool)> {
+    return Ok(match config {
+        IndexConfig::Elasticsearch(config) => {
+            let index = crate::index::elasticsearch::Index::from_config(config).await?;
+            let created = index.is_created();
+            (Arc::new(index), created)
+        }
+    });
+}
+
+/// Fills an index created empty with the archived documents of the repository.
+async fn fill(index: Arc<dyn Index + Send + Sync>, repo: Repository, status: Arc<Status>) {
+    match maintenance::Reindex::new(repo, index).run().await {
+        Ok(summary) => info!("Filled created index: {}", summary),
+        Err(err) => {
+            error!("Failed to fill created index: {:#}", err);
+            status.failed("index", &err);
+        }
+    }
+}
+
+/// Creates the alternative juicers failed jobs can be retried with.
+async fn create_alternatives(config: &QueueConfig) -> Result<HashMap<String, Arc<dyn Juicer + Send + Sync>>> {
+    let mut juicers = HashMap::new();
+    for (name, juicer) in &config.juicers {
+        juicers.insert(name.clone(), crate::juicer::from_config(juicer.clone()).await?);
+    }
+
+    return Ok(juicers);
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let matches = App::new("adacta")
+    let app = App::new("adacta")
         .version(env!("CARGO_PKG_VERSION"))
         .name(env!("CARGO_PKG_NAME"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
@@ -40,30 +172,400 @@ async fn main() -> Result<()> {
             .help("Sets a custom config file")
             .takes_value(true)
             .default_value("adacta.yaml"))
-        .get_matches();
+        .arg(Arg::with_name("fsck")
+            .long("fsck")
+            .help("Verify the integrity of the repository and exit")
+            .takes_value(false))
+        .arg(Arg::with_name("migrate")
+            .long("migrate")
+            .help("Migrate the repository to the current format and exit")
+            .takes_value(false))
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .requires("migrate")
+            .help("List the pending migrations without applying them")
+            .takes_value(false))
+        .arg(Arg::with_name("fork")
+            .long("fork")
+            .value_name("PATH")
+            .help("Create a space-efficient copy of the repository at PATH and exit")
+            .takes_value(true))
+        .arg(Arg::with_name("restore")
+            .long("restore")
+            .value_name("NAME")
+            .help("Restore the backup NAME, or the latest one, into the empty repository directory and exit")
+            .takes_value(true))
+        .arg(Arg::with_name("export")
+            .long("export")
+            .value_name("FILE")
+            .help("Export all archived documents to a portable archive and exit")
+            .takes_value(true))
+        .arg(Arg::with_name("import")
+            .long("import")
+            .value_name("FILE")
+            .help("Import the documents of a portable archive and exit")
+            .takes_value(true))
+        .arg(Arg::with_name("import-paperless")
+            .long("import-paperless")
+            .value_name("PATH")
+            .help("Import the documents of a paperless-ngx export at PATH and exit")
+            .takes_value(true));
+
+    #[cfg(feature = "harness")]
+    let app = app.arg(Arg::with_name("harness")
+        .long("harness")
+        .value_name("PORT")
+        .help("Serve a throwaway repository with a stub juicer on PORT, ignoring the config")
+        .takes_value(true));
+
+    let matches = app.get_matches();
+
+    #[cfg(feature = "harness")]
+    if let Some(port) = matches.value_of("harness") {
+        return crate::harness::Harness::serve(port.parse()?).await;
+    }
 
 
     let config = Config::load(matches.value_of("config").expect("No config arg")).await?;
 
-    // Create auth instance
-    let auth = Authenticator::from_config(config.auth).await?;
+    // Export spans of the ingest pipeline, pending spans are flushed on exit
+    let _telemetry = config.tracing.clone().map(Telemetry::from_config).transpose()?;
+
+    if let Some(name) = matches.value_of("restore") {
+        let backup = config.backup.clone()
+            .ok_or_else(|| anyhow::anyhow!("No backup configured"))?;
+        let name = crate::backup::restore(backup, name, &config.repository.path).await?;
+
+        println!("Restored backup {} to {}", name, config.repository.path);
+        return Ok(());
+    }
+
+    if matches.is_present("migrate") {
+        let pending = Repository::pending_migrations(&config.repository.path).await?;
+        for migration in &pending {
+            println!("{}: {}", migration.version, migration.description);
+        }
+
+        if matches.is_present("dry-run") {
+            println!("{} migrations pending", pending.len());
+            return Ok(());
+        }
+
+        // Opening the repository applies all pending migrations
+        Repository::from_config(config.repository).await?;
+
+        println!("Migrated repository to format {}", crate::repository::VERSION);
+        return Ok(());
+    }
 
     // Open repository
+    let trash_retention = config.repository.trash_retention;
     let repo = Repository::from_config(config.repository).await?;
 
-    // Connect to index
-    let index: Box<dyn Index + Send + Sync> = match config.index {
-        IndexConfig::Elasticsearch(config) => {
-            Box::new(crate::index::elasticsearch::Index::from_config(config).await?)
+    if matches.is_present("fsck") {
+        let report = repo.verify().await?;
+        for (id, problem) in &report.problems {
+            println!("{}: {}", id, problem);
+        }
+
+        println!("Verified {} bundles: {} problems found", report.bundles, report.problems.len());
+        std::process::exit(if report.is_ok() { 0 } else { 1 });
+    }
+
+    if let Some(path) = matches.value_of("fork") {
+        repo.fork(path.to_string()).await?;
+
+        println!("Forked repository to {}", path);
+        return Ok(());
+    }
+
+    if let Some(path) = matches.value_of("export") {
+        let export = Export::archive(&repo).await?;
+        let file = std::fs::File::create(path)?;
+        let exported = tokio::task::spawn_blocking(move || export.write(file)).await??;
+
+        println!("Exported {} documents to {}", exported, path);
+        return Ok(());
+    }
+
+    if let Some(path) = matches.value_of("import") {
+        let imported = crate::export::import(&repo, std::fs::File::open(path)?).await?;
+
+        // The index is not following the repository yet
+        let (index, created) = connect(config.index).await?;
+        if created {
+            maintenance::Reindex::new(repo.clone(), index.clone()).run().await?;
+        } else {
+            for id in imported.changed() {
+                if let Some(bundle) = repo.archive().get(id).await {
+                    index.index(&bundle).await?;
+                }
+            }
+        }
+
+        for (id, outcome) in &imported.report {
+            if *outcome != crate::export::Outcome::Imported {
+                println!("{}: {}", id, serde_json::to_string(outcome)?);
+            }
+        }
+
+        println!("Imported {} documents from {}, skipped {} existing ones", imported.bundles.len(), path, imported.skipped);
+        return Ok(());
+    }
+
+    // Create auth instance, issued API tokens and second factors are stored alongside the repository
+    let auth = Arc::new(Authenticator::from_config(config.auth, repo.path().to_path_buf()).await?);
+
+    // Runtime information for the admin dashboard
+    let status = Arc::new(Status::new());
+
+    // The retention formerly configured is kept in the repository settings unless set there already
+    let mut retention = repo.load_settings::<Retention>().await?;
+    if retention.trash.is_none() && trash_retention.is_some() {
+        retention.trash = trash_retention;
+        repo.save_settings(&retention).await?;
+    }
+
+    // Maintenance tasks scheduled in the configuration run at their intervals instead of their own schedules
+    let mut scheduler = Scheduler::new(status.clone());
+    let schedule = config.maintenance.iter()
+        .map(|maintenance| (maintenance.task, maintenance.interval))
+        .collect::<HashMap<_, _>>();
+
+    // Periodically purge expired bundles from the trash, following changes of the retention settings
+    if let Some(interval) = schedule.get(&MaintenanceTask::Trash) {
+        scheduler.schedule(MaintenanceTask::Trash, *interval, Box::new(maintenance::Trash::new(repo.clone())));
+    } else {
+        let repo = repo.clone();
+        let status = status.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+            loop {
+                interval.tick().await;
+
+                let result = match repo.load_settings::<Retention>().await {
+                    Ok(Retention { trash: Some(days) }) => repo.trash().purge(chrono::Duration::days(days.into())).await,
+                    Ok(Retention { trash: None }) => continue,
+                    Err(err) => Err(err),
+                };
+
+                match result {
+                    Ok(purged) => info!("Purged {} bundles from trash", purged),
+                    Err(err) => {
+                        error!("Failed to purge trash: {:#}", err);
+                        status.failed("trash", &err);
+                    }
+                }
+            }
+        });
+    }
+
+    // Collect bundles left behind in the staging area
+    let orphans = config.orphans.enabled;
+    let collector = Collector::from_config(config.orphans, repo.clone(), status.clone());
+    if let Some(interval) = schedule.get(&MaintenanceTask::Orphans) {
+        scheduler.schedule(MaintenanceTask::Orphans, *interval, Box::new(collector));
+    } else if orphans {
+        tokio::spawn(collector.run());
+    }
+
+    // Periodically back up the repository
+    if let Some(config) = config.backup {
+        let backup = Backup::from_config(config, repo.clone(), status.clone()).await?;
+        match schedule.get(&MaintenanceTask::Backup) {
+            Some(interval) => scheduler.schedule(MaintenanceTask::Backup, *interval, Box::new(backup)),
+            None => { tokio::spawn(backup.run()); }
+        }
+    } else if schedule.contains_key(&MaintenanceTask::Backup) {
+        bail!("Backup scheduled for maintenance, but not configured");
+    }
+
+    // Integrations compiled into this build
+    let plugins = plugins::plugins();
+
+    // Bring snoozed documents back to the inbox
+    let snoozer = Snoozer::from_config(config.reminders.clone(), repo.clone(), status.clone())?;
+    tokio::spawn(snoozer.run());
+
+    // Announce new documents and failures in chat channels, which are notified of due documents as well
+    let mut notifiers = plugins.notifiers;
+    let notifications = match config.notifications {
+        Some(config) => {
+            let notifications = Arc::new(Notifications::from_config(config, repo.clone(), status.clone())?);
+            notifiers.extend(notifications.notifiers(NotificationEvent::Due));
+            tokio::spawn(notifications.clone().run());
+            Some(notifications)
         }
+        None => None,
     };
 
+    // Notify about documents approaching their due date
+    if let Some(config) = config.reminders {
+        let reminders = Reminders::from_config(config, repo.clone(), notifiers, status.clone()).await?;
+        tokio::spawn(reminders.run());
+    }
+
+    // Sign manifests of all bundle checksums
+    if let Some(config) = config.attestation {
+        let attestor = Attestor::from_config(config, repo.clone(), status.clone()).await?;
+        tokio::spawn(attestor.run());
+    }
+
+    // Obtain trusted timestamps for archived documents
+    if let Some(config) = config.timestamping {
+        let timestamper = Timestamper::from_config(config, repo.clone(), status.clone())?;
+        tokio::spawn(timestamper.run());
+    }
+
+    // Sync with upstream instance
+    if let Some(config) = config.satellite {
+        let satellite = Satellite::from_config(config, repo.clone(), status.clone()).await?;
+        tokio::spawn(satellite.run());
+    }
+
+    // Connect to index
+    let (index, created) = connect(config.index.clone()).await?;
+    if created {
+        tokio::spawn(fill(index.clone(), repo.clone(), status.clone()));
+    }
+
+    // Keep the index in sync with the repository
+    tokio::spawn(crate::index::follow(index.clone(), repo.clone(), status.clone()));
+
+    if let Some(interval) = schedule.get(&MaintenanceTask::Reindex) {
+        scheduler.schedule(MaintenanceTask::Reindex, *interval, Box::new(maintenance::Reindex::new(repo.clone(), index.clone())));
+    }
+
+    if let Some(interval) = schedule.get(&MaintenanceTask::Duplicates) {
+        scheduler.schedule(MaintenanceTask::Duplicates, *interval, Box::new(maintenance::Duplicates::new(repo.clone())));
+    }
+
+    if !scheduler.is_empty() {
+        tokio::spawn(scheduler.run());
+    }
+
+    // Render missing renditions of archived documents before they are first viewed
+    if config.warmup.enabled {
+        let warmup = Warmup::from_config(config.warmup, repo.clone(), status.clone());
+        tokio::spawn(warmup.run());
+    }
+
+    // Render previews in the requested size on demand and evict them with their bundles
+    let previews = Previews::from_config(config.previews.clone(), &repo);
+    tokio::spawn(previews.clone().run(repo.clone()));
+
+    // Enrich the metadata of archived documents by processing their fragments
+    if !config.processors.is_empty() || !plugins.processors.is_empty() {
+        let processors = Processors::from_config(config.processors, plugins.processors, repo.clone(), status.clone());
+        tokio::spawn(processors.run());
+    }
+
     // Create juicer instance
-    let juicer: Box<dyn Juicer + Send + Sync> = match config.juicer {
-        JuicerConfig::Docker(config) => {
-            Box::new(crate::juicer::docker::Juicer::from_config(config).await?)
+    let juicer = crate::juicer::from_config(config.juicer.clone()).await?;
+
+    // Correspondents are registered alongside the repository and identified while juicing
+    let correspondents = Arc::new(Correspondents::load(repo.path().join("correspondents.json")).await?);
+
+    // Rules applied to documents landing in the inbox are stored in the repository settings
+    let rules = Arc::new(Rules::load(repo.clone()).await?);
+
+    // Details of labels are registered in the repository settings
+    let labels = Labels::load(repo.clone()).await?;
+
+    // Run the juicer in the background and pick up jobs interrupted by a restart
+    let alternatives = create_alternatives(&config.queue).await?;
+    let queue = Queue::new(config.queue.clone(), repo.clone(), juicer, alternatives.clone(), rules.clone(), correspondents.clone(), status.clone());
+
+    if let Some(path) = matches.value_of("import-paperless") {
+        let imported = Paperless::new(path, &queue, &labels, &correspondents).import().await?;
+
+        // Index right away, as the process exits before the index follows the archived documents
+        for id in &imported.bundles {
+            if let Some(bundle) = repo.archive().get(*id).await {
+                index.index(&bundle).await?;
+            }
         }
-    };
+
+        println!("Imported {} documents from {}, skipped {} imported before, {} failed",
+                 imported.bundles.len(), path, imported.skipped, imported.failed);
+        std::process::exit(if imported.failed == 0 { 0 } else { 1 });
+    }
+
+    queue.resume().await?;
+
+    // Ingest the documents brought in by sources
+    plugins::run_sources(plugins.sources, queue.clone(), status.clone());
+
+    // Serve the named repositories along with queues, indices and the services bound to a repository of their own
+    let mut repositories = Vec::new();
+    for (name, named) in config.repositories {
+        if name.is_empty() || name.contains('/') {
+            bail!("Invalid repository name: {:?}", name);
+        }
+
+        let repo = Repository::from_config(named.repository).await?;
+        let juicer = crate::juicer::from_config(named.juicer.unwrap_or_else(|| config.juicer.clone())).await?;
+
+        let rules = Arc::new(Rules::load(repo.clone()).await?);
+        let correspondents = Arc::new(Correspondents::load(repo.path().join("correspondents.json")).await?);
+
+        let queue = Queue::new(config.queue.clone(), repo.clone(), juicer, alternatives.clone(), rules.clone(), correspondents.clone(), status.clone());
+        queue.resume().await?;
+
+        let (index, created) = connect(config.index.named(&name)).await?;
+        if created {
+            tokio::spawn(fill(index.clone(), repo.clone(), status.clone()));
+        }
+        tokio::spawn(crate::index::follow(index.clone(), repo.clone(), status.clone()));
+
+        let previews = Previews::from_config(config.previews.clone(), &repo);
+        tokio::spawn(previews.clone().run(repo.clone()));
+
+        let named = Named {
+            labels: Labels::load(repo.clone()).await?,
+            keyring: Keyring::new(config.domains.clone(), repo.path().join("domains")).await?,
+            filing: Filing::new(config.filing.clone(), repo.clone()),
+            previews,
+            rules,
+            correspondents,
+            persons: Persons::load(repo.path().join("persons.json")).await?,
+            checklists: Checklists::load(repo.path().join("checklists.json")).await?,
+            merger: Merger::new(config.merge.clone(), queue.clone()),
+            editor: Editor::new(config.editing.clone(), queue.clone()),
+            undo: Undo::new(Duration::from_secs(config.web.undo_window)),
+            reviews: Reviews::open(repo.path().join(Reviews::FILENAME)),
+            suggestions: Suggestions::new(),
+            index,
+            queue,
+            repository: repo,
+        };
+
+        info!("Serving repository {} at {:?}", name, named.repository.path());
+        repositories.push((name, named));
+    }
+
+    // Reload the juicer, the notification channels and the rules on SIGHUP or request without interrupting ingests
+    let named = repositories.iter()
+        .map(|(name, named)| (name.clone(), named.queue.clone(), named.rules.clone()))
+        .collect();
+    let reloader = Arc::new(Reloader::new(matches.value_of("config").map(PathBuf::from), queue.clone(), rules.clone(), named, notifications, status.clone()));
+    tokio::spawn(reloader.clone().run());
+
+    // Watch the consume directory
+    if let Some(config) = config.consume {
+        let consumer = Consumer::from_config(config, queue.clone(), status.clone()).await?;
+        tokio::spawn(async move {
+            if let Err(err) = consumer.run().await {
+                error!("Consumer failed: {:#}", err);
+            }
+        });
+    }
+
+    // Poll the IMAP mailbox
+    if let Some(config) = config.imap {
+        let mailbox = Mailbox::from_config(config, queue.clone(), status.clone()).await?;
+        tokio::spawn(mailbox.run());
+    }
 
     // Load suggester
     let suggester: Box<dyn Suggester + Send + Sync> = match config.suggester {
@@ -75,8 +577,88 @@ async fn main() -> Result<()> {
         }
     };
 
+    // Per-user preferences are stored alongside the repository
+    let preferences = Preferences::with_path(repo.path().join("preferences")).await?;
+
+    // Keys of encryption domains are unlocked at runtime, only their parameters are stored
+    let keyring = Keyring::new(config.domains.clone(), repo.path().join("domains")).await?;
+
+    // Archive serial numbers for filing labels are counted in the repository settings
+    let filing = Filing::new(config.filing, repo.clone());
+
+    // Archived documents can be sent by mail
+    let mailer = Mailer::new(config.mail);
+
+    // Audio attached to documents is transcribed into the index
+    let transcriber = Transcriber::new(config.transcription);
+
+    // Documents arriving as separate scans are merged by juicing them again
+    let merger = Merger::new(config.merge, queue.clone());
+    let editor = Editor::new(config.editing, queue.clone());
+
+    // Uploads are rejected if the documents of the repository or the uploading user would exceed their quota
+    let quotas = Quotas::new(config.quota);
+
+    // Household members documents belong to
+    let persons = Persons::load(repo.path().join("persons.json")).await?;
+
+    // Checklists of documents expected every year
+    let checklists = Checklists::load(repo.path().join("checklists.json")).await?;
+
+    // Open document requests handed out as upload links
+    let requests = Requests::load(repo.path().join("requests.json")).await?;
+
+    // Signed links sharing documents until they expire
+    let shares = Shares::load(repo.path().join("shares.json"), repo.path().join("shares.key")).await?;
+
+    // Serve the read-only view of the archive
+    if let Some(dav) = config.dav {
+        let dav = Dav::from_config(dav, auth.clone(), repo.clone(), status.clone());
+        tokio::spawn(dav.run());
+    }
+
+    // Serve the core operations for integrations
+    if let Some(grpc) = config.grpc {
+        let grpc = Grpc::from_config(grpc, auth.clone(), config.domains, repo.clone(), index.clone(), queue.clone(), quotas.clone(), status.clone());
+        tokio::spawn(grpc.run());
+    }
+
+    // Obtain the certificate before launching, as the web server loads it on launch only
+    if let Some(TlsConfig::Acme(acme)) = config.web.tls.clone() {
+        let acme = Acme::from_config(acme, status.clone());
+        acme.serve(&config.web.address);
+        acme.obtain().await?;
+        tokio::spawn(acme.run());
+    }
+
     // Serve the HTTP Interface
-    web::server(config.web, auth, repo, index, juicer, suggester)?.launch().await?;
+    let services = web::Services {
+        auth,
+        repository: repo,
+        index,
+        queue,
+        suggester,
+        preferences,
+        keyring,
+        filing,
+        mailer,
+        previews,
+        transcriber,
+        merger,
+        editor,
+        quotas,
+        labels,
+        correspondents,
+        persons,
+        checklists,
+        rules,
+        requests,
+        shares,
+        repositories: Repositories::new(repositories),
+        reloader,
+    };
+
+    web::server(config.web, services, status)?.launch().await?;
 
     return Ok(());
 }
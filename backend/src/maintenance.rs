@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::join_all;
+use log::{info, warn};
+use tokio::time::Instant;
+
+use crate::config::MaintenanceTask;
+use crate::index::Index;
+use crate::proto::model::DocId;
+use crate::repository::{Repository, Retention};
+use crate::status::Status;
+
+/// Filename of the document fragment, which identifies the content of a bundle
+const DOCUMENT: &str = "document.pdf";
+
+/// A maintenance task which can be run by the scheduler.
+#[async_trait]
+pub trait Task: Send + Sync {
+    /// Runs the task once and returns a summary of what has been done.
+    async fn run(&self) -> Result<String>;
+}
+
+/// Indexes all archived documents again.
+pub struct Reindex {
+    repository: Repository,
+    index: Arc<dyn Index + Send + Sync>,
+}
+
+impl Reindex {
+    pub fn new(repository: Repository, index: Arc<dyn Index + Send + Sync>) -> Self {
+        return Self { repository, index };
+    }
+}
+
+#[async_trait]
+impl Task for Reindex {
+    async fn run(&self) -> Result<String> {
+        let mut indexed = 0;
+        for bundle in self.repository.archive().list().await? {
+            self.index.index(&bundle).await?;
+            indexed += 1;
+        }
+
+        return Ok(format!("Indexed {} documents", indexed));
+    }
+}
+
+/// Purges expired bundles from the trash, following the retention settings of the repository.
+pub struct Trash {
+    repository: Repository,
+}
+
+impl Trash {
+    pub fn new(repository: Repository) -> Self {
+        return Self { repository };
+    }
+}
+
+#[async_trait]
+impl Task for Trash {
+    async fn run(&self) -> Result<String> {
+        let days = match self.repository.load_settings::<Retention>().await?.trash {
+            Some(days) => days,
+            None => return Ok(String::from("No retention configured")),
+        };
+
+        let purged = self.repository.trash().purge(chrono::Duration::days(days.into())).await?;
+
+        return Ok(format!("Purged {} bundles", purged));
+    }
+}
+
+/// Reports documents stored more than once in the inbox and archive, by the checksum of their document.
+///
+/// Duplicates are only reported, as it is up to the user which of the copies to keep.
+pub struct Duplicates {
+    repository: Repository,
+}
+
+impl Duplicates {
+    pub fn new(repository: Repository) -> Self {
+        return Self { repository };
+    }
+
+    /// Collects the bundles sharing the same document, by the checksum of the document.
+    pub async fn scan(&self) -> Result<Vec<Vec<DocId>>> {
+        let mut documents = BTreeMap::<String, Vec<DocId>>::new();
+
+        for bundle in self.repository.inbox().list().await? {
+            if let Some(checksum) = bundle.read_checksums().await?.as_ref().and_then(|checksums| checksums.get(DOCUMENT)) {
+                documents.entry(checksum.to_string()).or_default().push(*bundle.id());
+            }
+        }
+
+        for bundle in self.repository.archive().list().await? {
+            if let Some(checksum) = bundle.read_checksums().await?.as_ref().and_then(|checksums| checksums.get(DOCUMENT)) {
+                documents.entry(checksum.to_string()).or_default().push(*bundle.id());
+            }
+        }
+
+        return Ok(documents.into_iter()
+            .map(|(_, ids)| ids)
+            .filter(|ids| ids.len() > 1)
+            .collect());
+    }
+}
+
+#[async_trait]
+impl Task for Duplicates {
+    async fn run(&self) -> Result<String> {
+        let duplicates = self.scan().await?;
+
+        for ids in &duplicates {
+            warn!("Found duplicate documents: {}", ids.iter().map(DocId::to_string).collect::<Vec<_>>().join(", "));
+        }
+
+        return Ok(format!("Found {} documents stored more than once", duplicates.len()));
+    }
+}
+
+/// Runs maintenance tasks at fixed intervals and records their last runs for the admin dashboard.
+///
+/// The first run of each task is after its first interval, so restarts do not trigger expensive tasks like reindexing.
+/// Runs of the same task never overlap, whereas different tasks run concurrently.
+pub struct Scheduler {
+    tasks: Vec<(MaintenanceTask, Duration, Box<dyn Task>)>,
+
+    status: Arc<Status>,
+}
+
+impl Scheduler {
+    pub fn new(status: Arc<Status>) -> Self {
+        return Self { tasks: Vec::new(), status };
+    }
+
+    /// Schedules a task to run every interval, given in seconds.
+    pub fn schedule(&mut self, task: MaintenanceTask, interval: u64, job: Box<dyn Task>) {
+        self.status.scheduled(task.name(), interval);
+        self.tasks.push((task, Duration::from_secs(interval.max(1)), job));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.tasks.is_empty();
+    }
+
+    pub async fn run(self) {
+        let status = self.status;
+
+        join_all(self.tasks.into_iter().map(|(task, interval, job)| {
+            let status = status.clone();
+            async move {
+                let mut ticks = tokio::time::interval_at(Instant::now() + interval, interval);
+                loop {
+                    ticks.tick().await;
+
+                    let started = Utc::now();
+                    let result = job.run().await;
+                    if let Ok(summary) = &result {
+                        info!("Finished maintenance task {}: {}", task.name(), summary);
+                    }
+
+                    status.ran(task.name(), started, &result);
+                }
+            }
+        })).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+    use tokio::io::AsyncWriteExt;
+
+    use crate::meta::Metadata;
+    use crate::proto::model::Kind;
+
+    use super::*;
+
+    async fn inboxed(repository: &Repository, document: &[u8]) -> DocId {
+        let staging = repository.stage().await.unwrap();
+        let mut fragment = staging.write(Kind::Document).await.unwrap();
+        fragment.write_all(document).await.unwrap();
+        fragment.commit().await.unwrap();
+        Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        return *staging.create().await.unwrap().id();
+    }
+
+    #[tokio::test]
+    async fn test_duplicates() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let first = inboxed(&repository, b"my document").await;
+        let second = inboxed(&repository, b"my document").await;
+        inboxed(&repository, b"my other document").await;
+
+        let mut duplicates = Duplicates::new(repository.clone()).scan().await.unwrap();
+        assert_that!(duplicates).has_length(1);
+
+        duplicates[0].sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_that!(duplicates[0]).is_equal_to(expected);
+    }
+
+    #[test]
+    fn test_status() {
+        let status = Status::new();
+        status.scheduled("trash", 3600);
+        assert_that!(status.tasks()[0].last_run).is_none();
+
+        status.ran("trash", Utc::now(), &Ok(String::from("Purged 2 bundles")));
+        assert_that!(status.tasks()[0].result.as_deref()).is_equal_to(Some("Purged 2 bundles"));
+
+        status.ran("trash", Utc::now(), &Err(anyhow::anyhow!("Disk failed")));
+        assert_that!(status.tasks()[0].result).is_none();
+        assert_that!(status.tasks()[0].error.as_deref()).is_equal_to(Some("Disk failed"));
+        assert_that!(status.failed_count()).is_equal_to(1);
+    }
+
+    #[tokio::test]
+    async fn test_trash() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let summary = Trash::new(repository.clone()).run().await.unwrap();
+        assert_that!(summary).is_equal_to(String::from("No retention configured"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::Merge as Config;
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind};
+use crate::queue::Queue;
+use crate::repository::{Bundle, Inboxed, Repository, Staging};
+
+/// A document to be merged.
+pub struct Source {
+    pub id: DocId,
+    pub metadata: Metadata,
+
+    /// Path of the unencrypted document
+    pub document: PathBuf,
+
+    pub plaintext: String,
+}
+
+/// Merges documents which arrived as separate scans into a single document.
+///
+/// The documents are concatenated using `qpdf` and juiced again to render the previews. The extracted text of the
+/// sources is kept instead of the text extracted from the merged document, as it may have been OCRed.
+pub struct Merger {
+    config: Config,
+
+    queue: Queue,
+}
+
+/// Combines the metadata of the sources.
+///
+/// Labels, properties, shares and relations are united, where the first source wins for conflicting properties. All
+/// other attributes are taken from the first source having them, except for the due date, where the earliest one is
+/// kept. Relations between the sources are dropped, as they vanish with the merge.
+fn metadata(sources: &[Source]) -> Metadata {
+    let mut metadata = Metadata::new();
+
+    for source in sources {
+        let source = &source.metadata;
+
+        metadata.uploaded = metadata.uploaded.min(source.uploaded);
+        metadata.pages += source.pages;
+
+        metadata.labels.extend(source.labels.iter().cloned());
+        for (key, value) in &source.properties {
+            metadata.properties.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        metadata.shared.extend(source.shared.iter().cloned());
+        metadata.relations.extend(source.relations.iter().cloned());
+
+        metadata.title = metadata.title.or_else(|| source.title.clone());
+        metadata.owner = metadata.owner.or_else(|| source.owner.clone());
+        metadata.correspondent = metadata.correspondent.or_else(|| source.correspondent.clone());
+        metadata.belongs_to = metadata.belongs_to.or_else(|| source.belongs_to.clone());
+        metadata.filename = metadata.filename.or_else(|| source.filename.clone());
+
+        metadata.due = match (metadata.due, source.due) {
+            (Some(due), Some(other)) => Some(due.min(other)),
+            (due, other) => due.or(other),
+        };
+    }
+
+    metadata.relations.retain(|relation| !sources.iter().any(|source| source.id == relation.target));
+
+    return metadata;
+}
+
+/// Concatenates the text of the sources, separating them by a page break like `pdftotext` separates pages.
+fn plaintext(sources: &[Source]) -> String {
+    let mut plaintext = String::new();
+    for source in sources {
+        plaintext.push_str(&source.plaintext);
+        if !plaintext.ends_with('\x0c') {
+            plaintext.push('\x0c');
+        }
+    }
+
+    return plaintext;
+}
+
+impl Merger {
+    pub fn new(config: Config, queue: Queue) -> Self {
+        return Self { config, queue };
+    }
+
+    async fn concatenate(&self, sources: &[Source], output: PathBuf) -> Result<()> {
+        let mut command = Command::new(&self.config.qpdf);
+        command.arg("--empty").arg("--pages");
+        for source in sources {
+            command.arg(&source.document);
+        }
+        command.arg("--").arg(output);
+
+        debug!("Running {:?}", command);
+
+        let output = command
+            .stdin(Stdio::null())
+            .output().await
+            .with_context(|| format!("Error executing {}", self.config.qpdf))?;
+
+        if !output.status.success() {
+            bail!("{} failed: {}: {}", self.config.qpdf, output.status, String::from_utf8_lossy(&output.stderr));
+        }
+
+        return Ok(());
+    }
+
+    async fn prepare(&self, staging: &Bundle<'_, Staging>, sources: &[Source]) -> Result<()> {
+        self.concatenate(sources, staging.path_of(Kind::other("original.pdf"))).await?;
+
+        metadata(sources).save(staging.write(Kind::Metadata).await?).await?;
+
+        self.queue.juice(staging).await?;
+
+        let mut fragment = staging.write(Kind::Plaintext).await?;
+        fragment.write_all(plaintext(sources).as_bytes()).await?;
+        fragment.commit().await?;
+
+        return Ok(());
+    }
+
+    /// Merges the sources into a new bundle in the inbox, keeping the sources as they are.
+    pub async fn merge<'r>(&self, repository: &'r Repository, sources: &[Source]) -> Result<Bundle<'r, Inboxed>> {
+        let staging = repository.stage().await?;
+
+        info!("Merging {} documents into staging bundle {}", sources.len(), staging.id());
+
+        if let Err(err) = self.prepare(&staging, sources).await {
+            staging.delete().await?;
+            return Err(err);
+        }
+
+        return staging.create().await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use chrono::NaiveDate;
+    use spectral::prelude::*;
+
+    use crate::proto::model::{Label, PropertyValue, Relation, RelationKind};
+
+    use super::*;
+
+    fn source(metadata: Metadata, plaintext: &str) -> Source {
+        return Source {
+            id: DocId::random(),
+            metadata,
+            document: PathBuf::new(),
+            plaintext: plaintext.to_string(),
+        };
+    }
+
+    #[test]
+    fn test_metadata() {
+        let other = DocId::random();
+
+        let mut first = Metadata::new();
+        first.pages = 2;
+        first.labels.insert(Label::from("invoice"));
+        first.properties.insert("amount".to_string(), PropertyValue::from("23"));
+        first.due = Some(NaiveDate::from_ymd(2020, 12, 24));
+        let first = source(first, "");
+
+        let mut second = Metadata::new();
+        second.title = Some("Invoice".to_string());
+        second.pages = 1;
+        second.labels.insert(Label::from("paid"));
+        second.properties.insert("amount".to_string(), PropertyValue::from("42"));
+        second.properties.insert("customer".to_string(), PropertyValue::from("1234"));
+        second.due = Some(NaiveDate::from_ymd(2020, 12, 1));
+        second.relations.insert(Relation { kind: RelationKind::Related, target: first.id });
+        second.relations.insert(Relation { kind: RelationKind::Related, target: other });
+        let second = source(second, "");
+
+        let metadata = metadata(&[first, second]);
+        assert_that!(metadata.title.as_deref()).is_equal_to(Some("Invoice"));
+        assert_that!(metadata.pages).is_equal_to(3);
+        assert_that!(metadata.labels).is_equal_to(vec![Label::from("invoice"), Label::from("paid")].into_iter().collect::<HashSet<_>>());
+        assert_that!(metadata.properties.get("amount")).is_equal_to(Some(&PropertyValue::from("23")));
+        assert_that!(metadata.properties.get("customer")).is_equal_to(Some(&PropertyValue::from("1234")));
+        assert_that!(metadata.due).is_equal_to(Some(NaiveDate::from_ymd(2020, 12, 1)));
+        assert_that!(metadata.relations).is_equal_to(vec![Relation { kind: RelationKind::Related, target: other }].into_iter().collect::<HashSet<_>>());
+    }
+
+    #[test]
+    fn test_plaintext() {
+        let sources = [
+            source(Metadata::new(), "first page\x0csecond page\x0c"),
+            source(Metadata::new(), "third page"),
+        ];
+
+        assert_that!(plaintext(&sources)).is_equal_to("first page\x0csecond page\x0cthird page\x0c".to_string());
+    }
+}
@@ -1,14 +1,35 @@
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
-use crate::proto::model::Label;
+use crate::config::Defaults;
+use crate::proto::model::{Label, PropertyValue, Proposal, Relation};
+use crate::proto::query::{Comparison, Filter, Query};
+use crate::repository::Fragment;
+
+/// Version of the metadata schema, increased with every change requiring existing metadata to be upgraded
+pub const VERSION: u32 = 1;
+
+/// Upgrades metadata written with an older version of the schema to the next version.
+fn upgrade(version: u32, metadata: Value) -> Result<Value> {
+    return match version {
+        // Metadata from before versioning matches the first version
+        0 => Ok(metadata),
+        _ => bail!("Unknown metadata version {}", version),
+    };
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct Metadata {
+    /// Version of the schema, metadata from before versioning is version zero
+    #[serde(default)]
+    pub version: u32,
+
     pub uploaded: DateTime<Utc>,
     pub archived: Option<DateTime<Utc>>,
 
@@ -17,41 +38,292 @@ pub struct Metadata {
 
     pub labels: HashSet<Label>,
 
-    pub properties: HashMap<String, String>,
+    pub properties: HashMap<String, PropertyValue>,
+
+    /// The user who uploaded the document, documents without owner are visible to everyone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
+    /// Other users the document is shared with
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub shared: HashSet<String>,
+
+    /// Links to other documents
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub relations: HashSet<Relation>,
+
+    /// Name of the correspondent who sent the document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correspondent: Option<String>,
+
+    /// Name of the household member the document belongs to, i.e. whose passport or payslip it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub belongs_to: Option<String>,
+
+    /// Day the document has been issued, i.e. the date of an invoice or a letter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+
+    /// Deadline for acting on the document, i.e. paying a bill
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
+
+    /// Day the document is hidden from the inbox until, i.e. to deal with it after a vacation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snoozed: Option<NaiveDate>,
+
+    /// Name of the file the document was ingested from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+
+    /// The encryption domain the fragments of the document are encrypted for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+
+    /// Language of the document detected from its text, as ISO 639-3 code like `deu`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// OCR languages requested on upload, like `deu+eng`, the languages of the juicer apply if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<String>,
+
+    /// Mean confidence of the OCR in percent, only set for OCRed documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+
+    /// Mean confidence of the OCR per page in percent, unset for pages without recognized words
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub page_confidence: Vec<Option<u8>>,
+
+    /// Name of the juicer of a fallback chain which extracted the document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub juicer: Option<String>,
+
+    /// Metadata proposed by the rules while juicing, which must be accepted before archiving
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposal: Option<Proposal>,
+
+    /// Fields unknown to this version, i.e. written by a newer one, which are written back unchanged
+    #[serde(flatten)]
+    pub unknown: Map<String, Value>,
 }
 
 impl Metadata {
     pub fn new() -> Self {
         Self {
+            version: VERSION,
             uploaded: Utc::now(),
             archived: None,
             title: None,
             pages: 0,
             labels: HashSet::new(),
             properties: HashMap::new(),
+            owner: None,
+            shared: HashSet::new(),
+            relations: HashSet::new(),
+            correspondent: None,
+            belongs_to: None,
+            date: None,
+            due: None,
+            snoozed: None,
+            filename: None,
+            domain: None,
+            language: None,
+            languages: None,
+            confidence: None,
+            page_confidence: Vec::new(),
+            juicer: None,
+            proposal: None,
+            unknown: Map::new(),
+        }
+    }
+
+    /// Checks if the document is visible to the given user.
+    pub fn is_visible_to(&self, user: &str) -> bool {
+        return self.owner.as_deref().map_or(true, |owner| owner == user)
+            || self.shared.contains(user);
+    }
+
+    /// Applies the defaults of the source the document was ingested from.
+    ///
+    /// Labels are added and properties are set unless present. An owner given by the source replaces the uploader.
+    pub fn with_defaults(mut self, defaults: &Defaults) -> Self {
+        self.labels.extend(defaults.labels.iter().cloned());
+
+        for (key, value) in &defaults.properties {
+            self.properties.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+
+        if let Some(owner) = &defaults.owner {
+            self.owner = Some(owner.clone());
         }
+
+        return self;
     }
 
+    /// Records the differences to the given metadata as proposal instead of applying them.
+    ///
+    /// Only additions are proposed, as the rules never remove labels or properties. Nothing is proposed if the given
+    /// metadata does not differ.
+    pub fn propose(&mut self, proposed: Metadata) {
+        let proposal = Proposal {
+            title: proposed.title.filter(|title| self.title.as_ref() != Some(title)),
+            labels: proposed.labels.difference(&self.labels).cloned().collect(),
+            correspondent: proposed.correspondent.filter(|correspondent| self.correspondent.as_ref() != Some(correspondent)),
+            date: proposed.date.filter(|date| self.date.as_ref() != Some(date)),
+            properties: proposed.properties.into_iter()
+                .filter(|(key, value)| self.properties.get(key) != Some(value))
+                .collect(),
+        };
+
+        self.proposal = Some(proposal).filter(|proposal| !proposal.is_empty());
+    }
+
+    /// Applies the pending proposal, if any.
+    pub fn accept(&mut self) {
+        let proposal = match self.proposal.take() {
+            Some(proposal) => proposal,
+            None => return,
+        };
+
+        if proposal.title.is_some() {
+            self.title = proposal.title;
+        }
+
+        if proposal.correspondent.is_some() {
+            self.correspondent = proposal.correspondent;
+        }
+
+        if proposal.date.is_some() {
+            self.date = proposal.date;
+        }
+
+        self.labels.extend(proposal.labels);
+        self.properties.extend(proposal.properties);
+    }
+
+    /// Carries over what this version can not represent from the metadata replaced by this one.
+    ///
+    /// Metadata built from scratch, i.e. from a request, lacks the fields unknown to this version and the version of
+    /// a newer schema, which would be lost otherwise.
+    pub fn carry_over(&mut self, replaced: &Metadata) {
+        for (key, value) in &replaced.unknown {
+            self.unknown.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+
+        self.version = self.version.max(replaced.version);
+    }
+
+    /// Loads metadata written with any version of the schema.
+    ///
+    /// Metadata of older versions is upgraded while loading, so it is written with the current version the next time
+    /// it is saved. Metadata of newer versions is loaded as far as it is known.
     pub async fn load(mut r: impl AsyncRead + Unpin) -> Result<Self> {
         let mut buffer = Vec::new();
         r.read_to_end(&mut buffer).await?;
 
-        Ok(serde_json::from_slice(&buffer)?)
+        let mut metadata = serde_json::from_slice::<Value>(&buffer)?;
+
+        let version = metadata.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+        for version in version..VERSION {
+            metadata = upgrade(version, metadata)?;
+        }
+
+        let mut metadata = serde_json::from_value::<Self>(metadata)?;
+        metadata.version = metadata.version.max(VERSION);
+
+        return Ok(metadata);
     }
 
-    pub async fn save(&self, mut w: impl AsyncWrite + Unpin) -> Result<()> {
-        return Ok(w.write_all(&self.to_vec()?).await?);
+    /// Writes the metadata to the fragment and commits it.
+    pub async fn save(&self, mut fragment: Fragment) -> Result<()> {
+        fragment.write_all(&self.to_vec()?).await?;
+        fragment.commit().await?;
+
+        return Ok(());
     }
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
         return Ok(serde_json::to_vec_pretty(self)?);
     }
+
+    /// Evaluates a query against the metadata.
+    ///
+    /// As the metadata does not contain the document text, free text terms are matched against the title and the
+    /// property values.
+    pub fn matches(&self, query: &Query) -> bool {
+        return query.terms.iter()
+            .all(|term| self.matches_filter(&term.filter) != term.negated);
+    }
+
+    fn matches_filter(&self, filter: &Filter) -> bool {
+        let contains = |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+
+        return match filter {
+            Filter::Text(text) | Filter::Phrase(text) => {
+                self.title.as_deref().map_or(false, |title| contains(title, text))
+                    || self.properties.values().any(|value| contains(&value.to_string(), text))
+            }
+            Filter::Label(label) => self.labels.iter().any(|l| l.is_within(label)),
+            Filter::Title(text) => self.title.as_deref().map_or(false, |title| contains(title, text)),
+            Filter::Correspondent(name) => self.correspondent.as_deref().map_or(false, |c| c.eq_ignore_ascii_case(name)),
+            Filter::BelongsTo(name) => self.belongs_to.as_deref().map_or(false, |person| person.eq_ignore_ascii_case(name)),
+            Filter::Property { key, comparison, value: expected } => self.properties.get(key)
+                .map_or(false, |value| match value.compare(expected) {
+                    Some(ordering) => comparison.matches(&ordering, &Ordering::Equal),
+                    // Incomparable values only match on equal text, i.e. a string property which looks like a date
+                    None => *comparison == Comparison::Eq && value.to_string().eq_ignore_ascii_case(&expected.to_string()),
+                }),
+            Filter::Near { key, center, radius } => self.properties.get(key)
+                .and_then(|value| value.distance(center))
+                .map_or(false, |distance| distance <= *radius as f64),
+            Filter::Uploaded(comparison, date) => comparison.matches(&self.uploaded.naive_utc().date(), date),
+            Filter::Archived(comparison, date) => self.archived
+                .map_or(false, |archived| comparison.matches(&archived.naive_utc().date(), date)),
+            Filter::Dated(comparison, date) => self.date.map_or(false, |dated| comparison.matches(&dated, date)),
+            Filter::Due(comparison, date) => self.due.map_or(false, |due| comparison.matches(&due, date)),
+            Filter::Confidence(comparison, confidence) => self.confidence
+                .map_or(false, |actual| comparison.matches(&actual, confidence)),
+        };
+    }
 }
 
 impl Default for Metadata {
     fn default() -> Self { Self::new() }
 }
 
+impl From<crate::proto::model::Metadata> for Metadata {
+    fn from(metadata: crate::proto::model::Metadata) -> Self {
+        return Self {
+            version: VERSION,
+            uploaded: metadata.uploaded,
+            archived: metadata.archived,
+            title: metadata.title,
+            pages: metadata.pages,
+            labels: metadata.labels,
+            properties: metadata.properties,
+            owner: metadata.owner,
+            shared: metadata.shared,
+            relations: metadata.relations,
+            correspondent: metadata.correspondent,
+            belongs_to: metadata.belongs_to,
+            date: metadata.date,
+            due: metadata.due,
+            snoozed: metadata.snoozed,
+            filename: metadata.filename,
+            domain: metadata.domain,
+            language: metadata.language,
+            languages: metadata.languages,
+            confidence: metadata.confidence,
+            page_confidence: metadata.page_confidence,
+            juicer: metadata.juicer,
+            proposal: metadata.proposal,
+            unknown: Map::new(),
+        };
+    }
+}
+
 impl Into<crate::proto::model::Metadata> for Metadata {
     fn into(self) -> crate::proto::model::Metadata {
         return crate::proto::model::Metadata {
@@ -61,6 +333,22 @@ impl Into<crate::proto::model::Metadata> for Metadata {
             pages: self.pages,
             labels: self.labels,
             properties: self.properties,
+            owner: self.owner,
+            shared: self.shared,
+            relations: self.relations,
+            correspondent: self.correspondent,
+            belongs_to: self.belongs_to,
+            date: self.date,
+            due: self.due,
+            snoozed: self.snoozed,
+            filename: self.filename,
+            domain: self.domain,
+            language: self.language,
+            languages: self.languages,
+            confidence: self.confidence,
+            page_confidence: self.page_confidence,
+            juicer: self.juicer,
+            proposal: self.proposal,
         };
     }
 }
@@ -0,0 +1,122 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::juicer::{IMAGE_FORMATS, OFFICE_FORMATS};
+use crate::proto::model::Kind;
+
+/// MIME type of fragments in an unknown format
+pub const UNKNOWN: &str = "application/octet-stream";
+
+/// Number of leading bytes sufficient to detect all formats by their magic bytes
+pub const MAGIC_LENGTH: usize = 8;
+
+/// Formats of other fragments, like originals and logs, by file extension and MIME type.
+///
+/// Office and image formats are taken from the formats converted by the juicer.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("pdf", "application/pdf"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("log", "text/plain; charset=utf-8"),
+    ("json", "application/json"),
+    ("jsonl", "application/x-ndjson"),
+    ("xml", "application/xml"),
+    ("eml", "message/rfc822"),
+    ("gif", "image/gif"),
+];
+
+/// Formats of audio attached to documents, like voice memos, by file extension and MIME type.
+pub const AUDIO_FORMATS: &[(&str, &str)] = &[
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("weba", "audio/webm"),
+];
+
+/// Formats detected by the leading bytes of their contents.
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"%PDF-", "application/pdf"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"II*\x00", "image/tiff"),
+    (b"MM\x00*", "image/tiff"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"<?xml", "application/xml"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"OggS", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+    (b"fLaC", "audio/flac"),
+];
+
+/// Returns the MIME type of a fragment by its filename.
+pub fn of_name(name: &str) -> Option<&'static str> {
+    let extension = &name[name.rfind('.')? + 1..];
+
+    return EXTENSIONS.iter()
+        .chain(OFFICE_FORMATS)
+        .chain(IMAGE_FORMATS)
+        .chain(AUDIO_FORMATS)
+        .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+        .map(|(_, mimetype)| *mimetype);
+}
+
+/// Returns the MIME type of a fragment by its kind, or by the file extension of other fragments.
+pub fn of_kind(kind: &Kind) -> Option<&'static str> {
+    return match kind {
+        Kind::Document => Some("application/pdf"),
+        Kind::Preview | Kind::Page(_) | Kind::Thumbnail => Some("image/png"),
+        Kind::Plaintext => Some("text/plain; charset=utf-8"),
+        Kind::Metadata => Some("application/json"),
+        Kind::Other { name } => of_name(&name.to_string_lossy()),
+    };
+}
+
+/// Detects the MIME type of contents by their leading bytes.
+pub fn detect(data: &[u8]) -> Option<&'static str> {
+    return MAGIC.iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|(_, mimetype)| *mimetype);
+}
+
+/// Returns the MIME type of a fragment, detecting it from the leading bytes of the contents if neither the kind nor
+/// the file extension tell.
+pub fn mimetype(kind: &Kind, head: &[u8]) -> &'static str {
+    return of_kind(kind)
+        .or_else(|| detect(head))
+        .unwrap_or(UNKNOWN);
+}
+
+/// Reads the leading bytes required for detection.
+///
+/// The returned bytes must be prepended when passing on the rest of the contents.
+pub async fn head(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(MAGIC_LENGTH);
+    reader.take(MAGIC_LENGTH as u64).read_to_end(&mut head).await?;
+
+    return Ok(head);
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_mimetype() {
+        assert_that!(mimetype(&Kind::Document, b"")).is_equal_to("application/pdf");
+        assert_that!(mimetype(&Kind::Page(1), b"")).is_equal_to("image/png");
+        assert_that!(mimetype(&Kind::other("original.docx"), b"PK\x03\x04"))
+            .is_equal_to("application/vnd.openxmlformats-officedocument.wordprocessingml.document");
+        assert_that!(mimetype(&Kind::other("original.JPG"), b"")).is_equal_to("image/jpeg");
+        assert_that!(mimetype(&Kind::other("juicer.log"), b"")).is_equal_to("text/plain; charset=utf-8");
+        assert_that!(mimetype(&Kind::other("signature"), b"%PDF-1.7")).is_equal_to("application/pdf");
+        assert_that!(mimetype(&Kind::other("attachment.bin"), b"\x89PNG\r\n\x1a\n")).is_equal_to("image/png");
+        assert_that!(mimetype(&Kind::other("memo.opus"), b"")).is_equal_to("audio/ogg");
+        assert_that!(mimetype(&Kind::other("memo"), b"OggS\x00\x02")).is_equal_to("audio/ogg");
+        assert_that!(mimetype(&Kind::other("trashed"), b"2020")).is_equal_to(UNKNOWN);
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde_json::json;
+use tokio::sync::broadcast::RecvError;
+
+use crate::config::{ChatChannel, MatrixChannel, NotificationEvent, Notifications as Config, SlackChannel, TelegramChannel};
+use crate::plugins::{Notification, Notifier};
+use crate::proto::model::DocId;
+use crate::repository::{Event, Repository};
+use crate::status::Status;
+
+/// Renders a notification as a single chat message.
+fn message(notification: &Notification) -> String {
+    return match notification.body.trim() {
+        "" => notification.subject.clone(),
+        body => format!("{}\n\n{}", notification.subject, body),
+    };
+}
+
+/// Posts notifications to a Matrix room.
+pub struct Matrix {
+    config: MatrixChannel,
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl Notifier for Matrix {
+    fn name(&self) -> &str { "matrix" }
+
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        let url = format!("{}/_matrix/client/r0/rooms/{}/send/m.room.message/{}",
+                          self.config.homeserver.trim_end_matches('/'),
+                          utf8_percent_encode(&self.config.room, NON_ALPHANUMERIC),
+                          uuid::Uuid::new_v4());
+
+        self.client.put(&url)
+            .bearer_auth(&self.config.token)
+            .json(&json!({
+                "msgtype": "m.text",
+                "body": message(notification),
+            }))
+            .send().await?
+            .error_for_status()?;
+
+        return Ok(());
+    }
+}
+
+/// Posts notifications to a Slack channel using an incoming webhook.
+pub struct Slack {
+    config: SlackChannel,
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl Notifier for Slack {
+    fn name(&self) -> &str { "slack" }
+
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        self.client.post(&self.config.webhook)
+            .json(&json!({
+                "text": message(notification),
+            }))
+            .send().await?
+            .error_for_status()?;
+
+        return Ok(());
+    }
+}
+
+/// Posts notifications to a Telegram chat as bot.
+pub struct Telegram {
+    config: TelegramChannel,
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    fn name(&self) -> &str { "telegram" }
+
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        self.client.post(&format!("https://api.telegram.org/bot{}/sendMessage", self.config.token))
+            .json(&json!({
+                "chat_id": self.config.chat,
+                "text": message(notification),
+            }))
+            .send().await?
+            .error_for_status()?;
+
+        return Ok(());
+    }
+}
+
+/// Creates the notifier posting to a configured chat channel.
+pub fn channel(config: ChatChannel) -> Result<Arc<dyn Notifier>> {
+    let client = reqwest::Client::builder().build()?;
+
+    return Ok(match config {
+        ChatChannel::Matrix(config) => Arc::new(Matrix { config, client }),
+        ChatChannel::Slack(config) => Arc::new(Slack { config, client }),
+        ChatChannel::Telegram(config) => Arc::new(Telegram { config, client }),
+    });
+}
+
+/// Creates the notifiers of the configured channels along with the events enabled for them.
+pub fn channels(config: &Config) -> Result<Vec<(HashSet<NotificationEvent>, Arc<dyn Notifier>)>> {
+    return config.channels.iter()
+        .map(|config| Ok((config.events.clone(), channel(config.channel.clone())?)))
+        .collect();
+}
+
+/// The events collected since the last announcement.
+#[derive(Debug, Default)]
+pub struct Pending {
+    pub inboxed: Vec<DocId>,
+    pub quarantined: Vec<DocId>,
+
+    /// Messages of the failed juicing jobs
+    pub failures: Vec<String>,
+}
+
+/// Announces new documents in the inbox and failures of the ingest pipeline in chat channels.
+///
+/// Events are collected for an interval and announced at once, so a batch of uploads results in a single message like
+/// "3 new documents in your inbox". Each channel announces the events enabled for it.
+///
+/// The channels can be replaced while running, whereas the interval is fixed at startup.
+pub struct Notifications {
+    interval: Duration,
+
+    channels: RwLock<Vec<(HashSet<NotificationEvent>, Arc<dyn Notifier>)>>,
+
+    repository: Repository,
+
+    status: Arc<Status>,
+}
+
+impl Notifications {
+    pub fn from_config(config: Config, repository: Repository, status: Arc<Status>) -> Result<Self> {
+        let channels = channels(&config)?;
+
+        return Ok(Self::new(Duration::from_secs(config.interval), channels, repository, status));
+    }
+
+    pub fn new(interval: Duration,
+               channels: Vec<(HashSet<NotificationEvent>, Arc<dyn Notifier>)>,
+               repository: Repository,
+               status: Arc<Status>) -> Self {
+        return Self { interval, channels: RwLock::new(channels), repository, status };
+    }
+
+    /// Replaces the channels, i.e. after the configuration has been reloaded.
+    ///
+    /// Pending events are announced in the new channels.
+    pub fn reconfigure(&self, channels: Vec<(HashSet<NotificationEvent>, Arc<dyn Notifier>)>) {
+        *self.channels.write().expect("Channels poisoned") = channels;
+    }
+
+    /// Returns the channels announcing an event, i.e. to pass them to the reminders.
+    pub fn notifiers(&self, event: NotificationEvent) -> Vec<Arc<dyn Notifier>> {
+        return self.channels.read().expect("Channels poisoned").iter()
+            .filter(|(events, _)| events.contains(&event))
+            .map(|(_, notifier)| notifier.clone())
+            .collect();
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut events = self.repository.subscribe();
+        let mut interval = tokio::time::interval(self.interval);
+
+        let mut pending = Pending::default();
+        let mut since = Utc::now();
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(Event::Inboxed(id)) => pending.inboxed.push(id),
+                    Ok(Event::Quarantined(id)) => pending.quarantined.push(id),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => warn!("Notifications missed {} repository events", missed),
+                    Err(RecvError::Closed) => break,
+                },
+
+                _ = interval.tick() => {
+                    pending.failures = self.failures(since);
+                    since = Utc::now();
+
+                    let announced = self.announce(std::mem::take(&mut pending)).await;
+                    if announced > 0 {
+                        info!("Sent {} notifications", announced);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the messages of juicing jobs failed since the given time.
+    fn failures(&self, since: DateTime<Utc>) -> Vec<String> {
+        return self.status.errors().into_iter()
+            .filter(|error| error.source == "juicer" && error.time > since)
+            .map(|error| error.message)
+            .rev()
+            .collect();
+    }
+
+    /// Announces the pending events in the channels enabled for them and returns the number of messages sent.
+    ///
+    /// Failing channels are reported, but do not keep the other channels from being notified.
+    pub async fn announce(&self, pending: Pending) -> usize {
+        let mut notifications = Vec::new();
+
+        if !pending.inboxed.is_empty() {
+            notifications.push((NotificationEvent::Inbox, self.inbox(pending.inboxed).await));
+        }
+
+        if !pending.failures.is_empty() {
+            notifications.push((NotificationEvent::Failures, Notification {
+                subject: format!("Juicing failed for {} documents", pending.failures.len()),
+                body: pending.failures.join("\n"),
+                docs: vec![],
+            }));
+        }
+
+        if !pending.quarantined.is_empty() {
+            notifications.push((NotificationEvent::Quarantine, Notification {
+                subject: format!("{} documents moved to the quarantine", pending.quarantined.len()),
+                body: pending.quarantined.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"),
+                docs: pending.quarantined,
+            }));
+        }
+
+        let mut sent = 0;
+        for (event, notification) in &notifications {
+            for notifier in self.notifiers(*event) {
+                let result = notifier.notify(notification).await
+                    .with_context(|| format!("Notifier {} failed", notifier.name()));
+
+                match result {
+                    Ok(()) => sent += 1,
+                    Err(err) => {
+                        error!("Failed to send notification: {:#}", err);
+                        self.status.failed("notifications", &err);
+                    }
+                }
+            }
+        }
+
+        return sent;
+    }
+
+    /// Describes the documents which arrived in the inbox, skipping the ones which are not there anymore.
+    async fn inbox(&self, ids: Vec<DocId>) -> Notification {
+        let mut body = String::new();
+        let mut docs = Vec::new();
+        for id in ids {
+            let bundle = match self.repository.inbox().get(id).await {
+                Some(bundle) => bundle,
+                None => continue,
+            };
+
+            let title = bundle.read_metadata().await.ok()
+                .and_then(|metadata| metadata.title.or(metadata.filename))
+                .unwrap_or_else(|| String::from("Untitled"));
+
+            let _ = writeln!(body, "{} ({})", title, id);
+            docs.push(id);
+        }
+
+        return Notification {
+            subject: format!("{} new documents in your inbox", docs.len()),
+            body,
+            docs,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use spectral::prelude::*;
+
+    use crate::meta::Metadata;
+    use crate::proto::model::Kind;
+
+    use super::*;
+
+    /// Records the subjects of the notifications.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl Notifier for Recorder {
+        fn name(&self) -> &str { "recorder" }
+
+        async fn notify(&self, notification: &Notification) -> Result<()> {
+            self.0.lock().unwrap().push(notification.subject.clone());
+            return Ok(());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_announce() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let mut inboxed = Vec::new();
+        for title in &["Invoice", "Letter", "Contract"] {
+            let staging = repository.stage().await.unwrap();
+            Metadata {
+                title: Some(title.to_string()),
+                ..Metadata::new()
+            }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            inboxed.push(*staging.create().await.unwrap().id());
+        }
+
+        let inbox = Arc::new(Recorder::default());
+        let failures = Arc::new(Recorder::default());
+
+        let notifications = Notifications::new(Duration::from_secs(60), vec![
+            (vec![NotificationEvent::Inbox].into_iter().collect(), inbox.clone() as Arc<dyn Notifier>),
+            (vec![NotificationEvent::Failures, NotificationEvent::Quarantine].into_iter().collect(), failures.clone() as Arc<dyn Notifier>),
+        ], repository, Arc::new(Status::new()));
+
+        let sent = notifications.announce(Pending {
+            inboxed: inboxed.clone(),
+            quarantined: vec![],
+            failures: vec![String::from("Juicer exited with 1")],
+        }).await;
+
+        assert_that!(sent).is_equal_to(2);
+        assert_that!(*inbox.0.lock().unwrap()).is_equal_to(vec![String::from("3 new documents in your inbox")]);
+        assert_that!(*failures.0.lock().unwrap()).is_equal_to(vec![String::from("Juicing failed for 1 documents")]);
+
+        // Nothing is sent without any events
+        assert_that!(notifications.announce(Pending::default()).await).is_equal_to(0);
+
+        // Reconfigured channels take over
+        notifications.reconfigure(vec![]);
+        assert_that!(notifications.announce(Pending {
+            inboxed: inboxed.clone(),
+            quarantined: vec![],
+            failures: vec![],
+        }).await).is_equal_to(0);
+    }
+
+    #[test]
+    fn test_message() {
+        assert_that!(message(&Notification {
+            subject: String::from("1 new documents in your inbox"),
+            body: String::from("Invoice (abc)\n"),
+            docs: vec![],
+        })).is_equal_to(String::from("1 new documents in your inbox\n\nInvoice (abc)"));
+
+        assert_that!(message(&Notification {
+            subject: String::from("Subject only"),
+            body: String::new(),
+            docs: vec![],
+        })).is_equal_to(String::from("Subject only"));
+    }
+}
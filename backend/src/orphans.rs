@@ -0,0 +1,144 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{error, info, warn};
+
+use crate::config::{OrphanAction, Orphans as Config};
+use crate::maintenance::Task;
+use crate::proto::model::Kind;
+use crate::queue::Job;
+use crate::repository::{Bundle, Repository, Staging};
+use crate::status::Status;
+use crate::uploads::{Scan, Upload};
+
+/// Collects bundles left behind in the staging area.
+///
+/// Staged bundles are either written and moved to the inbox right away, queued for juicing or receiving a resumable
+/// upload or the pages of a scan. If the process dies in between, the bundle is never touched again. Bundles which have not changed for the
+/// configured age are moved to the quarantine or deleted. Queued bundles are never collected, as the queue resumes
+/// them after a restart and keeps failed ones for inspection.
+pub struct Collector {
+    config: Config,
+
+    repository: Repository,
+
+    status: Arc<Status>,
+}
+
+impl Collector {
+    pub fn from_config(config: Config, repository: Repository, status: Arc<Status>) -> Self {
+        return Self { config, repository, status };
+    }
+
+    /// Returns the time of the last change to the bundle or any of its fragments.
+    async fn touched(bundle: &Bundle<'_, Staging>) -> Result<SystemTime> {
+        let mut touched = tokio::fs::metadata(bundle.path()).await?.modified()?;
+
+        let mut entries = tokio::fs::read_dir(bundle.path()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            touched = touched.max(entry.metadata().await?.modified()?);
+        }
+
+        return Ok(touched);
+    }
+
+    /// Moves or deletes all orphaned bundles and returns how many have been collected.
+    pub async fn collect(&self) -> Result<usize> {
+        let deadline = SystemTime::now() - Duration::from_secs(self.config.age);
+
+        let mut collected = 0;
+        for bundle in self.repository.staging().list().await? {
+            if tokio::fs::metadata(bundle.path_of(Kind::other(Job::FRAGMENT))).await.is_ok() {
+                continue;
+            }
+
+            let touched = Self::touched(&bundle).await?;
+            if touched >= deadline {
+                continue;
+            }
+
+            let since = chrono::DateTime::<chrono::Utc>::from(touched);
+            let reason = match (Upload::load(&bundle).await?, Scan::load(&bundle).await?) {
+                (Some(upload), _) => format!("Upload by {} abandoned since {}", upload.owner, since),
+                (None, Some(scan)) => format!("Scan by {} abandoned since {}", scan.owner, since),
+                (None, None) => format!("Orphaned in staging since {}", since),
+            };
+
+            warn!("Collecting staged bundle {}: {}", bundle.id(), reason);
+
+            match self.config.action {
+                OrphanAction::Quarantine => { bundle.quarantine(reason).await?; }
+                OrphanAction::Delete => { bundle.delete().await?; }
+            }
+
+            collected += 1;
+        }
+
+        return Ok(collected);
+    }
+
+    /// Collects orphaned bundles on startup and periodically afterwards.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval.max(1)));
+        loop {
+            interval.tick().await;
+
+            match self.collect().await {
+                Ok(0) => {}
+                Ok(collected) => info!("Collected {} orphaned staged bundles", collected),
+                Err(err) => {
+                    error!("Failed to collect orphaned staged bundles: {:#}", err);
+                    self.status.failed("orphans", &err);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Task for Collector {
+    async fn run(&self) -> Result<String> {
+        return Ok(format!("Collected {} orphaned staged bundles", self.collect().await?));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let orphan = *repository.stage().await.unwrap().id();
+
+        let queued = repository.stage().await.unwrap();
+        Job::queued_now().save(&queued).await.unwrap();
+        let queued = *queued.id();
+
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+
+        let collector = Collector::from_config(Config { age: 0, ..Config::default() },
+                                               repository.clone(),
+                                               Arc::new(Status::new()));
+
+        assert_that!(collector.collect().await.unwrap()).is_equal_to(1);
+
+        assert_that!(repository.staging().get(orphan).await).is_none();
+        assert_that!(repository.staging().get(queued).await).is_some();
+
+        let quarantined = repository.quarantine().get(orphan).await.unwrap();
+        assert_that!(quarantined.reason().await.unwrap().reason).starts_with("Orphaned in staging");
+
+        // Recently changed bundles are kept
+        let recent = *repository.stage().await.unwrap().id();
+        let collector = Collector::from_config(Config::default(), repository.clone(), Arc::new(Status::new()));
+
+        assert_that!(collector.collect().await.unwrap()).is_equal_to(0);
+        assert_that!(repository.staging().get(recent).await).is_some();
+    }
+}
@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+
+/// A period of calendar time, like the notice period of a contract or the duration of a warranty.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Period {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+    Years(u32),
+}
+
+/// Shifts a day by a number of months, ending at the end of shorter months if the day does not exist there.
+fn shift_months(date: NaiveDate, months: i32) -> NaiveDate {
+    let months = date.year() * 12 + date.month0() as i32 + months;
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+
+    return (1..=date.day()).rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .expect("Invalid date");
+}
+
+impl Period {
+    /// The day the period starts if it ends at the given day.
+    pub fn before(&self, end: NaiveDate) -> NaiveDate {
+        return match *self {
+            Self::Days(days) => end - Duration::days(days.into()),
+            Self::Weeks(weeks) => end - Duration::weeks(weeks.into()),
+            Self::Months(months) => shift_months(end, -(months as i32)),
+            Self::Years(years) => shift_months(end, -(years as i32) * 12),
+        };
+    }
+
+    /// The day the period ends if it starts at the given day.
+    pub fn after(&self, start: NaiveDate) -> NaiveDate {
+        return match *self {
+            Self::Days(days) => start + Duration::days(days.into()),
+            Self::Weeks(weeks) => start + Duration::weeks(weeks.into()),
+            Self::Months(months) => shift_months(start, months as i32),
+            Self::Years(years) => shift_months(start, years as i32 * 12),
+        };
+    }
+}
+
+impl FromStr for Period {
+    type Err = Error;
+
+    /// Parses a period like `3 months`, a plain number is taken as months.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split_whitespace();
+
+        let count = parts.next()
+            .ok_or_else(|| anyhow!("Empty period"))?
+            .parse::<u32>()?;
+
+        return Ok(match parts.next().map(str::to_lowercase).as_deref() {
+            Some("day") | Some("days") => Self::Days(count),
+            Some("week") | Some("weeks") => Self::Weeks(count),
+            Some("month") | Some("months") | None => Self::Months(count),
+            Some("year") | Some("years") => Self::Years(count),
+            Some(unit) => bail!("Unknown unit of period: {}", unit),
+        });
+    }
+}
+
+impl std::fmt::Display for Period {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (count, unit) = match *self {
+            Self::Days(count) => (count, "day"),
+            Self::Weeks(count) => (count, "week"),
+            Self::Months(count) => (count, "month"),
+            Self::Years(count) => (count, "year"),
+        };
+
+        return write!(f, "{} {}{}", count, unit, if count == 1 { "" } else { "s" });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_that!("3 months".parse::<Period>().unwrap()).is_equal_to(Period::Months(3));
+        assert_that!("4 Weeks".parse::<Period>().unwrap()).is_equal_to(Period::Weeks(4));
+        assert_that!("1 day".parse::<Period>().unwrap()).is_equal_to(Period::Days(1));
+        assert_that!("2 years".parse::<Period>().unwrap()).is_equal_to(Period::Years(2));
+        assert_that!("2".parse::<Period>().unwrap()).is_equal_to(Period::Months(2));
+        assert_that!("2 fortnights".parse::<Period>().is_err()).is_true();
+
+        assert_that!(Period::Years(2).to_string().parse::<Period>().unwrap()).is_equal_to(Period::Years(2));
+    }
+
+    #[test]
+    fn test_shift() {
+        let day = NaiveDate::from_ymd(2020, 2, 29);
+
+        assert_that!(Period::Years(1).after(day)).is_equal_to(NaiveDate::from_ymd(2021, 2, 28));
+        assert_that!(Period::Months(1).before(NaiveDate::from_ymd(2021, 3, 31))).is_equal_to(NaiveDate::from_ymd(2021, 2, 28));
+        assert_that!(Period::Weeks(2).after(day)).is_equal_to(NaiveDate::from_ymd(2020, 3, 14));
+    }
+}
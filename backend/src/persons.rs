@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+
+use crate::proto::model::Person;
+
+/// Repository-wide registry of the household members documents belong to.
+///
+/// Persons are identified by their unique name, which is referenced by the metadata of their documents.
+pub struct Persons {
+    path: PathBuf,
+    persons: RwLock<BTreeMap<String, Person>>,
+}
+
+impl Persons {
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let persons = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        return Ok(Self { path, persons: RwLock::new(persons) });
+    }
+
+    async fn save(&self, persons: &BTreeMap<String, Person>) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(persons)?).await?;
+        return Ok(());
+    }
+
+    pub async fn list(&self) -> Vec<Person> {
+        return self.persons.read().await.values().cloned().collect();
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Person> {
+        return self.persons.read().await.get(name).cloned();
+    }
+
+    /// Registers a person or replaces the existing one with the same name.
+    pub async fn define(&self, person: Person) -> Result<()> {
+        let mut persons = self.persons.write().await;
+        persons.insert(person.name.clone(), person);
+        return self.save(&persons).await;
+    }
+
+    /// Removes a person from the registry and returns whether it was registered.
+    ///
+    /// Documents belonging to the person are not changed.
+    pub async fn remove(&self, name: &str) -> Result<bool> {
+        let mut persons = self.persons.write().await;
+        if persons.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&persons).await?;
+
+        return Ok(true);
+    }
+}
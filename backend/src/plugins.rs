@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{error, info};
+
+pub use adacta_plugin::{Document, Enrichment, Notification, Notifier, Plugins, Processor, Sink, Source};
+
+use crate::ingest;
+use crate::meta::Metadata;
+use crate::proto::model::DocId;
+use crate::queue::Queue;
+use crate::status::Status;
+
+/// Returns the plugins compiled into this build.
+///
+/// Custom builds add the crates of their plugins as dependencies and register them here, like
+/// `plugins.source(my_source::Source::new())`.
+pub fn plugins() -> Plugins {
+    return Plugins::default();
+}
+
+/// Ingests the documents submitted by sources into the inbox.
+pub struct Ingester(Queue);
+
+#[async_trait]
+impl Sink for Ingester {
+    async fn submit(&self, document: Document) -> Result<DocId> {
+        let mut metadata = Metadata::new();
+        metadata.filename = document.filename;
+        metadata.labels = document.labels;
+        metadata.properties = document.properties;
+
+        return ingest::ingest(&self.0, document.data.as_slice(), &document.extension, metadata).await;
+    }
+}
+
+/// Runs all sources, ingesting their documents into the repository of the queue.
+pub fn run_sources(sources: Vec<Arc<dyn Source>>, queue: Queue, status: Arc<Status>) {
+    let sink: Arc<dyn Sink> = Arc::new(Ingester(queue));
+
+    for source in sources {
+        let sink = sink.clone();
+        let status = status.clone();
+
+        tokio::spawn(async move {
+            info!("Running source {}", source.name());
+
+            if let Err(err) = source.run(sink).await {
+                error!("Source {} failed: {:#}", source.name(), err);
+                status.failed("sources", &err);
+            }
+        });
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+/// Per-user key-value store for UI preferences.
+///
+/// The preferences of each user are stored as a JSON object in a file of their own.
+pub struct Preferences {
+    path: PathBuf,
+
+    /// Serializes read-modify-write cycles
+    lock: Mutex<()>,
+}
+
+pub type Values = BTreeMap<String, Value>;
+
+impl Preferences {
+    pub async fn with_path(path: PathBuf) -> Result<Self> {
+        tokio::fs::create_dir_all(&path).await?;
+
+        return Ok(Self { path, lock: Mutex::new(()) });
+    }
+
+    fn path_of(&self, user: &str) -> PathBuf {
+        // Usernames are hex encoded to get safe filenames
+        return self.path.join(format!("{}.json", hex::encode(user)));
+    }
+
+    async fn load(&self, user: &str) -> Result<Values> {
+        return match tokio::fs::read(self.path_of(user)).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Values::new()),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    async fn save(&self, user: &str, values: &Values) -> Result<()> {
+        tokio::fs::write(self.path_of(user), serde_json::to_vec_pretty(values)?).await?;
+        return Ok(());
+    }
+
+    pub async fn get(&self, user: &str) -> Result<Values> {
+        let _lock = self.lock.lock().await;
+        return self.load(user).await;
+    }
+
+    pub async fn set(&self, user: &str, key: String, value: Value) -> Result<()> {
+        let _lock = self.lock.lock().await;
+
+        let mut values = self.load(user).await?;
+        values.insert(key, value);
+
+        return self.save(user, &values).await;
+    }
+
+    /// Replaces all preferences of a user.
+    pub async fn replace(&self, user: &str, values: Values) -> Result<()> {
+        let _lock = self.lock.lock().await;
+        return self.save(user, &values).await;
+    }
+
+    /// Removes a preference and returns whether it existed.
+    pub async fn remove(&self, user: &str, key: &str) -> Result<bool> {
+        let _lock = self.lock.lock().await;
+
+        let mut values = self.load(user).await?;
+        if values.remove(key).is_none() {
+            return Ok(false);
+        }
+
+        self.save(user, &values).await?;
+
+        return Ok(true);
+    }
+}
@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::future::try_join;
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::broadcast::RecvError;
+use tokio::sync::Semaphore;
+
+use crate::config::Previews as Config;
+use crate::proto::model::{DocId, Kind};
+use crate::repository::{Bundle, BundleState, Event, Repository, sha256};
+
+/// Requested sizes are rounded up to a multiple of this, so clients asking for arbitrary sizes share cached variants
+const SIZE_STEP: u32 = 64;
+
+/// Renders previews of pages in the requested size on demand and caches them.
+///
+/// Cached previews are keyed by the checksum of the document they are rendered from, so previews of a replaced
+/// document are never served but rendered again. Stale variants are removed when a preview of the current document is
+/// cached, and all variants of a bundle are removed once it is trashed.
+#[derive(Clone)]
+pub struct Previews {
+    config: Config,
+
+    path: PathBuf,
+
+    permits: Arc<Semaphore>,
+}
+
+impl Previews {
+    pub fn from_config(config: Config, repository: &Repository) -> Self {
+        let path = config.path.as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| repository.path().join("cache").join("previews"));
+        let permits = Arc::new(Semaphore::new(config.concurrency.max(1)));
+
+        return Self { config, path, permits };
+    }
+
+    /// Rounds the requested size up to the next step, returning `None` if it exceeds the configured maximum.
+    pub fn size(&self, size: u32) -> Option<u32> {
+        let size = ((size.max(1) + SIZE_STEP - 1) / SIZE_STEP) * SIZE_STEP;
+        return Some(size).filter(|size| *size <= self.config.max_size.max(SIZE_STEP));
+    }
+
+    fn directory(&self, id: &DocId) -> PathBuf {
+        return self.path.join(id.to_string());
+    }
+
+    /// Returns the checksum the previews of the current document are keyed by.
+    async fn checksum<S: BundleState>(bundle: &Bundle<'_, S>) -> Result<String> {
+        let path = bundle.path_of(Kind::Document);
+
+        let recorded = match (bundle.read_checksums().await?, path.file_name()) {
+            (Some(checksums), Some(name)) => checksums.get(&name.to_string_lossy()).map(String::from),
+            _ => None,
+        };
+
+        return match recorded {
+            Some(checksum) => Ok(checksum),
+            None => sha256(&path).await,
+        };
+    }
+
+    /// Returns the tag identifying the variant of a page preview rendered from the current document.
+    async fn tag<S: BundleState>(bundle: &Bundle<'_, S>, page: u32, size: u32) -> Result<(String, String)> {
+        let checksum = Self::checksum(bundle).await?;
+        let prefix = checksum[..16.min(checksum.len())].to_string();
+        let tag = format!("{}-{}-{}", prefix, page, size);
+
+        return Ok((prefix, tag));
+    }
+
+    /// Returns the preview of a page, counting from one, with the longer side scaled to a size returned by `size`.
+    ///
+    /// The returned tag identifies the rendered variant and changes with the document. The document is rendered as
+    /// read, so the caller must ensure the bundle is not encrypted.
+    pub async fn preview<S: BundleState>(&self, bundle: &Bundle<'_, S>, page: u32, size: u32) -> Result<(String, Vec<u8>)> {
+        let (prefix, tag) = Self::tag(bundle, page, size).await?;
+
+        let directory = self.directory(bundle.id());
+        let path = directory.join(format!("{}.png", tag));
+
+        match tokio::fs::read(&path).await {
+            Ok(data) => return Ok((tag, data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        // The document may be stored compressed, so it is rendered from the contents as read
+        let mut document = Vec::new();
+        bundle.read(Kind::Document).await?
+            .ok_or_else(|| anyhow!("Document missing in bundle: {}", bundle.id()))?
+            .read_to_end(&mut document).await?;
+
+        let data = {
+            let _permit = self.permits.acquire().await;
+            self.render(&document, page, size).await?
+        };
+
+        tokio::fs::create_dir_all(&directory).await?;
+        self.prune(&directory, &prefix).await?;
+
+        // Concurrent requests for the same variant race for the rename, which is atomic
+        let temp = directory.join(format!(".{}.{}", tag, DocId::random()));
+        tokio::fs::write(&temp, &data).await?;
+        tokio::fs::rename(&temp, &path).await?;
+
+        debug!("Cached preview {} of bundle {}", tag, bundle.id());
+
+        return Ok((tag, data));
+    }
+
+    /// Returns the preview of a page of an encrypted bundle, rendered from the given decrypted document.
+    ///
+    /// These previews are never cached, as the cache is not encrypted.
+    pub async fn preview_decrypted<S: BundleState>(&self, bundle: &Bundle<'_, S>, document: &[u8], page: u32, size: u32) -> Result<(String, Vec<u8>)> {
+        let (_, tag) = Self::tag(bundle, page, size).await?;
+
+        let data = {
+            let _permit = self.permits.acquire().await;
+            self.render(document, page, size).await?
+        };
+
+        return Ok((tag, data));
+    }
+
+    /// Renders a page of the given document, which is passed to the renderer on stdin.
+    async fn render(&self, document: &[u8], page: u32, size: u32) -> Result<Vec<u8>> {
+        let page = page.to_string();
+        let size = size.to_string();
+        let args = ["-png", "-f", &page, "-l", &page, "-singlefile", "-scale-to", &size, "-"];
+
+        debug!("Running {} {:?}", self.config.pdftoppm, args);
+
+        // Without an output root, pdftoppm writes the single rendered page to stdout
+        let mut child = Command::new(&self.config.pdftoppm)
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Error executing {}", self.config.pdftoppm))?;
+
+        let mut stdin = child.stdin.take().expect("No stdin");
+        let input = async move {
+            // A renderer exiting early fails on its own, which is reported below
+            return match stdin.write_all(document).await {
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+                result => result,
+            };
+        };
+
+        let (_, output) = try_join(input, child.wait_with_output()).await
+            .with_context(|| format!("Error executing {}", self.config.pdftoppm))?;
+
+        if !output.status.success() {
+            bail!("{} failed: {}: {}", self.config.pdftoppm, output.status, String::from_utf8_lossy(&output.stderr));
+        }
+
+        return Ok(output.stdout);
+    }
+
+    /// Removes the variants rendered from other versions of the document.
+    async fn prune(&self, directory: &Path, current: &str) -> Result<()> {
+        let mut entries = tokio::fs::read_dir(directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(current) && !name.starts_with('.') {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Removes all cached variants of a bundle.
+    pub async fn evict(&self, id: &DocId) -> Result<()> {
+        return match tokio::fs::remove_dir_all(self.directory(id)).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    /// Follows the repository events and evicts the previews of trashed bundles.
+    pub async fn run(self, repository: Repository) {
+        let mut events = repository.subscribe();
+
+        loop {
+            let id = match events.recv().await {
+                Ok(Event::Trashed(id)) | Ok(Event::Purged(id)) | Ok(Event::Erased(id)) => id,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Preview cache missed {} repository events", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            match self.evict(&id).await {
+                Ok(()) => info!("Evicted cached previews of bundle {}", id),
+                Err(err) => error!("Failed to evict cached previews of bundle {}: {:#}", id, err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_size() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let previews = Previews::from_config(Config::default(), &repository);
+
+        assert_that!(previews.size(1)).is_equal_to(Some(64));
+        assert_that!(previews.size(64)).is_equal_to(Some(64));
+        assert_that!(previews.size(65)).is_equal_to(Some(128));
+        assert_that!(previews.size(2048)).is_equal_to(Some(2048));
+        assert_that!(previews.size(2049)).is_none();
+    }
+
+    #[tokio::test]
+    async fn test_preview() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let staging = repository.stage().await.unwrap();
+        tokio::fs::write(staging.path_of(Kind::Document), b"first version").await.unwrap();
+        let bundle = staging.create().await.unwrap();
+
+        // The fake renderer outputs the arguments it has been called with
+        let previews = Previews::from_config(Config {
+            pdftoppm: String::from("echo"),
+            ..Config::default()
+        }, &repository);
+
+        let (tag, data) = previews.preview(&bundle, 2, 128).await.unwrap();
+        assert_that!(String::from_utf8(data).unwrap()).contains("-f 2 -l 2 -singlefile -scale-to 128");
+
+        // Cached variants are served without rendering
+        let directory = previews.directory(bundle.id());
+        tokio::fs::write(directory.join(format!("{}.png", tag)), b"cached").await.unwrap();
+        assert_that!(previews.preview(&bundle, 2, 128).await.unwrap().1).is_equal_to(b"cached".to_vec());
+
+        // Replacing the document invalidates the cached variants
+        bundle.replace(Kind::Document, b"second version").await.unwrap();
+        let (updated, data) = previews.preview(&bundle, 2, 128).await.unwrap();
+        assert_that!(updated).is_not_equal_to(&tag);
+        assert_that!(data).is_not_equal_to(b"cached".to_vec());
+        assert_that!(tokio::fs::metadata(directory.join(format!("{}.png", tag))).await.is_err()).is_true();
+
+        previews.evict(bundle.id()).await.unwrap();
+        assert_that!(tokio::fs::metadata(&directory).await.is_err()).is_true();
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{HashMap, HashSet};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use tokio::process::Command;
+use tokio::sync::broadcast::RecvError;
+
+use crate::config::Processor as Config;
+use crate::plugins::Processor as Plugin;
+use crate::proto::model::{DocInfo, Label, PropertyValue};
+use crate::repository::{Archived, Bundle, Event, Repository};
+use crate::status::Status;
+
+/// Metadata a processor extracted from a fragment, printed as JSON object to stdout.
+#[derive(Debug, Default, Deserialize)]
+struct Enrichment {
+    #[serde(default)]
+    labels: HashSet<Label>,
+
+    #[serde(default)]
+    properties: HashMap<String, PropertyValue>,
+}
+
+/// Returns true if the fragment is matched by the pattern, which is either a filename or `*.` and an extension.
+fn matches(pattern: &str, name: &str) -> bool {
+    return match pattern.strip_prefix('*') {
+        Some(suffix) => name.len() > suffix.len() && name.to_lowercase().ends_with(&suffix.to_lowercase()),
+        None => name == pattern,
+    };
+}
+
+/// Enriches the metadata of archived documents by running external commands over their fragments.
+///
+/// Processors are selected by the filenames of the fragments, i.e. to extract the distance of a GPS track attached as
+/// `track.gpx` or to transcribe voice memos. The labels and properties printed by the processors are merged into the
+/// metadata. Bundles of encryption domains are skipped, as their fragments are encrypted.
+///
+/// Processors compiled into the build as plugins run after the configured ones.
+pub struct Processors {
+    processors: Vec<Config>,
+    plugins: Vec<Arc<dyn Plugin>>,
+
+    repository: Repository,
+
+    status: Arc<Status>,
+}
+
+impl Processors {
+    pub fn from_config(processors: Vec<Config>, plugins: Vec<Arc<dyn Plugin>>, repository: Repository, status: Arc<Status>) -> Self {
+        return Self { processors, plugins, repository, status };
+    }
+
+    async fn run_processor(&self, processor: &Config, bundle: &Bundle<'_, Archived>, name: &str) -> Result<Enrichment> {
+        let (command, args) = processor.command.split_first()
+            .ok_or_else(|| anyhow!("No command configured for processor {}", processor.name))?;
+
+        debug!("Running {} {:?} on {}", command, args, name);
+
+        let output = Command::new(command)
+            .args(args)
+            .arg(bundle.path().join(name))
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+
+        let output = tokio::time::timeout(Duration::from_secs(processor.timeout), output).await
+            .map_err(|_| anyhow!("{} timed out after {}s", processor.name, processor.timeout))?
+            .with_context(|| format!("Error executing {}", command))?;
+
+        if !output.status.success() {
+            bail!("{} failed: {}: {}", processor.name, output.status, String::from_utf8_lossy(&output.stderr));
+        }
+
+        return serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Invalid output of {}", processor.name));
+    }
+
+    /// Runs all processors matching the fragments of an archived bundle and updates its metadata.
+    ///
+    /// Returns the number of processed fragments.
+    pub async fn process(&self, bundle: &Bundle<'_, Archived>) -> Result<usize> {
+        let mut metadata = bundle.read_metadata().await?;
+        if metadata.domain.is_some() {
+            return Ok(0);
+        }
+
+        let original = metadata.clone();
+
+        let mut processed = 0;
+        for name in bundle.fragment_names().await? {
+            for processor in self.processors.iter().filter(|processor| matches(&processor.fragment, &name)) {
+                let enrichment = self.run_processor(processor, bundle, &name).await?;
+
+                metadata.labels.extend(enrichment.labels);
+                metadata.properties.extend(enrichment.properties);
+
+                processed += 1;
+            }
+
+            let plugins = self.plugins.iter().filter(|plugin| plugin.matches(&name)).collect::<Vec<_>>();
+            if !plugins.is_empty() {
+                let data = tokio::fs::read(bundle.path().join(&name)).await?;
+                let doc = DocInfo::from((*bundle.id(), original.clone()));
+
+                for plugin in plugins {
+                    let enrichment = plugin.process(&doc, &name, &data).await
+                        .with_context(|| format!("Processor {} failed", plugin.name()))?;
+
+                    metadata.labels.extend(enrichment.labels);
+                    metadata.properties.extend(enrichment.properties);
+
+                    processed += 1;
+                }
+            }
+        }
+
+        if metadata != original {
+            bundle.write_metadata(&metadata).await?;
+        }
+
+        return Ok(processed);
+    }
+
+    /// Follows the repository events and processes every archived bundle.
+    pub async fn run(self) {
+        let mut events = self.repository.subscribe();
+
+        loop {
+            let id = match events.recv().await {
+                Ok(Event::Archived(id)) => id,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Processors missed {} repository events", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let bundle = match self.repository.archive().get(id).await {
+                Some(bundle) => bundle,
+                None => continue,
+            };
+
+            match self.process(&bundle).await {
+                Ok(0) => {}
+                Ok(processed) => info!("Processed {} fragments of bundle {}", processed, id),
+                Err(err) => {
+                    error!("Failed to process bundle {}: {:#}", id, err);
+                    self.status.failed("processors", &err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+    use tokio::io::AsyncWriteExt;
+
+    use crate::meta::Metadata;
+    use crate::proto::model::Kind;
+
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert_that!(matches("*.gpx", "track.gpx")).is_true();
+        assert_that!(matches("*.gpx", "TRACK.GPX")).is_true();
+        assert_that!(matches("*.gpx", ".gpx")).is_false();
+        assert_that!(matches("*.gpx", "track.gpx.txt")).is_false();
+        assert_that!(matches("memo.ogg", "memo.ogg")).is_true();
+        assert_that!(matches("memo.ogg", "other.ogg")).is_false();
+    }
+
+    #[tokio::test]
+    async fn test_process() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let staging = repository.stage().await.unwrap();
+        let mut fragment = staging.write(Kind::other("track.gpx")).await.unwrap();
+        fragment.write_all(b"<gpx/>").await.unwrap();
+        fragment.commit().await.unwrap();
+        Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        let bundle = staging.create().await.unwrap().archive().await.unwrap();
+
+        let processors = Processors::from_config(vec![Config {
+            name: String::from("gpx"),
+            fragment: String::from("*.gpx"),
+            command: vec![
+                String::from("sh"),
+                String::from("-c"),
+                String::from(r#"test -f "$1" && echo '{"labels": ["hike"], "properties": {"distance": 42}}'"#),
+                String::from("sh"),
+            ],
+            timeout: 10,
+        }], vec![], repository.clone(), Arc::new(Status::new()));
+
+        assert_that!(processors.process(&bundle).await.unwrap()).is_equal_to(1);
+
+        let metadata = bundle.read_metadata().await.unwrap();
+        assert_that!(metadata.labels.contains(&Label::from("hike"))).is_true();
+        assert_that!(metadata.properties.get("distance")).is_equal_to(Some(&PropertyValue::Integer(42)));
+    }
+}
@@ -0,0 +1,290 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::proto::api::quarantine::{Review, ReviewAction};
+use crate::proto::model::DocId;
+use crate::repository::{Bundle, Quarantined};
+
+/// Password of the sample archives unless another one is requested, as commonly used to share malware samples
+pub const PASSWORD: &str = "infected";
+
+/// Append-only log of all actions taken while reviewing quarantined bundles.
+///
+/// Unlike the journal, which records state transitions only, the log also records who inspected and downloaded a
+/// sample. It is stored as a file with one JSON encoded review per line and outlives the reviewed bundles.
+pub struct Reviews {
+    path: PathBuf,
+
+    /// Serializes appending reviews
+    lock: Mutex<()>,
+}
+
+impl Reviews {
+    pub const FILENAME: &'static str = "quarantine.jsonl";
+
+    pub fn open(path: PathBuf) -> Self {
+        return Self { path, lock: Mutex::new(()) };
+    }
+
+    pub async fn record(&self, id: DocId, actor: &str, action: ReviewAction) -> Result<Review> {
+        let _lock = self.lock.lock().await;
+
+        let review = Review {
+            time: Utc::now(),
+            actor: actor.to_string(),
+            id,
+            action,
+        };
+
+        let mut line = serde_json::to_vec(&review)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+
+        return Ok(review);
+    }
+
+    /// Returns all reviews recorded for the given bundle.
+    pub async fn of(&self, id: DocId) -> Result<Vec<Review>> {
+        let data = match tokio::fs::read(&self.path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let reviews = data.split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<Vec<Review>, _>>()?;
+
+        return Ok(reviews.into_iter().filter(|review| review.id == id).collect());
+    }
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    return table;
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+fn crc_update(crc: u32, b: u8) -> u32 {
+    return (crc >> 8) ^ CRC_TABLE[((crc ^ b as u32) & 0xff) as usize];
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    return !data.iter().fold(!0, |crc, b| crc_update(crc, *b));
+}
+
+/// The traditional PKWARE encryption, which is weak but understood by all common unzip tools.
+///
+/// It only serves to keep virus scanners and mail filters from acting on the sample, not to protect its contents.
+struct Cipher([u32; 3]);
+
+impl Cipher {
+    fn new(password: &[u8]) -> Self {
+        let mut cipher = Self([0x1234_5678, 0x2345_6789, 0x3456_7890]);
+        for b in password {
+            cipher.update(*b);
+        }
+
+        return cipher;
+    }
+
+    fn update(&mut self, b: u8) {
+        let keys = &mut self.0;
+        keys[0] = crc_update(keys[0], b);
+        keys[1] = keys[1].wrapping_add(keys[0] & 0xff).wrapping_mul(134_775_813).wrapping_add(1);
+        keys[2] = crc_update(keys[2], (keys[1] >> 24) as u8);
+    }
+
+    fn stream(&self) -> u8 {
+        let t = (self.0[2] | 2) & 0xffff;
+        return ((t * (t ^ 1)) >> 8) as u8;
+    }
+
+    fn encrypt(&mut self, data: &[u8]) -> Vec<u8> {
+        return data.iter()
+            .map(|b| {
+                let c = b ^ self.stream();
+                self.update(*b);
+                return c;
+            })
+            .collect();
+    }
+}
+
+/// Encodes a timestamp as MS-DOS time and date.
+fn dos_time(time: &DateTime<Utc>) -> (u16, u16) {
+    return (
+        ((time.hour() << 11) | (time.minute() << 5) | (time.second() / 2)) as u16,
+        (((time.year().max(1980) - 1980) as u32) << 9 | (time.month() << 5) | time.day()) as u16,
+    );
+}
+
+/// Writes the files into an uncompressed ZIP archive encrypted with the given password.
+fn zip(files: &[(String, Vec<u8>)], password: &str, time: &DateTime<Utc>) -> Result<Vec<u8>> {
+    let (time, date) = dos_time(time);
+
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+
+    for (name, data) in files {
+        let crc = crc32(data);
+
+        // The encryption header is random except for its last byte, which allows to check the password
+        let mut header = [0u8; 12];
+        OsRng.fill_bytes(&mut header[..11]);
+        header[11] = (crc >> 24) as u8;
+
+        let mut cipher = Cipher::new(password.as_bytes());
+        let mut encrypted = cipher.encrypt(&header);
+        encrypted.extend(cipher.encrypt(data));
+
+        if encrypted.len() > u32::MAX as usize || archive.len() > u32::MAX as usize {
+            bail!("Sample too large for archive: {}", name);
+        }
+
+        // Version needed, encrypted, stored, time, date, checksum and sizes as shared by both headers
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&1u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&time.to_le_bytes());
+        fields.extend_from_slice(&date.to_le_bytes());
+        fields.extend_from_slice(&crc.to_le_bytes());
+        fields.extend_from_slice(&(encrypted.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        directory.extend_from_slice(&fields);
+        directory.extend_from_slice(&[0u8; 10]);
+        directory.extend_from_slice(&(archive.len() as u32).to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+
+        archive.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        archive.extend_from_slice(&fields);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&encrypted);
+    }
+
+    let offset = archive.len() as u32;
+    let entries = files.len() as u16;
+
+    archive.extend_from_slice(&directory);
+
+    archive.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    archive.extend_from_slice(&[0u8; 4]);
+    archive.extend_from_slice(&entries.to_le_bytes());
+    archive.extend_from_slice(&entries.to_le_bytes());
+    archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes());
+
+    return Ok(archive);
+}
+
+/// Packs all fragments of a quarantined bundle into a password protected ZIP archive.
+///
+/// The sample is neither opened nor rendered on the server, and the encryption keeps it from being picked up by virus
+/// scanners on its way to the reviewer.
+pub async fn sample(bundle: &Bundle<'_, Quarantined>, password: &str) -> Result<Vec<u8>> {
+    let mut names = bundle.fragment_names().await?;
+    names.sort();
+
+    let mut files = Vec::with_capacity(names.len());
+    for name in names {
+        let data = tokio::fs::read(bundle.path().join(&name)).await?;
+        files.push((format!("{}/{}", bundle.id(), name), data));
+    }
+
+    return zip(&files, password, &Utc::now());
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_that!(crc32(b"123456789")).is_equal_to(0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_zip() {
+        let time = DateTime::parse_from_rfc3339("2020-08-15T12:34:56Z").unwrap().with_timezone(&Utc);
+        let files = vec![(String::from("sample.txt"), b"X5O!P%@AP".to_vec())];
+
+        let archive = zip(&files, PASSWORD, &time).unwrap();
+
+        assert_that!(archive[..4].to_vec()).is_equal_to(vec![0x50, 0x4b, 0x03, 0x04]);
+        assert_that!(archive[6..8].to_vec()).is_equal_to(vec![0x01, 0x00]);
+
+        // Decrypt the single entry following the local header
+        let encrypted = &archive[30 + 10..30 + 10 + 12 + 9];
+        let mut cipher = Cipher::new(PASSWORD.as_bytes());
+        let decrypted = encrypted.iter()
+            .map(|c| {
+                let b = c ^ cipher.stream();
+                cipher.update(b);
+                return b;
+            })
+            .collect::<Vec<_>>();
+
+        assert_that!(decrypted[11]).is_equal_to((crc32(&files[0].1) >> 24) as u8);
+        assert_that!(decrypted[12..].to_vec()).is_equal_to(files[0].1.clone());
+        assert_that!(archive[30 + 10 + 12..30 + 10 + 12 + 9].to_vec()).is_not_equal_to(files[0].1.clone());
+
+        // The archive ends with the end of central directory record pointing at a single entry
+        let end = &archive[archive.len() - 22..];
+        assert_that!(end[..4].to_vec()).is_equal_to(vec![0x50, 0x4b, 0x05, 0x06]);
+        assert_that!(end[10..12].to_vec()).is_equal_to(vec![0x01, 0x00]);
+    }
+
+    #[tokio::test]
+    async fn test_reviews() {
+        let dir = tempfile::tempdir().unwrap();
+        let reviews = Reviews::open(dir.path().join(Reviews::FILENAME));
+
+        let id = DocId::random();
+        reviews.record(id, "admin", ReviewAction::Inspected).await.unwrap();
+        reviews.record(DocId::random(), "admin", ReviewAction::Purged).await.unwrap();
+        reviews.record(id, "admin", ReviewAction::Released).await.unwrap();
+
+        let recorded = reviews.of(id).await.unwrap();
+        assert_that!(recorded.iter().map(|review| review.action).collect::<Vec<_>>())
+            .is_equal_to(vec![ReviewAction::Inspected, ReviewAction::Released]);
+    }
+}
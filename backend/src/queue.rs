@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use tracing::info_span;
+use tracing_futures::Instrument;
+
+use crate::barcodes::Detector;
+use crate::confidence;
+use crate::config::Queue as Config;
+use crate::correspondents::Correspondents;
+use crate::dates;
+use crate::geotag;
+use crate::rules::Rules;
+use crate::juicer::Juicer;
+use crate::juicer::report::Failure;
+use crate::language;
+use crate::proto::model::{DocId, Kind};
+use crate::repository::{Bundle, BundleState, Failed, Repository, Staging};
+use crate::split::Splitter;
+use crate::status::Status;
+use crate::warranties;
+
+/// Progress of a juicing job which has not failed permanently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for a juicer to become available or for the next attempt
+    Queued,
+
+    /// The juicer is running
+    Juicing,
+}
+
+impl Default for JobState {
+    fn default() -> Self { Self::Queued }
+}
+
+/// State of a juicing job, persisted in the staging bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub attempts: u32,
+
+    pub queued: DateTime<Utc>,
+
+    #[serde(default)]
+    pub state: JobState,
+
+    /// Set if the job has failed permanently
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<Failure>,
+
+    /// Name of the alternative juicer used instead of the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub juicer: Option<String>,
+}
+
+impl Job {
+    pub const FRAGMENT: &'static str = "job.json";
+
+    /// Creates a job queued right now.
+    pub fn queued_now() -> Self {
+        return Self {
+            attempts: 0,
+            queued: Utc::now(),
+            state: JobState::Queued,
+            failed: None,
+            juicer: None,
+        };
+    }
+
+    pub async fn load<State: BundleState>(bundle: &Bundle<'_, State>) -> Result<Option<Self>> {
+        let mut file = match bundle.read(Kind::other(Self::FRAGMENT)).await? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).await?;
+
+        return Ok(Some(serde_json::from_slice(&buffer)?));
+    }
+
+    pub async fn save(&self, bundle: &Bundle<'_, Staging>) -> Result<()> {
+        let mut fragment = bundle.write(Kind::other(Self::FRAGMENT)).await?;
+        fragment.write_all(&serde_json::to_vec_pretty(self)?).await?;
+        fragment.commit().await?;
+
+        return Ok(());
+    }
+}
+
+struct Inner {
+    config: Config,
+
+    repository: Repository,
+
+    /// The default juicer, which can be replaced while jobs keep the one they started with
+    juicer: RwLock<Arc<dyn Juicer + Send + Sync>>,
+
+    /// Alternative juicers failed jobs can be retried with, by name
+    alternatives: HashMap<String, Arc<dyn Juicer + Send + Sync>>,
+
+    /// Splits scanned stacks into their documents before juicing, if enabled
+    splitter: Option<Splitter>,
+
+    /// Detects barcodes on the pages of juiced documents, if enabled
+    detector: Option<Detector>,
+
+    /// Rules are applied and correspondents are identified once the text of a document has been extracted
+    rules: Arc<Rules>,
+    correspondents: Arc<Correspondents>,
+
+    /// Limits the number of concurrently running juicers
+    permits: Semaphore,
+
+    status: Arc<Status>,
+}
+
+/// Runs the juicer over staged bundles in the background and moves them to the inbox afterwards.
+///
+/// The queue state is persisted in the staging bundles, so pending jobs are picked up again after a restart.
+#[derive(Clone)]
+pub struct Queue(Arc<Inner>);
+
+impl Queue {
+    pub fn new(config: Config,
+               repository: Repository,
+               juicer: Arc<dyn Juicer + Send + Sync>,
+               alternatives: HashMap<String, Arc<dyn Juicer + Send + Sync>>,
+               rules: Arc<Rules>,
+               correspondents: Arc<Correspondents>,
+               status: Arc<Status>) -> Self {
+        return Self(Arc::new(Inner {
+            permits: Semaphore::new(config.concurrency.max(1)),
+            splitter: config.split.clone().map(Splitter::from_config),
+            detector: config.barcodes.clone().map(Detector::from_config),
+            config,
+            repository,
+            juicer: RwLock::new(juicer),
+            alternatives,
+            rules,
+            correspondents,
+            status,
+        }));
+    }
+
+    pub fn repository(&self) -> &Repository { &self.0.repository }
+
+    /// Schedules a staged bundle for juicing.
+    pub async fn enqueue(&self, bundle: Bundle<'_, Staging>) -> Result<DocId> {
+        Job::queued_now().save(&bundle).await?;
+
+        info!("Queued bundle {} for juicing", bundle.id());
+
+        self.spawn(*bundle.id());
+
+        return Ok(*bundle.id());
+    }
+
+    /// Runs the juicer over a staged bundle right away, bypassing the queue.
+    ///
+    /// Neither retries nor rules apply, the caller takes care of the bundle.
+    pub async fn juice(&self, bundle: &Bundle<'_, Staging>) -> Result<()> {
+        let _permit = self.0.permits.acquire().await;
+        return self.0.juicer().extract(bundle).await;
+    }
+
+    /// Replaces the default juicer, i.e. after the configuration has been reloaded.
+    ///
+    /// Jobs already running keep the juicer they started with.
+    pub fn set_juicer(&self, juicer: Arc<dyn Juicer + Send + Sync>) {
+        *self.0.juicer.write().expect("Juicer poisoned") = juicer;
+    }
+
+    /// Lists all queued and failed jobs.
+    pub async fn jobs(&self) -> Result<Vec<(DocId, Job)>> {
+        let mut jobs = Vec::new();
+        for bundle in self.0.repository.staging().list().await? {
+            if let Some(job) = Job::load(&bundle).await? {
+                jobs.push((*bundle.id(), job));
+            }
+        }
+
+        for bundle in self.0.repository.failed().list().await? {
+            if let Some(job) = Job::load(&bundle).await? {
+                jobs.push((*bundle.id(), job));
+            }
+        }
+
+        return Ok(jobs);
+    }
+
+    /// Whether an alternative juicer with the given name is configured.
+    pub fn has_juicer(&self, name: &str) -> bool {
+        return self.0.alternatives.contains_key(name);
+    }
+
+    /// Moves a failed bundle back to the staging area and queues it again, optionally for an alternative juicer.
+    ///
+    /// The attempts start over, the log of the failed attempts is replaced by the next one.
+    pub async fn retry(&self, bundle: Bundle<'_, Failed>, juicer: Option<String>) -> Result<DocId> {
+        if let Some(name) = &juicer {
+            if !self.has_juicer(name) {
+                bail!("Unknown juicer: {}", name);
+            }
+        }
+
+        let staged = bundle.retry().await?;
+
+        Job { juicer: juicer.clone(), ..Job::queued_now() }.save(&staged).await?;
+
+        info!("Retrying failed bundle {} with {} juicer", staged.id(), juicer.as_deref().unwrap_or("default"));
+
+        self.spawn(*staged.id());
+
+        return Ok(*staged.id());
+    }
+
+    /// Re-schedules all pending jobs found in the staging area.
+    pub async fn resume(&self) -> Result<usize> {
+        let mut resumed = 0;
+        for bundle in self.0.repository.staging().list().await? {
+            match Job::load(&bundle).await? {
+                Some(job) if job.failed.is_none() => {
+                    self.spawn(*bundle.id());
+                    resumed += 1;
+                }
+
+                // Jobs failed before failed bundles were moved aside
+                Some(_) => {
+                    bundle.fail().await?;
+                }
+
+                None => {}
+            }
+        }
+
+        info!("Resumed {} juicing jobs", resumed);
+
+        return Ok(resumed);
+    }
+
+    /// Spawns the job of a bundle, tracing it as part of the current span, i.e. the upload.
+    fn spawn(&self, id: DocId) {
+        let queue = self.clone();
+        let span = info_span!("ingest", bundle = %id);
+        tokio::spawn(async move {
+            let inner = &queue.0;
+
+            // Stacks which have been split are replaced by their parts, which are juiced on their own
+            match inner.split(id).await {
+                Ok(Some(parts)) => {
+                    for part in parts {
+                        queue.spawn(part);
+                    }
+                    return;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    warn!("Failed to split bundle {}, juicing it as a whole: {:#}", id, err);
+                    inner.status.failed("split", &err);
+                }
+            }
+
+            if let Err(err) = inner.process(id).await {
+                error!("Failed to process juicing job {}: {:#}", id, err);
+                inner.status.failed("queue", &err);
+            }
+        }.instrument(span));
+    }
+}
+
+impl Inner {
+    /// Returns the current default juicer.
+    fn juicer(&self) -> Arc<dyn Juicer + Send + Sync> {
+        return self.juicer.read().expect("Juicer poisoned").clone();
+    }
+
+    /// Applies the rules and assigns the correspondent mentioned in the extracted text before the bundle enters the
+    /// inbox.
+    #[tracing::instrument(skip(self, bundle))]
+    async fn classify(&self, bundle: &Bundle<'_, Staging>) -> Result<()> {
+        let mut file = match bundle.read(Kind::Plaintext).await? {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let mut text = String::new();
+        file.read_to_string(&mut text).await?;
+
+        let mut metadata = bundle.read_metadata().await?;
+        let original = metadata.clone();
+
+        // Under review, the findings are proposed and the metadata itself stays untouched until they are accepted
+        let mut found = metadata.clone();
+
+        let matched = self.rules.apply(&mut found, &text).await;
+        if !matched.is_empty() {
+            info!("Applied rules to bundle {}: {}", bundle.id(), matched.join(", "));
+        }
+
+        if self.correspondents.assign(&mut found, &text).await {
+            info!("Identified correspondent of bundle {}: {}", bundle.id(), found.correspondent.as_deref().unwrap_or_default());
+        }
+
+        if warranties::suggest(&mut found, &text) {
+            info!("Proposed warranty of bundle {}", bundle.id());
+        }
+
+        if dates::suggest(&mut found, &text, Utc::today().naive_utc()) {
+            info!("Found date of bundle {}", bundle.id());
+        }
+
+        if self.config.review {
+            metadata.propose(found);
+        } else {
+            metadata = found;
+        }
+
+        if let Some(language) = language::detect(&text) {
+            metadata.language = Some(language.to_string());
+        }
+
+        // Only OCRed documents come with the confidence of the recognized words
+        if let Some(mut file) = bundle.read(Kind::other(confidence::FRAGMENT)).await? {
+            let mut tsv = String::new();
+            file.read_to_string(&mut tsv).await?;
+
+            if let Some(confidence) = confidence::parse(&tsv) {
+                metadata.confidence = Some(confidence.mean);
+                metadata.page_confidence = confidence.pages;
+            }
+        }
+
+        if metadata != original {
+            metadata.save(bundle.write(Kind::Metadata).await?).await?;
+        }
+
+        return Ok(());
+    }
+
+    /// Splits a staged bundle at its separator pages, if enabled.
+    ///
+    /// The parts are queued in place of the bundle, which is removed. Returns the IDs of the parts, or `None` if the
+    /// bundle has not been split.
+    #[tracing::instrument(skip(self))]
+    async fn split(&self, id: DocId) -> Result<Option<Vec<DocId>>> {
+        let splitter = match &self.splitter {
+            Some(splitter) => splitter,
+            None => return Ok(None),
+        };
+
+        let bundle = self.repository.staging().get(id).await
+            .ok_or_else(|| anyhow!("Staged bundle vanished: {}", id))?;
+
+        let parts = {
+            // Rendering the pages is as expensive as juicing
+            let _permit = self.permits.acquire().await;
+            splitter.split(&self.repository, &bundle).await?
+        };
+
+        let parts = match parts {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+
+        let mut ids = Vec::with_capacity(parts.len());
+        for part in parts {
+            Job::queued_now().save(&part).await?;
+            info!("Queued bundle {} split from {} for juicing", part.id(), id);
+
+            ids.push(*part.id());
+        }
+
+        bundle.delete().await?;
+
+        return Ok(Some(ids));
+    }
+
+    async fn process(&self, id: DocId) -> Result<()> {
+        loop {
+            let bundle = self.repository.staging().get(id).await
+                .ok_or_else(|| anyhow!("Staged bundle vanished: {}", id))?;
+
+            let mut job = Job::load(&bundle).await?
+                .ok_or_else(|| anyhow!("Job missing for bundle: {}", id))?;
+
+            let result = async {
+                let _permit = self.permits.acquire()
+                    .instrument(info_span!("queued"))
+                    .await;
+
+                job.state = JobState::Juicing;
+                job.save(&bundle).await?;
+
+                let juicer = match &job.juicer {
+                    Some(name) => self.alternatives.get(name)
+                        .ok_or_else(|| anyhow!("Unknown juicer: {}", name))?
+                        .clone(),
+                    None => self.juicer(),
+                };
+
+                juicer.extract(&bundle).await
+            }.instrument(info_span!("juice", attempt = job.attempts + 1)).await;
+
+            match result {
+                Ok(()) => {
+                    tokio::fs::remove_file(bundle.path_of(Kind::other(Job::FRAGMENT))).await?;
+                    if let Some(detector) = &self.detector {
+                        detector.detect(&bundle).await?;
+                    }
+                    self.classify(&bundle).await?;
+                    geotag::geotag(&bundle).await?;
+                    bundle.create().await?;
+
+                    info!("Juiced bundle {}", id);
+                    return Ok(());
+                }
+
+                Err(err) => {
+                    job.attempts += 1;
+                    job.state = JobState::Queued;
+
+                    if job.attempts > self.config.retries {
+                        let log = match bundle.read(Kind::other("juicer.log")).await? {
+                            Some(mut file) => {
+                                let mut log = Vec::new();
+                                file.read_to_end(&mut log).await?;
+                                String::from_utf8_lossy(&log).into_owned()
+                            }
+                            None => String::new(),
+                        };
+
+                        job.failed = Some(Failure::classify(&err, &log));
+                        job.save(&bundle).await?;
+
+                        // The original and the juicer log are kept aside until the job is retried or discarded
+                        bundle.fail().await?;
+
+                        error!("Juicing bundle {} failed permanently after {} attempts: {:#}", id, job.attempts, err);
+                        self.status.failed("juicer", &err);
+                        return Ok(());
+                    }
+
+                    job.save(&bundle).await?;
+
+                    // Exponential backoff between the attempts
+                    let delay = Duration::from_secs(self.config.backoff) * 2u32.pow(job.attempts - 1);
+                    warn!("Juicing bundle {} failed (attempt {}), retrying in {:?}: {:#}", id, job.attempts, delay, err);
+                    tokio::time::delay_for(delay).await;
+                }
+            }
+        }
+    }
+}
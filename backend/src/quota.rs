@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+use crate::config::Quota as Config;
+use crate::repository::Repository;
+
+/// Error returned if storing a document would exceed a storage quota.
+#[derive(Debug, thiserror::Error)]
+pub enum Exceeded {
+    #[error("Storage quota of the repository exceeded: {used} of {limit} bytes used, {size} bytes more requested")]
+    Repository {
+        used: u64,
+        limit: u64,
+        size: u64,
+    },
+
+    #[error("Storage quota of user {user} exceeded: {used} of {limit} bytes used, {size} bytes more requested")]
+    User {
+        user: String,
+        used: u64,
+        limit: u64,
+        size: u64,
+    },
+}
+
+/// Storage used by the documents of a repository and a single user.
+#[derive(Debug, Clone)]
+pub struct Usage {
+    pub repository: u64,
+    pub user: u64,
+}
+
+/// Interval after which the storage used by a repository is counted again.
+///
+/// In between, the counted usage is kept up to date by the bytes reserved for each document stored. Documents deleted
+/// in the meantime are released on the next count only.
+const RECOUNT: Duration = Duration::from_secs(600);
+
+/// The bytes used in a repository by owner, as counted and stored since.
+#[derive(Default)]
+struct Ledger {
+    /// Bytes of the documents counted in the repository and the ones stored since
+    stored: BTreeMap<Option<String>, u64>,
+
+    /// Bytes of the documents being stored, which are not counted until committed
+    pending: BTreeMap<Option<String>, u64>,
+
+    /// When the repository has been counted, never if unset
+    counted: Option<Instant>,
+}
+
+impl Ledger {
+    fn used(&self, owner: Option<&str>) -> Usage {
+        let key = owner.map(String::from);
+
+        return Usage {
+            repository: self.stored.values().sum::<u64>() + self.pending.values().sum::<u64>(),
+            user: self.stored.get(&key).copied().unwrap_or(0) + self.pending.get(&key).copied().unwrap_or(0),
+        };
+    }
+}
+
+/// The ledger of a repository, which is counted once at a time.
+struct Account {
+    ledger: std::sync::Mutex<Ledger>,
+    counting: Mutex<()>,
+}
+
+impl Default for Account {
+    fn default() -> Self {
+        return Self {
+            ledger: std::sync::Mutex::new(Ledger::default()),
+            counting: Mutex::new(()),
+        };
+    }
+}
+
+impl Account {
+    fn ledger(&self) -> MutexGuard<'_, Ledger> {
+        return self.ledger.lock().expect("Ledger poisoned");
+    }
+}
+
+/// Bytes reserved for a document while it is stored.
+///
+/// The reservation grows with the bytes stored and fails as soon as they exceed a quota. The reserved bytes are kept
+/// accounted once committed and are released if the reservation is dropped before, i.e. if storing the document fails.
+pub struct Reservation {
+    /// The account bytes are reserved in, unset if unlimited
+    account: Option<Arc<Account>>,
+
+    limits: (Option<u64>, Option<u64>),
+
+    owner: Option<String>,
+
+    size: AtomicU64,
+}
+
+impl Reservation {
+    /// Reserves additional bytes, failing with `Exceeded` if they do not fit into the quotas.
+    pub fn grow(&self, size: u64) -> Result<()> {
+        let account = match &self.account {
+            Some(account) => account,
+            None => return Ok(()),
+        };
+
+        let mut ledger = account.ledger();
+        let usage = ledger.used(self.owner.as_deref());
+
+        if let Some(limit) = self.limits.0 {
+            if usage.repository + size > limit {
+                bail!(Exceeded::Repository { used: usage.repository, limit, size });
+            }
+        }
+
+        if let (Some(limit), Some(owner)) = (self.limits.1, &self.owner) {
+            if usage.user + size > limit {
+                bail!(Exceeded::User { user: owner.clone(), used: usage.user, limit, size });
+            }
+        }
+
+        *ledger.pending.entry(self.owner.clone()).or_default() += size;
+        self.size.fetch_add(size, Ordering::SeqCst);
+
+        return Ok(());
+    }
+
+    /// Copies all bytes from the reader to the writer, reserving them before they are written.
+    pub async fn copy<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<u64>
+        where R: AsyncRead + Unpin,
+              W: AsyncWrite + Unpin {
+        let mut buffer = vec![0; 64 * 1024];
+        let mut copied = 0;
+
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+
+            self.grow(read as u64)?;
+            writer.write_all(&buffer[..read]).await?;
+
+            copied += read as u64;
+        }
+
+        writer.flush().await?;
+
+        return Ok(copied);
+    }
+
+    /// Keeps the reserved bytes accounted as stored.
+    pub fn commit(mut self) {
+        if let Some(account) = self.account.take() {
+            let size = self.size.swap(0, Ordering::SeqCst);
+
+            let mut ledger = account.ledger();
+            release(&mut ledger.pending, &self.owner, size);
+            *ledger.stored.entry(self.owner.clone()).or_default() += size;
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(account) = &self.account {
+            let size = self.size.swap(0, Ordering::SeqCst);
+            release(&mut account.ledger().pending, &self.owner, size);
+        }
+    }
+}
+
+fn release(pending: &mut BTreeMap<Option<String>, u64>, owner: &Option<String>, size: u64) {
+    if let Some(reserved) = pending.get_mut(owner) {
+        *reserved = reserved.saturating_sub(size);
+    }
+}
+
+/// Limits the storage used by the documents of each repository and of each user, by their owner.
+///
+/// Documents are rejected while they are stored as soon as they exceed a quota, so the disk does not fill up and fail
+/// the ingest half way. The usage of each repository is counted once and kept up to date by the bytes reserved for
+/// each stored document, so concurrent uploads can not exceed the quotas together.
+#[derive(Clone)]
+pub struct Quotas {
+    config: Config,
+
+    /// Accounts by the path of their repository
+    accounts: Arc<std::sync::Mutex<HashMap<PathBuf, Arc<Account>>>>,
+}
+
+impl Quotas {
+    pub fn new(config: Config) -> Self {
+        return Self { config, accounts: Arc::default() };
+    }
+
+    /// Returns the quota of the repository, if limited.
+    pub fn repository(&self) -> Option<u64> {
+        return self.config.repository;
+    }
+
+    /// Returns the quota of the user, if limited.
+    pub fn user(&self, user: &str) -> Option<u64> {
+        return self.config.users.get(user).copied().or(self.config.user);
+    }
+
+    fn is_limited(&self, owner: Option<&str>) -> bool {
+        return self.repository().is_some() || owner.and_then(|owner| self.user(owner)).is_some();
+    }
+
+    /// Returns the account of the repository, counting its usage if not counted recently.
+    async fn account(&self, repository: &Repository) -> Result<Arc<Account>> {
+        let account = self.accounts.lock().expect("Accounts poisoned")
+            .entry(repository.path().to_path_buf())
+            .or_default()
+            .clone();
+
+        let stale = |account: &Account| account.ledger().counted
+            .map_or(true, |counted| counted.elapsed() > RECOUNT);
+
+        if stale(&account) {
+            let _counting = account.counting.lock().await;
+
+            // Another request may have counted while waiting
+            if stale(&account) {
+                let stored = repository.disk_usage_by_owner().await?;
+
+                let mut ledger = account.ledger();
+                ledger.stored = stored;
+                ledger.counted = Some(Instant::now());
+            }
+        }
+
+        return Ok(account);
+    }
+
+    /// Calculates the storage used in the repository and by the user.
+    pub async fn usage(&self, repository: &Repository, user: &str) -> Result<Usage> {
+        return Ok(self.account(repository).await?.ledger().used(Some(user)));
+    }
+
+    /// Checks that storing additional bytes for the owner keeps within the quotas, failing with `Exceeded` otherwise.
+    ///
+    /// Nothing is reserved, so this rejects documents of known size early only, i.e. by the announced length.
+    pub async fn check(&self, repository: &Repository, owner: Option<&str>, size: u64) -> Result<()> {
+        let reservation = self.reserve(repository, owner, 0).await?;
+        reservation.grow(size)?;
+
+        return Ok(());
+    }
+
+    /// Reserves bytes for a document of the owner, which can grow further while the document is stored.
+    pub async fn reserve(&self, repository: &Repository, owner: Option<&str>, size: u64) -> Result<Reservation> {
+        let account = if self.is_limited(owner) {
+            Some(self.account(repository).await?)
+        } else {
+            None
+        };
+
+        let reservation = Reservation {
+            account,
+            limits: (self.repository(), owner.and_then(|owner| self.user(owner))),
+            owner: owner.map(String::from),
+            size: AtomicU64::new(0),
+        };
+
+        reservation.grow(size)?;
+
+        return Ok(reservation);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+    use tokio::io::AsyncWriteExt;
+
+    use crate::meta::Metadata;
+    use crate::proto::model::Kind;
+
+    use super::*;
+
+    async fn inboxed(repository: &Repository, owner: Option<&str>, document: &[u8]) {
+        let staging = repository.stage().await.unwrap();
+        let mut fragment = staging.write(Kind::Document).await.unwrap();
+        fragment.write_all(document).await.unwrap();
+        fragment.commit().await.unwrap();
+        Metadata {
+            owner: owner.map(String::from),
+            ..Metadata::new()
+        }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        staging.create().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        inboxed(&repository, Some("alice"), &[0; 1000]).await;
+        inboxed(&repository, None, &[0; 1000]).await;
+
+        let usage = Quotas::new(Config::default()).usage(&repository, "alice").await.unwrap();
+        assert_that!(usage.user).is_greater_than_or_equal_to(1000);
+        assert_that!(usage.user).is_less_than(2000);
+        assert_that!(usage.repository).is_greater_than_or_equal_to(2000);
+
+        // Unlimited without any quota
+        assert_that!(Quotas::new(Config::default()).check(&repository, Some("alice"), u64::MAX / 2).await.is_ok()).is_true();
+
+        let quotas = Quotas::new(Config {
+            repository: Some(usage.repository + 5000),
+            user: Some(usage.user + 100),
+            users: vec![(String::from("bob"), 10)].into_iter().collect(),
+        });
+
+        assert_that!(quotas.check(&repository, Some("alice"), 100).await.is_ok()).is_true();
+
+        let err = quotas.check(&repository, Some("alice"), 101).await.unwrap_err();
+        assert_that!(err.downcast_ref::<Exceeded>()).matches(|err| matches!(err, Some(Exceeded::User { .. })));
+
+        assert_that!(quotas.check(&repository, Some("bob"), 10).await.is_ok()).is_true();
+        assert_that!(quotas.check(&repository, Some("bob"), 11).await.is_err()).is_true();
+
+        let err = quotas.check(&repository, Some("carol"), 5001).await.unwrap_err();
+        assert_that!(err.downcast_ref::<Exceeded>()).matches(|err| matches!(err, Some(Exceeded::Repository { .. })));
+
+        // Documents without owner are limited by the quota of the repository only
+        assert_that!(quotas.check(&repository, None, 5000).await.is_ok()).is_true();
+        assert_that!(quotas.check(&repository, None, 5001).await.is_err()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_reserve() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let quotas = Quotas::new(Config {
+            repository: Some(1000),
+            user: Some(600),
+            users: HashMap::new(),
+        });
+
+        // Concurrent reservations count against the quota together
+        let first = quotas.reserve(&repository, Some("alice"), 400).await.unwrap();
+        let second = quotas.reserve(&repository, Some("alice"), 200).await.unwrap();
+        assert_that!(second.grow(1).is_err()).is_true();
+        assert_that!(quotas.reserve(&repository, Some("bob"), 401).await.is_err()).is_true();
+
+        // Dropped reservations are released, committed ones are kept
+        drop(second);
+        first.commit();
+        assert_that!(quotas.usage(&repository, "alice").await.unwrap().user).is_equal_to(400);
+
+        // Copies are cut off as soon as they exceed the quota
+        let reservation = quotas.reserve(&repository, Some("alice"), 0).await.unwrap();
+        let mut written = Vec::new();
+        let err = reservation.copy(&mut &[0u8; 201][..], &mut written).await.unwrap_err();
+        assert_that!(err.downcast_ref::<Exceeded>()).is_some();
+        assert_that!(written.is_empty()).is_true();
+
+        let mut written = Vec::new();
+        assert_that!(reservation.copy(&mut &[0u8; 200][..], &mut written).await.unwrap()).is_equal_to(200);
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use log::{error, info};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::notifications::{self, Notifications};
+use crate::queue::Queue;
+use crate::rules::Rules;
+use crate::status::Status;
+
+/// Reloads the settings which can change without a restart, on SIGHUP or on request of an admin.
+///
+/// The juicer and the notification channels are taken from the configuration file, the rules from the repository
+/// settings. The juicers and rules of the named repositories are reloaded alike. Everything is loaded before anything
+/// is applied, so a broken configuration keeps the running one in place. Jobs already juicing finish with the juicer
+/// they started with.
+pub struct Reloader {
+    /// The configuration file, which is not reloaded if unset
+    path: Option<PathBuf>,
+
+    queue: Queue,
+    rules: Arc<Rules>,
+
+    /// Queues and rules of the named repositories, by name
+    repositories: Vec<(String, Queue, Arc<Rules>)>,
+
+    notifications: Option<Arc<Notifications>>,
+
+    status: Arc<Status>,
+
+    /// Keeps concurrent reloads from applying their settings interleaved
+    lock: Mutex<()>,
+}
+
+impl Reloader {
+    pub fn new(path: Option<PathBuf>,
+               queue: Queue,
+               rules: Arc<Rules>,
+               repositories: Vec<(String, Queue, Arc<Rules>)>,
+               notifications: Option<Arc<Notifications>>,
+               status: Arc<Status>) -> Self {
+        return Self { path, queue, rules, repositories, notifications, status, lock: Mutex::new(()) };
+    }
+
+    /// Reloads the settings and returns the names of the reloaded ones.
+    ///
+    /// Notifications disabled at startup can not be enabled by a reload.
+    pub async fn reload(&self) -> Result<Vec<String>> {
+        let _lock = self.lock.lock().await;
+
+        let (juicer, juicers, channels) = match &self.path {
+            Some(path) => {
+                let config = Config::load(path).await?;
+
+                // Named repositories removed from the configuration keep their juicer until restarted
+                let mut juicers = Vec::new();
+                for (name, queue, _) in &self.repositories {
+                    if let Some(named) = config.repositories.get(name) {
+                        let juicer = crate::juicer::from_config(named.juicer.clone().unwrap_or_else(|| config.juicer.clone())).await?;
+                        juicers.push((queue, juicer));
+                    }
+                }
+
+                let juicer = crate::juicer::from_config(config.juicer).await?;
+
+                let channels = match (&self.notifications, &config.notifications) {
+                    (Some(_), Some(config)) => Some(notifications::channels(config)?),
+                    (Some(_), None) => Some(Vec::new()),
+                    (None, _) => None,
+                };
+
+                (Some(juicer), juicers, channels)
+            }
+            None => (None, Vec::new(), None),
+        };
+
+        self.rules.reload().await?;
+        for (_, _, rules) in &self.repositories {
+            rules.reload().await?;
+        }
+
+        let mut reloaded = vec![String::from("rules")];
+
+        if let Some(juicer) = juicer {
+            self.queue.set_juicer(juicer);
+            for (queue, juicer) in juicers {
+                queue.set_juicer(juicer);
+            }
+            reloaded.push(String::from("juicer"));
+        }
+
+        if let (Some(notifications), Some(channels)) = (&self.notifications, channels) {
+            notifications.reconfigure(channels);
+            reloaded.push(String::from("notifications"));
+        }
+
+        info!("Reloaded {}", reloaded.join(", "));
+
+        return Ok(reloaded);
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                error!("Failed to listen for SIGHUP: {}", err);
+                return;
+            }
+        };
+
+        while hangups.recv().await.is_some() {
+            info!("Reloading configuration on SIGHUP");
+
+            if let Err(err) = self.reload().await {
+                error!("Failed to reload configuration: {:#}", err);
+                self.status.failed("reload", &err);
+            }
+        }
+    }
+}
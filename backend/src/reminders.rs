@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{NaiveDate, Utc};
+use lettre_email::EmailBuilder;
+use log::{error, info};
+
+use crate::config::{Email, Reminders as Config};
+use crate::contracts;
+use crate::mailer;
+use crate::warranties;
+use crate::meta::Metadata;
+use crate::plugins::{Notification, Notifier};
+use crate::proto::api::due::{DueInfo, ListResponse};
+use crate::proto::model::DocId;
+use crate::repository::Repository;
+use crate::status::Status;
+
+/// Number of days before the due date documents are listed as due if not requested otherwise
+pub const DEFAULT_LEAD: u32 = 7;
+
+/// Returns the earliest day a document requires action as of the given day.
+///
+/// Besides the due date, running contracts are due on the last day they can be cancelled and warranties running out
+/// on their last day.
+pub fn deadline(metadata: &Metadata, today: NaiveDate) -> Option<NaiveDate> {
+    let cancel_by = contracts::terms(metadata)
+        .filter(|(end, _)| *end >= today)
+        .map(|(end, notice)| notice.before(end));
+    let expires = warranties::expires(metadata)
+        .filter(|expires| *expires >= today);
+
+    return metadata.due.into_iter().chain(cancel_by).chain(expires).min();
+}
+
+/// Lists all inboxed and archived documents matching the predicate which are due until the given date.
+///
+/// Documents are ordered by the day they are due.
+pub async fn due(repository: &Repository,
+                 today: NaiveDate,
+                 until: NaiveDate,
+                 predicate: impl Fn(&Metadata) -> bool) -> Result<Vec<(DocId, Metadata)>> {
+    let mut docs = Vec::new();
+
+    for bundle in repository.inbox().list().await? {
+        docs.push((*bundle.id(), bundle.read_metadata().await?));
+    }
+
+    for bundle in repository.archive().list().await? {
+        docs.push((*bundle.id(), bundle.read_metadata().await?));
+    }
+
+    let mut docs = docs.into_iter()
+        .filter(|(_, metadata)| deadline(metadata, today).map_or(false, |due| due <= until))
+        .filter(|(_, metadata)| predicate(metadata))
+        .collect::<Vec<_>>();
+
+    docs.sort_by_key(|(_, metadata)| deadline(metadata, today));
+
+    return Ok(docs);
+}
+
+/// Describes due documents as of the given day.
+pub fn describe(docs: Vec<(DocId, Metadata)>, today: NaiveDate) -> ListResponse {
+    return ListResponse {
+        docs: docs.into_iter()
+            .map(|(id, metadata)| DueInfo {
+                overdue: deadline(&metadata, today).map_or(false, |due| due < today),
+                cancel_by: contracts::cancel_by(&metadata),
+                warranty_expires: warranties::expires(&metadata),
+                doc: (id, metadata).into(),
+            })
+            .collect(),
+    };
+}
+
+/// Periodically notifies about documents approaching their due date.
+///
+/// Each document is notified about once per due date, the notified due dates are kept in a file in the repository.
+pub struct Reminders {
+    config: Config,
+
+    repository: Repository,
+
+    /// Path of the file holding the notified due dates
+    path: PathBuf,
+
+    client: reqwest::Client,
+
+    /// Notifiers compiled into the build as plugins and chat channels announcing due documents
+    notifiers: Vec<Arc<dyn Notifier>>,
+
+    status: Arc<Status>,
+}
+
+impl Reminders {
+    pub async fn from_config(config: Config, repository: Repository, notifiers: Vec<Arc<dyn Notifier>>, status: Arc<Status>) -> Result<Self> {
+        let path = repository.path().join("reminders.json");
+        let client = reqwest::Client::builder().build()?;
+
+        return Ok(Self { config, repository, path, client, notifiers, status });
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval));
+        loop {
+            interval.tick().await;
+
+            match self.remind().await {
+                Ok(0) => {}
+                Ok(notified) => info!("Sent reminders for {} due documents", notified),
+                Err(err) => {
+                    error!("Failed to send reminders: {:#}", err);
+                    self.status.failed("reminders", &err);
+                }
+            }
+        }
+    }
+
+    async fn load(&self) -> Result<HashMap<DocId, NaiveDate>> {
+        return match tokio::fs::read(&self.path).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    async fn save(&self, notified: &HashMap<DocId, NaiveDate>) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(notified)?).await?;
+        return Ok(());
+    }
+
+    /// Notifies about all due documents which have not been notified about yet and returns their number.
+    pub async fn remind(&self) -> Result<usize> {
+        let today = Utc::today().naive_utc();
+
+        let docs = due(&self.repository, today, today + chrono::Duration::days(self.config.lead.into()), |_| true).await?;
+
+        let mut notified = self.load().await?;
+
+        // Forget about documents which are not due anymore, so they are notified again if they become due again
+        notified.retain(|id, _| docs.iter().any(|(doc, _)| doc == id));
+
+        let pending = docs.into_iter()
+            .filter(|(id, metadata)| notified.get(id) != deadline(metadata, today).as_ref())
+            .collect::<Vec<_>>();
+
+        if pending.is_empty() {
+            self.save(&notified).await?;
+            return Ok(0);
+        }
+
+        for (id, metadata) in &pending {
+            notified.insert(*id, deadline(metadata, today).expect("Due document without due date"));
+        }
+
+        let count = pending.len();
+        let response = describe(pending, today);
+
+        if let Some(webhook) = &self.config.webhook {
+            self.client.post(webhook)
+                .json(&response)
+                .send().await?
+                .error_for_status()?;
+        }
+
+        if let Some(email) = &self.config.email {
+            mail(email, format!("{} documents due", count), summary(&response)).await?;
+        }
+
+        if !self.notifiers.is_empty() {
+            let notification = Notification {
+                subject: format!("{} documents due", count),
+                body: summary(&response),
+                docs: response.docs.iter().map(|info| info.doc.id).collect(),
+            };
+
+            for notifier in &self.notifiers {
+                notifier.notify(&notification).await
+                    .with_context(|| format!("Notifier {} failed", notifier.name()))?;
+            }
+        }
+
+        self.save(&notified).await?;
+
+        return Ok(count);
+    }
+}
+
+/// Renders the due documents as plain text.
+fn summary(response: &ListResponse) -> String {
+    let mut text = String::new();
+    for info in &response.docs {
+        let deadline = info.doc.metadata.due.into_iter().chain(info.cancel_by).chain(info.warranty_expires).min();
+        let due = deadline.map(|due| due.format("%Y-%m-%d").to_string()).unwrap_or_default();
+        let title = info.doc.metadata.title.as_deref().unwrap_or("Untitled");
+        let reason = if deadline == info.doc.metadata.due {
+            ""
+        } else if deadline == info.cancel_by {
+            " - cancel by"
+        } else {
+            " - warranty expires"
+        };
+
+        let _ = writeln!(text, "{} {}{}{} ({})", due, title, reason, if info.overdue { " - overdue" } else { "" }, info.doc.id);
+    }
+
+    return text;
+}
+
+pub async fn mail(config: &Email, subject: String, body: String) -> Result<()> {
+    let mut builder = EmailBuilder::new()
+        .from(config.from.as_str())
+        .subject(subject)
+        .text(body);
+    for to in &config.to {
+        builder = builder.to(to.as_str());
+    }
+
+    let email = builder.build()
+        .map_err(|err| anyhow!("Failed to build reminder mail: {}", err))?;
+
+    return mailer::deliver(&config.host, &config.username, &config.password, email).await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use spectral::prelude::*;
+
+    use crate::proto::model::{Kind, PropertyValue};
+
+    use super::*;
+
+    /// Records the notified documents.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<DocId>>);
+
+    #[async_trait]
+    impl Notifier for Recorder {
+        fn name(&self) -> &str { "recorder" }
+
+        async fn notify(&self, notification: &Notification) -> Result<()> {
+            self.0.lock().unwrap().extend(&notification.docs);
+            return Ok(());
+        }
+    }
+
+    async fn inboxed(repository: &Repository, due: Option<NaiveDate>) -> DocId {
+        let staging = repository.stage().await.unwrap();
+
+        Metadata {
+            due,
+            ..Metadata::new()
+        }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        return *staging.create().await.unwrap().id();
+    }
+
+    #[tokio::test]
+    async fn test_remind() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let today = Utc::today().naive_utc();
+        let overdue = inboxed(&repository, Some(today - chrono::Duration::days(1))).await;
+        let soon = inboxed(&repository, Some(today + chrono::Duration::days(3))).await;
+        inboxed(&repository, Some(today + chrono::Duration::days(30))).await;
+        inboxed(&repository, None).await;
+
+        let docs = due(&repository, today, today + chrono::Duration::days(7), |_| true).await.unwrap();
+        assert_that!(docs.iter().map(|(id, _)| *id).collect::<Vec<_>>()).is_equal_to(vec![overdue, soon]);
+
+        let response = describe(docs, today);
+        assert_that!(response.docs[0].overdue).is_true();
+        assert_that!(response.docs[1].overdue).is_false();
+
+        let recorder = Arc::new(Recorder::default());
+
+        let reminders = Reminders::from_config(Config {
+            lead: 7,
+            interval: 60,
+            webhook: None,
+            email: None,
+        }, repository.clone(), vec![recorder.clone() as Arc<dyn Notifier>], Arc::new(Status::new())).await.unwrap();
+
+        assert_that!(reminders.remind().await.unwrap()).is_equal_to(2);
+        assert_that!(*recorder.0.lock().unwrap()).is_equal_to(vec![overdue, soon]);
+
+        // Documents are only notified once per due date
+        assert_that!(reminders.remind().await.unwrap()).is_equal_to(0);
+    }
+
+    #[tokio::test]
+    async fn test_remind_contract() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let today = Utc::today().naive_utc();
+
+        let staging = repository.stage().await.unwrap();
+        let mut metadata = Metadata::new();
+        metadata.properties.insert(contracts::TERM_END.to_string(), PropertyValue::Date(today + chrono::Duration::days(30)));
+        metadata.properties.insert(contracts::NOTICE_PERIOD.to_string(), PropertyValue::String(String::from("4 weeks")));
+        metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        let contract = *staging.create().await.unwrap().id();
+
+        let docs = due(&repository, today, today + chrono::Duration::days(7), |_| true).await.unwrap();
+        assert_that!(docs.iter().map(|(id, _)| *id).collect::<Vec<_>>()).is_equal_to(vec![contract]);
+
+        let response = describe(docs, today);
+        assert_that!(response.docs[0].cancel_by).is_equal_to(Some(today + chrono::Duration::days(2)));
+        assert_that!(response.docs[0].overdue).is_false();
+    }
+
+    #[tokio::test]
+    async fn test_remind_warranty() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let today = Utc::today().naive_utc();
+
+        let receipt = |purchased: NaiveDate| {
+            let mut metadata = Metadata::new();
+            metadata.properties.insert(warranties::PURCHASED.to_string(), PropertyValue::Date(purchased));
+            metadata.properties.insert(warranties::WARRANTY.to_string(), PropertyValue::String(String::from("2 weeks")));
+            return metadata;
+        };
+
+        let staging = repository.stage().await.unwrap();
+        receipt(today - chrono::Duration::days(10)).save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        let expiring = *staging.create().await.unwrap().id();
+
+        // Expired warranties are not due anymore
+        let staging = repository.stage().await.unwrap();
+        receipt(today - chrono::Duration::days(30)).save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        staging.create().await.unwrap();
+
+        let docs = due(&repository, today, today + chrono::Duration::days(7), |_| true).await.unwrap();
+        assert_that!(docs.iter().map(|(id, _)| *id).collect::<Vec<_>>()).is_equal_to(vec![expiring]);
+
+        let response = describe(docs, today);
+        assert_that!(response.docs[0].warranty_expires).is_equal_to(Some(today + chrono::Duration::days(4)));
+    }
+}
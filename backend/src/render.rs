@@ -0,0 +1,142 @@
+use std::io::BufWriter;
+
+use anyhow::{anyhow, Result};
+use printpdf::{BuiltinFont, IndirectFontRef, Line, Mm, PdfDocument, PdfLayerReference, Point};
+use qrcode::{Color, QrCode};
+
+use crate::config::Sheet;
+
+const LINES_PER_PAGE: usize = 50;
+const COLUMNS: usize = 90;
+
+/// Renders plain text lines to a simple monospaced A4 PDF document.
+///
+/// Lines exceeding the page width are wrapped.
+pub fn render_text(title: &str, text: &[String]) -> Result<Vec<u8>> {
+    let lines = text.iter()
+        .flat_map(|line| {
+            let chars = line.chars().collect::<Vec<_>>();
+            if chars.is_empty() {
+                return vec![String::new()];
+            }
+
+            return chars.chunks(COLUMNS)
+                .map(|chunk| chunk.iter().collect::<String>())
+                .collect();
+        })
+        .collect::<Vec<_>>();
+
+    let (doc, page, layer) = PdfDocument::new(title, Mm(210.0), Mm(297.0), "Text");
+    let font = doc.add_builtin_font(BuiltinFont::Courier)?;
+
+    for (i, chunk) in lines.chunks(LINES_PER_PAGE).enumerate() {
+        let layer = if i == 0 {
+            doc.get_page(page).get_layer(layer)
+        } else {
+            let (page, layer) = doc.add_page(Mm(210.0), Mm(297.0), "Text");
+            doc.get_page(page).get_layer(layer)
+        };
+
+        for (j, line) in chunk.iter().enumerate() {
+            layer.use_text(line.as_str(), 10, Mm(15.0), Mm(280.0 - j as f64 * 5.0), &font);
+        }
+    }
+
+    let mut buffer = BufWriter::new(Vec::new());
+    doc.save(&mut buffer)?;
+
+    return Ok(buffer.into_inner()?);
+}
+
+/// Draws a label with a QR code of the link and a caption next to it at the given position.
+fn draw_label(layer: &PdfLayerReference, font: &IndirectFontRef,
+              caption: &str, link: &str,
+              x: f64, y: f64, height: f64) -> Result<()> {
+    const MARGIN: f64 = 2.0;
+
+    let code = QrCode::new(link.as_bytes())?;
+    let modules = code.width();
+
+    // The QR code fills the height of the label
+    let size = height - 2.0 * MARGIN;
+    let module = size / modules as f64;
+
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color != Color::Dark {
+            continue;
+        }
+
+        let left = x + MARGIN + (i % modules) as f64 * module;
+        let bottom = y + height - MARGIN - (i / modules + 1) as f64 * module;
+
+        layer.add_shape(Line {
+            points: vec![
+                (Point::new(Mm(left), Mm(bottom)), false),
+                (Point::new(Mm(left + module), Mm(bottom)), false),
+                (Point::new(Mm(left + module), Mm(bottom + module)), false),
+                (Point::new(Mm(left), Mm(bottom + module)), false),
+            ],
+            is_closed: true,
+            has_fill: true,
+            has_stroke: false,
+            is_clipping_path: false,
+        });
+    }
+
+    layer.use_text(caption, 12, Mm(x + size + 3.0 * MARGIN), Mm(y + height / 2.0 - 2.0), font);
+
+    return Ok(());
+}
+
+/// Renders a label for the paper original of a document with a QR code of the given link and a caption next to it.
+pub fn render_label(caption: &str, link: &str, width: f64, height: f64) -> Result<Vec<u8>> {
+    let (doc, page, layer) = PdfDocument::new(caption, Mm(width), Mm(height), "Label");
+    let layer = doc.get_page(page).get_layer(layer);
+    let font = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+
+    draw_label(&layer, &font, caption, link, 0.0, 0.0, height)?;
+
+    let mut buffer = BufWriter::new(Vec::new());
+    doc.save(&mut buffer)?;
+
+    return Ok(buffer.into_inner()?);
+}
+
+/// Renders labels given as caption and link onto sheets, filling rows from the top left.
+///
+/// The first `skip` positions are left empty to allow re-using partially used sheets.
+pub fn render_sheet(labels: &[(String, String)], layout: &Sheet, skip: usize) -> Result<Vec<u8>> {
+    let per_page = layout.columns * layout.rows;
+    if per_page == 0 {
+        return Err(anyhow!("Sheet layout without labels"));
+    }
+
+    let (doc, page, layer) = PdfDocument::new("Labels", Mm(layout.page_width), Mm(layout.page_height), "Labels");
+    let font = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+
+    let mut layers = vec![doc.get_page(page).get_layer(layer)];
+
+    for (i, (caption, link)) in labels.iter().enumerate() {
+        let position = skip + i;
+
+        let page = position / per_page;
+        while layers.len() <= page {
+            let (page, layer) = doc.add_page(Mm(layout.page_width), Mm(layout.page_height), "Labels");
+            layers.push(doc.get_page(page).get_layer(layer));
+        }
+
+        let column = (position % per_page) % layout.columns;
+        let row = (position % per_page) / layout.columns;
+
+        // PDF coordinates start at the bottom left corner of the page
+        let x = layout.margin_left + column as f64 * (layout.width + layout.gap_x);
+        let y = layout.page_height - layout.margin_top - (row + 1) as f64 * layout.height - row as f64 * layout.gap_y;
+
+        draw_label(&layers[page], &font, caption, link, x, y, layout.height)?;
+    }
+
+    let mut buffer = BufWriter::new(Vec::new());
+    doc.save(&mut buffer)?;
+
+    return Ok(buffer.into_inner()?);
+}
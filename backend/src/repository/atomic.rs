@@ -0,0 +1,189 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use log::warn;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::proto::model::DocId;
+
+/// Suffix of the temporary files fragments are written to before they replace the fragment
+const TEMP: &str = ".tmp";
+
+/// Returns a unique temporary path next to the given one.
+///
+/// Temporary files are hidden and never listed as fragments, so a write interrupted by a crash leaves the fragment
+/// either untouched or missing, but never truncated.
+fn temp(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    return path.with_file_name(format!(".{}.{}{}", name, DocId::random(), TEMP));
+}
+
+/// Returns true if the name is the one of a temporary file left behind by an interrupted write.
+pub(super) fn is_temp(name: &str) -> bool {
+    return name.starts_with('.') && name.ends_with(TEMP);
+}
+
+/// Persists the directory entries of a directory, i.e. after a file has been renamed into it.
+pub(super) async fn sync_dir(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    return Ok(tokio::task::spawn_blocking(move || std::fs::File::open(path)?.sync_all()).await??);
+}
+
+/// Moves a synced temporary file in place of the target.
+async fn replace(temp: &Path, path: &Path) -> Result<()> {
+    tokio::fs::rename(temp, path).await?;
+
+    if let Some(parent) = path.parent() {
+        sync_dir(parent).await?;
+    }
+
+    return Ok(());
+}
+
+/// Atomically replaces the contents of a file.
+pub(super) async fn write(path: impl AsRef<Path>, data: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let temp = temp(path);
+
+    let mut file = File::create(&temp).await?;
+    let result = async {
+        file.write_all(data).await?;
+        file.sync_all().await?;
+        return Result::<_, anyhow::Error>::Ok(());
+    }.await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(err);
+    }
+
+    return replace(&temp, path).await;
+}
+
+/// A fragment being written to a temporary file, which replaces the fragment once committed.
+///
+/// Committing syncs the written data before the fragment is replaced. Fragments dropped without being committed are
+/// discarded, so a write failing half way leaves the fragment untouched.
+pub struct Fragment {
+    file: File,
+
+    path: PathBuf,
+
+    /// The temporary file, unless already committed
+    temp: Option<PathBuf>,
+}
+
+impl Fragment {
+    pub(super) async fn create(path: PathBuf) -> Result<Self> {
+        let temp = temp(&path);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+
+        return Ok(Self { file, path, temp: Some(temp) });
+    }
+
+    /// Syncs the written data and replaces the fragment.
+    pub async fn commit(mut self) -> Result<()> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+
+        if let Some(temp) = self.temp.take() {
+            replace(&temp, &self.path).await?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl Drop for Fragment {
+    fn drop(&mut self) {
+        if let Some(temp) = self.temp.take() {
+            if let Err(err) = std::fs::remove_file(&temp) {
+                warn!("Failed to discard uncommitted fragment {:?}: {}", temp, err);
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Fragment {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        return Pin::new(&mut self.file).poll_write(cx, buf);
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        return Pin::new(&mut self.file).poll_flush(cx);
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        return Pin::new(&mut self.file).poll_shutdown(cx);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fragment");
+
+        write(&path, b"first").await.unwrap();
+        write(&path, b"second").await.unwrap();
+
+        assert_that!(tokio::fs::read(&path).await.unwrap()).is_equal_to(b"second".to_vec());
+        assert_that!(std::fs::read_dir(dir.path()).unwrap().count()).is_equal_to(1);
+    }
+
+    #[tokio::test]
+    async fn test_fragment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fragment");
+
+        tokio::fs::write(&path, b"previous").await.unwrap();
+
+        let mut fragment = Fragment::create(path.clone()).await.unwrap();
+        fragment.write_all(b"partial").await.unwrap();
+        fragment.flush().await.unwrap();
+
+        // The fragment is untouched until the written data is committed
+        assert_that!(tokio::fs::read(&path).await.unwrap()).is_equal_to(b"previous".to_vec());
+
+        let names = std::fs::read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| is_temp(name))
+            .count();
+        assert_that!(names).is_equal_to(1);
+
+        fragment.commit().await.unwrap();
+
+        assert_that!(tokio::fs::read(&path).await.unwrap()).is_equal_to(b"partial".to_vec());
+        assert_that!(std::fs::read_dir(dir.path()).unwrap().count()).is_equal_to(1);
+    }
+
+    #[tokio::test]
+    async fn test_fragment_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fragment");
+
+        tokio::fs::write(&path, b"previous").await.unwrap();
+
+        let mut fragment = Fragment::create(path.clone()).await.unwrap();
+        fragment.write_all(b"partial").await.unwrap();
+        drop(fragment);
+
+        // Uncommitted data is discarded along with the temporary file
+        assert_that!(tokio::fs::read(&path).await.unwrap()).is_equal_to(b"previous".to_vec());
+        assert_that!(std::fs::read_dir(dir.path()).unwrap().count()).is_equal_to(1);
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+use log::{debug, warn};
+use rand::Rng;
+use tokio::fs::File;
+
+use crate::config::Cache as Config;
+use crate::proto::model::{DocId, Kind};
+
+use super::{Filename, shred};
+
+/// Checks if fragments of a kind are cached, which are the documents and previews viewed repeatedly.
+fn is_cached(kind: &Kind) -> bool {
+    return matches!(kind, Kind::Document | Kind::Preview | Kind::Page(_) | Kind::Thumbnail);
+}
+
+struct Entry {
+    size: u64,
+
+    /// The tick of the last use
+    used: u64,
+}
+
+/// The copies held by the cache in the order they have been used.
+#[derive(Default)]
+struct Entries {
+    /// Copies by their file name
+    files: HashMap<String, Entry>,
+
+    /// File names of the copies by the tick of their last use
+    lru: BTreeMap<u64, String>,
+
+    /// Total size of all copies
+    size: u64,
+
+    ticks: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.ticks += 1;
+        return self.ticks;
+    }
+
+    /// Marks a copy as used, returns `false` if it is not known.
+    fn touch(&mut self, name: &str) -> bool {
+        let tick = self.tick();
+
+        let entry = match self.files.get_mut(name) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        self.lru.remove(&entry.used);
+        self.lru.insert(tick, name.to_string());
+        entry.used = tick;
+
+        return true;
+    }
+
+    fn insert(&mut self, name: String, size: u64) {
+        self.remove(&name);
+
+        let tick = self.tick();
+        self.lru.insert(tick, name.clone());
+        self.files.insert(name, Entry { size, used: tick });
+        self.size += size;
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some(entry) = self.files.remove(name) {
+            self.lru.remove(&entry.used);
+            self.size -= entry.size;
+        }
+    }
+
+    /// Removes the least recently used copies until the total size is within the bound and returns their names.
+    fn evict(&mut self, bound: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.size > bound {
+            let name = match self.lru.values().next() {
+                Some(name) => name.clone(),
+                None => break,
+            };
+
+            self.remove(&name);
+            evicted.push(name);
+        }
+
+        return evicted;
+    }
+}
+
+/// Local copies of the fragments read from the repository, for repositories on network filesystems.
+///
+/// If the repository is kept on a remote storage like an NFS share or a mounted S3 bucket or WebDAV server, every read
+/// has to go over the network. Documents and previews are copied to a local directory on first read instead and are
+/// served from there, evicting the least recently used copies beyond the size bound.
+///
+/// Copies are named by the size and modification time of the fragment, so changed fragments are copied again, while
+/// the outdated copies are never used again and will be evicted eventually.
+pub(super) struct Cache {
+    path: PathBuf,
+
+    /// Size bound of all copies in bytes
+    size: u64,
+
+    entries: Mutex<Entries>,
+}
+
+impl Cache {
+    pub(super) async fn from_config(config: Config) -> Result<Self> {
+        return Self::open(config.path, config.size * 1024 * 1024).await;
+    }
+
+    /// Opens the cache directory and takes over the copies from before in the order they have been made.
+    pub(super) async fn open(path: impl Into<PathBuf>, size: u64) -> Result<Self> {
+        let path = path.into();
+        tokio::fs::create_dir_all(&path).await?;
+
+        let mut copies = Vec::new();
+
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            // Copies interrupted before
+            if name.starts_with('.') {
+                tokio::fs::remove_file(entry.path()).await?;
+                continue;
+            }
+
+            let metadata = entry.metadata().await?;
+            copies.push((metadata.modified()?, name, metadata.len()));
+        }
+
+        copies.sort();
+
+        let mut entries = Entries::default();
+        for (_, name, size) in copies {
+            entries.insert(name, size);
+        }
+
+        let cache = Self {
+            path,
+            size,
+            entries: Mutex::new(entries),
+        };
+
+        // The size bound may have been lowered since
+        let evicted = cache.entries.lock().expect("Cache poisoned").evict(size);
+        cache.remove(evicted).await;
+
+        return Ok(cache);
+    }
+
+    /// Removes the copies of a bundle, which are overwritten before if requested.
+    pub(super) async fn forget(&self, id: &DocId, shred: bool) {
+        let prefix = format!("{}.", id);
+
+        let names = {
+            let mut entries = self.entries.lock().expect("Cache poisoned");
+            let names = entries.files.keys()
+                .filter(|name| name.starts_with(&prefix))
+                .cloned()
+                .collect::<Vec<_>>();
+            names.iter().for_each(|name| entries.remove(name));
+            names
+        };
+
+        if shred {
+            for name in &names {
+                if let Err(err) = shred::shred_file(self.path.join(name)).await {
+                    warn!("Failed to shred cached fragment {}: {:#}", name, err);
+                }
+            }
+        }
+
+        self.remove(names).await;
+    }
+
+    async fn remove(&self, names: Vec<String>) {
+        for name in names {
+            if let Err(err) = tokio::fs::remove_file(self.path.join(&name)).await {
+                warn!("Failed to evict cached fragment {}: {}", name, err);
+            }
+        }
+    }
+
+    /// Opens a fragment, preferring the local copy and copying the fragment if there is none.
+    ///
+    /// Fragments are opened directly if not cached by kind or if they exceed the cache. If the copy fails, the
+    /// fragment is opened directly as well, so a full local disk does not keep the documents from being read.
+    pub(super) async fn open_fragment(&self, source: &Path, id: &DocId, kind: &Kind) -> std::io::Result<File> {
+        if !is_cached(kind) {
+            return File::open(source).await;
+        }
+
+        let metadata = tokio::fs::metadata(source).await?;
+        if metadata.len() > self.size {
+            return File::open(source).await;
+        }
+
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)
+            .map_or(0, |modified| modified.as_nanos());
+
+        let name = format!("{}.{}.{}.{}", id, kind.filename().to_string_lossy(), metadata.len(), modified);
+        let path = self.path.join(&name);
+
+        // The copy may have been evicted concurrently, in which case it is copied again
+        let known = self.entries.lock().expect("Cache poisoned").touch(&name);
+        if known {
+            if let Ok(file) = File::open(&path).await {
+                return Ok(file);
+            }
+        }
+
+        if let Err(err) = self.copy(source, &name, metadata.len()).await {
+            warn!("Failed to cache fragment {:?}: {}", source, err);
+            return File::open(source).await;
+        }
+
+        return match File::open(&path).await {
+            Ok(file) => Ok(file),
+            Err(_) => File::open(source).await,
+        };
+    }
+
+    async fn copy(&self, source: &Path, name: &str, size: u64) -> std::io::Result<()> {
+        debug!("Caching fragment {:?}", source);
+
+        // Copied to a temporary file first, so a copy is never opened while incomplete
+        let temp = self.path.join(format!(".{}.{}", name, hex::encode(rand::thread_rng().gen::<[u8; 4]>())));
+
+        if let Err(err) = tokio::fs::copy(source, &temp).await {
+            tokio::fs::remove_file(&temp).await.ok();
+            return Err(err);
+        }
+
+        tokio::fs::rename(&temp, self.path.join(name)).await?;
+
+        let evicted = {
+            let mut entries = self.entries.lock().expect("Cache poisoned");
+            entries.insert(name.to_string(), size);
+            entries.evict(self.size)
+        };
+
+        self.remove(evicted).await;
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_evict() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("a"), 3);
+        entries.insert(String::from("b"), 3);
+        entries.insert(String::from("c"), 3);
+
+        assert_that!(entries.touch("a")).is_true();
+        assert_that!(entries.touch("d")).is_false();
+
+        assert_that!(entries.evict(6)).is_equal_to(vec![String::from("b")]);
+        assert_that!(entries.evict(3)).is_equal_to(vec![String::from("c")]);
+        assert_that!(entries.size).is_equal_to(3);
+
+        entries.insert(String::from("a"), 4);
+        assert_that!(entries.size).is_equal_to(4);
+        assert_that!(entries.evict(3)).is_equal_to(vec![String::from("a")]);
+        assert_that!(entries.lru.is_empty()).is_true();
+    }
+}
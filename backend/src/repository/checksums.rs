@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+/// SHA-256 checksums of all fragments of a bundle, keyed by fragment filename.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(transparent)]
+pub struct Checksums(BTreeMap<String, String>);
+
+impl Checksums {
+    pub const FILENAME: &'static str = "checksums.json";
+
+    /// Returns true if the fragment with the given filename is covered by checksums.
+    pub fn covers(name: &str) -> bool {
+        return name != Self::FILENAME && name != super::Manifest::FILENAME && name != "trashed";
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> { self.0.get(name).map(String::as_str) }
+
+    pub fn insert(&mut self, name: impl Into<String>, checksum: String) { self.0.insert(name.into(), checksum); }
+
+    pub fn remove(&mut self, name: &str) { self.0.remove(name); }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        return self.0.iter().map(|(name, checksum)| (name.as_str(), checksum.as_str()));
+    }
+
+    pub async fn load(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        match tokio::fs::read(path).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        super::atomic::write(path, &serde_json::to_vec_pretty(self)?).await?;
+
+        return Ok(());
+    }
+}
+
+/// Calculates the hex encoded SHA-256 checksum of a file.
+pub async fn sha256(path: impl AsRef<Path>) -> Result<String> {
+    let mut file = File::open(path).await?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..n]);
+    }
+
+    return Ok(hex::encode(hasher.finalize()));
+}
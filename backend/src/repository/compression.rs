@@ -0,0 +1,131 @@
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+
+use crate::config::Compression as Config;
+use crate::proto::model::Kind;
+
+/// Magic number every zstd frame starts with
+const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Kinds of fragments which can be compressed.
+///
+/// All other fragments, like the document and its previews, are handed to external tools and served by their path, so
+/// they must be stored as they are.
+const COMPRESSIBLE: &[Kind] = &[Kind::Plaintext, Kind::Metadata];
+
+/// Transparent compression of fragments.
+///
+/// Only the configured kinds are compressed when written. Compressed fragments are marked as such in the manifest of
+/// their bundle and decompressed when read, which keeps them readable after compression has been disabled, while
+/// fragments written before it was enabled are read as they are.
+#[derive(Clone, Default)]
+pub(super) struct Compression {
+    kinds: Arc<Vec<Kind>>,
+
+    level: i32,
+}
+
+impl Compression {
+    pub(super) fn from_config(config: Config) -> Result<Self> {
+        let kinds = config.fragments.into_iter().map(Kind::from).collect::<Vec<_>>();
+
+        if let Some(kind) = kinds.iter().find(|kind| !COMPRESSIBLE.contains(kind)) {
+            bail!("Fragments of kind {:?} can not be compressed, as they are read from disk directly", kind);
+        }
+
+        return Ok(Self {
+            kinds: Arc::new(kinds),
+            level: config.level,
+        });
+    }
+
+    /// The kinds of fragments compressed when written.
+    pub(super) fn kinds(&self) -> impl Iterator<Item=&Kind> {
+        return self.kinds.iter();
+    }
+
+    /// Checks if fragments of the given kind are compressed when written.
+    pub(super) fn applies(&self, kind: &Kind) -> bool {
+        return self.kinds.contains(kind);
+    }
+
+    /// Compresses the data to write for a fragment of the given kind, if configured.
+    pub(super) fn encode<'d>(&self, kind: &Kind, data: &'d [u8]) -> Result<Cow<'d, [u8]>> {
+        if !self.applies(kind) {
+            return Ok(Cow::Borrowed(data));
+        }
+
+        let mut encoder = zstd::stream::Encoder::new(Vec::new(), self.level)?;
+        encoder.include_checksum(true)?;
+
+        encoder.write_all(data)?;
+        return Ok(Cow::Owned(encoder.finish()?));
+    }
+}
+
+/// Checks if the data starts with a zstd frame.
+///
+/// This does not tell if a fragment is compressed, as the marker in the manifest does, but allows to skip looking it
+/// up for fragments which can not be compressed.
+pub(super) fn is_frame(data: &[u8]) -> bool {
+    return data.starts_with(&MAGIC);
+}
+
+/// Decompresses the contents of a fragment marked as compressed.
+///
+/// Data which is not a frame is taken as it is, as a fragment is marked before it is written compressed.
+pub(super) fn decode(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_frame(&data) {
+        return Ok(data);
+    }
+
+    return Ok(zstd::stream::decode_all(&data[..])?);
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let compression = Compression::from_config(Config::default()).unwrap();
+
+        let text = "All work and no play makes Jack a dull boy.\n".repeat(100);
+
+        let encoded = compression.encode(&Kind::Plaintext, text.as_bytes()).unwrap();
+        assert_that!(is_frame(&encoded)).is_true();
+        assert_that!(encoded.len()).is_less_than(text.len());
+        assert_that!(decode(encoded.into_owned()).unwrap()).is_equal_to(text.as_bytes().to_vec());
+
+        // Data looking like a frame is compressed nevertheless, so it is read back as written
+        let framed = vec![0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x42];
+        let encoded = compression.encode(&Kind::Plaintext, &framed).unwrap();
+        assert_that!(decode(encoded.into_owned()).unwrap()).is_equal_to(framed);
+
+        // Other kinds are written as they are
+        let encoded = compression.encode(&Kind::Metadata, b"{}").unwrap();
+        assert_that!(encoded.as_ref()).is_equal_to(&b"{}"[..]);
+
+        // Uncompressed data is read as it is
+        assert_that!(decode(text.as_bytes().to_vec()).unwrap()).is_equal_to(text.as_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_compressible() {
+        let config = |fragments: &[&str]| Config {
+            fragments: fragments.iter().map(|fragment| fragment.to_string()).collect(),
+            level: 3,
+        };
+
+        assert_that!(Compression::from_config(config(&["plaintext", "metadata"])).is_ok()).is_true();
+
+        // The document is rendered and served from disk
+        assert_that!(Compression::from_config(config(&["plaintext", "document"])).is_err()).is_true();
+        assert_that!(Compression::from_config(config(&["original.pdf"])).is_err()).is_true();
+    }
+}
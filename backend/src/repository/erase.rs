@@ -0,0 +1,65 @@
+use anyhow::Result;
+use log::info;
+
+use crate::proto::model::DocId;
+
+use super::{Archived, Bundle, BundleState, Entry, Event, Failed, get, Inboxed, Quarantined, Repository, shred, Staging, Trashed};
+
+impl<State: BundleState> Bundle<'_, State> {
+    /// Shreds and removes the bundle regardless of the shredding configuration.
+    async fn erase(self) -> Result<()> {
+        let _modifying = self.modify().await?;
+
+        self.ensure_deletable().await?;
+
+        info!("Erasing bundle {:?}", self.path());
+        shred::shred_dir(&self.path()).await?;
+        tokio::fs::remove_dir_all(&self.path()).await?;
+
+        if let Some(cache) = &self.repository.cache {
+            cache.forget(&self.id, true).await;
+        }
+
+        return Ok(());
+    }
+}
+
+impl Repository {
+    /// Removes all traces of a bundle, i.e. to comply with a request for deletion of personal data.
+    ///
+    /// The bundle is shredded and removed in whichever state it is, and the metadata changes recorded in the journal
+    /// are scrubbed. Subscribers like the index and the preview cache drop the bundle on the published event. A
+    /// tombstone entry is recorded in the journal proving when and by whom the bundle has been erased, which is
+    /// returned. Retained bundles can not be erased.
+    ///
+    /// Returns `None` if no bundle with the given ID exists.
+    pub async fn erase(&self, id: DocId) -> Result<Option<Entry>> {
+        let erased = if let Some(bundle) = get::<Inboxed>(self, id).await {
+            bundle.erase().await.map(Some)
+        } else if let Some(bundle) = get::<Archived>(self, id).await {
+            bundle.erase().await.map(Some)
+        } else if let Some(bundle) = get::<Trashed>(self, id).await {
+            bundle.erase().await.map(Some)
+        } else if let Some(bundle) = get::<Quarantined>(self, id).await {
+            bundle.erase().await.map(Some)
+        } else if let Some(bundle) = get::<Failed>(self, id).await {
+            bundle.erase().await.map(Some)
+        } else if let Some(bundle) = get::<Staging>(self, id).await {
+            bundle.erase().await.map(Some)
+        } else {
+            Ok(None)
+        };
+
+        if erased?.is_none() {
+            return Ok(None);
+        }
+
+        let scrubbed = self.journal.scrub(id).await?;
+        info!("Scrubbed {} journal entries of erased bundle {}", scrubbed, id);
+
+        let tombstone = self.journal.append(Event::Erased(id), self.actor.clone(), Default::default()).await?;
+        self.events.publish(Event::Erased(id));
+
+        return Ok(Some(tombstone));
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::proto::model::DocId;
+
+/// Number of events buffered per subscriber before it starts lagging behind
+const CAPACITY: usize = 256;
+
+/// Events published by the repository on bundle state transitions.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", content = "id", rename_all = "kebab-case")]
+pub enum Event {
+    Staged(DocId),
+    Inboxed(DocId),
+    Archived(DocId),
+    Unarchived(DocId),
+    MetadataUpdated(DocId),
+    Trashed(DocId),
+    Restored(DocId),
+    Purged(DocId),
+    Quarantined(DocId),
+    Erased(DocId),
+}
+
+impl Event {
+    pub fn id(&self) -> &DocId {
+        return match self {
+            Self::Staged(id) |
+            Self::Inboxed(id) |
+            Self::Archived(id) |
+            Self::Unarchived(id) |
+            Self::MetadataUpdated(id) |
+            Self::Trashed(id) |
+            Self::Restored(id) |
+            Self::Purged(id) |
+            Self::Quarantined(id) |
+            Self::Erased(id) => id,
+        };
+    }
+
+    /// The name of the event as used in its serialized form.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Self::Staged(_) => "staged",
+            Self::Inboxed(_) => "inboxed",
+            Self::Archived(_) => "archived",
+            Self::Unarchived(_) => "unarchived",
+            Self::MetadataUpdated(_) => "metadata-updated",
+            Self::Trashed(_) => "trashed",
+            Self::Restored(_) => "restored",
+            Self::Purged(_) => "purged",
+            Self::Quarantined(_) => "quarantined",
+            Self::Erased(_) => "erased",
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Events(broadcast::Sender<Event>);
+
+impl Events {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        return Self(sender);
+    }
+
+    pub fn publish(&self, event: Event) {
+        // Sending only fails if there are no subscribers, which is fine
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        return self.0.subscribe();
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self { Self::new() }
+}
@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+
+use super::{Checksums, Repository, Snapshot, Snapshotted};
+use super::snapshot::is_mutable;
+
+/// Reads the recorded checksums of a bundle, if any.
+fn checksums(bundle: &Path) -> Option<Checksums> {
+    let data = std::fs::read(bundle.join(Checksums::FILENAME)).ok()?;
+    return serde_json::from_slice(&data).ok();
+}
+
+/// Links or copies the fragments of a bundle as captured by the snapshot.
+///
+/// Fragments modified in place are written from the snapshot. Others which can not be linked from the source are
+/// linked from the base instead if the checksums recorded in the base match, which makes forks to another filesystem
+/// incremental. Copied fragments are verified against the snapshot.
+fn fork_bundle(snapshot: &Snapshot, bundle: &Snapshotted, source: &Path, target: &Path, base: Option<&Path>) -> Result<()> {
+    std::fs::create_dir_all(target)?;
+
+    let base = base.and_then(|base| Some((base, checksums(base)?)));
+
+    for name in bundle.fragment_names() {
+        let source = source.join(name);
+        let target = target.join(name);
+
+        if is_mutable(name) {
+            std::fs::write(&target, snapshot.read(bundle, name)?)
+                .with_context(|| format!("Writing {:?}", target))?;
+            continue;
+        }
+
+        if std::fs::hard_link(&source, &target).is_ok() {
+            continue;
+        }
+
+        // Fall back to copying if linking is not possible, i.e. across filesystems
+        let linked = match &base {
+            Some((base, checksums)) if checksums.get(name).map(String::from) == bundle.checksum(name) => {
+                std::fs::hard_link(base.join(name), &target).is_ok()
+            }
+            _ => false,
+        };
+        if !linked {
+            std::fs::write(&target, snapshot.read(bundle, name)?)
+                .with_context(|| format!("Copying {:?} to {:?}", source, target))?;
+        }
+    }
+
+    return Ok(());
+}
+
+impl Repository {
+    /// Creates a space-efficient copy of the repository at the given path.
+    ///
+    /// Immutable fragments are hard-linked into the fork while mutable ones like the metadata are copied. The fork is
+    /// taken from a snapshot, so bundles changed while forking are consistent. Bundles currently being staged are not
+    /// forked.
+    pub async fn fork(&self, path: impl AsRef<Path> + Send + Sync + 'static) -> Result<Repository> {
+        return self.fork_from(path, None).await;
+    }
+
+    /// Creates a copy of the repository at the given path, sharing unchanged fragments with a previous fork.
+    ///
+    /// This keeps backups to another filesystem incremental, where fragments can not be linked from the repository.
+    pub async fn fork_from(&self, path: impl AsRef<Path> + Send + Sync + 'static, base: Option<PathBuf>) -> Result<Repository> {
+        let target: PathBuf = path.as_ref().to_path_buf();
+
+        if target.exists() && target.read_dir()?.next().is_some() {
+            bail!("Fork target is not empty: {:?}", target);
+        }
+
+        info!("Forking repository {:?} -> {:?}", self.path(), target);
+
+        let snapshot = self.snapshot().await?;
+
+        let fork = Repository::with_path(path).await?;
+
+        let source = self.path().to_path_buf();
+        let target = fork.path().to_path_buf();
+
+        let bundles = tokio::task::spawn_blocking(move || -> Result<usize> {
+            // Bundles are placed in the fork and the base like in the repository
+            for bundle in snapshot.bundles() {
+                fork_bundle(&snapshot,
+                            bundle,
+                            &source.join(bundle.path()),
+                            &target.join(bundle.path()),
+                            base.as_ref().map(|base| base.join(bundle.path())).as_deref())?;
+            }
+
+            return Ok(snapshot.bundles().len());
+        }).await??;
+
+        info!("Forked {} bundles", bundles);
+
+        return Ok(fork);
+    }
+}
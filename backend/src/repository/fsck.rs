@@ -0,0 +1,171 @@
+use std::fmt;
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::proto::model::{DocId, Kind};
+
+use super::{Archived, Bundle, BundleState, Filename, Repository};
+use super::checksums::{Checksums, sha256};
+
+/// An integrity problem found in a bundle.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Problem {
+    MissingChecksums,
+    MissingFragment { name: String },
+    Corrupted { name: String, expected: String, actual: String },
+    Unchecked { name: String },
+    InvalidMetadata { error: String },
+    PageCount { recorded: u32, actual: u32 },
+}
+
+impl Problem {
+    /// The kind of the problem as used for serialization.
+    pub fn kind(&self) -> &'static str {
+        return match self {
+            Self::MissingChecksums => "missing-checksums",
+            Self::MissingFragment { .. } => "missing-fragment",
+            Self::Corrupted { .. } => "corrupted",
+            Self::Unchecked { .. } => "unchecked",
+            Self::InvalidMetadata { .. } => "invalid-metadata",
+            Self::PageCount { .. } => "page-count",
+        };
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::MissingChecksums => write!(f, "no checksums recorded"),
+            Self::MissingFragment { name } => write!(f, "fragment missing: {}", name),
+            Self::Corrupted { name, expected, actual } => write!(f, "fragment corrupted: {} (expected {}, got {})", name, expected, actual),
+            Self::Unchecked { name } => write!(f, "fragment without checksum: {}", name),
+            Self::InvalidMetadata { error } => write!(f, "invalid metadata: {}", error),
+            Self::PageCount { recorded, actual } => write!(f, "page count mismatch: {} recorded, {} in plaintext", recorded, actual),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub bundles: usize,
+    pub problems: Vec<(DocId, Problem)>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool { self.problems.is_empty() }
+}
+
+/// The integrity of a single bundle.
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    pub id: DocId,
+    pub problems: Vec<Problem>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool { self.problems.is_empty() }
+}
+
+/// Fragments every bundle past staging must have.
+const EXPECTED: &[Kind] = &[Kind::Document, Kind::Plaintext, Kind::Metadata];
+
+impl<State: BundleState> Bundle<'_, State> {
+    /// Verifies the integrity of the bundle.
+    ///
+    /// Besides checking the fragments against their recorded checksums, this ensures the expected fragments exist and
+    /// the recorded page count agrees with the pages of the plaintext. The contents of encrypted bundles are not
+    /// checked.
+    pub async fn verify(&self) -> Result<Verification> {
+        let mut problems = self.check_fragments().await?;
+
+        let names = self.fragment_names().await?;
+        for kind in EXPECTED {
+            let name = kind.filename().to_string_lossy().into_owned();
+            if !names.contains(&name) && !problems.contains(&Problem::MissingFragment { name: name.clone() }) {
+                problems.push(Problem::MissingFragment { name });
+            }
+        }
+
+        if let Ok(metadata) = self.read_metadata().await {
+            if metadata.domain.is_none() && names.iter().any(|name| Kind::Plaintext.filename() == name.as_str()) {
+                let plaintext = self.read_plaintext().await?;
+
+                // Every page is terminated by a form feed, but the last one may lack it
+                let actual = plaintext.matches('\x0c').count()
+                    + if plaintext.is_empty() || plaintext.ends_with('\x0c') { 0 } else { 1 };
+                let actual = actual as u32;
+
+                if actual != metadata.pages {
+                    problems.push(Problem::PageCount { recorded: metadata.pages, actual });
+                }
+            }
+        }
+
+        return Ok(Verification { id: self.id, problems });
+    }
+
+    pub(super) async fn check_fragments(&self) -> Result<Vec<Problem>> {
+        let mut problems = Vec::new();
+
+        if let Err(err) = self.read_metadata().await {
+            problems.push(Problem::InvalidMetadata { error: format!("{:#}", err) });
+        }
+
+        let checksums = match self.read_checksums().await? {
+            Some(checksums) => checksums,
+            None => {
+                problems.push(Problem::MissingChecksums);
+                return Ok(problems);
+            }
+        };
+
+        let names = self.fragment_names().await?;
+
+        for (name, expected) in checksums.iter() {
+            if !names.iter().any(|n| n == name) {
+                problems.push(Problem::MissingFragment { name: name.to_string() });
+                continue;
+            }
+
+            let actual = sha256(self.path().join(name)).await?;
+            if actual != expected {
+                problems.push(Problem::Corrupted {
+                    name: name.to_string(),
+                    expected: expected.to_string(),
+                    actual,
+                });
+            }
+        }
+
+        for name in names {
+            if Checksums::covers(&name) && checksums.get(&name).is_none() {
+                problems.push(Problem::Unchecked { name });
+            }
+        }
+
+        return Ok(problems);
+    }
+}
+
+impl Repository {
+    /// Walks the archive and verifies all fragments against their recorded checksums.
+    pub async fn verify(&self) -> Result<Report> {
+        let mut report = Report::default();
+
+        for bundle in super::list::<Archived>(self).await? {
+            report.bundles += 1;
+
+            for problem in bundle.check_fragments().await? {
+                warn!("Bundle {}: {}", bundle.id(), problem);
+                report.problems.push((*bundle.id(), problem));
+            }
+        }
+
+        info!("Verified {} bundles: {} problems", report.bundles, report.problems.len());
+
+        return Ok(report);
+    }
+}
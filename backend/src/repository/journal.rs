@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::meta::Metadata;
+use crate::proto::model::DocId;
+
+use super::{atomic, Event};
+
+/// A changed metadata field.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub before: Value,
+    pub after: Value,
+}
+
+/// The changed metadata fields by name.
+pub type Diff = BTreeMap<String, Change>;
+
+/// Calculates the changes between two versions of metadata.
+pub fn diff(before: &Metadata, after: &Metadata) -> Result<Diff> {
+    let before = serde_json::to_value(before)?;
+    let after = serde_json::to_value(after)?;
+
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    return Ok(before.keys().chain(after.keys())
+        .filter_map(|key| {
+            let before = before.get(key).cloned().unwrap_or(Value::Null);
+            let after = after.get(key).cloned().unwrap_or(Value::Null);
+
+            return (before != after).then_some((key.clone(), Change { before, after }));
+        })
+        .collect());
+}
+
+/// An event recorded in the journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Monotonically increasing sequence number usable as a cursor
+    pub seq: u64,
+
+    pub time: DateTime<Utc>,
+
+    pub change: Event,
+
+    /// The user who caused the change, if triggered by a request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+
+    /// Changed metadata fields
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub diff: Diff,
+}
+
+/// Append-only log of all events published by the repository.
+///
+/// Besides serving as a change feed, the journal is the audit log of the repository: each entry records who caused the
+/// change and how the metadata was modified. The journal is stored as a file with one JSON encoded entry per line.
+pub struct Journal {
+    path: PathBuf,
+
+    /// Sequence number of the last entry written
+    seq: Mutex<u64>,
+}
+
+impl Journal {
+    pub const FILENAME: &'static str = "journal.jsonl";
+
+    pub async fn open(path: PathBuf) -> Result<Self> {
+        let seq = Self::read(&path).await?.last()
+            .map_or(0, |entry| entry.seq);
+
+        return Ok(Self { path, seq: Mutex::new(seq) });
+    }
+
+    async fn read(path: &PathBuf) -> Result<Vec<Entry>> {
+        let data = match tokio::fs::read(path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        return Ok(data.split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<_, _>>()?);
+    }
+
+    pub async fn append(&self, change: Event, actor: Option<String>, diff: Diff) -> Result<Entry> {
+        let mut seq = self.seq.lock().await;
+
+        let entry = Entry {
+            seq: *seq + 1,
+            time: Utc::now(),
+            change,
+            actor,
+            diff,
+        };
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+
+        *seq = entry.seq;
+
+        return Ok(entry);
+    }
+
+    /// Removes the recorded metadata changes from all entries of the given bundle, returning the number of entries changed.
+    ///
+    /// The entries themselves are kept, so the journal still tells when and by whom the bundle has been changed.
+    pub async fn scrub(&self, id: DocId) -> Result<usize> {
+        // Hold off appending while the journal is rewritten
+        let _seq = self.seq.lock().await;
+
+        let mut entries = Self::read(&self.path).await?;
+
+        let mut scrubbed = 0;
+        for entry in entries.iter_mut().filter(|entry| *entry.change.id() == id && !entry.diff.is_empty()) {
+            entry.diff.clear();
+            scrubbed += 1;
+        }
+
+        if scrubbed == 0 {
+            return Ok(0);
+        }
+
+        let mut data = Vec::new();
+        for entry in &entries {
+            data.extend(serde_json::to_vec(entry)?);
+            data.push(b'\n');
+        }
+
+        atomic::write(&self.path, &data).await?;
+
+        return Ok(scrubbed);
+    }
+
+    /// Returns all entries recorded after the given cursor.
+    pub async fn since(&self, cursor: u64) -> Result<Vec<Entry>> {
+        let mut entries = Self::read(&self.path).await?;
+        entries.retain(|entry| entry.seq > cursor);
+
+        return Ok(entries);
+    }
+
+    /// Returns all entries recorded for the given bundle.
+    pub async fn history(&self, id: DocId) -> Result<Vec<Entry>> {
+        let mut entries = Self::read(&self.path).await?;
+        entries.retain(|entry| *entry.change.id() == id);
+
+        return Ok(entries);
+    }
+
+    /// Returns the sequence number of the last recorded entry.
+    pub async fn cursor(&self) -> u64 {
+        return *self.seq.lock().await;
+    }
+}
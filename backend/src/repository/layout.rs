@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use log::info;
+
+use crate::proto::model::DocId;
+
+use super::{Archived, BundleState, Filename, Repository};
+
+/// Lists the partition directories below a state directory.
+///
+/// Partitions are nested two levels deep (i.e. `YYYY/MM`) and never parse as a bundle ID.
+fn partitions(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    fn dirs(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let entries = match std::fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut dirs = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && DocId::from_str(&entry.file_name().to_string_lossy()).is_err() {
+                dirs.push(entry.path());
+            }
+        }
+
+        return Ok(dirs);
+    }
+
+    let mut partitions = Vec::new();
+    for year in dirs(root)? {
+        partitions.extend(dirs(&year)?);
+    }
+
+    return Ok(partitions);
+}
+
+/// Finds the directories of all bundles in the given state together with their modification time.
+pub(super) async fn scan<State: BundleState>(repository: &Repository) -> Result<Vec<(SystemTime, DocId, PathBuf)>> {
+    fn bundles(path: &Path, bundles: &mut Vec<(SystemTime, DocId, PathBuf)>) -> Result<()> {
+        let entries = match std::fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+
+            // Skip partitions as they are scanned on their own
+            let id = match DocId::from_str(&entry.file_name().to_string_lossy()) {
+                Ok(id) => id,
+                Err(_) if entry.file_type()?.is_dir() && State::PARTITIONED => continue,
+                Err(err) => return Err(anyhow!("Invalid bundle {:?}: {}", entry.path(), err)),
+            };
+
+            bundles.push((entry.metadata()?.modified()?, id, entry.path()));
+        }
+
+        return Ok(());
+    }
+
+    let root = State::path(repository);
+    return tokio::task::spawn_blocking(move || -> Result<_> {
+        let mut result = Vec::new();
+        bundles::<State>(&root, &mut result)?;
+
+        if State::PARTITIONED {
+            for partition in partitions(&root)? {
+                bundles::<State>(&partition, &mut result)?;
+            }
+        }
+
+        return Ok(result);
+    }).await?;
+}
+
+/// Finds the directory of the bundle with the given ID in the given state.
+pub(super) async fn locate<State: BundleState>(repository: &Repository, id: DocId) -> Option<PathBuf> {
+    let root = State::path(repository);
+
+    // Bundles in unpartitioned states and not yet migrated bundles are placed in the state directory itself
+    let path = root.join(id.filename());
+    if tokio::fs::metadata(&path).await.is_ok() {
+        return Some(path);
+    }
+
+    if !State::PARTITIONED {
+        return None;
+    }
+
+    return tokio::task::spawn_blocking(move || {
+        return partitions(&root).ok()?.into_iter()
+            .map(|partition| partition.join(id.filename()))
+            .find(|path| path.exists());
+    }).await.ok()?;
+}
+
+impl Repository {
+    /// Moves archived bundles from the flat layout used by earlier versions into their partitions.
+    pub(super) async fn partition_archive(&self) -> Result<usize> {
+        let mut migrated = 0;
+
+        for (_, id, path) in scan::<Archived>(self).await? {
+            if path.parent() != Some(Archived::path(self).as_path()) {
+                continue;
+            }
+
+            let metadata = self.archive().get(id).await
+                .ok_or_else(|| anyhow!("Bundle vanished: {}", id))?
+                .read_metadata().await?;
+
+            let target = Archived::path(self).join(Archived::partition(&metadata)).join(id.filename());
+
+            info!("Migrating archived bundle {:?} -> {:?}", path, target);
+            tokio::fs::create_dir_all(target.parent().expect("No parent directory")).await?;
+            tokio::fs::rename(&path, &target).await?;
+
+            migrated += 1;
+        }
+
+        return Ok(migrated);
+    }
+}
@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+
+use crate::meta::Metadata;
+use crate::proto::query::{Sort, SortKey};
+
+/// Sorting and pagination of a listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    /// The sort order, the natural order of the source is kept if unset
+    pub sort: Option<Sort>,
+
+    /// Items of the same group are listed together, ordered by the sort order within the group
+    pub group: Option<Grouping>,
+
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// Attributes listings can be grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    Correspondent,
+    BelongsTo,
+}
+
+impl Grouping {
+    /// The key of the group the item belongs to, items without key are grouped together.
+    pub fn key(&self, metadata: &Metadata) -> Option<String> {
+        return match self {
+            Self::Correspondent => metadata.correspondent.clone(),
+            Self::BelongsTo => metadata.belongs_to.clone(),
+        };
+    }
+}
+
+/// A slice of a sorted listing.
+#[derive(Debug)]
+pub struct Page<T> {
+    /// Number of items in the whole listing
+    pub total: usize,
+
+    /// Keys and sizes of all groups of the whole listing in listing order, empty if the listing is not grouped
+    pub groups: Vec<(Option<String>, usize)>,
+
+    pub items: Vec<(T, Metadata)>,
+}
+
+impl Listing {
+    pub const DEFAULT_LIMIT: usize = 10;
+    pub const MAX_LIMIT: usize = 100;
+
+    pub fn new(sort: Option<Sort>, offset: Option<usize>, limit: Option<usize>) -> Self {
+        return Self {
+            sort,
+            group: None,
+            offset: offset.unwrap_or(0),
+            limit: limit.unwrap_or(Self::DEFAULT_LIMIT).min(Self::MAX_LIMIT),
+        };
+    }
+
+    pub fn grouped(self, group: Option<Grouping>) -> Self {
+        return Self { group, ..self };
+    }
+
+    fn compare(sort: &Sort, a: &Metadata, b: &Metadata) -> Ordering {
+        let ordering = match &sort.key {
+            SortKey::Uploaded => a.uploaded.cmp(&b.uploaded),
+            SortKey::Title => a.title.as_deref().map(str::to_lowercase)
+                .cmp(&b.title.as_deref().map(str::to_lowercase)),
+            SortKey::Pages => a.pages.cmp(&b.pages),
+            SortKey::Date => match (a.date, b.date) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                // Documents without date are listed last in either direction
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            SortKey::Due => match (a.due, b.due) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                // Documents without due date are listed last in either direction
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            SortKey::Property(key) => match (a.properties.get(key), b.properties.get(key)) {
+                // Incomparable values are kept in natural order
+                (Some(a), Some(b)) => a.compare(b).unwrap_or(Ordering::Equal),
+                // Documents without the property are listed last in either direction
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+
+        return if sort.descending { ordering.reverse() } else { ordering };
+    }
+
+    /// Sorts the items and cuts out the requested page.
+    pub fn apply<T>(&self, mut items: Vec<(T, Metadata)>) -> Page<T> {
+        if let Some(sort) = &self.sort {
+            // Sorting is stable so items with equal keys stay in natural order
+            items.sort_by(|(_, a), (_, b)| Self::compare(sort, a, b));
+        }
+
+        let mut groups = Vec::<(Option<String>, usize)>::new();
+        if let Some(group) = self.group {
+            // Groups are ordered by key with the items without key listed last
+            items.sort_by_key(|(_, metadata)| {
+                let key = group.key(metadata);
+                (key.is_none(), key)
+            });
+
+            for (_, metadata) in &items {
+                let key = group.key(metadata);
+                match groups.last_mut() {
+                    Some((last, count)) if *last == key => *count += 1,
+                    _ => groups.push((key, 1)),
+                }
+            }
+        }
+
+        return Page {
+            total: items.len(),
+            groups,
+            items: items.into_iter()
+                .skip(self.offset)
+                .take(self.limit)
+                .collect(),
+        };
+    }
+}
+
+impl Default for Listing {
+    fn default() -> Self {
+        return Self::new(None, None, None);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use fs2::FileExt;
+use log::warn;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::proto::model::DocId;
+
+use super::Filename;
+
+/// Locks serializing all modifications of the same bundle.
+///
+/// Locks are held in-process and are optionally backed by advisory lock files, which serialize modifications across
+/// multiple processes sharing the repository.
+pub(super) struct Locks {
+    held: std::sync::Mutex<HashMap<DocId, Arc<Mutex<()>>>>,
+
+    /// Directory holding the lock files, if enabled
+    files: Option<PathBuf>,
+}
+
+/// A held lock on a bundle, released when dropped.
+pub(super) struct Lock {
+    /// The lock file and its path, removed before the lock is released
+    file: Option<(std::fs::File, PathBuf)>,
+
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Locks {
+    pub(super) fn new(files: Option<PathBuf>) -> Self {
+        return Self {
+            held: std::sync::Mutex::new(HashMap::new()),
+            files,
+        };
+    }
+
+    /// Waits until no other modification of the bundle is in progress and locks it.
+    pub(super) async fn lock(&self, id: DocId) -> Result<Lock> {
+        let mutex = {
+            let mut held = self.held.lock().expect("Locks poisoned");
+
+            // Forget about locks no one is holding or waiting for
+            held.retain(|_, mutex| Arc::strong_count(mutex) > 1);
+
+            held.entry(id).or_default().clone()
+        };
+
+        let guard = mutex.lock_owned().await;
+
+        let file = match &self.files {
+            Some(files) => Some(Self::lock_file(files.join(id.filename())).await?),
+            None => None,
+        };
+
+        return Ok(Lock { file, _guard: guard });
+    }
+
+    /// Creates and exclusively locks the lock file.
+    ///
+    /// Lock files are removed on release, so a lock acquired on a file another process has removed in the meantime is
+    /// retried on a fresh file.
+    async fn lock_file(path: PathBuf) -> Result<(std::fs::File, PathBuf)> {
+        return Ok(tokio::task::spawn_blocking(move || -> Result<_> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            loop {
+                let file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .open(&path)?;
+                file.lock_exclusive()?;
+
+                match std::fs::metadata(&path) {
+                    Ok(metadata) if metadata.ino() == file.metadata()?.ino() => return Ok((file, path)),
+                    Ok(_) => continue,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }).await??);
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Some((file, path)) = self.file.take() {
+            if let Err(err) = std::fs::remove_file(&path) {
+                warn!("Failed to remove lock file {:?}: {}", path, err);
+            }
+
+            if let Err(err) = file.unlock() {
+                warn!("Failed to release lock file {:?}: {}", path, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = Arc::new(Locks::new(Some(dir.path().join("locks"))));
+
+        let id = DocId::random();
+        let lock = locks.lock(id).await.unwrap();
+
+        // Other bundles are not affected
+        locks.lock(DocId::random()).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let locks = locks.clone();
+            async move { locks.lock(id).await.map(|_| ()) }
+        });
+
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+        assert_that!(std::fs::read_dir(dir.path().join("locks")).unwrap().count()).is_equal_to(1);
+
+        drop(lock);
+        waiting.await.unwrap().unwrap();
+
+        assert_that!(std::fs::read_dir(dir.path().join("locks")).unwrap().count()).is_equal_to(0);
+        assert_that!(locks.held.lock().unwrap().values().all(|mutex| Arc::strong_count(mutex) == 1)).is_true();
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::mimetype;
+use crate::proto::model::Kind;
+
+/// The format and size of a fragment.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct FragmentInfo {
+    pub mimetype: String,
+
+    /// Size of the contents as written, before compression
+    pub size: u64,
+
+    /// Whether the fragment is stored compressed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed: bool,
+}
+
+impl FragmentInfo {
+    /// Describes the contents of a fragment, using the leading bytes to detect the format if the kind does not tell.
+    pub fn of(kind: &Kind, data: &[u8]) -> Self {
+        return Self {
+            mimetype: mimetype::mimetype(kind, data).to_string(),
+            size: data.len() as u64,
+            compressed: false,
+        };
+    }
+}
+
+/// Format and size of all fragments of a bundle, keyed by fragment filename.
+///
+/// The format is detected while the bundle is staged, as the contents of encrypted fragments do not tell it later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(transparent)]
+pub struct Manifest(BTreeMap<String, FragmentInfo>);
+
+impl Manifest {
+    pub const FILENAME: &'static str = "fragments.json";
+
+    pub fn get(&self, name: &str) -> Option<&FragmentInfo> { self.0.get(name) }
+
+    pub fn insert(&mut self, name: impl Into<String>, info: FragmentInfo) { self.0.insert(name.into(), info); }
+
+    pub fn remove(&mut self, name: &str) { self.0.remove(name); }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &FragmentInfo)> {
+        return self.0.iter().map(|(name, info)| (name.as_str(), info));
+    }
+
+    pub async fn load(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        match tokio::fs::read(path).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        super::atomic::write(path, &serde_json::to_vec_pretty(self)?).await?;
+
+        return Ok(());
+    }
+}
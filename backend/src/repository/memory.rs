@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind};
+
+use super::{Filename, Location, Store};
+
+struct Entry {
+    location: Location,
+
+    metadata: Metadata,
+
+    /// Fragments by their filename
+    fragments: HashMap<OsString, Vec<u8>>,
+}
+
+/// Store keeping the documents in memory, standing in for the repository in tests.
+///
+/// Documents are kept along with their metadata and fragments only, so anything working on the paths of bundles, like
+/// the juicers, needs a `Repository` instead.
+#[derive(Default)]
+pub struct Repository {
+    entries: RwLock<BTreeMap<DocId, Entry>>,
+}
+
+impl Repository {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Adds a fragment to a document, i.e. the plaintext usually extracted by the juicer.
+    pub async fn attach(&self, id: DocId, kind: Kind, data: &[u8]) -> Result<()> {
+        let mut entries = self.entries.write().await;
+        let entry = entries.get_mut(&id)
+            .ok_or_else(|| anyhow!("Bundle not found: {}", id))?;
+
+        entry.fragments.insert(kind.filename(), data.to_vec());
+
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl Store for Repository {
+    async fn store(&self, document: &[u8], metadata: Metadata) -> Result<DocId> {
+        let id = DocId::random();
+
+        let mut fragments = HashMap::new();
+        fragments.insert(Kind::Document.filename(), document.to_vec());
+
+        self.entries.write().await.insert(id, Entry {
+            location: Location::Inbox,
+            metadata,
+            fragments,
+        });
+
+        return Ok(id);
+    }
+
+    async fn list(&self, location: Location) -> Result<Vec<DocId>> {
+        return Ok(self.entries.read().await.iter()
+            .filter(|(_, entry)| entry.location == location)
+            .map(|(id, _)| *id)
+            .collect());
+    }
+
+    async fn locate(&self, id: DocId) -> Result<Option<Location>> {
+        return Ok(self.entries.read().await.get(&id)
+            .map(|entry| entry.location));
+    }
+
+    async fn read_metadata(&self, id: DocId) -> Result<Metadata> {
+        return self.entries.read().await.get(&id)
+            .map(|entry| entry.metadata.clone())
+            .ok_or_else(|| anyhow!("Bundle not found: {}", id));
+    }
+
+    async fn write_metadata(&self, id: DocId, metadata: &Metadata) -> Result<()> {
+        let mut entries = self.entries.write().await;
+        let entry = entries.get_mut(&id)
+            .filter(|entry| entry.location != Location::Trash)
+            .ok_or_else(|| anyhow!("Bundle not found: {}", id))?;
+
+        entry.metadata = metadata.clone();
+
+        return Ok(());
+    }
+
+    async fn read(&self, id: DocId, kind: Kind) -> Result<Option<Vec<u8>>> {
+        let entries = self.entries.read().await;
+        let entry = entries.get(&id)
+            .ok_or_else(|| anyhow!("Bundle not found: {}", id))?;
+
+        if kind == Kind::Metadata {
+            return Ok(Some(serde_json::to_vec(&entry.metadata)?));
+        }
+
+        return Ok(entry.fragments.get(&kind.filename()).cloned());
+    }
+
+    async fn relocate(&self, id: DocId, location: Location) -> Result<Location> {
+        let mut entries = self.entries.write().await;
+        let entry = match entries.get_mut(&id) {
+            Some(entry) => entry,
+            None => bail!("Bundle not found: {}", id),
+        };
+
+        entry.location = match (entry.location, location) {
+            (Location::Trash, Location::Trash) => Location::Trash,
+            (Location::Trash, _) if entry.metadata.archived.is_some() => Location::Archive,
+            (Location::Trash, _) => Location::Inbox,
+            (_, location) => location,
+        };
+
+        return Ok(entry.location);
+    }
+}
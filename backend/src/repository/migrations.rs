@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use futures::FutureExt;
+use log::info;
+
+use super::{atomic, Repository, settings};
+
+/// Format of the repositories written by this version
+pub const VERSION: u32 = 3;
+
+/// File in the repository root holding the format version
+const MARKER: &str = "format";
+
+/// A step upgrading the repository from the previous format version.
+pub struct Migration {
+    /// The version the repository has after the migration
+    pub version: u32,
+
+    pub description: &'static str,
+}
+
+/// All migrations in order, repositories without a format marker are assumed to be of the first version.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 2, description: "Move archived bundles into partitions by the month of archiving" },
+    Migration { version: 3, description: "Move repository settings into versioned files in the settings directory" },
+];
+
+/// Error returned if a repository has been written by a newer version.
+#[derive(Debug, thiserror::Error)]
+#[error("Repository format {found} is newer than the supported format {supported}")]
+pub struct Unsupported {
+    pub found: u32,
+    pub supported: u32,
+}
+
+/// Reads the format version of the repository at the given path, if marked.
+async fn read(path: &Path) -> Result<Option<u32>> {
+    return match tokio::fs::read_to_string(path.join(MARKER)).await {
+        Ok(version) => Ok(Some(version.trim().parse()
+            .with_context(|| format!("Invalid repository format: {}", version.trim()))?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    };
+}
+
+async fn write(path: &Path, version: u32) -> Result<()> {
+    return atomic::write(path.join(MARKER), format!("{}\n", version).as_bytes()).await;
+}
+
+/// Returns the format version of an existing repository, failing if it is newer than supported.
+async fn version(path: &Path) -> Result<u32> {
+    let version = read(path).await?.unwrap_or(1);
+    if version > VERSION {
+        return Err(Unsupported { found: version, supported: VERSION }.into());
+    }
+
+    return Ok(version);
+}
+
+impl Repository {
+    /// Lists the migrations required to open the repository at the given path, without changing anything.
+    pub async fn pending_migrations(path: impl AsRef<Path>) -> Result<Vec<&'static Migration>> {
+        let path = path.as_ref();
+
+        // Missing or empty repositories are created in the current format
+        let empty = match tokio::fs::read_dir(path).await {
+            Ok(mut entries) => entries.next_entry().await?.is_none(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => true,
+            Err(err) => return Err(err.into()),
+        };
+        if empty {
+            return Ok(Vec::new());
+        }
+
+        let version = version(path).await?;
+        return Ok(MIGRATIONS.iter().filter(|migration| migration.version > version).collect());
+    }
+
+    /// Marks a repository created right now with the current format.
+    pub(super) async fn mark_format(&self) -> Result<()> {
+        return write(self.path(), VERSION).await;
+    }
+
+    /// Upgrades the repository to the current format and returns the number of migrations applied.
+    ///
+    /// A fork of the repository is taken before the first migration is applied. The format is marked after each
+    /// migration, so an interrupted upgrade continues with the migration that has been interrupted.
+    pub(super) async fn migrate(&self) -> Result<usize> {
+        let version = version(self.path()).await?;
+
+        let pending = MIGRATIONS.iter()
+            .filter(|migration| migration.version > version)
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let backup = self.backup_format(version).await?;
+        info!("Backed up repository in format {} to {:?}", version, backup);
+
+        for migration in &pending {
+            info!("Migrating repository to format {}: {}", migration.version, migration.description);
+
+            let changed = match migration.version {
+                2 => self.partition_archive().await?,
+                3 => self.move_settings().await?,
+                version => unreachable!("Unknown migration: {}", version),
+            };
+
+            write(self.path(), migration.version).await?;
+
+            info!("Migrated repository to format {}, {} changes", migration.version, changed);
+        }
+
+        return Ok(pending.len());
+    }
+
+    /// Forks the repository next to itself before it is migrated.
+    ///
+    /// Besides the bundles, all files in the repository root and the settings are copied. The fork keeps the previous
+    /// format, so it can be opened by the previous version.
+    async fn backup_format(&self, version: u32) -> Result<PathBuf> {
+        let name = self.path().file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("repository"));
+        let target = self.path().with_file_name(format!("{}.v{}-{}", name, version, Utc::now().format("%Y%m%d%H%M%S")));
+
+        // The fork is opened as a repository in turn, which would make opening a repository recurse endlessly
+        let fork = self.fork(target.clone()).boxed().await?;
+
+        let mut entries = tokio::fs::read_dir(self.path()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_file() && entry.file_name() != MARKER {
+                tokio::fs::copy(entry.path(), fork.path().join(entry.file_name())).await?;
+            }
+        }
+
+        let dir = self.path().join(settings::DIR);
+        if dir.exists() {
+            tokio::fs::create_dir_all(fork.path().join(settings::DIR)).await?;
+
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                tokio::fs::copy(entry.path(), fork.path().join(settings::DIR).join(entry.file_name())).await?;
+            }
+        }
+
+        match version {
+            1 => tokio::fs::remove_file(fork.path().join(MARKER)).await?,
+            version => write(fork.path(), version).await?,
+        }
+
+        return Ok(target);
+    }
+}
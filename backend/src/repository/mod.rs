@@ -1,26 +1,88 @@
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::collections::BTreeMap;
 use std::ffi::OsString;
+use std::io::{Cursor, SeekFrom};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{anyhow, Result};
-use futures::TryStreamExt;
-use log::info;
-use tokio::fs::OpenOptions;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
-
-use crate::config::Repository as Config;
+use chrono::{DateTime, Duration, Utc};
+use futures::{Stream, StreamExt, TryStreamExt};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, BufReader, Split};
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+use crate::config::{IdScheme, Repository as Config};
 use crate::meta::Metadata;
+use crate::mimetype;
 use crate::proto::model::{DocId, Kind};
 
+pub use self::atomic::Fragment;
+pub use self::checksums::{Checksums, sha256};
+pub use self::events::{Event, Events};
+pub use self::fsck::{Problem, Report, Verification};
+pub use self::journal::{Change, diff, Diff, Entry, Journal};
+pub use self::listing::{Grouping, Listing, Page};
+pub use self::manifest::{FragmentInfo, Manifest};
+pub use self::migrations::{Migration, MIGRATIONS, Unsupported, VERSION};
+pub use self::retention::{Retained, WriteOnce};
+pub use self::revisions::Revision;
+pub use self::settings::{Retention, Settings, UnsupportedSettings};
+pub use self::snapshot::{Location, Snapshot, Snapshotted};
+pub use self::store::Store;
+pub use self::versions::Version;
+
+use self::cache::Cache;
+use self::compression::Compression;
+use self::locks::{Lock, Locks};
+use self::retention::Rules;
+
+mod atomic;
+mod cache;
+mod checksums;
+mod compression;
+mod erase;
+mod events;
+mod fork;
+mod fsck;
+mod journal;
+mod layout;
+mod listing;
+mod locks;
+mod manifest;
+#[cfg(any(test, feature = "testing"))]
+pub mod memory;
+mod migrations;
+mod recovery;
+mod retention;
+mod revisions;
+mod settings;
+mod shred;
+mod snapshot;
+mod store;
+mod versions;
+
+#[cfg(test)]
+mod test;
+
 trait Filename {
     fn filename(&self) -> OsString;
 }
 
 pub trait BundleState {
+    /// Whether bundles are placed in partitions below the state directory
+    const PARTITIONED: bool = false;
+
     fn path(repository: &Repository) -> PathBuf;
+
+    /// The partition a bundle is placed in, relative to the state directory.
+    fn partition(_metadata: &Metadata) -> PathBuf {
+        return PathBuf::new();
+    }
 }
 
 pub struct Staging {}
@@ -42,76 +104,317 @@ impl BundleState for Inboxed {
 pub struct Archived {}
 
 impl BundleState for Archived {
+    const PARTITIONED: bool = true;
+
     fn path(repository: &Repository) -> PathBuf {
         return repository.path.as_ref().as_ref().join("archive");
     }
+
+    /// Archived bundles are partitioned by year and month of the document date to keep directories small.
+    ///
+    /// Documents without date are partitioned by the month of archiving. Bundles stay in their partition if the date
+    /// changes later on, as they are looked up in all partitions.
+    fn partition(metadata: &Metadata) -> PathBuf {
+        let date = metadata.date
+            .unwrap_or_else(|| metadata.archived.unwrap_or_else(Utc::now).naive_utc().date());
+        return PathBuf::from(date.format("%Y").to_string()).join(date.format("%m").to_string());
+    }
+}
+
+pub struct Trashed {}
+
+impl BundleState for Trashed {
+    fn path(repository: &Repository) -> PathBuf {
+        return repository.path.as_ref().as_ref().join("trash");
+    }
+}
+
+/// Bundles the juicer failed on, kept with their original and the juicer log until retried or discarded.
+pub struct Failed {}
+
+impl BundleState for Failed {
+    fn path(repository: &Repository) -> PathBuf {
+        return repository.path.as_ref().as_ref().join("failed");
+    }
+}
+
+/// Bundles set aside for review instead of being processed or deleted.
+pub struct Quarantined {}
+
+impl BundleState for Quarantined {
+    fn path(repository: &Repository) -> PathBuf {
+        return repository.path.as_ref().as_ref().join("quarantine");
+    }
 }
 
 pub struct Bundle<'r, State: BundleState> {
     id: DocId,
     repository: &'r Repository,
+    path: PathBuf,
     state: PhantomData<State>,
 }
 
+/// Error returned if a bundle can not be moved because the target state already holds a bundle with the same ID.
+#[derive(Debug, thiserror::Error)]
+#[error("Bundle already exists: {id}")]
+pub struct Conflict {
+    pub id: DocId,
+}
+
+/// Error returned if a bundle has been moved away by a concurrent modification before it could be changed.
+#[derive(Debug, thiserror::Error)]
+#[error("Bundle modified concurrently: {id}")]
+pub struct Moved {
+    pub id: DocId,
+}
+
+/// Guards held while a bundle is changed.
+struct Modifying<'r> {
+    _lock: Lock,
+    _writing: RwLockReadGuard<'r, ()>,
+}
+
+#[derive(Clone)]
 pub struct Repository {
-    path: Box<dyn AsRef<Path> + Send + Sync>,
+    path: Arc<dyn AsRef<Path> + Send + Sync>,
+
+    /// Overwrite fragments before purging bundles
+    shred: bool,
+
+    compression: Compression,
+
+    /// Local copies of fragments read before, if enabled
+    cache: Option<Arc<Cache>>,
+
+    /// Number of bundles read concurrently while listing
+    concurrency: usize,
+
+    /// Rules retaining archived documents from deletion
+    retention: Rules,
+
+    /// Refuse to replace existing fragments of archived bundles
+    write_once: bool,
+
+    /// How the IDs of new bundles are generated
+    ids: IdScheme,
+
+    events: Events,
+    journal: Arc<Journal>,
+
+    /// Held shared while bundles are changed and exclusively while a snapshot is taken
+    writes: Arc<RwLock<()>>,
+
+    /// Held while a single bundle is changed
+    locks: Arc<Locks>,
+
+    /// The user on whose behalf changes are made
+    actor: Option<String>,
+}
+
+/// Lists all bundles in the given state ordered by modification time.
+async fn list<'r, State: BundleState>(repository: &'r Repository) -> Result<Vec<Bundle<'r, State>>> {
+    let list = layout::scan::<State>(repository).await?.into_iter()
+        .map(|(time, id, path)| {
+            let bundle = Bundle {
+                id,
+                repository,
+                path,
+                state: PhantomData::default(),
+            };
+
+            return ((time, id), bundle);
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    return Ok(list.into_iter().map(|(_, id)| id).collect());
+}
+
+fn disk_usage(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() { disk_usage(&entry.path())? } else { metadata.len() };
+    }
+    return Ok(size);
+}
+
+async fn get<'r, State: BundleState>(repository: &'r Repository, id: DocId) -> Option<Bundle<'r, State>> {
+    let path = layout::locate::<State>(repository, id).await?;
+
+    return Some(Bundle {
+        id,
+        repository,
+        path,
+        state: PhantomData::default(),
+    });
+}
+
+/// Reads the metadata of all bundles in the given state, keeping the order of the listing.
+///
+/// The metadata is read for multiple bundles concurrently, bounded by the configured concurrency.
+async fn read_all<'r, State: BundleState>(repository: &'r Repository) -> Result<Vec<(Bundle<'r, State>, Metadata)>> {
+    let started = Instant::now();
+
+    let mut bundles = futures::stream::iter(list::<State>(repository).await?.into_iter().enumerate())
+        .map(|(index, bundle)| async move {
+            return bundle.read_metadata().await
+                .map(|metadata| (index, bundle, metadata));
+        })
+        .buffer_unordered(repository.concurrency)
+        .try_collect::<Vec<_>>().await?;
+
+    bundles.sort_by_key(|(index, _, _)| *index);
+
+    debug!("Read metadata of {} bundles in {:?}", bundles.len(), started.elapsed());
+
+    return Ok(bundles.into_iter().map(|(_, bundle, metadata)| (bundle, metadata)).collect());
+}
+
+/// Streams the metadata of all bundles in the given state in the order of the listing.
+///
+/// Only the bundles are listed upfront, their metadata is read while the stream is consumed, bounded by the configured
+/// concurrency.
+async fn stream<'r, State: BundleState + 'r>(repository: &'r Repository) -> Result<impl Stream<Item=Result<(Bundle<'r, State>, Metadata)>> + 'r> {
+    return Ok(futures::stream::iter(list::<State>(repository).await?)
+        .map(|bundle| async move {
+            return bundle.read_metadata().await
+                .map(|metadata| (bundle, metadata));
+        })
+        .buffered(repository.concurrency));
+}
+
+/// Adds the number of bytes used by the bundles in the given state to the usage of their owners.
+async fn usage_by_owner<State: BundleState>(repository: &Repository, usage: &mut BTreeMap<Option<String>, u64>) -> Result<()> {
+    for (bundle, metadata) in read_all::<State>(repository).await? {
+        let path = bundle.path();
+        *usage.entry(metadata.owner).or_default() += tokio::task::spawn_blocking(move || disk_usage(&path)).await??;
+    }
+
+    return Ok(());
+}
+
+/// Lists the bundles in the given state with a relation to the given bundle.
+async fn referencing<State: BundleState>(repository: &Repository, id: DocId) -> Result<Vec<(DocId, Metadata)>> {
+    return Ok(read_all::<State>(repository).await?.into_iter()
+        .filter(|(_, metadata)| metadata.relations.iter().any(|relation| relation.target == id))
+        .map(|(bundle, metadata)| (bundle.id, metadata))
+        .collect());
+}
+
+/// Lists the bundles in the given state with metadata matching the predicate, sorted and paginated.
+async fn query<'r, State: BundleState>(repository: &'r Repository,
+                                       listing: &Listing,
+                                       predicate: impl Fn(&Metadata) -> bool) -> Result<Page<Bundle<'r, State>>> {
+    let bundles = read_all::<State>(repository).await?.into_iter()
+        .filter(|(_, metadata)| predicate(metadata))
+        .collect();
+
+    return Ok(listing.apply(bundles));
 }
 
 pub struct Inbox<'r>(&'r Repository);
 
 impl<'r> Inbox<'r> {
     pub async fn list(&self) -> Result<Vec<Bundle<'r, Inboxed>>> {
-        let list = tokio::fs::read_dir(Inboxed::path(self.0)).await?
-            .err_into::<anyhow::Error>()
-            .and_then(|entry| async move {
-                let time = entry.metadata().await?.modified()?;
-
-                let id = DocId::from_str(entry.file_name().to_string_lossy().as_ref())?;
-                let bundle = Bundle {
-                    id,
-                    repository: &self.0,
-                    state: PhantomData::default(),
-                };
+        return list(self.0).await;
+    }
 
-                return Ok(((time, bundle.id), bundle));
-            })
-            .try_collect::<BTreeMap<_, _>>().await?;
+    pub async fn query(&self, listing: &Listing, predicate: impl Fn(&Metadata) -> bool) -> Result<Page<Bundle<'r, Inboxed>>> {
+        return query(self.0, listing, predicate).await;
+    }
 
-        return Ok(list.into_iter().map(|(_, id)| id).collect());
+    /// Streams all bundles with their metadata, which is read while the stream is consumed.
+    pub async fn stream(&self) -> Result<impl Stream<Item=Result<(Bundle<'r, Inboxed>, Metadata)>> + 'r> {
+        return stream(self.0).await;
     }
 
     pub async fn get(&self, id: DocId) -> Option<Bundle<'r, Inboxed>> {
-        let bundle = Bundle {
-            id,
-            repository: &self.0,
-            state: PhantomData::default(),
-        };
-
-        let metadata = tokio::fs::metadata(&bundle.path()).await;
-        if metadata.is_err() {
-            return None;
-        }
-
-        return Some(bundle);
+        return get(self.0, id).await;
     }
 }
 
 pub struct Archive<'r>(&'r Repository);
 
 impl<'r> Archive<'r> {
+    pub async fn list(&self) -> Result<Vec<Bundle<'r, Archived>>> {
+        return list(self.0).await;
+    }
+
+    pub async fn query(&self, listing: &Listing, predicate: impl Fn(&Metadata) -> bool) -> Result<Page<Bundle<'r, Archived>>> {
+        return query(self.0, listing, predicate).await;
+    }
+
+    /// Streams all bundles with their metadata, which is read while the stream is consumed.
+    pub async fn stream(&self) -> Result<impl Stream<Item=Result<(Bundle<'r, Archived>, Metadata)>> + 'r> {
+        return stream(self.0).await;
+    }
+
     pub async fn get(&self, id: DocId) -> Option<Bundle<'r, Archived>> {
-        let bundle = Bundle {
-            id,
-            repository: &self.0,
-            state: PhantomData::default(),
-        };
+        return get(self.0, id).await;
+    }
+}
+
+pub struct Stage<'r>(&'r Repository);
+
+impl<'r> Stage<'r> {
+    pub async fn list(&self) -> Result<Vec<Bundle<'r, Staging>>> {
+        return list(self.0).await;
+    }
+
+    pub async fn get(&self, id: DocId) -> Option<Bundle<'r, Staging>> {
+        return get(self.0, id).await;
+    }
+}
+
+pub struct Failures<'r>(&'r Repository);
+
+impl<'r> Failures<'r> {
+    pub async fn list(&self) -> Result<Vec<Bundle<'r, Failed>>> {
+        return list(self.0).await;
+    }
+
+    pub async fn get(&self, id: DocId) -> Option<Bundle<'r, Failed>> {
+        return get(self.0, id).await;
+    }
+}
+
+pub struct Quarantine<'r>(&'r Repository);
+
+impl<'r> Quarantine<'r> {
+    pub async fn list(&self) -> Result<Vec<Bundle<'r, Quarantined>>> {
+        return list(self.0).await;
+    }
+
+    pub async fn get(&self, id: DocId) -> Option<Bundle<'r, Quarantined>> {
+        return get(self.0, id).await;
+    }
+}
 
-        let metadata = tokio::fs::metadata(&bundle.path()).await;
-        if metadata.is_err() {
-            return None;
+pub struct Trash<'r>(&'r Repository);
+
+impl<'r> Trash<'r> {
+    pub async fn list(&self) -> Result<Vec<Bundle<'r, Trashed>>> {
+        return list(self.0).await;
+    }
+
+    pub async fn get(&self, id: DocId) -> Option<Bundle<'r, Trashed>> {
+        return get(self.0, id).await;
+    }
+
+    /// Permanently deletes all bundles which are in the trash for longer than the given retention.
+    pub async fn purge(&self, retention: Duration) -> Result<usize> {
+        let deadline = Utc::now() - retention;
+
+        let mut purged = 0;
+        for bundle in self.list().await? {
+            if bundle.trashed().await? < deadline {
+                bundle.purge().await?;
+                purged += 1;
+            }
         }
 
-        return Some(bundle);
+        return Ok(purged);
     }
 }
 
@@ -120,6 +423,8 @@ impl Filename for Kind {
         return match self {
             Self::Document => OsString::from("document.pdf"),
             Self::Preview => OsString::from("preview.png"),
+            Self::Page(page) => OsString::from(format!("preview-{:04}.png", page)),
+            Self::Thumbnail => OsString::from("thumbnail.png"),
             Self::Plaintext => OsString::from("document.txt"),
             Self::Metadata => OsString::from("metadata.json"),
             Self::Other { name } => OsString::from(name),
@@ -131,25 +436,82 @@ impl Filename for DocId {
     fn filename(&self) -> OsString { return self.to_string().into(); }
 }
 
+/// Determines the kind of a fragment by its filename, the reverse of `Filename`.
+pub fn kind_of(name: &str) -> Kind {
+    return match name {
+        "document.pdf" => Kind::Document,
+        "preview.png" => Kind::Preview,
+        "thumbnail.png" => Kind::Thumbnail,
+        "document.txt" => Kind::Plaintext,
+        "metadata.json" => Kind::Metadata,
+        name => name.strip_prefix("preview-")
+            .and_then(|name| name.strip_suffix(".png"))
+            .filter(|page| page.len() == 4)
+            .and_then(|page| page.parse().ok())
+            .map_or_else(|| Kind::other(name), Kind::Page),
+    };
+}
+
+/// A fragment present in a bundle.
+#[derive(Debug, Clone)]
+pub struct FragmentEntry {
+    pub kind: Kind,
+    pub name: String,
+
+    /// Size of the fragment as stored, which may be compressed or encrypted
+    pub size: u64,
+
+    pub modified: DateTime<Utc>,
+
+    /// Hex encoded SHA-256 checksum of the fragment as stored
+    pub checksum: String,
+}
+
 impl<State: BundleState> Bundle<'_, State> {
     pub fn id(&self) -> &DocId { return &self.id; }
 
-    pub fn path(&self) -> PathBuf { return State::path(self.repository).join(self.id.filename()); }
+    pub fn path(&self) -> PathBuf { return self.path.clone(); }
 
     pub fn path_of(&self, kind: impl Borrow<Kind>) -> PathBuf { return self.path().join(kind.borrow().filename()); }
 
-    pub async fn read(&self, kind: impl Borrow<Kind>) -> Result<Option<impl AsyncRead>> {
+    /// Opens the file of a fragment, which is a local copy if the repository is cached.
+    ///
+    /// The file is read as stored, so fragments which may be compressed must be read by `read` instead.
+    pub async fn open(&self, kind: impl Borrow<Kind>) -> std::io::Result<File> {
+        let kind = kind.borrow();
         let path = self.path_of(kind);
 
-        info!("Reading fragment {:?}", path);
-        let file = OpenOptions::new()
-            .read(true)
-            .open(path)
-            .await;
+        return match &self.repository.cache {
+            Some(cache) => cache.open_fragment(&path, &self.id, kind).await,
+            None => File::open(path).await,
+        };
+    }
+
+    /// Reads a fragment, which is decompressed transparently if stored compressed.
+    pub async fn read(&self, kind: impl Borrow<Kind>) -> Result<Option<impl AsyncRead>> {
+        let kind = kind.borrow();
+
+        info!("Reading fragment {:?}", self.path_of(kind));
+        let file = self.open(kind).await;
 
         match file {
-            Ok(file) => {
-                return Ok(Some(file));
+            Ok(mut file) => {
+                let mut head = Vec::new();
+                (&mut file).take(4).read_to_end(&mut head).await?;
+
+                // Compressed fragments are decompressed in memory, all others are streamed from disk
+                if compression::is_frame(&head) && self.is_compressed(kind).await? {
+                    file.read_to_end(&mut head).await?;
+                    let data = tokio::task::spawn_blocking(move || compression::decode(head)).await??;
+
+                    let reader: Box<dyn AsyncRead + Send + Sync + Unpin> = Box::new(Cursor::new(data));
+                    return Ok(Some(reader));
+                }
+
+                file.seek(SeekFrom::Start(0)).await?;
+
+                let reader: Box<dyn AsyncRead + Send + Sync + Unpin> = Box::new(file);
+                return Ok(Some(reader));
             }
 
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
@@ -162,6 +524,11 @@ impl<State: BundleState> Bundle<'_, State> {
         }
     }
 
+    /// Checks if a fragment is stored compressed, which makes its contents differ from the file on disk.
+    pub async fn is_compressed(&self, kind: impl Borrow<Kind>) -> Result<bool> {
+        return Ok(self.fragment_info(kind).await?.map_or(false, |info| info.compressed));
+    }
+
     pub async fn read_plaintext(&self) -> Result<String> {
         let mut file = self.read(Kind::Plaintext).await?
             .ok_or_else(|| anyhow!("Plaintext missing in bundle: {}", self.id))?;
@@ -172,17 +539,174 @@ impl<State: BundleState> Bundle<'_, State> {
         return Ok(buffer);
     }
 
+    /// Reads the plaintext page by page.
+    ///
+    /// Pages are separated by form feeds as emitted by `pdftotext`.
+    pub async fn read_pages(&self) -> Result<Split<BufReader<impl AsyncRead>>> {
+        let file = self.read(Kind::Plaintext).await?
+            .ok_or_else(|| anyhow!("Plaintext missing in bundle: {}", self.id))?;
+
+        return Ok(BufReader::new(file).split(b'\x0c'));
+    }
+
     pub async fn read_metadata(&self) -> Result<Metadata> {
         let file = self.read(Kind::Metadata).await?
             .ok_or_else(|| anyhow!("Metadata missing in bundle: {}", self.id))?;
 
         return Metadata::load(file).await;
     }
+
+    /// Lists the filenames of all fragments in the bundle.
+    pub async fn fragment_names(&self) -> Result<Vec<String>> {
+        return Ok(tokio::fs::read_dir(self.path()).await?
+            .err_into::<anyhow::Error>()
+            .try_filter_map(|entry| async move {
+                if !entry.file_type().await?.is_file() {
+                    return Ok(None);
+                }
+
+                // Fragments being written are not yet part of the bundle
+                if atomic::is_temp(&entry.file_name().to_string_lossy()) {
+                    return Ok(None);
+                }
+
+                return Ok(Some(entry.file_name().to_string_lossy().into_owned()));
+            })
+            .try_collect().await?);
+    }
+
+    /// Describes all fragments present in the bundle, ordered by name.
+    ///
+    /// Checksums are taken from the recorded ones and only computed for fragments without a recorded checksum.
+    pub async fn fragments(&self) -> Result<Vec<FragmentEntry>> {
+        let checksums = self.read_checksums().await?.unwrap_or_default();
+
+        let mut names = self.fragment_names().await?;
+        names.retain(|name| Checksums::covers(name));
+        names.sort();
+
+        let mut fragments = Vec::with_capacity(names.len());
+        for name in names {
+            let path = self.path().join(&name);
+            let stat = tokio::fs::metadata(&path).await?;
+
+            let checksum = match checksums.get(&name) {
+                Some(checksum) => checksum.to_string(),
+                None => checksums::sha256(&path).await?,
+            };
+
+            fragments.push(FragmentEntry {
+                kind: kind_of(&name),
+                size: stat.len(),
+                modified: DateTime::from(stat.modified()?),
+                checksum,
+                name,
+            });
+        }
+
+        return Ok(fragments);
+    }
+
+    pub async fn read_checksums(&self) -> Result<Option<Checksums>> {
+        return Checksums::load(self.path().join(Checksums::FILENAME)).await;
+    }
+
+    pub async fn read_manifest(&self) -> Result<Option<Manifest>> {
+        return Manifest::load(self.path().join(Manifest::FILENAME)).await;
+    }
+
+    /// Returns the format and size of a fragment, if recorded.
+    pub async fn fragment_info(&self, kind: impl Borrow<Kind>) -> Result<Option<FragmentInfo>> {
+        let name = kind.borrow().filename().to_string_lossy().into_owned();
+        return Ok(self.read_manifest().await?.and_then(|manifest| manifest.get(&name).cloned()));
+    }
+
+    /// Records the format and size of all fragments currently present in the bundle.
+    ///
+    /// The fragments are read as written, so this must happen before they are compressed or encrypted.
+    async fn update_manifest(&self) -> Result<()> {
+        let mut manifest = Manifest::default();
+        for name in self.fragment_names().await? {
+            if Checksums::covers(&name) {
+                let mut file = tokio::fs::File::open(self.path().join(&name)).await?;
+                let size = file.metadata().await?.len();
+                let head = mimetype::head(&mut file).await?;
+
+                let mimetype = mimetype::mimetype(&Kind::other(&name), &head).to_string();
+                manifest.insert(name, FragmentInfo { mimetype, size, compressed: false });
+            }
+        }
+
+        return manifest.save(self.path().join(Manifest::FILENAME)).await;
+    }
+
+    /// Updates the recorded format and size of a single fragment and whether it is stored compressed.
+    ///
+    /// The recorded format is kept if it can not be told from the new contents, i.e. as they are encrypted.
+    async fn update_fragment_info(&self, kind: &Kind, data: &[u8], compressed: bool) -> Result<()> {
+        let name = kind.filename().to_string_lossy().into_owned();
+
+        let mut manifest = self.read_manifest().await?.unwrap_or_default();
+
+        let mut info = FragmentInfo::of(kind, data);
+        info.compressed = compressed;
+        if info.mimetype == mimetype::UNKNOWN {
+            if let Some(recorded) = manifest.get(&name) {
+                info.mimetype = recorded.mimetype.clone();
+            }
+        }
+
+        manifest.insert(name, info);
+
+        return manifest.save(self.path().join(Manifest::FILENAME)).await;
+    }
+
+    /// Records the checksums of all fragments currently present in the bundle.
+    async fn update_checksums(&self) -> Result<()> {
+        let mut checksums = Checksums::default();
+        for name in self.fragment_names().await? {
+            if Checksums::covers(&name) {
+                let checksum = checksums::sha256(self.path().join(&name)).await?;
+                checksums.insert(name, checksum);
+            }
+        }
+
+        return checksums.save(self.path().join(Checksums::FILENAME)).await;
+    }
+
+    /// Updates the recorded checksum of a single fragment.
+    async fn update_checksum(&self, kind: impl Borrow<Kind>) -> Result<()> {
+        let name = kind.borrow().filename().to_string_lossy().into_owned();
+
+        let mut checksums = self.read_checksums().await?.unwrap_or_default();
+        checksums.insert(name, checksums::sha256(self.path_of(kind)).await?);
+
+        return checksums.save(self.path().join(Checksums::FILENAME)).await;
+    }
 }
 
 impl Repository {
     pub async fn from_config(config: Config) -> Result<Self> {
-        return Self::with_path(config.path).await;
+        let mut repository = Self::with_path(config.path).await?;
+        repository.shred = config.shred;
+        repository.concurrency = config.concurrency.max(1);
+        repository.retention = Rules::from_config(config.retention);
+        repository.write_once = config.write_once;
+        repository.ids = config.ids;
+
+        if let Some(compression) = config.compression {
+            repository.compression = Compression::from_config(compression)?;
+        }
+
+        if config.lock_files {
+            repository.locks = Arc::new(Locks::new(Some(repository.path().join("locks"))));
+        }
+
+        if let Some(cache) = config.cache {
+            repository.cache = Some(Arc::new(Cache::from_config(cache).await?));
+        }
+
+        return Ok(repository);
     }
 
     pub async fn with_path(path: impl AsRef<Path> + Send + Sync + 'static) -> Result<Self> {
@@ -191,11 +715,49 @@ impl Repository {
         // Create repository path if missing
         tokio::fs::create_dir_all(&path).await?;
 
-        return Ok(Self { path: Box::new(path) });
+        // Refuse to touch repositories written by newer versions
+        let fresh = tokio::fs::read_dir(&path).await?.next_entry().await?.is_none();
+        let pending = Self::pending_migrations(path.as_ref()).await?;
+
+        let journal = Journal::open(path.as_ref().join(Journal::FILENAME)).await?;
+
+        let repository = Self {
+            path: Arc::new(path),
+            shred: false,
+            compression: Compression::default(),
+            cache: None,
+            concurrency: Config::default_concurrency(),
+            retention: Rules::default(),
+            write_once: false,
+            ids: IdScheme::default(),
+            events: Events::new(),
+            journal: Arc::new(journal),
+            writes: Arc::new(RwLock::new(())),
+            locks: Arc::new(Locks::new(None)),
+            actor: None,
+        };
+
+        if fresh {
+            repository.mark_format().await?;
+        } else if !pending.is_empty() {
+            let migrated = repository.migrate().await?;
+            info!("Applied {} migrations to the repository format", migrated);
+        }
+
+        let repaired = repository.repair().await?;
+        if repaired > 0 {
+            info!("Repaired {} bundles interrupted while moving", repaired);
+        }
+
+        return Ok(repository);
     }
 
     pub fn path(&self) -> &Path { return self.path.as_ref().as_ref(); }
 
+    pub fn staging(&self) -> Stage<'_> {
+        return Stage(self);
+    }
+
     pub fn inbox(&self) -> Inbox<'_> {
         return Inbox(self);
     }
@@ -204,74 +766,503 @@ impl Repository {
         return Archive(self);
     }
 
+    pub fn trash(&self) -> Trash<'_> {
+        return Trash(self);
+    }
+
+    pub fn failed(&self) -> Failures<'_> {
+        return Failures(self);
+    }
+
+    pub fn quarantine(&self) -> Quarantine<'_> {
+        return Quarantine(self);
+    }
+
+    /// Subscribes to the events published on bundle state transitions.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Event> {
+        return self.events.subscribe();
+    }
+
+    /// Counts the bundles in the given state.
+    pub async fn count<State: BundleState>(&self) -> Result<usize> {
+        return Ok(list::<State>(self).await?.len());
+    }
+
+    /// Finds all inboxed and archived bundles linking to the given bundle.
+    ///
+    /// Relations are only stored in the metadata of the linking bundle, so this has to scan all bundles.
+    pub async fn referencing(&self, id: DocId) -> Result<Vec<(DocId, Metadata)>> {
+        let mut result = referencing::<Inboxed>(self, id).await?;
+        result.extend(referencing::<Archived>(self, id).await?);
+        return Ok(result);
+    }
+
+    /// Calculates the number of bytes used by the repository.
+    pub async fn disk_usage(&self) -> Result<u64> {
+        let path = self.path().to_path_buf();
+        return Ok(tokio::task::spawn_blocking(move || disk_usage(&path)).await??);
+    }
+
+    /// Calculates the number of bytes used by the documents in the inbox, archive, trash and quarantine by their owner.
+    ///
+    /// Documents without owner are accounted to `None`. Bundles still being ingested are not accounted yet.
+    pub async fn disk_usage_by_owner(&self) -> Result<BTreeMap<Option<String>, u64>> {
+        let mut usage = BTreeMap::new();
+        usage_by_owner::<Inboxed>(self, &mut usage).await?;
+        usage_by_owner::<Archived>(self, &mut usage).await?;
+        usage_by_owner::<Trashed>(self, &mut usage).await?;
+        usage_by_owner::<Quarantined>(self, &mut usage).await?;
+        return Ok(usage);
+    }
+
+    /// Calculates the number of bytes used by the bundles in the given state.
+    pub async fn disk_usage_of<State: BundleState>(&self) -> Result<u64> {
+        let path = State::path(self);
+        return Ok(tokio::task::spawn_blocking(move || match disk_usage(&path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            result => result,
+        }).await??);
+    }
+
+    pub fn journal(&self) -> &Journal {
+        return &self.journal;
+    }
+
+    /// Returns a handle to the repository recording all changes on behalf of the given user.
+    pub fn acting_as(&self, actor: impl Into<String>) -> Self {
+        return Self {
+            actor: Some(actor.into()),
+            ..self.clone()
+        };
+    }
+
+    /// Holds off snapshots while a bundle is changed.
+    async fn writing(&self) -> RwLockReadGuard<'_, ()> {
+        return self.writes.read().await;
+    }
+
+    /// Waits for all other changes of the bundle to complete and holds off further ones and snapshots.
+    ///
+    /// The bundle lock is taken first, so no change waits for another bundle while holding off snapshots.
+    async fn modifying(&self, id: DocId) -> Result<Modifying<'_>> {
+        let lock = self.locks.lock(id).await?;
+        let writing = self.writing().await;
+
+        return Ok(Modifying { _lock: lock, _writing: writing });
+    }
+
+    /// Records an event in the journal and publishes it to all subscribers.
+    async fn publish(&self, event: Event) {
+        self.publish_diff(event, Diff::new()).await;
+    }
+
+    async fn publish_diff(&self, event: Event, diff: Diff) {
+        if let Err(err) = self.journal.append(event, self.actor.clone(), diff).await {
+            error!("Failed to record {:?} in journal: {:#}", event, err);
+        }
+
+        self.events.publish(event);
+    }
+
     pub async fn stage(&self) -> Result<Bundle<'_, Staging>> {
+        let id = match self.ids {
+            IdScheme::Random => DocId::random(),
+            IdScheme::TimeOrdered => DocId::time_ordered(),
+        };
+
+        return self.stage_with_id(id).await;
+    }
+
+    /// Creates a staging bundle re-using an existing ID, i.e. for bundles copied from another repository.
+    pub async fn stage_with_id(&self, id: DocId) -> Result<Bundle<'_, Staging>> {
         let bundle = Bundle {
-            id: DocId::random(),
+            id,
             repository: self,
+            path: Staging::path(self).join(id.filename()),
             state: Default::default(),
         };
 
         info!("Creating staged bundle {:?}", bundle.path());
         tokio::fs::create_dir_all(&bundle.path()).await?;
 
+        self.publish(Event::Staged(id)).await;
+
         return Ok(bundle);
     }
 }
 
 impl<'r> Bundle<'r, Inboxed> {
+    /// Moves the bundle to the archive.
+    ///
+    /// Archiving a bundle which has already been archived is a no-op and returns the archived bundle.
+    #[tracing::instrument(name = "archive", skip(self), fields(bundle = %self.id))]
     pub async fn archive(self) -> Result<Bundle<'r, Archived>> {
-        let archived = Bundle {
-            id: self.id,
-            repository: self.repository,
-            state: PhantomData::default(),
-        };
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = repository.modifying(id).await?;
 
-        info!("Archiving inboxed bundle {:?} -> {:?}", self.path(), archived.path());
+        if tokio::fs::metadata(&self.path()).await.is_err() {
+            if let Some(archived) = repository.archive().get(id).await {
+                info!("Bundle {:?} already archived", archived.path());
+                return Ok(archived);
+            }
+
+            return Err(Moved { id }.into());
+        }
 
-        tokio::fs::create_dir_all(archived.path().parent().expect("No parent directory")).await?;
-        tokio::fs::rename(&self.path(), &archived.path()).await?;
+        let archived = self.transition::<Archived>("Archiving inboxed").await?;
+        repository.publish(Event::Archived(id)).await;
 
         return Ok(archived);
     }
 
-    pub async fn delete(self) -> Result<()> {
-        info!("Deleting inboxed bundle {:?}", self.path());
+    /// Moves the bundle to the trash.
+    ///
+    /// Fails with `Retained` if the bundle has been archived before and is still retained.
+    pub async fn delete(self) -> Result<Bundle<'r, Trashed>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        self.ensure_deletable().await?;
+
+        let transition = repository.begin_transition(id, "trashing").await?;
+        let trashed = self.transition::<Trashed>("Trashing inboxed").await?.mark_trashed().await?;
+        transition.finish().await?;
+
+        repository.publish(Event::Trashed(id)).await;
+
+        return Ok(trashed);
+    }
+}
+
+impl<'r> Bundle<'r, Archived> {
+    /// Moves the bundle back to the inbox.
+    ///
+    /// The metadata is left untouched and must be updated by the caller.
+    pub async fn unarchive(self) -> Result<Bundle<'r, Inboxed>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        let inboxed = self.transition::<Inboxed>("Unarchiving archived").await?;
+        repository.publish(Event::Unarchived(id)).await;
+
+        return Ok(inboxed);
+    }
+
+    /// Moves the bundle to the trash.
+    ///
+    /// Fails with `Retained` if the bundle is retained by a retention rule.
+    pub async fn delete(self) -> Result<Bundle<'r, Trashed>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        self.ensure_deletable().await?;
+
+        let transition = repository.begin_transition(id, "trashing").await?;
+        let trashed = self.transition::<Trashed>("Trashing archived").await?.mark_trashed().await?;
+        transition.finish().await?;
+
+        repository.publish(Event::Trashed(id)).await;
+
+        return Ok(trashed);
+    }
+}
+
+/// A bundle restored from the trash into the state it was deleted from.
+pub enum Restored<'r> {
+    Inboxed(Bundle<'r, Inboxed>),
+    Archived(Bundle<'r, Archived>),
+}
+
+/// Why and when a bundle has been quarantined.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reason {
+    pub quarantined: DateTime<Utc>,
+    pub reason: String,
+}
+
+impl<'r> Bundle<'r, Quarantined> {
+    const REASON: &'static str = "quarantined.json";
+
+    async fn mark_quarantined(self, reason: String) -> Result<Self> {
+        let reason = Reason { quarantined: Utc::now(), reason };
+        atomic::write(self.path_of(Kind::other(Self::REASON)), &serde_json::to_vec_pretty(&reason)?).await?;
+
+        return Ok(self);
+    }
+
+    /// Returns why and when the bundle has been quarantined.
+    pub async fn reason(&self) -> Result<Reason> {
+        let reason = tokio::fs::read(self.path_of(Kind::other(Self::REASON))).await?;
+        return Ok(serde_json::from_slice(&reason)?);
+    }
+
+    /// Moves the bundle back to the staging area.
+    pub async fn release(self) -> Result<Bundle<'r, Staging>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        let transition = repository.begin_transition(id, "releasing").await?;
+
+        tokio::fs::remove_file(self.path_of(Kind::other(Self::REASON))).await?;
+
+        let staged = self.transition::<Staging>("Releasing quarantined").await?;
+        transition.finish().await?;
+        repository.publish(Event::Staged(id)).await;
+
+        return Ok(staged);
+    }
+
+    /// Permanently deletes the bundle.
+    ///
+    /// If shredding is enabled, all fragments are overwritten before the bundle is removed.
+    pub async fn purge(self) -> Result<()> {
+        let _modifying = self.modify().await?;
+
+        if self.repository.shred {
+            info!("Shredding quarantined bundle {:?}", self.path());
+            shred::shred_dir(&self.path()).await?;
+        }
+
+        info!("Purging quarantined bundle {:?}", self.path());
         tokio::fs::remove_dir_all(&self.path()).await?;
 
+        if let Some(cache) = &self.repository.cache {
+            cache.forget(&self.id, self.repository.shred).await;
+        }
+
+        self.repository.publish(Event::Purged(self.id)).await;
+
         return Ok(());
     }
 }
 
-impl<'r> Bundle<'r, Staging> {
-    pub async fn create(self) -> Result<Bundle<'r, Inboxed>> {
-        let inboxed = Bundle {
+impl<'r> Bundle<'r, Failed> {
+    /// Moves the bundle back to the staging area to be juiced again.
+    pub async fn retry(self) -> Result<Bundle<'r, Staging>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        let staged = self.transition::<Staging>("Retrying failed").await?;
+        repository.publish(Event::Staged(id)).await;
+
+        return Ok(staged);
+    }
+
+    /// Permanently deletes the bundle.
+    pub async fn discard(self) -> Result<()> {
+        let _modifying = self.modify().await?;
+
+        info!("Discarding failed bundle {:?}", self.path());
+        tokio::fs::remove_dir_all(&self.path()).await?;
+
+        return Ok(());
+    }
+}
+
+impl<'r> Bundle<'r, Trashed> {
+    const TRASHED: &'static str = "trashed";
+
+    async fn mark_trashed(self) -> Result<Self> {
+        atomic::write(self.path_of(Kind::other(Self::TRASHED)), Utc::now().to_rfc3339().as_bytes()).await?;
+
+        return Ok(self);
+    }
+
+    /// Returns the point in time the bundle was moved to the trash.
+    pub async fn trashed(&self) -> Result<DateTime<Utc>> {
+        let trashed = tokio::fs::read_to_string(self.path_of(Kind::other(Self::TRASHED))).await?;
+        return Ok(DateTime::parse_from_rfc3339(trashed.trim())?.with_timezone(&Utc));
+    }
+
+    /// Restores the bundle to the archive if it has been archived before or to the inbox otherwise.
+    pub async fn restore(self) -> Result<Restored<'r>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        let metadata = self.read_metadata().await?;
+
+        let transition = repository.begin_transition(id, "restoring").await?;
+
+        tokio::fs::remove_file(self.path_of(Kind::other(Self::TRASHED))).await?;
+
+        let restored = if metadata.archived.is_some() {
+            Restored::Archived(self.transition::<Archived>("Restoring trashed").await?)
+        } else {
+            Restored::Inboxed(self.transition::<Inboxed>("Restoring trashed").await?)
+        };
+
+        transition.finish().await?;
+
+        repository.publish(Event::Restored(id)).await;
+
+        return Ok(restored);
+    }
+
+    /// Permanently deletes the bundle.
+    ///
+    /// If shredding is enabled, all fragments are overwritten before the bundle is removed.
+    pub async fn purge(self) -> Result<()> {
+        let _modifying = self.modify().await?;
+
+        if self.repository.shred {
+            info!("Shredding trashed bundle {:?}", self.path());
+            shred::shred_dir(&self.path()).await?;
+        }
+
+        info!("Purging trashed bundle {:?}", self.path());
+        tokio::fs::remove_dir_all(&self.path()).await?;
+
+        if let Some(cache) = &self.repository.cache {
+            cache.forget(&self.id, self.repository.shred).await;
+        }
+
+        self.repository.publish(Event::Purged(self.id)).await;
+
+        return Ok(());
+    }
+}
+
+impl<'r, State: BundleState> Bundle<'r, State> {
+    /// Locks the bundle for a change.
+    ///
+    /// Fails with `Moved` if the bundle has been moved away while waiting for the lock.
+    async fn modify(&self) -> Result<Modifying<'r>> {
+        let modifying = self.repository.modifying(self.id).await?;
+
+        if tokio::fs::metadata(&self.path()).await.is_err() {
+            return Err(Moved { id: self.id }.into());
+        }
+
+        return Ok(modifying);
+    }
+
+    /// Moves the bundle to another state.
+    ///
+    /// Fails with a `Conflict` if the target state already contains a bundle with the same ID.
+    async fn transition<Target: BundleState>(self, action: &str) -> Result<Bundle<'r, Target>> {
+        // Renaming onto an existing (empty) directory would silently succeed
+        if layout::locate::<Target>(self.repository, self.id).await.is_some() {
+            return Err(Conflict { id: self.id }.into());
+        }
+
+        let partition = if Target::PARTITIONED {
+            Target::partition(&self.read_metadata().await?)
+        } else {
+            PathBuf::new()
+        };
+
+        let target = Bundle {
             id: self.id,
             repository: self.repository,
+            path: Target::path(self.repository).join(partition).join(self.id.filename()),
             state: PhantomData::default(),
         };
 
-        info!("Inboxing staged bundle {:?} -> {:?}", self.path(), inboxed.path());
-        tokio::fs::create_dir_all(inboxed.path().parent().expect("No parent directory")).await?;
-        tokio::fs::rename(&self.path(), &inboxed.path()).await?;
+        info!("{} bundle {:?} -> {:?}", action, self.path(), target.path());
+
+        tokio::fs::create_dir_all(target.path().parent().expect("No parent directory")).await?;
+        tokio::fs::rename(&self.path(), &target.path()).await?;
+
+        return Ok(target);
+    }
+}
+
+impl<'r> Bundle<'r, Staging> {
+    #[tracing::instrument(name = "inbox", skip(self), fields(bundle = %self.id))]
+    pub async fn create(self) -> Result<Bundle<'r, Inboxed>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        self.update_manifest().await?;
+        self.compress().await?;
+        self.update_checksums().await?;
+
+        let inboxed = self.transition::<Inboxed>("Inboxing staged").await?;
+        repository.publish(Event::Inboxed(id)).await;
 
         return Ok(inboxed);
     }
 
-    pub async fn write(&self, kind: Kind) -> Result<impl AsyncWrite> {
+    /// Compresses all fragments configured for compression, which are written uncompressed while staged.
+    async fn compress(&self) -> Result<()> {
+        for kind in self.repository.compression.kinds() {
+            let path = self.path_of(kind);
+            let data = match tokio::fs::read(&path).await {
+                Ok(data) => data,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            if let Cow::Owned(encoded) = self.repository.compression.encode(kind, &data)? {
+                info!("Compressing fragment {:?}", path);
+                self.update_fragment_info(kind, &data, true).await?;
+                atomic::write(&path, &encoded).await?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Writes a fragment, which is replaced once the returned writer is committed and discarded if dropped before.
+    ///
+    /// Fragments are written uncompressed and compressed, if configured, once the bundle enters the inbox.
+    pub async fn write(&self, kind: Kind) -> Result<Fragment> {
         let path = self.path().join(kind.filename());
 
         info!("Writing fragment {:?} to {:?}", kind, path);
+        return Fragment::create(path).await;
+    }
+
+    /// Opens a fragment for writing at its end, i.e. to continue an interrupted upload.
+    pub async fn append(&self, kind: Kind) -> Result<impl AsyncWrite> {
+        let path = self.path().join(kind.filename());
+
+        info!("Appending to fragment {:?} at {:?}", kind, path);
         let file = OpenOptions::new()
-            .write(true)
+            .append(true)
             .create(true)
-            .truncate(true)
             .open(path)
             .await?;
 
         return Ok(file);
     }
 
+    /// Cuts a fragment off after the given length, i.e. to discard a partially written chunk.
+    pub async fn truncate(&self, kind: Kind, length: u64) -> Result<()> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(self.path().join(kind.filename()))
+            .await?;
+        file.set_len(length).await?;
+
+        return Ok(());
+    }
+
+    /// Moves the bundle aside after the juicer failed on it permanently, keeping all fragments written so far.
+    pub async fn fail(self) -> Result<Bundle<'r, Failed>> {
+        let _modifying = self.modify().await?;
+
+        return self.transition::<Failed>("Failing staged").await;
+    }
+
+    /// Moves the bundle to the quarantine, recording why it has been set aside.
+    pub async fn quarantine(self, reason: impl Into<String>) -> Result<Bundle<'r, Quarantined>> {
+        let (id, repository) = (self.id, self.repository);
+        let _modifying = self.modify().await?;
+
+        let transition = repository.begin_transition(id, "quarantining").await?;
+        let quarantined = self.transition::<Quarantined>("Quarantining staged").await?
+            .mark_quarantined(reason.into()).await?;
+        transition.finish().await?;
+
+        repository.publish(Event::Quarantined(id)).await;
+
+        return Ok(quarantined);
+    }
+
     pub async fn delete(self) -> Result<()> {
+        let _modifying = self.modify().await?;
+
         info!("Deleting staged bundle {:?}", self.path());
         tokio::fs::remove_dir_all(&self.path()).await?;
 
@@ -279,21 +1270,137 @@ impl<'r> Bundle<'r, Staging> {
     }
 }
 
-impl<'r> Bundle<'r, Inboxed> {
-    pub async fn write_metadata(&self, metadata: &Metadata) -> Result<()> {
+impl<State: BundleState> Bundle<'_, State> {
+    async fn store_metadata(&self, metadata: &Metadata) -> Result<()> {
+        let _modifying = self.modify().await?;
+
         let path = self.path().join(Kind::Metadata.filename());
 
+        let current = self.read_metadata().await.ok();
+
+        let mut metadata = metadata.clone();
+        if let Some(current) = &current {
+            metadata.carry_over(current);
+        }
+        let metadata = &metadata;
+
+        let diff = journal::diff(current.as_ref().unwrap_or(&Metadata::default()), metadata)?;
+
+        // Keep the replaced metadata to allow reverting changes
+        if let Some(current) = current.filter(|current| current != metadata) {
+            self.record_revision(current).await?;
+        }
+
         info!("Writing metadata fragment to {:?}", path);
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)
-            .await?;
+        self.write_encoded(&Kind::Metadata, &metadata.to_vec()?).await?;
+
+        self.update_checksum(Kind::Metadata).await?;
 
-        metadata.save(file).await?;
+        self.repository.publish_diff(Event::MetadataUpdated(self.id), diff).await;
 
         return Ok(());
     }
+
+    async fn store_fragment(&self, kind: Kind, data: &[u8]) -> Result<()> {
+        let _modifying = self.modify().await?;
+
+        return self.write_fragment(kind, data).await;
+    }
+
+    /// Replaces a fragment, while the caller holds the modification lock of the bundle.
+    async fn write_fragment(&self, kind: Kind, data: &[u8]) -> Result<()> {
+        let path = self.path_of(&kind);
+
+        info!("Replacing fragment {:?}", path);
+        self.write_encoded(&kind, data).await?;
+
+        return self.update_checksum(kind).await;
+    }
+
+    /// Writes the contents of a fragment, compressed if configured, and records the fragment in the manifest.
+    ///
+    /// A fragment is marked as compressed before it is written compressed and unmarked after it is written as it is,
+    /// so it is never read compressed without being marked if interrupted in between.
+    async fn write_encoded(&self, kind: &Kind, data: &[u8]) -> Result<()> {
+        let path = self.path_of(kind);
+
+        match self.repository.compression.encode(kind, data)? {
+            Cow::Owned(encoded) => {
+                self.update_fragment_info(kind, data, true).await?;
+                atomic::write(&path, &encoded).await?;
+            }
+
+            Cow::Borrowed(data) => {
+                atomic::write(&path, data).await?;
+                self.update_fragment_info(kind, data, false).await?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    async fn remove_fragment(&self, kind: Kind) -> Result<()> {
+        let _modifying = self.modify().await?;
+
+        let path = self.path_of(&kind);
+
+        info!("Removing fragment {:?}", path);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+
+        let name = kind.filename().to_string_lossy().into_owned();
+
+        if let Some(mut manifest) = self.read_manifest().await? {
+            manifest.remove(&name);
+            manifest.save(self.path().join(Manifest::FILENAME)).await?;
+        }
+
+        if let Some(mut checksums) = self.read_checksums().await? {
+            checksums.remove(&name);
+            checksums.save(self.path().join(Checksums::FILENAME)).await?;
+        }
+
+        return Ok(());
+    }
+
+    /// Returns a revision identifying the current state of the metadata.
+    pub async fn metadata_revision(&self) -> Result<String> {
+        return checksums::sha256(self.path_of(Kind::Metadata)).await;
+    }
+}
+
+impl<'r> Bundle<'r, Inboxed> {
+    pub async fn write_metadata(&self, metadata: &Metadata) -> Result<()> {
+        return self.store_metadata(metadata).await;
+    }
+
+    /// Replaces the contents of a fragment, i.e. to encrypt it before archiving.
+    pub async fn replace(&self, kind: Kind, data: &[u8]) -> Result<()> {
+        return self.store_fragment(kind, data).await;
+    }
+}
+
+impl<'r> Bundle<'r, Archived> {
+    pub async fn write_metadata(&self, metadata: &Metadata) -> Result<()> {
+        return self.store_metadata(metadata).await;
+    }
+
+    /// Replaces the contents of a fragment, i.e. to add renditions missing after archiving.
+    ///
+    /// In write-once mode, only missing fragments can be added and replacing existing ones fails with `WriteOnce`.
+    pub async fn replace(&self, kind: Kind, data: &[u8]) -> Result<()> {
+        self.ensure_writable(&kind).await?;
+        return self.store_fragment(kind, data).await;
+    }
+
+    /// Removes a fragment, i.e. the preview of a page which has been dropped.
+    ///
+    /// In write-once mode, existing fragments can not be removed and fail with `WriteOnce`.
+    pub async fn remove(&self, kind: Kind) -> Result<()> {
+        self.ensure_writable(&kind).await?;
+        return self.remove_fragment(kind).await;
+    }
 }
@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::proto::model::{DocId, Kind};
+
+use super::{Archived, atomic, Bundle, Filename, Inboxed, Quarantined, Repository, Staging, Trashed};
+use super::{get, layout};
+
+/// A transition of a bundle spanning multiple steps, i.e. moving it and marking it afterwards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Marker {
+    action: String,
+    started: DateTime<Utc>,
+}
+
+/// A transition in progress, recorded outside of the bundle until all of its steps are done.
+pub(super) struct Transition {
+    path: PathBuf,
+}
+
+impl Transition {
+    pub(super) async fn finish(self) -> Result<()> {
+        tokio::fs::remove_file(&self.path).await?;
+        return Ok(());
+    }
+}
+
+impl Repository {
+    pub(super) fn transitions(&self) -> PathBuf {
+        return self.path().join("transitions");
+    }
+
+    /// Records that a bundle is about to undergo a transition spanning multiple steps.
+    pub(super) async fn begin_transition(&self, id: DocId, action: &str) -> Result<Transition> {
+        let path = self.transitions().join(id.filename());
+        tokio::fs::create_dir_all(self.transitions()).await?;
+
+        let marker = Marker { action: action.to_string(), started: Utc::now() };
+        atomic::write(&path, &serde_json::to_vec(&marker)?).await?;
+
+        return Ok(Transition { path });
+    }
+
+    /// Removes the temporary files left behind by interrupted writes.
+    async fn sweep(path: &Path) -> Result<()> {
+        let mut entries = tokio::fs::read_dir(path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if atomic::is_temp(&entry.file_name().to_string_lossy()) {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Brings a bundle interrupted while moving to a consistent state, wherever it has ended up.
+    ///
+    /// Bundles in the trash or in the quarantine without their marker get one, as if they had been moved right now.
+    async fn repair_bundle(&self, id: DocId, marker: &Marker) -> Result<bool> {
+        if let Some(bundle) = get::<Trashed>(self, id).await {
+            Self::sweep(&bundle.path()).await?;
+            if tokio::fs::metadata(bundle.path_of(Kind::other(Bundle::<'_, Trashed>::TRASHED))).await.is_err() {
+                bundle.mark_trashed().await?;
+            }
+            return Ok(true);
+        }
+
+        if let Some(bundle) = get::<Quarantined>(self, id).await {
+            Self::sweep(&bundle.path()).await?;
+            if bundle.reason().await.is_err() {
+                bundle.mark_quarantined(format!("Interrupted while {}", marker.action)).await?;
+            }
+            return Ok(true);
+        }
+
+        let path = match layout::locate::<Staging>(self, id).await {
+            Some(path) => Some(path),
+            None => match layout::locate::<Inboxed>(self, id).await {
+                Some(path) => Some(path),
+                None => layout::locate::<Archived>(self, id).await,
+            },
+        };
+
+        return match path {
+            Some(path) => {
+                Self::sweep(&path).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        };
+    }
+
+    /// Completes the transitions interrupted by a crash and returns how many bundles have been repaired.
+    pub(super) async fn repair(&self) -> Result<usize> {
+        let mut entries = match tokio::fs::read_dir(self.transitions()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut repaired = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            // The marker has not been written completely, so the transition has not begun
+            if atomic::is_temp(&name) {
+                tokio::fs::remove_file(entry.path()).await?;
+                continue;
+            }
+
+            let id = match DocId::from_str(&name) {
+                Ok(id) => id,
+                Err(err) => {
+                    warn!("Invalid transition marker {:?}: {}", entry.path(), err);
+                    continue;
+                }
+            };
+
+            let marker = serde_json::from_slice(&tokio::fs::read(entry.path()).await?)
+                .unwrap_or_else(|_| Marker { action: String::from("moving"), started: Utc::now() });
+
+            if self.repair_bundle(id, &marker).await? {
+                warn!("Repaired bundle {} interrupted while {} at {}", id, marker.action, marker.started);
+                repaired += 1;
+            } else {
+                warn!("Bundle {} interrupted while {} at {} vanished", id, marker.action, marker.started);
+            }
+
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+
+        return Ok(repaired);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Utc};
+
+use crate::config::RetentionRule as Config;
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind, Label};
+
+use super::{Bundle, BundleState, Filename};
+
+/// Error returned if a bundle can not be deleted as it is retained by a retention rule.
+#[derive(Debug, thiserror::Error)]
+#[error("Bundle {id} is retained until {until}")]
+pub struct Retained {
+    pub id: DocId,
+    pub until: DateTime<Utc>,
+}
+
+/// Error returned if an existing fragment of an archived bundle should be replaced in write-once mode.
+#[derive(Debug, thiserror::Error)]
+#[error("Fragment {fragment} of bundle {id} is write-once")]
+pub struct WriteOnce {
+    pub id: DocId,
+    pub fragment: String,
+}
+
+/// A rule retaining documents with a label for a number of years after archiving.
+#[derive(Debug, Clone)]
+struct Rule {
+    label: Label,
+    years: u32,
+}
+
+/// The retention rules of the repository.
+///
+/// Unlike the retention of the trash, the rules are taken from the configuration only, so they can not be lifted at
+/// runtime.
+#[derive(Clone, Default)]
+pub(super) struct Rules {
+    rules: Arc<Vec<Rule>>,
+}
+
+impl Rules {
+    pub(super) fn from_config(config: Vec<Config>) -> Self {
+        return Self {
+            rules: Arc::new(config.into_iter()
+                .map(|rule| Rule { label: Label::from(rule.label), years: rule.years })
+                .collect()),
+        };
+    }
+
+    /// Returns the point in time up to which a document is retained, if any rule applies.
+    ///
+    /// Documents never archived are not retained. Labels nested below the label of a rule are covered by the rule.
+    pub(super) fn until(&self, metadata: &Metadata) -> Option<DateTime<Utc>> {
+        let archived = metadata.archived?;
+
+        return self.rules.iter()
+            .filter(|rule| metadata.labels.iter().any(|label| label.is_within(&rule.label)))
+            .map(|rule| {
+                // Documents archived on a leap day are retained until the end of February
+                let year = archived.year() + rule.years as i32;
+                return archived.with_year(year)
+                    .or_else(|| archived.with_day(28).and_then(|archived| archived.with_year(year)))
+                    .expect("Invalid retention date");
+            })
+            .max();
+    }
+}
+
+impl<State: BundleState> Bundle<'_, State> {
+    /// Fails with `Retained` if the bundle must not be deleted yet.
+    pub(super) async fn ensure_deletable(&self) -> Result<()> {
+        let metadata = self.read_metadata().await?;
+
+        if let Some(until) = self.repository.retention.until(&metadata) {
+            if until > Utc::now() {
+                return Err(Retained { id: self.id, until }.into());
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Fails with `WriteOnce` if the fragment exists already and the repository is in write-once mode.
+    pub(super) async fn ensure_writable(&self, kind: &Kind) -> Result<()> {
+        if !self.repository.write_once {
+            return Ok(());
+        }
+
+        if tokio::fs::metadata(self.path_of(kind)).await.is_ok() {
+            return Err(WriteOnce { id: self.id, fragment: kind.filename().to_string_lossy().into_owned() }.into());
+        }
+
+        return Ok(());
+    }
+}
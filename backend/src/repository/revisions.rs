@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+
+use super::{Archived, Bundle, BundleState, Inboxed};
+
+/// A prior revision of the metadata of a bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    /// Sequence number of the revision within the bundle, starting at 1
+    pub revision: usize,
+
+    /// The point in time the revision was replaced
+    pub replaced: DateTime<Utc>,
+
+    /// The user who replaced the revision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+
+    pub metadata: Metadata,
+}
+
+impl<State: BundleState> Bundle<'_, State> {
+    /// Fragment keeping all replaced revisions of the metadata, one JSON object per line.
+    pub(super) const REVISIONS: &'static str = "metadata.history.jsonl";
+
+    /// Returns all replaced revisions of the metadata, oldest first.
+    pub async fn revisions(&self) -> Result<Vec<Revision>> {
+        let data = match tokio::fs::read(self.path_of(Kind::other(Self::REVISIONS))).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        return data.split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| Ok(serde_json::from_slice(line)?))
+            .collect();
+    }
+
+    /// Appends the metadata about to be replaced to the revisions.
+    pub(super) async fn record_revision(&self, metadata: Metadata) -> Result<()> {
+        let revision = Revision {
+            revision: self.revisions().await?.len() + 1,
+            replaced: Utc::now(),
+            actor: self.repository.actor.clone(),
+            metadata,
+        };
+
+        let mut line = serde_json::to_vec(&revision)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path_of(Kind::other(Self::REVISIONS)))
+            .await?;
+        file.write_all(&line).await?;
+
+        return self.update_checksum(Kind::other(Self::REVISIONS)).await;
+    }
+
+    /// Replaces the metadata with a prior revision.
+    ///
+    /// The archive timestamp is kept as it reflects the state of the bundle rather than its content.
+    async fn restore_revision(&self, revision: usize) -> Result<Metadata> {
+        let current = self.read_metadata().await?;
+
+        let mut metadata = self.revisions().await?.into_iter()
+            .find(|candidate| candidate.revision == revision)
+            .ok_or_else(|| anyhow!("No such revision of {}: {}", self.id, revision))?
+            .metadata;
+        metadata.archived = current.archived;
+
+        self.store_metadata(&metadata).await?;
+
+        return Ok(metadata);
+    }
+}
+
+impl<'r> Bundle<'r, Inboxed> {
+    pub async fn revert_metadata(&self, revision: usize) -> Result<Metadata> {
+        return self.restore_revision(revision).await;
+    }
+}
+
+impl<'r> Bundle<'r, Archived> {
+    pub async fn revert_metadata(&self, revision: usize) -> Result<Metadata> {
+        return self.restore_revision(revision).await;
+    }
+}
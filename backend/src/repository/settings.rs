@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use super::{atomic, Repository};
+
+/// Directory in the repository root holding the settings files
+pub const DIR: &str = "settings";
+
+/// A document of repository-scoped settings stored in a file of its own.
+///
+/// Documents are stored along with the version of their schema. Documents written with an older version are upgraded
+/// when loaded, documents written by a newer version are refused.
+pub trait Settings: Serialize + DeserializeOwned + Default {
+    /// Name of the file in the settings directory, without extension
+    const NAME: &'static str;
+
+    /// Version of the schema, increased with every incompatible change
+    const VERSION: u32;
+
+    /// Upgrades a document written with an older version of the schema to the next version.
+    fn upgrade(version: u32, _settings: serde_json::Value) -> Result<serde_json::Value> {
+        bail!("Unknown version {} of settings {}", version, Self::NAME);
+    }
+
+    /// Checks the constraints the schema can not express by its types.
+    fn validate(&self) -> Result<()> {
+        return Ok(());
+    }
+}
+
+/// The file format of a settings document.
+#[derive(Serialize, Deserialize)]
+struct Document<S> {
+    version: u32,
+    settings: S,
+}
+
+/// Error returned if settings have been written by a newer version.
+#[derive(Debug, thiserror::Error)]
+#[error("Settings {name} version {found} is newer than the supported version {supported}")]
+pub struct UnsupportedSettings {
+    pub name: &'static str,
+    pub found: u32,
+    pub supported: u32,
+}
+
+/// Retention of documents in the repository.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Retention {
+    /// Days after which trashed bundles are purged automatically, kept forever if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash: Option<u32>,
+}
+
+impl Settings for Retention {
+    const NAME: &'static str = "retention";
+    const VERSION: u32 = 1;
+
+    fn validate(&self) -> Result<()> {
+        if self.trash == Some(0) {
+            bail!("Trash retention must be at least one day");
+        }
+
+        return Ok(());
+    }
+}
+
+/// Settings files in the repository root from before the settings directory, with the name of their settings
+const LEGACY: &[(&str, &str)] = &[
+    ("labels.json", "labels"),
+    ("rules.json", "rules"),
+    ("asn", "asn"),
+];
+
+/// Parses a settings file from the repository root into the first version of its settings.
+fn legacy(file: &str, data: &[u8]) -> Result<serde_json::Value> {
+    return match file {
+        // The last assigned ASN has been stored as plain number
+        "asn" => Ok(serde_json::json!({ "last": std::str::from_utf8(data)?.trim().parse::<i64>()? })),
+        _ => Ok(serde_json::from_slice(data)?),
+    };
+}
+
+impl Repository {
+    fn settings_path(&self, name: &str) -> PathBuf {
+        return self.path().join(DIR).join(format!("{}.json", name));
+    }
+
+    /// Loads repository-scoped settings, which are the defaults if never saved.
+    pub async fn load_settings<S: Settings>(&self) -> Result<S> {
+        let path = self.settings_path(S::NAME);
+
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(S::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let document = serde_json::from_slice::<Document<serde_json::Value>>(&data)
+            .with_context(|| format!("Invalid settings {}", S::NAME))?;
+
+        if document.version > S::VERSION {
+            return Err(UnsupportedSettings { name: S::NAME, found: document.version, supported: S::VERSION }.into());
+        }
+
+        let mut settings = document.settings;
+        for version in document.version..S::VERSION {
+            settings = S::upgrade(version, settings)?;
+        }
+
+        let settings = serde_json::from_value::<S>(settings)
+            .with_context(|| format!("Invalid settings {}", S::NAME))?;
+        settings.validate()
+            .with_context(|| format!("Invalid settings {}", S::NAME))?;
+
+        return Ok(settings);
+    }
+
+    /// Validates and atomically replaces repository-scoped settings.
+    pub async fn save_settings<S: Settings>(&self, settings: &S) -> Result<()> {
+        settings.validate()
+            .with_context(|| format!("Invalid settings {}", S::NAME))?;
+
+        let path = self.settings_path(S::NAME);
+        tokio::fs::create_dir_all(path.parent().expect("No parent directory")).await?;
+
+        let document = Document { version: S::VERSION, settings };
+        return atomic::write(&path, &serde_json::to_vec_pretty(&document)?).await;
+    }
+
+    /// Moves the settings files from the repository root into the settings directory, returning the number moved.
+    pub(super) async fn move_settings(&self) -> Result<usize> {
+        tokio::fs::create_dir_all(self.path().join(DIR)).await?;
+
+        let mut moved = 0;
+        for (file, name) in LEGACY {
+            let source = self.path().join(file);
+            let data = match tokio::fs::read(&source).await {
+                Ok(data) => data,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            let settings = legacy(file, &data)
+                .with_context(|| format!("Invalid settings file {:?}", source))?;
+
+            info!("Moving settings {:?} to {}", source, name);
+            let document = Document { version: 1, settings };
+            atomic::write(self.settings_path(name), &serde_json::to_vec_pretty(&document)?).await?;
+            tokio::fs::remove_file(&source).await?;
+
+            moved += 1;
+        }
+
+        return Ok(moved);
+    }
+}
@@ -0,0 +1,64 @@
+use std::io::SeekFrom;
+use std::path::Path;
+
+use anyhow::Result;
+use futures::TryStreamExt;
+use log::warn;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// Number of passes used to overwrite a file
+const PASSES: usize = 3;
+
+/// Overwrites the contents of a file before it gets unlinked.
+///
+/// This is best-effort only: copy-on-write or journaling filesystems, SSD wear leveling and backups may retain copies
+/// of the original data.
+pub(super) async fn shred_file(path: impl AsRef<Path>) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await?;
+
+    let len = file.metadata().await?.len();
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    for pass in 0..PASSES {
+        // Alternate between ones and zeros, ending with zeros
+        let pattern = if (PASSES - pass) % 2 == 0 { 0xff } else { 0x00 };
+        buffer.iter_mut().for_each(|b| *b = pattern);
+
+        file.seek(SeekFrom::Start(0)).await?;
+
+        let mut remaining = len;
+        while remaining > 0 {
+            let n = remaining.min(buffer.len() as u64) as usize;
+            file.write_all(&buffer[..n]).await?;
+            remaining -= n as u64;
+        }
+
+        file.sync_all().await?;
+    }
+
+    return Ok(());
+}
+
+/// Overwrites all files in the given directory.
+///
+/// Failures are logged but do not abort the shredding of the remaining files.
+pub async fn shred_dir(path: impl AsRef<Path>) -> Result<()> {
+    let entries = tokio::fs::read_dir(path).await?
+        .try_collect::<Vec<_>>().await?;
+
+    for entry in entries {
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+
+        if let Err(err) = shred_file(entry.path()).await {
+            warn!("Failed to shred {:?}: {:#}", entry.path(), err);
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind};
+
+use super::{Archived, Bundle, BundleState, Checksums, compression, Filename, Inboxed, list, Manifest, Repository, sha256, Trashed};
+
+/// Fragments which are modified in place and are therefore captured by value.
+pub(super) fn is_mutable(name: &str) -> bool {
+    return name == Kind::Metadata.filename() || name == Checksums::FILENAME || name == Manifest::FILENAME || name == "trashed"
+        || name == Bundle::<Archived>::REVISIONS || name == Bundle::<Archived>::VERSIONS;
+}
+
+/// The state a bundle was in when the snapshot was taken.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Location {
+    Inbox,
+    Archive,
+    Trash,
+}
+
+/// A bundle as captured by a snapshot.
+#[derive(Debug, Clone)]
+pub struct Snapshotted {
+    id: DocId,
+    location: Location,
+
+    /// Path of the bundle relative to the repository
+    path: PathBuf,
+
+    /// Contents of the fragments modified in place, like the metadata
+    mutable: BTreeMap<String, Vec<u8>>,
+
+    /// SHA-256 checksums of all other fragments
+    immutable: BTreeMap<String, String>,
+}
+
+impl Snapshotted {
+    async fn capture<State: BundleState>(bundle: &Bundle<'_, State>, location: Location, root: &Path) -> Result<Self> {
+        let checksums = bundle.read_checksums().await?.unwrap_or_default();
+
+        let mut mutable = BTreeMap::new();
+        let mut immutable = BTreeMap::new();
+        for name in bundle.fragment_names().await? {
+            let path = bundle.path().join(&name);
+            if is_mutable(&name) {
+                mutable.insert(name, tokio::fs::read(&path).await?);
+            } else {
+                let checksum = match checksums.get(&name) {
+                    Some(checksum) => checksum.to_string(),
+                    None => sha256(&path).await?,
+                };
+                immutable.insert(name, checksum);
+            }
+        }
+
+        return Ok(Self {
+            id: *bundle.id(),
+            location,
+            path: bundle.path().strip_prefix(root)?.to_path_buf(),
+            mutable,
+            immutable,
+        });
+    }
+
+    pub fn id(&self) -> &DocId { return &self.id; }
+
+    pub fn location(&self) -> Location { return self.location; }
+
+    /// Returns the path of the bundle relative to the repository.
+    pub fn path(&self) -> &Path { return &self.path; }
+
+    /// Lists the filenames of all fragments in the bundle.
+    pub fn fragment_names(&self) -> impl Iterator<Item=&str> {
+        return self.mutable.keys().chain(self.immutable.keys()).map(String::as_str);
+    }
+
+    /// Returns the SHA-256 checksum of a fragment at the time of the snapshot.
+    pub fn checksum(&self, name: &str) -> Option<String> {
+        if let Some(data) = self.mutable.get(name) {
+            return Some(hex::encode(Sha256::digest(data)));
+        }
+
+        return self.immutable.get(name).cloned();
+    }
+
+    pub fn metadata(&self) -> Result<Metadata> {
+        let name = Kind::Metadata.filename().to_string_lossy().into_owned();
+
+        let data = self.mutable.get(&name)
+            .ok_or_else(|| anyhow!("Metadata missing in bundle: {}", self.id))?;
+
+        // The metadata is compressed only if marked so in the manifest captured along with it
+        let manifest = match self.mutable.get(Manifest::FILENAME) {
+            Some(manifest) => serde_json::from_slice::<Manifest>(manifest)?,
+            None => Manifest::default(),
+        };
+
+        if manifest.get(&name).map_or(false, |info| info.compressed) {
+            return Ok(serde_json::from_slice(&compression::decode(data.clone())?)?);
+        }
+
+        return Ok(serde_json::from_slice(data)?);
+    }
+
+    /// Checks fragments read after the snapshot against the state captured by the snapshot.
+    fn verify(&self, name: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self.immutable.get(name) {
+            Some(checksum) if *checksum == hex::encode(Sha256::digest(&data)) => {
+                return Ok(data);
+            }
+
+            Some(_) => {
+                bail!("Fragment {} of bundle {} changed since the snapshot", name, self.id);
+            }
+
+            None => {
+                bail!("Fragment {} of bundle {} not in snapshot", name, self.id);
+            }
+        }
+    }
+}
+
+/// A consistent view of the bundles in the inbox, the archive and the trash at a point in time.
+///
+/// Fragments modified in place are captured by value, all others by checksum. Reading a fragment which has been
+/// replaced since the snapshot was taken fails instead of mixing states. Bundles being staged are not captured.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    taken: DateTime<Utc>,
+
+    root: PathBuf,
+
+    bundles: Vec<Snapshotted>,
+}
+
+impl Snapshot {
+    pub fn taken(&self) -> DateTime<Utc> { return self.taken; }
+
+    pub fn bundles(&self) -> &[Snapshotted] { return &self.bundles; }
+
+    pub fn get(&self, id: DocId) -> Option<&Snapshotted> {
+        return self.bundles.iter().find(|bundle| bundle.id == id);
+    }
+
+    /// Reads a fragment of a bundle as of the snapshot, blocking until it is read.
+    pub fn read(&self, bundle: &Snapshotted, name: &str) -> Result<Vec<u8>> {
+        if let Some(data) = bundle.mutable.get(name) {
+            return Ok(data.clone());
+        }
+
+        let path = self.root.join(&bundle.path).join(name);
+        let data = std::fs::read(&path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+
+        return bundle.verify(name, data);
+    }
+
+    /// Reads a fragment of a bundle as of the snapshot.
+    pub async fn load(&self, bundle: &Snapshotted, name: &str) -> Result<Vec<u8>> {
+        if let Some(data) = bundle.mutable.get(name) {
+            return Ok(data.clone());
+        }
+
+        let path = self.root.join(&bundle.path).join(name);
+        let data = tokio::fs::read(&path).await
+            .with_context(|| format!("Failed to read {:?}", path))?;
+
+        return bundle.verify(name, data);
+    }
+}
+
+impl Repository {
+    /// Takes a snapshot of the repository.
+    ///
+    /// Changes to bundles are held off while the snapshot is taken, which only requires reading the mutable fragments.
+    pub async fn snapshot(&self) -> Result<Snapshot> {
+        let _quiesced = self.writes.write().await;
+
+        let taken = Utc::now();
+        let root = self.path().to_path_buf();
+
+        let mut bundles = Vec::new();
+        for bundle in list::<Inboxed>(self).await? {
+            bundles.push(Snapshotted::capture(&bundle, Location::Inbox, &root).await?);
+        }
+        for bundle in list::<Archived>(self).await? {
+            bundles.push(Snapshotted::capture(&bundle, Location::Archive, &root).await?);
+        }
+        for bundle in list::<Trashed>(self).await? {
+            bundles.push(Snapshotted::capture(&bundle, Location::Trash, &root).await?);
+        }
+
+        info!("Took snapshot of {} bundles", bundles.len());
+
+        return Ok(Snapshot { taken, root, bundles });
+    }
+}
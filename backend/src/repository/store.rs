@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind};
+
+use super::{Bundle, BundleState, Location, Repository, Restored};
+
+/// The operations on the documents of a repository, regardless of how their bundles are laid out.
+///
+/// Code depending on this instead of `Repository` can be tested against the in-memory store from `memory`, which
+/// does not touch the filesystem.
+#[async_trait]
+pub trait Store {
+    /// Stores a new document along with its metadata in the inbox, bypassing the juicer.
+    async fn store(&self, document: &[u8], metadata: Metadata) -> Result<DocId>;
+
+    /// Lists the IDs of the documents at the location.
+    async fn list(&self, location: Location) -> Result<Vec<DocId>>;
+
+    /// Returns the location of a document, if the document exists.
+    async fn locate(&self, id: DocId) -> Result<Option<Location>>;
+
+    /// Reads the metadata of a document.
+    async fn read_metadata(&self, id: DocId) -> Result<Metadata>;
+
+    /// Replaces the metadata of an inboxed or archived document.
+    async fn write_metadata(&self, id: DocId, metadata: &Metadata) -> Result<()>;
+
+    /// Reads a fragment of a document, if the fragment exists.
+    async fn read(&self, id: DocId, kind: Kind) -> Result<Option<Vec<u8>>>;
+
+    /// Moves a document to another location.
+    ///
+    /// Documents are moved between the inbox and the archive and can be deleted from both into the trash. Restoring
+    /// a document from the trash moves it back to where it has been deleted from, regardless of the location given.
+    async fn relocate(&self, id: DocId, location: Location) -> Result<Location>;
+}
+
+fn not_found(id: DocId) -> anyhow::Error {
+    return anyhow!("Bundle not found: {}", id);
+}
+
+async fn read_all<State: BundleState>(bundle: &Bundle<'_, State>, kind: Kind) -> Result<Option<Vec<u8>>> {
+    let mut reader = match bundle.read(kind).await? {
+        Some(reader) => Box::pin(reader),
+        None => return Ok(None),
+    };
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+
+    return Ok(Some(data));
+}
+
+#[async_trait]
+impl Store for Repository {
+    async fn store(&self, document: &[u8], metadata: Metadata) -> Result<DocId> {
+        let staging = self.stage().await?;
+
+        let mut fragment = staging.write(Kind::Document).await?;
+        fragment.write_all(document).await?;
+        fragment.commit().await?;
+
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
+
+        return Ok(*staging.create().await?.id());
+    }
+
+    async fn list(&self, location: Location) -> Result<Vec<DocId>> {
+        return Ok(match location {
+            Location::Inbox => self.inbox().list().await?.iter().map(|bundle| *bundle.id()).collect(),
+            Location::Archive => self.archive().list().await?.iter().map(|bundle| *bundle.id()).collect(),
+            Location::Trash => self.trash().list().await?.iter().map(|bundle| *bundle.id()).collect(),
+        });
+    }
+
+    async fn locate(&self, id: DocId) -> Result<Option<Location>> {
+        if self.inbox().get(id).await.is_some() {
+            return Ok(Some(Location::Inbox));
+        }
+
+        if self.archive().get(id).await.is_some() {
+            return Ok(Some(Location::Archive));
+        }
+
+        if self.trash().get(id).await.is_some() {
+            return Ok(Some(Location::Trash));
+        }
+
+        return Ok(None);
+    }
+
+    async fn read_metadata(&self, id: DocId) -> Result<Metadata> {
+        if let Some(bundle) = self.inbox().get(id).await {
+            return bundle.read_metadata().await;
+        }
+
+        if let Some(bundle) = self.archive().get(id).await {
+            return bundle.read_metadata().await;
+        }
+
+        if let Some(bundle) = self.trash().get(id).await {
+            return bundle.read_metadata().await;
+        }
+
+        return Err(not_found(id));
+    }
+
+    async fn write_metadata(&self, id: DocId, metadata: &Metadata) -> Result<()> {
+        if let Some(bundle) = self.inbox().get(id).await {
+            return bundle.write_metadata(metadata).await;
+        }
+
+        if let Some(bundle) = self.archive().get(id).await {
+            return bundle.write_metadata(metadata).await;
+        }
+
+        return Err(not_found(id));
+    }
+
+    async fn read(&self, id: DocId, kind: Kind) -> Result<Option<Vec<u8>>> {
+        if let Some(bundle) = self.inbox().get(id).await {
+            return read_all(&bundle, kind).await;
+        }
+
+        if let Some(bundle) = self.archive().get(id).await {
+            return read_all(&bundle, kind).await;
+        }
+
+        if let Some(bundle) = self.trash().get(id).await {
+            return read_all(&bundle, kind).await;
+        }
+
+        return Err(not_found(id));
+    }
+
+    async fn relocate(&self, id: DocId, location: Location) -> Result<Location> {
+        if let Some(bundle) = self.inbox().get(id).await {
+            match location {
+                Location::Inbox => {}
+                Location::Archive => { bundle.archive().await?; }
+                Location::Trash => { bundle.delete().await?; }
+            }
+
+            return Ok(location);
+        }
+
+        if let Some(bundle) = self.archive().get(id).await {
+            match location {
+                Location::Inbox => { bundle.unarchive().await?; }
+                Location::Archive => {}
+                Location::Trash => { bundle.delete().await?; }
+            }
+
+            return Ok(location);
+        }
+
+        if let Some(bundle) = self.trash().get(id).await {
+            if location == Location::Trash {
+                return Ok(location);
+            }
+
+            return Ok(match bundle.restore().await? {
+                Restored::Inboxed(_) => Location::Inbox,
+                Restored::Archived(_) => Location::Archive,
+            });
+        }
+
+        return Err(not_found(id));
+    }
+}
@@ -0,0 +1,1015 @@
+use spectral::prelude::*;
+use tokio::io::AsyncWriteExt;
+
+use crate::meta::Metadata;
+use crate::proto::model::Kind;
+
+use super::*;
+
+async fn archived(repository: &Repository) -> Bundle<'_, Archived> {
+    let staging = repository.stage().await.unwrap();
+
+    let mut document = staging.write(Kind::Document).await.unwrap();
+    document.write_all(b"my document").await.unwrap();
+    document.commit().await.unwrap();
+
+    let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+    plaintext.write_all(b"my document plaintext").await.unwrap();
+    plaintext.commit().await.unwrap();
+
+    Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+    return staging.create().await.unwrap()
+        .archive().await.unwrap();
+}
+
+mod fsck {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_ok() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        archived(&repository).await;
+
+        let report = repository.verify().await.unwrap();
+        assert_that!(report.bundles).is_equal_to(1);
+        assert_that!(report.problems).is_empty();
+    }
+
+    #[tokio::test]
+    async fn test_verify_corrupted() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+
+        tokio::fs::write(bundle.path_of(Kind::Plaintext), b"tampered").await.unwrap();
+        tokio::fs::remove_file(bundle.path_of(Kind::Document)).await.unwrap();
+
+        let report = repository.verify().await.unwrap();
+        let problems = report.problems.into_iter().map(|(_, problem)| problem).collect::<Vec<_>>();
+
+        assert_that!(problems).has_length(2);
+        assert_that!(problems).contains(Problem::MissingFragment { name: String::from("document.pdf") });
+        assert_that!(problems.iter().any(|problem| matches!(problem, Problem::Corrupted { name, .. } if name == "document.txt"))).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_verify_replaced() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+
+        bundle.replace(Kind::Thumbnail, b"my thumbnail").await.unwrap();
+
+        let report = repository.verify().await.unwrap();
+        assert_that!(report.problems).is_empty();
+    }
+
+    #[tokio::test]
+    async fn test_verify_bundle() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+
+        let verification = bundle.verify().await.unwrap();
+        assert_that!(verification.problems).is_equal_to(vec![Problem::PageCount { recorded: 0, actual: 1 }]);
+
+        let mut metadata = bundle.read_metadata().await.unwrap();
+        metadata.pages = 1;
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        assert_that!(bundle.verify().await.unwrap().is_ok()).is_true();
+
+        tokio::fs::remove_file(bundle.path_of(Kind::Document)).await.unwrap();
+
+        let verification = bundle.verify().await.unwrap();
+        assert_that!(verification.problems).is_equal_to(vec![Problem::MissingFragment { name: String::from("document.pdf") }]);
+    }
+}
+
+mod events {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lifecycle() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let mut events = repository.subscribe();
+
+        let bundle = archived(&repository).await;
+        let id = *bundle.id();
+
+        bundle.delete().await.unwrap()
+            .purge().await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+
+        assert_that!(received).is_equal_to(vec![
+            Event::Staged(id),
+            Event::Inboxed(id),
+            Event::Archived(id),
+            Event::Trashed(id),
+            Event::Purged(id),
+        ]);
+    }
+}
+
+mod archive {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_archive_idempotent() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let id = *archived(&repository).await.id();
+
+        // A stale handle to the bundle formerly in the inbox
+        let stale = Bundle::<Inboxed> {
+            id,
+            repository: &repository,
+            path: Inboxed::path(&repository).join(id.filename()),
+            state: PhantomData::default(),
+        };
+
+        let archived = stale.archive().await.unwrap();
+        assert_that!(archived.id()).is_equal_to(&id);
+        assert_that!(repository.archive().get(id).await.is_some()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_unarchive() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+        let id = *bundle.id();
+
+        let mut events = repository.subscribe();
+
+        bundle.unarchive().await.unwrap();
+        assert_that!(repository.archive().get(id).await.is_none()).is_true();
+        assert_that!(repository.inbox().get(id).await.is_some()).is_true();
+        assert_that!(events.try_recv().ok()).is_equal_to(Some(Event::Unarchived(id)));
+    }
+
+    #[tokio::test]
+    async fn test_archive_conflict() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+        let id = *bundle.id();
+
+        // Re-create a bundle with the same ID in the inbox
+        tokio::fs::create_dir_all(repository.path().join("inbox").join(id.to_string())).await.unwrap();
+        let inboxed = repository.inbox().get(id).await.unwrap();
+
+        let err = inboxed.archive().await.err().unwrap();
+        assert_that!(err.downcast_ref::<Conflict>().map(|conflict| conflict.id)).is_equal_to(Some(id));
+        assert_that!(repository.inbox().get(id).await.is_some()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_archive_partitioned() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+
+        let partition = Utc::now().format("%Y/%m").to_string();
+        assert_that!(bundle.path()).is_equal_to(repository.path().join("archive").join(partition).join(bundle.id().filename()));
+        assert_that!(repository.archive().list().await.unwrap()).has_length(1);
+    }
+
+    #[tokio::test]
+    async fn test_archive_partitioned_by_date() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let staging = repository.stage().await.unwrap();
+        Metadata {
+            date: Some(chrono::NaiveDate::from_ymd(2019, 3, 14)),
+            ..Metadata::new()
+        }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        let bundle = staging.create().await.unwrap()
+            .archive().await.unwrap();
+
+        assert_that!(bundle.path()).is_equal_to(repository.path().join("archive").join("2019/03").join(bundle.id().filename()));
+        assert_that!(repository.archive().get(*bundle.id()).await.is_some()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_migrate_flat() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repository");
+
+        let repository = Repository::with_path(path.clone()).await.unwrap();
+        let bundle = archived(&repository).await;
+        let id = *bundle.id();
+
+        let mut metadata = bundle.read_metadata().await.unwrap();
+        metadata.archived = Some(DateTime::parse_from_rfc3339("2019-03-14T12:00:00Z").unwrap().with_timezone(&Utc));
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        // Move the bundle back to the flat layout of repositories without format marker
+        tokio::fs::rename(bundle.path(), path.join("archive").join(id.filename())).await.unwrap();
+        tokio::fs::remove_file(path.join("format")).await.unwrap();
+
+        let pending = Repository::pending_migrations(&path).await.unwrap();
+        assert_that!(pending.iter().map(|migration| migration.version).collect::<Vec<_>>()).is_equal_to(vec![2, 3]);
+
+        let repository = Repository::with_path(path.clone()).await.unwrap();
+        let bundle = repository.archive().get(id).await.unwrap();
+        assert_that!(bundle.path()).is_equal_to(path.join("archive").join("2019").join("03").join(id.filename()));
+        assert_that!(Repository::pending_migrations(&path).await.unwrap()).is_empty();
+
+        // The backup keeps the previous format
+        let backup = std::fs::read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|entry| entry != &path)
+            .unwrap();
+        assert_that!(backup.join("archive").join(id.filename()).join("metadata.json").exists()).is_true();
+        assert_that!(backup.join("format").exists()).is_false();
+        assert_that!(backup.join(Journal::FILENAME).exists()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_path_buf();
+
+        Repository::with_path(path.clone()).await.unwrap();
+        tokio::fs::write(path.join("format"), format!("{}\n", VERSION + 1)).await.unwrap();
+
+        let err = Repository::with_path(path.clone()).await.err().unwrap();
+        assert_that!(err.downcast_ref::<Unsupported>().map(|unsupported| unsupported.found)).is_equal_to(Some(VERSION + 1));
+    }
+}
+
+mod settings {
+    use crate::labels::Definition;
+    use crate::proto::model::Label;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_settings() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        assert_that!(repository.load_settings::<Retention>().await.unwrap()).is_equal_to(Retention::default());
+
+        repository.save_settings(&Retention { trash: Some(30) }).await.unwrap();
+        assert_that!(repository.load_settings::<Retention>().await.unwrap()).is_equal_to(Retention { trash: Some(30) });
+
+        // Invalid settings are never written
+        assert_that!(repository.save_settings(&Retention { trash: Some(0) }).await).is_err();
+        assert_that!(repository.load_settings::<Retention>().await.unwrap()).is_equal_to(Retention { trash: Some(30) });
+
+        tokio::fs::write(repository.path().join("settings").join("retention.json"), br#"{"version": 2, "settings": {}}"#).await.unwrap();
+        let err = repository.load_settings::<Retention>().await.err().unwrap();
+        assert_that!(err.downcast_ref::<UnsupportedSettings>().map(|unsupported| unsupported.found)).is_equal_to(Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repository");
+
+        Repository::with_path(path.clone()).await.unwrap();
+
+        // Settings of repositories in the previous format are kept in the repository root
+        tokio::fs::write(path.join("format"), b"2\n").await.unwrap();
+        tokio::fs::write(path.join("asn"), b"42").await.unwrap();
+        tokio::fs::write(path.join("labels.json"), br##"{"finance": {"color": "#ff8800"}}"##).await.unwrap();
+
+        let repository = Repository::with_path(path.clone()).await.unwrap();
+        assert_that!(path.join("asn").exists()).is_false();
+        assert_that!(path.join("labels.json").exists()).is_false();
+
+        let labels = repository.load_settings::<BTreeMap<Label, Definition>>().await.unwrap();
+        assert_that!(labels.get(&Label::from("finance")).and_then(|definition| definition.color.clone())).is_equal_to(Some(String::from("#ff8800")));
+
+        let asn = tokio::fs::read_to_string(path.join("settings").join("asn.json")).await.unwrap();
+        assert_that!(serde_json::from_str::<serde_json::Value>(&asn).unwrap()).is_equal_to(serde_json::json!({ "version": 1, "settings": { "last": 42 } }));
+    }
+}
+
+mod locks {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_archive_and_write() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let id = *archived(&repository).await.unarchive().await.unwrap().id();
+
+        let first = repository.inbox().get(id).await.unwrap();
+        let second = repository.inbox().get(id).await.unwrap();
+
+        let (archived, written) = tokio::join!(first.archive(), second.write_metadata(&Metadata {
+            title: Some(String::from("Concurrent")),
+            ..Metadata::new()
+        }));
+
+        // Either the metadata is written before the bundle is archived or the write fails cleanly afterwards
+        let archived = archived.unwrap();
+        match written {
+            Ok(()) => assert_that!(archived.read_metadata().await.unwrap().title).is_equal_to(Some(String::from("Concurrent"))),
+            Err(err) => assert_that!(err.downcast_ref::<Moved>().map(|moved| moved.id)).is_equal_to(Some(id)),
+        }
+
+        // Writing through a handle to the former location fails without leaving anything behind
+        let err = second.write_metadata(&Metadata::new()).await.err().unwrap();
+        assert_that!(err.downcast_ref::<Moved>().map(|moved| moved.id)).is_equal_to(Some(id));
+        assert_that!(repository.inbox().get(id).await.is_none()).is_true();
+    }
+}
+
+mod compression {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_compressed() {
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.compression = Compression::from_config(crate::config::Compression {
+            fragments: vec![String::from("plaintext"), String::from("metadata")],
+            level: 3,
+        }).unwrap();
+
+        let bundle = archived(&repository).await;
+        assert_that!(bundle.is_compressed(Kind::Plaintext).await.unwrap()).is_true();
+        assert_that!(bundle.is_compressed(Kind::Metadata).await.unwrap()).is_true();
+        assert_that!(bundle.is_compressed(Kind::Document).await.unwrap()).is_false();
+
+        assert_that!(bundle.read_plaintext().await.unwrap()).is_equal_to(String::from("my document plaintext"));
+
+        bundle.write_metadata(&Metadata {
+            title: Some(String::from("Compressed")),
+            ..Metadata::new()
+        }).await.unwrap();
+        assert_that!(bundle.is_compressed(Kind::Metadata).await.unwrap()).is_true();
+        assert_that!(bundle.read_metadata().await.unwrap().title).is_equal_to(Some(String::from("Compressed")));
+
+        // Fragments written before compression was enabled are read as they are
+        tokio::fs::write(bundle.path_of(Kind::Plaintext), b"uncompressed plaintext").await.unwrap();
+        assert_that!(bundle.read_plaintext().await.unwrap()).is_equal_to(String::from("uncompressed plaintext"));
+        // Compressed fragments are told by their marker, not by their contents
+        let id = *bundle.id();
+        repository.compression = Compression::default();
+        let bundle = repository.archive().get(id).await.unwrap();
+        bundle.replace(Kind::Plaintext, &[0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x42]).await.unwrap();
+        assert_that!(bundle.is_compressed(Kind::Plaintext).await.unwrap()).is_false();
+        assert_that!(bundle.read_plaintext().await.is_err()).is_true();
+
+        let mut data = Vec::new();
+        bundle.read(Kind::Plaintext).await.unwrap().unwrap().read_to_end(&mut data).await.unwrap();
+        assert_that!(data).is_equal_to(vec![0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x42]);
+
+        // Metadata compressed before is still read after compression has been disabled
+        assert_that!(bundle.read_metadata().await.unwrap().title).is_equal_to(Some(String::from("Compressed")));
+    }
+}
+
+mod cache {
+    use super::*;
+
+    fn copies(path: &Path) -> usize {
+        return std::fs::read_dir(path).unwrap().count();
+    }
+
+    #[tokio::test]
+    async fn test_read_through() {
+        let cache = tempfile::tempdir().unwrap();
+
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.cache = Some(Arc::new(Cache::open(cache.path(), 16).await.unwrap()));
+
+        let bundle = archived(&repository).await;
+
+        let mut data = Vec::new();
+        bundle.open(Kind::Document).await.unwrap().read_to_end(&mut data).await.unwrap();
+        assert_that!(data).is_equal_to(b"my document".to_vec());
+        assert_that!(copies(cache.path())).is_equal_to(1);
+
+        // Changed fragments are copied again, while the outdated copy is evicted beyond the size bound
+        tokio::fs::write(bundle.path_of(Kind::Document), b"my new document").await.unwrap();
+
+        let mut data = Vec::new();
+        bundle.read(Kind::Document).await.unwrap().unwrap().read_to_end(&mut data).await.unwrap();
+        assert_that!(data).is_equal_to(b"my new document".to_vec());
+        assert_that!(copies(cache.path())).is_equal_to(1);
+
+        // Other fragments and those exceeding the cache are read directly
+        assert_that!(bundle.read_plaintext().await.unwrap()).is_equal_to(String::from("my document plaintext"));
+
+        tokio::fs::write(bundle.path_of(Kind::Document), b"my document exceeding the cache").await.unwrap();
+
+        let mut data = Vec::new();
+        bundle.open(Kind::Document).await.unwrap().read_to_end(&mut data).await.unwrap();
+        assert_that!(data).is_equal_to(b"my document exceeding the cache".to_vec());
+        assert_that!(copies(cache.path())).is_equal_to(1);
+
+        // Copies are taken over when opened again, which evicts them if the size bound has been lowered
+        Cache::open(cache.path(), 16).await.unwrap();
+        assert_that!(copies(cache.path())).is_equal_to(1);
+
+        Cache::open(cache.path(), 8).await.unwrap();
+        assert_that!(copies(cache.path())).is_equal_to(0);
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        let cache = tempfile::tempdir().unwrap();
+
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.cache = Some(Arc::new(Cache::open(cache.path(), 1024).await.unwrap()));
+
+        let bundle = archived(&repository).await;
+        bundle.open(Kind::Document).await.unwrap();
+        assert_that!(copies(cache.path())).is_equal_to(1);
+
+        // Copies of purged bundles are removed along with the bundle
+        bundle.delete().await.unwrap().purge().await.unwrap();
+        assert_that!(copies(cache.path())).is_equal_to(0);
+    }
+}
+
+mod revisions {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_revert() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+
+        let original = bundle.read_metadata().await.unwrap();
+
+        let mut metadata = original.clone();
+        metadata.title = Some(String::from("first"));
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        metadata.title = Some(String::from("second"));
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        // Writing unchanged metadata does not create a revision
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        let revisions = bundle.revisions().await.unwrap();
+        assert_that!(revisions.iter().map(|revision| revision.metadata.title.clone()).collect::<Vec<_>>())
+            .is_equal_to(vec![None, Some(String::from("first"))]);
+
+        bundle.revert_metadata(2).await.unwrap();
+        assert_that!(bundle.read_metadata().await.unwrap().title).is_equal_to(Some(String::from("first")));
+        assert_that!(bundle.revisions().await.unwrap()).has_length(3);
+
+        assert_that!(bundle.revert_metadata(7).await.is_err()).is_true();
+
+        let report = repository.verify().await.unwrap();
+        assert_that!(report.problems).is_empty();
+    }
+}
+
+mod versions {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replace_document() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap().acting_as("admin");
+
+        let bundle = archived(&repository).await;
+        assert_that!(bundle.versions().await.unwrap()).is_empty();
+
+        let version = bundle.replace_document(b"my signed document", "Uploaded signed").await.unwrap();
+        assert_that!(version.version).is_equal_to(1);
+        assert_that!(version.actor.as_deref()).is_equal_to(Some("admin"));
+
+        bundle.replace_document(b"my countersigned document", "Uploaded countersigned").await.unwrap();
+
+        let versions = bundle.versions().await.unwrap();
+        assert_that!(versions.iter().map(|version| version.reason.as_str()).collect::<Vec<_>>())
+            .is_equal_to(vec!["Uploaded signed", "Uploaded countersigned"]);
+
+        assert_that!(tokio::fs::read(bundle.path_of(Version::kind(1))).await.unwrap()).is_equal_to(b"my document".to_vec());
+        assert_that!(tokio::fs::read(bundle.path_of(Version::kind(2))).await.unwrap()).is_equal_to(b"my signed document".to_vec());
+        assert_that!(tokio::fs::read(bundle.path_of(Kind::Document)).await.unwrap()).is_equal_to(b"my countersigned document".to_vec());
+
+        assert_that!(repository.verify().await.unwrap().problems).is_empty();
+    }
+
+    #[test]
+    fn test_is_fragment() {
+        assert_that!(Version::is_fragment("document.v1.pdf")).is_true();
+        assert_that!(Version::is_fragment("document.versions.jsonl")).is_true();
+        assert_that!(Version::is_fragment("document.vx.pdf")).is_false();
+        assert_that!(Version::is_fragment("document.pdf")).is_false();
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let bundle = archived(&repository).await;
+        bundle.replace(Kind::Page(2), b"my page").await.unwrap();
+
+        bundle.remove(Kind::Page(2)).await.unwrap();
+        assert_that!(bundle.path_of(Kind::Page(2)).exists()).is_false();
+        assert_that!(bundle.read_checksums().await.unwrap().unwrap().get("preview-0002.png")).is_none();
+
+        // Missing fragments are removed already
+        assert_that!(bundle.remove(Kind::Page(3)).await).is_ok();
+    }
+}
+
+mod shred {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shred_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        tokio::fs::write(dir.path().join("secret.txt"), b"my secret").await.unwrap();
+
+        super::super::shred::shred_dir(dir.path()).await.unwrap();
+
+        let data = tokio::fs::read(dir.path().join("secret.txt")).await.unwrap();
+        assert_that!(data).is_equal_to(vec![0u8; 9]);
+    }
+
+    #[tokio::test]
+    async fn test_purge_shredded() {
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.shred = true;
+
+        let id = *archived(&repository).await.id();
+        repository.archive().get(id).await.unwrap()
+            .delete().await.unwrap()
+            .purge().await.unwrap();
+
+        assert_that!(repository.trash().get(id).await.is_none()).is_true();
+    }
+}
+
+mod recovery {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_replaces() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let staging = repository.stage().await.unwrap();
+
+        let mut document = staging.write(Kind::Document).await.unwrap();
+        document.write_all(b"first").await.unwrap();
+        document.commit().await.unwrap();
+
+        // Uncommitted fragments are neither visible nor replace the existing one
+        let mut fragment = staging.write(Kind::Document).await.unwrap();
+        fragment.write_all(b"second").await.unwrap();
+        fragment.flush().await.unwrap();
+
+        assert_that!(tokio::fs::read(staging.path_of(Kind::Document)).await.unwrap()).is_equal_to(b"first".to_vec());
+        assert_that!(staging.fragment_names().await.unwrap()).is_equal_to(vec![String::from("document.pdf")]);
+
+        fragment.commit().await.unwrap();
+        assert_that!(tokio::fs::read(staging.path_of(Kind::Document)).await.unwrap()).is_equal_to(b"second".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_repair_interrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::with_path(dir.path().to_path_buf()).await.unwrap();
+
+        let trashed = archived(&repository).await.delete().await.unwrap();
+        let id = *trashed.id();
+
+        // Simulate a crash after the bundle has been moved but before it has been marked as trashed
+        tokio::fs::remove_file(trashed.path_of(Kind::other("trashed"))).await.unwrap();
+        tokio::fs::write(trashed.path().join(".document.pdf.0.tmp"), b"partial").await.unwrap();
+        repository.begin_transition(id, "trashing").await.unwrap();
+
+        let repository = Repository::with_path(dir.path().to_path_buf()).await.unwrap();
+
+        let trashed = repository.trash().get(id).await.unwrap();
+        assert_that!(trashed.trashed().await.is_ok()).is_true();
+        assert_that!(tokio::fs::metadata(trashed.path().join(".document.pdf.0.tmp")).await.is_err()).is_true();
+        assert_that!(std::fs::read_dir(repository.transitions()).unwrap().count()).is_equal_to(0);
+
+        assert_that!(repository.repair().await.unwrap()).is_equal_to(0);
+    }
+}
+
+mod fork {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fork() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let id = *archived(&repository).await.id();
+
+        let target = tempfile::tempdir().unwrap();
+        let fork = repository.fork(target.path().to_path_buf()).await.unwrap();
+
+        let forked = fork.archive().get(id).await.unwrap();
+        assert_that!(forked.read_plaintext().await.unwrap().as_str()).is_equal_to("my document plaintext");
+
+        // Changing the metadata of the fork must not touch the original
+        let mut metadata = forked.read_metadata().await.unwrap();
+        metadata.title = Some(String::from("forked"));
+        tokio::fs::write(forked.path_of(Kind::Metadata), metadata.to_vec().unwrap()).await.unwrap();
+
+        let original = repository.archive().get(id).await.unwrap();
+        assert_that!(original.read_metadata().await.unwrap().title).is_none();
+
+        assert_that!(fork.verify().await.unwrap().is_ok()).is_false();
+        assert_that!(repository.verify().await.unwrap().is_ok()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_fork_not_empty() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        std::fs::write(target.path().join("something"), b"").unwrap();
+
+        assert_that!(repository.fork(target.path().to_path_buf()).await.is_err()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_fork_from() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let id = *archived(&repository).await.id();
+
+        let targets = tempfile::tempdir().unwrap();
+        let base = repository.fork(targets.path().join("base")).await.unwrap();
+        let fork = repository.fork_from(targets.path().join("fork"), Some(base.path().to_path_buf())).await.unwrap();
+
+        let forked = fork.archive().get(id).await.unwrap();
+        assert_that!(forked.read_plaintext().await.unwrap().as_str()).is_equal_to("my document plaintext");
+        assert_that!(fork.verify().await.unwrap().is_ok()).is_true();
+    }
+}
+
+mod snapshot {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+
+        let snapshot = repository.snapshot().await.unwrap();
+        let snapshotted = snapshot.get(*bundle.id()).unwrap();
+        assert_that!(snapshotted.location()).is_equal_to(Location::Archive);
+
+        // Changes to the metadata after the snapshot are not visible
+        let mut metadata = bundle.read_metadata().await.unwrap();
+        metadata.title = Some(String::from("changed"));
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        assert_that!(snapshotted.metadata().unwrap().title).is_none();
+        let data = snapshot.load(snapshotted, "metadata.json").await.unwrap();
+        assert_that!(serde_json::from_slice::<Metadata>(&data).unwrap().title).is_none();
+
+        // Replaced fragments can not be read from the snapshot
+        assert_that!(snapshot.load(snapshotted, "document.txt").await.is_ok()).is_true();
+        bundle.replace(Kind::Plaintext, b"replaced").await.unwrap();
+        assert_that!(snapshot.load(snapshotted, "document.txt").await.is_err()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_versions() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+        bundle.replace_document(b"my signed document", "Uploaded signed").await.unwrap();
+
+        let snapshot = repository.snapshot().await.unwrap();
+        let snapshotted = snapshot.get(*bundle.id()).unwrap();
+
+        // The list of versions is appended in place, so the snapshot keeps its own copy
+        bundle.replace_document(b"my countersigned document", "Uploaded countersigned").await.unwrap();
+
+        let data = snapshot.load(snapshotted, "document.versions.jsonl").await.unwrap();
+        assert_that!(data.split(|b| *b == b'\n').filter(|line| !line.is_empty()).count()).is_equal_to(1);
+    }
+}
+
+mod journal {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_since() {
+        let path = tempfile::tempdir().unwrap();
+        let repository = Repository::with_path(path.path().to_path_buf()).await.unwrap();
+
+        let bundle = archived(&repository).await;
+        let id = *bundle.id();
+        bundle.delete().await.unwrap();
+
+        let entries = repository.journal().since(2).await.unwrap();
+        let changes = entries.iter().map(|entry| entry.change).collect::<Vec<_>>();
+        assert_that!(changes).is_equal_to(vec![Event::Archived(id), Event::Trashed(id)]);
+        assert_that!(repository.journal().cursor().await).is_equal_to(4);
+
+        // The sequence continues after re-opening the repository
+        let repository = Repository::with_path(path.path().to_path_buf()).await.unwrap();
+        assert_that!(repository.journal().cursor().await).is_equal_to(4);
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let bundle = archived(&repository).await;
+        let id = *bundle.id();
+
+        let mut metadata = bundle.read_metadata().await.unwrap();
+        metadata.title = Some(String::from("My Document"));
+
+        let repository = repository.acting_as("admin");
+        repository.archive().get(id).await.unwrap()
+            .write_metadata(&metadata).await.unwrap();
+
+        let history = repository.journal().history(id).await.unwrap();
+        assert_that!(history.iter().map(|entry| entry.change).collect::<Vec<_>>())
+            .is_equal_to(vec![Event::Staged(id), Event::Inboxed(id), Event::Archived(id), Event::MetadataUpdated(id)]);
+
+        let entry = history.last().unwrap();
+        assert_that!(entry.actor.as_deref()).is_equal_to(Some("admin"));
+        assert_that!(entry.diff.keys().collect::<Vec<_>>()).is_equal_to(vec![&String::from("title")]);
+        assert_that!(entry.diff["title"].after).is_equal_to(serde_json::json!("My Document"));
+    }
+}
+
+mod relations {
+    use crate::proto::model::{Relation, RelationKind};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_referencing() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let target = archived(&repository).await;
+        let other = archived(&repository).await;
+
+        let linking = archived(&repository).await;
+        let mut metadata = linking.read_metadata().await.unwrap();
+        metadata.relations.insert(Relation { kind: RelationKind::InvoiceFor, target: *target.id() });
+        linking.write_metadata(&metadata).await.unwrap();
+
+        let referencing = repository.referencing(*target.id()).await.unwrap();
+        assert_that!(referencing.iter().map(|(id, _)| *id).collect::<Vec<_>>()).is_equal_to(vec![*linking.id()]);
+
+        assert_that!(repository.referencing(*other.id()).await.unwrap()).is_empty();
+    }
+}
+
+mod listing {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_keeps_order() {
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.concurrency = 2;
+
+        let mut ids = Vec::new();
+        for _ in 0..5 {
+            ids.push(*archived(&repository).await.unarchive().await.unwrap().id());
+        }
+
+        let expected = repository.inbox().list().await.unwrap().iter()
+            .map(|bundle| *bundle.id())
+            .collect::<Vec<_>>();
+        assert_that!(expected).has_length(5);
+
+        let page = repository.inbox().query(&Listing::new(None, None, None), |_| true).await.unwrap();
+        assert_that!(page.items.iter().map(|(bundle, _)| *bundle.id()).collect::<Vec<_>>()).is_equal_to(expected);
+    }
+}
+
+mod retention {
+    use crate::config::RetentionRule;
+    use crate::proto::model::Label;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delete_retained() {
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.retention = Rules::from_config(vec![RetentionRule { label: String::from("tax"), years: 10 }]);
+
+        let bundle = archived(&repository).await;
+        let mut metadata = bundle.read_metadata().await.unwrap();
+        metadata.archived = Some(Utc::now());
+        metadata.labels.insert(Label::from("tax/2020"));
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        let id = *bundle.id();
+        let err = bundle.delete().await.err().unwrap();
+        assert_that!(err.downcast_ref::<Retained>().map(|retained| retained.id)).is_equal_to(Some(id));
+
+        // Moving the bundle back to the inbox does not lift the retention
+        let inboxed = repository.archive().get(id).await.unwrap().unarchive().await.unwrap();
+        assert_that!(inboxed.delete().await.err().unwrap().downcast_ref::<Retained>().is_some()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_delete_expired() {
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.retention = Rules::from_config(vec![RetentionRule { label: String::from("tax"), years: 10 }]);
+
+        let bundle = archived(&repository).await;
+        let mut metadata = bundle.read_metadata().await.unwrap();
+        metadata.archived = Some(Utc::now() - Duration::days(11 * 366));
+        metadata.labels.insert(Label::from("tax"));
+        bundle.write_metadata(&metadata).await.unwrap();
+
+        assert_that!(bundle.delete().await).is_ok();
+    }
+
+    #[tokio::test]
+    async fn test_write_once() {
+        let mut repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        repository.write_once = true;
+
+        let bundle = archived(&repository).await;
+
+        let err = bundle.replace(Kind::Document, b"tampered").await.err().unwrap();
+        assert_that!(err.downcast_ref::<WriteOnce>().map(|write_once| write_once.id)).is_equal_to(Some(*bundle.id()));
+        assert_that!(tokio::fs::read(bundle.path_of(Kind::Document)).await.unwrap()).is_equal_to(b"my document".to_vec());
+
+        // Missing fragments can still be added
+        bundle.replace(Kind::Thumbnail, b"my thumbnail").await.unwrap();
+        assert_that!(bundle.replace(Kind::Thumbnail, b"other thumbnail").await).is_err();
+    }
+}
+
+mod erase {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_erase() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap().acting_as("admin");
+        let mut events = repository.subscribe();
+
+        let bundle = archived(&repository).await;
+        let id = *bundle.id();
+        let path = bundle.path();
+
+        bundle.write_metadata(&Metadata {
+            title: Some(String::from("Very personal")),
+            ..Metadata::new()
+        }).await.unwrap();
+
+        let tombstone = repository.erase(id).await.unwrap().unwrap();
+        assert_that!(tombstone.change).is_equal_to(Event::Erased(id));
+        assert_that!(tombstone.actor.as_deref()).is_equal_to(Some("admin"));
+
+        assert_that!(path.exists()).is_false();
+        assert_that!(repository.archive().get(id).await.is_none()).is_true();
+
+        // The history is kept, but without any of the changed metadata
+        let history = repository.journal().history(id).await.unwrap();
+        assert_that!(history.iter().all(|entry| entry.diff.is_empty())).is_true();
+        assert_that!(history.last().map(|entry| entry.change)).is_equal_to(Some(Event::Erased(id)));
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_that!(received.last()).is_equal_to(Some(&Event::Erased(id)));
+
+        assert_that!(repository.erase(id).await.unwrap().is_none()).is_true();
+    }
+}
+
+mod manifest {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_manifest() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let staging = repository.stage().await.unwrap();
+        let mut document = staging.write(Kind::Document).await.unwrap();
+        document.write_all(b"%PDF-1.7 my document").await.unwrap();
+        document.commit().await.unwrap();
+        let mut fragment = staging.write(Kind::other("original")).await.unwrap();
+        fragment.write_all(b"\x89PNG\r\n\x1a\nmy scan").await.unwrap();
+        fragment.commit().await.unwrap();
+        Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        let bundle = staging.create().await.unwrap();
+
+        let info = bundle.fragment_info(Kind::other("original")).await.unwrap().unwrap();
+        assert_that!(info.mimetype.as_str()).is_equal_to("image/png");
+        assert_that!(info.size).is_equal_to(15);
+
+        assert_that!(bundle.fragment_info(Kind::Document).await.unwrap().map(|info| info.mimetype))
+            .is_equal_to(Some(String::from("application/pdf")));
+
+        // The recorded format is kept if the replaced contents do not tell it
+        bundle.replace(Kind::other("original"), b"encrypted").await.unwrap();
+        let info = bundle.fragment_info(Kind::other("original")).await.unwrap().unwrap();
+        assert_that!(info.mimetype.as_str()).is_equal_to("image/png");
+        assert_that!(info.size).is_equal_to(9);
+    }
+
+    #[tokio::test]
+    async fn test_fragments() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let staging = repository.stage().await.unwrap();
+        let mut document = staging.write(Kind::Document).await.unwrap();
+        document.write_all(b"%PDF-1.7 my document").await.unwrap();
+        document.commit().await.unwrap();
+        let mut page = staging.write(Kind::Page(1)).await.unwrap();
+        page.write_all(b"\x89PNG\r\n\x1a\nmy page").await.unwrap();
+        page.commit().await.unwrap();
+        let mut fragment = staging.write(Kind::other("juicer.log")).await.unwrap();
+        fragment.write_all(b"my log").await.unwrap();
+        fragment.commit().await.unwrap();
+        Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        let bundle = staging.create().await.unwrap();
+
+        let fragments = bundle.fragments().await.unwrap();
+        assert_that!(fragments.iter().map(|fragment| fragment.kind.clone()).collect::<Vec<_>>()).is_equal_to(vec![
+            Kind::Document,
+            Kind::other("juicer.log"),
+            Kind::Metadata,
+            Kind::Page(1),
+        ]);
+
+        let log = &fragments[1];
+        assert_that!(log.name.as_str()).is_equal_to("juicer.log");
+        assert_that!(log.checksum.clone()).is_equal_to(sha256(bundle.path_of(Kind::other("juicer.log"))).await.unwrap());
+        assert_that!(log.size).is_equal_to(tokio::fs::metadata(bundle.path_of(Kind::other("juicer.log"))).await.unwrap().len());
+    }
+}
+
+mod metadata {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upgrade_lazily() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        let bundle = archived(&repository).await;
+
+        // Metadata from before versioning with a field of a newer version
+        let mut legacy = serde_json::to_value(bundle.read_metadata().await.unwrap()).unwrap();
+        legacy.as_object_mut().unwrap().remove("version");
+        legacy.as_object_mut().unwrap().insert(String::from("colour"), serde_json::json!("red"));
+        tokio::fs::write(bundle.path_of(Kind::Metadata), serde_json::to_vec(&legacy).unwrap()).await.unwrap();
+
+        let metadata = bundle.read_metadata().await.unwrap();
+        assert_that!(metadata.version).is_equal_to(crate::meta::VERSION);
+        assert_that!(metadata.unknown.get("colour")).is_equal_to(Some(&serde_json::json!("red")));
+
+        // Reading leaves the file untouched
+        let data = tokio::fs::read(bundle.path_of(Kind::Metadata)).await.unwrap();
+        assert_that!(serde_json::from_slice::<serde_json::Value>(&data).unwrap().get("version")).is_none();
+
+        // Metadata built from scratch keeps the unknown fields
+        bundle.write_metadata(&Metadata {
+            title: Some(String::from("upgraded")),
+            ..Metadata::new()
+        }).await.unwrap();
+
+        let data = tokio::fs::read(bundle.path_of(Kind::Metadata)).await.unwrap();
+        let written = serde_json::from_slice::<serde_json::Value>(&data).unwrap();
+        assert_that!(written.get("version")).is_equal_to(Some(&serde_json::json!(crate::meta::VERSION)));
+        assert_that!(written.get("colour")).is_equal_to(Some(&serde_json::json!("red")));
+    }
+}
+
+mod store {
+    use super::*;
+
+    async fn roundtrip(store: &(dyn Store + Send + Sync)) {
+        let id = store.store(b"my document", Metadata { title: Some(String::from("Invoice")), ..Metadata::new() }).await.unwrap();
+
+        assert_that!(store.list(Location::Inbox).await.unwrap()).is_equal_to(vec![id]);
+        assert_that!(store.locate(id).await.unwrap()).is_equal_to(Some(Location::Inbox));
+        assert_that!(store.read(id, Kind::Document).await.unwrap()).is_equal_to(Some(b"my document".to_vec()));
+        assert_that!(store.read(id, Kind::Plaintext).await.unwrap()).is_none();
+
+        let mut metadata = store.read_metadata(id).await.unwrap();
+        assert_that!(metadata.title.as_deref()).is_equal_to(Some("Invoice"));
+
+        metadata.title = Some(String::from("Receipt"));
+        metadata.archived = Some(Utc::now());
+        store.write_metadata(id, &metadata).await.unwrap();
+        assert_that!(store.read_metadata(id).await.unwrap().title.as_deref()).is_equal_to(Some("Receipt"));
+
+        assert_that!(store.relocate(id, Location::Archive).await.unwrap()).is_equal_to(Location::Archive);
+        assert_that!(store.list(Location::Inbox).await.unwrap()).is_empty();
+        assert_that!(store.list(Location::Archive).await.unwrap()).is_equal_to(vec![id]);
+
+        // Trashed documents are restored to the archive if archived before
+        store.relocate(id, Location::Trash).await.unwrap();
+        assert_that!(store.write_metadata(id, &metadata).await.is_err()).is_true();
+        assert_that!(store.relocate(id, Location::Inbox).await.unwrap()).is_equal_to(Location::Archive);
+
+        assert_that!(store.locate(DocId::random()).await.unwrap()).is_none();
+        assert_that!(store.read_metadata(DocId::random()).await.is_err()).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_repository() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+        roundtrip(&repository).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory() {
+        roundtrip(&memory::Repository::new()).await;
+    }
+}
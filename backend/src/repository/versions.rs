@@ -0,0 +1,107 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::proto::model::Kind;
+
+use super::{Archived, Bundle, BundleState};
+
+/// A prior version of the document of a bundle, kept when the document has been replaced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Version {
+    /// Sequence number of the version within the bundle, starting at 1
+    pub version: usize,
+
+    /// The point in time the version was replaced
+    pub replaced: DateTime<Utc>,
+
+    /// The user who replaced the version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+
+    /// Why the version was replaced, i.e. `Edited pages`
+    pub reason: String,
+}
+
+impl Version {
+    /// Returns the fragment keeping the document of a prior version.
+    pub fn kind(version: usize) -> Kind {
+        return Kind::other(format!("document.v{}.pdf", version));
+    }
+
+    /// Returns true if the fragment keeps a prior version or lists them.
+    pub fn is_fragment(name: &str) -> bool {
+        return name == Bundle::<Archived>::VERSIONS || name.strip_prefix("document.v")
+            .and_then(|version| version.strip_suffix(".pdf"))
+            .map_or(false, |version| version.parse::<usize>().is_ok());
+    }
+}
+
+impl<State: BundleState> Bundle<'_, State> {
+    /// Fragment listing all prior versions of the document, one JSON object per line.
+    pub(super) const VERSIONS: &'static str = "document.versions.jsonl";
+
+    /// Returns all prior versions of the document, oldest first.
+    pub async fn versions(&self) -> Result<Vec<Version>> {
+        let data = match tokio::fs::read(self.path_of(Kind::other(Self::VERSIONS))).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        return data.split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| Ok(serde_json::from_slice(line)?))
+            .collect();
+    }
+
+    /// Keeps the current document as the next prior version, while the caller holds the modification lock.
+    async fn record_version(&self, reason: String) -> Result<Version> {
+        let mut document = Vec::new();
+        self.read(Kind::Document).await?
+            .ok_or_else(|| anyhow!("Document missing in bundle: {}", self.id))?
+            .read_to_end(&mut document).await?;
+
+        let version = Version {
+            version: self.versions().await?.len() + 1,
+            replaced: Utc::now(),
+            actor: self.repository.actor.clone(),
+            reason,
+        };
+
+        self.write_fragment(Version::kind(version.version), &document).await?;
+
+        let mut line = serde_json::to_vec(&version)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path_of(Kind::other(Self::VERSIONS)))
+            .await?;
+        file.write_all(&line).await?;
+
+        self.update_checksum(Kind::other(Self::VERSIONS)).await?;
+
+        return Ok(version);
+    }
+}
+
+impl<'r> Bundle<'r, Archived> {
+    /// Replaces the document, keeping the replaced one as prior version.
+    ///
+    /// In write-once mode, the document can not be replaced and fails with `WriteOnce`.
+    pub async fn replace_document(&self, data: &[u8], reason: impl Into<String>) -> Result<Version> {
+        self.ensure_writable(&Kind::Document).await?;
+
+        // Numbering, keeping and replacing must not interleave with another replacement
+        let _modifying = self.modify().await?;
+
+        let version = self.record_version(reason.into()).await?;
+        self.write_fragment(Kind::Document, data).await?;
+
+        return Ok(version);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::proto::model::{DocId, Label};
+
+/// A request asking someone to upload a specific document.
+///
+/// The request is handed out as a link containing the token, which allows to upload a single document without
+/// further authentication.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRequest {
+    pub id: String,
+    pub token: String,
+
+    /// The user who created the request and owns the uploaded document
+    pub requester: String,
+
+    /// Name of the person asked for the document
+    pub recipient: String,
+    pub message: String,
+
+    /// Labels assigned to the uploaded document
+    pub labels: HashSet<Label>,
+
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+
+    /// The uploaded document, once the request is fulfilled
+    pub fulfilled: Option<(DateTime<Utc>, DocId)>,
+
+    /// Whether a document is being uploaded for the request, which is not persisted so a crash does not block it
+    #[serde(skip)]
+    pub claimed: bool,
+}
+
+/// Error returned if a request does not accept an upload anymore.
+#[derive(Debug, thiserror::Error)]
+#[error("Request already fulfilled or expired: {id}")]
+pub struct Closed {
+    pub id: String,
+}
+
+impl DocumentRequest {
+    /// Checks if the request still accepts an upload.
+    pub fn is_open(&self) -> bool {
+        return self.fulfilled.is_none()
+            && self.expires.map_or(true, |expires| expires > Utc::now());
+    }
+}
+
+/// Persistent store of document requests.
+pub struct Requests {
+    path: PathBuf,
+    requests: RwLock<HashMap<String, DocumentRequest>>,
+}
+
+impl Requests {
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let requests = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice::<Vec<DocumentRequest>>(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let requests = requests.into_iter()
+            .map(|request| (request.id.clone(), request))
+            .collect();
+
+        return Ok(Self { path, requests: RwLock::new(requests) });
+    }
+
+    async fn save(&self, requests: &HashMap<String, DocumentRequest>) -> Result<()> {
+        let requests = requests.values().collect::<Vec<_>>();
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(&requests)?).await?;
+        return Ok(());
+    }
+
+    pub async fn create(&self,
+                        requester: String,
+                        recipient: String,
+                        message: String,
+                        labels: HashSet<Label>,
+                        expires: Option<DateTime<Utc>>) -> Result<DocumentRequest> {
+        let mut rng = rand::thread_rng();
+
+        let request = DocumentRequest {
+            id: hex::encode(rng.gen::<[u8; 8]>()),
+            token: hex::encode(rng.gen::<[u8; 32]>()),
+            requester,
+            recipient,
+            message,
+            labels,
+            created: Utc::now(),
+            expires,
+            fulfilled: None,
+            claimed: false,
+        };
+
+        let mut requests = self.requests.write().await;
+        requests.insert(request.id.clone(), request.clone());
+        self.save(&requests).await?;
+
+        return Ok(request);
+    }
+
+    /// Lists the requests created by a user, latest first.
+    pub async fn list(&self, requester: &str) -> Vec<DocumentRequest> {
+        let mut requests = self.requests.read().await.values()
+            .filter(|request| request.requester == requester)
+            .cloned()
+            .collect::<Vec<_>>();
+        requests.sort_by_key(|request| std::cmp::Reverse(request.created));
+
+        return requests;
+    }
+
+    /// Finds a request by the token handed out in the link.
+    pub async fn by_token(&self, token: &str) -> Option<DocumentRequest> {
+        return self.requests.read().await.values()
+            .find(|request| request.token == token)
+            .cloned();
+    }
+
+    /// Claims the open request of the token for an upload, so a link can not be used by concurrent uploads.
+    ///
+    /// Fails with `Closed` if the request is fulfilled, expired or already claimed. The claim is released by fulfilling
+    /// the request or by `release` if the upload failed.
+    pub async fn claim(&self, token: &str) -> Result<Option<DocumentRequest>> {
+        let mut requests = self.requests.write().await;
+
+        let request = match requests.values_mut().find(|request| request.token == token) {
+            Some(request) => request,
+            None => return Ok(None),
+        };
+
+        if !request.is_open() || request.claimed {
+            bail!(Closed { id: request.id.clone() });
+        }
+
+        request.claimed = true;
+
+        return Ok(Some(request.clone()));
+    }
+
+    /// Releases the claim of a request after a failed upload, so the link can be used again.
+    pub async fn release(&self, id: &str) {
+        if let Some(request) = self.requests.write().await.get_mut(id) {
+            request.claimed = false;
+        }
+    }
+
+    /// Marks a request as fulfilled by the uploaded document.
+    ///
+    /// Fails with `Closed` if the request has already been fulfilled.
+    pub async fn fulfill(&self, id: &str, doc: DocId) -> Result<()> {
+        let mut requests = self.requests.write().await;
+        if let Some(request) = requests.get_mut(id) {
+            if request.fulfilled.is_some() {
+                bail!(Closed { id: id.to_string() });
+            }
+
+            request.fulfilled = Some((Utc::now(), doc));
+            request.claimed = false;
+        }
+
+        return self.save(&requests).await;
+    }
+
+    /// Withdraws a request of a user and returns whether it existed.
+    pub async fn cancel(&self, requester: &str, id: &str) -> Result<bool> {
+        let mut requests = self.requests.write().await;
+        if requests.get(id).map_or(true, |request| request.requester != requester) {
+            return Ok(false);
+        }
+
+        requests.remove(id);
+        self.save(&requests).await?;
+
+        return Ok(true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_claim() {
+        let dir = tempfile::tempdir().unwrap();
+        let requests = Requests::load(dir.path().join("requests.json")).await.unwrap();
+
+        let request = requests.create(String::from("test"), String::from("Jane"), String::new(), HashSet::new(), None).await.unwrap();
+
+        assert_that!(requests.claim("unknown").await.unwrap().is_none()).is_true();
+
+        // Concurrent uploads are rejected while the request is claimed
+        assert_that!(requests.claim(&request.token).await.unwrap().is_some()).is_true();
+        let err = requests.claim(&request.token).await.unwrap_err();
+        assert_that!(err.downcast_ref::<Closed>().is_some()).is_true();
+
+        requests.release(&request.id).await;
+        assert_that!(requests.claim(&request.token).await.unwrap().is_some()).is_true();
+
+        requests.fulfill(&request.id, DocId::random()).await.unwrap();
+        assert_that!(requests.claim(&request.token).await.is_err()).is_true();
+        assert_that!(requests.fulfill(&request.id, DocId::random()).await.is_err()).is_true();
+    }
+}
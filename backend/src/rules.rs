@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use regex::{Captures, Regex};
+use tokio::sync::RwLock;
+
+use crate::meta::Metadata;
+use crate::proto::model::{Condition, Field, PropertyValue, Rule};
+use crate::repository::{Repository, Settings};
+
+/// A condition compiled to a regular expression.
+struct Matcher {
+    field: Field,
+    regex: Regex,
+}
+
+impl Matcher {
+    fn compile(condition: &Condition) -> Result<Self> {
+        return match condition {
+            Condition::Keyword { field, keyword } => Ok(Self {
+                field: *field,
+                regex: Regex::new(&format!("(?i){}", regex::escape(keyword)))?,
+            }),
+            Condition::Regex { field, pattern } => Ok(Self {
+                field: *field,
+                regex: Regex::new(pattern)
+                    .map_err(|err| anyhow!("Invalid pattern {}: {}", pattern, err))?,
+            }),
+        };
+    }
+}
+
+struct Compiled {
+    rule: Rule,
+    matchers: Vec<Matcher>,
+}
+
+impl Compiled {
+    fn compile(rule: Rule) -> Result<Self> {
+        let matchers = rule.conditions.iter()
+            .map(Matcher::compile)
+            .collect::<Result<_>>()?;
+
+        return Ok(Self { rule, matchers });
+    }
+
+    /// Matches all conditions and returns the named capture groups of all regular expressions.
+    fn matches(&self, text: &str, filename: Option<&str>) -> Option<HashMap<String, String>> {
+        let mut captures = HashMap::new();
+
+        for matcher in &self.matchers {
+            let haystack = match matcher.field {
+                Field::Text => text,
+                Field::Filename => filename?,
+            };
+
+            let found = matcher.regex.captures(haystack)?;
+            collect(&matcher.regex, &found, &mut captures);
+        }
+
+        return Some(captures);
+    }
+}
+
+fn collect(regex: &Regex, found: &Captures, captures: &mut HashMap<String, String>) {
+    for name in regex.capture_names().flatten() {
+        if let Some(value) = found.name(name) {
+            captures.insert(name.to_string(), value.as_str().trim().to_string());
+        }
+    }
+}
+
+/// Checks if all patterns of the rule are valid regular expressions.
+pub fn validate(rule: &Rule) -> Result<()> {
+    for condition in &rule.conditions {
+        Matcher::compile(condition)?;
+    }
+
+    return Ok(());
+}
+
+/// Replaces `{name}` placeholders by the captured values.
+fn expand(template: &str, captures: &HashMap<String, String>) -> String {
+    let mut result = template.to_string();
+    for (name, value) in captures {
+        result = result.replace(&format!("{{{}}}", name), value);
+    }
+
+    return result;
+}
+
+impl Settings for Vec<Rule> {
+    const NAME: &'static str = "rules";
+    const VERSION: u32 = 1;
+
+    fn validate(&self) -> Result<()> {
+        return self.iter().try_for_each(validate);
+    }
+}
+
+/// Repository-wide list of rules applied to documents landing in the inbox.
+pub struct Rules {
+    repository: Repository,
+    rules: RwLock<Vec<Compiled>>,
+}
+
+impl Rules {
+    pub async fn load(repository: Repository) -> Result<Self> {
+        let rules = repository.load_settings::<Vec<Rule>>().await?.into_iter()
+            .map(Compiled::compile)
+            .collect::<Result<_>>()?;
+
+        return Ok(Self { repository, rules: RwLock::new(rules) });
+    }
+
+    /// Loads the rules again from the repository settings, i.e. after they have been edited on disk.
+    ///
+    /// The current rules are kept if any of the loaded rules fails to compile.
+    pub async fn reload(&self) -> Result<()> {
+        let rules = self.repository.load_settings::<Vec<Rule>>().await?.into_iter()
+            .map(Compiled::compile)
+            .collect::<Result<_>>()?;
+
+        *self.rules.write().await = rules;
+
+        return Ok(());
+    }
+
+    async fn save(&self, rules: &[Compiled]) -> Result<()> {
+        let rules = rules.iter().map(|compiled| compiled.rule.clone()).collect::<Vec<_>>();
+        return self.repository.save_settings(&rules).await;
+    }
+
+    pub async fn list(&self) -> Vec<Rule> {
+        return self.rules.read().await.iter().map(|compiled| compiled.rule.clone()).collect();
+    }
+
+    /// Adds a rule or replaces the existing rule with the same name while keeping its position.
+    ///
+    /// Fails if any pattern is not a valid regular expression.
+    pub async fn define(&self, rule: Rule) -> Result<()> {
+        let compiled = Compiled::compile(rule)?;
+
+        let mut rules = self.rules.write().await;
+        match rules.iter_mut().find(|existing| existing.rule.name == compiled.rule.name) {
+            Some(existing) => *existing = compiled,
+            None => rules.push(compiled),
+        }
+
+        return self.save(&rules).await;
+    }
+
+    /// Removes a rule and returns whether it existed.
+    pub async fn remove(&self, name: &str) -> Result<bool> {
+        let mut rules = self.rules.write().await;
+
+        let count = rules.len();
+        rules.retain(|compiled| compiled.rule.name != name);
+        if rules.len() == count {
+            return Ok(false);
+        }
+
+        self.save(&rules).await?;
+
+        return Ok(true);
+    }
+
+    /// Applies all matching rules in order and returns the names of the matching rules.
+    ///
+    /// Labels of all matching rules are added. The title, correspondent and properties are only set if not set already,
+    /// so values given on upload and by earlier rules take precedence.
+    pub async fn apply(&self, metadata: &mut Metadata, text: &str) -> Vec<String> {
+        let rules = self.rules.read().await;
+
+        let mut matched = Vec::new();
+        for compiled in rules.iter() {
+            let captures = match compiled.matches(text, metadata.filename.as_deref()) {
+                Some(captures) => captures,
+                None => continue,
+            };
+
+            let rule = &compiled.rule;
+
+            metadata.labels.extend(rule.labels.iter().cloned());
+
+            if metadata.title.is_none() {
+                metadata.title = rule.title.as_deref().map(|title| expand(title, &captures));
+            }
+
+            if metadata.correspondent.is_none() {
+                metadata.correspondent = rule.correspondent.clone();
+            }
+
+            for (key, value) in &rule.properties {
+                let value = match value {
+                    PropertyValue::String(value) => PropertyValue::parse(&expand(value, &captures)),
+                    value => value.clone(),
+                };
+
+                metadata.properties.entry(key.clone()).or_insert(value);
+            }
+
+            matched.push(rule.name.clone());
+        }
+
+        return matched;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::proto::model::Label;
+
+    use super::*;
+
+    fn rule(name: &str, conditions: Vec<Condition>) -> Rule {
+        return Rule {
+            name: name.to_string(),
+            conditions,
+            labels: Default::default(),
+            title: None,
+            correspondent: None,
+            properties: Default::default(),
+        };
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        let rules = Rules::load(Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap()).await.unwrap();
+
+        rules.define(Rule {
+            labels: vec![Label::from("utilities")].into_iter().collect(),
+            title: Some(String::from("Power bill {number}")),
+            correspondent: Some(String::from("Stadtwerke")),
+            properties: vec![(String::from("total"), PropertyValue::from("{total} EUR"))].into_iter().collect(),
+            ..rule("power", vec![
+                Condition::Keyword { field: Field::Text, keyword: String::from("stadtwerke") },
+                Condition::Regex { field: Field::Text, pattern: String::from(r"Invoice (?P<number>\d+)") },
+                Condition::Regex { field: Field::Text, pattern: String::from(r"Total: (?P<total>[\d.]+)") },
+            ])
+        }).await.unwrap();
+
+        rules.define(Rule {
+            labels: vec![Label::from("scan")].into_iter().collect(),
+            ..rule("scanner", vec![
+                Condition::Regex { field: Field::Filename, pattern: String::from(r"^scan_\d+\.pdf$") },
+            ])
+        }).await.unwrap();
+
+        let mut metadata = Metadata::new();
+        let matched = rules.apply(&mut metadata, "STADTWERKE\nInvoice 4711\nTotal: 42.50").await;
+        assert_that!(matched).is_equal_to(vec![String::from("power")]);
+        assert_that!(metadata.title.as_deref()).is_equal_to(Some("Power bill 4711"));
+        assert_that!(metadata.correspondent.as_deref()).is_equal_to(Some("Stadtwerke"));
+        assert_that!(metadata.labels.contains(&Label::from("utilities"))).is_true();
+        assert_that!(metadata.properties.get("total")).is_equal_to(Some(&PropertyValue::parse("42.50 EUR")));
+
+        let mut metadata = Metadata {
+            title: Some(String::from("Uploaded")),
+            filename: Some(String::from("scan_0001.pdf")),
+            ..Metadata::new()
+        };
+        let matched = rules.apply(&mut metadata, "Invoice 4711").await;
+        assert_that!(matched).is_equal_to(vec![String::from("scanner")]);
+        assert_that!(metadata.title.as_deref()).is_equal_to(Some("Uploaded"));
+        assert_that!(metadata.labels.contains(&Label::from("scan"))).is_true();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_pattern() {
+        let rules = Rules::load(Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap()).await.unwrap();
+
+        let result = rules.define(rule("broken", vec![
+            Condition::Regex { field: Field::Text, pattern: String::from("(unclosed") },
+        ])).await;
+        assert_that!(result).is_err();
+        assert_that!(rules.list().await).is_empty();
+    }
+}
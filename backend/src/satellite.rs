@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use reqwest::{Method, RequestBuilder, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::config::{Resolution, Satellite as Config};
+use crate::meta::Metadata;
+use crate::proto::api::sync::{ListResponse, SyncDoc, UpdateRequest, UpdateResponse};
+use crate::proto::model::{DocId, Kind};
+use crate::repository::{Archived, Bundle, Repository};
+use crate::status::Status;
+
+/// The revisions of both sides after the last successful sync of a bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Base {
+    local: String,
+    upstream: String,
+}
+
+/// Keeps the documents with selected labels in sync with an upstream instance.
+///
+/// Documents are copied from upstream, while metadata changes are synced in both directions. If the metadata has been
+/// changed on both sides since the last sync, the conflict is resolved according to the configured resolution.
+pub struct Satellite {
+    config: Config,
+
+    upstream: Url,
+    client: reqwest::Client,
+
+    repository: Repository,
+
+    status: Arc<Status>,
+}
+
+impl Satellite {
+    const STATE: &'static str = "satellite.json";
+
+    pub async fn from_config(config: Config, repository: Repository, status: Arc<Status>) -> Result<Self> {
+        let upstream = Url::parse(&format!("{}/api/", config.upstream.trim_end_matches('/')))?;
+        let client = reqwest::Client::builder().build()?;
+
+        return Ok(Self { config, upstream, client, repository, status });
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval));
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.sync().await {
+                error!("Failed to sync with {}: {:#}", self.config.upstream, err);
+                self.status.failed("satellite", &err);
+            }
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        return Ok(self.client.request(method, self.upstream.join(path)?)
+            .basic_auth(&self.config.username, Some(&self.config.password)));
+    }
+
+    fn state_path(&self) -> PathBuf { self.repository.path().join(Self::STATE) }
+
+    async fn load_state(&self) -> Result<HashMap<DocId, Base>> {
+        return match tokio::fs::read(self.state_path()).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    async fn save_state(&self, state: &HashMap<DocId, Base>) -> Result<()> {
+        tokio::fs::write(self.state_path(), serde_json::to_vec_pretty(state)?).await?;
+        return Ok(());
+    }
+
+    pub async fn sync(&self) -> Result<()> {
+        let list: ListResponse = self.request(Method::GET, "sync")?
+            .query(&[("labels", self.config.labels.join(","))])
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        info!("Syncing {} documents from {}", list.docs.len(), self.config.upstream);
+
+        let mut state = self.load_state().await?;
+
+        for doc in list.docs {
+            let id = doc.doc.id;
+
+            let result = match self.repository.archive().get(id).await {
+                Some(bundle) => self.sync_metadata(&bundle, &doc, state.get(&id)).await,
+                None => self.download(&doc).await,
+            };
+
+            match result {
+                Ok(base) => { state.insert(id, base); }
+                Err(err) => {
+                    error!("Failed to sync bundle {}: {:#}", id, err);
+                    self.status.failed("satellite", &err);
+                }
+            }
+        }
+
+        self.save_state(&state).await?;
+
+        return Ok(());
+    }
+
+    /// Copies a bundle from upstream.
+    async fn download(&self, doc: &SyncDoc) -> Result<Base> {
+        let staging = self.repository.stage_with_id(doc.doc.id).await?;
+
+        let result: Result<()> = async {
+            for fragment in &doc.fragments {
+                let data = self.request(Method::GET, &format!("archive/{}/{}", doc.doc.id, fragment))?
+                    .send().await?
+                    .error_for_status()?
+                    .bytes().await?;
+
+                let mut file = staging.write(Kind::other(fragment.as_str())).await?;
+                file.write_all(&data).await
+                    .with_context(|| format!("Writing {}", fragment))?;
+                file.commit().await?;
+            }
+
+            return Ok(());
+        }.await;
+
+        if let Err(err) = result {
+            staging.delete().await?;
+            return Err(err);
+        }
+
+        let bundle = staging.create().await?.archive().await?;
+
+        info!("Downloaded bundle {}", bundle.id());
+
+        return Ok(Base {
+            local: bundle.metadata_revision().await?,
+            upstream: doc.revision.clone(),
+        });
+    }
+
+    async fn sync_metadata(&self, bundle: &Bundle<'_, Archived>, doc: &SyncDoc, base: Option<&Base>) -> Result<Base> {
+        let local = bundle.metadata_revision().await?;
+
+        let (local_changed, upstream_changed) = match base {
+            Some(base) => (base.local != local, base.upstream != doc.revision),
+            None => (true, true),
+        };
+
+        let upstream_metadata = Metadata::from(doc.doc.metadata.clone());
+
+        let resolved = match (local_changed, upstream_changed) {
+            (false, false) => return Ok(Base { local, upstream: doc.revision.clone() }),
+
+            (false, true) => upstream_metadata,
+            (true, false) => bundle.read_metadata().await?,
+
+            (true, true) => {
+                let local_metadata = bundle.read_metadata().await?;
+                if local_metadata == upstream_metadata {
+                    upstream_metadata
+                } else {
+                    warn!("Conflicting metadata for bundle {} - resolving by {:?}", bundle.id(), self.config.resolution);
+                    resolve(self.config.resolution, local_metadata, upstream_metadata)
+                }
+            }
+        };
+
+        // Update upstream if the resolved metadata differs from there
+        let upstream = if resolved != Metadata::from(doc.doc.metadata.clone()) {
+            self.push(bundle.id(), &doc.revision, &resolved).await?
+        } else {
+            doc.revision.clone()
+        };
+
+        // Update locally if the resolved metadata differs from here
+        if resolved != bundle.read_metadata().await? {
+            bundle.write_metadata(&resolved).await?;
+        }
+
+        return Ok(Base {
+            local: bundle.metadata_revision().await?,
+            upstream,
+        });
+    }
+
+    async fn push(&self, id: &DocId, base: &str, metadata: &Metadata) -> Result<String> {
+        let response = self.request(Method::PUT, &format!("sync/{}", id))?
+            .json(&UpdateRequest {
+                base: base.to_string(),
+                metadata: metadata.clone().into(),
+            })
+            .send().await?;
+
+        if response.status() == StatusCode::CONFLICT {
+            anyhow::bail!("Bundle modified upstream during sync: {}", id);
+        }
+
+        let response: UpdateResponse = response.error_for_status()?.json().await?;
+
+        return Ok(response.revision);
+    }
+}
+
+/// Resolves conflicting metadata changes.
+fn resolve(resolution: Resolution, local: Metadata, upstream: Metadata) -> Metadata {
+    return match resolution {
+        Resolution::Upstream => upstream,
+        Resolution::Local => local,
+        Resolution::Merge => {
+            // Labels and relations are combined while properties, title, correspondent and due date from upstream take precedence
+            let mut merged = upstream;
+            merged.labels.extend(local.labels);
+            merged.relations.extend(local.relations);
+            for (key, value) in local.properties {
+                merged.properties.entry(key).or_insert(value);
+            }
+            merged.title = merged.title.or(local.title);
+            merged.correspondent = merged.correspondent.or(local.correspondent);
+            merged.belongs_to = merged.belongs_to.or(local.belongs_to);
+            merged.due = merged.due.or(local.due);
+            merged
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::proto::model::{Label, PropertyValue};
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_merge() {
+        let mut local = Metadata::new();
+        local.title = Some(String::from("local"));
+        local.labels.insert(Label::from("travel"));
+        local.labels.insert(Label::from("local"));
+        local.properties.insert(String::from("a"), PropertyValue::from("local"));
+        local.properties.insert(String::from("b"), PropertyValue::from("local"));
+
+        let mut upstream = local.clone();
+        upstream.title = Some(String::from("upstream"));
+        upstream.labels.remove(&Label::from("local"));
+        upstream.labels.insert(Label::from("upstream"));
+        upstream.properties.insert(String::from("a"), PropertyValue::from("upstream"));
+
+        let merged = resolve(Resolution::Merge, local, upstream);
+
+        assert_that!(merged.title.as_deref()).is_equal_to(Some("upstream"));
+        assert_that!(merged.labels).has_length(3);
+        assert_that!(merged.properties.get("a")).is_equal_to(Some(&PropertyValue::from("upstream")));
+        assert_that!(merged.properties.get("b")).is_equal_to(Some(&PropertyValue::from("local")));
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::RwLock;
+
+use crate::proto::model::DocId;
+
+/// A link sharing a document, or a single fragment of it, until it expires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Share {
+    pub id: String,
+
+    /// The user who created the link
+    pub owner: String,
+
+    pub doc: DocId,
+
+    /// The shared fragment, the document itself if unset
+    pub fragment: Option<String>,
+
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+impl Share {
+    pub fn is_expired(&self) -> bool {
+        return self.expires <= Utc::now();
+    }
+
+    /// The data covered by the signature.
+    fn claims(&self) -> String {
+        return format!("{}\n{}\n{}\n{}", self.id, self.doc, self.fragment.as_deref().unwrap_or_default(), self.expires.timestamp());
+    }
+}
+
+/// Persistent store of share links.
+///
+/// Links carry a signature of the shared document and the expiry, so a link can not be changed to access other
+/// documents or for longer. Links are valid as long as they are stored, so revoking a link removes it. The signing key
+/// is generated on first use and kept next to the links.
+pub struct Shares {
+    path: PathBuf,
+    key: Vec<u8>,
+    shares: RwLock<HashMap<String, Share>>,
+}
+
+impl Shares {
+    pub async fn load(path: PathBuf, key: PathBuf) -> Result<Self> {
+        let shares = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice::<Vec<Share>>(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let shares = shares.into_iter()
+            .map(|share| (share.id.clone(), share))
+            .collect();
+
+        let key = match tokio::fs::read_to_string(&key).await {
+            Ok(data) => hex::decode(data.trim())?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let data = rand::thread_rng().gen::<[u8; 32]>().to_vec();
+                tokio::fs::write(&key, hex::encode(&data)).await?;
+                data
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        return Ok(Self { path, key, shares: RwLock::new(shares) });
+    }
+
+    async fn save(&self, shares: &HashMap<String, Share>) -> Result<()> {
+        let shares = shares.values().collect::<Vec<_>>();
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(&shares)?).await?;
+        return Ok(());
+    }
+
+    fn mac(&self, share: &Share) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key).expect("HMAC accepts any key length");
+        mac.update(share.claims().as_bytes());
+        return mac;
+    }
+
+    /// Returns the signature included in the link of a share.
+    pub fn signature(&self, share: &Share) -> String {
+        return hex::encode(self.mac(share).finalize().into_bytes());
+    }
+
+    pub async fn create(&self,
+                        owner: String,
+                        doc: DocId,
+                        fragment: Option<String>,
+                        expires: DateTime<Utc>) -> Result<Share> {
+        let share = Share {
+            id: hex::encode(rand::thread_rng().gen::<[u8; 8]>()),
+            owner,
+            doc,
+            fragment,
+            created: Utc::now(),
+            expires,
+        };
+
+        let mut shares = self.shares.write().await;
+
+        // Expired links are of no use anymore
+        shares.retain(|_, share| !share.is_expired());
+
+        shares.insert(share.id.clone(), share.clone());
+        self.save(&shares).await?;
+
+        return Ok(share);
+    }
+
+    /// Lists the links of a user which have not expired yet, latest first.
+    pub async fn list(&self, owner: &str) -> Vec<Share> {
+        let mut shares = self.shares.read().await.values()
+            .filter(|share| share.owner == owner && !share.is_expired())
+            .cloned()
+            .collect::<Vec<_>>();
+        shares.sort_by_key(|share| std::cmp::Reverse(share.created));
+
+        return shares;
+    }
+
+    /// Looks up the share of a link, if it has neither been revoked nor expired and the signature is valid.
+    pub async fn verify(&self, id: &str, signature: &str) -> Option<Share> {
+        let share = self.shares.read().await.get(id).cloned()?;
+
+        let signature = hex::decode(signature).ok()?;
+        self.mac(&share).verify(&signature).ok()?;
+
+        if share.is_expired() {
+            return None;
+        }
+
+        return Some(share);
+    }
+
+    /// Revokes a link of a user and returns whether it existed.
+    pub async fn revoke(&self, owner: &str, id: &str) -> Result<bool> {
+        let mut shares = self.shares.write().await;
+        if shares.get(id).map_or(true, |share| share.owner != owner) {
+            return Ok(false);
+        }
+
+        shares.remove(id);
+        self.save(&shares).await?;
+
+        return Ok(true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify() {
+        let path = tempfile::tempdir().unwrap();
+        let shares = Shares::load(path.path().join("shares.json"), path.path().join("shares.key")).await.unwrap();
+
+        let doc = DocId::random();
+        let share = shares.create(String::from("test"), doc, None, Utc::now() + chrono::Duration::hours(1)).await.unwrap();
+        let signature = shares.signature(&share);
+
+        assert_that!(shares.verify(&share.id, &signature).await.map(|share| share.doc)).is_equal_to(Some(doc));
+        assert_that!(shares.verify(&share.id, "00").await).is_none();
+
+        // Links stay valid after restarting
+        let shares = Shares::load(path.path().join("shares.json"), path.path().join("shares.key")).await.unwrap();
+        assert_that!(shares.verify(&share.id, &signature).await).is_some();
+
+        // Only the owner can revoke a link
+        assert_that!(shares.revoke("other", &share.id).await.unwrap()).is_false();
+        assert_that!(shares.revoke("test", &share.id).await.unwrap()).is_true();
+        assert_that!(shares.verify(&share.id, &signature).await).is_none();
+
+        let expired = shares.create(String::from("test"), doc, Some(String::from("plaintext")), Utc::now() - chrono::Duration::seconds(1)).await.unwrap();
+        assert_that!(shares.verify(&expired.id, &shares.signature(&expired)).await).is_none();
+        assert_that!(shares.list("test").await).is_empty();
+    }
+}
@@ -0,0 +1,151 @@
+use crate::proto::model::{Highlight, Snippet};
+
+/// Number of characters shown before and after a matched term
+const CONTEXT: usize = 60;
+
+/// Maximal number of snippets shown per document
+pub const MAX_SNIPPETS: usize = 3;
+
+/// Marks the start of a highlighted term in text highlighted by the index
+pub const PRE_MARK: char = '\u{1}';
+
+/// Marks the end of a highlighted term in text highlighted by the index
+pub const POST_MARK: char = '\u{2}';
+
+/// Lowercases a text character by character, so offsets in the result match the ones in the original text.
+fn lowercase(text: &[char]) -> Vec<char> {
+    return text.iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+}
+
+/// Replaces line and page breaks by spaces, as snippets are shown on a single line.
+fn flatten(text: &[char]) -> String {
+    return text.iter()
+        .map(|c| if c.is_whitespace() { ' ' } else { *c })
+        .collect();
+}
+
+/// Finds the terms in the text and cuts out snippets around them.
+///
+/// Terms are matched case-insensitive. Terms close to each other are shown in the same snippet. Pages are counted by
+/// the form feeds `pdftotext` separates pages with.
+pub fn find(text: &str, terms: &[&str]) -> Vec<Snippet> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let lower = lowercase(&chars);
+
+    let mut matches = terms.iter()
+        .map(|term| lowercase(&term.chars().collect::<Vec<_>>()))
+        .filter(|term| !term.is_empty())
+        .flat_map(|term| {
+            let lower = &lower;
+            (0..lower.len().saturating_sub(term.len() - 1))
+                .filter(move |start| lower[*start..*start + term.len()] == term[..])
+                .map(move |start| (start, start + term.len()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+
+    let mut snippets = Vec::new();
+
+    let mut matches = matches.into_iter().peekable();
+    while let Some((start, end)) = matches.next() {
+        if snippets.len() == MAX_SNIPPETS {
+            break;
+        }
+
+        let from = start.saturating_sub(CONTEXT);
+        let to = (end + CONTEXT).min(chars.len());
+
+        let mut highlights = vec![Highlight { start: start - from, end: end - from }];
+        while let Some((start, end)) = matches.peek().copied().filter(|(_, end)| *end <= to) {
+            matches.next();
+
+            // Overlapping terms are merged into a single highlight
+            let last = highlights.last_mut().expect("No highlight");
+            if start - from <= last.end {
+                last.end = last.end.max(end - from);
+            } else {
+                highlights.push(Highlight { start: start - from, end: end - from });
+            }
+        }
+
+        let page = chars[..start].iter().filter(|c| **c == '\u{c}').count() as u32 + 1;
+
+        snippets.push(Snippet {
+            text: flatten(&chars[from..to]),
+            page: Some(page),
+            highlights,
+        });
+    }
+
+    return snippets;
+}
+
+/// Converts a snippet highlighted by the index, which marks the terms by `PRE_MARK` and `POST_MARK`.
+pub fn parse_marked(marked: &str, page: Option<u32>) -> Snippet {
+    let mut text = Vec::new();
+    let mut highlights = Vec::new();
+
+    let mut start = None;
+    for c in marked.chars() {
+        match c {
+            c if c == PRE_MARK => start = Some(text.len()),
+            c if c == POST_MARK => if let Some(start) = start.take() {
+                highlights.push(Highlight { start, end: text.len() });
+            },
+            c => text.push(c),
+        }
+    }
+
+    return Snippet {
+        text: flatten(&text),
+        page,
+        highlights,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let text = "Invoice\nTotal amount: 42 EUR\u{c}Please pay the total within 14 days";
+
+        let snippets = find(text, &["total", "EUR"]);
+        assert_that!(snippets).has_length(1);
+        assert_that!(snippets[0].text.as_str()).is_equal_to("Invoice Total amount: 42 EUR Please pay the total within 14 days");
+        assert_that!(snippets[0].page).is_equal_to(Some(1));
+        assert_that!(snippets[0].highlights).is_equal_to(vec![
+            Highlight { start: 8, end: 13 },
+            Highlight { start: 25, end: 28 },
+            Highlight { start: 44, end: 49 },
+        ]);
+
+        assert_that!(find(text, &["missing"])).is_empty();
+        assert_that!(find(text, &[""])).is_empty();
+    }
+
+    #[test]
+    fn test_find_pages() {
+        let text = format!("{}needle\u{c}{}needle", "x".repeat(100), "y".repeat(100));
+
+        let snippets = find(&text, &["Needle"]);
+        assert_that!(snippets.iter().map(|snippet| snippet.page).collect::<Vec<_>>()).is_equal_to(vec![Some(1), Some(2)]);
+        assert_that!(snippets[1].highlights).is_equal_to(vec![Highlight { start: 60, end: 66 }]);
+    }
+
+    #[test]
+    fn test_parse_marked() {
+        let snippet = parse_marked("the \u{1}total\u{2} amount:\n42 \u{1}EUR\u{2}", Some(2));
+        assert_that!(snippet).is_equal_to(Snippet {
+            text: String::from("the total amount: 42 EUR"),
+            page: Some(2),
+            highlights: vec![Highlight { start: 4, end: 9 }, Highlight { start: 21, end: 24 }],
+        });
+    }
+}
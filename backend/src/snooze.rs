@@ -0,0 +1,155 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{NaiveDate, Utc};
+use log::{error, info};
+
+use crate::config::Reminders;
+use crate::proto::api::inbox::ResurfacedResponse;
+use crate::proto::model::DocInfo;
+use crate::reminders::mail;
+use crate::repository::Repository;
+use crate::status::Status;
+
+/// Interval in seconds snoozed documents are checked for re-surfacing
+const INTERVAL: u64 = 60 * 60;
+
+/// Wakes all snoozed inbox documents which are snoozed until the given day or before.
+///
+/// Waking a document updates its metadata, which notifies connected clients about it re-surfacing in the inbox.
+pub async fn resurface(repository: &Repository, today: NaiveDate) -> Result<Vec<DocInfo>> {
+    let mut docs = Vec::new();
+
+    for bundle in repository.inbox().list().await? {
+        let mut metadata = bundle.read_metadata().await?;
+        if !metadata.snoozed.map_or(false, |until| until <= today) {
+            continue;
+        }
+
+        metadata.snoozed = None;
+        bundle.write_metadata(&metadata).await?;
+
+        docs.push((*bundle.id(), metadata).into());
+    }
+
+    return Ok(docs);
+}
+
+/// Periodically brings snoozed documents back to the inbox.
+///
+/// Re-surfaced documents are additionally notified about on the channels configured for reminders, if any.
+pub struct Snoozer {
+    reminders: Option<Reminders>,
+
+    repository: Repository,
+
+    client: reqwest::Client,
+
+    status: Arc<Status>,
+}
+
+impl Snoozer {
+    pub fn from_config(reminders: Option<Reminders>, repository: Repository, status: Arc<Status>) -> Result<Self> {
+        let client = reqwest::Client::builder().build()?;
+
+        return Ok(Self { reminders, repository, client, status });
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(INTERVAL));
+        loop {
+            interval.tick().await;
+
+            match self.wake().await {
+                Ok(0) => {}
+                Ok(woken) => info!("Re-surfaced {} snoozed documents", woken),
+                Err(err) => {
+                    error!("Failed to re-surface snoozed documents: {:#}", err);
+                    self.status.failed("snooze", &err);
+                }
+            }
+        }
+    }
+
+    /// Wakes all snoozed documents which are due today and returns their number.
+    pub async fn wake(&self) -> Result<usize> {
+        let docs = resurface(&self.repository, Utc::today().naive_utc()).await?;
+        if docs.is_empty() {
+            return Ok(0);
+        }
+
+        let count = docs.len();
+        let response = ResurfacedResponse { docs };
+
+        if let Some(config) = &self.reminders {
+            if let Some(webhook) = &config.webhook {
+                self.client.post(webhook)
+                    .json(&response)
+                    .send().await?
+                    .error_for_status()?;
+            }
+
+            if let Some(email) = &config.email {
+                mail(email, format!("{} snoozed documents back in the inbox", count), summary(&response)).await?;
+            }
+        }
+
+        return Ok(count);
+    }
+}
+
+/// Renders the re-surfaced documents as plain text.
+fn summary(response: &ResurfacedResponse) -> String {
+    let mut text = String::new();
+    for doc in &response.docs {
+        let title = doc.metadata.title.as_deref().unwrap_or("Untitled");
+        let _ = writeln!(text, "{} ({})", title, doc.id);
+    }
+
+    return text;
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use crate::meta::Metadata;
+    use crate::proto::model::{DocId, Kind};
+
+    use super::*;
+
+    async fn inboxed(repository: &Repository, snoozed: Option<NaiveDate>) -> DocId {
+        let staging = repository.stage().await.unwrap();
+
+        Metadata {
+            snoozed,
+            ..Metadata::new()
+        }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+        return *staging.create().await.unwrap().id();
+    }
+
+    #[tokio::test]
+    async fn test_resurface() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let today = Utc::today().naive_utc();
+        let elapsed = inboxed(&repository, Some(today)).await;
+        let pending = inboxed(&repository, Some(today + chrono::Duration::days(14))).await;
+        inboxed(&repository, None).await;
+
+        let docs = resurface(&repository, today).await.unwrap();
+        assert_that!(docs.iter().map(|doc| doc.id).collect::<Vec<_>>()).is_equal_to(vec![elapsed]);
+
+        let metadata = repository.inbox().get(elapsed).await.unwrap().read_metadata().await.unwrap();
+        assert_that!(metadata.snoozed).is_none();
+
+        let metadata = repository.inbox().get(pending).await.unwrap().read_metadata().await.unwrap();
+        assert_that!(metadata.snoozed).is_equal_to(Some(today + chrono::Duration::days(14)));
+
+        // Documents are only woken once
+        assert_that!(resurface(&repository, today).await.unwrap()).is_empty();
+    }
+}
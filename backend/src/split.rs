@@ -0,0 +1,252 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::StreamExt;
+use log::{debug, info};
+use tokio::process::Command;
+
+use crate::config::{Separator, Split as Config};
+use crate::juicer::{image_original, office_original};
+use crate::proto::model::Kind;
+use crate::repository::{Bundle, Repository, Staging};
+
+/// Resolution the pages are rendered at to detect separators in DPI, high enough to decode barcodes
+const RESOLUTION: &str = "150";
+
+/// Splits a scanned stack of documents into a bundle per document.
+///
+/// The pages of the original PDF are rendered and checked for separators, which are either blank pages or sheets
+/// carrying the configured barcode. The pages between two separators form a document on their own, whereas the
+/// separators are dropped. Office documents and scanned images are never split.
+pub struct Splitter {
+    config: Config,
+}
+
+/// Runs a command and returns its output if it succeeded.
+async fn run(program: &str, command: &mut Command) -> Result<Vec<u8>> {
+    debug!("Running {:?}", command);
+
+    let output = command
+        .stdin(Stdio::null())
+        .output().await
+        .with_context(|| format!("Error executing {}", program))?;
+
+    if !output.status.success() {
+        bail!("{} failed: {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr));
+    }
+
+    return Ok(output.stdout);
+}
+
+/// Parses the page number from the file names `pdftoppm` emits for grayscale pages, like `page-01.pgm`.
+fn page_number(filename: &str) -> Option<u32> {
+    return filename.strip_prefix("page-")?.strip_suffix(".pgm")?.parse().ok();
+}
+
+/// Returns the share of dark pixels in a binary grayscale PGM image.
+fn darkness(image: &[u8]) -> Option<f64> {
+    // The header consists of the magic number, the dimensions and the maximum value separated by whitespace and
+    // comments, followed by a single whitespace
+    let mut fields = Vec::with_capacity(4);
+    let mut pos = 0;
+    while fields.len() < 4 {
+        loop {
+            match image.get(pos)? {
+                b'#' => while *image.get(pos)? != b'\n' { pos += 1; },
+                c if c.is_ascii_whitespace() => pos += 1,
+                _ => break,
+            }
+        }
+
+        let start = pos;
+        while !image.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+
+        fields.push(std::str::from_utf8(&image[start..pos]).ok()?);
+    }
+
+    if fields[0] != "P5" {
+        return None;
+    }
+
+    let width: usize = fields[1].parse().ok()?;
+    let height: usize = fields[2].parse().ok()?;
+    let max: u32 = fields[3].parse().ok()?;
+
+    // Larger maximum values use two bytes per pixel, which pdftoppm does not emit
+    if max == 0 || max > 255 || width * height == 0 {
+        return None;
+    }
+
+    let pixels = image.get(pos + 1..pos + 1 + width * height)?;
+    let dark = pixels.iter().filter(|pixel| u32::from(**pixel) * 2 < max).count();
+
+    return Some(dark as f64 / pixels.len() as f64);
+}
+
+/// Groups the page numbers between separators, dropping the separators and empty groups.
+fn partition(separators: &[bool]) -> Vec<Vec<usize>> {
+    let mut parts = vec![Vec::new()];
+    for (i, separator) in separators.iter().enumerate() {
+        if *separator {
+            parts.push(Vec::new());
+        } else {
+            parts.last_mut().expect("No part").push(i + 1);
+        }
+    }
+
+    parts.retain(|part| !part.is_empty());
+
+    return parts;
+}
+
+impl Splitter {
+    pub fn from_config(config: Config) -> Self {
+        return Self { config };
+    }
+
+    /// Checks whether a rendered page is a separator.
+    async fn is_separator(&self, page: &Path) -> Result<bool> {
+        match self.config.separator {
+            Separator::Blank => {
+                let image = tokio::fs::read(page).await?;
+                let darkness = darkness(&image)
+                    .ok_or_else(|| anyhow!("Invalid page image: {:?}", page))?;
+
+                return Ok(darkness <= self.config.blank_threshold);
+            }
+
+            Separator::Barcode => {
+                let output = Command::new(&self.config.zbarimg)
+                    .args(&["--raw", "--quiet"])
+                    .arg(page)
+                    .stdin(Stdio::null())
+                    .output().await
+                    .with_context(|| format!("Error executing {}", self.config.zbarimg))?;
+
+                // zbarimg exits with status 4 if the page does not contain any barcode
+                return match output.status.code() {
+                    Some(0) => Ok(String::from_utf8_lossy(&output.stdout).lines()
+                        .any(|code| code.trim() == self.config.barcode)),
+                    Some(4) => Ok(false),
+                    _ => Err(anyhow!("{} failed: {}: {}", self.config.zbarimg, output.status, String::from_utf8_lossy(&output.stderr))),
+                };
+            }
+        }
+    }
+
+    /// Renders all pages of the original and checks each of them for being a separator.
+    async fn detect(&self, bundle: &Bundle<'_, Staging>, dir: &Path) -> Result<Vec<bool>> {
+        run(&self.config.pdftoppm, Command::new(&self.config.pdftoppm)
+            .args(&["-gray", "-r", RESOLUTION])
+            .arg(bundle.path_of(Kind::other("original.pdf")))
+            .arg(dir.join("page"))).await?;
+
+        let mut pages = tokio::fs::read_dir(dir).await?
+            .filter_map(|entry| async move {
+                let entry = entry.ok()?;
+                let number = page_number(&entry.file_name().to_string_lossy())?;
+                Some((number, entry.path()))
+            })
+            .collect::<Vec<(u32, PathBuf)>>().await;
+        pages.sort();
+
+        let mut separators = Vec::with_capacity(pages.len());
+        for (_, page) in pages {
+            separators.push(self.is_separator(&page).await?);
+        }
+
+        return Ok(separators);
+    }
+
+    /// Splits a staged bundle at its separator pages into new staged bundles sharing its metadata.
+    ///
+    /// Returns `None` if the bundle does not contain any separator, in which case it is left as is. Otherwise, the
+    /// caller takes care of the source bundle and the returned parts, which are not queued for juicing yet.
+    pub async fn split<'r>(&self, repository: &'r Repository, bundle: &Bundle<'_, Staging>) -> Result<Option<Vec<Bundle<'r, Staging>>>> {
+        if office_original(bundle).await.is_some() || image_original(bundle).await.is_some() {
+            return Ok(None);
+        }
+
+        let dir = bundle.path().join("split");
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let separators = self.detect(bundle, &dir).await;
+        tokio::fs::remove_dir_all(&dir).await?;
+
+        let separators = separators?;
+        if !separators.contains(&true) {
+            return Ok(None);
+        }
+
+        let metadata = bundle.read_metadata().await?;
+
+        let parts = partition(&separators);
+        info!("Splitting bundle {} into {} documents", bundle.id(), parts.len());
+
+        let mut bundles = Vec::with_capacity(parts.len());
+        for pages in parts {
+            let part = repository.stage().await?;
+
+            let result: Result<()> = async {
+                let pages = pages.iter().map(usize::to_string).collect::<Vec<_>>().join(",");
+                run(&self.config.qpdf, Command::new(&self.config.qpdf)
+                    .arg("--empty")
+                    .arg("--pages").arg(bundle.path_of(Kind::other("original.pdf"))).arg(&pages).arg("--")
+                    .arg(part.path_of(Kind::other("original.pdf")))).await?;
+
+                metadata.save(part.write(Kind::Metadata).await?).await?;
+
+                return Ok(());
+            }.await;
+
+            bundles.push(part);
+
+            // Remove the parts created so far, so the stack can be juiced as a whole
+            if let Err(err) = result {
+                for part in bundles {
+                    part.delete().await?;
+                }
+                return Err(err);
+            }
+        }
+
+        return Ok(Some(bundles));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn pgm(pixels: &[u8]) -> Vec<u8> {
+        let mut image = format!("P5\n# rendered\n{} 1\n255\n", pixels.len()).into_bytes();
+        image.extend_from_slice(pixels);
+        return image;
+    }
+
+    #[test]
+    fn test_darkness() {
+        assert_that!(darkness(&pgm(&[255, 255, 255, 255]))).is_equal_to(Some(0.0));
+        assert_that!(darkness(&pgm(&[0, 255, 200, 10]))).is_equal_to(Some(0.5));
+        assert_that!(darkness(b"P6\n1 1\n255\n\x00\x00\x00")).is_none();
+        assert_that!(darkness(b"P5\n4 1\n255\n\x00")).is_none();
+    }
+
+    #[test]
+    fn test_partition() {
+        assert_that!(partition(&[false, false, true, false, true, true, false])).is_equal_to(vec![vec![1, 2], vec![4], vec![7]]);
+        assert_that!(partition(&[true, false, false, true])).is_equal_to(vec![vec![2, 3]]);
+        assert_that!(partition(&[false, false])).is_equal_to(vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn test_page_number() {
+        assert_that!(page_number("page-07.pgm")).is_equal_to(Some(7));
+        assert_that!(page_number("page-07.png")).is_none();
+    }
+}
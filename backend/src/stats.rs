@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::meta::Metadata;
+use crate::proto::api::stats::{Count, Day, Group, Month, Total};
+use crate::proto::model::{Decimal, PropertyValue};
+
+/// Property holding the amount of a document if not requested otherwise
+pub const DEFAULT_AMOUNT: &str = "invoice.total";
+
+/// Property holding the date of a document if not requested otherwise
+pub const DEFAULT_DATE: &str = "date";
+
+/// Groups documents by correspondent and year and sums up their amounts.
+///
+/// The year is taken from the given date property and falls back to the upload date if the property is missing.
+/// Amounts are summed up per currency, as amounts in different currencies can not be added up.
+pub fn group<'m>(docs: impl IntoIterator<Item=&'m Metadata>, amount: &str, date: Option<&str>) -> Vec<Group> {
+    let mut groups = BTreeMap::<(Option<String>, i32), (usize, BTreeMap<Option<String>, Decimal>)>::new();
+
+    for metadata in docs {
+        let year = match date.and_then(|date| metadata.properties.get(date)) {
+            Some(PropertyValue::Date(date)) => date.year(),
+            _ => metadata.uploaded.year(),
+        };
+
+        let (count, totals) = groups.entry((metadata.correspondent.clone(), year)).or_default();
+        *count += 1;
+
+        let (currency, amount) = match metadata.properties.get(amount) {
+            Some(PropertyValue::Decimal { amount, currency }) => (currency.clone(), *amount),
+            Some(PropertyValue::Integer(amount)) => (None, Decimal::from(*amount)),
+            _ => continue,
+        };
+
+        let total = totals.entry(currency).or_insert_with(|| Decimal::from(0));
+        *total = *total + amount;
+    }
+
+    return groups.into_iter()
+        .map(|((correspondent, year), (count, totals))| Group {
+            correspondent,
+            year,
+            count,
+            totals: totals.into_iter()
+                .map(|(currency, amount)| Total { currency, amount })
+                .collect(),
+        })
+        .collect();
+}
+
+/// Counts documents per day between the given dates, both by upload date and by the given date property.
+///
+/// Documents without the date property are only counted by their upload date.
+pub fn calendar<'m>(docs: impl IntoIterator<Item=&'m Metadata>, date: &str, from: NaiveDate, to: NaiveDate) -> Vec<Day> {
+    let mut days = BTreeMap::<NaiveDate, (usize, usize)>::new();
+
+    let within = |day: &NaiveDate| from <= *day && *day <= to;
+
+    for metadata in docs {
+        let uploaded = metadata.uploaded.date().naive_utc();
+        if within(&uploaded) {
+            days.entry(uploaded).or_default().0 += 1;
+        }
+
+        if let Some(PropertyValue::Date(dated)) = metadata.properties.get(date) {
+            if within(dated) {
+                days.entry(*dated).or_default().1 += 1;
+            }
+        }
+    }
+
+    return days.into_iter()
+        .map(|(date, (uploaded, dated))| Day { date, uploaded, dated })
+        .collect();
+}
+
+/// Counts documents per month of their upload.
+pub fn months<'m>(docs: impl IntoIterator<Item=&'m Metadata>) -> Vec<Month> {
+    let mut months = BTreeMap::<(i32, u32), usize>::new();
+
+    for metadata in docs {
+        *months.entry((metadata.uploaded.year(), metadata.uploaded.month())).or_default() += 1;
+    }
+
+    return months.into_iter()
+        .map(|((year, month), count)| Month { year, month, count })
+        .collect();
+}
+
+/// Counts the occurrences of the names and returns the most frequent ones, ties ordered by name.
+pub fn top(names: impl IntoIterator<Item=String>, limit: usize) -> Vec<Count> {
+    let mut counts = HashMap::<String, usize>::new();
+    for name in names {
+        *counts.entry(name).or_default() += 1;
+    }
+
+    let mut counts = counts.into_iter()
+        .map(|(name, count)| Count { name, count })
+        .collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    counts.truncate(limit);
+
+    return counts;
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn doc(correspondent: Option<&str>, uploaded: i32, amount: Option<&str>) -> Metadata {
+        let mut metadata = Metadata {
+            uploaded: Utc.ymd(uploaded, 6, 1).and_hms(0, 0, 0),
+            correspondent: correspondent.map(String::from),
+            ..Metadata::new()
+        };
+
+        if let Some(amount) = amount {
+            metadata.properties.insert(String::from(DEFAULT_AMOUNT), PropertyValue::amount(Decimal::from_str(amount).unwrap(), Some(String::from("EUR"))).unwrap());
+        }
+
+        return metadata;
+    }
+
+    #[test]
+    fn test_group() {
+        let mut issued = doc(Some("ACME"), 2023, Some("5.00"));
+        issued.properties.insert(String::from("invoice.issued"), PropertyValue::Date(NaiveDate::from_ymd(2022, 12, 30)));
+
+        let docs = vec![
+            doc(Some("ACME"), 2022, Some("10.50")),
+            doc(Some("ACME"), 2022, None),
+            doc(Some("ACME"), 2023, Some("1.25")),
+            doc(None, 2022, Some("3")),
+            issued,
+        ];
+
+        let groups = group(&docs, DEFAULT_AMOUNT, Some("invoice.issued"));
+        assert_that!(groups).has_length(3);
+
+        assert_that!(groups[0].correspondent).is_none();
+
+        assert_that!(groups[1].correspondent.as_deref()).is_equal_to(Some("ACME"));
+        assert_that!(groups[1].year).is_equal_to(2022);
+        assert_that!(groups[1].count).is_equal_to(3);
+        assert_that!(groups[1].totals[0].amount.to_string()).is_equal_to(String::from("15.50"));
+        assert_that!(groups[1].totals[0].currency.as_deref()).is_equal_to(Some("EUR"));
+
+        assert_that!(groups[2].year).is_equal_to(2023);
+        assert_that!(groups[2].count).is_equal_to(1);
+    }
+
+    #[test]
+    fn test_calendar() {
+        let mut dated = doc(None, 2023, None);
+        dated.properties.insert(String::from(DEFAULT_DATE), PropertyValue::Date(NaiveDate::from_ymd(2023, 5, 20)));
+
+        let mut outdated = doc(None, 2023, None);
+        outdated.properties.insert(String::from(DEFAULT_DATE), PropertyValue::Date(NaiveDate::from_ymd(2020, 1, 1)));
+
+        let docs = vec![
+            dated,
+            outdated,
+            doc(None, 2023, None),
+            doc(None, 2022, None),
+        ];
+
+        let days = calendar(&docs, DEFAULT_DATE, NaiveDate::from_ymd(2023, 1, 1), NaiveDate::from_ymd(2023, 12, 31));
+        assert_that!(days).is_equal_to(vec![
+            Day { date: NaiveDate::from_ymd(2023, 5, 20), uploaded: 0, dated: 1 },
+            Day { date: NaiveDate::from_ymd(2023, 6, 1), uploaded: 3, dated: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_months() {
+        let docs = vec![
+            doc(None, 2023, None),
+            doc(None, 2022, None),
+            doc(None, 2023, None),
+        ];
+
+        assert_that!(months(&docs)).is_equal_to(vec![
+            Month { year: 2022, month: 6, count: 1 },
+            Month { year: 2023, month: 6, count: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_top() {
+        let names = vec!["tax", "invoice", "tax", "insurance", "invoice", "tax"];
+
+        assert_that!(top(names.into_iter().map(String::from), 2)).is_equal_to(vec![
+            Count { name: String::from("tax"), count: 3 },
+            Count { name: String::from("invoice"), count: 2 },
+        ]);
+    }
+}
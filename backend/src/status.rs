@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::auth::Device;
+use crate::proto::api::admin::{ErrorInfo, LoginFailureInfo, TaskInfo};
+
+/// Number of recent errors kept for reporting
+const ERRORS: usize = 50;
+
+/// Number of recent failed logins kept for auditing
+const LOGINS: usize = 50;
+
+/// Collects runtime information of the background tasks for the admin dashboard.
+pub struct Status {
+    started: DateTime<Utc>,
+
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    errors: VecDeque<ErrorInfo>,
+    failed: u64,
+
+    logins: VecDeque<LoginFailureInfo>,
+
+    indexed: Option<DateTime<Utc>>,
+    backup: Option<DateTime<Utc>>,
+
+    /// Scheduled maintenance tasks by name
+    tasks: BTreeMap<String, TaskInfo>,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        return Self {
+            started: Utc::now(),
+            inner: Mutex::new(Inner::default()),
+        };
+    }
+
+    pub fn started(&self) -> DateTime<Utc> { self.started }
+
+    /// Records a failed background job.
+    pub fn failed(&self, source: &str, err: &anyhow::Error) {
+        let mut inner = self.inner.lock().expect("Status poisoned");
+
+        inner.failed += 1;
+
+        if inner.errors.len() >= ERRORS {
+            inner.errors.pop_front();
+        }
+        inner.errors.push_back(ErrorInfo {
+            time: Utc::now(),
+            source: source.to_string(),
+            message: format!("{:#}", err),
+        });
+    }
+
+    /// Records a failed or rejected login attempt.
+    pub fn login_failed(&self, device: &Device, locked: bool) {
+        let mut inner = self.inner.lock().expect("Status poisoned");
+
+        if inner.logins.len() >= LOGINS {
+            inner.logins.pop_front();
+        }
+        inner.logins.push_back(LoginFailureInfo {
+            time: Utc::now(),
+            address: device.address.clone(),
+            user_agent: device.user_agent.clone(),
+            locked,
+        });
+    }
+
+    /// Records a successful update of the index.
+    pub fn indexed(&self) {
+        self.inner.lock().expect("Status poisoned").indexed = Some(Utc::now());
+    }
+
+    /// Records a finished backup.
+    pub fn backed_up(&self) {
+        self.inner.lock().expect("Status poisoned").backup = Some(Utc::now());
+    }
+
+    /// Registers a scheduled maintenance task.
+    pub fn scheduled(&self, task: &str, interval: u64) {
+        self.inner.lock().expect("Status poisoned").tasks.insert(task.to_string(), TaskInfo {
+            task: task.to_string(),
+            interval,
+            last_run: None,
+            duration: None,
+            result: None,
+            error: None,
+        });
+    }
+
+    /// Records a finished run of a scheduled maintenance task, failed runs are recorded as errors as well.
+    pub fn ran(&self, task: &str, started: DateTime<Utc>, result: &anyhow::Result<String>) {
+        if let Err(err) = result {
+            self.failed(task, err);
+        }
+
+        let mut inner = self.inner.lock().expect("Status poisoned");
+        if let Some(info) = inner.tasks.get_mut(task) {
+            info.last_run = Some(started);
+            info.duration = Some((Utc::now() - started).num_milliseconds().max(0) as u64);
+            info.result = result.as_ref().ok().cloned();
+            info.error = result.as_ref().err().map(|err| format!("{:#}", err));
+        }
+    }
+
+    /// Returns the scheduled maintenance tasks by name.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        return self.inner.lock().expect("Status poisoned").tasks.values().cloned().collect();
+    }
+
+    pub fn last_indexed(&self) -> Option<DateTime<Utc>> { self.inner.lock().expect("Status poisoned").indexed }
+
+    pub fn last_backup(&self) -> Option<DateTime<Utc>> { self.inner.lock().expect("Status poisoned").backup }
+
+    pub fn failed_count(&self) -> u64 { self.inner.lock().expect("Status poisoned").failed }
+
+    /// Returns the recent errors, latest first.
+    pub fn errors(&self) -> Vec<ErrorInfo> {
+        return self.inner.lock().expect("Status poisoned").errors.iter().rev().cloned().collect();
+    }
+
+    /// Returns the recent failed logins, latest first.
+    pub fn failed_logins(&self) -> Vec<LoginFailureInfo> {
+        return self.inner.lock().expect("Status poisoned").logins.iter().rev().cloned().collect();
+    }
+}
+
+impl Default for Status {
+    fn default() -> Self { Self::new() }
+}
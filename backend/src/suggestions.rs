@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+
+use crate::meta::Metadata;
+use crate::proto::api::suggestions::{Suggestion, SuggestionsResponse};
+use crate::proto::model::Label;
+use crate::repository::Repository;
+
+/// Minimal confidence of proposed values
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// Maximal number of proposed labels
+const MAX_LABELS: usize = 5;
+
+/// Splits the text into lower-cased words and counts their occurrences.
+fn tokenize(text: &str) -> HashMap<String, f64> {
+    let mut tokens = HashMap::new();
+    for token in text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| token.chars().count() > 2)
+        .map(str::to_lowercase) {
+        *tokens.entry(token).or_insert(0.0) += 1.0;
+    }
+
+    return tokens;
+}
+
+/// Inverse document frequencies of all terms seen while training.
+struct Vocabulary {
+    idf: HashMap<String, f64>,
+}
+
+impl Vocabulary {
+    fn new<'d>(docs: impl Iterator<Item=&'d HashMap<String, f64>>) -> Self {
+        let mut count = 0;
+        let mut frequencies = HashMap::<String, usize>::new();
+        for doc in docs {
+            count += 1;
+            for term in doc.keys() {
+                *frequencies.entry(term.clone()).or_default() += 1;
+            }
+        }
+
+        let idf = frequencies.into_iter()
+            .map(|(term, frequency)| (term, ((1 + count) as f64 / (1 + frequency) as f64).ln() + 1.0))
+            .collect();
+
+        return Self { idf };
+    }
+
+    fn len(&self) -> usize {
+        return self.idf.len();
+    }
+
+    /// Weights the term counts by their inverse document frequency and drops unknown terms.
+    fn weigh(&self, tokens: HashMap<String, f64>) -> HashMap<String, f64> {
+        return tokens.into_iter()
+            .filter_map(|(term, count)| self.idf.get(&term).map(|idf| (term, count * idf)))
+            .collect();
+    }
+}
+
+/// Accumulated term weights of the documents belonging to a class.
+#[derive(Default)]
+struct Class {
+    docs: usize,
+    weights: HashMap<String, f64>,
+    total: f64,
+}
+
+impl Class {
+    fn add(&mut self, doc: &HashMap<String, f64>) {
+        self.docs += 1;
+        for (term, weight) in doc {
+            *self.weights.entry(term.clone()).or_default() += weight;
+            self.total += weight;
+        }
+    }
+
+    fn weight(&self, term: &str) -> f64 {
+        return self.weights.get(term).copied().unwrap_or_default();
+    }
+}
+
+/// Calculates the logarithmic, unnormalized probability of a document belonging to a class using Laplace smoothing.
+fn score(prior: f64, weight: impl Fn(&str) -> f64, total: f64, doc: &HashMap<String, f64>, vocabulary: usize) -> f64 {
+    return prior.ln() + doc.iter()
+        .map(|(term, count)| count * ((weight(term) + 1.0) / (total + vocabulary as f64)).ln())
+        .sum::<f64>();
+}
+
+/// Turns logarithmic scores into probabilities summing up to one.
+fn normalize<K>(scores: Vec<(K, f64)>) -> Vec<(K, f64)> {
+    let max = scores.iter().map(|(_, score)| *score).fold(f64::NEG_INFINITY, f64::max);
+    let sum = scores.iter().map(|(_, score)| (score - max).exp()).sum::<f64>();
+
+    return scores.into_iter()
+        .map(|(key, score)| (key, (score - max).exp() / sum))
+        .collect();
+}
+
+/// Multinomial naive Bayes classifier choosing exactly one of multiple classes.
+struct Classifier<K> {
+    docs: usize,
+    classes: HashMap<K, Class>,
+}
+
+impl<K: Hash + Eq + Clone> Classifier<K> {
+    fn new() -> Self {
+        return Self { docs: 0, classes: HashMap::new() };
+    }
+
+    fn train(&mut self, key: K, doc: &HashMap<String, f64>) {
+        self.docs += 1;
+        self.classes.entry(key).or_default().add(doc);
+    }
+
+    /// Returns the most probable class with its probability.
+    fn predict(&self, doc: &HashMap<String, f64>, vocabulary: usize) -> Option<(K, f64)> {
+        let scores = self.classes.iter()
+            .map(|(key, class)| (key.clone(), score(class.docs as f64 / self.docs as f64, |term| class.weight(term), class.total, doc, vocabulary)))
+            .collect();
+
+        return normalize(scores).into_iter()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("Probability is NaN"));
+    }
+}
+
+/// Model trained on archived documents proposing the labels, correspondent and title of new documents.
+///
+/// Each label is decided on its own by comparing the documents having the label to all other documents. The
+/// correspondent and title are chosen from the values seen in the archive, including the absence of a value, so only
+/// values typical for similar documents are proposed.
+struct Model {
+    vocabulary: Vocabulary,
+
+    /// Term weights of all documents
+    all: Class,
+
+    /// Term weights of the documents having a label
+    labels: HashMap<Label, Class>,
+
+    correspondents: Classifier<Option<String>>,
+    titles: Classifier<Option<String>>,
+}
+
+impl Model {
+    fn train(docs: Vec<(String, Metadata)>) -> Self {
+        let docs = docs.into_iter()
+            .map(|(text, metadata)| (tokenize(&text), metadata))
+            .collect::<Vec<_>>();
+
+        let vocabulary = Vocabulary::new(docs.iter().map(|(tokens, _)| tokens));
+
+        let mut all = Class::default();
+        let mut labels = HashMap::<Label, Class>::new();
+        let mut correspondents = Classifier::new();
+        let mut titles = Classifier::new();
+
+        for (tokens, metadata) in docs {
+            let doc = vocabulary.weigh(tokens);
+
+            all.add(&doc);
+            for label in &metadata.labels {
+                labels.entry(label.clone()).or_default().add(&doc);
+            }
+
+            correspondents.train(metadata.correspondent, &doc);
+            titles.train(metadata.title, &doc);
+        }
+
+        return Self { vocabulary, all, labels, correspondents, titles };
+    }
+
+    fn suggest(&self, text: &str) -> SuggestionsResponse {
+        let doc = self.vocabulary.weigh(tokenize(text));
+        let size = self.vocabulary.len();
+
+        let mut labels = self.labels.iter()
+            .map(|(label, pro)| {
+                let con = self.all.docs - pro.docs;
+                let scores = vec![
+                    (true, score(pro.docs as f64 / self.all.docs as f64, |term| pro.weight(term), pro.total, &doc, size)),
+                    (false, score(con as f64 / self.all.docs as f64, |term| self.all.weight(term) - pro.weight(term), self.all.total - pro.total, &doc, size)),
+                ];
+
+                let confidence = normalize(scores).into_iter()
+                    .find_map(|(pro, confidence)| pro.then(|| confidence))
+                    .unwrap_or_default();
+
+                return Suggestion { value: label.clone(), confidence };
+            })
+            .filter(|suggestion| suggestion.confidence >= MIN_CONFIDENCE)
+            .collect::<Vec<_>>();
+
+        labels.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).expect("Probability is NaN"));
+        labels.truncate(MAX_LABELS);
+
+        let propose = |classifier: &Classifier<Option<String>>| classifier.predict(&doc, size)
+            .and_then(|(value, confidence)| Some(Suggestion { value: value?, confidence }))
+            .filter(|suggestion| suggestion.confidence >= MIN_CONFIDENCE);
+
+        return SuggestionsResponse {
+            labels,
+            correspondent: propose(&self.correspondents),
+            title: propose(&self.titles),
+        };
+    }
+}
+
+/// Proposes metadata for inboxed documents based on the archived documents of each user.
+///
+/// Models are trained on first use and re-trained as soon as the repository has changed, so suggestions improve with
+/// every archived document. Documents of encryption domains are not used for training, as their text is encrypted.
+pub struct Suggestions {
+    /// Trained models per user with the journal cursor they have been trained at
+    models: RwLock<HashMap<String, (u64, Arc<Model>)>>,
+}
+
+impl Suggestions {
+    pub fn new() -> Self {
+        return Self { models: RwLock::new(HashMap::new()) };
+    }
+
+    async fn model(&self, repository: &Repository, user: &str) -> Result<Arc<Model>> {
+        let cursor = repository.journal().cursor().await;
+
+        if let Some((trained, model)) = self.models.read().await.get(user) {
+            if *trained == cursor {
+                return Ok(model.clone());
+            }
+        }
+
+        let mut docs = Vec::new();
+        for bundle in repository.archive().list().await? {
+            let metadata = bundle.read_metadata().await?;
+            if !metadata.is_visible_to(user) || metadata.domain.is_some() {
+                continue;
+            }
+
+            docs.push((bundle.read_plaintext().await?, metadata));
+        }
+
+        let model = Arc::new(Model::train(docs));
+        self.models.write().await.insert(user.to_string(), (cursor, model.clone()));
+
+        return Ok(model);
+    }
+
+    /// Proposes labels, correspondent and title for a document by its text.
+    ///
+    /// Labels the document already has are not proposed again.
+    pub async fn suggest(&self, repository: &Repository, user: &str, text: &str, existing: &HashSet<Label>) -> Result<SuggestionsResponse> {
+        let model = self.model(repository, user).await?;
+
+        let mut response = model.suggest(text);
+        response.labels.retain(|suggestion| !existing.contains(&suggestion.value));
+
+        return Ok(response);
+    }
+}
+
+impl Default for Suggestions {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn doc(text: &str, labels: &[&str], correspondent: Option<&str>, title: Option<&str>) -> (String, Metadata) {
+        return (text.to_string(), Metadata {
+            labels: labels.iter().map(|label| Label::from(*label)).collect(),
+            correspondent: correspondent.map(String::from),
+            title: title.map(String::from),
+            ..Metadata::new()
+        });
+    }
+
+    #[test]
+    fn test_suggest() {
+        let model = Model::train(vec![
+            doc("Stadtwerke electricity bill for march, consumption 120 kwh", &["utilities", "finance"], Some("Stadtwerke"), Some("Power bill")),
+            doc("Stadtwerke electricity bill for april, consumption 140 kwh", &["utilities", "finance"], Some("Stadtwerke"), Some("Power bill")),
+            doc("Stadtwerke electricity bill for may, consumption 100 kwh", &["utilities"], Some("Stadtwerke"), Some("Power bill")),
+            doc("Doctor appointment confirmation, bring your insurance card", &["medical"], Some("Practice"), None),
+            doc("Doctor prescription for ibuprofen, insurance covered", &["medical"], None, None),
+            doc("Holiday postcard from the beach, sunny weather", &[], None, None),
+        ]);
+
+        let response = model.suggest("Stadtwerke electricity bill for june, consumption 130 kwh");
+        assert_that!(response.labels.iter().any(|suggestion| suggestion.value == Label::from("utilities"))).is_true();
+        assert_that!(response.labels.iter().any(|suggestion| suggestion.value == Label::from("medical"))).is_false();
+        assert_that!(response.correspondent.as_ref().map(|suggestion| suggestion.value.as_str())).is_equal_to(Some("Stadtwerke"));
+        assert_that!(response.title.as_ref().map(|suggestion| suggestion.value.as_str())).is_equal_to(Some("Power bill"));
+
+        let response = model.suggest("Doctor appointment, insurance card required");
+        assert_that!(response.labels.iter().map(|suggestion| suggestion.value.clone()).collect::<Vec<_>>()).is_equal_to(vec![Label::from("medical")]);
+        assert_that!(response.title).is_none();
+    }
+
+    #[test]
+    fn test_suggest_untrained() {
+        let model = Model::train(vec![]);
+
+        let response = model.suggest("Anything");
+        assert_that!(response.labels).is_empty();
+        assert_that!(response.correspondent).is_none();
+        assert_that!(response.title).is_none();
+    }
+}
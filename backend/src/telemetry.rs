@@ -0,0 +1,35 @@
+use anyhow::Result;
+use log::info;
+use opentelemetry::KeyValue;
+use opentelemetry::sdk::{Resource, trace};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+use crate::config::Tracing as Config;
+
+/// Keeps exporting spans until dropped, flushing the spans still pending.
+pub struct Telemetry {
+    _uninstall: opentelemetry_otlp::Uninstall,
+}
+
+impl Telemetry {
+    /// Installs a global subscriber exporting all spans to an OTLP collector.
+    ///
+    /// Spans cover the ingest pipeline from the upload through the juicer up to the archive, so slow ingests can be
+    /// traced down to the container running the juicer.
+    pub fn from_config(config: Config) -> Result<Self> {
+        let (tracer, uninstall) = opentelemetry_otlp::new_pipeline()
+            .with_endpoint(&config.endpoint)
+            .with_trace_config(trace::config()
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", config.service.clone())])))
+            .install()?;
+
+        let subscriber = Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::set_global_default(subscriber)?;
+
+        info!("Exporting spans to {} as {}", config.endpoint, config.service);
+
+        return Ok(Self { _uninstall: uninstall });
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use log::{error, info, warn};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use tokio::sync::broadcast::RecvError;
+
+use crate::config::Timestamping as Config;
+use crate::proto::model::Kind;
+use crate::repository::{Archived, Bundle, Event, Repository, sha256};
+use crate::status::Status;
+
+/// The fragment holding the timestamp token of the document
+pub const FRAGMENT: &str = "timestamp.tst";
+
+/// DER encoded algorithm identifier of SHA-256, including the absent parameters
+const SHA256: &[u8] = &[0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00];
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const BOOLEAN: u8 = 0x01;
+const SEQUENCE: u8 = 0x30;
+
+/// Encodes a single DER element.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+
+    if content.len() < 0x80 {
+        element.push(content.len() as u8);
+    } else {
+        let length = (content.len() as u64).to_be_bytes();
+        let length = &length[length.iter().take_while(|b| **b == 0).count()..];
+        element.push(0x80 | length.len() as u8);
+        element.extend_from_slice(length);
+    }
+
+    element.extend_from_slice(content);
+
+    return element;
+}
+
+/// Decodes a single DER element and returns its tag, its content and the whole element.
+fn parse(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.get(0)?;
+    let first = *data.get(1)?;
+
+    let (length, offset) = if first < 0x80 {
+        (first as usize, 2)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 8 {
+            return None;
+        }
+
+        let length = data.get(2..2 + count)?.iter()
+            .fold(0usize, |length, b| (length << 8) | *b as usize);
+        (length, 2 + count)
+    };
+
+    let end = offset.checked_add(length)?;
+    return Some((tag, data.get(offset..end)?, &data[..end]));
+}
+
+/// Builds a time-stamp request for a SHA-256 hash, asking the authority to include its certificate.
+fn request(hash: &[u8], nonce: u64) -> Vec<u8> {
+    // Integers are encoded minimally and signed, so a leading zero keeps the nonce positive
+    let nonce = nonce.to_be_bytes();
+    let mut nonce = nonce[nonce.iter().take_while(|b| **b == 0).count().min(7)..].to_vec();
+    if nonce[0] & 0x80 != 0 {
+        nonce.insert(0, 0);
+    }
+
+    let imprint = [SHA256, &der(OCTET_STRING, hash)].concat();
+
+    return der(SEQUENCE, &[
+        der(INTEGER, &[1]),
+        der(SEQUENCE, &imprint),
+        der(INTEGER, &nonce),
+        der(BOOLEAN, &[0xff]),
+    ].concat());
+}
+
+/// Extracts the timestamp token from a time-stamp response, failing if the authority rejected the request.
+fn token(response: &[u8]) -> Result<Vec<u8>> {
+    let invalid = || anyhow!("Invalid time-stamp response");
+
+    let (tag, content, _) = parse(response).ok_or_else(invalid)?;
+    if tag != SEQUENCE {
+        return Err(invalid());
+    }
+
+    let (tag, info, status) = parse(content).ok_or_else(invalid)?;
+    if tag != SEQUENCE {
+        return Err(invalid());
+    }
+
+    let (tag, value, _) = parse(info).ok_or_else(invalid)?;
+    if tag != INTEGER || value.len() != 1 {
+        return Err(invalid());
+    }
+
+    // Granted or granted with modifications
+    if value[0] > 1 {
+        bail!("Time-stamping authority rejected the request with status {}", value[0]);
+    }
+
+    let (_, _, token) = parse(&content[status.len()..]).ok_or_else(invalid)?;
+
+    return Ok(token.to_vec());
+}
+
+/// Obtains RFC 3161 timestamps for the documents of archived bundles from a time-stamping authority.
+///
+/// The token signed by the authority proves the document existed unmodified at the time given in the token. It is
+/// stored as fragment next to the document and can be verified with standard tools, i.e.
+/// `openssl ts -verify -token_in -in timestamp.tst -data document.pdf`.
+pub struct Timestamper {
+    config: Config,
+
+    repository: Repository,
+
+    client: reqwest::Client,
+
+    status: Arc<Status>,
+}
+
+impl Timestamper {
+    pub fn from_config(config: Config, repository: Repository, status: Arc<Status>) -> Result<Self> {
+        let client = reqwest::Client::builder().build()?;
+
+        return Ok(Self { config, repository, client, status });
+    }
+
+    /// Requests a timestamp for the document of the bundle unless it has been timestamped before.
+    ///
+    /// Returns true if a timestamp has been obtained.
+    pub async fn timestamp(&self, bundle: &Bundle<'_, Archived>) -> Result<bool> {
+        if tokio::fs::metadata(bundle.path_of(Kind::other(FRAGMENT))).await.is_ok() {
+            return Ok(false);
+        }
+
+        let document = bundle.path_of(Kind::Document);
+        if tokio::fs::metadata(&document).await.is_err() {
+            return Ok(false);
+        }
+
+        let hash = hex::decode(sha256(&document).await?)?;
+
+        let mut request = self.client.post(&self.config.url)
+            .header("Content-Type", "application/timestamp-query")
+            .body(self::request(&hash, OsRng.next_u64()));
+
+        if let Some(username) = &self.config.username {
+            request = request.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let response = request
+            .send().await?
+            .error_for_status()?
+            .bytes().await?;
+
+        bundle.replace(Kind::other(FRAGMENT), &token(&response)?).await?;
+
+        return Ok(true);
+    }
+
+    async fn handle(&self, bundle: &Bundle<'_, Archived>) {
+        match self.timestamp(bundle).await {
+            Ok(true) => info!("Timestamped document of bundle {}", bundle.id()),
+            Ok(false) => {}
+            Err(err) => {
+                error!("Failed to timestamp bundle {}: {:#}", bundle.id(), err);
+                self.status.failed("timestamping", &err);
+            }
+        }
+    }
+
+    /// Timestamps all archived documents lacking a timestamp, then follows the repository for newly archived ones.
+    pub async fn run(self) {
+        let mut events = self.repository.subscribe();
+
+        match self.repository.archive().list().await {
+            Ok(bundles) => for bundle in bundles {
+                self.handle(&bundle).await;
+            },
+            Err(err) => {
+                error!("Failed to list archive for timestamping: {:#}", err);
+                self.status.failed("timestamping", &err);
+            }
+        }
+
+        loop {
+            let id = match events.recv().await {
+                Ok(Event::Archived(id)) => id,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Timestamping missed {} repository events", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            if let Some(bundle) = self.repository.archive().get(id).await {
+                self.handle(&bundle).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_der() {
+        assert_that!(der(INTEGER, &[1])).is_equal_to(vec![0x02, 0x01, 0x01]);
+
+        let long = der(OCTET_STRING, &[0u8; 300]);
+        assert_that!(long[..4].to_vec()).is_equal_to(vec![0x04, 0x82, 0x01, 0x2c]);
+
+        let (tag, content, element) = parse(&long).unwrap();
+        assert_that!(tag).is_equal_to(OCTET_STRING);
+        assert_that!(content.len()).is_equal_to(300);
+        assert_that!(element.len()).is_equal_to(304);
+
+        assert_that!(parse(&long[..100])).is_none();
+    }
+
+    #[test]
+    fn test_request() {
+        let hash = [0xab; 32];
+        let request = request(&hash, 0x8000_0000_0000_0001);
+
+        let (tag, content, _) = parse(&request).unwrap();
+        assert_that!(tag).is_equal_to(SEQUENCE);
+
+        let (_, version, version_element) = parse(content).unwrap();
+        assert_that!(version.to_vec()).is_equal_to(vec![1]);
+
+        let rest = &content[version_element.len()..];
+        let (_, imprint, imprint_element) = parse(rest).unwrap();
+        assert_that!(imprint.starts_with(SHA256)).is_true();
+        assert_that!(imprint.ends_with(&hash)).is_true();
+
+        let rest = &rest[imprint_element.len()..];
+        let (_, nonce, _) = parse(rest).unwrap();
+        assert_that!(nonce.to_vec()).is_equal_to(vec![0x00, 0x80, 0, 0, 0, 0, 0, 0, 0x01]);
+
+        let request = super::request(&hash, 0x7f);
+        assert_that!(request.ends_with(&[0x02, 0x01, 0x7f, 0x01, 0x01, 0xff])).is_true();
+    }
+
+    #[test]
+    fn test_token() {
+        let signed = der(SEQUENCE, b"signed data");
+
+        let granted = der(SEQUENCE, &[der(SEQUENCE, &der(INTEGER, &[0])), signed.clone()].concat());
+        assert_that!(token(&granted).unwrap()).is_equal_to(signed);
+
+        let rejected = der(SEQUENCE, &der(SEQUENCE, &der(INTEGER, &[2])));
+        assert_that!(token(&rejected).is_err()).is_true();
+
+        assert_that!(token(b"garbage").is_err()).is_true();
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use tokio::process::Command;
+
+use crate::config::Transcription as Config;
+
+/// Transcribes audio attached to documents, i.e. a dictated note about a contract.
+///
+/// The transcript is stored as separate fragment next to the audio, so it is indexed along the plaintext of the
+/// document without changing its pages.
+pub struct Transcriber {
+    config: Option<Config>,
+}
+
+impl Transcriber {
+    /// The suffix of the fragments holding transcripts
+    pub const SUFFIX: &'static str = ".transcript.txt";
+
+    pub fn new(config: Option<Config>) -> Self {
+        return Self { config };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.config.is_some();
+    }
+
+    /// Returns the fragment name of the transcript of an audio fragment.
+    pub fn transcript(name: &str) -> String {
+        return format!("{}{}", name, Self::SUFFIX);
+    }
+
+    /// Returns true if the fragment holds a transcript.
+    pub fn is_transcript(name: &str) -> bool {
+        return name.len() > Self::SUFFIX.len() && name.ends_with(Self::SUFFIX);
+    }
+
+    /// Runs the configured engine over an audio file.
+    ///
+    /// Returns `None` if transcription is not configured.
+    pub async fn transcribe(&self, path: impl AsRef<Path>) -> Result<Option<String>> {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+
+        let (command, args) = config.command.split_first()
+            .ok_or_else(|| anyhow!("No command configured for transcription"))?;
+
+        debug!("Transcribing {:?} with {} {:?}", path.as_ref(), command, args);
+
+        let output = Command::new(command)
+            .args(args)
+            .arg(path.as_ref())
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+
+        let output = tokio::time::timeout(Duration::from_secs(config.timeout), output).await
+            .map_err(|_| anyhow!("Transcription timed out after {}s", config.timeout))?
+            .with_context(|| format!("Error executing {}", command))?;
+
+        if !output.status.success() {
+            bail!("Transcription failed: {}: {}", output.status, String::from_utf8_lossy(&output.stderr));
+        }
+
+        let transcript = String::from_utf8(output.stdout)
+            .context("Transcript is not valid UTF-8")?;
+
+        return Ok(Some(transcript.trim().to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_is_transcript() {
+        assert_that!(Transcriber::is_transcript(&Transcriber::transcript("memo.ogg"))).is_true();
+        assert_that!(Transcriber::is_transcript("memo.ogg")).is_false();
+        assert_that!(Transcriber::is_transcript(".transcript.txt")).is_false();
+    }
+
+    #[tokio::test]
+    async fn test_transcribe() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memo.ogg");
+        tokio::fs::write(&path, b"OggS").await.unwrap();
+
+        let transcriber = Transcriber::new(Some(Config {
+            command: vec![
+                String::from("sh"),
+                String::from("-c"),
+                String::from(r#"test -f "$1" && echo 'Cancel the contract by March'"#),
+                String::from("sh"),
+            ],
+            timeout: 10,
+        }));
+
+        assert_that!(transcriber.transcribe(&path).await.unwrap())
+            .is_equal_to(Some(String::from("Cancel the contract by March")));
+
+        assert_that!(Transcriber::new(None).transcribe(&path).await.unwrap()).is_none();
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use tokio::sync::Mutex;
+
+use crate::meta::Metadata;
+use crate::proto::model::DocId;
+
+/// Reverts the change an operation made to a single document.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Restores a deleted bundle from the trash
+    Restore(DocId),
+
+    /// Moves an archived bundle back to the inbox and restores the metadata it had before archiving
+    Unarchive(DocId, Metadata),
+
+    /// Restores the metadata a bundle had before the operation
+    Revert(DocId, Metadata),
+}
+
+impl Action {
+    pub fn id(&self) -> &DocId {
+        return match self {
+            Self::Restore(id) |
+            Self::Unarchive(id, _) |
+            Self::Revert(id, _) => id,
+        };
+    }
+}
+
+struct Operation {
+    actor: String,
+    expires: DateTime<Utc>,
+    actions: Vec<Action>,
+}
+
+/// Short-lived buffer of recently applied operations which can be reverted by the user who applied them.
+///
+/// The buffer is kept in memory only, as it covers mis-clicks and not long-term recovery, which is handled by the
+/// trash and metadata revisions.
+pub struct Undo {
+    window: Duration,
+    operations: Mutex<HashMap<String, Operation>>,
+}
+
+impl Undo {
+    pub fn new(window: Duration) -> Self {
+        return Self {
+            window,
+            operations: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Records the actions reverting an operation and returns the ID of the operation and its expiry.
+    pub async fn record(&self, actor: &str, actions: Vec<Action>) -> (String, DateTime<Utc>) {
+        let now = Utc::now();
+
+        let id = hex::encode(rand::thread_rng().gen::<[u8; 8]>());
+        let expires = now + chrono::Duration::from_std(self.window).expect("Undo window out of range");
+
+        let mut operations = self.operations.lock().await;
+        operations.retain(|_, operation| operation.expires > now);
+        operations.insert(id.clone(), Operation {
+            actor: actor.to_string(),
+            expires,
+            actions,
+        });
+
+        return (id, expires);
+    }
+
+    /// Takes the actions reverting an operation, latest change first.
+    ///
+    /// Returns `None` if the operation does not exist, has expired or has been applied by another user. Each operation
+    /// can only be taken once.
+    pub async fn take(&self, id: &str, actor: &str) -> Option<Vec<Action>> {
+        let mut operations = self.operations.lock().await;
+
+        let operation = operations.get(id)?;
+        if operation.actor != actor {
+            return None;
+        }
+
+        let operation = operations.remove(id)?;
+        if operation.expires <= Utc::now() {
+            return None;
+        }
+
+        return Some(operation.actions.into_iter().rev().collect());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_take() {
+        let undo = Undo::new(Duration::from_secs(30));
+
+        let first = DocId::random();
+        let second = DocId::random();
+
+        let (id, _) = undo.record("admin", vec![Action::Restore(first), Action::Restore(second)]).await;
+
+        assert_that!(undo.take(&id, "other").await).is_none();
+
+        let actions = undo.take(&id, "admin").await.unwrap();
+        assert_that!(actions.iter().map(Action::id).collect::<Vec<_>>()).is_equal_to(vec![&second, &first]);
+
+        // Operations can only be reverted once
+        assert_that!(undo.take(&id, "admin").await).is_none();
+    }
+
+    #[tokio::test]
+    async fn test_expired() {
+        let undo = Undo::new(Duration::from_secs(0));
+
+        let (id, _) = undo.record("admin", vec![]).await;
+        assert_that!(undo.take(&id, "admin").await).is_none();
+    }
+}
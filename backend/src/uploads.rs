@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::proto::model::{DocId, Kind};
+use crate::repository::{Bundle, Staging};
+
+/// State of a resumable upload, persisted in the staging bundle it is written to.
+///
+/// The bundle is not queued for juicing before all bytes have arrived, so interrupted uploads survive restarts and
+/// can be continued from the offset already written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upload {
+    pub owner: String,
+
+    pub created: DateTime<Utc>,
+
+    /// Total size of the document in bytes
+    pub length: u64,
+
+    /// Extension of the original fragment the document is written to
+    pub extension: String,
+
+    /// Hex encoded SHA-256 checksum the completed document is verified against, if given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl Upload {
+    pub const FRAGMENT: &'static str = "upload.json";
+
+    /// The fragment the uploaded document is written to.
+    pub fn original(&self) -> Kind {
+        return Kind::other(format!("original.{}", self.extension));
+    }
+
+    /// Returns the number of bytes written so far.
+    pub async fn offset(&self, bundle: &Bundle<'_, Staging>) -> Result<u64> {
+        return Ok(tokio::fs::metadata(bundle.path_of(self.original())).await?.len());
+    }
+
+    pub async fn load(bundle: &Bundle<'_, Staging>) -> Result<Option<Self>> {
+        let mut file = match bundle.read(Kind::other(Self::FRAGMENT)).await? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).await?;
+
+        return Ok(Some(serde_json::from_slice(&buffer)?));
+    }
+
+    pub async fn save(&self, bundle: &Bundle<'_, Staging>) -> Result<()> {
+        let mut fragment = bundle.write(Kind::other(Self::FRAGMENT)).await?;
+        fragment.write_all(&serde_json::to_vec_pretty(self)?).await?;
+        fragment.commit().await?;
+
+        return Ok(());
+    }
+
+    /// Removes the upload state once the document is complete.
+    pub async fn complete(bundle: &Bundle<'_, Staging>) -> Result<()> {
+        tokio::fs::remove_file(bundle.path_of(Kind::other(Self::FRAGMENT))).await?;
+        return Ok(());
+    }
+}
+
+/// State of a scan assembled from page images sent one at a time, persisted in the staging bundle it is written to.
+///
+/// Like resumable uploads, the bundle is not queued for juicing before the scanner reports the last page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scan {
+    pub owner: String,
+
+    pub created: DateTime<Utc>,
+
+    /// Extensions of the pages received so far, in scanning order
+    pub pages: Vec<String>,
+}
+
+impl Scan {
+    pub const FRAGMENT: &'static str = "scan.json";
+
+    /// The fragment a page is written to, named by its zero-padded number to keep them in order.
+    pub fn page(number: usize, extension: &str) -> Kind {
+        return Kind::other(format!("scan-{:04}.{}", number, extension));
+    }
+
+    pub async fn load(bundle: &Bundle<'_, Staging>) -> Result<Option<Self>> {
+        let mut file = match bundle.read(Kind::other(Self::FRAGMENT)).await? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).await?;
+
+        return Ok(Some(serde_json::from_slice(&buffer)?));
+    }
+
+    pub async fn save(&self, bundle: &Bundle<'_, Staging>) -> Result<()> {
+        let mut fragment = bundle.write(Kind::other(Self::FRAGMENT)).await?;
+        fragment.write_all(&serde_json::to_vec_pretty(self)?).await?;
+        fragment.commit().await?;
+
+        return Ok(());
+    }
+
+    /// Removes the scan state once the last page has been received.
+    pub async fn complete(bundle: &Bundle<'_, Staging>) -> Result<()> {
+        tokio::fs::remove_file(bundle.path_of(Kind::other(Self::FRAGMENT))).await?;
+        return Ok(());
+    }
+}
+
+/// Tracks the uploads chunks are currently written to.
+///
+/// A client retrying a chunk while the stalled request is still being received must not write to the same fragment
+/// concurrently, so only a single chunk is accepted per upload at a time.
+#[derive(Default)]
+pub struct Uploads {
+    active: Arc<Mutex<HashSet<DocId>>>,
+}
+
+/// Marks an upload as active until dropped.
+pub struct Active {
+    id: DocId,
+    active: Arc<Mutex<HashSet<DocId>>>,
+}
+
+impl Uploads {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Marks the upload as active, unless a chunk is already being written to it.
+    pub fn begin(&self, id: DocId) -> Option<Active> {
+        if !self.active.lock().expect("Poisoned").insert(id) {
+            return None;
+        }
+
+        return Some(Active { id, active: self.active.clone() });
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.active.lock().expect("Poisoned").remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_begin() {
+        let uploads = Uploads::new();
+        let id = DocId::random();
+
+        let active = uploads.begin(id);
+        assert_that!(active.is_some()).is_true();
+        assert_that!(uploads.begin(id).is_some()).is_false();
+        assert_that!(uploads.begin(DocId::random()).is_some()).is_true();
+
+        drop(active);
+        assert_that!(uploads.begin(id).is_some()).is_true();
+    }
+}
@@ -0,0 +1,111 @@
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use tokio::process::Command;
+use tokio::sync::broadcast::RecvError;
+
+use crate::config::Warmup as Config;
+use crate::juicer::native::{PAGE_RESOLUTION, THUMBNAIL_SIZE};
+use crate::proto::model::Kind;
+use crate::repository::{Archived, Bundle, Event, Repository};
+use crate::status::Status;
+
+/// Renders the renditions the archive view shows first as soon as a document is archived.
+///
+/// Bundles juiced before per page previews and thumbnails existed lack these renditions, so they are rendered from the
+/// document instead of on first access. Bundles of encryption domains are skipped, as their document is encrypted.
+pub struct Warmup {
+    config: Config,
+
+    repository: Repository,
+
+    status: Arc<Status>,
+}
+
+impl Warmup {
+    pub fn from_config(config: Config, repository: Repository, status: Arc<Status>) -> Self {
+        return Self { config, repository, status };
+    }
+
+    /// Renders the first page of the document to PNG using the given extra arguments.
+    async fn render(&self, bundle: &Bundle<'_, Archived>, args: &[&str]) -> Result<Vec<u8>> {
+        debug!("Running {} {:?}", self.config.pdftoppm, args);
+
+        // Without an output root, pdftoppm writes the single rendered page to stdout
+        let output = Command::new(&self.config.pdftoppm)
+            .args(&["-png", "-f", "1", "-singlefile"])
+            .args(args)
+            .arg(bundle.path_of(Kind::Document))
+            .stdin(Stdio::null())
+            .output().await
+            .with_context(|| format!("Error executing {}", self.config.pdftoppm))?;
+
+        if !output.status.success() {
+            bail!("{} failed: {}: {}", self.config.pdftoppm, output.status, String::from_utf8_lossy(&output.stderr));
+        }
+
+        return Ok(output.stdout);
+    }
+
+    /// Renders the thumbnail and first page preview of an archived bundle if missing.
+    ///
+    /// Returns the number of renditions rendered.
+    pub async fn warm_up(&self, bundle: &Bundle<'_, Archived>) -> Result<usize> {
+        let metadata = bundle.read_metadata().await?;
+        if metadata.domain.is_some() {
+            return Ok(0);
+        }
+
+        let renditions: [(Kind, &[&str]); 2] = [
+            (Kind::Thumbnail, &["-scale-to", THUMBNAIL_SIZE]),
+            (Kind::Page(1), &["-r", PAGE_RESOLUTION]),
+        ];
+
+        let mut rendered = 0;
+        for (kind, args) in renditions.iter() {
+            if tokio::fs::metadata(bundle.path_of(kind)).await.is_ok() {
+                continue;
+            }
+
+            let data = self.render(bundle, args).await?;
+            bundle.replace(kind.clone(), &data).await?;
+
+            rendered += 1;
+        }
+
+        return Ok(rendered);
+    }
+
+    /// Follows the repository events and warms up every archived or restored bundle.
+    pub async fn run(self) {
+        let mut events = self.repository.subscribe();
+
+        loop {
+            let id = match events.recv().await {
+                Ok(Event::Archived(id)) | Ok(Event::Restored(id)) => id,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Warm-up missed {} repository events", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let bundle = match self.repository.archive().get(id).await {
+                Some(bundle) => bundle,
+                None => continue,
+            };
+
+            match self.warm_up(&bundle).await {
+                Ok(0) => {}
+                Ok(rendered) => info!("Rendered {} missing renditions for bundle {}", rendered, id),
+                Err(err) => {
+                    error!("Failed to warm up bundle {}: {:#}", id, err);
+                    self.status.failed("warmup", &err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::meta::Metadata;
+use crate::period::Period;
+use crate::proto::model::{DocId, PropertyValue};
+use crate::repository::Repository;
+
+/// Property holding the day an item has been bought, which starts the warranty
+pub const PURCHASED: &str = "purchase-date";
+
+/// Property holding the duration of the warranty, i.e. `2 years`, a plain number is taken as months
+pub const WARRANTY: &str = "warranty";
+
+/// Returns the day the warranty of a receipt ends.
+///
+/// Documents without a purchase date and a warranty are not covered by a warranty.
+pub fn expires(metadata: &Metadata) -> Option<NaiveDate> {
+    let purchased = match metadata.properties.get(PURCHASED)? {
+        PropertyValue::Date(purchased) => *purchased,
+        _ => return None,
+    };
+
+    let warranty = match metadata.properties.get(WARRANTY)? {
+        PropertyValue::Integer(months) if *months >= 0 => Period::Months(*months as u32),
+        PropertyValue::String(warranty) => warranty.parse().ok()?,
+        _ => return None,
+    };
+
+    return Some(warranty.after(purchased));
+}
+
+/// Finds the duration of a warranty in the text of a receipt by its typical wording.
+///
+/// Recognizes phrases like `2 years warranty`, `warranty: 24 months` or `2 Jahre Garantie`.
+pub fn detect(text: &str) -> Option<Period> {
+    let count = r"(\d{1,3})";
+    let unit = r"(years?|months?|jahre?n?|monate?n?)";
+    let warranty = r"(?:warranty|guarantee|garantie|gewährleistung)";
+
+    let patterns = [
+        format!(r"(?i)\b{}[ -]{}\s+(?:of\s+)?{}", count, unit, warranty),
+        format!(r"(?i){}\s*(?:period\s+)?(?:of|:|von)?\s*{}\s+{}\b", warranty, count, unit),
+    ];
+
+    return patterns.iter()
+        .filter_map(|pattern| Regex::new(pattern).expect("Invalid warranty pattern").captures(text))
+        .find_map(|captures| {
+            let count = captures[1].parse().ok()?;
+            let unit = captures[2].to_lowercase();
+
+            return Some(if unit.starts_with("year") || unit.starts_with("jahr") {
+                Period::Years(count)
+            } else {
+                Period::Months(count)
+            });
+        });
+}
+
+/// Proposes the duration of the warranty mentioned in the text unless the metadata has one already.
+///
+/// Returns true if the warranty has been proposed.
+pub fn suggest(metadata: &mut Metadata, text: &str) -> bool {
+    if metadata.properties.contains_key(WARRANTY) {
+        return false;
+    }
+
+    return match detect(text) {
+        Some(warranty) => {
+            metadata.properties.insert(String::from(WARRANTY), PropertyValue::String(warranty.to_string()));
+            true
+        }
+        None => false,
+    };
+}
+
+/// Lists all inboxed and archived documents matching the predicate which are still under warranty at the given day.
+///
+/// Documents are ordered by the day their warranty ends.
+pub async fn covered(repository: &Repository,
+                     today: NaiveDate,
+                     predicate: impl Fn(&Metadata) -> bool) -> Result<Vec<(DocId, Metadata)>> {
+    let mut docs = Vec::new();
+
+    for bundle in repository.inbox().list().await? {
+        docs.push((*bundle.id(), bundle.read_metadata().await?));
+    }
+
+    for bundle in repository.archive().list().await? {
+        docs.push((*bundle.id(), bundle.read_metadata().await?));
+    }
+
+    let mut docs = docs.into_iter()
+        .filter(|(_, metadata)| expires(metadata).map_or(false, |expires| expires >= today))
+        .filter(|(_, metadata)| predicate(metadata))
+        .collect::<Vec<_>>();
+
+    docs.sort_by_key(|(_, metadata)| expires(metadata));
+
+    return Ok(docs);
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_expires() {
+        let mut metadata = Metadata::new();
+        assert_that!(expires(&metadata)).is_none();
+
+        metadata.properties.insert(String::from(PURCHASED), PropertyValue::Date(NaiveDate::from_ymd(2020, 2, 29)));
+        assert_that!(expires(&metadata)).is_none();
+
+        metadata.properties.insert(String::from(WARRANTY), PropertyValue::String(String::from("2 years")));
+        assert_that!(expires(&metadata)).is_equal_to(Some(NaiveDate::from_ymd(2022, 2, 28)));
+
+        metadata.properties.insert(String::from(WARRANTY), PropertyValue::Integer(6));
+        assert_that!(expires(&metadata)).is_equal_to(Some(NaiveDate::from_ymd(2020, 8, 29)));
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_that!(detect("Includes 2 years warranty")).is_equal_to(Some(Period::Years(2)));
+        assert_that!(detect("3-year limited guarantee")).is_none();
+        assert_that!(detect("3-year guarantee")).is_equal_to(Some(Period::Years(3)));
+        assert_that!(detect("Warranty: 24 months")).is_equal_to(Some(Period::Months(24)));
+        assert_that!(detect("warranty period of 12 months applies")).is_equal_to(Some(Period::Months(12)));
+        assert_that!(detect("Sie erhalten 2 Jahre Garantie auf dieses Gerät")).is_equal_to(Some(Period::Years(2)));
+        assert_that!(detect("Gewährleistung: 24 Monate")).is_equal_to(Some(Period::Months(24)));
+        assert_that!(detect("Total 24.99 EUR")).is_none();
+    }
+
+    #[test]
+    fn test_suggest() {
+        let mut metadata = Metadata::new();
+        assert_that!(suggest(&mut metadata, "2 years warranty")).is_true();
+        assert_that!(metadata.properties.get(WARRANTY)).is_equal_to(Some(&PropertyValue::String(String::from("2 years"))));
+
+        // Warranties set already are kept
+        assert_that!(suggest(&mut metadata, "6 months warranty")).is_false();
+        assert_that!(metadata.properties.get(WARRANTY)).is_equal_to(Some(&PropertyValue::String(String::from("2 years"))));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use acme_lib::{create_p384_key, Directory, DirectoryUrl};
+use acme_lib::persist::FilePersist;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{error, info, warn};
+use rocket::{get, Request, routes, State};
+use rocket::http::uri::Origin;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::Redirect;
+
+use crate::config::{Acme as Config, Tls};
+use crate::status::Status;
+
+/// Interval between checks whether the certificate is due for renewal
+const RENEWAL_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Delay between polls of the ACME server while waiting for validations and issuance in milliseconds
+const POLL_DELAY: u64 = 5000;
+
+/// Proofs of the pending HTTP-01 challenges by token.
+#[derive(Debug, Clone, Default)]
+pub struct Challenges(Arc<RwLock<HashMap<String, String>>>);
+
+impl Challenges {
+    pub(super) fn insert(&self, token: String, proof: String) {
+        self.0.write().expect("Challenges poisoned").insert(token, proof);
+    }
+
+    fn remove(&self, token: &str) {
+        self.0.write().expect("Challenges poisoned").remove(token);
+    }
+
+    fn get(&self, token: &str) -> Option<String> {
+        return self.0.read().expect("Challenges poisoned").get(token).cloned();
+    }
+}
+
+/// Obtains and renews the TLS certificate from an ACME server like Let's Encrypt.
+///
+/// Domains are validated using HTTP-01 challenges, which are answered by a plain HTTP server running alongside the web
+/// server. All other requests to the plain HTTP server are redirected to HTTPS. The web server loads the certificate
+/// on launch only, so renewed certificates are applied on the next restart.
+pub struct Acme {
+    config: Config,
+
+    challenges: Challenges,
+
+    status: Arc<Status>,
+}
+
+#[get("/.well-known/acme-challenge/<token>")]
+fn challenge(token: String, challenges: State<'_, Challenges>) -> Option<String> {
+    return challenges.get(&token);
+}
+
+#[get("/<_path..>", rank = 2)]
+fn redirect(_path: PathBuf, uri: &Origin<'_>, host: Option<Host>) -> Option<Redirect> {
+    return Some(Redirect::permanent(format!("https://{}{}", host?.0, uri)));
+}
+
+/// The host the client requested.
+struct Host(String);
+
+#[async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for Host {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        return match request.headers().get_one("Host") {
+            Some(host) => Outcome::Success(Host(host.to_string())),
+            None => Outcome::Forward(()),
+        };
+    }
+}
+
+/// Builds the plain HTTP server answering the challenges.
+pub fn server(address: &str, port: u16, challenges: Challenges) -> rocket::Rocket {
+    let figment = rocket::figment::Figment::from(rocket::Config::default())
+        .merge(("address", address))
+        .merge(("port", port));
+
+    return rocket::custom(figment)
+        .manage(challenges)
+        .mount("/", routes![challenge, redirect]);
+}
+
+/// Writes the certificate chain and private key to the paths the web server loads them from.
+fn store(config: &Config, certs: &str, key: &str) -> Result<()> {
+    let tls = Tls::Acme(config.clone());
+    std::fs::write(tls.certs(), certs)?;
+    std::fs::write(tls.key(), key)?;
+
+    return Ok(());
+}
+
+/// Obtains a certificate unless a valid one has been issued before.
+///
+/// Returns whether a new certificate has been issued.
+fn obtain(config: &Config, challenges: &Challenges) -> Result<bool> {
+    let (primary, alternatives) = config.domains.split_first()
+        .ok_or_else(|| anyhow!("No domains configured for ACME"))?;
+    let alternatives = alternatives.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let directory = Directory::from_url(FilePersist::new(&config.path), DirectoryUrl::Other(&config.directory))?;
+    let account = directory.account(&config.email)?;
+
+    if let Some(certificate) = account.certificate(primary)? {
+        if certificate.valid_days_left() > config.renew_days {
+            store(config, certificate.certificate(), certificate.private_key())?;
+            return Ok(false);
+        }
+    }
+
+    info!("Ordering certificate for {}", config.domains.join(", "));
+
+    let mut order = account.new_order(primary, &alternatives)?;
+    let csr = loop {
+        if let Some(csr) = order.confirm_validations() {
+            break csr;
+        }
+
+        for authorization in order.authorizations()? {
+            let challenge = authorization.http_challenge();
+            let token = challenge.http_token().to_string();
+
+            challenges.insert(token.clone(), challenge.http_proof());
+            let result = challenge.validate(POLL_DELAY);
+            challenges.remove(&token);
+
+            result?;
+        }
+
+        order.refresh()?;
+    };
+
+    let certificate = csr.finalize_pkey(create_p384_key(), POLL_DELAY)?
+        .download_and_save_cert()?;
+    store(config, certificate.certificate(), certificate.private_key())?;
+
+    info!("Obtained certificate for {} valid for {} days", config.domains.join(", "), certificate.valid_days_left());
+
+    return Ok(true);
+}
+
+impl Acme {
+    pub fn from_config(config: Config, status: Arc<Status>) -> Self {
+        return Self {
+            config,
+            challenges: Challenges::default(),
+            status,
+        };
+    }
+
+    /// Starts the plain HTTP server answering the challenges on the given address.
+    pub fn serve(&self, address: &str) {
+        let server = server(address, self.config.http_port, self.challenges.clone());
+        let status = self.status.clone();
+        tokio::spawn(async move {
+            if let Err(err) = server.launch().await {
+                error!("ACME challenge server failed: {}", err);
+                status.failed("acme", &anyhow!("Challenge server failed: {}", err));
+            }
+        });
+    }
+
+    /// Ensures a valid certificate exists, obtaining a new one if required.
+    ///
+    /// Returns whether a new certificate has been issued.
+    pub async fn obtain(&self) -> Result<bool> {
+        tokio::fs::create_dir_all(Path::new(&self.config.path)).await?;
+
+        // The ACME client is blocking
+        let config = self.config.clone();
+        let challenges = self.challenges.clone();
+        return tokio::task::spawn_blocking(move || obtain(&config, &challenges)).await?;
+    }
+
+    /// Renews the certificate before it expires.
+    pub async fn run(self) {
+        loop {
+            tokio::time::delay_for(RENEWAL_INTERVAL).await;
+
+            match self.obtain().await {
+                Ok(true) => warn!("Renewed certificate, restart to apply it"),
+                Ok(false) => {}
+                Err(err) => {
+                    error!("Failed to renew certificate: {:#}", err);
+                    self.status.failed("acme", &err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use rocket::{get, post, State};
+use rocket_contrib::json::Json;
+
+use crate::proto::api::admin::{Bundles, Disk, IndexInfo, Jobs, MaintenanceResponse, ReloadResponse, StatusResponse};
+use crate::reload::Reloader;
+use crate::repository::{Archived, Inboxed, Repository, Staging, Trashed};
+use crate::status::Status;
+
+use super::{ApiError, Token};
+
+#[get("/admin/status")]
+pub(super) async fn status(repository: &'_ Repository,
+                           status: State<'_, Arc<Status>>,
+                           _token: &'_ Token) -> Result<Json<StatusResponse>, ApiError> {
+    let staging = repository.count::<Staging>().await? as u64;
+
+    let path = repository.path().to_path_buf();
+    let (available, total) = tokio::task::spawn_blocking(move || -> std::io::Result<(u64, u64)> {
+        return Ok((fs2::available_space(&path)?, fs2::total_space(&path)?));
+    }).await.map_err(anyhow::Error::from)?.map_err(anyhow::Error::from)?;
+
+    Ok(Json(StatusResponse {
+        started: status.started(),
+        bundles: Bundles {
+            staging,
+            inbox: repository.count::<Inboxed>().await? as u64,
+            archive: repository.count::<Archived>().await? as u64,
+            trash: repository.count::<Trashed>().await? as u64,
+        },
+        jobs: Jobs {
+            queued: staging,
+            failed: status.failed_count(),
+        },
+        disk: Disk {
+            used: repository.disk_usage().await?,
+            available,
+            total,
+        },
+        index: IndexInfo {
+            last_update: status.last_indexed(),
+            journal_cursor: repository.journal().cursor().await,
+        },
+        last_backup: status.last_backup(),
+        errors: status.errors(),
+        failed_logins: status.failed_logins(),
+    }))
+}
+
+/// Lists the scheduled maintenance tasks along with their last runs.
+#[get("/admin/maintenance")]
+pub(super) async fn maintenance(status: State<'_, Arc<Status>>,
+                                _token: &'_ Token) -> Result<Json<MaintenanceResponse>, ApiError> {
+    Ok(Json(MaintenanceResponse {
+        tasks: status.tasks(),
+    }))
+}
+
+/// Reloads the juicer, the notification channels and the rules, like on SIGHUP.
+#[post("/admin/reload")]
+pub(super) async fn reload(reloader: State<'_, Arc<Reloader>>,
+                           _token: &'_ Token) -> Result<Json<ReloadResponse>, ApiError> {
+    Ok(Json(ReloadResponse {
+        reloaded: reloader.reload().await?,
+    }))
+}
@@ -1,81 +1,341 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use anyhow::anyhow;
-use rocket::{get, http::ContentType, State};
+use log::info;
+use rocket::{delete, get, post, State};
+use futures::TryStreamExt;
 use rocket::http::RawStr;
 use rocket::response::{Content, Stream};
 use rocket_contrib::json::Json;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
-use crate::index::Index;
-use crate::proto::api::archive::{BundleResponse, SearchResponse};
-use crate::proto::model::{DocId, Kind};
+use crate::crypto::{self, Keyring};
+use crate::mailer::Mailer;
+use crate::previews::Previews;
+use crate::proto::api::archive::{BrowseResponse, BundleResponse, SearchResponse};
+use crate::proto::api::inbox::GroupInfo;
+use crate::proto::api::mail::SendRequest;
+use crate::proto::api::undo::UndoInfo;
+use crate::proto::model::{DocId, DocInfo, Kind};
 use crate::repository::Repository;
+use crate::undo::{Action, Undo};
 
-use super::{ApiError, InternalError, Token};
+use super::{ApiError, ensure_visible, InternalError, listing, Namespace, ndjson, ScopedIndex, Token, undo};
+use super::ranges::{Conditions, Served};
 
 #[get("/archive/<id>")]
 pub(super) async fn bundle(id: &RawStr,
-                           repository: State<'_, Repository>,
-                           _token: &'_ Token) -> Result<Json<BundleResponse>, ApiError> {
+                           repository: &'_ Repository,
+                           namespace: State<'_, Namespace>,
+                           token: &'_ Token) -> Result<Json<BundleResponse>, ApiError> {
     let id = DocId::from_str(id.as_str())?;
 
     let bundle = repository.archive().get(id).await
         .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
 
     let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
 
     Ok(Json(BundleResponse {
-        doc: (id, metadata).into(),
+        doc: namespace.qualify((id, metadata).into()),
     }))
 }
 
+pub(super) async fn serve(id: &RawStr,
+                          kind: Kind,
+                          name: &str,
+                          repository: &Repository,
+                          keyring: &Keyring,
+                          conditions: &Conditions,
+                          token: &Token) -> Result<Served, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let bundle = repository.archive().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    let mut file = bundle.read(&kind).await
+        .map_err(InternalError)?
+        .ok_or_else(|| ApiError::not_found(format!("Fragment not found: {}/{}", id, name)))?;
+
+    // Decrypt fragments of encrypted documents, the metadata itself is never encrypted
+    let encrypted = crypto::is_sensitive(&kind);
+    let decrypted = if let Some(domain) = metadata.domain.as_ref().filter(|_| encrypted) {
+        let key = keyring.key(token.subject(), domain).await
+            .ok_or_else(|| ApiError::forbidden(format!("Encryption domain locked: {}", domain)))?;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await
+            .map_err(|err| InternalError(err.into()))?;
+
+        Some(crypto::decrypt(&key, &data)?)
+    } else {
+        None
+    };
+
+    return Ok(Served::fragment(&bundle, &kind, decrypted, conditions).await?);
+}
+
 #[get("/archive/<id>/<fragment>")]
 pub(super) async fn fragment(id: &RawStr,
                              fragment: String,
-                             repository: State<'_, Repository>,
-                             _token: &'_ Token) -> Result<Content<Stream<impl AsyncRead>>, ApiError> {
+                             repository: &'_ Repository,
+                             keyring: &'_ Keyring,
+                             conditions: Conditions,
+                             token: &'_ Token) -> Result<Served, ApiError> {
+    return serve(id, Kind::from(fragment.as_str()), &fragment, &repository, keyring, &conditions, token).await;
+}
+
+/// Sends a document by mail as PDF attachment along with a summary of its metadata.
+#[post("/archive/<id>/send", data = "<request>")]
+pub(super) async fn send(id: &RawStr,
+                         request: Json<SendRequest>,
+                         repository: &'_ Repository,
+                         keyring: &'_ Keyring,
+                         mailer: State<'_, Mailer>,
+                         token: &'_ Token) -> Result<(), ApiError> {
+    if !mailer.is_enabled() {
+        return Err(ApiError::bad_request(String::from("Sending mails is disabled")));
+    }
+
     let id = DocId::from_str(id.as_str())?;
-    let kind = Kind::from(fragment.as_str());
-
-    let content_type = match kind {
-        Kind::Document => ContentType::PDF,
-        Kind::Preview => ContentType::PNG,
-        Kind::Plaintext => ContentType::Plain,
-        Kind::Metadata => ContentType::JSON,
-        Kind::Other { .. } => ContentType::Any,
-    };
+
+    if !request.to.contains('@') {
+        return Err(ApiError::bad_request(format!("Invalid address: {}", request.to)));
+    }
 
     let bundle = repository.archive().get(id).await
         .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
 
-    let file = bundle.read(kind).await
+    let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    let mut data = Vec::new();
+    bundle.read(Kind::Document).await
         .map_err(InternalError)?
-        .ok_or_else(|| ApiError::not_found(format!("Fragment not found: {}/{}", id, fragment)))?;
+        .ok_or_else(|| ApiError::not_found(format!("Fragment not found: {}/document.pdf", id)))?
+        .read_to_end(&mut data).await
+        .map_err(|err| InternalError(err.into()))?;
+
+    // Documents are sent decrypted, so the domain must be unlocked
+    if let Some(domain) = &metadata.domain {
+        let key = keyring.key(token.subject(), domain).await
+            .ok_or_else(|| ApiError::forbidden(format!("Encryption domain locked: {}", domain)))?;
+
+        data = crypto::decrypt(&key, &data)?;
+    }
+
+    mailer.send(&request.to, request.message.as_deref(), id, &metadata, &data).await?;
 
-    return Ok(Content(content_type, file.into()));
+    info!("Sent document {} to {}", id, request.to);
+
+    return Ok(());
 }
 
-#[get("/archive?<query>")]
-pub(super) async fn search(query: &RawStr,
-                           index: State<'_, Box<dyn Index + Send + Sync>>,
-                           repository: State<'_, Repository>,
-                           _token: &'_ Token) -> Result<Json<SearchResponse>, ApiError> {
-    let response = index.search(query).await?;
+/// Serves the preview of a single page, counting from one, optionally scaled to the given size.
+#[get("/archive/<id>/preview/<page>?<size>")]
+pub(super) async fn page(id: &RawStr,
+                         page: u32,
+                         size: Option<u32>,
+                         repository: &'_ Repository,
+                         keyring: &'_ Keyring,
+                         previews: &'_ Previews,
+                         conditions: Conditions,
+                         token: &'_ Token) -> Result<Served, ApiError> {
+    if page == 0 {
+        return Err(ApiError::bad_request(String::from("Pages are counted from one")));
+    }
+
+    if let Some(size) = size {
+        let doc_id = DocId::from_str(id.as_str())?;
+        let bundle = repository.archive().get(doc_id).await
+            .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", doc_id)))?;
+
+        let metadata = bundle.read_metadata().await?;
+        ensure_visible(doc_id, &metadata, token)?;
 
+        // Encrypted documents are rendered from the decrypted document, like the fragments are served decrypted
+        let decrypted = match &metadata.domain {
+            Some(domain) => {
+                let key = keyring.key(token.subject(), domain).await
+                    .ok_or_else(|| ApiError::forbidden(format!("Encryption domain locked: {}", domain)))?;
+
+                let mut data = Vec::new();
+                bundle.read(Kind::Document).await
+                    .map_err(InternalError)?
+                    .ok_or_else(|| ApiError::not_found(format!("Fragment not found: {}/document.pdf", doc_id)))?
+                    .read_to_end(&mut data).await
+                    .map_err(|err| InternalError(err.into()))?;
+
+                Some(crypto::decrypt(&key, &data)?)
+            }
+            None => None,
+        };
+
+        return Served::preview(&bundle, page, size, decrypted, previews, &conditions).await;
+    }
+
+    return serve(id, Kind::Page(page), &format!("preview/{}", page), &repository, keyring, &conditions, token).await;
+}
+
+#[delete("/archive/<id>")]
+pub(super) async fn delete(id: &RawStr,
+                           repository: &'_ Repository,
+                           buffer: &'_ Undo,
+                           token: &'_ Token) -> Result<Json<UndoInfo>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.archive().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    ensure_visible(id, &bundle.read_metadata().await?, token)?;
+
+    bundle.delete().await?;
+
+    return Ok(Json(undo::record(&buffer, token, vec![Action::Restore(id)]).await));
+}
+
+#[get("/archive?<query>&<label>&<from>&<to>&<sort>&<offset>&<limit>", rank = 2)]
+pub(super) async fn search(query: Option<String>,
+                           label: Option<String>,
+                           from: Option<String>,
+                           to: Option<String>,
+                           sort: Option<String>,
+                           offset: Option<usize>,
+                           limit: Option<usize>,
+                           index: ScopedIndex,
+                           repository: &'_ Repository,
+                           namespace: State<'_, Namespace>,
+                           token: &'_ Token) -> Result<Json<SearchResponse>, ApiError> {
+    let query = listing::query(query, label, from, to)?;
+    let listing = listing::listing(sort, offset, limit)?;
+
+    let response = index.search(&query, &listing).await?;
+
+    return visible(response, &repository, &namespace, token).await.map(Json);
+}
+
+/// Finds archived documents similar to the given one, like last year's version of a recurring invoice or contract.
+#[get("/archive/<id>/similar?<offset>&<limit>")]
+pub(super) async fn similar(id: &RawStr,
+                            offset: Option<usize>,
+                            limit: Option<usize>,
+                            index: ScopedIndex,
+                            repository: &'_ Repository,
+                            namespace: State<'_, Namespace>,
+                            token: &'_ Token) -> Result<Json<SearchResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let bundle = repository.archive().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    let listing = listing::listing(None, offset, limit)?;
+
+    let response = index.similar(&id, &listing).await?;
+
+    return visible(response, &repository, &namespace, token).await.map(Json);
+}
+
+/// Resolves the documents found by the index while dropping the ones hidden from the requesting user.
+async fn visible(response: crate::index::SearchResponse,
+                 repository: &Repository,
+                 namespace: &Namespace,
+                 token: &Token) -> Result<SearchResponse, ApiError> {
     // TODO: Can this be a done as stream?
     let mut docs = Vec::new();
+    let mut snippets = HashMap::new();
+    let mut hidden = 0;
     for id in response.docs {
         let bundle = repository.archive().get(id).await
             .ok_or_else(|| anyhow!("Bundle missing: {}", id))?;
 
         let metadata = bundle.read_metadata().await?;
+        if !metadata.is_visible_to(token.subject()) {
+            hidden += 1;
+            continue;
+        }
 
-        docs.push((*bundle.id(), metadata).into());
+        if let Some(found) = response.snippets.get(&id) {
+            snippets.insert(id, found.clone());
+        }
+
+        docs.push(namespace.qualify((*bundle.id(), metadata).into()));
     }
 
-    Ok(Json(SearchResponse {
-        count: response.count,
+    // The index is not aware of ownership, so the total count only accounts for hidden documents on this page
+    return Ok(SearchResponse {
+        count: response.count.saturating_sub(hidden),
         docs,
+        snippets,
+    });
+}
+
+/// Browses the archive by the metadata of the documents, i.e. to navigate the archive in the UI.
+///
+/// Unlike the search, the listing does not depend on the index, so it is always complete and the total count accounts
+/// for the documents hidden from the requesting user.
+#[get("/archive/browse?<query>&<label>&<labels>&<correspondent>&<property>&<from>&<to>&<sort>&<group>&<offset>&<limit>")]
+pub(super) async fn browse(query: Option<String>,
+                           label: Option<String>,
+                           labels: Option<String>,
+                           correspondent: Option<String>,
+                           property: Option<String>,
+                           from: Option<String>,
+                           to: Option<String>,
+                           sort: Option<String>,
+                           group: Option<String>,
+                           offset: Option<usize>,
+                           limit: Option<usize>,
+                           repository: &'_ Repository,
+                           namespace: State<'_, Namespace>,
+                           token: &'_ Token) -> Result<Json<BrowseResponse>, ApiError> {
+    let query = listing::query(query, label, from, to)?;
+    let query = listing::browse(query, labels, correspondent, property)?;
+    let listing = listing::listing(sort, offset, limit)?
+        .grouped(listing::grouping(group)?);
+
+    let page = repository.archive().query(&listing, |metadata| {
+        metadata.is_visible_to(token.subject()) && metadata.matches(&query)
+    }).await?;
+
+    Ok(Json(BrowseResponse {
+        count: page.total as u64,
+        docs: page.items.into_iter()
+            .map(|(bundle, metadata)| namespace.qualify((*bundle.id(), metadata).into()))
+            .collect(),
+        groups: page.groups.into_iter()
+            .map(|(key, count)| GroupInfo { key, count: count as u64 })
+            .collect(),
     }))
 }
+
+/// Streams all archived documents matching the query as newline delimited JSON.
+///
+/// Unlike the search, the documents are matched against their metadata only and listed in natural order without
+/// pagination, so arbitrarily large listings are not bounded by the index.
+#[get("/archive?<query>&<label>&<from>&<to>", format = "application/x-ndjson")]
+pub(super) async fn streamed<'r>(query: Option<String>,
+                                 label: Option<String>,
+                                 from: Option<String>,
+                                 to: Option<String>,
+                                 repository: &'r Repository,
+                                 namespace: State<'r, Namespace>,
+                                 token: &'_ Token) -> Result<Content<Stream<impl AsyncRead + 'r>>, ApiError> {
+    let query = listing::query(query, label, from, to)?;
+    let subject = token.subject().to_string();
+    let namespace = namespace.inner();
+
+    let docs = repository.archive().stream().await?
+        .try_filter(move |(_, metadata)| futures::future::ready(
+            metadata.is_visible_to(&subject) && metadata.matches(&query)))
+        .map_ok(move |(bundle, metadata)| namespace.qualify(DocInfo::from((*bundle.id(), metadata))));
+
+    return Ok(ndjson::respond(docs));
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use log::info;
+use rocket::{Data, post, State};
+use rocket::data::ToByteUnit;
+use rocket::http::{ContentType, RawStr};
+use rocket_contrib::json::Json;
+use tokio::io::AsyncReadExt;
+
+use crate::attachments;
+use crate::meta::Metadata;
+use crate::mimetype;
+use crate::proto::api::attachments::AttachResponse;
+use crate::proto::model::{DocId, Kind};
+use crate::quota::Quotas;
+use crate::repository::{kind_of, Repository, Version};
+use crate::transcription::Transcriber;
+
+use super::{ApiError, ensure_visible, ScopedIndex, Token};
+
+/// Checks that the attachment does not replace any other fragment and that audio is in a format which can be played
+/// back from its fragment.
+fn validate(name: &str, content_type: Option<&ContentType>) -> Result<(), ApiError> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') || Transcriber::is_transcript(name) || attachments::is_text(name) || Version::is_fragment(name) {
+        return Err(ApiError::bad_request(format!("Invalid attachment name: {}", name)));
+    }
+
+    let replaces = !matches!(Kind::from(name), Kind::Other { .. }) || !matches!(kind_of(name), Kind::Other { .. });
+    if name.starts_with("original.") || replaces {
+        return Err(ApiError::bad_request(format!("Attachment would replace fragment: {}", name)));
+    }
+
+    // Audio must be named by its format, as it is played back by the format told by the name
+    let audio = content_type.map_or(false, |content_type| content_type.top() == "audio");
+    if audio != mimetype::of_name(name).map_or(false, |mimetype| mimetype.starts_with("audio/")) {
+        return Err(ApiError::bad_request(format!("Not an audio attachment: {} ({})", name,
+                                                 content_type.map_or_else(|| String::from("unknown"), ToString::to_string))));
+    }
+
+    return Ok(());
+}
+
+/// Derives the searchable text of an attachment stored at the given path.
+///
+/// Audio is transcribed if transcription is configured, the text of all other formats is extracted if possible. Returns
+/// the name of the fragment to store the text in along with the text.
+async fn derive(name: &str, data: &[u8], path: &Path, transcriber: &Transcriber) -> Result<Option<(String, String)>, ApiError> {
+    let mimetype = mimetype::of_name(name)
+        .or_else(|| mimetype::detect(data))
+        .unwrap_or(mimetype::UNKNOWN);
+
+    if mimetype.starts_with("audio/") {
+        return Ok(transcriber.transcribe(path).await?
+            .map(|text| (Transcriber::transcript(name), text)));
+    }
+
+    return Ok(attachments::extract(path, mimetype).await?
+        .map(|text| (attachments::text(name), text)));
+}
+
+fn ensure_unencrypted(id: DocId, metadata: &Metadata) -> Result<(), ApiError> {
+    if let Some(domain) = &metadata.domain {
+        return Err(ApiError::bad_request(format!("Bundle is encrypted for domain {}: {}", domain, id)));
+    }
+
+    return Ok(());
+}
+
+/// Attaches a supplementary file to an inboxed or archived document, i.e. an e-invoice, a signed contract or a
+/// dictated note.
+///
+/// The file is stored as fragment of the given name and served like any other fragment. Audio is transcribed if
+/// transcription is configured and the text of plain text, XML and PDF files is extracted. The text is stored next to
+/// the attachment and indexed along the plaintext of archived documents. Bundles of encryption domains can not be
+/// attached to, as their fragments are encrypted. Attachments count towards the quota of the owner of the document.
+#[post("/attachments/<id>/<name>", data = "<data>")]
+pub(super) async fn attach(id: &RawStr,
+                           name: String,
+                           data: Data,
+                           content_type: Option<&ContentType>,
+                           repository: &'_ Repository,
+                           transcriber: State<'_, Transcriber>,
+                           quotas: State<'_, Quotas>,
+                           index: ScopedIndex,
+                           token: &'_ Token) -> Result<Json<AttachResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    validate(&name, content_type)?;
+
+    let mut attachment = Vec::new();
+    data.open(256.mebibytes())
+        .read_to_end(&mut attachment).await
+        .context("Reading attachment")?;
+
+    let derived = if let Some(bundle) = repository.inbox().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        ensure_visible(id, &metadata, token)?;
+        ensure_unencrypted(id, &metadata)?;
+
+        let reservation = quotas.reserve(&repository, metadata.owner.as_deref(), attachment.len() as u64).await?;
+        bundle.replace(Kind::other(&name), &attachment).await?;
+        reservation.commit();
+
+        let derived = derive(&name, &attachment, &bundle.path_of(Kind::other(&name)), &transcriber).await?;
+        if let Some((fragment, text)) = &derived {
+            bundle.replace(Kind::other(fragment), text.as_bytes()).await?;
+        }
+
+        derived
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        ensure_visible(id, &metadata, token)?;
+        ensure_unencrypted(id, &metadata)?;
+
+        let reservation = quotas.reserve(&repository, metadata.owner.as_deref(), attachment.len() as u64).await?;
+        bundle.replace(Kind::other(&name), &attachment).await?;
+        reservation.commit();
+
+        let derived = derive(&name, &attachment, &bundle.path_of(Kind::other(&name)), &transcriber).await?;
+        if let Some((fragment, text)) = &derived {
+            bundle.replace(Kind::other(fragment), text.as_bytes()).await?;
+            index.index(&bundle).await?;
+        }
+
+        derived
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    };
+
+    info!("Attached {} to bundle {}", name, id);
+
+    let derived = derived.map(|(fragment, _)| fragment);
+
+    Ok(Json(AttachResponse {
+        transcript: derived.clone().filter(|fragment| Transcriber::is_transcript(fragment)),
+        text: derived.filter(|fragment| attachments::is_text(fragment)),
+        fragment: name,
+    }))
+}
@@ -0,0 +1,33 @@
+use rocket::get;
+use rocket::http::ContentType;
+use rocket::response::Content;
+use rocket_contrib::json::Json;
+
+use crate::attestation;
+use crate::proto::api::attestations::ListResponse;
+use crate::repository::Repository;
+
+use super::{ApiError, Token};
+
+#[get("/attestations")]
+pub(super) async fn list(repository: &'_ Repository,
+                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    Ok(Json(ListResponse {
+        attestations: attestation::list(&repository).await?,
+    }))
+}
+
+/// Returns a manifest exactly as signed, so the signature can be verified against the response.
+#[get("/attestations/<name>")]
+pub(super) async fn get(name: String,
+                        repository: &'_ Repository,
+                        _token: &'_ Token) -> Result<Content<Vec<u8>>, ApiError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(ApiError::bad_request(format!("Invalid attestation: {}", name)));
+    }
+
+    let (manifest, _) = attestation::read(&repository, &name).await
+        .map_err(|_| ApiError::not_found(format!("Attestation not found: {}", name)))?;
+
+    return Ok(Content(ContentType::JSON, manifest));
+}
@@ -1,15 +1,22 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use log::info;
-use rocket::{Data, post, Request, Response, State};
+use log::{info, warn};
+use rocket::{Data, delete, get, post, Request, Response, State};
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::http::{Header, Status};
+use rocket::http::{ContentType, Header, Method, Status};
 use rocket::request::{FromRequest, Outcome};
 use rocket_contrib::json::Json;
 
-use crate::auth::Authenticator;
+use crate::auth::{ApiToken, Authenticator, Device, Login, Passkey};
 pub use crate::auth::Token;
-use crate::proto::api::auth::AuthRequest;
-use crate::utils::StrExt;
+use crate::meta::Metadata;
+use crate::proto::model::DocId;
+use crate::proto::api::auth::{AuthRequest, CodeRequest, ConfirmPasskeyRequest, ConfirmResponse, CreateTokenRequest, CreateTokenResponse, EnrollResponse, PasskeyInfo, PasskeysResponse, RegisterPasskeyResponse, Scope, SecondFactorResponse, SessionInfo, SessionsResponse, TokenInfo, TokensResponse};
+use crate::web::proxy::Forwarded;
+
+use super::{ApiError, versions};
 
 pub struct Authorization {}
 
@@ -25,32 +32,12 @@ impl Fairing for Authorization {
     async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data) {
         request.local_cache_async::<Option<Token>, _>(async {
             let auth = request
-                .guard::<State<'_, Authenticator>>()
+                .guard::<State<'_, Arc<Authenticator>>>()
                 .await
                 .expect("No Authenticator");
 
             let header = request.headers().get_one("Authorization")?;
-            let (kind, payload) = header.split2(' ')?;
-
-            match kind {
-                "Bearer" => {
-                    let token = auth.verify_token(payload).await.ok()?;
-                    return Some(token);
-                }
-
-                "Basic" => {
-                    let payload = base64::decode(payload).ok()?;
-                    let payload = String::from_utf8(payload).ok()?;
-
-                    let (username, password) = payload.split2(':')?;
-                    let token = auth.verify_key(username, password).await?;
-                    return Some(token);
-                }
-
-                _ => {
-                    return None;
-                }
-            }
+            return auth.authorize(header).await;
         }).await;
     }
 
@@ -59,8 +46,9 @@ impl Fairing for Authorization {
             return None;
         }).await;
 
-        if let Some(token) = token {
-            let auth = request.guard::<State<'_, Authenticator>>().await
+        // Only session tokens are renewed, API keys and tokens are sent with every request
+        if let Some(token) = token.as_ref().filter(|token| token.session().is_some()) {
+            let auth = request.guard::<State<'_, Arc<Authenticator>>>().await
                 .expect("No Authenticator");
 
             let bearer = auth.sign_token(token).await
@@ -81,27 +69,306 @@ impl<'a, 'r> FromRequest<'a, 'r> for &'a Token {
         }).await;
 
         match token {
-            Some(token) => Outcome::Success(token),
+            Some(token) if token.permits(scope(request)) => Outcome::Success(token),
+            Some(_) => Outcome::Failure((Status::Forbidden, ())),
             None => Outcome::Failure((Status::Unauthorized, ())),
         }
     }
 }
 
+/// Determines the scope required to access the requested endpoint.
+fn scope(request: &Request<'_>) -> Scope {
+    let path = request.uri().path();
+
+    // Short links for paper codes are served outside of the API mount point
+    if request.method() == Method::Get && path.starts_with("/d/") {
+        return Scope::Read;
+    }
+
+    let segments = path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .skip(1) // The API mount point
+        .collect::<Vec<_>>();
+
+    // Skip the version if given, as the scopes apply to all versions alike
+    let segments = match segments.split_first() {
+        Some((version, rest)) if versions::parse(version).is_some() => rest,
+        _ => segments.as_slice(),
+    };
+
+    return match (request.method(), segments) {
+        (Method::Post, ["upload"]) | (Method::Post, ["uploads"]) | (Method::Get, ["uploads", _]) | (Method::Patch, ["uploads", _]) | (Method::Delete, ["uploads", _]) | (Method::Post, ["scans"]) | (Method::Post, ["scans", _]) | (Method::Post, ["scans", _, "pages"]) | (Method::Delete, ["scans", _]) | (Method::Get, ["queue", _]) => Scope::Upload,
+        (Method::Post, ["filing", "sheet"]) => Scope::Read,
+        (Method::Get, ["inbox"]) | (Method::Get, ["archive"]) | (Method::Get, ["archive", "browse"]) | (Method::Get, ["archive", _, "similar"]) | (Method::Get, ["labels"]) | (Method::Get, ["correspondents"]) | (Method::Get, ["stats"]) | (Method::Get, ["stats", "calendar"]) | (Method::Get, ["stats", "dashboard"]) | (Method::Get, ["stats", "quota"]) | (Method::Get, ["due"]) | (Method::Get, ["confidence"]) | (Method::Get, ["contracts"]) | (Method::Get, ["warranties"]) | (Method::Get, ["repositories"]) | (Method::Post, ["graphql"]) | (Method::Get, ["changes"]) | (Method::Get, ["persons"]) | (Method::Get, ["checklists"]) | (Method::Get, ["checklists", _, "progress"]) => Scope::Search,
+        (Method::Get, ["inbox", ..]) | (Method::Get, ["archive", ..]) | (Method::Get, ["fragments", ..]) | (Method::Get, ["documents", _, "versions", ..]) | (Method::Get, ["history", _]) | (Method::Get, ["revisions", _]) | (Method::Get, ["relations", _]) | (Method::Get, ["resolve", _]) | (Method::Get, ["correspondents", _]) | (Method::Get, ["persons", _]) | (Method::Get, ["checklists", _]) | (Method::Get, ["suggestions", _]) | (Method::Post, ["archive", _, "send"]) => Scope::Read,
+        _ => Scope::Admin,
+    };
+}
+
+#[async_trait::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for Device {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        // The address is taken from trusted reverse proxies, as login attempts are throttled by it
+        let forwarded = request.guard::<Forwarded>().await
+            .expect("No Forwarded");
+
+        Outcome::Success(Device {
+            user_agent: request.headers().get_one("User-Agent").map(String::from),
+            address: forwarded.client.map(|ip| ip.to_string()),
+        })
+    }
+}
+
+/// Hides documents which are neither owned by nor shared with the authenticated user.
+pub(super) fn ensure_visible(id: DocId, metadata: &Metadata, token: &Token) -> Result<(), ApiError> {
+    if !metadata.is_visible_to(token.subject()) {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    }
+
+    return Ok(());
+}
+
 #[post("/auth/login", data = "<request>")]
-pub(super) async fn login(auth: State<'_, Authenticator>,
-                          request: Json<AuthRequest>) -> Response<'_> {
-    if let Some(token) = auth.login(&request.password).await {
-        info!("Login successful");
-
-        let bearer = auth.sign_token(&token).await.expect("Can not sign token");
-
-        return Response::build()
-            .header(Header::new("Authorization", bearer))
-            .status(Status::Accepted)
-            .finalize();
-    } else {
-        return Response::build()
-            .status(Status::BadRequest)
-            .finalize();
+pub(super) async fn login(auth: State<'_, Arc<Authenticator>>,
+                          status: State<'_, Arc<crate::status::Status>>,
+                          device: Device,
+                          request: Json<AuthRequest>) -> Result<Response<'_>, ApiError> {
+    match auth.login(&request.password, request.code.as_deref(), request.assertion.as_ref(), device.clone()).await? {
+        Login::Success(token) => {
+            info!("Login successful");
+
+            let bearer = auth.sign_token(&token).await.expect("Can not sign token");
+
+            return Ok(Response::build()
+                .header(Header::new("Authorization", bearer))
+                .status(Status::Accepted)
+                .finalize());
+        }
+
+        Login::CodeRequired(challenge) => {
+            let body = serde_json::to_vec(&SecondFactorResponse { challenge }).expect("Challenge not serializable");
+
+            return Ok(Response::build()
+                .header(ContentType::JSON)
+                .sized_body(body.len(), Cursor::new(body))
+                .status(Status::Unauthorized)
+                .finalize());
+        }
+
+        Login::Failed => {
+            warn!("Login failed from {}", device.address.as_deref().unwrap_or("unknown address"));
+            status.login_failed(&device, false);
+
+            return Ok(Response::build()
+                .status(Status::BadRequest)
+                .finalize());
+        }
+
+        Login::Locked(remaining) => {
+            warn!("Login from {} locked for {}s", device.address.as_deref().unwrap_or("unknown address"), remaining.as_secs());
+            status.login_failed(&device, true);
+
+            return Ok(Response::build()
+                .header(Header::new("Retry-After", remaining.as_secs().to_string()))
+                .status(Status::TooManyRequests)
+                .finalize());
+        }
+    }
+}
+
+#[post("/auth/2fa")]
+pub(super) async fn enroll(auth: State<'_, Arc<Authenticator>>,
+                           token: &'_ Token) -> Result<Json<EnrollResponse>, ApiError> {
+    if auth.two_factor().is_enabled(token.subject()).await {
+        return Err(ApiError::conflict(String::from("Two-factor authentication already enabled")));
+    }
+
+    let pending = auth.two_factor().enroll(token.subject()).await?;
+
+    Ok(Json(EnrollResponse {
+        secret: pending.secret,
+        uri: pending.uri,
+    }))
+}
+
+#[post("/auth/2fa/confirm", data = "<request>")]
+pub(super) async fn confirm(request: Json<CodeRequest>,
+                            auth: State<'_, Arc<Authenticator>>,
+                            token: &'_ Token) -> Result<Json<ConfirmResponse>, ApiError> {
+    let recovery_codes = auth.two_factor().confirm(token.subject(), &request.code).await?
+        .ok_or_else(|| ApiError::bad_request(String::from("Invalid code")))?;
+
+    info!("Enabled two-factor authentication for {}", token.subject());
+
+    Ok(Json(ConfirmResponse { recovery_codes }))
+}
+
+#[delete("/auth/2fa", data = "<request>")]
+pub(super) async fn disable(request: Json<CodeRequest>,
+                            auth: State<'_, Arc<Authenticator>>,
+                            token: &'_ Token) -> Result<(), ApiError> {
+    if !auth.two_factor().verify(token.subject(), &request.code).await? {
+        return Err(ApiError::bad_request(String::from("Invalid code")));
     }
+
+    auth.two_factor().disable(token.subject()).await?;
+    auth.passkeys().clear(token.subject()).await?;
+
+    info!("Disabled two-factor authentication for {}", token.subject());
+
+    return Ok(());
+}
+
+#[post("/auth/passkeys")]
+pub(super) async fn register_passkey(auth: State<'_, Arc<Authenticator>>,
+                                     token: &'_ Token) -> Result<Json<RegisterPasskeyResponse>, ApiError> {
+    if !auth.passkeys().is_configured() {
+        return Err(ApiError::not_found(String::from("Passkeys not configured")));
+    }
+
+    // Passkeys replace the code on login, so the recovery codes of the enrollment stay available
+    if !auth.two_factor().is_enabled(token.subject()).await {
+        return Err(ApiError::conflict(String::from("Two-factor authentication not enabled")));
+    }
+
+    let options = auth.passkeys().register(token.subject()).await?;
+
+    Ok(Json(RegisterPasskeyResponse { options }))
+}
+
+#[post("/auth/passkeys/confirm", data = "<request>")]
+pub(super) async fn confirm_passkey(request: Json<ConfirmPasskeyRequest>,
+                                    auth: State<'_, Arc<Authenticator>>,
+                                    token: &'_ Token) -> Result<(), ApiError> {
+    let request = request.into_inner();
+    if request.name.is_empty() {
+        return Err(ApiError::bad_request(String::from("Passkey name must not be empty")));
+    }
+
+    if !auth.passkeys().confirm(token.subject(), request.name, request.credential).await? {
+        return Err(ApiError::bad_request(String::from("Invalid credential")));
+    }
+
+    info!("Registered passkey for {}", token.subject());
+
+    return Ok(());
+}
+
+fn passkey_info(passkey: Passkey) -> PasskeyInfo {
+    return PasskeyInfo {
+        id: passkey.id(),
+        name: passkey.name,
+        created: passkey.created,
+        last_used: passkey.last_used,
+    };
+}
+
+#[get("/auth/passkeys")]
+pub(super) async fn passkeys(auth: State<'_, Arc<Authenticator>>,
+                             token: &'_ Token) -> Json<PasskeysResponse> {
+    let passkeys = auth.passkeys().list(token.subject()).await.into_iter()
+        .map(passkey_info)
+        .collect();
+
+    Json(PasskeysResponse { passkeys })
+}
+
+#[delete("/auth/passkeys/<id>")]
+pub(super) async fn remove_passkey(id: String,
+                                   auth: State<'_, Arc<Authenticator>>,
+                                   token: &'_ Token) -> Result<(), ApiError> {
+    if !auth.passkeys().remove(token.subject(), &id).await? {
+        return Err(ApiError::not_found(format!("Passkey not found: {}", id)));
+    }
+
+    info!("Removed passkey {} of {}", id, token.subject());
+
+    return Ok(());
+}
+
+fn token_info(token: ApiToken) -> TokenInfo {
+    return TokenInfo {
+        id: token.id,
+        name: token.name,
+        created: token.created,
+        scopes: token.scopes,
+        last_used: token.last_used,
+    };
+}
+
+#[get("/auth/tokens")]
+pub(super) async fn tokens(auth: State<'_, Arc<Authenticator>>,
+                           _token: &'_ Token) -> Json<TokensResponse> {
+    let tokens = auth.api_tokens().await.into_iter()
+        .map(token_info)
+        .collect();
+
+    Json(TokensResponse { tokens })
+}
+
+#[post("/auth/tokens", data = "<request>")]
+pub(super) async fn create_token(auth: State<'_, Arc<Authenticator>>,
+                                 request: Json<CreateTokenRequest>,
+                                 _token: &'_ Token) -> Result<Json<CreateTokenResponse>, ApiError> {
+    let request = request.into_inner();
+    if request.name.is_empty() {
+        return Err(ApiError::bad_request(String::from("Token name must not be empty")));
+    }
+
+    if request.scopes.is_empty() {
+        return Err(ApiError::bad_request(String::from("Token must be granted at least one scope")));
+    }
+
+    let (info, token) = auth.create_api_token(request.name, request.scopes).await?;
+    info!("Created API token {} ({})", info.name, info.id);
+
+    Ok(Json(CreateTokenResponse {
+        info: token_info(info),
+        token,
+    }))
+}
+
+#[delete("/auth/tokens/<id>")]
+pub(super) async fn revoke_token(id: String,
+                                 auth: State<'_, Arc<Authenticator>>,
+                                 _token: &'_ Token) -> Result<(), ApiError> {
+    if !auth.revoke_api_token(&id).await? {
+        return Err(ApiError::not_found(format!("Token not found: {}", id)));
+    }
+
+    info!("Revoked API token {}", id);
+
+    return Ok(());
+}
+
+#[get("/auth/sessions")]
+pub(super) async fn sessions(auth: State<'_, Arc<Authenticator>>,
+                             token: &'_ Token) -> Json<SessionsResponse> {
+    let sessions = auth.sessions().list(token.subject()).await.into_iter()
+        .map(|session| SessionInfo {
+            current: token.session() == Some(session.id.as_str()),
+            id: session.id,
+            created: session.created,
+            last_seen: session.last_seen,
+            user_agent: session.device.user_agent,
+            address: session.device.address,
+        })
+        .collect();
+
+    Json(SessionsResponse { sessions })
+}
+
+#[delete("/auth/sessions/<id>")]
+pub(super) async fn revoke_session(id: String,
+                                   auth: State<'_, Arc<Authenticator>>,
+                                   token: &'_ Token) -> Result<(), ApiError> {
+    if !auth.sessions().revoke(token.subject(), &id).await? {
+        return Err(ApiError::not_found(format!("Session not found: {}", id)));
+    }
+
+    info!("Revoked session {}", id);
+
+    return Ok(());
 }
@@ -0,0 +1,300 @@
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use rocket::{post, State};
+use rocket::http::ContentType;
+use rocket::response::{Content, Stream};
+use rocket_contrib::json::Json;
+use tokio::io::AsyncRead;
+
+use crate::crypto::Keyring;
+use crate::filing::Filing;
+use crate::meta::Metadata;
+use crate::proto::api::bulk::{BulkRequest, BulkResponse, DocResult, Operation, Progress};
+use crate::proto::model::DocId;
+use crate::repository::{Archived, Bundle, Inboxed, Repository};
+use crate::suggester::Suggester;
+use crate::undo::{Action, Undo};
+
+use super::{ApiError, ensure_visible, Token, undo};
+use super::inbox::archive_bundle;
+
+/// A bundle targeted by a bulk operation.
+enum Target<'r> {
+    Inbox(Bundle<'r, Inboxed>, Metadata),
+    Archive(Bundle<'r, Archived>, Metadata),
+}
+
+async fn resolve<'r>(repository: &'r Repository,
+                     id: DocId,
+                     operation: &Operation,
+                     keyring: &Keyring,
+                     token: &Token) -> Result<Target<'r>, ApiError> {
+    let target = if let Some(bundle) = repository.inbox().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        Target::Inbox(bundle, metadata)
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        Target::Archive(bundle, metadata)
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    };
+
+    match &target {
+        Target::Inbox(_, metadata) | Target::Archive(_, metadata) => ensure_visible(id, metadata, token)?,
+    }
+
+    match (operation, &target) {
+        (Operation::Archive, Target::Archive(..)) => {
+            return Err(ApiError::conflict(format!("Bundle already archived: {}", id)));
+        }
+
+        (Operation::Archive, Target::Inbox(_, metadata)) => {
+            if metadata.proposal.is_some() {
+                return Err(ApiError::conflict(format!("Proposal pending: {}", id)));
+            }
+
+            if let Some(domain) = keyring.domain_of(metadata) {
+                if keyring.key(token.subject(), domain).await.is_none() {
+                    return Err(ApiError::forbidden(format!("Encryption domain locked: {}", domain)));
+                }
+            }
+        }
+
+        _ => {}
+    }
+
+    return Ok(target);
+}
+
+fn update(metadata: &mut Metadata, operation: &Operation) {
+    match operation {
+        Operation::AddLabel { label } => {
+            metadata.labels.insert(label.clone());
+        }
+        Operation::RemoveLabel { label } => {
+            metadata.labels.remove(label);
+        }
+        Operation::SetProperty { key, value: Some(value) } => {
+            metadata.properties.insert(key.clone(), value.clone());
+        }
+        Operation::SetProperty { key, value: None } => {
+            metadata.properties.remove(key);
+        }
+        Operation::Archive | Operation::Delete => {}
+    }
+}
+
+/// Applies the operation to a single document and returns the action reverting it.
+async fn apply(id: DocId,
+               target: Target<'_>,
+               operation: &Operation,
+               suggester: &(dyn Suggester + Send + Sync),
+               keyring: &Keyring,
+               filing: &Filing,
+               token: &Token) -> Result<Action, ApiError> {
+    return match (operation, target) {
+        (Operation::Archive, Target::Inbox(bundle, mut metadata)) => {
+            let previous = metadata.clone();
+            metadata.archived = Some(Utc::now());
+            archive_bundle(bundle, metadata, suggester, keyring, filing, token).await?;
+            Ok(Action::Unarchive(id, previous))
+        }
+        (Operation::Archive, Target::Archive(..)) => unreachable!("Rejected on validation"),
+
+        (Operation::Delete, Target::Inbox(bundle, _)) => {
+            bundle.delete().await?;
+            Ok(Action::Restore(id))
+        }
+        (Operation::Delete, Target::Archive(bundle, _)) => {
+            bundle.delete().await?;
+            Ok(Action::Restore(id))
+        }
+
+        (operation, Target::Inbox(bundle, mut metadata)) => {
+            let previous = metadata.clone();
+            update(&mut metadata, operation);
+            bundle.write_metadata(&metadata).await?;
+            Ok(Action::Revert(id, previous))
+        }
+        (operation, Target::Archive(bundle, mut metadata)) => {
+            let previous = metadata.clone();
+            update(&mut metadata, operation);
+            bundle.write_metadata(&metadata).await?;
+            Ok(Action::Revert(id, previous))
+        }
+    };
+}
+
+/// Applies an operation to multiple documents.
+///
+/// All documents are validated first and nothing is changed if any of them can not be processed. Failures while
+/// applying the operation are reported per document but do not roll back the documents already processed. The
+/// documents processed successfully can be reverted using the returned undo handle.
+#[post("/bulk", data = "<request>")]
+pub(super) async fn bulk(request: Json<BulkRequest>,
+                         repository: &'_ Repository,
+                         suggester: State<'_, Box<dyn Suggester + Send + Sync>>,
+                         keyring: &'_ Keyring,
+                         filing: &'_ Filing,
+                         buffer: &'_ Undo,
+                         token: &'_ Token) -> Json<BulkResponse> {
+    let request = request.into_inner();
+
+    let repository = repository.acting_as(token.subject());
+
+    let mut targets = Vec::with_capacity(request.ids.len());
+    let mut results = Vec::with_capacity(request.ids.len());
+    for id in &request.ids {
+        match resolve(&repository, *id, &request.operation, keyring, token).await {
+            Ok(target) => {
+                targets.push((*id, target));
+                results.push(DocResult { id: *id, error: None });
+            }
+            Err(err) => results.push(DocResult { id: *id, error: Some(err.to_string()) }),
+        }
+    }
+
+    if results.iter().any(|result| result.error.is_some()) {
+        return Json(BulkResponse { applied: false, results, undo: None });
+    }
+
+    let mut results = Vec::with_capacity(targets.len());
+    let mut actions = Vec::with_capacity(targets.len());
+    for (id, target) in targets {
+        let error = match apply(id, target, &request.operation, suggester.as_ref(), keyring, filing, token).await {
+            Ok(action) => {
+                actions.push(action);
+                None
+            }
+            Err(err) => Some(err.to_string()),
+        };
+
+        results.push(DocResult { id, error });
+    }
+
+    Json(BulkResponse { applied: true, results, undo: Some(undo::record(&buffer, token, actions).await) })
+}
+
+/// A bulk operation processing one document at a time.
+struct Run<'r> {
+    repository: Repository,
+    operation: Operation,
+    ids: std::vec::IntoIter<DocId>,
+
+    total: usize,
+    done: usize,
+    failed: usize,
+    actions: Vec<Action>,
+    finished: bool,
+
+    suggester: &'r (dyn Suggester + Send + Sync),
+    keyring: &'r Keyring,
+    filing: &'r Filing,
+    buffer: &'r Undo,
+    token: Token,
+}
+
+impl Run<'_> {
+    /// Processes the next document and reports the progress, or reports the end of the operation after the last one.
+    async fn next(&mut self) -> Option<Progress> {
+        if let Some(id) = self.ids.next() {
+            // Documents may have changed since validation, so they are resolved again
+            let result = match resolve(&self.repository, id, &self.operation, self.keyring, &self.token).await {
+                Ok(target) => apply(id, target, &self.operation, self.suggester, self.keyring, self.filing, &self.token).await,
+                Err(err) => Err(err),
+            };
+
+            let error = match result {
+                Ok(action) => {
+                    self.actions.push(action);
+                    None
+                }
+                Err(err) => {
+                    self.failed += 1;
+                    Some(err.to_string())
+                }
+            };
+
+            self.done += 1;
+
+            return Some(Progress::Processed { id, error, done: self.done, total: self.total });
+        }
+
+        if self.finished {
+            return None;
+        }
+
+        self.finished = true;
+
+        let undo = undo::record(self.buffer, &self.token, std::mem::take(&mut self.actions)).await;
+
+        return Some(Progress::Finished { failed: self.failed, undo: Some(undo) });
+    }
+}
+
+fn encode(progress: &Progress) -> Bytes {
+    let data = serde_json::to_string(progress).expect("Progress not serializable");
+    return Bytes::from(format!("{}\n", data));
+}
+
+/// Applies an operation to multiple documents and streams the progress as newline delimited JSON.
+///
+/// Documents are validated like for the plain bulk operation. They are processed while the response is read, so
+/// processing stops if the client disconnects, leaving the documents processed until then changed.
+#[post("/bulk/stream", data = "<request>")]
+pub(super) async fn streamed<'r>(request: Json<BulkRequest>,
+                                 repository: &'r Repository,
+                                 suggester: State<'r, Box<dyn Suggester + Send + Sync>>,
+                                 keyring: &'r Keyring,
+                                 filing: &'r Filing,
+                                 buffer: &'r Undo,
+                                 token: &'_ Token) -> Content<Stream<impl AsyncRead + 'r>> {
+    let request = request.into_inner();
+
+    let repository = repository.acting_as(token.subject());
+
+    let mut results = Vec::with_capacity(request.ids.len());
+    for id in &request.ids {
+        let error = resolve(&repository, *id, &request.operation, keyring, token).await.err()
+            .map(|err| err.to_string());
+
+        results.push(DocResult { id: *id, error });
+    }
+
+    let rejected = results.iter().any(|result| result.error.is_some());
+
+    let (head, ids) = if rejected {
+        (Progress::Rejected { results }, Vec::new())
+    } else {
+        (Progress::Started { total: request.ids.len() }, request.ids)
+    };
+
+    let run = Run {
+        repository,
+        operation: request.operation,
+        total: ids.len(),
+        ids: ids.into_iter(),
+        done: 0,
+        failed: 0,
+        actions: Vec::new(),
+        finished: rejected,
+        suggester: suggester.inner().as_ref(),
+        keyring,
+        filing,
+        buffer,
+        token: token.clone(),
+    };
+
+    let progress = stream::unfold(run, |mut run| async move {
+        return run.next().await.map(|progress| (progress, run));
+    });
+
+    let body = stream::once(async move { head })
+        .chain(progress)
+        .map(|progress| encode(&progress))
+        .map(Ok::<_, std::io::Error>);
+
+    return Content(ContentType::new("application", "x-ndjson"),
+                   Stream::from(tokio::io::stream_reader(body)));
+}
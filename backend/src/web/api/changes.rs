@@ -0,0 +1,99 @@
+use rocket::get;
+use rocket_contrib::json::Json;
+
+use crate::meta::Metadata;
+use crate::proto::api::changes::{Change, ChangeKind, ChangesResponse};
+use crate::proto::api::sync::Location;
+use crate::proto::model::DocId;
+use crate::repository::{Event, Repository};
+
+use super::{ApiError, Token};
+
+/// Default and maximum number of journal entries processed per request
+const LIMIT: usize = 1000;
+
+/// Looks up the current location and metadata of a bundle.
+async fn locate(repository: &Repository, id: DocId) -> anyhow::Result<Option<(Option<Location>, Metadata)>> {
+    if let Some(bundle) = repository.inbox().get(id).await {
+        return Ok(Some((Some(Location::Inbox), bundle.read_metadata().await?)));
+    }
+    if let Some(bundle) = repository.archive().get(id).await {
+        return Ok(Some((Some(Location::Archive), bundle.read_metadata().await?)));
+    }
+    if let Some(bundle) = repository.trash().get(id).await {
+        return Ok(Some((None, bundle.read_metadata().await?)));
+    }
+
+    return Ok(None);
+}
+
+/// Lists the metadata level changes of documents after the given cursor in the order they happened.
+///
+/// Unlike `/sync/changes`, changes are not collapsed, so integrations can mirror the state of the documents by applying
+/// them in order. Changes of documents not visible to the user are skipped, but the returned cursor still advances past
+/// them. Purged documents can not be checked for visibility, so their purges are always listed.
+#[get("/changes?<since>&<limit>")]
+pub(super) async fn changes(since: Option<u64>,
+                            limit: Option<usize>,
+                            repository: &'_ Repository,
+                            token: &'_ Token) -> Result<Json<ChangesResponse>, ApiError> {
+    let since = since.unwrap_or(0);
+    let limit = limit.unwrap_or(LIMIT).min(LIMIT);
+
+    let entries = repository.journal().since(since).await?;
+
+    let more = entries.len() > limit;
+    let entries = &entries[..entries.len().min(limit)];
+
+    let cursor = entries.last().map_or(since, |entry| entry.seq);
+
+    let mut changes = Vec::new();
+    for entry in entries {
+        let id = *entry.change.id();
+
+        let kind = match entry.change {
+            Event::Staged(_) | Event::Quarantined(_) => continue,
+            Event::Inboxed(_) | Event::Restored(_) => ChangeKind::Created,
+            Event::Archived(_) | Event::Unarchived(_) | Event::MetadataUpdated(_) => ChangeKind::Updated,
+            Event::Trashed(_) => ChangeKind::Trashed,
+            Event::Purged(_) | Event::Erased(_) => ChangeKind::Purged,
+        };
+
+        let current = locate(&repository, id).await?;
+        if let Some((_, metadata)) = &current {
+            if !metadata.is_visible_to(token.subject()) {
+                continue;
+            }
+        }
+
+        let location = match entry.change {
+            Event::Inboxed(_) | Event::Unarchived(_) => Some(Location::Inbox),
+            Event::Archived(_) => Some(Location::Archive),
+            Event::Restored(_) => current.as_ref().and_then(|(location, _)| *location),
+            _ => None,
+        };
+
+        let metadata = match kind {
+            ChangeKind::Created => current.map(|(_, metadata)| metadata.into()),
+            _ => None,
+        };
+
+        changes.push(Change {
+            cursor: entry.seq,
+            time: entry.time,
+            id,
+            kind,
+            location,
+            metadata,
+            fields: entry.diff.iter()
+                .map(|(field, change)| (field.clone(), change.after.clone()))
+                .collect(),
+        });
+    }
+
+    Ok(Json(ChangesResponse {
+        cursor,
+        more,
+        changes,
+    }))
+}
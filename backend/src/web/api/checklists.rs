@@ -0,0 +1,116 @@
+use std::str::FromStr;
+
+use chrono::{Datelike, Utc};
+use rocket::{delete, get, put};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::checklists::{self, Checklists};
+use crate::proto::api::checklists::{ListResponse, ProgressResponse, UpdateRequest};
+use crate::proto::model::Checklist;
+use crate::proto::query::Query;
+use crate::repository::Repository;
+
+use super::{ApiError, Token};
+
+fn parse(name: &RawStr) -> Result<String, ApiError> {
+    return name.url_decode()
+        .map(|name| name.into_owned())
+        .map_err(|err| ApiError::bad_request(err.to_string()));
+}
+
+#[get("/checklists")]
+pub(super) async fn list(checklists: &'_ Checklists,
+                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    Ok(Json(ListResponse {
+        checklists: checklists.list().await,
+    }))
+}
+
+#[get("/checklists/<name>")]
+pub(super) async fn get(name: &RawStr,
+                        checklists: &'_ Checklists,
+                        _token: &'_ Token) -> Result<Json<Checklist>, ApiError> {
+    let name = parse(name)?;
+
+    let checklist = checklists.get(&name).await
+        .ok_or_else(|| ApiError::not_found(format!("Checklist not found: {}", name)))?;
+
+    Ok(Json(checklist))
+}
+
+#[put("/checklists/<name>", data = "<request>")]
+pub(super) async fn update(name: &RawStr,
+                           request: Json<UpdateRequest>,
+                           checklists: &'_ Checklists,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let request = request.into_inner();
+
+    let checklist = Checklist {
+        name: parse(name)?,
+        items: request.items,
+        date: request.date,
+    };
+
+    if !checklist.is_valid() {
+        return Err(ApiError::bad_request(format!("Invalid checklist: {}", checklist.name)));
+    }
+
+    for item in &checklist.items {
+        Query::from_str(&item.query)
+            .map_err(|err| ApiError::bad_request(format!("Invalid query of {}: {:#}", item.name, err)))?;
+    }
+
+    checklists.define(checklist).await?;
+
+    return Ok(());
+}
+
+#[delete("/checklists/<name>")]
+pub(super) async fn remove(name: &RawStr,
+                           checklists: &'_ Checklists,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let name = parse(name)?;
+
+    if !checklists.remove(&name).await? {
+        return Err(ApiError::not_found(format!("Checklist not found: {}", name)));
+    }
+
+    return Ok(());
+}
+
+/// Reports which of the expected documents have arrived in the archive for a year, the current one by default.
+#[get("/checklists/<name>/progress?<year>")]
+pub(super) async fn progress(name: &RawStr,
+                             year: Option<i32>,
+                             checklists: &'_ Checklists,
+                             repository: &'_ Repository,
+                             token: &'_ Token) -> Result<Json<ProgressResponse>, ApiError> {
+    let name = parse(name)?;
+
+    let checklist = checklists.get(&name).await
+        .ok_or_else(|| ApiError::not_found(format!("Checklist not found: {}", name)))?;
+
+    let year = year.unwrap_or_else(|| Utc::now().year());
+
+    let mut docs = Vec::new();
+    for bundle in repository.archive().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if metadata.is_visible_to(token.subject()) {
+            docs.push((*bundle.id(), metadata));
+        }
+    }
+
+    let items = checklists::progress(&checklist, year, &docs)?;
+    let arrived = items.iter()
+        .filter(|item| !item.documents.is_empty())
+        .count();
+
+    Ok(Json(ProgressResponse {
+        name: checklist.name,
+        year,
+        arrived,
+        missing: items.len() - arrived,
+        items,
+    }))
+}
@@ -0,0 +1,45 @@
+use rocket::{get, State};
+use rocket_contrib::json::Json;
+
+use crate::confidence::DEFAULT_THRESHOLD;
+use crate::proto::api::confidence::ListResponse;
+use crate::repository::Repository;
+
+use super::{ApiError, Namespace, Token};
+
+/// Lists the OCRed documents whose confidence is below the given threshold, i.e. to find scans which need to be
+/// re-scanned or corrected manually.
+///
+/// Documents which have not been OCRed, as they already contained text, are never listed.
+#[get("/confidence?<threshold>")]
+pub(super) async fn list(threshold: Option<u8>,
+                         repository: &'_ Repository,
+                         namespace: State<'_, Namespace>,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD);
+
+    let mut docs = Vec::new();
+
+    for bundle in repository.inbox().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if metadata.is_visible_to(token.subject()) && metadata.confidence.map_or(false, |confidence| confidence < threshold) {
+            docs.push((*bundle.id(), metadata));
+        }
+    }
+
+    for bundle in repository.archive().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if metadata.is_visible_to(token.subject()) && metadata.confidence.map_or(false, |confidence| confidence < threshold) {
+            docs.push((*bundle.id(), metadata));
+        }
+    }
+
+    docs.sort_by_key(|(_, metadata)| metadata.confidence);
+
+    Ok(Json(ListResponse {
+        threshold,
+        docs: docs.into_iter()
+            .map(|doc| namespace.qualify(doc.into()))
+            .collect(),
+    }))
+}
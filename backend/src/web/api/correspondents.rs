@@ -0,0 +1,74 @@
+use rocket::{delete, get, put};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::correspondents::Correspondents;
+use crate::proto::api::correspondents::{ListResponse, UpdateRequest};
+use crate::proto::model::Correspondent;
+
+use super::{ApiError, Token};
+
+fn parse(name: &RawStr) -> Result<String, ApiError> {
+    return name.url_decode()
+        .map(|name| name.into_owned())
+        .map_err(|err| ApiError::bad_request(err.to_string()));
+}
+
+#[get("/correspondents")]
+pub(super) async fn list(correspondents: &'_ Correspondents,
+                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    Ok(Json(ListResponse {
+        correspondents: correspondents.list().await,
+    }))
+}
+
+#[get("/correspondents/<name>")]
+pub(super) async fn get(name: &RawStr,
+                        correspondents: &'_ Correspondents,
+                        _token: &'_ Token) -> Result<Json<Correspondent>, ApiError> {
+    let name = parse(name)?;
+
+    let correspondent = correspondents.get(&name).await
+        .ok_or_else(|| ApiError::not_found(format!("Correspondent not found: {}", name)))?;
+
+    Ok(Json(correspondent))
+}
+
+#[put("/correspondents/<name>", data = "<request>")]
+pub(super) async fn update(name: &RawStr,
+                           request: Json<UpdateRequest>,
+                           correspondents: &'_ Correspondents,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let request = request.into_inner();
+
+    let correspondent = Correspondent {
+        name: parse(name)?,
+        aliases: request.aliases,
+        labels: request.labels,
+    };
+
+    if !correspondent.is_valid() {
+        return Err(ApiError::bad_request(format!("Invalid correspondent name: {}", correspondent.name)));
+    }
+
+    if let Some(label) = correspondent.labels.iter().find(|label| !label.is_valid()) {
+        return Err(ApiError::bad_request(format!("Invalid label: {}", label)));
+    }
+
+    correspondents.define(correspondent).await?;
+
+    return Ok(());
+}
+
+#[delete("/correspondents/<name>")]
+pub(super) async fn remove(name: &RawStr,
+                           correspondents: &'_ Correspondents,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let name = parse(name)?;
+
+    if !correspondents.remove(&name).await? {
+        return Err(ApiError::not_found(format!("Correspondent not found: {}", name)));
+    }
+
+    return Ok(());
+}
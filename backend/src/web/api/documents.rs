@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+use log::info;
+use rocket::{Data, get, post, State};
+use rocket::data::ToByteUnit;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+use tokio::io::AsyncReadExt;
+
+use crate::crypto::Keyring;
+use crate::editing::Editor;
+use crate::proto::api::documents::{ReplaceResponse, VersionInfo, VersionsResponse};
+use crate::proto::model::DocId;
+use crate::quota::Quotas;
+use crate::repository::{Repository, Version};
+
+use super::{ApiError, archive, ensure_visible, ScopedIndex, Token};
+use super::ranges::{Conditions, Served};
+
+/// Reason recorded for replaced documents if none is given.
+const REPLACED: &str = "Replaced document";
+
+/// Lists the prior versions of the document of an archived bundle, oldest first.
+#[get("/documents/<id>/versions")]
+pub(super) async fn versions(id: &RawStr,
+                             repository: &'_ Repository,
+                             token: &'_ Token) -> Result<Json<VersionsResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let bundle = repository.archive().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    ensure_visible(id, &bundle.read_metadata().await?, token)?;
+
+    let versions = bundle.versions().await?.into_iter()
+        .map(|version| VersionInfo {
+            version: version.version,
+            replaced: version.replaced,
+            actor: version.actor,
+            reason: version.reason,
+        })
+        .collect();
+
+    Ok(Json(VersionsResponse { versions }))
+}
+
+/// Downloads a prior version of the document of an archived bundle.
+#[get("/documents/<id>/versions/<version>")]
+pub(super) async fn version(id: &RawStr,
+                            version: usize,
+                            repository: &'_ Repository,
+                            keyring: &'_ Keyring,
+                            conditions: Conditions,
+                            token: &'_ Token) -> Result<Served, ApiError> {
+    let name = format!("versions/{}", version);
+
+    return archive::serve(id, Version::kind(version), &name, repository, keyring, &conditions, token).await;
+}
+
+/// Replaces the document of an archived bundle by a new PDF, i.e. by the signed version of a contract.
+///
+/// The document keeps its ID and metadata, while the replaced document is kept as prior version. The new document is
+/// processed to regenerate its text and previews and reindexed afterwards. Documents of encryption domains can not be
+/// replaced, as their fragments are encrypted.
+#[post("/documents/<id>?<reason>", format = "application/pdf", data = "<data>")]
+pub(super) async fn replace(id: &RawStr,
+                            reason: Option<String>,
+                            data: Data,
+                            repository: &'_ Repository,
+                            editor: &'_ Editor,
+                            quotas: State<'_, Quotas>,
+                            index: ScopedIndex,
+                            token: &'_ Token) -> Result<Json<ReplaceResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.archive().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    if let Some(domain) = &metadata.domain {
+        return Err(ApiError::bad_request(format!("Bundle is encrypted for domain {}: {}", domain, id)));
+    }
+
+    let mut document = Vec::new();
+    data.open(512.mebibytes())
+        .read_to_end(&mut document).await
+        .context("Reading document")?;
+
+    if document.is_empty() {
+        return Err(ApiError::bad_request(String::from("Empty document")));
+    }
+
+    // The replaced document is kept as prior version, so the new document adds to the storage used by the owner
+    let reservation = quotas.reserve(&repository, metadata.owner.as_deref(), document.len() as u64).await?;
+
+    let reason = reason.filter(|reason| !reason.trim().is_empty());
+    let version = editor.replace(&repository, &bundle, &document, reason.as_deref().unwrap_or(REPLACED)).await?;
+    reservation.commit();
+
+    index.index(&bundle).await?;
+
+    info!("Replaced document of bundle {}, keeping version {}", id, version.version);
+
+    let metadata = bundle.read_metadata().await?;
+
+    return Ok(Json(ReplaceResponse {
+        doc: (id, metadata).into(),
+        version: version.version,
+    }));
+}
@@ -0,0 +1,45 @@
+use rocket::{get, post};
+use rocket_contrib::json::Json;
+
+use crate::crypto::Keyring;
+use crate::proto::api::domains::{DomainInfo, ListResponse, UnlockRequest};
+
+use super::{ApiError, Token};
+
+#[get("/domains")]
+pub(super) async fn list(keyring: &'_ Keyring,
+                         token: &'_ Token) -> Json<ListResponse> {
+    let mut domains = Vec::new();
+    for domain in keyring.domains() {
+        domains.push(DomainInfo {
+            name: domain.name.clone(),
+            labels: domain.labels.iter().map(|label| label.as_str().into()).collect(),
+            unlocked: keyring.key(token.subject(), &domain.name).await.is_some(),
+        });
+    }
+
+    Json(ListResponse { domains })
+}
+
+#[post("/domains/<name>/unlock", data = "<request>")]
+pub(super) async fn unlock(name: String,
+                           request: Json<UnlockRequest>,
+                           keyring: &'_ Keyring,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    if !keyring.domains().iter().any(|domain| domain.name == name) {
+        return Err(ApiError::not_found(format!("Domain not found: {}", name)));
+    }
+
+    if !keyring.unlock(token.subject(), &name, &request.passphrase).await? {
+        return Err(ApiError::forbidden(format!("Wrong passphrase for domain: {}", name)));
+    }
+
+    return Ok(());
+}
+
+#[post("/domains/<name>/lock")]
+pub(super) async fn lock(name: String,
+                         keyring: &'_ Keyring,
+                         token: &'_ Token) {
+    keyring.lock(token.subject(), &name).await;
+}
@@ -0,0 +1,61 @@
+use std::str::FromStr;
+
+use chrono::{Duration, MAX_DATE, Utc};
+use log::info;
+use rocket::{get, put};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::contracts;
+use crate::proto::api::due::{ListResponse, UpdateRequest};
+use crate::proto::model::DocId;
+use crate::reminders::{self, DEFAULT_LEAD};
+use crate::repository::Repository;
+
+use super::{ApiError, Token};
+use super::relations;
+
+/// Lists the documents which are overdue or due within the given number of days.
+#[get("/due?<days>")]
+pub(super) async fn list(days: Option<u32>,
+                         repository: &'_ Repository,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let today = Utc::today().naive_utc();
+    let until = today + Duration::days(days.unwrap_or(DEFAULT_LEAD).into());
+
+    let docs = reminders::due(&repository, today, until, |metadata| metadata.is_visible_to(token.subject())).await?;
+
+    Ok(Json(reminders::describe(docs, today)))
+}
+
+/// Lists all running contracts ordered by the last day they can be cancelled.
+#[get("/contracts")]
+pub(super) async fn contracts(repository: &'_ Repository,
+                              token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let today = Utc::today().naive_utc();
+
+    let docs = reminders::due(&repository, today, MAX_DATE.naive_utc(), |metadata| {
+        return contracts::cancel_by(metadata).is_some() && metadata.is_visible_to(token.subject());
+    }).await?;
+
+    Ok(Json(reminders::describe(docs, today)))
+}
+
+/// Sets or clears the due date of a document, i.e. to dismiss a paid bill.
+#[put("/due/<id>", data = "<request>")]
+pub(super) async fn update(id: &RawStr,
+                           request: Json<UpdateRequest>,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+    let due = request.into_inner().due;
+
+    let repository = repository.acting_as(token.subject());
+
+    info!("Setting due date of {} to {:?}", id, due);
+
+    return relations::update(&repository, id, token, |metadata| {
+        metadata.due = due;
+        return Ok(());
+    }).await;
+}
@@ -0,0 +1,46 @@
+use std::str::FromStr;
+
+use rocket::post;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::history::HistoryEntry;
+use crate::proto::model::DocId;
+use crate::repository::Repository;
+
+use super::{ApiError, ensure_visible, Token};
+
+/// Erases all traces of a bundle and returns the tombstone recorded for it.
+#[post("/erase/<id>")]
+pub(super) async fn erase(id: &RawStr,
+                          repository: &'_ Repository,
+                          token: &'_ Token) -> Result<Json<HistoryEntry>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let metadata = if let Some(bundle) = repository.inbox().get(id).await {
+        Some(bundle.read_metadata().await?)
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        Some(bundle.read_metadata().await?)
+    } else if let Some(bundle) = repository.trash().get(id).await {
+        Some(bundle.read_metadata().await?)
+    } else {
+        None
+    };
+
+    if let Some(metadata) = &metadata {
+        ensure_visible(id, metadata, token)?;
+    }
+
+    let tombstone = repository.erase(id).await?
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    Ok(Json(HistoryEntry {
+        seq: tombstone.seq,
+        time: tombstone.time,
+        event: tombstone.change.name().to_string(),
+        actor: tombstone.actor,
+        diff: Default::default(),
+    }))
+}
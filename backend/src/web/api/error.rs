@@ -3,7 +3,7 @@ use log::error;
 use rocket::{Request, Response};
 use rocket::http::Status;
 use rocket::response::Responder;
-use rocket::response::status::NotFound;
+use rocket::response::status::{BadRequest, Conflict, Custom, NotFound};
 
 #[derive(Debug)]
 pub(super) struct InternalError(pub Error);
@@ -27,22 +27,100 @@ impl From<Error> for InternalError {
 
 #[derive(Debug, Responder)]
 pub(super) enum ApiError {
+    BadRequest(BadRequest<String>),
     NotFound(NotFound<String>),
+    Conflict(Conflict<String>),
+    Custom(Custom<String>),
     InternalError(InternalError),
 }
 
 impl ApiError {
+    pub const fn bad_request(s: String) -> Self { Self::BadRequest(BadRequest(Some(s))) }
+
     pub const fn not_found(s: String) -> Self { Self::NotFound(NotFound(s)) }
+
+    pub const fn conflict(s: String) -> Self { Self::Conflict(Conflict(Some(s))) }
+
+    pub const fn forbidden(s: String) -> Self { Self::Custom(Custom(Status::Forbidden, s)) }
+
+    pub const fn gone(s: String) -> Self { Self::Custom(Custom(Status::Gone, s)) }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Self::BadRequest(BadRequest(message)) => f.write_str(message.as_deref().unwrap_or("Bad request")),
+            Self::NotFound(NotFound(message)) => f.write_str(message),
+            Self::Conflict(Conflict(message)) => f.write_str(message.as_deref().unwrap_or("Conflict")),
+            Self::Custom(Custom(_, message)) => f.write_str(message),
+            Self::InternalError(InternalError(err)) => write!(f, "{:#}", err),
+        };
+    }
+}
+
+impl From<BadRequest<String>> for ApiError {
+    fn from(r: BadRequest<String>) -> Self { Self::BadRequest(r) }
 }
 
 impl From<NotFound<String>> for ApiError {
     fn from(r: NotFound<String>) -> Self { Self::NotFound(r) }
 }
 
+impl From<Conflict<String>> for ApiError {
+    fn from(r: Conflict<String>) -> Self { Self::Conflict(r) }
+}
+
 impl From<InternalError> for ApiError {
     fn from(r: InternalError) -> Self { Self::InternalError(r) }
 }
 
 impl From<Error> for ApiError {
-    fn from(err: Error) -> Self { Self::InternalError(err.into()) }
+    fn from(err: Error) -> Self {
+        let err = match err.downcast::<crate::repository::Conflict>() {
+            Ok(conflict) => return Self::conflict(conflict.to_string()),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::repository::Moved>() {
+            Ok(moved) => return Self::conflict(moved.to_string()),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::repository::Retained>() {
+            Ok(retained) => return Self::forbidden(retained.to_string()),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::repository::WriteOnce>() {
+            Ok(write_once) => return Self::forbidden(write_once.to_string()),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::filing::Collision>() {
+            Ok(collision) => return Self::conflict(collision.to_string()),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::requests::Closed>() {
+            Ok(closed) => return Self::gone(closed.to_string()),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::quota::Exceeded>() {
+            Ok(exceeded) => return Self::Custom(Custom(Status::InsufficientStorage, exceeded.to_string())),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::mailer::TooLarge>() {
+            Ok(too_large) => return Self::Custom(Custom(Status::PayloadTooLarge, too_large.to_string())),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<crate::juicer::JuicerError>() {
+            Ok(err @ crate::juicer::JuicerError::Timeout(_)) => return Self::Custom(Custom(Status::GatewayTimeout, err.to_string())),
+            Err(err) => err,
+        };
+
+        return Self::InternalError(err.into());
+    }
 }
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use rocket::get;
+use rocket::http::ContentType;
+use rocket::response::{Content, Stream};
+use tokio::io::AsyncRead;
+use tokio::sync::broadcast::RecvError;
+
+use crate::repository::{Event, Repository};
+
+use super::Token;
+
+/// Interval of comments sent to keep idle connections open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+fn encode(event: &Event) -> Bytes {
+    let data = serde_json::to_string(event).expect("Event not serializable");
+    return Bytes::from(format!("event: {}\ndata: {}\n\n", event.name(), data));
+}
+
+/// Streams repository events as server-sent events.
+#[get("/events")]
+pub(super) async fn stream(repository: &'_ Repository,
+                           _token: &'_ Token) -> Content<Stream<impl AsyncRead>> {
+    let events = repository.subscribe()
+        .filter_map(|event| async move {
+            return match event {
+                Ok(event) => Some(encode(&event)),
+
+                // Slow clients miss events but stay connected
+                Err(RecvError::Lagged(_)) => None,
+
+                Err(RecvError::Closed) => None,
+            };
+        });
+
+    let keep_alive = tokio::time::interval(KEEP_ALIVE)
+        .map(|_| Bytes::from_static(b": keep-alive\n\n"));
+
+    let body = stream::select(events, keep_alive)
+        .map(Ok::<_, std::io::Error>);
+
+    return Content(ContentType::new("text", "event-stream"),
+                   Stream::from(tokio::io::stream_reader(body)));
+}
@@ -0,0 +1,59 @@
+use std::str::FromStr;
+
+use rocket::get;
+use rocket::http::ContentType;
+use rocket::response::{Content, Stream};
+use tokio::io::AsyncRead;
+
+use crate::export::Export;
+use crate::proto::model::DocId;
+use crate::repository::{Location, Repository};
+
+use super::{ApiError, ensure_visible, Token};
+
+/// Exports documents as a portable tar archive, which is streamed while it is written.
+///
+/// The documents are selected by a comma separated list of IDs from the inbox or the archive. Without IDs, all
+/// archived documents visible to the user are exported.
+#[get("/export?<ids>")]
+pub(super) async fn export(ids: Option<String>,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Content<Stream<impl AsyncRead>>, ApiError> {
+    let mut export = Export::new(repository.snapshot().await?);
+
+    match ids {
+        Some(ids) => {
+            for id in ids.split(',').filter(|id| !id.is_empty()) {
+                let id = DocId::from_str(id)?;
+
+                let metadata = match export.snapshot().get(id) {
+                    Some(bundle) if bundle.location() != Location::Trash => bundle.metadata()?,
+                    _ => return Err(ApiError::not_found(format!("Bundle not found: {}", id))),
+                };
+                ensure_visible(id, &metadata, token)?;
+
+                export.add(id)?;
+            }
+        }
+
+        None => {
+            let mut ids = Vec::new();
+            for bundle in export.snapshot().bundles() {
+                if bundle.location() == Location::Archive && bundle.metadata()?.is_visible_to(token.subject()) {
+                    ids.push(*bundle.id());
+                }
+            }
+
+            for id in ids {
+                export.add(id)?;
+            }
+        }
+    }
+
+    if export.is_empty() {
+        return Err(ApiError::bad_request(String::from("No documents to export")));
+    }
+
+    return Ok(Content(ContentType::new("application", "x-tar"),
+                      Stream::from(tokio::io::stream_reader(export.stream()))));
+}
@@ -0,0 +1,106 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use rocket::post;
+use rocket::http::ContentType;
+use rocket::response::Content;
+use rocket_contrib::json::Json;
+
+use crate::filing::{self, Filing};
+use crate::index::Index;
+use crate::proto::api::filing::SheetRequest;
+use crate::proto::model::DocId;
+use crate::proto::query::Query;
+use crate::repository::{Listing, Repository};
+
+use super::{ApiError, ensure_visible, ScopedIndex, Token};
+
+/// Collects the archived documents with their ASN for the given IDs, failing for any document not ready for filing.
+async fn by_ids(repository: &Repository, ids: Vec<DocId>, token: &Token) -> Result<Vec<(DocId, i64)>, ApiError> {
+    let mut docs = Vec::new();
+    for id in ids {
+        let bundle = repository.archive().get(id).await
+            .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+        let metadata = bundle.read_metadata().await?;
+        ensure_visible(id, &metadata, token)?;
+
+        let asn = filing::asn(&metadata)
+            .ok_or_else(|| ApiError::bad_request(format!("Bundle without ASN: {}", id)))?;
+
+        docs.push((id, asn));
+    }
+
+    return Ok(docs);
+}
+
+/// Collects all archived documents matching the query which are visible to the user and have an ASN.
+async fn by_query(repository: &Repository,
+                  index: &(dyn Index + Send + Sync),
+                  query: &str,
+                  token: &Token) -> Result<Vec<(DocId, i64)>, ApiError> {
+    let query = Query::from_str(query)
+        .map_err(|err| ApiError::bad_request(format!("{:#}", err)))?;
+
+    let mut docs = Vec::new();
+    let mut offset = 0;
+    loop {
+        let response = index.search(&query, &Listing::new(None, Some(offset), Some(Listing::MAX_LIMIT))).await?;
+        if response.docs.is_empty() {
+            break;
+        }
+
+        offset += response.docs.len();
+
+        for id in response.docs {
+            let bundle = repository.archive().get(id).await
+                .ok_or_else(|| anyhow!("Bundle missing: {}", id))?;
+
+            let metadata = bundle.read_metadata().await?;
+            if !metadata.is_visible_to(token.subject()) {
+                continue;
+            }
+
+            if let Some(asn) = filing::asn(&metadata) {
+                docs.push((id, asn));
+            }
+        }
+
+        if offset as u64 >= response.count {
+            break;
+        }
+    }
+
+    // Labels are printed in filing order
+    docs.sort_by_key(|(_, asn)| *asn);
+
+    return Ok(docs);
+}
+
+/// Renders a sheet of filing labels for a selection of archived documents.
+#[post("/filing/sheet", data = "<request>")]
+pub(super) async fn sheet(request: Json<SheetRequest>,
+                          filing: &'_ Filing,
+                          repository: &'_ Repository,
+                          index: ScopedIndex,
+                          token: &'_ Token) -> Result<Content<Vec<u8>>, ApiError> {
+    if !filing.is_enabled() {
+        return Err(ApiError::bad_request(String::from("Filing is disabled")));
+    }
+
+    let request = request.into_inner();
+
+    let docs = match (request.ids, request.query) {
+        (Some(ids), None) => by_ids(&repository, ids, token).await?,
+        (None, Some(query)) => by_query(&repository, &*index, &query, token).await?,
+        _ => return Err(ApiError::bad_request(String::from("Either ids or query required"))),
+    };
+
+    if docs.is_empty() {
+        return Err(ApiError::not_found(String::from("No documents to label")));
+    }
+
+    let sheet = filing.sheet(&docs, request.skip)?;
+
+    return Ok(Content(ContentType::PDF, sheet));
+}
@@ -0,0 +1,84 @@
+use std::str::FromStr;
+
+use rocket::get;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::crypto::Keyring;
+use crate::proto::api::fragments::{FragmentInfo, ListResponse};
+use crate::proto::model::{DocId, Kind};
+use crate::repository::{FragmentEntry, kind_of, Repository};
+
+use super::{ApiError, archive, ensure_visible, inbox, Token};
+use super::ranges::{Conditions, Served};
+
+fn kind_name(kind: &Kind) -> &'static str {
+    return match kind {
+        Kind::Document => "document",
+        Kind::Preview => "preview",
+        Kind::Page(_) => "page",
+        Kind::Thumbnail => "thumbnail",
+        Kind::Plaintext => "plaintext",
+        Kind::Metadata => "metadata",
+        Kind::Other { .. } => "other",
+    };
+}
+
+fn fragment_info(fragment: FragmentEntry) -> FragmentInfo {
+    return FragmentInfo {
+        kind: kind_name(&fragment.kind).to_string(),
+        name: fragment.name,
+        size: fragment.size,
+        modified: fragment.modified,
+        sha256: fragment.checksum,
+    };
+}
+
+/// Lists all fragments of an inboxed or archived document, including the additional files written by the juicer.
+#[get("/fragments/<id>")]
+pub(super) async fn list(id: &RawStr,
+                         repository: &'_ Repository,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let fragments = if let Some(bundle) = repository.inbox().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        bundle.fragments().await?
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        bundle.fragments().await?
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    };
+
+    Ok(Json(ListResponse {
+        fragments: fragments.into_iter().map(fragment_info).collect(),
+    }))
+}
+
+/// Downloads an additional fragment of an inboxed or archived document by its filename.
+///
+/// The well-known fragments are served by the inbox and archive endpoints and rejected here.
+#[get("/fragments/<id>/<name>")]
+pub(super) async fn download(id: &RawStr,
+                             name: String,
+                             repository: &'_ Repository,
+                             keyring: &'_ Keyring,
+                             conditions: Conditions,
+                             token: &'_ Token) -> Result<Served, ApiError> {
+    let kind = kind_of(&name);
+    if !matches!(kind, Kind::Other { .. }) {
+        return Err(ApiError::bad_request(format!("Not an additional fragment: {}", name)));
+    }
+
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(ApiError::bad_request(format!("Invalid fragment name: {}", name)));
+    }
+
+    let inboxed = repository.inbox().get(DocId::from_str(id.as_str())?).await.is_some();
+    return if inboxed {
+        inbox::serve(id, kind, &name, repository, &conditions, token).await
+    } else {
+        archive::serve(id, kind, &name, repository, keyring, &conditions, token).await
+    };
+}
@@ -0,0 +1,323 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Enum, Json as JsonValue, Object, Result, Schema, SimpleObject};
+use chrono::{DateTime, NaiveDate, Utc};
+use rocket::{post, State};
+use rocket_contrib::json::Json;
+
+use crate::correspondents::Correspondents;
+use crate::index::Index;
+use crate::labels::{Definition, Labels};
+use crate::meta::Metadata;
+use crate::proto::model::{self, DocId, Label, PropertyValue, RelationKind};
+use crate::repository::Repository;
+
+use super::{listing, ScopedIndex, Token};
+
+/// Everything queries are resolved against, for the user issuing the query.
+struct Data {
+    repository: Repository,
+    index: Arc<dyn Index + Send + Sync>,
+    correspondents: BTreeMap<String, model::Correspondent>,
+    labels: BTreeMap<Label, Definition>,
+    subject: String,
+}
+
+impl Data {
+    /// Reads the metadata of a document in the inbox or archive, unless it is hidden from the user.
+    async fn read(&self, id: DocId) -> anyhow::Result<Option<Document>> {
+        let metadata = if let Some(bundle) = self.repository.inbox().get(id).await {
+            bundle.read_metadata().await?
+        } else if let Some(bundle) = self.repository.archive().get(id).await {
+            bundle.read_metadata().await?
+        } else {
+            return Ok(None);
+        };
+
+        if !metadata.is_visible_to(&self.subject) {
+            return Ok(None);
+        }
+
+        return Ok(Some(Document { id, metadata }));
+    }
+}
+
+pub(super) type GraphQL = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Maximum nesting of fields in a query, which allows to follow relations a few documents deep.
+const MAX_DEPTH: usize = 10;
+
+/// Maximum number of fields selected by a query, counting every field of nested selections.
+const MAX_COMPLEXITY: usize = 250;
+
+/// Builds the schema served by the endpoint.
+///
+/// Relations can be followed back and forth endlessly, so queries exceeding the depth or complexity limits are
+/// rejected before they are resolved.
+pub(super) fn schema() -> GraphQL {
+    return Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish();
+}
+
+pub(super) struct Query;
+
+#[Object]
+impl Query {
+    /// A single document in the inbox or archive
+    async fn document(&self, ctx: &Context<'_>, id: String) -> Result<Option<Document>> {
+        let data = ctx.data::<Data>()?;
+        return Ok(data.read(DocId::from_str(&id)?).await?);
+    }
+
+    /// The archived documents matching a query, in the syntax of the archive search
+    async fn documents(&self,
+                       ctx: &Context<'_>,
+                       query: Option<String>,
+                       label: Option<String>,
+                       sort: Option<String>,
+                       offset: Option<usize>,
+                       limit: Option<usize>) -> Result<Vec<Document>> {
+        let data = ctx.data::<Data>()?;
+
+        let query = listing::query(query, label, None, None)?;
+        let listing = listing::listing(sort, offset, limit)?;
+
+        let mut docs = Vec::new();
+        for id in data.index.search(&query, &listing).await?.docs {
+            if let Some(doc) = data.read(id).await? {
+                docs.push(doc);
+            }
+        }
+
+        return Ok(docs);
+    }
+
+    /// The documents in the inbox
+    async fn inbox(&self, ctx: &Context<'_>) -> Result<Vec<Document>> {
+        let data = ctx.data::<Data>()?;
+
+        let mut docs = Vec::new();
+        for bundle in data.repository.inbox().list().await? {
+            let metadata = bundle.read_metadata().await?;
+            if metadata.is_visible_to(&data.subject) {
+                docs.push(Document { id: *bundle.id(), metadata });
+            }
+        }
+
+        return Ok(docs);
+    }
+
+    /// The registered labels
+    async fn labels(&self, ctx: &Context<'_>) -> Result<Vec<LabelInfo>> {
+        let data = ctx.data::<Data>()?;
+        return Ok(data.labels.keys().map(|label| label_info(data, label)).collect());
+    }
+
+    /// All correspondents
+    async fn correspondents(&self, ctx: &Context<'_>) -> Result<Vec<Correspondent>> {
+        let data = ctx.data::<Data>()?;
+        return Ok(data.correspondents.values().cloned().map(Correspondent).collect());
+    }
+
+    /// A single correspondent by name
+    async fn correspondent(&self, ctx: &Context<'_>, name: String) -> Result<Option<Correspondent>> {
+        let data = ctx.data::<Data>()?;
+        return Ok(data.correspondents.get(&name).cloned().map(Correspondent));
+    }
+}
+
+pub(super) struct Document {
+    id: DocId,
+    metadata: Metadata,
+}
+
+#[Object]
+impl Document {
+    async fn id(&self) -> String {
+        return self.id.to_string();
+    }
+
+    async fn title(&self) -> Option<&str> {
+        return self.metadata.title.as_deref();
+    }
+
+    async fn pages(&self) -> u32 {
+        return self.metadata.pages;
+    }
+
+    async fn uploaded(&self) -> DateTime<Utc> {
+        return self.metadata.uploaded;
+    }
+
+    async fn archived(&self) -> Option<DateTime<Utc>> {
+        return self.metadata.archived;
+    }
+
+    async fn due(&self) -> Option<NaiveDate> {
+        return self.metadata.due;
+    }
+
+    async fn filename(&self) -> Option<&str> {
+        return self.metadata.filename.as_deref();
+    }
+
+    /// The properties as JSON object, like in the metadata
+    async fn properties(&self) -> JsonValue<HashMap<String, PropertyValue>> {
+        return JsonValue(self.metadata.properties.clone());
+    }
+
+    async fn labels(&self, ctx: &Context<'_>) -> Result<Vec<LabelInfo>> {
+        let data = ctx.data::<Data>()?;
+
+        let mut labels = self.metadata.labels.iter().collect::<Vec<_>>();
+        labels.sort();
+
+        return Ok(labels.into_iter().map(|label| label_info(data, label)).collect());
+    }
+
+    async fn correspondent(&self, ctx: &Context<'_>) -> Result<Option<Correspondent>> {
+        let data = ctx.data::<Data>()?;
+
+        return Ok(match &self.metadata.correspondent {
+            Some(name) => data.correspondents.get(name).cloned().map(Correspondent),
+            None => None,
+        });
+    }
+
+    /// The documents this document links to, skipping purged and hidden ones
+    async fn related(&self, ctx: &Context<'_>) -> Result<Vec<Related>> {
+        let data = ctx.data::<Data>()?;
+
+        let mut related = Vec::new();
+        for relation in &self.metadata.relations {
+            if let Some(document) = data.read(relation.target).await? {
+                related.push(Related { kind: relation.kind.into(), document });
+            }
+        }
+
+        return Ok(related);
+    }
+
+    /// The documents linking to this document, skipping hidden ones
+    async fn referenced_by(&self, ctx: &Context<'_>) -> Result<Vec<Related>> {
+        let data = ctx.data::<Data>()?;
+
+        let mut related = Vec::new();
+        for (source, metadata) in data.repository.referencing(self.id).await? {
+            if !metadata.is_visible_to(&data.subject) {
+                continue;
+            }
+
+            let kinds = metadata.relations.iter()
+                .filter(|relation| relation.target == self.id)
+                .map(|relation| relation.kind)
+                .collect::<Vec<_>>();
+
+            for kind in kinds {
+                related.push(Related {
+                    kind: kind.into(),
+                    document: Document { id: source, metadata: metadata.clone() },
+                });
+            }
+        }
+
+        return Ok(related);
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Enum)]
+#[graphql(name = "RelationKind")]
+pub(super) enum Kind {
+    InvoiceFor,
+    ReplyTo,
+    Supersedes,
+    AttachmentOf,
+    Related,
+}
+
+impl From<RelationKind> for Kind {
+    fn from(kind: RelationKind) -> Self {
+        return match kind {
+            RelationKind::InvoiceFor => Self::InvoiceFor,
+            RelationKind::ReplyTo => Self::ReplyTo,
+            RelationKind::Supersedes => Self::Supersedes,
+            RelationKind::AttachmentOf => Self::AttachmentOf,
+            RelationKind::Related => Self::Related,
+        };
+    }
+}
+
+#[derive(SimpleObject)]
+pub(super) struct Related {
+    kind: Kind,
+    document: Document,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Label")]
+pub(super) struct LabelInfo {
+    name: String,
+    color: Option<String>,
+    description: Option<String>,
+}
+
+fn label_info(data: &Data, label: &Label) -> LabelInfo {
+    let definition = data.labels.get(label).cloned().unwrap_or_default();
+    return LabelInfo {
+        name: label.to_string(),
+        color: definition.color,
+        description: definition.description,
+    };
+}
+
+pub(super) struct Correspondent(model::Correspondent);
+
+#[Object]
+impl Correspondent {
+    async fn name(&self) -> &str {
+        return &self.0.name;
+    }
+
+    async fn aliases(&self) -> &[String] {
+        return &self.0.aliases;
+    }
+
+    /// The labels added to documents from the correspondent
+    async fn labels(&self, ctx: &Context<'_>) -> Result<Vec<LabelInfo>> {
+        let data = ctx.data::<Data>()?;
+
+        let mut labels = self.0.labels.iter().collect::<Vec<_>>();
+        labels.sort();
+
+        return Ok(labels.into_iter().map(|label| label_info(data, label)).collect());
+    }
+}
+
+/// Resolves a GraphQL query against the documents visible to the user.
+///
+/// Queries can select exactly the fields needed and follow the relations between documents, labels and
+/// correspondents in a single request. Mutations are done using the other endpoints.
+#[post("/graphql", data = "<request>")]
+pub(super) async fn graphql(request: Json<async_graphql::Request>,
+                            schema: State<'_, GraphQL>,
+                            repository: &'_ Repository,
+                            index: ScopedIndex,
+                            correspondents: &'_ Correspondents,
+                            labels: &'_ Labels,
+                            token: &'_ Token) -> Json<async_graphql::Response> {
+    let data = Data {
+        repository: repository.clone(),
+        index: index.0,
+        correspondents: correspondents.list().await.into_iter()
+            .map(|correspondent| (correspondent.name.clone(), correspondent))
+            .collect(),
+        labels: labels.list().await,
+        subject: token.subject().to_string(),
+    };
+
+    Json(schema.execute(request.into_inner().data(data)).await)
+}
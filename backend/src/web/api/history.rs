@@ -0,0 +1,52 @@
+use std::str::FromStr;
+
+use rocket::get;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::history::{Change, HistoryEntry, HistoryResponse};
+use crate::proto::model::DocId;
+use crate::repository::Repository;
+
+use super::{ApiError, ensure_visible, Token};
+
+#[get("/history/<id>")]
+pub(super) async fn history(id: &RawStr,
+                            repository: &'_ Repository,
+                            token: &'_ Token) -> Result<Json<HistoryResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    // The history of purged bundles is still available, but can not be checked for visibility
+    let metadata = if let Some(bundle) = repository.inbox().get(id).await {
+        Some(bundle.read_metadata().await?)
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        Some(bundle.read_metadata().await?)
+    } else if let Some(bundle) = repository.trash().get(id).await {
+        Some(bundle.read_metadata().await?)
+    } else {
+        None
+    };
+
+    if let Some(metadata) = &metadata {
+        ensure_visible(id, metadata, token)?;
+    }
+
+    let entries = repository.journal().history(id).await?;
+    if entries.is_empty() {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    }
+
+    let entries = entries.into_iter()
+        .map(|entry| HistoryEntry {
+            seq: entry.seq,
+            time: entry.time,
+            event: entry.change.name().to_string(),
+            actor: entry.actor,
+            diff: entry.diff.into_iter()
+                .map(|(field, change)| (field, Change { before: change.before, after: change.after }))
+                .collect(),
+        })
+        .collect();
+
+    Ok(Json(HistoryResponse { entries }))
+}
@@ -2,133 +2,384 @@ use std::str::FromStr;
 
 use anyhow::Result;
 use chrono::Utc;
-use futures::{StreamExt, TryStreamExt};
-use rocket::{delete, get, post, State};
-use rocket::http::{ContentType, RawStr};
+use rocket::{delete, get, post, put, State};
+use futures::TryStreamExt;
+use rocket::http::RawStr;
 use rocket::response::{Content, Stream};
 use rocket_contrib::json::Json;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
-use crate::index::Index;
-use crate::proto::api::inbox::{ArchiveRequest, GetResponse, ListResponse};
-use crate::proto::model::{DocId, DocInfo, Kind};
-use crate::repository::Repository;
+use crate::crypto::{self, Keyring};
+use crate::filing::Filing;
+use crate::meta::Metadata;
+use crate::persons::Persons;
+use crate::previews::Previews;
+use crate::proto::api::inbox::{ArchiveRequest, GetResponse, GroupInfo, ListResponse, SnoozeRequest};
+use crate::proto::api::undo::UndoInfo;
+use crate::proto::model::{DocId, DocInfo, Kind, Proposal};
+use crate::repository::{Bundle, Inboxed, Repository};
 use crate::suggester::Suggester;
+use crate::undo::{Action, Undo};
 use crate::web::api::InternalError;
 
-use super::{ApiError, Token};
+use super::{ApiError, ensure_visible, listing, Namespace, ndjson, Token, undo};
+use super::ranges::{Conditions, Served};
 
-#[get("/inbox")]
-pub(super) async fn list(repository: State<'_, Repository>,
-                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
-    let bundles = repository.inbox().list().await?;
+/// Lists the inbox, optionally grouped by an attribute to triage related documents together.
+///
+/// Snoozed documents are hidden unless requested explicitly, in which case only those are listed.
+#[get("/inbox?<query>&<label>&<from>&<to>&<sort>&<group>&<snoozed>&<offset>&<limit>", rank = 2)]
+pub(super) async fn list(query: Option<String>,
+                         label: Option<String>,
+                         from: Option<String>,
+                         to: Option<String>,
+                         sort: Option<String>,
+                         group: Option<String>,
+                         snoozed: Option<bool>,
+                         offset: Option<usize>,
+                         limit: Option<usize>,
+                         repository: &'_ Repository,
+                         namespace: State<'_, Namespace>,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let query = listing::query(query, label, from, to)?;
+    let listing = listing::listing(sort, offset, limit)?
+        .grouped(listing::grouping(group)?);
 
-    let docs = tokio::stream::iter(bundles.iter())
-        .take(10)
-        .then(|bundle| async move {
-            return bundle.read_metadata().await
-                .map(|metadata| DocInfo {
-                    id: *bundle.id(),
-                    metadata: metadata.into(),
-                });
-        });
+    let today = Utc::today().naive_utc();
+    let snoozed = snoozed.unwrap_or(false);
+
+    // Filtering by owner and query requires the metadata of all bundles
+    let page = repository.inbox().query(&listing, |metadata| {
+        metadata.is_visible_to(token.subject())
+            && metadata.matches(&query)
+            && metadata.snoozed.map_or(false, |until| until > today) == snoozed
+    }).await?;
 
     Ok(Json(ListResponse {
-        count: bundles.len() as u64,
-        docs: docs.try_collect().await?,
+        count: page.total as u64,
+        docs: page.items.into_iter()
+            .map(|(bundle, metadata)| namespace.qualify((*bundle.id(), metadata).into()))
+            .collect(),
+        groups: page.groups.into_iter()
+            .map(|(key, count)| GroupInfo { key, count: count as u64 })
+            .collect(),
     }))
 }
 
+/// Streams the whole inbox as newline delimited JSON, filtered like the plain listing.
+///
+/// The documents are listed in natural order without pagination, as sorting would require to keep all of them.
+#[get("/inbox?<query>&<label>&<from>&<to>&<snoozed>", format = "application/x-ndjson")]
+pub(super) async fn streamed<'r>(query: Option<String>,
+                                 label: Option<String>,
+                                 from: Option<String>,
+                                 to: Option<String>,
+                                 snoozed: Option<bool>,
+                                 repository: &'r Repository,
+                                 namespace: State<'r, Namespace>,
+                                 token: &'_ Token) -> Result<Content<Stream<impl AsyncRead + 'r>>, ApiError> {
+    let query = listing::query(query, label, from, to)?;
+
+    let today = Utc::today().naive_utc();
+    let snoozed = snoozed.unwrap_or(false);
+    let subject = token.subject().to_string();
+    let namespace = namespace.inner();
+
+    let docs = repository.inbox().stream().await?
+        .try_filter(move |(_, metadata)| futures::future::ready(
+            metadata.is_visible_to(&subject)
+                && metadata.matches(&query)
+                && metadata.snoozed.map_or(false, |until| until > today) == snoozed))
+        .map_ok(move |(bundle, metadata)| namespace.qualify((*bundle.id(), metadata).into()));
+
+    return Ok(ndjson::respond(docs));
+}
+
 #[get("/inbox/<id>")]
 pub(super) async fn bundle(id: &RawStr,
-                           repository: State<'_, Repository>,
+                           repository: &'_ Repository,
                            suggester: State<'_, Box<dyn Suggester + Send + Sync>>,
-                           _token: &'_ Token) -> Result<Json<GetResponse>, ApiError> {
+                           namespace: State<'_, Namespace>,
+                           token: &'_ Token) -> Result<Json<GetResponse>, ApiError> {
     let id = DocId::from_str(id.as_str())?;
 
     let bundle = repository.inbox().get(id).await
         .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
 
     let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
     let plaintext = bundle.read_plaintext().await?;
 
     let suggestions = suggester.guess(&plaintext).await?;
 
     return Ok(Json(GetResponse {
-        doc: (id, metadata).into(),
+        doc: namespace.qualify((id, metadata).into()),
         suggestions,
     }));
 }
 
+pub(super) async fn serve(id: &RawStr,
+                          kind: Kind,
+                          name: &str,
+                          repository: &Repository,
+                          conditions: &Conditions,
+                          token: &Token) -> Result<Served, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let bundle = repository.inbox().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    ensure_visible(id, &bundle.read_metadata().await?, token)?;
+
+    bundle.read(&kind).await
+        .map_err(InternalError)?
+        .ok_or_else(|| ApiError::not_found(format!("Fragment not found: {}/{}", id, name)))?;
+
+    return Ok(Served::fragment(&bundle, &kind, None, conditions).await?);
+}
+
 #[get("/inbox/<id>/<fragment>")]
-pub(super) async fn fragment<'r>(id: &RawStr,
+pub(super) async fn fragment(id: &RawStr,
                              fragment: &RawStr,
-                             repository: State<'r, Repository>,
-                             _token: &'_ Token) -> Result<Content<Stream<impl AsyncRead + 'r>>, ApiError> {
+                             repository: &'_ Repository,
+                             conditions: Conditions,
+                             token: &'_ Token) -> Result<Served, ApiError> {
+    return serve(id, Kind::from(fragment.as_str()), fragment.as_str(), repository, &conditions, token).await;
+}
+
+/// Serves the preview of a single page, counting from one, optionally scaled to the given size.
+#[get("/inbox/<id>/preview/<page>?<size>")]
+pub(super) async fn page(id: &RawStr,
+                         page: u32,
+                         size: Option<u32>,
+                         repository: &'_ Repository,
+                         previews: &'_ Previews,
+                         conditions: Conditions,
+                         token: &'_ Token) -> Result<Served, ApiError> {
+    if page == 0 {
+        return Err(ApiError::bad_request(String::from("Pages are counted from one")));
+    }
+
+    if let Some(size) = size {
+        let doc_id = DocId::from_str(id.as_str())?;
+        let bundle = repository.inbox().get(doc_id).await
+            .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", doc_id)))?;
+        ensure_visible(doc_id, &bundle.read_metadata().await?, token)?;
+
+        return Served::preview(&bundle, page, size, None, previews, &conditions).await;
+    }
+
+    return serve(id, Kind::Page(page), &format!("preview/{}", page), repository, &conditions, token).await;
+}
+
+/// Hides a document from the inbox until the given day, or wakes it right away if no day is given.
+#[put("/inbox/<id>/snooze", data = "<data>")]
+pub(super) async fn snooze(id: &RawStr,
+                           data: Json<SnoozeRequest>,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<DocInfo>, ApiError> {
     let id = DocId::from_str(id.as_str())?;
-    let kind = Kind::from(fragment.as_str());
-
-    let content_type = match kind {
-        Kind::Document => ContentType::PDF,
-        Kind::Preview => ContentType::PNG,
-        Kind::Plaintext => ContentType::Plain,
-        Kind::Metadata => ContentType::JSON,
-        Kind::Other { .. } => ContentType::Any,
-    };
+
+    if let Some(until) = data.until {
+        if until <= Utc::today().naive_utc() {
+            return Err(ApiError::bad_request(format!("Snooze date not in the future: {}", until)));
+        }
+    }
+
+    let repository = repository.acting_as(token.subject());
 
     let bundle = repository.inbox().get(id).await
         .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
 
-    let file = bundle.read(kind).await
-        .map_err(InternalError)?
-        .ok_or_else(|| ApiError::not_found(format!("Fragment not found: {}/{}", id, fragment)))?;
+    let mut metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    metadata.snoozed = data.until;
+    bundle.write_metadata(&metadata).await?;
 
-    return Ok(Content(content_type, file.into()));
+    return Ok(Json((id, metadata).into()));
+}
+
+/// Accepts the metadata proposed while juicing, as edited by the reviewer.
+///
+/// The given proposal replaces the pending one, so proposed values can be corrected or dropped before they are applied.
+#[post("/inbox/<id>/proposal", data = "<data>")]
+pub(super) async fn accept(id: &RawStr,
+                           data: Json<Proposal>,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<DocInfo>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.inbox().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let mut metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    if metadata.proposal.is_none() {
+        return Err(ApiError::not_found(format!("No proposal pending: {}", id)));
+    }
+
+    metadata.proposal = Some(data.into_inner());
+    metadata.accept();
+    bundle.write_metadata(&metadata).await?;
+
+    return Ok(Json((id, metadata).into()));
+}
+
+/// Rejects the metadata proposed while juicing, leaving the metadata as it was before.
+#[delete("/inbox/<id>/proposal")]
+pub(super) async fn reject(id: &RawStr,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<DocInfo>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.inbox().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let mut metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    if metadata.proposal.take().is_none() {
+        return Err(ApiError::not_found(format!("No proposal pending: {}", id)));
+    }
+
+    bundle.write_metadata(&metadata).await?;
+
+    return Ok(Json((id, metadata).into()));
 }
 
 #[delete("/inbox/<id>")]
 pub(super) async fn delete(id: &RawStr,
-                           repository: State<'_, Repository>,
-                           _token: &'_ Token) -> Result<(), ApiError> {
+                           repository: &'_ Repository,
+                           buffer: &'_ Undo,
+                           token: &'_ Token) -> Result<Json<UndoInfo>, ApiError> {
     let id = DocId::from_str(id.as_str())?;
 
+    let repository = repository.acting_as(token.subject());
+
     let bundle = repository.inbox().get(id).await
         .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    ensure_visible(id, &bundle.read_metadata().await?, token)?;
+
     bundle.delete().await?;
 
-    return Ok(());
+    return Ok(Json(undo::record(&buffer, token, vec![Action::Restore(id)]).await));
 }
 
 #[post("/inbox/<id>", data = "<data>")]
 pub(super) async fn archive(id: &RawStr,
                             data: Json<ArchiveRequest>,
-                            repository: State<'_, Repository>,
-                            index: State<'_, Box<dyn Index + Send + Sync>>,
+                            repository: &'_ Repository,
                             suggester: State<'_, Box<dyn Suggester + Send + Sync>>,
-                            _token: &'_ Token) -> Result<(), ApiError> {
+                            keyring: &'_ Keyring,
+                            filing: &'_ Filing,
+                            persons: &'_ Persons,
+                            buffer: &'_ Undo,
+                            token: &'_ Token) -> Result<Json<UndoInfo>, ApiError> {
     let id = DocId::from_str(id.as_str())?;
 
-    let bundle = repository.inbox().get(id).await
-        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    if let Some(person) = &data.belongs_to {
+        if persons.get(person).await.is_none() {
+            return Err(ApiError::bad_request(format!("Unknown person: {}", person)));
+        }
+    }
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = match repository.inbox().get(id).await {
+        Some(bundle) => bundle,
+
+        // Archiving an already archived bundle again is a no-op
+        None if repository.archive().get(id).await.is_some() => return Ok(Json(undo::record(&buffer, token, vec![]).await)),
+
+        None => return Err(ApiError::not_found(format!("Bundle not found: {}", id))),
+    };
 
     // Update the metadata
     let mut metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    let previous = metadata.clone();
 
     metadata.archived = Some(Utc::now());
     metadata.labels = data.labels.clone();
     metadata.properties = data.properties.clone();
+    metadata.shared = data.shared.clone();
+    if let Some(correspondent) = &data.correspondent {
+        metadata.correspondent = Some(correspondent.clone());
+    }
+    if let Some(person) = &data.belongs_to {
+        metadata.belongs_to = Some(person.clone());
+    }
+    if let Some(due) = data.due {
+        metadata.due = Some(due);
+    }
+    if let Some(date) = data.date {
+        metadata.date = Some(date);
+    }
+
+    archive_bundle(bundle, metadata, suggester.as_ref(), keyring, filing, token).await?;
+
+    return Ok(Json(undo::record(&buffer, token, vec![Action::Unarchive(id, previous)]).await));
+}
+
+/// Archives an inbox bundle with the given final metadata.
+///
+/// Fragments of documents belonging to an encryption domain are encrypted before the bundle is moved to the archive.
+/// The filing label is rendered unencrypted as it only contains the ASN and the link.
+///
+/// Bundles with a pending proposal are refused, as the proposed metadata must be accepted or rejected first. So are
+/// bundles with an ASN already held by another document.
+pub(super) async fn archive_bundle(bundle: Bundle<'_, Inboxed>,
+                                   mut metadata: Metadata,
+                                   suggester: &(dyn Suggester + Send + Sync),
+                                   keyring: &Keyring,
+                                   filing: &Filing,
+                                   token: &Token) -> Result<(), ApiError> {
+    if metadata.proposal.is_some() {
+        return Err(ApiError::conflict(format!("Proposal pending: {}", bundle.id())));
+    }
+
+    let plaintext = bundle.read_plaintext().await?;
+
+    // Archived documents can not re-surface in the inbox
+    metadata.snoozed = None;
+
+    // Unlock the domain of sensitive documents first, as archiving locked ones is refused before assigning an ASN
+    let encryption = match keyring.domain_of(&metadata) {
+        Some(domain) => Some((domain, keyring.key(token.subject(), domain).await
+            .ok_or_else(|| ApiError::forbidden(format!("Encryption domain locked: {}", domain)))?)),
+        None => None,
+    };
+
+    // Assign the ASN before encrypting, as an ASN held by another document refuses archiving
+    filing.label(&bundle, &mut metadata).await?;
+
+    // Encrypt the fragments of sensitive documents before they reach the archive
+    if let Some((domain, key)) = encryption {
+        for kind in crypto::sensitive(&metadata) {
+            let mut data = Vec::new();
+            match bundle.read(&kind).await? {
+                Some(mut file) => file.read_to_end(&mut data).await.map_err(anyhow::Error::from)?,
+                None => continue,
+            };
+
+            bundle.replace(kind, &crypto::encrypt(&key, &data)?).await?;
+        }
+
+        metadata.domain = Some(domain.to_string());
+    }
 
     bundle.write_metadata(&metadata).await?;
 
     // Archive the bundle
-    let archived = bundle.archive().await?;
-
-    // Add the archived bundle to the index
-    index.index(&archived).await?;
+    bundle.archive().await?;
 
     // Train the suggester with the final labels
-    let plaintext = archived.read_plaintext().await?;
-
     suggester.train(&plaintext, &metadata.labels).await?;
 
     return Ok(());
@@ -1,19 +1,118 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 
-use rocket::{get, State};
+use rocket::{delete, get, post, put, State};
+use rocket::http::RawStr;
 use rocket_contrib::json::Json;
 
+use crate::labels::{self, Definition, Labels};
+use crate::proto::api::labels::{LabelInfo, ListResponse, RenameRequest, RenameResponse, UpdateRequest};
 use crate::proto::model::Label;
-use crate::suggester::Suggester;
+use crate::repository::Repository;
 
 use super::{ApiError, Token};
 
+/// Parses a label given as path segment, the separators of hierarchical labels are expected to be percent-encoded.
+fn parse(label: &RawStr) -> Result<Label, ApiError> {
+    let label = label.url_decode()
+        .map(|label| Label::from(label.as_ref()))
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+
+    if !label.is_valid() {
+        return Err(ApiError::bad_request(format!("Invalid label: {}", label)));
+    }
+
+    return Ok(label);
+}
+
 #[get("/labels")]
-pub(super) async fn list(suggester: State<'_, Box<dyn Suggester + Send + Sync>>,
-                         _token: &'_ Token) -> Result<Json<HashSet<Label>>, ApiError> {
-    let labels = suggester.labels().await;
+pub(super) async fn list(labels: &'_ Labels,
+                         repository: &'_ Repository,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let registry = labels.list().await;
+
+    // Only documents visible to the user are counted
+    let usage = labels::usage(&repository, |metadata| metadata.is_visible_to(token.subject())).await?;
+
+    // Registered labels are listed with the labels above them, even if they are not used
+    let names = registry.keys()
+        .flat_map(|label| std::iter::once(label.clone()).chain(label.ancestors()))
+        .chain(usage.keys().cloned())
+        .collect::<BTreeSet<_>>();
+
+    Ok(Json(ListResponse {
+        labels: names.into_iter()
+            .map(|label| {
+                let definition = registry.get(&label).cloned().unwrap_or_default();
+                return LabelInfo {
+                    count: usage.get(&label).copied().unwrap_or(0),
+                    color: definition.color,
+                    description: definition.description,
+                    label,
+                };
+            })
+            .collect(),
+    }))
+}
+
+#[put("/labels/<label>", data = "<request>")]
+pub(super) async fn update(label: &RawStr,
+                           request: Json<UpdateRequest>,
+                           labels: &'_ Labels,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let label = parse(label)?;
+    let request = request.into_inner();
+
+    let definition = Definition {
+        color: request.color,
+        description: request.description,
+    };
+
+    if !definition.is_valid() {
+        return Err(ApiError::bad_request(format!("Invalid color: {}", definition.color.unwrap_or_default())));
+    }
+
+    labels.define(label, definition).await?;
+
+    return Ok(());
+}
+
+#[delete("/labels/<label>")]
+pub(super) async fn remove(label: &RawStr,
+                           labels: &'_ Labels,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let label = parse(label)?;
+
+    if !labels.remove(&label).await? {
+        return Err(ApiError::not_found(format!("Label not registered: {}", label)));
+    }
+
+    return Ok(());
+}
+
+#[post("/labels/<label>/rename", data = "<request>")]
+pub(super) async fn rename(label: &RawStr,
+                           request: Json<RenameRequest>,
+                           labels: &'_ Labels,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<RenameResponse>, ApiError> {
+    let from = parse(label)?;
+    let to = request.into_inner().to;
+
+    if !to.is_valid() {
+        return Err(ApiError::bad_request(format!("Invalid label: {}", to)));
+    }
+
+    if from == to {
+        return Err(ApiError::bad_request(format!("Label already named {}", to)));
+    }
+
+    let repository = repository.acting_as(token.subject());
+
+    let documents = labels.rename(&repository, &from, &to).await?;
 
-    Ok(Json(labels))
+    Ok(Json(RenameResponse {
+        documents,
+    }))
 }
 
 // #[get("/labels/guess/<id>")]
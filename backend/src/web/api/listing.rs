@@ -0,0 +1,89 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use crate::proto::model::{Label, PropertyValue};
+use crate::proto::query::{Comparison, Filter, Query, Sort};
+use crate::repository::{Grouping, Listing};
+
+use super::ApiError;
+
+/// Parses the search query and extends it by the filters given as separate request parameters.
+pub(super) fn query(query: Option<String>,
+                    label: Option<String>,
+                    from: Option<String>,
+                    to: Option<String>) -> Result<Query, ApiError> {
+    let mut query = query
+        .map(|query| Query::from_str(&query))
+        .transpose()
+        .map_err(|err| ApiError::bad_request(format!("{:#}", err)))?
+        .unwrap_or_default();
+
+    let date = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| ApiError::bad_request(format!("Invalid date: {}", date)));
+
+    if let Some(label) = label {
+        query = query.and(Filter::Label(Label::from(label)));
+    }
+
+    if let Some(from) = from {
+        query = query.and(Filter::Uploaded(Comparison::Ge, date(&from)?));
+    }
+
+    if let Some(to) = to {
+        query = query.and(Filter::Uploaded(Comparison::Le, date(&to)?));
+    }
+
+    return Ok(query);
+}
+
+/// Extends the query by the filters of a browsed listing given as separate request parameters.
+///
+/// Labels are given as comma separated list which are all required. Properties are given as `key=value`.
+pub(super) fn browse(mut query: Query,
+                     labels: Option<String>,
+                     correspondent: Option<String>,
+                     property: Option<String>) -> Result<Query, ApiError> {
+    for label in labels.iter().flat_map(|labels| labels.split(',')).map(str::trim).filter(|label| !label.is_empty()) {
+        query = query.and(Filter::Label(Label::from(label)));
+    }
+
+    if let Some(correspondent) = correspondent {
+        query = query.and(Filter::Correspondent(correspondent));
+    }
+
+    if let Some(property) = property {
+        let i = property.find('=')
+            .filter(|i| *i > 0)
+            .ok_or_else(|| ApiError::bad_request(format!("Invalid property filter: {}", property)))?;
+
+        query = query.and(Filter::Property {
+            key: property[..i].to_string(),
+            comparison: Comparison::Eq,
+            value: PropertyValue::parse(&property[i + 1..]),
+        });
+    }
+
+    return Ok(query);
+}
+
+pub(super) fn listing(sort: Option<String>,
+                      offset: Option<usize>,
+                      limit: Option<usize>) -> Result<Listing, ApiError> {
+    let sort = sort
+        .map(|sort| Sort::from_str(&sort))
+        .transpose()
+        .map_err(|err| ApiError::bad_request(format!("{:#}", err)))?;
+
+    return Ok(Listing::new(sort, offset, limit));
+}
+
+pub(super) fn grouping(group: Option<String>) -> Result<Option<Grouping>, ApiError> {
+    return group
+        .map(|group| match group.as_str() {
+            "correspondent" => Ok(Grouping::Correspondent),
+            "belongs_to" => Ok(Grouping::BelongsTo),
+            group => Err(ApiError::bad_request(format!("Unknown grouping: {}", group))),
+        })
+        .transpose();
+}
@@ -0,0 +1,102 @@
+use chrono::Utc;
+use log::info;
+use rocket::{post, State};
+use rocket_contrib::json::Json;
+
+use crate::crypto::Keyring;
+use crate::filing::Filing;
+use crate::merge::{Merger, Source};
+use crate::proto::api::merge::{MergeRequest, MergeResponse};
+use crate::proto::model::{DocId, Kind};
+use crate::repository::{Archived, Bundle, Inboxed, Repository};
+use crate::suggester::Suggester;
+
+use super::{ApiError, ensure_visible, Token};
+use super::inbox::archive_bundle;
+
+/// A bundle to be merged.
+enum Target<'r> {
+    Inbox(Bundle<'r, Inboxed>),
+    Archive(Bundle<'r, Archived>),
+}
+
+async fn resolve<'r>(repository: &'r Repository, id: DocId, token: &Token) -> Result<(Target<'r>, Source), ApiError> {
+    let (target, metadata, path) = if let Some(bundle) = repository.inbox().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        let path = bundle.path_of(Kind::Document);
+        (Target::Inbox(bundle), metadata, path)
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        let path = bundle.path_of(Kind::Document);
+        (Target::Archive(bundle), metadata, path)
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    };
+
+    ensure_visible(id, &metadata, token)?;
+
+    if let Some(domain) = &metadata.domain {
+        return Err(ApiError::bad_request(format!("Bundle is encrypted for domain {}: {}", domain, id)));
+    }
+
+    let plaintext = match &target {
+        Target::Inbox(bundle) => bundle.read_plaintext().await?,
+        Target::Archive(bundle) => bundle.read_plaintext().await?,
+    };
+
+    return Ok((target, Source { id, metadata, document: path, plaintext }));
+}
+
+/// Merges documents into a single archived document and moves the sources to the trash.
+///
+/// The pages of the merged document follow the order of the requested documents. Documents of encryption domains can
+/// not be merged, as their fragments are encrypted.
+#[post("/merge", data = "<data>")]
+pub(super) async fn merge(data: Json<MergeRequest>,
+                          repository: &'_ Repository,
+                          merger: &'_ Merger,
+                          suggester: State<'_, Box<dyn Suggester + Send + Sync>>,
+                          keyring: &'_ Keyring,
+                          filing: &'_ Filing,
+                          token: &'_ Token) -> Result<Json<MergeResponse>, ApiError> {
+    if data.ids.len() < 2 {
+        return Err(ApiError::bad_request(String::from("At least two documents are required")));
+    }
+
+    for (i, id) in data.ids.iter().enumerate() {
+        if data.ids[..i].contains(id) {
+            return Err(ApiError::bad_request(format!("Duplicate document: {}", id)));
+        }
+    }
+
+    let repository = repository.acting_as(token.subject());
+
+    let mut targets = Vec::with_capacity(data.ids.len());
+    let mut sources = Vec::with_capacity(data.ids.len());
+    for id in &data.ids {
+        let (target, source) = resolve(&repository, *id, token).await?;
+        targets.push(target);
+        sources.push(source);
+    }
+
+    let bundle = merger.merge(&repository, &sources).await?;
+    let id = *bundle.id();
+
+    let mut metadata = bundle.read_metadata().await?;
+    metadata.archived = Some(Utc::now());
+
+    archive_bundle(bundle, metadata.clone(), suggester.as_ref(), keyring, filing, token).await?;
+
+    for target in targets {
+        match target {
+            Target::Inbox(bundle) => bundle.delete().await?,
+            Target::Archive(bundle) => bundle.delete().await?,
+        };
+    }
+
+    info!("Merged {} documents into {}", data.ids.len(), id);
+
+    return Ok(Json(MergeResponse {
+        doc: (id, metadata).into(),
+    }));
+}
@@ -1,29 +1,234 @@
 use rocket::{Route, routes};
+use rocket::http::ContentType;
 
 pub(super) use auth::Authorization;
-pub(self) use auth::Token;
+pub(super) use graphql::schema as graphql_schema;
+pub(super) use namespace::{Namespace, Namespacing};
+pub use repositories::{Named, Repositories};
+pub use scans::Profiles;
+pub(super) use repositories::Scoping;
+use repositories::ScopedIndex;
+pub(super) use versions::Versioning;
+pub(self) use auth::{ensure_visible, Token};
 pub(self) use error::{ApiError, InternalError};
 
 pub(self) mod auth;
 pub(self) mod error;
 
 mod upload;
+mod scans;
 mod inbox;
 mod archive;
 mod labels;
+mod checklists;
+mod correspondents;
+mod persons;
+mod rules;
+mod trash;
+mod erase;
+mod quarantine;
+mod sync;
+mod changes;
+mod preferences;
+mod admin;
+mod events;
+mod queue;
+mod settings;
+mod domains;
+mod history;
+mod bulk;
+mod revisions;
+mod relations;
+mod resolve;
+mod filing;
+mod stats;
+mod due;
+mod confidence;
+mod requests;
+mod shares;
+mod suggestions;
+mod triage;
+mod undo;
+mod listing;
+mod namespace;
+mod ndjson;
+mod ranges;
+mod versions;
+mod openapi;
+mod merge;
+mod reprocess;
+mod export;
+mod attachments;
+mod fragments;
+mod pages;
+mod documents;
+mod attestations;
+mod verify;
+mod repositories;
+mod warranties;
+mod graphql;
 
 pub fn routes() -> Vec<Route> {
     routes![
         auth::login,
+        auth::enroll,
+        auth::confirm,
+        auth::disable,
+        auth::register_passkey,
+        auth::confirm_passkey,
+        auth::passkeys,
+        auth::remove_passkey,
+        auth::tokens,
+        auth::create_token,
+        auth::revoke_token,
+        auth::sessions,
+        auth::revoke_session,
         upload::upload_pdf,
+        upload::upload_xml,
+        upload::upload_office,
+        upload::upload_mail,
+        upload::begin_resumable,
+        upload::get_resumable,
+        upload::append_resumable,
+        upload::abort_resumable,
+        scans::begin,
+        scans::page,
+        scans::complete,
+        scans::abort,
         inbox::list,
+        inbox::streamed,
         inbox::bundle,
         inbox::fragment,
+        inbox::page,
+        inbox::snooze,
+        inbox::accept,
+        inbox::reject,
         inbox::delete,
         inbox::archive,
+        triage::first,
+        triage::decide,
+        archive::browse,
         archive::bundle,
         archive::fragment,
+        archive::page,
+        archive::send,
+        archive::delete,
         archive::search,
+        archive::similar,
+        archive::streamed,
+        trash::list,
+        trash::restore,
+        trash::purge,
+        erase::erase,
+        quarantine::list,
+        quarantine::inspect,
+        quarantine::sample,
+        quarantine::release,
+        quarantine::purge,
         labels::list,
+        labels::update,
+        labels::remove,
+        labels::rename,
+        correspondents::list,
+        correspondents::get,
+        correspondents::update,
+        correspondents::remove,
+        persons::list,
+        persons::get,
+        persons::update,
+        persons::remove,
+        checklists::list,
+        checklists::get,
+        checklists::update,
+        checklists::remove,
+        checklists::progress,
+        attestations::list,
+        attestations::get,
+        rules::list,
+        rules::update,
+        rules::remove,
+        sync::list,
+        sync::update,
+        sync::changes,
+        changes::changes,
+        preferences::list,
+        preferences::set,
+        preferences::remove,
+        admin::status,
+        admin::maintenance,
+        admin::reload,
+        events::stream,
+        queue::list,
+        queue::status,
+        queue::retry,
+        queue::discard,
+        settings::export,
+        settings::import,
+        domains::list,
+        domains::unlock,
+        domains::lock,
+        history::history,
+        bulk::bulk,
+        bulk::streamed,
+        revisions::list,
+        revisions::revert,
+        relations::list,
+        relations::create,
+        relations::remove,
+        resolve::resolve,
+        filing::sheet,
+        stats::stats,
+        stats::calendar,
+        stats::dashboard,
+        stats::quota,
+        due::list,
+        due::update,
+        due::contracts,
+        confidence::list,
+        requests::list,
+        requests::create,
+        requests::cancel,
+        requests::link,
+        requests::upload,
+        shares::list,
+        shares::create,
+        shares::revoke,
+        shares::link,
+        undo::undo,
+        suggestions::suggest,
+        merge::merge,
+        reprocess::reprocess,
+        export::export,
+        attachments::attach,
+        fragments::list,
+        fragments::download,
+        pages::edit,
+        documents::versions,
+        documents::version,
+        documents::replace,
+        verify::verify,
+        repositories::list,
+        warranties::list,
+        graphql::graphql,
     ]
 }
+
+/// Routes served at the API mount point independent of the version.
+pub fn unversioned() -> Vec<Route> {
+    routes![
+        versions::list,
+        openapi::openapi,
+    ]
+}
+
+/// Routes served outside of the API mount point.
+pub fn shortcuts() -> Vec<Route> {
+    routes![
+        resolve::redirect,
+    ]
+}
+
+/// Converts the MIME type of a fragment for a response.
+pub(self) fn content_type(mimetype: &str) -> ContentType {
+    return ContentType::parse_flexible(mimetype).unwrap_or(ContentType::Binary);
+}
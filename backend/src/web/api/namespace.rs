@@ -0,0 +1,88 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use percent_encoding::percent_decode_str;
+use rocket::{Data, Request};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+
+use crate::proto::model::{DocInfo, QualifiedId};
+
+/// The namespace qualifying the IDs of the documents held by this instance in federated setups.
+#[derive(Debug, Clone, Default)]
+pub struct Namespace(Option<String>);
+
+impl Namespace {
+    pub fn new(namespace: Option<String>) -> Result<Self> {
+        if let Some(namespace) = &namespace {
+            if !QualifiedId::is_valid_namespace(namespace) {
+                bail!("Invalid namespace: {}", namespace);
+            }
+        }
+
+        return Ok(Self(namespace));
+    }
+
+    /// Tags the document with the namespace of this instance.
+    pub(super) fn qualify(&self, doc: DocInfo) -> DocInfo {
+        return DocInfo { namespace: self.0.clone(), ..doc };
+    }
+
+    /// Returns the plain ID if the segment is an ID qualified by the namespace of this instance.
+    fn resolve(&self, segment: &str) -> Option<String> {
+        let namespace = self.0.as_deref()?;
+
+        let segment = percent_decode_str(segment).decode_utf8().ok()?;
+        let id = QualifiedId::from_str(&segment).ok()?;
+
+        return (id.namespace.as_deref() == Some(namespace)).then(|| id.id.to_string());
+    }
+}
+
+/// Resolves document IDs qualified by the namespace of this instance in request paths.
+///
+/// Qualified IDs are replaced by the plain ones before routing, so all endpoints accept both forms. IDs of other
+/// namespaces are left untouched and rejected as invalid by the endpoints.
+pub struct Namespacing(pub Namespace);
+
+#[async_trait]
+impl Fairing for Namespacing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Namespacing",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data) {
+        if self.0.0.is_none() || !request.uri().path().starts_with("/api/") {
+            return;
+        }
+
+        let mut resolved = false;
+        let path = request.uri().path().split('/')
+            .map(|segment| match self.0.resolve(segment) {
+                Some(id) => {
+                    resolved = true;
+                    id
+                }
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if !resolved {
+            return;
+        }
+
+        let uri = match request.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+
+        if let Ok(uri) = Origin::parse_owned(uri) {
+            request.set_uri(uri);
+        }
+    }
+}
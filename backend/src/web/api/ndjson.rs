@@ -0,0 +1,27 @@
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use log::error;
+use rocket::http::ContentType;
+use rocket::response::{Content, Stream as Body};
+use serde::Serialize;
+use tokio::io::AsyncRead;
+
+/// Encodes the records as newline delimited JSON while they are produced.
+///
+/// Failing records abort the response, so clients see a truncated body instead of a silently incomplete listing.
+pub(super) fn respond<'r, T: Serialize>(records: impl Stream<Item=anyhow::Result<T>> + Send + 'r) -> Content<Body<impl AsyncRead + 'r>> {
+    let body = records.map(|record| {
+        let record = record.map_err(|err| {
+            error!("Failed to stream listing: {:#}", err);
+            return std::io::Error::new(std::io::ErrorKind::Other, err.to_string());
+        })?;
+
+        let mut data = serde_json::to_vec(&record)?;
+        data.push(b'\n');
+
+        return Ok(Bytes::from(data));
+    });
+
+    return Content(ContentType::new("application", "x-ndjson"),
+                   Body::from(tokio::io::stream_reader(body)));
+}
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use rocket::get;
+use rocket_contrib::json::Json;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use schemars::schema::Schema;
+use serde_json::{json, Map, Value};
+
+use crate::proto::api;
+use crate::proto::model::{Checklist, Correspondent, DocInfo, Person, Proposal, Relation};
+
+/// Generates the schema of a JSON body.
+type Generate = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    return gen.subschema_for::<T>();
+}
+
+/// The body of a request or response.
+enum Body {
+    Empty,
+    Json(Generate),
+
+    /// Raw data of the given content type
+    Raw(&'static str),
+}
+
+/// An operation of the API, along with the types it takes and returns.
+struct Operation {
+    method: &'static str,
+
+    /// The path as routed, including path and query parameters, like `/inbox/<id>?<size>`
+    path: &'static str,
+
+    summary: &'static str,
+    request: Body,
+    response: Body,
+}
+
+impl Operation {
+    fn new(method: &'static str, path: &'static str, summary: &'static str, request: Body, response: Body) -> Self {
+        return Self { method, path, summary, request, response };
+    }
+}
+
+/// The operations described by the specification.
+///
+/// Keep this in sync with the routes when adding or changing endpoints.
+fn operations() -> Vec<Operation> {
+    return vec![
+        Operation::new("post", "/auth/login", "Log in by password", Body::Json(schema::<api::auth::AuthRequest>), Body::Empty),
+        Operation::new("post", "/auth/2fa", "Enroll two-factor authentication", Body::Empty, Body::Json(schema::<api::auth::EnrollResponse>)),
+        Operation::new("post", "/auth/2fa/confirm", "Confirm two-factor authentication", Body::Json(schema::<api::auth::CodeRequest>), Body::Json(schema::<api::auth::ConfirmResponse>)),
+        Operation::new("delete", "/auth/2fa", "Disable two-factor authentication", Body::Json(schema::<api::auth::CodeRequest>), Body::Empty),
+        Operation::new("post", "/auth/passkeys", "Begin registering a passkey", Body::Empty, Body::Json(schema::<api::auth::RegisterPasskeyResponse>)),
+        Operation::new("post", "/auth/passkeys/confirm", "Complete registering a passkey", Body::Json(schema::<api::auth::ConfirmPasskeyRequest>), Body::Empty),
+        Operation::new("get", "/auth/passkeys", "List passkeys", Body::Empty, Body::Json(schema::<api::auth::PasskeysResponse>)),
+        Operation::new("delete", "/auth/passkeys/<id>", "Remove a passkey", Body::Empty, Body::Empty),
+        Operation::new("get", "/auth/tokens", "List API tokens", Body::Empty, Body::Json(schema::<api::auth::TokensResponse>)),
+        Operation::new("post", "/auth/tokens", "Create an API token", Body::Json(schema::<api::auth::CreateTokenRequest>), Body::Json(schema::<api::auth::CreateTokenResponse>)),
+        Operation::new("delete", "/auth/tokens/<id>", "Revoke an API token", Body::Empty, Body::Empty),
+        Operation::new("get", "/auth/sessions", "List sessions", Body::Empty, Body::Json(schema::<api::auth::SessionsResponse>)),
+        Operation::new("delete", "/auth/sessions/<id>", "Revoke a session", Body::Empty, Body::Empty),
+        Operation::new("post", "/upload?<filename>&<sha256>&<languages>", "Upload a document", Body::Raw("application/pdf"), Body::Json(schema::<api::upload::UploadResponse>)),
+        Operation::new("post", "/uploads?<filename>&<length>&<sha256>", "Begin a resumable upload", Body::Empty, Body::Json(schema::<api::upload::ResumableInfo>)),
+        Operation::new("get", "/uploads/<id>", "Get the state of a resumable upload", Body::Empty, Body::Json(schema::<api::upload::ResumableInfo>)),
+        Operation::new("patch", "/uploads/<id>?<offset>", "Continue a resumable upload", Body::Raw("application/offset+octet-stream"), Body::Json(schema::<api::upload::ResumableInfo>)),
+        Operation::new("delete", "/uploads/<id>", "Abort a resumable upload", Body::Empty, Body::Empty),
+        Operation::new("post", "/scans?<profile>&<languages>", "Begin a scan assembled page by page", Body::Empty, Body::Json(schema::<api::scans::ScanInfo>)),
+        Operation::new("post", "/scans/<id>/pages", "Add the next page to a scan", Body::Raw("image/*"), Body::Json(schema::<api::scans::ScanInfo>)),
+        Operation::new("post", "/scans/<id>", "Complete a scan", Body::Empty, Body::Json(schema::<api::scans::ScanInfo>)),
+        Operation::new("delete", "/scans/<id>", "Abort a scan", Body::Empty, Body::Empty),
+        Operation::new("get", "/inbox?<query>&<label>&<from>&<to>&<sort>&<group>&<snoozed>&<offset>&<limit>", "List the documents in the inbox", Body::Empty, Body::Json(schema::<api::inbox::ListResponse>)),
+        Operation::new("get", "/inbox/<id>", "Get a document in the inbox", Body::Empty, Body::Json(schema::<api::inbox::GetResponse>)),
+        Operation::new("get", "/inbox/<id>/<fragment>", "Get a fragment of a document in the inbox", Body::Empty, Body::Raw("application/octet-stream")),
+        Operation::new("get", "/inbox/<id>/preview/<page>?<size>", "Get the preview of a page of a document in the inbox", Body::Empty, Body::Raw("image/png")),
+        Operation::new("put", "/inbox/<id>/snooze", "Snooze a document in the inbox", Body::Json(schema::<api::inbox::SnoozeRequest>), Body::Json(schema::<DocInfo>)),
+        Operation::new("post", "/inbox/<id>/proposal", "Accept the proposed metadata of a document in the inbox", Body::Json(schema::<Proposal>), Body::Json(schema::<DocInfo>)),
+        Operation::new("delete", "/inbox/<id>/proposal", "Reject the proposed metadata of a document in the inbox", Body::Empty, Body::Json(schema::<DocInfo>)),
+        Operation::new("delete", "/inbox/<id>", "Delete a document in the inbox", Body::Empty, Body::Json(schema::<api::undo::UndoInfo>)),
+        Operation::new("post", "/inbox/<id>", "Archive a document in the inbox", Body::Json(schema::<api::inbox::ArchiveRequest>), Body::Json(schema::<api::undo::UndoInfo>)),
+        Operation::new("get", "/triage?<after>", "Get the next document to triage", Body::Empty, Body::Json(schema::<api::triage::NextResponse>)),
+        Operation::new("post", "/triage/<id>", "Decide on a document to triage", Body::Json(schema::<api::triage::Decision>), Body::Json(schema::<api::triage::DecisionResponse>)),
+        Operation::new("get", "/archive?<query>&<label>&<from>&<to>&<sort>&<offset>&<limit>", "Search the archive", Body::Empty, Body::Json(schema::<api::archive::SearchResponse>)),
+        Operation::new("get", "/archive/browse?<query>&<label>&<labels>&<correspondent>&<property>&<from>&<to>&<sort>&<group>&<offset>&<limit>", "Browse the archive by metadata", Body::Empty, Body::Json(schema::<api::archive::BrowseResponse>)),
+        Operation::new("get", "/archive/<id>", "Get an archived document", Body::Empty, Body::Json(schema::<api::archive::BundleResponse>)),
+        Operation::new("get", "/archive/<id>/similar?<offset>&<limit>", "Find similar archived documents", Body::Empty, Body::Json(schema::<api::archive::SearchResponse>)),
+        Operation::new("get", "/archive/<id>/<fragment>", "Get a fragment of an archived document", Body::Empty, Body::Raw("application/octet-stream")),
+        Operation::new("get", "/archive/<id>/preview/<page>?<size>", "Get the preview of a page of an archived document", Body::Empty, Body::Raw("image/png")),
+        Operation::new("post", "/archive/<id>/send", "Send an archived document by mail", Body::Json(schema::<api::mail::SendRequest>), Body::Empty),
+        Operation::new("delete", "/archive/<id>", "Delete an archived document", Body::Empty, Body::Json(schema::<api::undo::UndoInfo>)),
+        Operation::new("get", "/trash", "List the documents in the trash", Body::Empty, Body::Json(schema::<api::trash::ListResponse>)),
+        Operation::new("post", "/trash/<id>/restore", "Restore a document from the trash", Body::Empty, Body::Empty),
+        Operation::new("delete", "/trash/<id>", "Purge a document from the trash", Body::Empty, Body::Empty),
+        Operation::new("post", "/erase/<id>", "Erase a document irrecoverably", Body::Empty, Body::Json(schema::<api::history::HistoryEntry>)),
+        Operation::new("get", "/quarantine", "List the quarantined documents", Body::Empty, Body::Json(schema::<api::quarantine::ListResponse>)),
+        Operation::new("get", "/quarantine/<id>", "Inspect a quarantined document", Body::Empty, Body::Json(schema::<api::quarantine::InspectResponse>)),
+        Operation::new("get", "/quarantine/<id>/sample?<password>", "Get a sample of a quarantined document", Body::Empty, Body::Raw("application/zip")),
+        Operation::new("post", "/quarantine/<id>/release", "Release a quarantined document", Body::Empty, Body::Empty),
+        Operation::new("delete", "/quarantine/<id>", "Purge a quarantined document", Body::Empty, Body::Empty),
+        Operation::new("get", "/labels", "List the labels", Body::Empty, Body::Json(schema::<api::labels::ListResponse>)),
+        Operation::new("put", "/labels/<label>", "Update a label", Body::Json(schema::<api::labels::UpdateRequest>), Body::Empty),
+        Operation::new("delete", "/labels/<label>", "Remove a label", Body::Empty, Body::Empty),
+        Operation::new("post", "/labels/<label>/rename", "Rename a label", Body::Json(schema::<api::labels::RenameRequest>), Body::Json(schema::<api::labels::RenameResponse>)),
+        Operation::new("get", "/correspondents", "List the correspondents", Body::Empty, Body::Json(schema::<api::correspondents::ListResponse>)),
+        Operation::new("get", "/correspondents/<name>", "Get a correspondent", Body::Empty, Body::Json(schema::<Correspondent>)),
+        Operation::new("put", "/correspondents/<name>", "Update a correspondent", Body::Json(schema::<api::correspondents::UpdateRequest>), Body::Empty),
+        Operation::new("delete", "/correspondents/<name>", "Remove a correspondent", Body::Empty, Body::Empty),
+        Operation::new("get", "/persons", "List the persons", Body::Empty, Body::Json(schema::<api::persons::ListResponse>)),
+        Operation::new("get", "/persons/<name>", "Get a person", Body::Empty, Body::Json(schema::<Person>)),
+        Operation::new("put", "/persons/<name>", "Update a person", Body::Json(schema::<api::persons::UpdateRequest>), Body::Empty),
+        Operation::new("delete", "/persons/<name>", "Remove a person", Body::Empty, Body::Empty),
+        Operation::new("get", "/checklists", "List the checklists", Body::Empty, Body::Json(schema::<api::checklists::ListResponse>)),
+        Operation::new("get", "/checklists/<name>", "Get a checklist", Body::Empty, Body::Json(schema::<Checklist>)),
+        Operation::new("put", "/checklists/<name>", "Update a checklist", Body::Json(schema::<api::checklists::UpdateRequest>), Body::Empty),
+        Operation::new("delete", "/checklists/<name>", "Remove a checklist", Body::Empty, Body::Empty),
+        Operation::new("get", "/checklists/<name>/progress?<year>", "Get the progress of a checklist", Body::Empty, Body::Json(schema::<api::checklists::ProgressResponse>)),
+        Operation::new("get", "/attestations", "List the attestations", Body::Empty, Body::Json(schema::<api::attestations::ListResponse>)),
+        Operation::new("get", "/attestations/<name>", "Get an attestation", Body::Empty, Body::Raw("application/octet-stream")),
+        Operation::new("get", "/rules", "List the rules", Body::Empty, Body::Json(schema::<api::rules::ListResponse>)),
+        Operation::new("put", "/rules/<name>", "Update a rule", Body::Json(schema::<api::rules::UpdateRequest>), Body::Empty),
+        Operation::new("delete", "/rules/<name>", "Remove a rule", Body::Empty, Body::Empty),
+        Operation::new("get", "/sync?<labels>", "List the documents to sync", Body::Empty, Body::Json(schema::<api::sync::ListResponse>)),
+        Operation::new("put", "/sync/<id>", "Update a synced document", Body::Json(schema::<api::sync::UpdateRequest>), Body::Json(schema::<api::sync::UpdateResponse>)),
+        Operation::new("get", "/sync/changes?<cursor>", "List the changes to sync", Body::Empty, Body::Json(schema::<api::sync::ChangesResponse>)),
+        Operation::new("get", "/changes?<since>&<limit>", "List the changes of documents", Body::Empty, Body::Json(schema::<api::changes::ChangesResponse>)),
+        Operation::new("get", "/preferences", "List the preferences", Body::Empty, Body::Json(schema::<BTreeMap<String, Value>>)),
+        Operation::new("put", "/preferences/<key>", "Set a preference", Body::Json(schema::<Value>), Body::Empty),
+        Operation::new("delete", "/preferences/<key>", "Remove a preference", Body::Empty, Body::Empty),
+        Operation::new("get", "/admin/status", "Get the status of the instance", Body::Empty, Body::Json(schema::<api::admin::StatusResponse>)),
+        Operation::new("get", "/admin/maintenance", "List the scheduled maintenance tasks", Body::Empty, Body::Json(schema::<api::admin::MaintenanceResponse>)),
+        Operation::new("post", "/admin/reload", "Reload the configuration without a restart", Body::Empty, Body::Json(schema::<api::admin::ReloadResponse>)),
+        Operation::new("get", "/events", "Stream events", Body::Empty, Body::Raw("text/event-stream")),
+        Operation::new("get", "/queue", "List the jobs in the queue", Body::Empty, Body::Json(schema::<api::queue::ListResponse>)),
+        Operation::new("get", "/queue/<id>", "Get the processing status of an uploaded document", Body::Empty, Body::Json(schema::<api::queue::StatusResponse>)),
+        Operation::new("post", "/queue/<id>/retry?<juicer>", "Retry a failed job", Body::Empty, Body::Empty),
+        Operation::new("delete", "/queue/<id>", "Discard a failed job", Body::Empty, Body::Empty),
+        Operation::new("get", "/settings", "Export the settings", Body::Empty, Body::Json(schema::<Value>)),
+        Operation::new("put", "/settings", "Import the settings", Body::Json(schema::<Value>), Body::Empty),
+        Operation::new("get", "/domains", "List the encryption domains", Body::Empty, Body::Json(schema::<api::domains::ListResponse>)),
+        Operation::new("post", "/domains/<name>/unlock", "Unlock an encryption domain", Body::Json(schema::<api::domains::UnlockRequest>), Body::Empty),
+        Operation::new("post", "/domains/<name>/lock", "Lock an encryption domain", Body::Empty, Body::Empty),
+        Operation::new("get", "/history/<id>", "Get the history of a document", Body::Empty, Body::Json(schema::<api::history::HistoryResponse>)),
+        Operation::new("post", "/bulk", "Apply an operation to many documents", Body::Json(schema::<api::bulk::BulkRequest>), Body::Json(schema::<api::bulk::BulkResponse>)),
+        Operation::new("post", "/bulk/stream", "Apply an operation to many documents, streaming the progress", Body::Json(schema::<api::bulk::BulkRequest>), Body::Raw("application/x-ndjson")),
+        Operation::new("get", "/revisions/<id>", "List the revisions of a document", Body::Empty, Body::Json(schema::<api::revisions::ListResponse>)),
+        Operation::new("post", "/revisions/<id>/<revision>/revert", "Revert a document to a revision", Body::Empty, Body::Json(schema::<api::revisions::RevertResponse>)),
+        Operation::new("get", "/relations/<id>", "List the relations of a document", Body::Empty, Body::Json(schema::<api::relations::ListResponse>)),
+        Operation::new("post", "/relations/<id>", "Relate a document to another one", Body::Json(schema::<Relation>), Body::Empty),
+        Operation::new("delete", "/relations/<id>/<kind>/<target>", "Remove a relation", Body::Empty, Body::Empty),
+        Operation::new("get", "/resolve/<code>", "Resolve a paper code", Body::Empty, Body::Json(schema::<api::resolve::ResolveResponse>)),
+        Operation::new("post", "/filing/sheet", "Create a filing sheet", Body::Json(schema::<api::filing::SheetRequest>), Body::Raw("application/pdf")),
+        Operation::new("get", "/stats?<query>&<amount>&<date>", "Get statistics of documents", Body::Empty, Body::Json(schema::<api::stats::StatsResponse>)),
+        Operation::new("get", "/stats/calendar?<from>&<to>&<query>&<date>", "Get the documents per day", Body::Empty, Body::Json(schema::<api::stats::CalendarResponse>)),
+        Operation::new("get", "/stats/dashboard", "Get the summary of the archive for the dashboard", Body::Empty, Body::Json(schema::<api::stats::DashboardResponse>)),
+        Operation::new("get", "/stats/quota", "Get the storage used by the repository and the user along with their quotas", Body::Empty, Body::Json(schema::<api::stats::QuotaResponse>)),
+        Operation::new("get", "/due?<days>", "List the documents due", Body::Empty, Body::Json(schema::<api::due::ListResponse>)),
+        Operation::new("put", "/due/<id>", "Update the due date of a document", Body::Json(schema::<api::due::UpdateRequest>), Body::Empty),
+        Operation::new("get", "/contracts", "List the running contracts", Body::Empty, Body::Json(schema::<api::due::ListResponse>)),
+        Operation::new("get", "/confidence?<threshold>", "List the documents with a low OCR confidence", Body::Empty, Body::Json(schema::<api::confidence::ListResponse>)),
+        Operation::new("get", "/warranties", "List the running warranties", Body::Empty, Body::Json(schema::<api::warranties::ListResponse>)),
+        Operation::new("get", "/requests", "List the document requests", Body::Empty, Body::Json(schema::<api::requests::ListResponse>)),
+        Operation::new("post", "/requests", "Request a document", Body::Json(schema::<api::requests::CreateRequest>), Body::Json(schema::<api::requests::RequestInfo>)),
+        Operation::new("delete", "/requests/<id>", "Cancel a document request", Body::Empty, Body::Empty),
+        Operation::new("get", "/requests/link/<token>", "Get a document request by its link", Body::Empty, Body::Json(schema::<api::requests::LinkResponse>)),
+        Operation::new("post", "/requests/link/<token>", "Upload a requested document", Body::Raw("application/pdf"), Body::Json(schema::<api::upload::UploadResponse>)),
+        Operation::new("get", "/shares", "List the share links", Body::Empty, Body::Json(schema::<api::shares::ListResponse>)),
+        Operation::new("post", "/shares", "Share a document by a signed link", Body::Json(schema::<api::shares::CreateRequest>), Body::Json(schema::<api::shares::ShareInfo>)),
+        Operation::new("delete", "/shares/<id>", "Revoke a share link", Body::Empty, Body::Empty),
+        Operation::new("get", "/shares/link/<id>/<signature>", "Get a shared document by its link", Body::Empty, Body::Raw("application/octet-stream")),
+        Operation::new("post", "/undo/<operation>", "Undo an operation", Body::Empty, Body::Json(schema::<api::undo::UndoResponse>)),
+        Operation::new("get", "/suggestions/<id>", "Get suggestions for a document", Body::Empty, Body::Json(schema::<api::suggestions::SuggestionsResponse>)),
+        Operation::new("post", "/merge", "Merge documents", Body::Json(schema::<api::merge::MergeRequest>), Body::Json(schema::<api::merge::MergeResponse>)),
+        Operation::new("post", "/reprocess/<id>", "Reprocess a document", Body::Empty, Body::Empty),
+        Operation::new("get", "/export?<ids>", "Export documents", Body::Empty, Body::Raw("application/x-tar")),
+        Operation::new("post", "/attachments/<id>/<name>", "Attach a file to a document", Body::Raw("application/octet-stream"), Body::Json(schema::<api::attachments::AttachResponse>)),
+        Operation::new("get", "/fragments/<id>", "List the fragments of a document", Body::Empty, Body::Json(schema::<api::fragments::ListResponse>)),
+        Operation::new("get", "/fragments/<id>/<name>", "Get an additional fragment of a document", Body::Empty, Body::Raw("application/octet-stream")),
+        Operation::new("post", "/pages/<id>", "Rotate, reorder and drop pages of a document", Body::Json(schema::<api::pages::EditRequest>), Body::Json(schema::<api::pages::EditResponse>)),
+        Operation::new("get", "/documents/<id>/versions", "List the prior versions of a document", Body::Empty, Body::Json(schema::<api::documents::VersionsResponse>)),
+        Operation::new("get", "/documents/<id>/versions/<version>", "Get a prior version of a document", Body::Empty, Body::Raw("application/pdf")),
+        Operation::new("post", "/documents/<id>?<reason>", "Replace a document keeping the prior version", Body::Raw("application/pdf"), Body::Json(schema::<api::documents::ReplaceResponse>)),
+        Operation::new("get", "/verify/<id>", "Verify the integrity of a document", Body::Empty, Body::Json(schema::<api::verify::VerifyResponse>)),
+        Operation::new("get", "/repositories", "List the named repositories", Body::Empty, Body::Json(schema::<api::repositories::ListResponse>)),
+    ];
+}
+
+/// Builds the content of a request or response body, if any.
+fn content(body: &Body, gen: &mut SchemaGenerator) -> Option<Value> {
+    return match body {
+        Body::Empty => None,
+        Body::Json(generate) => Some(json!({ "application/json": { "schema": generate(gen) } })),
+        Body::Raw(content_type) => {
+            let mut content = Map::new();
+            content.insert(content_type.to_string(), json!({ "schema": { "type": "string", "format": "binary" } }));
+            Some(Value::Object(content))
+        }
+    };
+}
+
+/// Builds the description of an operation.
+fn operation(operation: &Operation, gen: &mut SchemaGenerator) -> (String, Value) {
+    let (path, query) = match operation.path.find('?') {
+        Some(index) => (&operation.path[..index], &operation.path[index + 1..]),
+        None => (operation.path, ""),
+    };
+
+    let mut parameters = Vec::new();
+
+    let path = path.split('/')
+        .map(|segment| match segment.strip_prefix('<').and_then(|segment| segment.strip_suffix('>')) {
+            Some(name) => {
+                parameters.push(json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } }));
+                format!("{{{}}}", name)
+            }
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    for name in query.split('&').filter_map(|param| param.strip_prefix('<')?.strip_suffix('>')) {
+        parameters.push(json!({ "name": name, "in": "query", "required": false, "schema": { "type": "string" } }));
+    }
+
+    let mut response = json!({ "description": "Success" });
+    if let Some(content) = content(&operation.response, gen) {
+        response["content"] = content;
+    }
+
+    let mut description = json!({
+        "summary": operation.summary,
+        "parameters": parameters,
+        "responses": {
+            "200": response,
+            "default": { "$ref": "#/components/responses/Error" },
+        },
+    });
+
+    if let Some(content) = content(&operation.request, gen) {
+        description["requestBody"] = json!({ "required": true, "content": content });
+    }
+
+    return (path, description);
+}
+
+/// Builds the OpenAPI specification of the current API version.
+pub(super) fn spec() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    let mut paths = Map::new();
+    for op in operations() {
+        let (path, description) = operation(&op, &mut gen);
+        if let Value::Object(path) = paths.entry(path).or_insert_with(|| json!({})) {
+            path.insert(op.method.to_string(), description);
+        }
+    }
+
+    // Logging in is the only operation not requiring authorization
+    if let Some(login) = paths.get_mut("/auth/login").and_then(|path| path.get_mut("post")) {
+        login["security"] = json!([]);
+    }
+
+    return json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Adacta",
+            "description": "Personal Document Archiving",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [
+            { "url": super::versions::current() },
+        ],
+        "security": [
+            { "token": [] },
+        ],
+        "paths": paths,
+        "components": {
+            "schemas": gen.definitions(),
+            "securitySchemes": {
+                "token": { "type": "http", "scheme": "bearer" },
+            },
+            "responses": {
+                "Error": {
+                    "description": "Failure",
+                    "content": { "text/plain": { "schema": { "type": "string" } } },
+                },
+            },
+        },
+    });
+}
+
+/// Serves the OpenAPI specification of the API, so clients can be generated from it.
+///
+/// This is served at the API mount point without authorization, like the versions.
+#[get("/openapi.json")]
+pub(super) async fn openapi() -> Json<Value> {
+    Json(spec())
+}
@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use log::info;
+use rocket::post;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::editing::{self, Editor, Page};
+use crate::proto::api::pages::{EditRequest, EditResponse};
+use crate::proto::model::DocId;
+use crate::repository::Repository;
+
+use super::{ApiError, ensure_visible, ScopedIndex, Token};
+
+/// Rotates, reorders and drops the pages of an archived document.
+///
+/// The document is assembled from the given pages in the given order and processed again to regenerate its text and
+/// previews. The current document is kept as prior version. Documents of encryption domains can not be edited, as
+/// their fragments are encrypted.
+#[post("/pages/<id>", data = "<data>")]
+pub(super) async fn edit(id: &RawStr,
+                         data: Json<EditRequest>,
+                         repository: &'_ Repository,
+                         editor: &'_ Editor,
+                         index: ScopedIndex,
+                         token: &'_ Token) -> Result<Json<EditResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.archive().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    if let Some(domain) = &metadata.domain {
+        return Err(ApiError::bad_request(format!("Bundle is encrypted for domain {}: {}", domain, id)));
+    }
+
+    let pages = data.pages.iter()
+        .map(|page| Page { page: page.page, rotate: page.rotate })
+        .collect::<Vec<_>>();
+
+    editing::validate(&pages, metadata.pages)
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+
+    let version = editor.edit(&repository, &bundle, &pages).await?;
+
+    index.index(&bundle).await?;
+
+    info!("Edited pages of bundle {}, keeping version {}", id, version.version);
+
+    let metadata = bundle.read_metadata().await?;
+
+    return Ok(Json(EditResponse {
+        doc: (id, metadata).into(),
+        version: version.version,
+    }));
+}
@@ -0,0 +1,69 @@
+use rocket::{delete, get, put};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::persons::Persons;
+use crate::proto::api::persons::{ListResponse, UpdateRequest};
+use crate::proto::model::Person;
+
+use super::{ApiError, Token};
+
+fn parse(name: &RawStr) -> Result<String, ApiError> {
+    return name.url_decode()
+        .map(|name| name.into_owned())
+        .map_err(|err| ApiError::bad_request(err.to_string()));
+}
+
+#[get("/persons")]
+pub(super) async fn list(persons: &'_ Persons,
+                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    Ok(Json(ListResponse {
+        persons: persons.list().await,
+    }))
+}
+
+#[get("/persons/<name>")]
+pub(super) async fn get(name: &RawStr,
+                        persons: &'_ Persons,
+                        _token: &'_ Token) -> Result<Json<Person>, ApiError> {
+    let name = parse(name)?;
+
+    let person = persons.get(&name).await
+        .ok_or_else(|| ApiError::not_found(format!("Person not found: {}", name)))?;
+
+    Ok(Json(person))
+}
+
+#[put("/persons/<name>", data = "<request>")]
+pub(super) async fn update(name: &RawStr,
+                           request: Json<UpdateRequest>,
+                           persons: &'_ Persons,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let request = request.into_inner();
+
+    let person = Person {
+        name: parse(name)?,
+        relationship: request.relationship,
+    };
+
+    if !person.is_valid() {
+        return Err(ApiError::bad_request(format!("Invalid person name: {}", person.name)));
+    }
+
+    persons.define(person).await?;
+
+    return Ok(());
+}
+
+#[delete("/persons/<name>")]
+pub(super) async fn remove(name: &RawStr,
+                           persons: &'_ Persons,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let name = parse(name)?;
+
+    if !persons.remove(&name).await? {
+        return Err(ApiError::not_found(format!("Person not found: {}", name)));
+    }
+
+    return Ok(());
+}
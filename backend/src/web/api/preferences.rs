@@ -0,0 +1,36 @@
+use rocket::{delete, get, put, State};
+use rocket_contrib::json::Json;
+use serde_json::Value;
+
+use crate::preferences::{Preferences, Values};
+
+use super::{ApiError, Token};
+
+#[get("/preferences")]
+pub(super) async fn list(preferences: State<'_, Preferences>,
+                         token: &'_ Token) -> Result<Json<Values>, ApiError> {
+    let values = preferences.get(token.subject()).await?;
+
+    Ok(Json(values))
+}
+
+#[put("/preferences/<key>", data = "<value>")]
+pub(super) async fn set(key: String,
+                        value: Json<Value>,
+                        preferences: State<'_, Preferences>,
+                        token: &'_ Token) -> Result<(), ApiError> {
+    preferences.set(token.subject(), key, value.into_inner()).await?;
+
+    return Ok(());
+}
+
+#[delete("/preferences/<key>")]
+pub(super) async fn remove(key: String,
+                           preferences: State<'_, Preferences>,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    if !preferences.remove(token.subject(), &key).await? {
+        return Err(ApiError::not_found(format!("Preference not found: {}", key)));
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+use rocket::{delete, get, post};
+use rocket::http::{ContentType, RawStr};
+use rocket::response::Content;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::quarantine::{FragmentInfo, InspectResponse, ListResponse, QuarantinedDoc, ReviewAction};
+use crate::proto::model::{DocId, Kind};
+use crate::quarantine::{self, Reviews};
+use crate::queue::Queue;
+use crate::repository::{Bundle, Quarantined, Repository, sha256};
+use crate::uploads::{Scan, Upload};
+
+use super::{ApiError, Token};
+
+async fn quarantined<'r>(repository: &'r Repository, id: &RawStr) -> Result<Bundle<'r, Quarantined>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    return repository.quarantine().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)));
+}
+
+async fn info(bundle: &Bundle<'_, Quarantined>) -> Result<QuarantinedDoc, ApiError> {
+    let reason = bundle.reason().await?;
+
+    return Ok(QuarantinedDoc {
+        id: *bundle.id(),
+        quarantined: reason.quarantined,
+        reason: reason.reason,
+    });
+}
+
+#[get("/quarantine")]
+pub(super) async fn list(repository: &'_ Repository,
+                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let bundles = repository.quarantine().list().await?;
+
+    let mut docs = Vec::with_capacity(bundles.len());
+    for bundle in bundles {
+        docs.push(info(&bundle).await?);
+    }
+
+    Ok(Json(ListResponse {
+        count: docs.len() as u64,
+        docs,
+    }))
+}
+
+/// Describes the fragments of a quarantined bundle without opening them.
+#[get("/quarantine/<id>")]
+pub(super) async fn inspect(id: &RawStr,
+                            repository: &'_ Repository,
+                            reviews: &'_ Reviews,
+                            token: &'_ Token) -> Result<Json<InspectResponse>, ApiError> {
+    let bundle = quarantined(&repository, id).await?;
+
+    let mut names = bundle.fragment_names().await?;
+    names.sort();
+
+    let mut fragments = Vec::with_capacity(names.len());
+    for name in names {
+        let path = bundle.path().join(&name);
+        fragments.push(FragmentInfo {
+            size: tokio::fs::metadata(&path).await?.len(),
+            sha256: sha256(&path).await?,
+            name,
+        });
+    }
+
+    reviews.record(*bundle.id(), token.subject(), ReviewAction::Inspected).await?;
+
+    Ok(Json(InspectResponse {
+        doc: info(&bundle).await?,
+        fragments,
+        reviews: reviews.of(*bundle.id()).await?,
+    }))
+}
+
+/// Downloads all fragments of a quarantined bundle as password protected ZIP archive.
+#[get("/quarantine/<id>/sample?<password>")]
+pub(super) async fn sample(id: &RawStr,
+                           password: Option<String>,
+                           repository: &'_ Repository,
+                           reviews: &'_ Reviews,
+                           token: &'_ Token) -> Result<Content<Vec<u8>>, ApiError> {
+    let bundle = quarantined(&repository, id).await?;
+
+    let password = password.unwrap_or_else(|| String::from(quarantine::PASSWORD));
+    if password.is_empty() {
+        return Err(ApiError::bad_request(String::from("Password must not be empty")));
+    }
+
+    let sample = quarantine::sample(&bundle, &password).await?;
+
+    reviews.record(*bundle.id(), token.subject(), ReviewAction::Downloaded).await?;
+
+    return Ok(Content(ContentType::ZIP, sample));
+}
+
+/// Moves a quarantined bundle back to the staging area.
+///
+/// Abandoned uploads and scans can be continued afterwards, all other bundles are queued for juicing.
+#[post("/quarantine/<id>/release")]
+pub(super) async fn release(id: &RawStr,
+                            repository: &'_ Repository,
+                            queue: &'_ Queue,
+                            reviews: &'_ Reviews,
+                            token: &'_ Token) -> Result<(), ApiError> {
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = quarantined(&repository, id).await?;
+    let id = *bundle.id();
+
+    let staged = bundle.release().await?;
+
+    reviews.record(id, token.subject(), ReviewAction::Released).await?;
+
+    let pending = tokio::fs::metadata(staged.path_of(Kind::other(Upload::FRAGMENT))).await.is_ok()
+        || tokio::fs::metadata(staged.path_of(Kind::other(Scan::FRAGMENT))).await.is_ok();
+    if !pending {
+        queue.enqueue(staged).await?;
+    }
+
+    return Ok(());
+}
+
+#[delete("/quarantine/<id>")]
+pub(super) async fn purge(id: &RawStr,
+                          repository: &'_ Repository,
+                          reviews: &'_ Reviews,
+                          token: &'_ Token) -> Result<(), ApiError> {
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = quarantined(&repository, id).await?;
+    let id = *bundle.id();
+
+    bundle.purge().await?;
+
+    reviews.record(id, token.subject(), ReviewAction::Purged).await?;
+
+    return Ok(());
+}
@@ -0,0 +1,134 @@
+use std::str::FromStr;
+
+use rocket::{delete, get, post};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::juicer::report::Failure;
+use crate::proto::api::queue::{BundleStatus, FailureInfo, JobInfo, ListResponse, StatusResponse};
+use crate::proto::model::DocId;
+use crate::queue::{Job, JobState, Queue};
+use crate::repository::{Bundle, Failed, Repository};
+
+use super::{ApiError, ensure_visible, Token};
+
+fn failure_info(failure: Failure) -> FailureInfo {
+    return FailureInfo {
+        kind: failure.kind.name().to_string(),
+        message: failure.description().to_string(),
+        details: failure.details,
+    };
+}
+
+async fn failed<'r>(repository: &'r Repository, id: &RawStr) -> Result<Bundle<'r, Failed>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    return repository.failed().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Failed job not found: {}", id)));
+}
+
+#[get("/queue")]
+pub(super) async fn list(queue: &'_ Queue,
+                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let jobs = queue.jobs().await?.into_iter()
+        .map(|(id, job)| JobInfo {
+            id,
+            attempts: job.attempts,
+            queued: job.queued,
+            failure: job.failed.map(failure_info),
+        })
+        .collect();
+
+    Ok(Json(ListResponse { jobs }))
+}
+
+/// Reports how far an uploaded document has been processed, so clients can show the progress of their uploads.
+#[get("/queue/<id>")]
+pub(super) async fn status(id: &RawStr,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<StatusResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let status = |status, attempts, failure| Json(StatusResponse { id, status, attempts, failure });
+
+    if let Some(bundle) = repository.staging().get(id).await {
+        // The metadata of bundles still being uploaded may be missing
+        if let Ok(metadata) = bundle.read_metadata().await {
+            ensure_visible(id, &metadata, token)?;
+        }
+
+        return Ok(match Job::load(&bundle).await? {
+            Some(Job { attempts, failed: Some(failure), .. }) => status(BundleStatus::Failed, attempts, Some(failure_info(failure))),
+            Some(Job { attempts, state: JobState::Juicing, .. }) => status(BundleStatus::Juicing, attempts, None),
+            Some(Job { attempts, state: JobState::Queued, .. }) => status(BundleStatus::Queued, attempts, None),
+            None => status(BundleStatus::Queued, 0, None),
+        });
+    }
+
+    if let Some(bundle) = repository.failed().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+
+        let job = Job::load(&bundle).await?;
+        return Ok(status(BundleStatus::Failed,
+                         job.as_ref().map_or(0, |job| job.attempts),
+                         job.and_then(|job| job.failed).map(failure_info)));
+    }
+
+    if let Some(bundle) = repository.inbox().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        return Ok(status(BundleStatus::Inboxed, 0, None));
+    }
+
+    if let Some(bundle) = repository.archive().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        return Ok(status(BundleStatus::Archived, 0, None));
+    }
+
+    // Quarantined bundles are set aside before their metadata is trusted, so only the reason is reported
+    if let Some(bundle) = repository.quarantine().get(id).await {
+        let reason = bundle.reason().await?;
+        return Ok(status(BundleStatus::Failed, 0, Some(FailureInfo {
+            kind: String::from("quarantined"),
+            message: reason.reason,
+            details: String::new(),
+        })));
+    }
+
+    return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+}
+
+/// Queues a failed job again, optionally for one of the alternative juicers configured by name.
+#[post("/queue/<id>/retry?<juicer>")]
+pub(super) async fn retry(id: &RawStr,
+                          juicer: Option<String>,
+                          repository: &'_ Repository,
+                          queue: &'_ Queue,
+                          token: &'_ Token) -> Result<(), ApiError> {
+    if let Some(juicer) = &juicer {
+        if !queue.has_juicer(juicer) {
+            return Err(ApiError::bad_request(format!("Unknown juicer: {}", juicer)));
+        }
+    }
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = failed(&repository, id).await?;
+
+    queue.retry(bundle, juicer).await?;
+
+    return Ok(());
+}
+
+/// Discards a failed job along with the uploaded document.
+#[delete("/queue/<id>")]
+pub(super) async fn discard(id: &RawStr,
+                            repository: &'_ Repository,
+                            token: &'_ Token) -> Result<(), ApiError> {
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = failed(&repository, id).await?;
+
+    bundle.discard().await?;
+
+    return Ok(());
+}
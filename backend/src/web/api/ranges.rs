@@ -0,0 +1,343 @@
+use std::io::{Cursor, SeekFrom};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rocket::{Request, Response};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+
+use crate::mimetype;
+use crate::previews::Previews;
+use crate::proto::model::Kind;
+use crate::repository::{Bundle, BundleState};
+
+use super::{ApiError, content_type};
+
+/// Headers making a request for a fragment conditional or partial.
+pub(super) struct Conditions {
+    range: Option<String>,
+    if_range: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+}
+
+#[async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for Conditions {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let header = |name| request.headers().get_one(name).map(String::from);
+
+        Outcome::Success(Self {
+            range: header("Range"),
+            if_range: header("If-Range"),
+            if_none_match: header("If-None-Match"),
+            if_modified_since: header("If-Modified-Since"),
+        })
+    }
+}
+
+/// Formats a timestamp as HTTP date.
+fn http_date(time: &DateTime<Utc>) -> String {
+    return time.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+}
+
+fn parse_http_date(s: &str) -> Option<DateTime<Utc>> {
+    return DateTime::parse_from_rfc2822(s.trim()).ok()
+        .map(|time| time.with_timezone(&Utc));
+}
+
+/// Checks if any of the comma separated entity tags matches, comparing weakly as for `If-None-Match`.
+fn matches_etag(header: &str, etag: &str) -> bool {
+    return header.split(',')
+        .map(|candidate| candidate.trim())
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag);
+}
+
+/// Parses a single byte range against the length of the fragment and returns the first and last byte.
+///
+/// Returns `None` if the header is malformed or requests multiple ranges, in which case the whole fragment is served,
+/// and an error if the range can not be satisfied.
+fn parse_range(header: &str, length: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+
+    let i = spec.find('-')?;
+    let (first, last) = (spec[..i].trim(), spec[i + 1..].trim());
+
+    let range = match (first, last) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?;
+            if suffix == 0 || length == 0 {
+                return Some(Err(()));
+            }
+            (length.saturating_sub(suffix), length - 1)
+        }
+        (first, "") => (first.parse::<u64>().ok()?, length.saturating_sub(1)),
+        (first, last) => {
+            let (first, last) = (first.parse::<u64>().ok()?, last.parse::<u64>().ok()?);
+            if last < first {
+                return None;
+            }
+            (first, last.min(length.saturating_sub(1)))
+        }
+    };
+
+    if range.0 >= length {
+        return Some(Err(()));
+    }
+
+    return Some(Ok(range));
+}
+
+enum Body {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+    NotModified,
+}
+
+/// Decides whether the whole fragment, a range of it or nothing at all is sent, as the client has it already.
+fn evaluate(conditions: &Conditions, etag: Option<&str>, modified: Option<DateTime<Utc>>, length: u64) -> Body {
+    let not_modified = match (&conditions.if_none_match, &conditions.if_modified_since) {
+        (Some(header), _) => etag.map_or(false, |etag| matches_etag(header, etag)),
+        (None, Some(header)) => match (parse_http_date(header), modified) {
+            (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+            _ => false,
+        },
+        (None, None) => false,
+    };
+
+    if not_modified {
+        return Body::NotModified;
+    }
+
+    // Ranges only apply to the representation the client has, if it asks for one
+    let current = match &conditions.if_range {
+        Some(header) if header.trim().starts_with('"') => etag == Some(header.trim()),
+        Some(header) => match (parse_http_date(header), modified) {
+            (Some(date), Some(modified)) => date.timestamp() == modified.timestamp(),
+            _ => false,
+        },
+        None => true,
+    };
+
+    return match conditions.range.as_deref().filter(|_| current).and_then(|range| parse_range(range, length)) {
+        Some(Ok((first, last))) => Body::Partial(first, last),
+        Some(Err(())) => Body::Unsatisfiable,
+        None => Body::Full,
+    };
+}
+
+/// A fragment served honoring conditional and range requests.
+///
+/// The entity tag is the checksum recorded for the fragment and the modification time is taken from the file, so
+/// browsers revalidate cached previews cheaply and PDF viewers can load large documents page by page.
+pub(super) struct Served {
+    content_type: ContentType,
+    length: u64,
+    etag: Option<String>,
+    modified: Option<DateTime<Utc>>,
+    body: Body,
+    reader: Box<dyn AsyncRead + Unpin + Send>,
+}
+
+impl Served {
+    /// Serves a fragment from disk or, if given, its decrypted contents.
+    ///
+    /// Compressed fragments are decompressed and served from memory, like decrypted ones.
+    pub(super) async fn fragment<S: BundleState>(bundle: &Bundle<'_, S>,
+                                                 kind: &Kind,
+                                                 decrypted: Option<Vec<u8>>,
+                                                 conditions: &Conditions) -> Result<Self> {
+        let path = bundle.path_of(kind);
+
+        let decrypted = match decrypted {
+            None if bundle.is_compressed(kind).await? => {
+                let mut data = Vec::new();
+                if let Some(mut file) = bundle.read(kind).await? {
+                    file.read_to_end(&mut data).await?;
+                }
+                Some(data)
+            }
+            decrypted => decrypted,
+        };
+
+        let etag = match bundle.read_checksums().await? {
+            Some(checksums) => path.file_name()
+                .and_then(|name| checksums.get(&name.to_string_lossy()).map(|checksum| format!("\"{}\"", checksum))),
+            None => None,
+        };
+
+        let modified = tokio::fs::metadata(&path).await?.modified().ok()
+            .map(DateTime::<Utc>::from);
+
+        // The format recorded while staging is preferred, as it is detected from the original contents
+        let recorded = bundle.fragment_info(kind).await?.map(|info| info.mimetype);
+
+        // Decrypted contents are held in memory, fragments on disk are only read as far as requested
+        let (file, length, head) = match &decrypted {
+            Some(data) => {
+                let head = mimetype::head(&mut Cursor::new(data)).await?;
+                (None, data.len() as u64, head)
+            }
+            None => {
+                let mut file = bundle.open(kind).await?;
+                let length = file.metadata().await?.len();
+                let head = match (&recorded, mimetype::of_kind(kind)) {
+                    (None, None) => mimetype::head(&mut file).await?,
+                    _ => Vec::new(),
+                };
+                (Some(file), length, head)
+            }
+        };
+
+        let content_type = content_type(recorded.as_deref().unwrap_or_else(|| mimetype::mimetype(kind, &head)));
+
+        let body = evaluate(conditions, etag.as_deref(), modified, length);
+
+        let (start, end) = match body {
+            Body::Partial(first, last) => (first, last + 1),
+            _ => (0, length),
+        };
+
+        let reader: Box<dyn AsyncRead + Unpin + Send> = match (decrypted, file) {
+            (Some(mut data), _) => {
+                data.truncate(end as usize);
+                data.drain(..start as usize);
+                Box::new(Cursor::new(data))
+            }
+            (None, Some(mut file)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                Box::new(file.take(end - start))
+            }
+            (None, None) => unreachable!(),
+        };
+
+        return Ok(Self { content_type, length, etag, modified, body, reader });
+    }
+
+    /// Serves the preview of a page, counting from one, scaled to the requested size and cached once rendered.
+    ///
+    /// Pages of encrypted bundles are rendered from the decrypted document, which must be given then, and not cached.
+    pub(super) async fn preview<S: BundleState>(bundle: &Bundle<'_, S>,
+                                                page: u32,
+                                                size: u32,
+                                                decrypted: Option<Vec<u8>>,
+                                                previews: &Previews,
+                                                conditions: &Conditions) -> Result<Self, ApiError> {
+        let size = previews.size(size)
+            .ok_or_else(|| ApiError::bad_request(format!("Preview size too large: {}", size)))?;
+
+        let metadata = bundle.read_metadata().await?;
+
+        if page > metadata.pages {
+            return Err(ApiError::not_found(format!("Page not found: {}/preview/{}", bundle.id(), page)));
+        }
+
+        let (tag, data) = match (&metadata.domain, decrypted) {
+            (None, _) => previews.preview(bundle, page, size).await?,
+            (Some(_), Some(document)) => previews.preview_decrypted(bundle, &document, page, size).await?,
+            (Some(domain), None) => return Err(ApiError::bad_request(format!("Bundle is encrypted for domain {}: {}", domain, bundle.id()))),
+        };
+
+        return Ok(Self::rendition(ContentType::PNG, data, &tag, conditions));
+    }
+
+    /// Serves a rendition held in memory, identified by a tag which changes with its contents.
+    pub(super) fn rendition(content_type: ContentType, mut data: Vec<u8>, tag: &str, conditions: &Conditions) -> Self {
+        let etag = format!("\"{}\"", tag);
+        let length = data.len() as u64;
+
+        let body = evaluate(conditions, Some(&etag), None, length);
+        if let Body::Partial(first, last) = body {
+            data.truncate(last as usize + 1);
+            data.drain(..first as usize);
+        }
+
+        return Self { content_type, length, etag: Some(etag), modified: None, body, reader: Box::new(Cursor::new(data)) };
+    }
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for Served {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = Response::build();
+
+        response.raw_header("Accept-Ranges", "bytes");
+        response.raw_header("Cache-Control", "private, no-cache");
+
+        if let Some(etag) = self.etag {
+            response.raw_header("ETag", etag);
+        }
+        if let Some(modified) = self.modified {
+            response.raw_header("Last-Modified", http_date(&modified));
+        }
+
+        match self.body {
+            Body::NotModified => {
+                response.status(Status::NotModified);
+            }
+            Body::Unsatisfiable => {
+                response.status(Status::RangeNotSatisfiable);
+                response.raw_header("Content-Range", format!("bytes */{}", self.length));
+            }
+            Body::Partial(first, last) => {
+                response.status(Status::PartialContent);
+                response.header(self.content_type);
+                response.raw_header("Content-Range", format!("bytes {}-{}/{}", first, last, self.length));
+                response.raw_header("Content-Length", (last - first + 1).to_string());
+                response.streamed_body(self.reader);
+            }
+            Body::Full => {
+                response.header(self.content_type);
+                response.raw_header("Content-Length", self.length.to_string());
+                response.streamed_body(self.reader);
+            }
+        }
+
+        return response.ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_that!(parse_range("bytes=0-99", 1000)).is_equal_to(Some(Ok((0, 99))));
+        assert_that!(parse_range("bytes=900-", 1000)).is_equal_to(Some(Ok((900, 999))));
+        assert_that!(parse_range("bytes=-100", 1000)).is_equal_to(Some(Ok((900, 999))));
+        assert_that!(parse_range("bytes=-2000", 1000)).is_equal_to(Some(Ok((0, 999))));
+        assert_that!(parse_range("bytes=500-2000", 1000)).is_equal_to(Some(Ok((500, 999))));
+
+        assert_that!(parse_range("bytes=1000-", 1000)).is_equal_to(Some(Err(())));
+        assert_that!(parse_range("bytes=-0", 1000)).is_equal_to(Some(Err(())));
+
+        assert_that!(parse_range("bytes=0-99,200-299", 1000)).is_none();
+        assert_that!(parse_range("bytes=99-0", 1000)).is_none();
+        assert_that!(parse_range("lines=0-99", 1000)).is_none();
+        assert_that!(parse_range("bytes=a-b", 1000)).is_none();
+    }
+
+    #[test]
+    fn test_matches_etag() {
+        assert_that!(matches_etag("\"abc\"", "\"abc\"")).is_true();
+        assert_that!(matches_etag("\"xyz\", W/\"abc\"", "\"abc\"")).is_true();
+        assert_that!(matches_etag("*", "\"abc\"")).is_true();
+        assert_that!(matches_etag("\"xyz\"", "\"abc\"")).is_false();
+    }
+
+    #[test]
+    fn test_http_date() {
+        let time = parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_that!(http_date(&time)).is_equal_to(String::from("Wed, 21 Oct 2015 07:28:00 GMT"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::str::FromStr;
+
+use log::info;
+use rocket::{delete, get, post};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::meta::Metadata;
+use crate::proto::api::relations::{ListResponse, RelatedInfo};
+use crate::proto::model::{DocId, Relation, RelationKind};
+use crate::repository::Repository;
+
+use super::{ApiError, ensure_visible, Token};
+
+/// Reads the metadata of an inboxed or archived bundle.
+async fn read(repository: &Repository, id: DocId) -> Result<Option<Metadata>, ApiError> {
+    if let Some(bundle) = repository.inbox().get(id).await {
+        return Ok(Some(bundle.read_metadata().await?));
+    }
+
+    if let Some(bundle) = repository.archive().get(id).await {
+        return Ok(Some(bundle.read_metadata().await?));
+    }
+
+    return Ok(None);
+}
+
+/// Applies a change to the metadata of an inboxed or archived bundle.
+pub(super) async fn update(repository: &Repository,
+                           id: DocId,
+                           token: &Token,
+                           f: impl FnOnce(&mut Metadata) -> Result<(), ApiError>) -> Result<(), ApiError> {
+    if let Some(bundle) = repository.inbox().get(id).await {
+        let mut metadata = bundle.read_metadata().await?;
+        ensure_visible(id, &metadata, token)?;
+        f(&mut metadata)?;
+        bundle.write_metadata(&metadata).await?;
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        let mut metadata = bundle.read_metadata().await?;
+        ensure_visible(id, &metadata, token)?;
+        f(&mut metadata)?;
+        bundle.write_metadata(&metadata).await?;
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    }
+
+    return Ok(());
+}
+
+#[get("/relations/<id>")]
+pub(super) async fn list(id: &RawStr,
+                         repository: &'_ Repository,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let metadata = read(&repository, id).await?
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    ensure_visible(id, &metadata, token)?;
+
+    // Links to purged documents and documents hidden from the user are skipped
+    let mut outgoing = Vec::new();
+    for relation in metadata.relations {
+        if let Some(target) = read(&repository, relation.target).await? {
+            if target.is_visible_to(token.subject()) {
+                outgoing.push(RelatedInfo {
+                    kind: relation.kind,
+                    doc: (relation.target, target).into(),
+                });
+            }
+        }
+    }
+
+    let mut incoming = Vec::new();
+    for (source, metadata) in repository.referencing(id).await? {
+        if !metadata.is_visible_to(token.subject()) {
+            continue;
+        }
+
+        let kinds = metadata.relations.iter()
+            .filter(|relation| relation.target == id)
+            .map(|relation| relation.kind)
+            .collect::<Vec<_>>();
+
+        for kind in kinds {
+            incoming.push(RelatedInfo {
+                kind,
+                doc: (source, metadata.clone()).into(),
+            });
+        }
+    }
+
+    Ok(Json(ListResponse {
+        outgoing,
+        incoming,
+    }))
+}
+
+#[post("/relations/<id>", data = "<relation>")]
+pub(super) async fn create(id: &RawStr,
+                           relation: Json<Relation>,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+    let relation = relation.into_inner();
+
+    if relation.target == id {
+        return Err(ApiError::bad_request(format!("Bundle can not relate to itself: {}", id)));
+    }
+
+    let target = read(&repository, relation.target).await?
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", relation.target)))?;
+    ensure_visible(relation.target, &target, token)?;
+
+    let repository = repository.acting_as(token.subject());
+
+    info!("Linking {} to {} as {}", id, relation.target, relation.kind);
+
+    return update(&repository, id, token, |metadata| {
+        metadata.relations.insert(relation);
+        return Ok(());
+    }).await;
+}
+
+#[delete("/relations/<id>/<kind>/<target>")]
+pub(super) async fn remove(id: &RawStr,
+                           kind: &RawStr,
+                           target: &RawStr,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let relation = Relation {
+        kind: RelationKind::from_str(kind.as_str())
+            .map_err(|err| ApiError::bad_request(err.to_string()))?,
+        target: DocId::from_str(target.as_str())?,
+    };
+
+    let repository = repository.acting_as(token.subject());
+
+    return update(&repository, id, token, |metadata| {
+        if !metadata.relations.remove(&relation) {
+            return Err(ApiError::not_found(format!("Relation not found: {}/{}/{}", id, relation.kind, relation.target)));
+        }
+
+        return Ok(());
+    }).await;
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rocket::{Data, get, Request, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::http::uri::Origin;
+use rocket::request::{FromRequest, Outcome};
+use rocket_contrib::json::Json;
+
+use crate::checklists::Checklists;
+use crate::correspondents::Correspondents;
+use crate::crypto::Keyring;
+use crate::editing::Editor;
+use crate::filing::Filing;
+use crate::index::Index;
+use crate::labels::Labels;
+use crate::merge::Merger;
+use crate::persons::Persons;
+use crate::previews::Previews;
+use crate::proto::api::repositories::ListResponse;
+use crate::quarantine::Reviews;
+use crate::queue::Queue;
+use crate::repository::Repository;
+use crate::rules::Rules;
+use crate::suggestions::Suggestions;
+use crate::undo::Undo;
+
+use super::{Token, versions};
+
+/// A repository served in addition to the default one along with the services bound to it.
+pub struct Named {
+    pub repository: Repository,
+    pub queue: Queue,
+    pub index: Arc<dyn Index + Send + Sync>,
+    pub labels: Labels,
+    pub keyring: Keyring,
+    pub filing: Filing,
+    pub previews: Previews,
+    pub rules: Arc<Rules>,
+    pub correspondents: Arc<Correspondents>,
+    pub persons: Persons,
+    pub checklists: Checklists,
+    pub merger: Merger,
+    pub editor: Editor,
+    pub undo: Undo,
+    pub reviews: Reviews,
+    pub suggestions: Suggestions,
+}
+
+/// The repositories served in addition to the default one, by name.
+#[derive(Clone, Default)]
+pub struct Repositories(Arc<BTreeMap<String, Named>>);
+
+impl Repositories {
+    pub fn new(repositories: impl IntoIterator<Item=(String, Named)>) -> Self {
+        return Self(Arc::new(repositories.into_iter().collect()));
+    }
+}
+
+/// The index of the repository a request is scoped to.
+///
+/// Unlike the other services, the index is shared as `Arc`, which can not be resolved by reference.
+#[derive(Clone)]
+pub struct ScopedIndex(pub Arc<dyn Index + Send + Sync>);
+
+impl std::ops::Deref for ScopedIndex {
+    type Target = dyn Index + Send + Sync;
+
+    fn deref(&self) -> &Self::Target {
+        return self.0.as_ref();
+    }
+}
+
+/// The name of the repository a request is scoped to, the default repository if unset.
+struct Scope(Option<String>);
+
+/// The endpoints served for named repositories, by the first segment of their path.
+///
+/// All other endpoints work on state kept for the instance as a whole, like shares and document requests handing out
+/// links to the default repository, and are not found below a named repository.
+const SCOPED: &[&str] = &[
+    "archive", "attachments", "attestations", "bulk", "changes", "checklists", "confidence", "contracts",
+    "correspondents", "documents", "domains", "due", "erase", "events", "export", "filing", "fragments", "graphql",
+    "history", "inbox", "labels", "merge", "pages", "persons", "quarantine", "queue", "relations", "reprocess",
+    "resolve", "revisions", "rules", "scans", "stats", "suggestions", "sync", "trash", "triage", "undo", "upload",
+    "uploads", "verify", "warranties",
+];
+
+/// Scopes requests to a named repository.
+///
+/// The API paths listed in [`SCOPED`] are served below `/api/repositories/<name>` for each named repository. The prefix
+/// is removed before routing while the name is kept for the request, so the endpoints work on the named repository and
+/// the services bound to it. Requests for unknown repositories are rejected by the endpoints.
+pub struct Scoping {}
+
+#[async_trait]
+impl Fairing for Scoping {
+    fn info(&self) -> Info {
+        Info {
+            name: "Scoping",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data) {
+        let segments = request.uri().path().split('/')
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        // The version comes first if given, like in `/api/v1/repositories/work/inbox`
+        let offset = match segments.get(2) {
+            Some(segment) if versions::parse(segment).is_some() => 3,
+            _ => 2,
+        };
+
+        if segments.get(1).map(String::as_str) != Some("api")
+            || segments.get(offset).map(String::as_str) != Some("repositories")
+            || segments.len() < offset + 3
+            || !SCOPED.contains(&segments[offset + 2].as_str()) {
+            return;
+        }
+
+        let name = segments[offset + 1].clone();
+        let path = segments[..offset].iter()
+            .chain(&segments[offset + 2..])
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let uri = match request.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+
+        if let Ok(uri) = Origin::parse_owned(uri) {
+            request.set_uri(uri);
+            request.local_cache(|| Scope(Some(name)));
+        }
+    }
+}
+
+/// Looks up the named repository the request is scoped to.
+async fn named<'a>(request: &'a Request<'_>) -> Option<Result<&'a Named, ()>> {
+    let name = request.local_cache(|| Scope(None)).0.as_ref()?;
+
+    let repositories = request.guard::<State<'_, Repositories>>().await
+        .expect("No Repositories");
+
+    return Some(repositories.inner().0.get(name).ok_or(()));
+}
+
+#[async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for &'a Repository {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        return match named(request).await {
+            Some(Ok(named)) => Outcome::Success(&named.repository),
+            Some(Err(())) => Outcome::Failure((Status::NotFound, ())),
+            None => request.guard::<State<'_, Repository>>().await.map(|repository| repository.inner()),
+        };
+    }
+}
+
+/// Resolves a service of the repository a request is scoped to, like the repository itself.
+///
+/// Services shared as `Arc` with other parts of the instance are resolved by reference to their content.
+macro_rules! scoped {
+    (Arc<$service:ty>, $field:ident) => {
+        #[async_trait]
+        impl<'a, 'r> FromRequest<'a, 'r> for &'a $service {
+            type Error = ();
+
+            async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+                return match named(request).await {
+                    Some(Ok(named)) => Outcome::Success(named.$field.as_ref()),
+                    Some(Err(())) => Outcome::Failure((Status::NotFound, ())),
+                    None => request.guard::<State<'_, Arc<$service>>>().await.map(|service| service.inner().as_ref()),
+                };
+            }
+        }
+    };
+
+    ($service:ty, $field:ident) => {
+        #[async_trait]
+        impl<'a, 'r> FromRequest<'a, 'r> for &'a $service {
+            type Error = ();
+
+            async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+                return match named(request).await {
+                    Some(Ok(named)) => Outcome::Success(&named.$field),
+                    Some(Err(())) => Outcome::Failure((Status::NotFound, ())),
+                    None => request.guard::<State<'_, $service>>().await.map(|service| service.inner()),
+                };
+            }
+        }
+    };
+}
+
+scoped!(Queue, queue);
+scoped!(Labels, labels);
+scoped!(Keyring, keyring);
+scoped!(Filing, filing);
+scoped!(Previews, previews);
+scoped!(Arc<Rules>, rules);
+scoped!(Arc<Correspondents>, correspondents);
+scoped!(Persons, persons);
+scoped!(Checklists, checklists);
+scoped!(Merger, merger);
+scoped!(Editor, editor);
+scoped!(Undo, undo);
+scoped!(Reviews, reviews);
+scoped!(Suggestions, suggestions);
+
+#[async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for ScopedIndex {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        return match named(request).await {
+            Some(Ok(named)) => Outcome::Success(ScopedIndex(named.index.clone())),
+            Some(Err(())) => Outcome::Failure((Status::NotFound, ())),
+            None => request.guard::<State<'_, Arc<dyn Index + Send + Sync>>>().await
+                .map(|index| ScopedIndex(index.inner().clone())),
+        };
+    }
+}
+
+/// Lists the names of the repositories served in addition to the default one.
+#[get("/repositories")]
+pub(super) async fn list(repositories: State<'_, Repositories>,
+                         _token: &'_ Token) -> Json<ListResponse> {
+    Json(ListResponse {
+        repositories: repositories.0.keys().cloned().collect(),
+    })
+}
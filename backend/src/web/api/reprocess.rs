@@ -0,0 +1,119 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Result;
+use futures::StreamExt;
+use log::info;
+use rocket::post;
+use rocket::http::RawStr;
+
+use crate::meta::Metadata;
+use crate::proto::model::{DocId, Kind};
+use crate::queue::Queue;
+use crate::repository::{Bundle, Repository, Staging};
+
+use super::{ApiError, ensure_visible, ScopedIndex, Token};
+
+/// Renditions replaced by reprocessing, besides the page previews.
+const RENDITIONS: &[Kind] = &[Kind::Plaintext, Kind::Preview, Kind::Thumbnail];
+
+/// Stages a copy of the originals of a bundle and runs the juicer over it.
+///
+/// Bundles lacking an original, which have been juiced before originals were kept, are juiced from their document.
+async fn juice<'r>(repository: &'r Repository, queue: &Queue, path: &Path, metadata: &Metadata) -> Result<Bundle<'r, Staging>> {
+    let staging = repository.stage().await?;
+
+    let result: Result<()> = async {
+        let originals = tokio::fs::read_dir(path).await?
+            .filter_map(|entry| async move { entry.ok() })
+            .filter(|entry| futures::future::ready(entry.file_name().to_string_lossy().starts_with("original.")))
+            .collect::<Vec<_>>().await;
+
+        for original in &originals {
+            tokio::fs::copy(original.path(), staging.path().join(original.file_name())).await?;
+        }
+
+        if originals.is_empty() {
+            tokio::fs::copy(path.join("document.pdf"), staging.path_of(Kind::other("original.pdf"))).await?;
+        }
+
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
+
+        return queue.juice(&staging).await;
+    }.await;
+
+    if let Err(err) = result {
+        staging.delete().await?;
+        return Err(err);
+    }
+
+    return Ok(staging);
+}
+
+/// Lists the renditions the juicer created in a staged bundle.
+async fn renditions(staging: &Bundle<'_, Staging>) -> Result<Vec<(Kind, Vec<u8>)>> {
+    let mut renditions = Vec::new();
+
+    for kind in RENDITIONS.iter().cloned().chain((1..).map(Kind::Page)) {
+        match tokio::fs::read(staging.path_of(&kind)).await {
+            Ok(data) => renditions.push((kind, data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => match kind {
+                Kind::Page(_) => break,
+                _ => continue,
+            },
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    return Ok(renditions);
+}
+
+/// Runs the juicer over an inboxed or archived bundle again, i.e. after the OCR has been improved.
+///
+/// The text and previews are replaced by the ones extracted again, whereas the originals, the document and the
+/// metadata are kept as they are. Archived bundles are reindexed afterwards. Bundles of encryption domains can not be
+/// reprocessed, as their fragments are encrypted.
+#[post("/reprocess/<id>")]
+pub(super) async fn reprocess(id: &RawStr,
+                              repository: &'_ Repository,
+                              queue: &'_ Queue,
+                              index: ScopedIndex,
+                              token: &'_ Token) -> Result<(), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    if let Some(bundle) = repository.inbox().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        ensure_visible(id, &metadata, token)?;
+
+        let staging = juice(&repository, &queue, &bundle.path(), &metadata).await?;
+        let renditions = renditions(&staging).await;
+        staging.delete().await?;
+
+        for (kind, data) in renditions? {
+            bundle.replace(kind, &data).await?;
+        }
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        let metadata = bundle.read_metadata().await?;
+        ensure_visible(id, &metadata, token)?;
+
+        if let Some(domain) = &metadata.domain {
+            return Err(ApiError::bad_request(format!("Bundle is encrypted for domain {}: {}", domain, id)));
+        }
+
+        let staging = juice(&repository, &queue, &bundle.path(), &metadata).await?;
+        let renditions = renditions(&staging).await;
+        staging.delete().await?;
+
+        for (kind, data) in renditions? {
+            bundle.replace(kind, &data).await?;
+        }
+
+        index.index(&bundle).await?;
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    }
+
+    info!("Reprocessed bundle {}", id);
+
+    return Ok(());
+}
@@ -0,0 +1,136 @@
+use log::info;
+use rocket::{Data, delete, get, post, State};
+use rocket::data::ToByteUnit;
+use rocket_contrib::json::Json;
+
+use crate::ingest;
+use crate::meta::Metadata;
+use crate::proto::api::requests::{CreateRequest, LinkResponse, ListResponse, RequestInfo};
+use crate::proto::api::upload::UploadResponse;
+use crate::queue::Queue;
+use crate::quota::Quotas;
+use crate::requests::{DocumentRequest, Requests};
+
+use super::{ApiError, Token};
+
+fn request_info(request: DocumentRequest) -> RequestInfo {
+    return RequestInfo {
+        id: request.id,
+        token: request.token,
+        recipient: request.recipient,
+        message: request.message,
+        labels: request.labels,
+        created: request.created,
+        expires: request.expires,
+        fulfilled: request.fulfilled.map(|(time, _)| time),
+        document: request.fulfilled.map(|(_, doc)| doc),
+    };
+}
+
+#[get("/requests")]
+pub(super) async fn list(requests: State<'_, Requests>,
+                         token: &'_ Token) -> Json<ListResponse> {
+    let requests = requests.list(token.subject()).await.into_iter()
+        .map(request_info)
+        .collect();
+
+    Json(ListResponse { requests })
+}
+
+#[post("/requests", data = "<request>")]
+pub(super) async fn create(request: Json<CreateRequest>,
+                           requests: State<'_, Requests>,
+                           token: &'_ Token) -> Result<Json<RequestInfo>, ApiError> {
+    let request = request.into_inner();
+    if request.recipient.is_empty() {
+        return Err(ApiError::bad_request(String::from("Recipient must not be empty")));
+    }
+
+    let request = requests.create(token.subject().to_string(),
+                                  request.recipient,
+                                  request.message,
+                                  request.labels,
+                                  request.expires).await?;
+
+    info!("Requested document from {} ({})", request.recipient, request.id);
+
+    Ok(Json(request_info(request)))
+}
+
+#[delete("/requests/<id>")]
+pub(super) async fn cancel(id: String,
+                           requests: State<'_, Requests>,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    if !requests.cancel(token.subject(), &id).await? {
+        return Err(ApiError::not_found(format!("Request not found: {}", id)));
+    }
+
+    info!("Cancelled document request {}", id);
+
+    return Ok(());
+}
+
+/// Looks up an open request by the token of a link.
+async fn open(requests: &Requests, token: &str) -> Result<DocumentRequest, ApiError> {
+    let request = requests.by_token(token).await
+        .ok_or_else(|| ApiError::not_found(String::from("Request not found")))?;
+
+    if !request.is_open() {
+        return Err(ApiError::gone(String::from("Request already fulfilled or expired")));
+    }
+
+    return Ok(request);
+}
+
+#[get("/requests/link/<token>")]
+pub(super) async fn link(token: String,
+                         requests: State<'_, Requests>) -> Result<Json<LinkResponse>, ApiError> {
+    let request = open(&requests, &token).await?;
+
+    Ok(Json(LinkResponse {
+        requester: request.requester,
+        recipient: request.recipient,
+        message: request.message,
+    }))
+}
+
+/// Uploads the requested document - the link itself authorizes the upload.
+#[post("/requests/link/<token>", format = "application/pdf", data = "<data>")]
+pub(super) async fn upload(token: String,
+                           data: Data,
+                           requests: State<'_, Requests>,
+                           queue: &'_ Queue,
+                           quotas: State<'_, Quotas>) -> Result<Json<UploadResponse>, ApiError> {
+    // Claimed before ingesting, so only a single upload can fulfill the request
+    let request = requests.claim(&token).await?
+        .ok_or_else(|| ApiError::not_found(String::from("Request not found")))?;
+
+    // The document lands in the inbox of the requester with the requested labels already assigned
+    let metadata = Metadata {
+        owner: Some(request.requester.clone()),
+        labels: request.labels.clone(),
+        ..Metadata::new()
+    };
+
+    let result: anyhow::Result<_> = async {
+        // The document is accounted to the requester owning it
+        let id = ingest::ingest_within(&queue, &quotas, data.open(512.mebibytes()), "pdf", metadata.clone()).await?;
+        requests.fulfill(&request.id, id).await?;
+
+        return Ok(id);
+    }.await;
+
+    let id = match result {
+        Ok(id) => id,
+        Err(err) => {
+            requests.release(&request.id).await;
+            return Err(err.into());
+        }
+    };
+
+    info!("Fulfilled document request {} with {}", request.id, id);
+
+    Ok(Json(UploadResponse {
+        doc: (id, metadata).into(),
+    }))
+}
@@ -0,0 +1,63 @@
+use rocket::get;
+use rocket::http::RawStr;
+use rocket::response::Redirect;
+use rocket_contrib::json::Json;
+
+use crate::filing::{self, Found};
+use crate::proto::api::resolve::ResolveResponse;
+use crate::repository::Repository;
+use crate::web::proxy::Forwarded;
+
+use super::{ApiError, Token};
+
+/// Finds the single document visible to the user a code printed on paper refers to.
+async fn find(repository: &Repository, code: &RawStr, token: &Token) -> Result<Found, ApiError> {
+    let code = code.url_decode()
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+
+    let mut found = filing::resolve(repository, &code).await?
+        .ok_or_else(|| ApiError::bad_request(format!("Invalid code: {}", code)))?
+        .into_iter()
+        .filter(|found| found.metadata.is_visible_to(token.subject()))
+        .collect::<Vec<_>>();
+
+    return match found.len() {
+        0 => Err(ApiError::not_found(format!("No document found for {}", code))),
+        1 => Ok(found.remove(0)),
+        _ => Err(ApiError::conflict(format!("Multiple documents found for {}", code))),
+    };
+}
+
+#[get("/resolve/<code>")]
+pub(super) async fn resolve(code: &RawStr,
+                            repository: &'_ Repository,
+                            token: &'_ Token) -> Result<Json<ResolveResponse>, ApiError> {
+    let found = find(&repository, code, token).await?;
+
+    Ok(Json(ResolveResponse {
+        doc: (found.id, found.metadata).into(),
+    }))
+}
+
+/// Short link for QR codes on labels and folders, redirecting to the document view.
+///
+/// Unauthenticated requests are sent to the login first. Redirects point to the URL the client reached adacta at, which
+/// may differ behind a reverse proxy.
+#[get("/d/<code>")]
+pub(super) async fn redirect(code: &RawStr,
+                             repository: &'_ Repository,
+                             forwarded: Forwarded,
+                             token: Option<&'_ Token>) -> Result<Redirect, ApiError> {
+    let token = match token {
+        Some(token) => token,
+        None => return Ok(Redirect::to(forwarded.url(&format!("/login?redirect={}/d/{}", forwarded.prefix, code)))),
+    };
+
+    let found = find(&repository, code, token).await?;
+
+    if found.archived {
+        return Ok(Redirect::to(forwarded.url(&format!("/archive/{}", found.id))));
+    } else {
+        return Ok(Redirect::to(forwarded.url(&format!("/inbox/{}", found.id))));
+    }
+}
@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+use log::info;
+use rocket::{get, post};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::revisions::{ListResponse, RevertResponse, RevisionInfo};
+use crate::proto::model::DocId;
+use crate::repository::Repository;
+
+use super::{ApiError, ensure_visible, Token};
+
+#[get("/revisions/<id>")]
+pub(super) async fn list(id: &RawStr,
+                         repository: &'_ Repository,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let (metadata, revisions) = if let Some(bundle) = repository.inbox().get(id).await {
+        (bundle.read_metadata().await?, bundle.revisions().await?)
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        (bundle.read_metadata().await?, bundle.revisions().await?)
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    };
+
+    ensure_visible(id, &metadata, token)?;
+
+    Ok(Json(ListResponse {
+        revisions: revisions.into_iter()
+            .map(|revision| RevisionInfo {
+                revision: revision.revision,
+                replaced: revision.replaced,
+                actor: revision.actor,
+                metadata: revision.metadata.into(),
+            })
+            .collect(),
+    }))
+}
+
+#[post("/revisions/<id>/<revision>/revert")]
+pub(super) async fn revert(id: &RawStr,
+                           revision: usize,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<RevertResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let metadata = if let Some(bundle) = repository.inbox().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        check_revision(id, revision, bundle.revisions().await?.len())?;
+        bundle.revert_metadata(revision).await?
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        check_revision(id, revision, bundle.revisions().await?.len())?;
+        bundle.revert_metadata(revision).await?
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    };
+
+    info!("Reverted metadata of {} to revision {}", id, revision);
+
+    Ok(Json(RevertResponse {
+        doc: (id, metadata).into(),
+    }))
+}
+
+fn check_revision(id: DocId, revision: usize, revisions: usize) -> Result<(), ApiError> {
+    if revision == 0 || revision > revisions {
+        return Err(ApiError::not_found(format!("Revision not found: {}/{}", id, revision)));
+    }
+
+    return Ok(());
+}
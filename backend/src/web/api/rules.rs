@@ -0,0 +1,61 @@
+use rocket::{delete, get, put};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::rules::{ListResponse, UpdateRequest};
+use crate::proto::model::Rule;
+use crate::rules::{Rules, validate};
+
+use super::{ApiError, Token};
+
+#[get("/rules")]
+pub(super) async fn list(rules: &'_ Rules,
+                         _token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    Ok(Json(ListResponse {
+        rules: rules.list().await,
+    }))
+}
+
+#[put("/rules/<name>", data = "<request>")]
+pub(super) async fn update(name: &RawStr,
+                           request: Json<UpdateRequest>,
+                           rules: &'_ Rules,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let name = name.url_decode()
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+    let request = request.into_inner();
+
+    if request.conditions.is_empty() {
+        return Err(ApiError::bad_request(format!("Rule without conditions: {}", name)));
+    }
+
+    let rule = Rule {
+        name: name.into_owned(),
+        conditions: request.conditions,
+        labels: request.labels,
+        title: request.title,
+        correspondent: request.correspondent,
+        properties: request.properties,
+    };
+
+    validate(&rule)
+        .map_err(|err| ApiError::bad_request(format!("{:#}", err)))?;
+
+    rules.define(rule).await?;
+
+    return Ok(());
+}
+
+#[delete("/rules/<name>")]
+pub(super) async fn remove(name: &RawStr,
+                           rules: &'_ Rules,
+                           _token: &'_ Token) -> Result<(), ApiError> {
+    let name = name.url_decode()
+        .map_err(|err| ApiError::bad_request(err.to_string()))?;
+
+    if !rules.remove(&name).await? {
+        return Err(ApiError::not_found(format!("Rule not found: {}", name)));
+    }
+
+    return Ok(());
+}
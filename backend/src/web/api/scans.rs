@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::Utc;
+use log::{info, trace};
+use rocket::{Data, delete, post, State};
+use rocket::data::ToByteUnit;
+use rocket::http::{ContentType, RawStr};
+use rocket_contrib::json::Json;
+
+use crate::auth::Authenticator;
+use crate::config::Defaults;
+use crate::juicer::image_format;
+use crate::meta::Metadata;
+use crate::proto::api::scans::ScanInfo;
+use crate::proto::model::{DocId, Kind};
+use crate::queue::Queue;
+use crate::quota::Quotas;
+use crate::repository::{Bundle, Repository, Staging};
+use crate::uploads::{Scan, Uploads};
+
+use super::{ApiError, Token};
+use super::upload::{finish, ocr_languages};
+
+/// Metadata applied to scans by the name of the profile selected by the scanner.
+pub struct Profiles(pub HashMap<String, Defaults>);
+
+/// Starts a scan which is assembled from page images sent one at a time, i.e. by the hook of a network scanner.
+///
+/// The profile selects the metadata configured for the scan, so scanners can offer a button per type of document.
+#[post("/scans?<profile>&<languages>")]
+pub(super) async fn begin(profile: Option<String>,
+                          languages: Option<String>,
+                          repository: &'_ Repository,
+                          profiles: State<'_, Profiles>,
+                          auth: State<'_, Arc<Authenticator>>,
+                          token: &'_ Token) -> Result<Json<ScanInfo>, ApiError> {
+    let profile = profile
+        .map(|profile| profiles.0.get(&profile)
+            .ok_or_else(|| ApiError::bad_request(format!("Unknown scan profile: {}", profile))))
+        .transpose()?;
+    let languages = ocr_languages(languages)?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let staging = repository.stage().await?;
+
+    info!("Starting scan to staging bundle {}", staging.id());
+
+    let result = (|| async {
+        Scan {
+            owner: token.subject().to_string(),
+            created: Utc::now(),
+            pages: Vec::new(),
+        }.save(&staging).await?;
+
+        let mut metadata = Metadata {
+            owner: Some(token.subject().to_string()),
+            languages,
+            ..Metadata::new()
+        }.with_defaults(&auth.defaults(token));
+        if let Some(profile) = profile {
+            metadata = metadata.with_defaults(profile);
+        }
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
+
+        return Result::<_, ApiError>::Ok(());
+    })().await;
+
+    if let Err(err) = result {
+        staging.delete().await?;
+        return Err(err);
+    }
+
+    Ok(Json(ScanInfo {
+        id: *staging.id(),
+        pages: 0,
+        doc: None,
+    }))
+}
+
+/// Looks up a pending scan of the requesting user.
+async fn pending<'r>(id: &RawStr, repository: &'r Repository, token: &Token) -> Result<(Bundle<'r, Staging>, Scan), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let not_found = || ApiError::not_found(format!("Scan not found: {}", id));
+
+    let staging = repository.staging().get(id).await
+        .ok_or_else(not_found)?;
+
+    return match Scan::load(&staging).await? {
+        Some(scan) if scan.owner == token.subject() => Ok((staging, scan)),
+        _ => Err(not_found()),
+    };
+}
+
+/// Appends the image of the next page to a scan.
+#[post("/scans/<id>/pages", data = "<data>")]
+pub(super) async fn page(id: &RawStr,
+                         data: Data,
+                         content_type: Option<&ContentType>,
+                         repository: &'_ Repository,
+                         uploads: State<'_, Uploads>,
+                         quotas: State<'_, Quotas>,
+                         token: &'_ Token) -> Result<Json<ScanInfo>, ApiError> {
+    let mimetype = content_type.map(|content_type| format!("{}/{}", content_type.top(), content_type.sub()));
+    let extension = image_format(mimetype.as_deref(), None)
+        .ok_or_else(|| ApiError::bad_request(format!("Unsupported page type: {}", mimetype.as_deref().unwrap_or("unknown"))))?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let (staging, mut scan) = pending(id, &repository, token).await?;
+
+    // Pages are numbered in the order they arrive, so a scan only accepts a single page at a time
+    let _active = uploads.begin(*staging.id())
+        .ok_or_else(|| ApiError::conflict(format!("Scan is receiving another page: {}", staging.id())))?;
+
+    // The pages are accounted to the owner of the document
+    let owner = staging.read_metadata().await?.owner;
+    let reservation = quotas.reserve(&repository, owner.as_deref(), 0).await?;
+
+    let kind = Scan::page(scan.pages.len() + 1, extension);
+    let mut fragment = staging.write(kind).await?;
+    reservation.copy(&mut data.open(64.mebibytes()), &mut fragment).await
+        .context("Writing page to staging")?;
+    fragment.commit().await?;
+
+    reservation.commit();
+
+    scan.pages.push(extension.to_string());
+    scan.save(&staging).await?;
+
+    trace!("Received page {} for staging bundle {}", scan.pages.len(), staging.id());
+
+    Ok(Json(ScanInfo {
+        id: *staging.id(),
+        pages: scan.pages.len(),
+        doc: None,
+    }))
+}
+
+/// Completes a scan after its last page and queues it for juicing.
+#[post("/scans/<id>")]
+pub(super) async fn complete(id: &RawStr,
+                             repository: &'_ Repository,
+                             queue: &'_ Queue,
+                             token: &'_ Token) -> Result<Json<ScanInfo>, ApiError> {
+    let repository = repository.acting_as(token.subject());
+
+    let (staging, scan) = pending(id, &repository, token).await?;
+
+    if scan.pages.is_empty() {
+        return Err(ApiError::bad_request(format!("Scan without pages: {}", staging.id())));
+    }
+
+    info!("Completed scan of {} pages to staging bundle {}", scan.pages.len(), staging.id());
+
+    let result = Scan::complete(&staging).await.map_err(ApiError::from);
+
+    let id = *staging.id();
+    let response = finish(&queue, staging, result).await?;
+
+    Ok(Json(ScanInfo {
+        id,
+        pages: scan.pages.len(),
+        doc: Some(response.into_inner().doc),
+    }))
+}
+
+/// Aborts a scan and discards the pages received so far.
+#[delete("/scans/<id>")]
+pub(super) async fn abort(id: &RawStr,
+                          repository: &'_ Repository,
+                          token: &'_ Token) -> Result<(), ApiError> {
+    let (staging, _) = pending(id, &repository, token).await?;
+
+    info!("Aborting scan to staging bundle {}", staging.id());
+    staging.delete().await?;
+
+    return Ok(());
+}
@@ -0,0 +1,46 @@
+use rocket::{get, put, State};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::preferences::{Preferences, Values};
+
+use super::{ApiError, Token};
+
+/// Current version of the settings document
+const VERSION: u32 = 1;
+
+/// All settings of a user as a single document.
+///
+/// The document can be imported into another instance to replicate the setup or be kept under version control.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Settings {
+    version: u32,
+
+    #[serde(default)]
+    preferences: Values,
+}
+
+#[get("/settings")]
+pub(super) async fn export(preferences: State<'_, Preferences>,
+                           token: &'_ Token) -> Result<Json<Settings>, ApiError> {
+    let preferences = preferences.get(token.subject()).await?;
+
+    Ok(Json(Settings {
+        version: VERSION,
+        preferences,
+    }))
+}
+
+#[put("/settings", data = "<settings>")]
+pub(super) async fn import(settings: Json<Settings>,
+                           preferences: State<'_, Preferences>,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    let settings = settings.into_inner();
+    if settings.version > VERSION {
+        return Err(ApiError::bad_request(format!("Unsupported settings version: {}", settings.version)));
+    }
+
+    preferences.replace(token.subject(), settings.preferences).await?;
+
+    return Ok(());
+}
@@ -0,0 +1,116 @@
+use chrono::{Duration, Utc};
+use log::info;
+use rocket::{delete, get, post, State};
+use rocket_contrib::json::Json;
+
+use crate::crypto;
+use crate::proto::api::shares::{CreateRequest, ListResponse, ShareInfo};
+use crate::proto::model::Kind;
+use crate::repository::Repository;
+use crate::shares::{Share, Shares};
+
+use super::{ApiError, ensure_visible, InternalError, Token};
+use super::ranges::{Conditions, Served};
+
+fn share_info(shares: &Shares, share: Share) -> ShareInfo {
+    let link = format!("/api/shares/link/{}/{}", share.id, shares.signature(&share));
+
+    return ShareInfo {
+        id: share.id,
+        doc: share.doc,
+        fragment: share.fragment,
+        created: share.created,
+        expires: share.expires,
+        link,
+    };
+}
+
+#[get("/shares")]
+pub(super) async fn list(shares: State<'_, Shares>,
+                         token: &'_ Token) -> Json<ListResponse> {
+    let list = shares.list(token.subject()).await.into_iter()
+        .map(|share| share_info(&shares, share))
+        .collect();
+
+    Json(ListResponse { shares: list })
+}
+
+#[post("/shares", data = "<request>")]
+pub(super) async fn create(request: Json<CreateRequest>,
+                           repository: &'_ Repository,
+                           shares: State<'_, Shares>,
+                           token: &'_ Token) -> Result<Json<ShareInfo>, ApiError> {
+    let request = request.into_inner();
+
+    let expires = request.expires.unwrap_or_else(|| Utc::now() + Duration::days(7));
+    if expires <= Utc::now() {
+        return Err(ApiError::bad_request(String::from("Expiry must be in the future")));
+    }
+
+    let bundle = repository.archive().get(request.doc).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", request.doc)))?;
+
+    let metadata = bundle.read_metadata().await?;
+    ensure_visible(request.doc, &metadata, token)?;
+
+    // Links are fetched without a user who could unlock the encryption domain
+    if metadata.domain.is_some() {
+        return Err(ApiError::bad_request(format!("Documents of encryption domains can not be shared: {}", request.doc)));
+    }
+
+    let name = request.fragment.as_deref().unwrap_or("document");
+    if bundle.read(Kind::from(name)).await.map_err(InternalError)?.is_none() {
+        return Err(ApiError::not_found(format!("Fragment not found: {}/{}", request.doc, name)));
+    }
+
+    let share = shares.create(token.subject().to_string(),
+                              request.doc,
+                              request.fragment,
+                              expires).await?;
+
+    info!("Shared {} until {} ({})", share.doc, share.expires, share.id);
+
+    Ok(Json(share_info(&shares, share)))
+}
+
+#[delete("/shares/<id>")]
+pub(super) async fn revoke(id: String,
+                           shares: State<'_, Shares>,
+                           token: &'_ Token) -> Result<(), ApiError> {
+    if !shares.revoke(token.subject(), &id).await? {
+        return Err(ApiError::not_found(format!("Share not found: {}", id)));
+    }
+
+    info!("Revoked share {}", id);
+
+    return Ok(());
+}
+
+/// Serves a shared document - the signed link itself authorizes the access.
+#[get("/shares/link/<id>/<signature>")]
+pub(super) async fn link(id: String,
+                         signature: String,
+                         repository: &'_ Repository,
+                         shares: State<'_, Shares>,
+                         conditions: Conditions) -> Result<Served, ApiError> {
+    let share = shares.verify(&id, &signature).await
+        .ok_or_else(|| ApiError::not_found(String::from("Share not found or expired")))?;
+
+    // The document may have been deleted in the meantime
+    let bundle = repository.archive().get(share.doc).await
+        .ok_or_else(|| ApiError::gone(String::from("Shared document does not exist anymore")))?;
+
+    let kind = Kind::from(share.fragment.as_deref().unwrap_or("document"));
+
+    // Sensitive fragments of documents moved to an encryption domain after sharing are encrypted on disk
+    let metadata = bundle.read_metadata().await?;
+    if metadata.domain.is_some() && crypto::is_sensitive(&kind) {
+        return Err(ApiError::gone(String::from("Shared document has been encrypted")));
+    }
+
+    if bundle.read(&kind).await.map_err(InternalError)?.is_none() {
+        return Err(ApiError::gone(String::from("Shared fragment does not exist anymore")));
+    }
+
+    return Ok(Served::fragment(&bundle, &kind, None, &conditions).await?);
+}
@@ -0,0 +1,147 @@
+use chrono::{Duration, NaiveDate, Utc};
+use rocket::{get, State};
+use rocket_contrib::json::Json;
+
+use crate::meta::Metadata;
+use crate::proto::api::stats::{CalendarResponse, DashboardResponse, Quota, QuotaResponse, StatsResponse, Usage};
+use crate::proto::query::Query;
+use crate::quota::Quotas;
+use crate::repository::{Archived, Inboxed, Quarantined, Repository, Trashed};
+use crate::stats::{DEFAULT_AMOUNT, DEFAULT_DATE, group, months, top};
+
+use super::{ApiError, listing, Token};
+
+/// Reads the metadata of all archived documents visible to the user and matching the query.
+async fn archived(repository: &Repository, query: &Query, token: &Token) -> Result<Vec<Metadata>, ApiError> {
+    let mut docs = Vec::new();
+    for bundle in repository.archive().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if metadata.is_visible_to(token.subject()) && metadata.matches(query) {
+            docs.push(metadata);
+        }
+    }
+
+    return Ok(docs);
+}
+
+/// Counts archived documents and sums up their amounts per correspondent and year.
+///
+/// The amount is read from the `amount` property, the year from the `date` property if given.
+#[get("/stats?<query>&<amount>&<date>")]
+pub(super) async fn stats(query: Option<String>,
+                          amount: Option<String>,
+                          date: Option<String>,
+                          repository: &'_ Repository,
+                          token: &'_ Token) -> Result<Json<StatsResponse>, ApiError> {
+    let query = listing::query(query, None, None, None)?;
+    let docs = archived(&repository, &query, token).await?;
+
+    let groups = group(&docs, amount.as_deref().unwrap_or(DEFAULT_AMOUNT), date.as_deref());
+
+    Ok(Json(StatsResponse {
+        groups,
+    }))
+}
+
+/// Counts archived documents per day by upload date and by document date, i.e. to render a calendar heatmap.
+///
+/// The range defaults to the year up to today, the document date is read from the `date` property unless another
+/// property is given.
+#[get("/stats/calendar?<from>&<to>&<query>&<date>")]
+pub(super) async fn calendar(from: Option<String>,
+                             to: Option<String>,
+                             query: Option<String>,
+                             date: Option<String>,
+                             repository: &'_ Repository,
+                             token: &'_ Token) -> Result<Json<CalendarResponse>, ApiError> {
+    let parse = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| ApiError::bad_request(format!("Invalid date: {}", date)));
+
+    let to = match to {
+        Some(to) => parse(&to)?,
+        None => Utc::today().naive_utc(),
+    };
+    let from = match from {
+        Some(from) => parse(&from)?,
+        None => to - Duration::days(364),
+    };
+
+    if from > to {
+        return Err(ApiError::bad_request(format!("Range ends before it starts: {} - {}", from, to)));
+    }
+
+    let query = listing::query(query, None, None, None)?;
+    let docs = archived(&repository, &query, token).await?;
+
+    let days = crate::stats::calendar(&docs, date.as_deref().unwrap_or(DEFAULT_DATE), from, to);
+
+    Ok(Json(CalendarResponse {
+        from,
+        to,
+        days,
+    }))
+}
+
+/// Number of labels and correspondents listed on the dashboard
+const TOP: usize = 10;
+
+/// Summarizes the archive for the dashboard.
+///
+/// The counts cover the archived documents visible to the user, while the storage consumption and the failure rate
+/// cover the whole repository.
+#[get("/stats/dashboard")]
+pub(super) async fn dashboard(repository: &'_ Repository,
+                              token: &'_ Token) -> Result<Json<DashboardResponse>, ApiError> {
+    let docs = archived(&repository, &Query::default(), token).await?;
+
+    let labels = top(docs.iter().flat_map(|metadata| metadata.labels.iter().map(ToString::to_string)), TOP);
+    let correspondents = top(docs.iter().filter_map(|metadata| metadata.correspondent.clone()), TOP);
+
+    let usage = Usage {
+        inbox: repository.disk_usage_of::<Inboxed>().await?,
+        archive: repository.disk_usage_of::<Archived>().await?,
+        trash: repository.disk_usage_of::<Trashed>().await?,
+        quarantine: repository.disk_usage_of::<Quarantined>().await?,
+    };
+
+    // Every ingested document ends up either in the quarantine or in the inbox and moves on from there
+    let quarantined = repository.count::<Quarantined>().await?;
+    let ingested = quarantined
+        + repository.count::<Inboxed>().await?
+        + repository.count::<Archived>().await?
+        + repository.count::<Trashed>().await?;
+
+    let failure_rate = match ingested {
+        0 => 0.0,
+        ingested => quarantined as f64 / ingested as f64,
+    };
+
+    Ok(Json(DashboardResponse {
+        documents: docs.len(),
+        pages: docs.iter().map(|metadata| metadata.pages as u64).sum(),
+        months: months(&docs),
+        labels,
+        correspondents,
+        usage,
+        failure_rate,
+    }))
+}
+
+/// Reports the storage used by the documents of the repository and of the user along with their quotas.
+#[get("/stats/quota")]
+pub(super) async fn quota(repository: &'_ Repository,
+                          quotas: State<'_, Quotas>,
+                          token: &'_ Token) -> Result<Json<QuotaResponse>, ApiError> {
+    let usage = quotas.usage(&repository, token.subject()).await?;
+
+    Ok(Json(QuotaResponse {
+        repository: Quota {
+            used: usage.repository,
+            limit: quotas.repository(),
+        },
+        user: Quota {
+            used: usage.user,
+            limit: quotas.user(token.subject()),
+        },
+    }))
+}
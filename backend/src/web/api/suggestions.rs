@@ -0,0 +1,33 @@
+use std::str::FromStr;
+
+use rocket::get;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::suggestions::SuggestionsResponse;
+use crate::proto::model::DocId;
+use crate::repository::Repository;
+use crate::suggestions::Suggestions;
+
+use super::{ApiError, ensure_visible, Token};
+
+/// Proposes labels, correspondent and title for an inboxed document based on similar archived documents.
+#[get("/suggestions/<id>")]
+pub(super) async fn suggest(id: &RawStr,
+                            repository: &'_ Repository,
+                            suggestions: &'_ Suggestions,
+                            token: &'_ Token) -> Result<Json<SuggestionsResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let bundle = repository.inbox().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    let plaintext = bundle.read_plaintext().await?;
+
+    let response = suggestions.suggest(&repository, token.subject(), &plaintext, &metadata.labels).await?;
+
+    Ok(Json(response))
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use rocket::{get, put, State};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::meta::Metadata;
+use crate::proto::api::sync::{ChangedDoc, ChangesResponse, ListResponse, Location, SyncDoc, UpdateRequest, UpdateResponse};
+use crate::proto::model::{DocId, Kind, Label};
+use crate::repository::{Checksums, Event, Repository};
+
+use super::{ApiError, ensure_visible, Namespace, Token};
+
+#[get("/sync?<labels>")]
+pub(super) async fn list(labels: String,
+                         repository: &'_ Repository,
+                         namespace: State<'_, Namespace>,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let labels = labels.split(',')
+        .filter(|label| !label.is_empty())
+        .map(Label::from)
+        .collect::<Vec<_>>();
+
+    let mut docs = Vec::new();
+    for bundle in repository.archive().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if !metadata.is_visible_to(token.subject()) {
+            continue;
+        }
+        if !labels.iter().any(|label| metadata.labels.contains(label)) {
+            continue;
+        }
+
+        let fragments = bundle.fragment_names().await?.into_iter()
+            .filter(|name| Checksums::covers(name))
+            .collect();
+
+        docs.push(SyncDoc {
+            revision: bundle.metadata_revision().await?,
+            doc: namespace.qualify((*bundle.id(), metadata).into()),
+            fragments,
+        });
+    }
+
+    Ok(Json(ListResponse { docs }))
+}
+
+#[put("/sync/<id>", data = "<data>")]
+pub(super) async fn update(id: &RawStr,
+                           data: Json<UpdateRequest>,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<UpdateResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.archive().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    ensure_visible(id, &bundle.read_metadata().await?, token)?;
+
+    let revision = bundle.metadata_revision().await?;
+    if revision != data.base {
+        return Err(ApiError::conflict(format!("Bundle modified concurrently: {} (revision {})", id, revision)));
+    }
+
+    let data = data.into_inner();
+    bundle.write_metadata(&Metadata::from(data.metadata)).await?;
+
+    Ok(Json(UpdateResponse {
+        revision: bundle.metadata_revision().await?,
+    }))
+}
+
+/// Maximum number of journal entries processed per changes request
+const CHANGES_LIMIT: usize = 1000;
+
+#[get("/sync/changes?<cursor>")]
+pub(super) async fn changes(cursor: Option<u64>,
+                            repository: &'_ Repository,
+                            token: &'_ Token) -> Result<Json<ChangesResponse>, ApiError> {
+    let entries = repository.journal().since(cursor.unwrap_or(0)).await?;
+
+    let more = entries.len() > CHANGES_LIMIT;
+    let entries = &entries[..entries.len().min(CHANGES_LIMIT)];
+
+    let cursor = entries.last().map_or(cursor.unwrap_or(0), |entry| entry.seq);
+
+    // Multiple changes of a single document are collapsed into its current state
+    let ids = entries.iter()
+        .filter(|entry| !matches!(entry.change, Event::Staged(_) | Event::Quarantined(_)))
+        .map(|entry| *entry.change.id())
+        .collect::<BTreeSet<_>>();
+
+    let mut updated = Vec::new();
+    let mut deleted = Vec::new();
+    for id in ids {
+        if let Some(bundle) = repository.archive().get(id).await {
+            let metadata = bundle.read_metadata().await?;
+            if !metadata.is_visible_to(token.subject()) {
+                continue;
+            }
+
+            updated.push(ChangedDoc {
+                doc: (id, metadata).into(),
+                location: Location::Archive,
+                preview: bundle.read(Kind::Preview).await?.is_some(),
+            });
+        } else if let Some(bundle) = repository.inbox().get(id).await {
+            let metadata = bundle.read_metadata().await?;
+            if !metadata.is_visible_to(token.subject()) {
+                continue;
+            }
+
+            updated.push(ChangedDoc {
+                doc: (id, metadata).into(),
+                location: Location::Inbox,
+                preview: bundle.read(Kind::Preview).await?.is_some(),
+            });
+        } else {
+            deleted.push(id);
+        }
+    }
+
+    Ok(Json(ChangesResponse {
+        cursor,
+        more,
+        updated,
+        deleted,
+    }))
+}
@@ -0,0 +1,70 @@
+use std::str::FromStr;
+
+use rocket::{delete, get, post};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::trash::{ListResponse, TrashedDoc};
+use crate::proto::model::DocId;
+use crate::repository::Repository;
+
+use super::{ApiError, ensure_visible, Token};
+
+#[get("/trash")]
+pub(super) async fn list(repository: &'_ Repository,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let bundles = repository.trash().list().await?;
+
+    let mut docs = Vec::with_capacity(bundles.len());
+    for bundle in bundles {
+        let metadata = bundle.read_metadata().await?;
+        if !metadata.is_visible_to(token.subject()) {
+            continue;
+        }
+
+        let trashed = bundle.trashed().await?;
+
+        docs.push(TrashedDoc {
+            doc: (*bundle.id(), metadata).into(),
+            trashed,
+        });
+    }
+
+    Ok(Json(ListResponse {
+        count: docs.len() as u64,
+        docs,
+    }))
+}
+
+#[post("/trash/<id>/restore")]
+pub(super) async fn restore(id: &RawStr,
+                            repository: &'_ Repository,
+                            token: &'_ Token) -> Result<(), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.trash().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    ensure_visible(id, &bundle.read_metadata().await?, token)?;
+
+    bundle.restore().await?;
+
+    return Ok(());
+}
+
+#[delete("/trash/<id>")]
+pub(super) async fn purge(id: &RawStr,
+                          repository: &'_ Repository,
+                          token: &'_ Token) -> Result<(), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.trash().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+    ensure_visible(id, &bundle.read_metadata().await?, token)?;
+    bundle.purge().await?;
+
+    return Ok(());
+}
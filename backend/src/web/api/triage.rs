@@ -0,0 +1,174 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use rocket::{get, post, State};
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::crypto::Keyring;
+use crate::filing::Filing;
+use crate::meta::Metadata;
+use crate::proto::api::triage::{Decision, DecisionResponse, NextResponse, TriageItem};
+use crate::proto::model::DocId;
+use crate::repository::{Bundle, Inboxed, Repository};
+use crate::suggester::Suggester;
+use crate::suggestions::Suggestions;
+use crate::undo::{Action, Undo};
+
+use super::{ApiError, ensure_visible, Token, undo};
+use super::inbox::archive_bundle;
+
+/// Position of a document in the triage order, oldest upload first.
+type Position = (DateTime<Utc>, DocId);
+
+/// Lists the inbox documents to triage in the order they are served.
+///
+/// Snoozed documents are left out until they re-surface.
+async fn pending<'r>(repository: &'r Repository, token: &Token) -> Result<Vec<(Bundle<'r, Inboxed>, Metadata)>, ApiError> {
+    let today = Utc::today().naive_utc();
+
+    let mut docs = Vec::new();
+    for bundle in repository.inbox().list().await? {
+        let metadata = bundle.read_metadata().await?;
+        if !metadata.is_visible_to(token.subject()) || metadata.snoozed.map_or(false, |until| until > today) {
+            continue;
+        }
+
+        docs.push((bundle, metadata));
+    }
+
+    docs.sort_by_key(|(bundle, metadata)| (metadata.uploaded, *bundle.id()));
+
+    return Ok(docs);
+}
+
+/// Serves the first document after the given position with its suggestions pre-computed.
+///
+/// Documents before the position have been decided on or skipped in this session, so the session ends once every
+/// document has been served.
+async fn next(repository: &Repository,
+              suggestions: &Suggestions,
+              token: &Token,
+              after: Option<Position>) -> Result<NextResponse, ApiError> {
+    let docs = pending(repository, token).await?
+        .into_iter()
+        .filter(|(bundle, metadata)| after.map_or(true, |after| (metadata.uploaded, *bundle.id()) > after))
+        .collect::<Vec<_>>();
+
+    let next = match docs.first() {
+        Some((bundle, metadata)) => {
+            let plaintext = bundle.read_plaintext().await?;
+            let suggestions = suggestions.suggest(repository, token.subject(), &plaintext, &metadata.labels).await?;
+
+            Some(TriageItem {
+                doc: (*bundle.id(), metadata.clone()).into(),
+                suggestions,
+            })
+        }
+        None => None,
+    };
+
+    return Ok(NextResponse {
+        remaining: docs.len() as u64,
+        next,
+    });
+}
+
+/// Serves the next inbox document to triage.
+///
+/// A session is resumed by passing the last served document, which starts over if that one has left the inbox.
+#[get("/triage?<after>")]
+pub(super) async fn first(after: Option<String>,
+                          repository: &'_ Repository,
+                          suggestions: &'_ Suggestions,
+                          token: &'_ Token) -> Result<Json<NextResponse>, ApiError> {
+    let after = match after {
+        Some(after) => {
+            let id = DocId::from_str(&after)?;
+            match repository.inbox().get(id).await {
+                Some(bundle) => Some((bundle.read_metadata().await?.uploaded, id)),
+                None => None,
+            }
+        }
+        None => None,
+    };
+
+    return Ok(Json(next(&repository, &suggestions, token, after).await?));
+}
+
+/// Applies the decision on a triaged document and serves the next one in a single round-trip.
+#[post("/triage/<id>", data = "<decision>")]
+pub(super) async fn decide(id: &RawStr,
+                           decision: Json<Decision>,
+                           repository: &'_ Repository,
+                           suggester: State<'_, Box<dyn Suggester + Send + Sync>>,
+                           suggestions: &'_ Suggestions,
+                           keyring: &'_ Keyring,
+                           filing: &'_ Filing,
+                           buffer: &'_ Undo,
+                           token: &'_ Token) -> Result<Json<DecisionResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let bundle = repository.inbox().get(id).await
+        .ok_or_else(|| ApiError::not_found(format!("Bundle not found: {}", id)))?;
+
+    let mut metadata = bundle.read_metadata().await?;
+    ensure_visible(id, &metadata, token)?;
+
+    let position = (metadata.uploaded, id);
+    let previous = metadata.clone();
+
+    let action = match decision.into_inner() {
+        Decision::Archive { title, labels, correspondent } => {
+            // Values not decided on are taken from the suggestions unless the document already has them
+            let plaintext = bundle.read_plaintext().await?;
+            let suggested = suggestions.suggest(&repository, token.subject(), &plaintext, &metadata.labels).await?;
+
+            metadata.title = title
+                .or(metadata.title)
+                .or_else(|| suggested.title.map(|suggestion| suggestion.value));
+            metadata.correspondent = correspondent
+                .or(metadata.correspondent)
+                .or_else(|| suggested.correspondent.map(|suggestion| suggestion.value));
+            match labels {
+                Some(labels) => metadata.labels = labels,
+                None => metadata.labels.extend(suggested.labels.into_iter().map(|suggestion| suggestion.value)),
+            }
+
+            metadata.archived = Some(Utc::now());
+            archive_bundle(bundle, metadata, suggester.as_ref(), keyring, filing, token).await?;
+
+            Some(Action::Unarchive(id, previous))
+        }
+
+        Decision::Snooze { until } => {
+            if until <= Utc::today().naive_utc() {
+                return Err(ApiError::bad_request(format!("Snooze date not in the future: {}", until)));
+            }
+
+            metadata.snoozed = Some(until);
+            bundle.write_metadata(&metadata).await?;
+
+            Some(Action::Revert(id, previous))
+        }
+
+        Decision::Delete => {
+            bundle.delete().await?;
+            Some(Action::Restore(id))
+        }
+
+        Decision::Skip => None,
+    };
+
+    let undo = match action {
+        Some(action) => Some(undo::record(&buffer, token, vec![action]).await),
+        None => None,
+    };
+
+    return Ok(Json(DecisionResponse {
+        undo,
+        next: next(&repository, &suggestions, token, Some(position)).await?,
+    }));
+}
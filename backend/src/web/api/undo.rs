@@ -0,0 +1,108 @@
+use rocket::post;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+use tokio::io::AsyncReadExt;
+
+use crate::crypto::{self, Keyring};
+use crate::filing::Filing;
+use crate::proto::api::bulk::DocResult;
+use crate::proto::api::undo::{UndoInfo, UndoResponse};
+use crate::repository::Repository;
+use crate::undo::{Action, Undo};
+
+use super::{ApiError, Token};
+
+/// Records the actions reverting an operation applied by the authenticated user.
+pub(super) async fn record(buffer: &Undo, token: &Token, actions: Vec<Action>) -> UndoInfo {
+    let (operation, expires) = buffer.record(token.subject(), actions).await;
+    return UndoInfo { operation, expires };
+}
+
+async fn revert(repository: &Repository,
+                action: Action,
+                keyring: &Keyring,
+                token: &Token) -> Result<(), ApiError> {
+    let id = *action.id();
+
+    match action {
+        Action::Restore(_) => {
+            let bundle = repository.trash().get(id).await
+                .ok_or_else(|| ApiError::not_found(format!("Bundle not in trash: {}", id)))?;
+            bundle.restore().await?;
+        }
+
+        Action::Unarchive(_, mut metadata) => {
+            let bundle = repository.archive().get(id).await
+                .ok_or_else(|| ApiError::not_found(format!("Bundle not archived: {}", id)))?;
+
+            let current = bundle.read_metadata().await?;
+
+            // Keep the filing number, as the label may already be printed and is reused when archiving again
+            if let Some(asn) = current.properties.get(Filing::PROPERTY) {
+                metadata.properties.insert(String::from(Filing::PROPERTY), asn.clone());
+            }
+
+            // Fragments have been encrypted on archiving and must be decrypted before they return to the inbox
+            let key = match current.domain {
+                Some(domain) => Some(keyring.key(token.subject(), &domain).await
+                    .ok_or_else(|| ApiError::forbidden(format!("Encryption domain locked: {}", domain)))?),
+                None => None,
+            };
+
+            let bundle = bundle.unarchive().await?;
+
+            if let Some(key) = key {
+                for kind in crypto::sensitive(&metadata) {
+                    let mut data = Vec::new();
+                    match bundle.read(&kind).await? {
+                        Some(mut file) => file.read_to_end(&mut data).await.map_err(anyhow::Error::from)?,
+                        None => continue,
+                    };
+
+                    bundle.replace(kind, &crypto::decrypt(&key, &data)?).await?;
+                }
+            }
+
+            bundle.write_metadata(&metadata).await?;
+        }
+
+        Action::Revert(_, metadata) => {
+            if let Some(bundle) = repository.inbox().get(id).await {
+                bundle.write_metadata(&metadata).await?;
+            } else if let Some(bundle) = repository.archive().get(id).await {
+                bundle.write_metadata(&metadata).await?;
+            } else {
+                return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// Reverts a recently applied operation.
+///
+/// Documents changed in the meantime are reverted anyway. Failures are reported per document and do not stop the
+/// remaining documents from being reverted.
+#[post("/undo/<operation>")]
+pub(super) async fn undo(operation: &RawStr,
+                         buffer: &'_ Undo,
+                         repository: &'_ Repository,
+                         keyring: &'_ Keyring,
+                         token: &'_ Token) -> Result<Json<UndoResponse>, ApiError> {
+    let actions = buffer.take(operation.as_str(), token.subject()).await
+        .ok_or_else(|| ApiError::not_found(format!("Operation not found or expired: {}", operation)))?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let mut results = Vec::with_capacity(actions.len());
+    for action in actions {
+        let id = *action.id();
+        let error = revert(&repository, action, keyring, token).await.err()
+            .map(|err| err.to_string());
+
+        results.push(DocResult { id, error });
+    }
+
+    Ok(Json(UndoResponse { results }))
+}
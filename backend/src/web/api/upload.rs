@@ -1,59 +1,488 @@
-use anyhow::Context;
+use std::path::Path;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use chrono::Utc;
 use log::{info, trace};
-use rocket::{Data, post, State};
+use rocket::{Data, delete, get, patch, post, State};
 use rocket::data::ToByteUnit;
+use rocket::http::{ContentType, RawStr};
 use rocket_contrib::json::Json;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::info_span;
+use tracing_futures::Instrument;
 
-use crate::juicer::Juicer;
+use crate::auth::Authenticator;
+use crate::einvoice::Invoice;
+use crate::ingest::mail;
+use crate::juicer::converted_format;
+use crate::language;
 use crate::meta::Metadata;
-use crate::proto::api::upload::UploadResponse;
-use crate::proto::model::{DocInfo, Kind};
-use crate::repository::Repository;
+use crate::proto::api::upload::{ResumableInfo, UploadMailResponse, UploadResponse};
+use crate::proto::model::{DocId, DocInfo, Kind};
+use crate::queue::Queue;
+use crate::quota::Quotas;
+use crate::repository::{Bundle, Repository, sha256, Staging};
+use crate::uploads::{Upload, Uploads};
 
 use super::{ApiError, Token};
 
-#[post("/upload", format = "application/pdf", data = "<data>")]
+/// Rejects uploads whose checksum differs from the hex encoded SHA-256 checksum the client calculated, if given.
+///
+/// Uploads are verified before juicing, so files corrupted in transit never reach the inbox.
+fn verify(expected: Option<&str>, actual: &str) -> Result<(), ApiError> {
+    return match expected {
+        Some(expected) if !expected.eq_ignore_ascii_case(actual) => {
+            Err(ApiError::bad_request(format!("Checksum mismatch: expected {}, received {}", expected, actual)))
+        }
+        _ => Ok(()),
+    };
+}
+
+async fn verify_file(expected: Option<&str>, path: impl AsRef<Path>) -> Result<(), ApiError> {
+    if expected.is_none() {
+        return Ok(());
+    }
+
+    return verify(expected, &sha256(path).await?);
+}
+
+/// Checks the OCR languages requested on upload, which are passed on to the juicer.
+pub(super) fn ocr_languages(languages: Option<String>) -> Result<Option<String>, ApiError> {
+    return match languages {
+        Some(languages) if !language::is_valid(&languages) => {
+            Err(ApiError::bad_request(format!("Invalid OCR languages: {}", languages)))
+        }
+        languages => Ok(languages),
+    };
+}
+
+#[post("/upload?<filename>&<sha256>&<languages>", format = "application/pdf", data = "<data>")]
 pub(super) async fn upload_pdf(data: Data,
-                               repository: State<'_, Repository>,
-                               juicer: State<'_, Box<dyn Juicer + Send + Sync>>,
-                               _token: &'_ Token) -> Result<Json<UploadResponse>, ApiError> {
+                               filename: Option<String>,
+                               sha256: Option<String>,
+                               languages: Option<String>,
+                               repository: &'_ Repository,
+                               queue: &'_ Queue,
+                               quotas: State<'_, Quotas>,
+                               auth: State<'_, Arc<Authenticator>>,
+                               token: &'_ Token) -> Result<Json<UploadResponse>, ApiError> {
+    let languages = ocr_languages(languages)?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let metadata = Metadata {
+        owner: Some(token.subject().to_string()),
+        filename,
+        languages,
+        ..Metadata::new()
+    }.with_defaults(&auth.defaults(token));
+
+    // The uploaded bytes are accounted to the owner of the document
+    let reservation = quotas.reserve(&repository, metadata.owner.as_deref(), 0).await?;
+
     // Create a new staging area
     let staging = repository.stage().await?;
 
     info!("Uploading to staging bundle {}", staging.id());
+    let span = info_span!("upload", bundle = %staging.id(), format = "pdf");
 
-    match (|| async {
+    let result = (|| async {
         // Write the uploaded file to the staging area
-        let original_fragment = staging.write(Kind::other("original.pdf")).await?;
-        data.open(512.mebibytes()) // TODO: Make this limit configurable
-            .stream_to(original_fragment).await
+        let mut original_fragment = staging.write(Kind::other("original.pdf")).await?;
+        reservation.copy(&mut data.open(512.mebibytes()), &mut original_fragment).await // TODO: Make this limit configurable
             .context("Writing original.pdf to staging")?;
+        original_fragment.commit().await?;
+
+        verify_file(sha256.as_deref(), staging.path_of(Kind::other("original.pdf"))).await?;
 
         trace!("Original fragment written");
 
         // Create initial metadata file for the uploaded bundle
-        let metadata = Metadata::new();
         metadata.save(staging.write(Kind::Metadata).await?).await?;
 
         trace!("Metadata fragment written");
 
-        // Run the juicer over this upload
-        juicer.extract(&staging).await?;
+        return Result::<_, ApiError>::Ok(());
+    })().instrument(span.clone()).await;
+
+    let response = finish(&queue, staging, result).instrument(span).await?;
+    reservation.commit();
+
+    return Ok(response);
+}
+
+/// Uploads an office document or a scanned image which is converted to PDF by the juicer.
+///
+/// The format is determined by the content type or, if not specific, by the extension of the filename.
+#[post("/upload?<filename>&<sha256>&<languages>", data = "<data>", rank = 2)]
+pub(super) async fn upload_office(data: Data,
+                                  filename: Option<String>,
+                                  sha256: Option<String>,
+                                  languages: Option<String>,
+                                  content_type: Option<&ContentType>,
+                                  repository: &'_ Repository,
+                                  queue: &'_ Queue,
+                                  quotas: State<'_, Quotas>,
+                                  auth: State<'_, Arc<Authenticator>>,
+                                  token: &'_ Token) -> Result<Json<UploadResponse>, ApiError> {
+    let mimetype = content_type.map(|content_type| format!("{}/{}", content_type.top(), content_type.sub()));
+    let extension = converted_format(mimetype.as_deref(), filename.as_deref())
+        .ok_or_else(|| ApiError::bad_request(format!("Unsupported document type: {}", mimetype.as_deref().unwrap_or("unknown"))))?;
+    let languages = ocr_languages(languages)?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let metadata = Metadata {
+        owner: Some(token.subject().to_string()),
+        filename,
+        languages,
+        ..Metadata::new()
+    }.with_defaults(&auth.defaults(token));
+
+    // The uploaded bytes are accounted to the owner of the document
+    let reservation = quotas.reserve(&repository, metadata.owner.as_deref(), 0).await?;
+
+    // Create a new staging area
+    let staging = repository.stage().await?;
+
+    info!("Uploading {} document to staging bundle {}", extension, staging.id());
+    let span = info_span!("upload", bundle = %staging.id(), format = extension);
+
+    let result = (|| async {
+        // Write the uploaded file to the staging area, the juicer converts it to the original PDF
+        let original = format!("original.{}", extension);
+        let mut original_fragment = staging.write(Kind::other(&original)).await?;
+        reservation.copy(&mut data.open(512.mebibytes()), &mut original_fragment).await
+            .with_context(|| format!("Writing {} to staging", original))?;
+        original_fragment.commit().await?;
+
+        verify_file(sha256.as_deref(), staging.path_of(Kind::other(&original))).await?;
+
+        trace!("Original fragment written");
+
+        // Create initial metadata file for the uploaded bundle
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
+
+        trace!("Metadata fragment written");
+
+        return Result::<_, ApiError>::Ok(());
+    })().instrument(span.clone()).await;
+
+    let response = finish(&queue, staging, result).instrument(span).await?;
+    reservation.commit();
+
+    return Ok(response);
+}
+
+/// Uploads a mail which is split into the rendered body and a document per attachment.
+#[post("/upload?<sha256>", format = "message/rfc822", data = "<data>")]
+pub(super) async fn upload_mail(data: Data,
+                                sha256: Option<String>,
+                                repository: &'_ Repository,
+                                queue: &'_ Queue,
+                                quotas: State<'_, Quotas>,
+                                auth: State<'_, Arc<Authenticator>>,
+                                token: &'_ Token) -> Result<Json<UploadMailResponse>, ApiError> {
+    let mut raw = Vec::new();
+    data.open(64.mebibytes())
+        .read_to_end(&mut raw).await
+        .context("Reading mail")?;
+
+    verify(sha256.as_deref(), &hex::encode(Sha256::digest(&raw)))?;
+
+    // The documents split from the mail are accounted to their owner, which is the same for all of them
+    let defaults = auth.defaults(token);
+    let owner = defaults.owner.as_deref().unwrap_or(token.subject());
+    let reservation = quotas.reserve(&repository, Some(owner), raw.len() as u64).await?;
+
+    let docs = mail::ingest(&queue, &raw, true, Some(token.subject()), &defaults)
+        .instrument(info_span!("upload", format = "mail"))
+        .await?;
+
+    reservation.commit();
+
+    Ok(Json(UploadMailResponse {
+        docs: docs.into_iter().map(DocInfo::from).collect(),
+    }))
+}
+
+#[post("/upload?<sha256>", format = "application/xml", data = "<data>")]
+pub(super) async fn upload_xml(data: Data,
+                               sha256: Option<String>,
+                               repository: &'_ Repository,
+                               queue: &'_ Queue,
+                               quotas: State<'_, Quotas>,
+                               auth: State<'_, Arc<Authenticator>>,
+                               token: &'_ Token) -> Result<Json<UploadResponse>, ApiError> {
+    let repository = repository.acting_as(token.subject());
+
+    let defaults = auth.defaults(token);
+
+    // The uploaded bytes are accounted to the owner of the document
+    let owner = defaults.owner.as_deref().unwrap_or(token.subject());
+    let reservation = quotas.reserve(&repository, Some(owner), 0).await?;
+
+    // Create a new staging area
+    let staging = repository.stage().await?;
+
+    info!("Uploading e-invoice to staging bundle {}", staging.id());
+    let span = info_span!("upload", bundle = %staging.id(), format = "xml");
+
+    let result = (|| async {
+        // Write the uploaded XML to the staging area as the source fragment
+        let mut original_fragment = staging.write(Kind::other("original.xml")).await?;
+        reservation.copy(&mut data.open(16.mebibytes()), &mut original_fragment).await
+            .context("Writing original.xml to staging")?;
+        original_fragment.commit().await?;
+
+        verify_file(sha256.as_deref(), staging.path_of(Kind::other("original.xml"))).await?;
+
+        trace!("Original fragment written");
+
+        let mut xml = String::new();
+        staging.read(Kind::other("original.xml")).await?
+            .ok_or_else(|| anyhow!("Original fragment missing in bundle: {}", staging.id()))?
+            .read_to_string(&mut xml).await
+            .context("Reading original.xml from staging")?;
+
+        let invoice = Invoice::parse(&xml)
+            .context("Parsing e-invoice")?;
+
+        // Render a human readable representation which is juiced as if it was uploaded
+        let rendered = invoice.render()?;
+        reservation.grow(rendered.len() as u64)?;
+
+        let mut rendered_fragment = staging.write(Kind::other("original.pdf")).await?;
+        rendered_fragment.write_all(&rendered).await
+            .context("Writing original.pdf to staging")?;
+        rendered_fragment.commit().await?;
+
+        trace!("Rendered fragment written");
+
+        // Create initial metadata file from the invoice fields
+        let metadata = Metadata {
+            title: Some(invoice.title()),
+            properties: invoice.properties(),
+            due: invoice.due_date(),
+            owner: Some(token.subject().to_string()),
+            ..Metadata::new()
+        }.with_defaults(&defaults);
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
+
+        trace!("Metadata fragment written");
+
+        return Result::<_, ApiError>::Ok(());
+    })().instrument(span.clone()).await;
+
+    let response = finish(&queue, staging, result).instrument(span).await?;
+    reservation.commit();
+
+    return Ok(response);
+}
+
+/// Starts a resumable upload of a PDF, office document or scanned image of the given total length.
+///
+/// The document is sent in chunks, each appended at the offset received so far. Clients losing the connection ask
+/// for the offset and continue from there instead of starting over. The bundle is queued for juicing once all bytes
+/// have arrived. Uploads exceeding a storage quota are rejected upfront by their length.
+#[post("/uploads?<filename>&<length>&<sha256>")]
+pub(super) async fn begin_resumable(filename: Option<String>,
+                                    length: u64,
+                                    sha256: Option<String>,
+                                    content_type: Option<&ContentType>,
+                                    repository: &'_ Repository,
+                                    quotas: State<'_, Quotas>,
+                                    auth: State<'_, Arc<Authenticator>>,
+                                    token: &'_ Token) -> Result<Json<ResumableInfo>, ApiError> {
+    if length == 0 || length > 512.mebibytes().as_u64() {
+        return Err(ApiError::bad_request(format!("Invalid upload length: {}", length)));
+    }
+
+    let mimetype = content_type.map(|content_type| format!("{}/{}", content_type.top(), content_type.sub()));
+    let extension = if mimetype.as_deref() == Some("application/pdf") || filename.as_deref().map_or(false, |filename| filename.to_lowercase().ends_with(".pdf")) {
+        "pdf"
+    } else {
+        converted_format(mimetype.as_deref(), filename.as_deref())
+            .ok_or_else(|| ApiError::bad_request(format!("Unsupported document type: {}", mimetype.as_deref().unwrap_or("unknown"))))?
+    };
 
-        trace!("Juicer finished");
+    let metadata = Metadata {
+        owner: Some(token.subject().to_string()),
+        filename,
+        ..Metadata::new()
+    }.with_defaults(&auth.defaults(token));
+
+    quotas.check(&repository, metadata.owner.as_deref(), length).await?;
+
+    let repository = repository.acting_as(token.subject());
+
+    let staging = repository.stage().await?;
+
+    info!("Starting resumable upload of {} bytes to staging bundle {}", length, staging.id());
+
+    let upload = Upload {
+        owner: token.subject().to_string(),
+        created: Utc::now(),
+        length,
+        extension: extension.to_string(),
+        sha256,
+    };
+
+    let result = (|| async {
+        staging.write(upload.original()).await?.commit().await?;
+        upload.save(&staging).await?;
+
+        metadata.save(staging.write(Kind::Metadata).await?).await?;
 
         return Result::<_, ApiError>::Ok(());
-    })().await {
+    })().await;
+
+    if let Err(err) = result {
+        staging.delete().await?;
+        return Err(err);
+    }
+
+    Ok(Json(ResumableInfo {
+        id: *staging.id(),
+        offset: 0,
+        length,
+        doc: None,
+    }))
+}
+
+/// Looks up a pending resumable upload of the requesting user.
+async fn resumable<'r>(id: &RawStr, repository: &'r Repository, token: &Token) -> Result<(Bundle<'r, Staging>, Upload), ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let not_found = || ApiError::not_found(format!("Upload not found: {}", id));
+
+    let staging = repository.staging().get(id).await
+        .ok_or_else(not_found)?;
+
+    return match Upload::load(&staging).await? {
+        Some(upload) if upload.owner == token.subject() => Ok((staging, upload)),
+        _ => Err(not_found()),
+    };
+}
+
+/// Returns the offset the next chunk of a resumable upload must start at.
+#[get("/uploads/<id>")]
+pub(super) async fn get_resumable(id: &RawStr,
+                                  repository: &'_ Repository,
+                                  token: &'_ Token) -> Result<Json<ResumableInfo>, ApiError> {
+    let (staging, upload) = resumable(id, &repository, token).await?;
+
+    Ok(Json(ResumableInfo {
+        id: *staging.id(),
+        offset: upload.offset(&staging).await?,
+        length: upload.length,
+        doc: None,
+    }))
+}
+
+/// Appends a chunk to a resumable upload.
+///
+/// The offset must match the number of bytes received so far, so chunks lost or sent twice are detected. Chunks
+/// exceeding the announced length are discarded. Receiving the last chunk queues the document for juicing.
+#[patch("/uploads/<id>?<offset>", data = "<data>")]
+pub(super) async fn append_resumable(id: &RawStr,
+                                     offset: u64,
+                                     data: Data,
+                                     repository: &'_ Repository,
+                                     uploads: State<'_, Uploads>,
+                                     queue: &'_ Queue,
+                                     quotas: State<'_, Quotas>,
+                                     token: &'_ Token) -> Result<Json<ResumableInfo>, ApiError> {
+    let repository = repository.acting_as(token.subject());
+
+    let (staging, upload) = resumable(id, &repository, token).await?;
+
+    let _active = uploads.begin(*staging.id())
+        .ok_or_else(|| ApiError::conflict(format!("Upload is receiving another chunk: {}", staging.id())))?;
+
+    let current = upload.offset(&staging).await?;
+    if offset != current {
+        return Err(ApiError::conflict(format!("Offset mismatch: expected {}, received {}", current, offset)));
+    }
+
+    // The received chunks are accounted to the owner of the document
+    let owner = staging.read_metadata().await?.owner;
+    let reservation = quotas.reserve(&repository, owner.as_deref(), 0).await?;
+
+    // Accept a single byte more than remaining to detect chunks exceeding the announced length
+    let remaining = upload.length - current;
+    let appended = reservation.copy(&mut data.open((remaining + 1).bytes()), &mut staging.append(upload.original()).await?).await
+        .context("Appending chunk to staging");
+    if let Err(err) = appended {
+        staging.truncate(upload.original(), current).await?;
+        return Err(err.into());
+    }
+
+    let offset = upload.offset(&staging).await?;
+    if offset > upload.length {
+        staging.truncate(upload.original(), current).await?;
+        return Err(ApiError::bad_request(format!("Chunk exceeds upload length of {} bytes", upload.length)));
+    }
+
+    reservation.commit();
+
+    trace!("Received {} bytes of {} for staging bundle {}", offset, upload.length, staging.id());
+
+    if offset < upload.length {
+        return Ok(Json(ResumableInfo {
+            id: *staging.id(),
+            offset,
+            length: upload.length,
+            doc: None,
+        }));
+    }
+
+    info!("Completed resumable upload to staging bundle {}", staging.id());
+
+    let result = (|| async {
+        verify_file(upload.sha256.as_deref(), staging.path_of(upload.original())).await?;
+        Upload::complete(&staging).await?;
+
+        return Result::<_, ApiError>::Ok(());
+    })().await;
+
+    let id = *staging.id();
+    let response = finish(&queue, staging, result).await?;
+
+    Ok(Json(ResumableInfo {
+        id,
+        offset,
+        length: upload.length,
+        doc: Some(response.into_inner().doc),
+    }))
+}
+
+/// Aborts a resumable upload and discards the bytes received so far.
+#[delete("/uploads/<id>")]
+pub(super) async fn abort_resumable(id: &RawStr,
+                                    repository: &'_ Repository,
+                                    token: &'_ Token) -> Result<(), ApiError> {
+    let (staging, _) = resumable(id, &repository, token).await?;
+
+    info!("Aborting resumable upload to staging bundle {}", staging.id());
+    staging.delete().await?;
+
+    return Ok(());
+}
+
+pub(super) async fn finish(queue: &Queue, staging: Bundle<'_, Staging>, result: Result<(), ApiError>) -> Result<Json<UploadResponse>, ApiError> {
+    match result {
         Ok(()) => {
-            // Make a inboxed bundle from the staging
-            let bundle = staging.create().await?;
-            let metadata = bundle.read_metadata().await?;
+            // Queue the staging for juicing, it will be moved to the inbox afterwards
+            let metadata = staging.read_metadata().await?;
+            let id = queue.enqueue(staging).await?;
 
             return Ok(Json(UploadResponse {
-                doc: DocInfo {
-                    id: *bundle.id(),
-                    metadata: metadata.into(),
-                }
+                doc: (id, metadata).into(),
             }));
         }
         Err(err) => {
@@ -0,0 +1,44 @@
+use std::str::FromStr;
+
+use rocket::get;
+use rocket::http::RawStr;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::verify::{Problem, VerifyResponse};
+use crate::proto::model::DocId;
+use crate::repository::{Repository, Verification};
+
+use super::{ApiError, ensure_visible, Token};
+
+/// Checks the integrity of a single document in the inbox or archive.
+#[get("/verify/<id>")]
+pub(super) async fn verify(id: &RawStr,
+                           repository: &'_ Repository,
+                           token: &'_ Token) -> Result<Json<VerifyResponse>, ApiError> {
+    let id = DocId::from_str(id.as_str())?;
+
+    let verification = if let Some(bundle) = repository.inbox().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        bundle.verify().await?
+    } else if let Some(bundle) = repository.archive().get(id).await {
+        ensure_visible(id, &bundle.read_metadata().await?, token)?;
+        bundle.verify().await?
+    } else {
+        return Err(ApiError::not_found(format!("Bundle not found: {}", id)));
+    };
+
+    Ok(Json(response(verification)))
+}
+
+fn response(verification: Verification) -> VerifyResponse {
+    return VerifyResponse {
+        id: verification.id,
+        ok: verification.is_ok(),
+        problems: verification.problems.into_iter()
+            .map(|problem| Problem {
+                kind: problem.kind().to_string(),
+                description: problem.to_string(),
+            })
+            .collect(),
+    };
+}
@@ -0,0 +1,103 @@
+use async_trait::async_trait;
+use rocket::{get, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::versions::{VersionInfo, VersionsResponse};
+use crate::utils::StrExt;
+
+/// The current version of the API.
+const CURRENT: u32 = 1;
+
+/// Versions currently served, oldest first.
+const VERSIONS: &[(u32, bool)] = &[
+    (1, false),
+];
+
+/// Mount point of the API.
+const ROOT: &str = "/api";
+
+/// Returns the mount point of a version.
+fn path(version: u32) -> String {
+    return format!("{}/v{}", ROOT, version);
+}
+
+/// Returns the mount point of the current version.
+pub(super) fn current() -> String {
+    return path(CURRENT);
+}
+
+/// Parses a path segment naming an API version, like `v1`.
+pub(super) fn parse(segment: &str) -> Option<u32> {
+    let version = segment.strip_prefix('v')?.parse().ok()?;
+    return VERSIONS.iter().any(|(v, _)| *v == version).then_some(version);
+}
+
+/// Adds the version headers to all API responses.
+///
+/// Each version is mounted at `/api/v<version>`. Within a version, only backwards compatible changes are made, like
+/// adding endpoints or optional fields. Breaking changes introduce a new version while the previous one is still served
+/// for a while and flagged as deprecated. Responses of deprecated paths carry a `Deprecation` header and link their
+/// successor, so integrations can migrate before the paths are removed.
+pub struct Versioning {}
+
+#[async_trait]
+impl Fairing for Versioning {
+    fn info(&self) -> Info {
+        Info {
+            name: "Versioning",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let uri = request.uri().path();
+
+        let rest = match uri.strip_prefix(ROOT).filter(|rest| rest.starts_with('/')) {
+            Some(rest) => rest,
+            None => return,
+        };
+
+        // Listing the versions and the specification is not subject to versioning itself
+        if rest == "/versions" || rest == "/openapi.json" {
+            return;
+        }
+
+        let (segment, tail) = rest[1..].split2('/').unwrap_or((&rest[1..], ""));
+        match parse(segment) {
+            Some(version) => {
+                response.set_header(Header::new("API-Version", version.to_string()));
+
+                if VERSIONS.iter().any(|(v, deprecated)| *v == version && *deprecated) {
+                    response.set_header(Header::new("Deprecation", "true"));
+                    response.set_header(Header::new("Link", format!("<{}/{}>; rel=\"successor-version\"", path(CURRENT), tail)));
+                }
+            }
+
+            None => {
+                // Paths without a version predate versioning and are served by the first version
+                response.set_header(Header::new("API-Version", VERSIONS[0].0.to_string()));
+                response.set_header(Header::new("Deprecation", "true"));
+                response.set_header(Header::new("Link", format!("<{}{}>; rel=\"successor-version\"", path(CURRENT), rest)));
+            }
+        }
+    }
+}
+
+/// Lists the versions of the API served.
+///
+/// This is served at the API mount point only, so clients can negotiate the version before using any other endpoint.
+#[get("/versions")]
+pub(super) async fn list() -> Json<VersionsResponse> {
+    Json(VersionsResponse {
+        current: CURRENT,
+        versions: VERSIONS.iter()
+            .map(|(version, deprecated)| VersionInfo {
+                version: *version,
+                path: path(*version),
+                deprecated: *deprecated,
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,27 @@
+use chrono::Utc;
+use rocket::get;
+use rocket_contrib::json::Json;
+
+use crate::proto::api::warranties::{ListResponse, WarrantyInfo};
+use crate::repository::Repository;
+use crate::warranties;
+
+use super::{ApiError, Token};
+
+/// Lists the documents still under warranty ordered by the end of their warranty.
+#[get("/warranties")]
+pub(super) async fn list(repository: &'_ Repository,
+                         token: &'_ Token) -> Result<Json<ListResponse>, ApiError> {
+    let today = Utc::today().naive_utc();
+
+    let docs = warranties::covered(&repository, today, |metadata| metadata.is_visible_to(token.subject())).await?;
+
+    Ok(Json(ListResponse {
+        docs: docs.into_iter()
+            .filter_map(|(id, metadata)| Some(WarrantyInfo {
+                expires: warranties::expires(&metadata)?,
+                doc: (id, metadata).into(),
+            }))
+            .collect(),
+    }))
+}
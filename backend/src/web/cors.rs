@@ -0,0 +1,66 @@
+use std::io::Cursor;
+
+use async_trait::async_trait;
+use rocket::{Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+
+use crate::config::Cors as Config;
+
+/// Headers clients may send in cross-origin requests
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type";
+
+/// Headers of responses exposed to cross-origin clients
+const EXPOSED_HEADERS: &str = "Authorization, API-Version, Deprecation, Link, Retry-After";
+
+/// Allows browsers to access the API from the configured origins, i.e. a separately hosted frontend.
+///
+/// Preflight requests of allowed origins are answered directly. Requests from other origins are served without any
+/// CORS headers, so browsers refuse to hand out the response.
+pub struct Cors {
+    config: Config,
+}
+
+impl Cors {
+    pub fn new(config: Config) -> Self {
+        return Self { config };
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        return self.config.origins.iter()
+            .any(|allowed| allowed == "*" || allowed.trim_end_matches('/').eq_ignore_ascii_case(origin));
+    }
+}
+
+#[async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // Responses differ by origin, caches must not hand them out to other origins
+        response.adjoin_header(Header::new("Vary", "Origin"));
+
+        let origin = match request.headers().get_one("Origin") {
+            Some(origin) if self.allows(origin) => origin,
+            _ => return,
+        };
+
+        response.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+        response.set_header(Header::new("Access-Control-Expose-Headers", EXPOSED_HEADERS));
+
+        // Preflight requests are not routed, so they are answered here
+        if request.method() == Method::Options && response.status() == Status::NotFound {
+            response.set_status(Status::NoContent);
+            response.set_sized_body(0, Cursor::new(""));
+
+            response.set_header(Header::new("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE"));
+            response.set_header(Header::new("Access-Control-Allow-Headers", ALLOWED_HEADERS));
+            response.set_header(Header::new("Access-Control-Max-Age", self.config.max_age.to_string()));
+        }
+    }
+}
@@ -0,0 +1,611 @@
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use bytes::BytesMut;
+use chrono::{DateTime, Datelike, Utc};
+use futures::TryStreamExt;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::header;
+use hyper::service::{make_service_fn, service_fn};
+use log::{debug, error, info};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+use crate::auth::{Authenticator, Token};
+use crate::config::Dav as Config;
+use crate::meta::Metadata;
+use crate::mimetype;
+use crate::proto::api::auth::Scope;
+use crate::proto::model::{DocId, Kind};
+use crate::repository::{Archived, Bundle, Repository};
+use crate::status::Status;
+
+/// The folders at the root of the view
+const YEARS: &str = "Years";
+const LABELS: &str = "Labels";
+const CORRESPONDENTS: &str = "Correspondents";
+
+/// Methods supported on all resources
+const ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND";
+
+/// Characters kept as they are in hrefs
+const HREF: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+/// An archived document visible in the view.
+struct Doc {
+    id: DocId,
+    metadata: Metadata,
+}
+
+/// An entry of a virtual folder.
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    Folder(String),
+    File(DocId, String),
+}
+
+/// A resource addressed by a path.
+#[derive(Debug, Clone, PartialEq)]
+enum Resource {
+    Folder(Vec<Entry>),
+    File(DocId),
+}
+
+/// Names the file of a document after its title, keeping the ID to tell documents of the same title apart.
+fn filename(id: &DocId, metadata: &Metadata) -> String {
+    let title = metadata.title.as_deref()
+        .or_else(|| metadata.filename.as_deref().map(|filename| filename.rsplitn(2, '.').last().unwrap_or(filename)))
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or("Untitled");
+
+    let title = title.trim().chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect::<String>();
+
+    return format!("{} ({}).pdf", title, id);
+}
+
+/// Parses the ID of the document from its filename.
+fn parse_filename(name: &str) -> Option<DocId> {
+    let name = name.strip_suffix(").pdf")?;
+    let id = &name[name.rfind('(')? + 1..];
+    return DocId::from_str(id).ok();
+}
+
+/// Lists the entries of a virtual folder, or returns `None` if the path does not refer to a folder.
+///
+/// Documents are filed by the year they have been uploaded, by each of their labels and by their correspondent.
+/// Labels are nested by their hierarchy.
+fn children(docs: &[Doc], path: &[String]) -> Option<Vec<Entry>> {
+    let files = |docs: Vec<&Doc>| {
+        let mut files = docs.into_iter()
+            .map(|doc| (filename(&doc.id, &doc.metadata), doc.id))
+            .collect::<Vec<_>>();
+        files.sort();
+        return files.into_iter().map(|(name, id)| Entry::File(id, name));
+    };
+
+    let folders = |names: BTreeSet<String>| names.into_iter().map(Entry::Folder);
+
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+    return match path.as_slice() {
+        [] => Some(vec![
+            Entry::Folder(YEARS.to_string()),
+            Entry::Folder(LABELS.to_string()),
+            Entry::Folder(CORRESPONDENTS.to_string()),
+        ]),
+
+        [YEARS] => Some(folders(docs.iter()
+            .map(|doc| doc.metadata.uploaded.year().to_string())
+            .collect()).collect()),
+
+        [YEARS, year] => {
+            let year = year.parse::<i32>().ok()?;
+            let docs = docs.iter()
+                .filter(|doc| doc.metadata.uploaded.year() == year)
+                .collect::<Vec<_>>();
+
+            if docs.is_empty() {
+                return None;
+            }
+
+            Some(files(docs).collect())
+        }
+
+        [CORRESPONDENTS] => Some(folders(docs.iter()
+            .filter_map(|doc| doc.metadata.correspondent.clone())
+            .collect()).collect()),
+
+        [CORRESPONDENTS, name] => {
+            let docs = docs.iter()
+                .filter(|doc| doc.metadata.correspondent.as_deref() == Some(*name))
+                .collect::<Vec<_>>();
+
+            if docs.is_empty() {
+                return None;
+            }
+
+            Some(files(docs).collect())
+        }
+
+        [LABELS, segments @ ..] => {
+            let mut nested = BTreeSet::new();
+            let mut labeled = Vec::new();
+
+            for doc in docs {
+                for label in &doc.metadata.labels {
+                    let label = label.to_string();
+                    let parts = label.split('/').collect::<Vec<_>>();
+
+                    if parts.len() > segments.len() && parts[..segments.len()] == *segments {
+                        nested.insert(parts[segments.len()].to_string());
+                    } else if !segments.is_empty() && parts == segments {
+                        labeled.push(doc);
+                    }
+                }
+            }
+
+            if !segments.is_empty() && nested.is_empty() && labeled.is_empty() {
+                return None;
+            }
+
+            Some(folders(nested).chain(files(labeled)).collect())
+        }
+
+        _ => None,
+    };
+}
+
+/// Resolves a path to a folder or to the file of a document in a folder.
+fn resolve(docs: &[Doc], path: &[String]) -> Option<Resource> {
+    if let Some(children) = children(docs, path) {
+        return Some(Resource::Folder(children));
+    }
+
+    let (name, parent) = path.split_last()?;
+    let id = parse_filename(name)?;
+
+    return children(docs, parent)?.into_iter()
+        .find(|entry| *entry == Entry::File(id, name.clone()))
+        .map(|_| Resource::File(id));
+}
+
+/// Splits a request path into its decoded segments.
+fn parse_path(path: &str) -> Option<Vec<String>> {
+    return path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8().ok().map(String::from))
+        .collect();
+}
+
+fn href(path: &[String], folder: bool) -> String {
+    let mut href = String::new();
+    for segment in path {
+        href.push('/');
+        href.extend(utf8_percent_encode(segment, HREF));
+    }
+
+    if folder || path.is_empty() {
+        href.push('/');
+    }
+
+    return href;
+}
+
+fn escape(s: &str) -> String {
+    return s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+fn propstat(href: &str, name: &str, props: &str) -> String {
+    return format!(
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop><D:displayname>{}</D:displayname>{}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+        escape(href), escape(name), props,
+    );
+}
+
+fn folder_props() -> String {
+    return String::from("<D:resourcetype><D:collection/></D:resourcetype>");
+}
+
+fn file_props(content_type: &str, length: u64, modified: DateTime<Utc>) -> String {
+    return format!(
+        "<D:resourcetype/><D:getcontenttype>{}</D:getcontenttype><D:getcontentlength>{}</D:getcontentlength><D:getlastmodified>{}</D:getlastmodified>",
+        escape(content_type), length, modified.format("%a, %d %b %Y %H:%M:%S GMT"),
+    );
+}
+
+/// Returns the MIME type of the document of a bundle, as recorded when it has been stored.
+async fn content_type(bundle: &Bundle<'_, Archived>) -> Result<String> {
+    let recorded = bundle.fragment_info(Kind::Document).await?.map(|info| info.mimetype);
+    return Ok(recorded.unwrap_or_else(|| mimetype::of_kind(&Kind::Document).unwrap_or(mimetype::UNKNOWN).to_string()));
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    return response;
+}
+
+/// Serves a read-only view of the archive via WebDAV, so documents can be browsed from any file manager.
+///
+/// Requests are authorized like requests to the API, so clients authenticate with the username and password of an API
+/// key or with an API token. Tokens need the search scope to browse the folders and the read scope to download the
+/// documents. Documents of encryption domains are left out, as they can only be decrypted by the API once the domain
+/// has been unlocked.
+pub struct Dav {
+    config: Config,
+
+    auth: Arc<Authenticator>,
+
+    repository: Repository,
+
+    status: Arc<Status>,
+}
+
+impl Dav {
+    pub fn from_config(config: Config, auth: Arc<Authenticator>, repository: Repository, status: Arc<Status>) -> Self {
+        return Self {
+            config,
+            auth,
+            repository,
+            status,
+        };
+    }
+
+    /// Returns the token authorized by the request.
+    async fn authenticate(&self, request: &Request<Body>) -> Option<Token> {
+        let authorization = request.headers().get(header::AUTHORIZATION)?
+            .to_str().ok()?;
+
+        return self.auth.authorize(authorization).await;
+    }
+
+    /// Lists the archived documents visible to the user.
+    async fn docs(&self, user: &str) -> Result<Vec<Doc>> {
+        let mut docs = Vec::new();
+        for bundle in self.repository.archive().list().await? {
+            let metadata = bundle.read_metadata().await?;
+            if metadata.is_visible_to(user) && metadata.domain.is_none() {
+                docs.push(Doc { id: *bundle.id(), metadata });
+            }
+        }
+
+        return Ok(docs);
+    }
+
+    async fn file(&self, docs: &[Doc], path: &[String], id: DocId) -> Result<String> {
+        let doc = docs.iter().find(|doc| doc.id == id)
+            .expect("Resolved document not listed");
+
+        let bundle = self.repository.archive().get(id).await
+            .with_context(|| format!("Bundle vanished: {}", id))?;
+        let length = tokio::fs::metadata(bundle.path_of(Kind::Document)).await?.len();
+        let content_type = content_type(&bundle).await?;
+
+        let name = path.last().map_or("", String::as_str);
+        let modified = doc.metadata.archived.unwrap_or(doc.metadata.uploaded);
+
+        return Ok(propstat(&href(path, false), name, &file_props(&content_type, length, modified)));
+    }
+
+    async fn propfind(&self, request: &Request<Body>, docs: &[Doc], path: &[String], resource: Resource) -> Result<Response<Body>> {
+        // An infinite depth is answered like a depth of one, as the clients walk down the folders anyway
+        let depth = request.headers().get("Depth")
+            .and_then(|depth| depth.to_str().ok())
+            .unwrap_or("infinity");
+
+        let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#);
+
+        match resource {
+            Resource::Folder(children) => {
+                let name = path.last().map_or("", String::as_str);
+                body.push_str(&propstat(&href(path, true), name, &folder_props()));
+
+                if depth != "0" {
+                    for entry in children {
+                        match entry {
+                            Entry::Folder(name) => {
+                                let mut path = path.to_vec();
+                                path.push(name.clone());
+                                body.push_str(&propstat(&href(&path, true), &name, &folder_props()));
+                            }
+
+                            Entry::File(id, name) => {
+                                let mut path = path.to_vec();
+                                path.push(name);
+                                body.push_str(&self.file(docs, &path, id).await?);
+                            }
+                        }
+                    }
+                }
+            }
+
+            Resource::File(id) => {
+                body.push_str(&self.file(docs, path, id).await?);
+            }
+        }
+
+        body.push_str("</D:multistatus>");
+
+        return Ok(Response::builder()
+            .status(StatusCode::MULTI_STATUS)
+            .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(Body::from(body))?);
+    }
+
+    async fn get(&self, request: &Request<Body>, id: DocId) -> Result<Response<Body>> {
+        let bundle = self.repository.archive().get(id).await
+            .with_context(|| format!("Bundle vanished: {}", id))?;
+
+        let file = bundle.open(Kind::Document).await?;
+        let length = file.metadata().await?.len();
+
+        let body = if request.method() == Method::HEAD {
+            Body::empty()
+        } else {
+            Body::wrap_stream(FramedRead::new(file, BytesCodec::new()).map_ok(BytesMut::freeze))
+        };
+
+        return Ok(Response::builder()
+            .header(header::CONTENT_TYPE, content_type(&bundle).await?)
+            .header(header::CONTENT_LENGTH, length)
+            .body(body)?);
+    }
+
+    async fn respond(&self, request: Request<Body>) -> Result<Response<Body>> {
+        if request.method() == Method::OPTIONS {
+            return Ok(Response::builder()
+                .header("DAV", "1")
+                .header(header::ALLOW, ALLOW)
+                .body(Body::empty())?);
+        }
+
+        let token = match self.authenticate(&request).await {
+            Some(token) => token,
+            None => {
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header(header::WWW_AUTHENTICATE, r#"Basic realm="adacta""#)
+                    .body(Body::empty())?);
+            }
+        };
+
+        // Browsing the folders lists documents, while downloading reads them
+        let scope = if request.method() == Method::GET || request.method() == Method::HEAD {
+            Scope::Read
+        } else {
+            Scope::Search
+        };
+
+        if !token.permits(scope) {
+            return Ok(status(StatusCode::FORBIDDEN));
+        }
+
+        let user = token.subject();
+
+        let path = match parse_path(request.uri().path()) {
+            Some(path) => path,
+            None => return Ok(status(StatusCode::BAD_REQUEST)),
+        };
+
+        let docs = self.docs(user).await?;
+
+        let resource = match resolve(&docs, &path) {
+            Some(resource) => resource,
+            None => return Ok(status(StatusCode::NOT_FOUND)),
+        };
+
+        debug!("WebDAV {} {:?} by {}", request.method(), path, user);
+
+        return match (request.method().as_str(), resource) {
+            ("PROPFIND", resource) => self.propfind(&request, &docs, &path, resource).await,
+            ("GET", Resource::File(id)) | ("HEAD", Resource::File(id)) => self.get(&request, id).await,
+            _ => Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, ALLOW)
+                .body(Body::empty())?),
+        };
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        return match self.respond(request).await {
+            Ok(response) => response,
+            Err(err) => {
+                error!("Failed to serve WebDAV request: {:#}", err);
+                status(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        };
+    }
+
+    pub async fn run(self) {
+        let status = self.status.clone();
+        let address = format!("{}:{}", self.config.address, self.config.port);
+
+        let dav = Arc::new(self);
+
+        let result: Result<()> = async {
+            let address = SocketAddr::from_str(&address)
+                .with_context(|| format!("Invalid WebDAV address: {}", address))?;
+
+            let service = make_service_fn(move |_| {
+                let dav = dav.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let dav = dav.clone();
+                        async move { Ok::<_, Infallible>(dav.handle(request).await) }
+                    }))
+                }
+            });
+
+            info!("Serving WebDAV on {}", address);
+            Server::try_bind(&address)?.serve(service).await?;
+
+            return Ok(());
+        }.await;
+
+        if let Err(err) = result {
+            error!("WebDAV server failed: {:#}", err);
+            status.failed("dav", &err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use spectral::prelude::*;
+    use tokio::io::AsyncWriteExt;
+
+    use crate::proto::model::Label;
+
+    use super::*;
+
+    fn doc(title: &str, year: i32, labels: &[&str], correspondent: Option<&str>) -> Doc {
+        let mut metadata = Metadata::new();
+        metadata.title = Some(title.to_string());
+        metadata.uploaded = metadata.uploaded.with_year(year).unwrap();
+        metadata.labels = labels.iter().map(|label| Label::from(*label)).collect();
+        metadata.correspondent = correspondent.map(String::from);
+
+        return Doc { id: DocId::random(), metadata };
+    }
+
+    fn path(path: &[&str]) -> Vec<String> {
+        return path.iter().map(|segment| segment.to_string()).collect();
+    }
+
+    #[test]
+    fn test_filename() {
+        let id = DocId::random();
+
+        let mut metadata = Metadata::new();
+        assert_that!(filename(&id, &metadata)).is_equal_to(format!("Untitled ({}).pdf", id));
+
+        metadata.filename = Some(String::from("scan.jpg"));
+        assert_that!(filename(&id, &metadata)).is_equal_to(format!("scan ({}).pdf", id));
+
+        metadata.title = Some(String::from("Invoice 01/2023"));
+        assert_that!(filename(&id, &metadata)).is_equal_to(format!("Invoice 01_2023 ({}).pdf", id));
+
+        assert_that!(parse_filename(&filename(&id, &metadata))).is_equal_to(Some(id));
+        assert_that!(parse_filename("Invoice.pdf")).is_none();
+    }
+
+    #[test]
+    fn test_resolve() {
+        let docs = vec![
+            doc("Tax return", 2022, &["finance/tax"], Some("Tax office")),
+            doc("Invoice", 2023, &["finance", "acme"], Some("ACME")),
+        ];
+
+        assert_that!(children(&docs, &path(&[]))).is_some().has_length(3);
+        assert_that!(children(&docs, &path(&["Years"]))).is_equal_to(Some(vec![
+            Entry::Folder(String::from("2022")),
+            Entry::Folder(String::from("2023")),
+        ]));
+        assert_that!(children(&docs, &path(&["Years", "2021"]))).is_none();
+        assert_that!(children(&docs, &path(&["Correspondents", "ACME"]))).is_equal_to(Some(vec![
+            Entry::File(docs[1].id, filename(&docs[1].id, &docs[1].metadata)),
+        ]));
+
+        // Labels are nested, documents are listed in the folder of the exact label only
+        assert_that!(children(&docs, &path(&["Labels"]))).is_equal_to(Some(vec![
+            Entry::Folder(String::from("acme")),
+            Entry::Folder(String::from("finance")),
+        ]));
+        assert_that!(children(&docs, &path(&["Labels", "finance"]))).is_equal_to(Some(vec![
+            Entry::Folder(String::from("tax")),
+            Entry::File(docs[1].id, filename(&docs[1].id, &docs[1].metadata)),
+        ]));
+        assert_that!(children(&docs, &path(&["Labels", "private"]))).is_none();
+
+        let name = filename(&docs[0].id, &docs[0].metadata);
+        assert_that!(resolve(&docs, &path(&["Labels", "finance", "tax", &name]))).is_equal_to(Some(Resource::File(docs[0].id)));
+        assert_that!(resolve(&docs, &path(&["Years", "2023", &name]))).is_none();
+    }
+
+    #[tokio::test]
+    async fn test_propfind() {
+        let repository = Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
+        let id = {
+            let staging = repository.stage().await.unwrap();
+            let mut document = staging.write(Kind::Document).await.unwrap();
+            document.write_all(b"my document").await.unwrap();
+            document.commit().await.unwrap();
+            let mut metadata = Metadata::new();
+            metadata.title = Some(String::from("Contract & Terms"));
+            metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            *staging.create().await.unwrap().archive().await.unwrap().id()
+        };
+
+        let mut api_keys = HashMap::new();
+        api_keys.insert(String::from("test"), String::from("$2y$12$8X8eghlzYFEKYhcOdqIU6OqaC5oACEYpfLzJChXPPIHBO6aRmzXaC")); // "testkey"
+
+        let auth = Authenticator::from_config(crate::config::Auth {
+            username: String::from("admin"),
+            passhash: String::new(),
+            secret: String::from("secret"),
+            api_keys,
+            defaults: HashMap::new(),
+            max_attempts: 3,
+            lockout: 60,
+            webauthn: None,
+        }, repository.path().to_path_buf()).await.unwrap();
+
+        let (_, upload_token) = auth.create_api_token(String::from("scanner"), vec![Scope::Upload].into_iter().collect()).await.unwrap();
+
+        let dav = Dav::from_config(Config {
+            address: String::from("127.0.0.1"),
+            port: 0,
+        }, Arc::new(auth), repository.clone(), Arc::new(Status::new()));
+
+        let authorized = |method: &str, uri: &str, authorization: &str| Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, authorization)
+            .header("Depth", "1")
+            .body(Body::empty())
+            .unwrap();
+
+        let basic = format!("Basic {}", base64::encode("test:testkey"));
+        let request = |method: &str, uri: &str| authorized(method, uri, &basic);
+
+        let year = Utc::now().year();
+
+        let response = dav.handle(request("PROPFIND", &format!("/Years/{}/", year))).await;
+        assert_that!(response.status()).is_equal_to(StatusCode::MULTI_STATUS);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_that!(body).contains(format!("<D:href>/Years/{}/Contract%20%26%20Terms%20%28{}%29.pdf</D:href>", year, id).as_str());
+        assert_that!(body).contains("<D:displayname>Contract &amp; Terms");
+        assert_that!(body).contains("<D:getcontentlength>11</D:getcontentlength>");
+        assert_that!(body).contains("<D:getcontenttype>application/pdf</D:getcontenttype>");
+
+        let response = dav.handle(request("GET", &format!("/Years/{}/Contract%20%26%20Terms%20%28{}%29.pdf", year, id))).await;
+        assert_that!(response.status()).is_equal_to(StatusCode::OK);
+        assert_that!(response.headers().get(header::CONTENT_TYPE)).is_equal_to(Some(&header::HeaderValue::from_static("application/pdf")));
+        assert_that!(hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec()).is_equal_to(b"my document".to_vec());
+
+        let response = dav.handle(request("DELETE", &format!("/Years/{}/", year))).await;
+        assert_that!(response.status()).is_equal_to(StatusCode::METHOD_NOT_ALLOWED);
+
+        let response = dav.handle(request("PROPFIND", "/Years/1999/")).await;
+        assert_that!(response.status()).is_equal_to(StatusCode::NOT_FOUND);
+
+        let unauthorized = Request::builder().method("PROPFIND").uri("/").body(Body::empty()).unwrap();
+        assert_that!(dav.handle(unauthorized).await.status()).is_equal_to(StatusCode::UNAUTHORIZED);
+
+        let response = dav.handle(authorized("PROPFIND", "/", &format!("Bearer {}", upload_token))).await;
+        assert_that!(response.status()).is_equal_to(StatusCode::FORBIDDEN);
+    }
+}
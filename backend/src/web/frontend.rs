@@ -1,15 +1,58 @@
+use std::borrow::Cow;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
 use rocket::{Data, Request, Response, Route};
 use rocket::handler::{Handler, Outcome};
-use rocket::http::{ContentType, Method};
+use rocket::http::{ContentType, Header, Method, Status};
 use rust_embed::RustEmbed;
 
-#[derive(RustEmbed, Debug, Clone)]
+#[derive(RustEmbed)]
 #[folder = "../frontend/dist/adacta"]
-pub struct Frontend;
+struct Assets;
+
+/// Serves the compiled frontend.
+///
+/// Paths not naming an asset are routes of the single page application and are answered with the index page, except
+/// for the API and paths looking like files.
+#[derive(Debug, Clone)]
+pub struct Frontend {
+    /// Directory to serve the assets from instead of the embedded ones
+    path: Option<PathBuf>,
+}
+
+impl Frontend {
+    const INDEX: &'static str = "index.html";
+
+    pub fn new(path: Option<String>) -> Self {
+        return Self { path: path.map(PathBuf::from) };
+    }
+
+    async fn load(&self, path: &Path) -> Option<Cow<'static, [u8]>> {
+        return match &self.path {
+            Some(root) => tokio::fs::read(root.join(path)).await.ok().map(Cow::Owned),
+            None => Assets::get(&path.to_string_lossy()),
+        };
+    }
+}
+
+/// Determines the caching policy of an asset.
+///
+/// Assets with a content hash in their filename, like `main.0123456789abcdef0123.js`, never change and can be cached
+/// forever. The index page must be revalidated, as it refers to the current assets.
+fn cache_control(path: &Path) -> &'static str {
+    let hashed = path.file_name()
+        .map(|name| name.to_string_lossy())
+        .map_or(false, |name| name.split('.')
+            .any(|part| part.len() >= 16 && part.chars().all(|c| c.is_ascii_hexdigit())));
+
+    return if hashed {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+}
 
 impl Into<Vec<Route>> for Frontend {
     fn into(self) -> Vec<Route> {
@@ -23,13 +66,26 @@ impl Into<Vec<Route>> for Frontend {
 #[async_trait]
 impl Handler for Frontend {
     async fn handle<'r, 's: 'r>(&'s self, request: &'r Request<'_>, _data: Data) -> Outcome<'r> {
-        // TODO: Im pretty sure this can be more readable...
-        let (path, file) = request.get_segments::<PathBuf>(0)
-            .map(|path| path.expect("segments"))
-            .and_then(|path| Self::get(&path.to_string_lossy()).map(|file| (path, file)))
-            .unwrap_or_else(|| (PathBuf::from("index.html"), Self::get("index.html").expect("no index")));
+        let path = match request.get_segments::<PathBuf>(0) {
+            Some(Ok(path)) => path,
+            Some(Err(_)) => return Outcome::failure(Status::NotFound),
+            None => PathBuf::new(),
+        };
+
+        let (path, file) = match self.load(&path).await {
+            Some(file) if !path.as_os_str().is_empty() => (path, file),
+
+            // Unknown API endpoints and missing files must not be answered with the index page
+            _ if path.starts_with("api") || path.extension().is_some() => return Outcome::failure(Status::NotFound),
+
+            _ => match self.load(Path::new(Self::INDEX)).await {
+                Some(file) => (PathBuf::from(Self::INDEX), file),
+                None => return Outcome::failure(Status::NotFound),
+            },
+        };
 
         let mut response = Response::build()
+            .header(Header::new("Cache-Control", cache_control(&path)))
             .sized_body(file.len(), Cursor::new(file))
             .finalize();
 
@@ -41,3 +97,18 @@ impl Handler for Frontend {
         Outcome::Success(response)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_cache_control() {
+        assert_that!(cache_control(Path::new("main.0123456789abcdef0123.js"))).is_equal_to("public, max-age=31536000, immutable");
+        assert_that!(cache_control(Path::new("assets/styles.0123456789abcdef0123.css"))).is_equal_to("public, max-age=31536000, immutable");
+        assert_that!(cache_control(Path::new("index.html"))).is_equal_to("no-cache");
+        assert_that!(cache_control(Path::new("favicon.ico"))).is_equal_to("no-cache");
+    }
+}
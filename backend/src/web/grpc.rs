@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::StreamExt;
+use log::{error, info};
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tonic::{Request, Response, Status as GrpcStatus, Streaming};
+use tonic::metadata::MetadataMap;
+use tonic::transport::Server;
+
+use crate::auth::{Authenticator, Token};
+use crate::config::{Domain, Grpc as Config};
+use crate::index::Index;
+use crate::ingest;
+use crate::juicer::converted_format;
+use crate::meta::Metadata;
+use crate::proto::grpc::{ArchiveRequest, Chunk, Document, DocumentList, DownloadRequest, GetDocumentRequest, ListInboxRequest, SearchRequest, SearchResponse, UploadRequest};
+use crate::proto::grpc::adacta_server::{Adacta, AdactaServer};
+use crate::proto::api::auth::Scope;
+use crate::proto::grpc::upload_request::Part;
+use crate::proto::model::{self, DocId, Kind, Label, PropertyValue};
+use crate::proto::query::{Query, Sort};
+use crate::queue::Queue;
+use crate::quota::{self, Quotas};
+use crate::repository::{Listing, Repository};
+use crate::status::Status;
+
+/// Size of the chunks documents are downloaded in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn internal(err: anyhow::Error) -> GrpcStatus {
+    error!("Failed to serve gRPC request: {:#}", err);
+    return GrpcStatus::internal(format!("{:#}", err));
+}
+
+fn parse_id(id: &str) -> Result<DocId, GrpcStatus> {
+    return DocId::from_str(id).map_err(|_| GrpcStatus::invalid_argument(format!("Invalid document ID: {}", id)));
+}
+
+fn document(id: DocId, metadata: Metadata) -> Result<Document, GrpcStatus> {
+    let metadata: model::Metadata = metadata.into();
+    let metadata = serde_json::to_string(&metadata).map_err(|err| internal(err.into()))?;
+
+    return Ok(Document { id: id.to_string(), metadata });
+}
+
+/// Serves the core operations for integrations of other services via gRPC.
+///
+/// Uploads and downloads are streamed in chunks, which avoids encoding documents as multipart bodies. The service is
+/// defined in the proto crate. Requests are authorized like requests to the API, so API tokens are limited to their
+/// scopes, and documents are scoped to the authorized user.
+///
+/// Archiving applies the labels and properties only, so documents belonging to encryption domains must be archived
+/// using the web interface. Filing labels are not rendered and the suggester is not trained.
+pub struct Grpc {
+    config: Config,
+
+    auth: Arc<Authenticator>,
+
+    domains: Vec<Domain>,
+
+    repository: Repository,
+    index: Arc<dyn Index + Send + Sync>,
+    queue: Queue,
+    quotas: Quotas,
+
+    status: Arc<Status>,
+}
+
+impl Grpc {
+    pub fn from_config(config: Config,
+                       auth: Arc<Authenticator>,
+                       domains: Vec<Domain>,
+                       repository: Repository,
+                       index: Arc<dyn Index + Send + Sync>,
+                       queue: Queue,
+                       quotas: Quotas,
+                       status: Arc<Status>) -> Self {
+        return Self {
+            config,
+            auth,
+            domains,
+            repository,
+            index,
+            queue,
+            quotas,
+            status,
+        };
+    }
+
+    /// Returns the token authorized by the request, if it grants the scope.
+    async fn authenticate(&self, metadata: &MetadataMap, scope: Scope) -> Result<Token, GrpcStatus> {
+        let authorization = metadata.get("authorization")
+            .and_then(|authorization| authorization.to_str().ok());
+
+        let token = match authorization {
+            Some(authorization) => self.auth.authorize(authorization).await,
+            None => None,
+        };
+
+        return match token {
+            Some(token) if token.permits(scope) => Ok(token),
+            Some(_) => Err(GrpcStatus::permission_denied(format!("Token lacks scope {:?}", scope))),
+            None => Err(GrpcStatus::unauthenticated("Invalid credentials")),
+        };
+    }
+
+    /// Reads the metadata of a document in the inbox or archive, unless it is hidden from the user.
+    async fn read(&self, id: DocId, user: &str) -> Result<Metadata, GrpcStatus> {
+        let metadata = if let Some(bundle) = self.repository.inbox().get(id).await {
+            bundle.read_metadata().await.map_err(internal)?
+        } else if let Some(bundle) = self.repository.archive().get(id).await {
+            bundle.read_metadata().await.map_err(internal)?
+        } else {
+            return Err(GrpcStatus::not_found(format!("Document not found: {}", id)));
+        };
+
+        if !metadata.is_visible_to(user) {
+            return Err(GrpcStatus::not_found(format!("Document not found: {}", id)));
+        }
+
+        return Ok(metadata);
+    }
+
+    pub async fn run(self) {
+        let status = self.status.clone();
+        let address = format!("{}:{}", self.config.address, self.config.port);
+
+        let result: Result<()> = async {
+            let address = SocketAddr::from_str(&address)
+                .with_context(|| format!("Invalid gRPC address: {}", address))?;
+
+            info!("Serving gRPC on {}", address);
+            Server::builder()
+                .add_service(AdactaServer::new(self))
+                .serve(address).await?;
+
+            return Ok(());
+        }.await;
+
+        if let Err(err) = result {
+            error!("gRPC server failed: {:#}", err);
+            status.failed("grpc", &err);
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Adacta for Grpc {
+    async fn upload(&self, request: Request<Streaming<UploadRequest>>) -> Result<Response<Document>, GrpcStatus> {
+        let token = self.authenticate(request.metadata(), Scope::Upload).await?;
+        let user = token.subject();
+        let mut parts = request.into_inner();
+
+        let header = match parts.message().await? {
+            Some(UploadRequest { part: Some(Part::Header(header)) }) => header,
+            _ => return Err(GrpcStatus::invalid_argument("Upload must start with the header")),
+        };
+
+        let name = if header.extension.is_empty() {
+            header.filename.clone()
+        } else {
+            format!("upload.{}", header.extension)
+        };
+
+        let extension = match converted_format(None, Some(&name)) {
+            Some(extension) => extension,
+            None if name.to_lowercase().ends_with(".pdf") => "pdf",
+            None => return Err(GrpcStatus::invalid_argument(format!("Unsupported document type: {}", name))),
+        };
+
+        let chunks = parts.map(|part| match part {
+            Ok(UploadRequest { part: Some(Part::Chunk(chunk)) }) => Ok(Bytes::from(chunk)),
+            Ok(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Expected chunk")),
+            Err(status) => Err(std::io::Error::new(std::io::ErrorKind::Other, status.to_string())),
+        });
+
+        let mut metadata = Metadata::new();
+        metadata.owner = Some(user.to_string());
+        metadata.filename = Some(header.filename).filter(|filename| !filename.is_empty());
+        let metadata = metadata.with_defaults(&self.auth.defaults(&token));
+
+        let id = ingest::ingest_within(&self.queue, &self.quotas, tokio::io::stream_reader(chunks), extension, metadata).await
+            .map_err(|err| match err.downcast_ref::<quota::Exceeded>() {
+                Some(exceeded) => GrpcStatus::resource_exhausted(exceeded.to_string()),
+                None => internal(err),
+            })?;
+
+        info!("Uploaded {} via gRPC", id);
+
+        let metadata = self.read(id, user).await?;
+        return Ok(Response::new(document(id, metadata)?));
+    }
+
+    async fn list_inbox(&self, request: Request<ListInboxRequest>) -> Result<Response<DocumentList>, GrpcStatus> {
+        let token = self.authenticate(request.metadata(), Scope::Search).await?;
+        let user = token.subject();
+
+        let mut docs = Vec::new();
+        for bundle in self.repository.inbox().list().await.map_err(internal)? {
+            let metadata = bundle.read_metadata().await.map_err(internal)?;
+            if metadata.is_visible_to(user) {
+                docs.push(document(*bundle.id(), metadata)?);
+            }
+        }
+
+        return Ok(Response::new(DocumentList { docs }));
+    }
+
+    async fn get_document(&self, request: Request<GetDocumentRequest>) -> Result<Response<Document>, GrpcStatus> {
+        let token = self.authenticate(request.metadata(), Scope::Read).await?;
+        let user = token.subject();
+        let id = parse_id(&request.get_ref().id)?;
+
+        let metadata = self.read(id, user).await?;
+        return Ok(Response::new(document(id, metadata)?));
+    }
+
+    async fn archive(&self, request: Request<ArchiveRequest>) -> Result<Response<Document>, GrpcStatus> {
+        let token = self.authenticate(request.metadata(), Scope::Admin).await?;
+        let user = token.subject();
+        let request = request.into_inner();
+        let id = parse_id(&request.id)?;
+
+        let properties = if request.properties.is_empty() {
+            HashMap::new()
+        } else {
+            serde_json::from_str::<HashMap<String, PropertyValue>>(&request.properties)
+                .map_err(|err| GrpcStatus::invalid_argument(format!("Invalid properties: {}", err)))?
+        };
+
+        let repository = self.repository.acting_as(user);
+
+        let bundle = repository.inbox().get(id).await
+            .ok_or_else(|| GrpcStatus::not_found(format!("Document not found: {}", id)))?;
+
+        let mut metadata = bundle.read_metadata().await.map_err(internal)?;
+        if !metadata.is_visible_to(user) {
+            return Err(GrpcStatus::not_found(format!("Document not found: {}", id)));
+        }
+
+        metadata.archived = Some(chrono::Utc::now());
+        metadata.labels = request.labels.into_iter().map(Label::from).collect();
+        metadata.properties = properties;
+        metadata.snoozed = None;
+
+        if let Some(domain) = self.domains.iter().find(|domain| domain.labels.iter().any(|label| metadata.labels.contains(label.as_str()))) {
+            return Err(GrpcStatus::failed_precondition(format!("Documents of encryption domain {} must be archived using the web interface", domain.name)));
+        }
+
+        bundle.write_metadata(&metadata).await.map_err(internal)?;
+        bundle.archive().await.map_err(internal)?;
+
+        return Ok(Response::new(document(id, metadata)?));
+    }
+
+    async fn search(&self, request: Request<SearchRequest>) -> Result<Response<SearchResponse>, GrpcStatus> {
+        let token = self.authenticate(request.metadata(), Scope::Search).await?;
+        let user = token.subject();
+        let request = request.into_inner();
+
+        let query = if request.query.is_empty() {
+            Query::default()
+        } else {
+            Query::from_str(&request.query).map_err(|err| GrpcStatus::invalid_argument(format!("{:#}", err)))?
+        };
+
+        let sort = Some(request.sort)
+            .filter(|sort| !sort.is_empty())
+            .map(|sort| Sort::from_str(&sort))
+            .transpose()
+            .map_err(|err| GrpcStatus::invalid_argument(format!("{:#}", err)))?;
+
+        let listing = Listing::new(sort,
+                                   Some(request.offset as usize),
+                                   Some(request.limit as usize).filter(|limit| *limit > 0));
+
+        let response = self.index.search(&query, &listing).await.map_err(internal)?;
+
+        let mut docs = Vec::new();
+        let mut hidden = 0;
+        for id in response.docs {
+            let bundle = match self.repository.archive().get(id).await {
+                Some(bundle) => bundle,
+                None => continue,
+            };
+
+            let metadata = bundle.read_metadata().await.map_err(internal)?;
+            if !metadata.is_visible_to(user) {
+                hidden += 1;
+                continue;
+            }
+
+            docs.push(document(id, metadata)?);
+        }
+
+        return Ok(Response::new(SearchResponse {
+            count: response.count.saturating_sub(hidden),
+            docs,
+        }));
+    }
+
+    type DownloadStream = mpsc::Receiver<Result<Chunk, GrpcStatus>>;
+
+    async fn download(&self, request: Request<DownloadRequest>) -> Result<Response<Self::DownloadStream>, GrpcStatus> {
+        let token = self.authenticate(request.metadata(), Scope::Read).await?;
+        let user = token.subject();
+        let request = request.into_inner();
+        let id = parse_id(&request.id)?;
+
+        let kind = if request.fragment.is_empty() {
+            Kind::Document
+        } else {
+            Kind::from(request.fragment.as_str())
+        };
+
+        let metadata = self.read(id, user).await?;
+        if metadata.domain.is_some() && crate::crypto::sensitive(&metadata).contains(&kind) {
+            return Err(GrpcStatus::failed_precondition(format!("Document {} is encrypted", id)));
+        }
+
+        let (mut tx, rx) = mpsc::channel(4);
+
+        let repository = self.repository.clone();
+        tokio::spawn(async move {
+            let result: Result<()> = async {
+                let file = match repository.inbox().get(id).await {
+                    Some(bundle) => bundle.read(&kind).await?,
+                    None => match repository.archive().get(id).await {
+                        Some(bundle) => bundle.read(&kind).await?,
+                        None => None,
+                    },
+                };
+
+                let file = match file {
+                    Some(file) => file,
+                    None => {
+                        let _ = tx.send(Err(GrpcStatus::not_found(format!("Fragment not found: {}", kind.filename())))).await;
+                        return Ok(());
+                    }
+                };
+                tokio::pin!(file);
+
+                let mut buffer = vec![0; CHUNK_SIZE];
+                loop {
+                    let read = file.read(&mut buffer).await?;
+                    if read == 0 {
+                        break;
+                    }
+
+                    // The client went away
+                    if tx.send(Ok(Chunk { data: buffer[..read].to_vec() })).await.is_err() {
+                        break;
+                    }
+                }
+
+                return Ok(());
+            }.await;
+
+            if let Err(err) = result {
+                let _ = tx.send(Err(internal(err))).await;
+            }
+        });
+
+        return Ok(Response::new(rx));
+    }
+}
@@ -1,35 +1,181 @@
-use anyhow::Result;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rocket::fairing::AdHoc;
 
 use crate::auth::Authenticator;
 use crate::config::Web as Config;
+use crate::checklists::Checklists;
+use crate::correspondents::Correspondents;
+use crate::crypto::Keyring;
+use crate::editing::Editor;
+use crate::filing::Filing;
 use crate::index::Index;
-use crate::juicer::Juicer;
+use crate::labels::Labels;
+use crate::mailer::Mailer;
+use crate::merge::Merger;
+use crate::persons::Persons;
+use crate::previews::Previews;
+use crate::preferences::Preferences;
+use crate::quarantine::Reviews;
+use crate::queue::Queue;
+use crate::quota::Quotas;
+use crate::reload::Reloader;
 use crate::repository::Repository;
+use crate::requests::Requests;
+use crate::shares::Shares;
+use crate::rules::Rules;
+use crate::status::Status;
 use crate::suggester::Suggester;
+use crate::suggestions::Suggestions;
+use crate::transcription::Transcriber;
+use crate::undo::Undo;
+use crate::uploads::Uploads;
+
+pub use self::acme::Acme;
+pub use self::api::{Named, Repositories};
+pub use self::dav::Dav;
+pub use self::grpc::Grpc;
 
+mod acme;
 mod api;
+mod dav;
+mod cors;
 mod frontend;
+mod grpc;
+mod proxy;
+mod socket;
 
 #[cfg(test)]
 mod test;
 
-pub fn server(config: Config,
-              auth: Authenticator,
-              repository: Repository,
-              index: Box<dyn Index + Send + Sync>,
-              juicer: Box<dyn Juicer + Send + Sync>,
-              suggester: Box<dyn Suggester + Send + Sync>) -> Result<rocket::Rocket> {
-    let figment = rocket::figment::Figment::from(rocket::Config::default())
+/// The services shared by all requests to the web server.
+pub struct Services {
+    pub auth: Arc<Authenticator>,
+    pub repository: Repository,
+    pub index: Arc<dyn Index + Send + Sync>,
+    pub queue: Queue,
+    pub suggester: Box<dyn Suggester + Send + Sync>,
+    pub preferences: Preferences,
+    pub keyring: Keyring,
+    pub filing: Filing,
+    pub mailer: Mailer,
+    pub previews: Previews,
+    pub transcriber: Transcriber,
+    pub merger: Merger,
+    pub editor: Editor,
+    pub quotas: Quotas,
+    pub labels: Labels,
+    pub correspondents: Arc<Correspondents>,
+    pub persons: Persons,
+    pub checklists: Checklists,
+    pub rules: Arc<Rules>,
+    pub requests: Requests,
+    pub shares: Shares,
+    pub repositories: Repositories,
+    pub reloader: Arc<Reloader>,
+}
+
+pub fn server(config: Config, services: Services, status: Arc<Status>) -> Result<rocket::Rocket> {
+    let undo = Undo::new(Duration::from_secs(config.undo_window));
+
+    // Reviews of quarantined bundles are logged alongside the repository
+    let reviews = Reviews::open(services.repository.path().join(Reviews::FILENAME));
+
+    let proxies = config.proxies.iter()
+        .map(|proxy| proxy.parse().with_context(|| format!("Invalid proxy address: {}", proxy)))
+        .collect::<Result<Vec<IpAddr>>>()?;
+
+    let mut figment = rocket::figment::Figment::from(rocket::Config::default())
         .merge(("address", config.address))
         .merge(("port", config.port));
 
-    Ok(rocket::custom(figment)
+    // Connections to the unix socket are relayed to the web server, which only listens on the loopback interface then
+    let socket = match config.socket {
+        Some(socket) => {
+            let target = socket::Socket::loopback()?;
+            figment = figment
+                .merge(("address", target.ip().to_string()))
+                .merge(("port", target.port()));
+
+            let relayed = socket::Relayed::default();
+            let socket = socket::Socket::from_config(socket, target, relayed.clone(), status.clone());
+            let listener = socket.bind()?;
+
+            Some((socket, listener, relayed))
+        }
+        None => None,
+    };
+
+    if let Some(tls) = &config.tls {
+        figment = figment
+            .merge(("tls.certs", tls.certs()))
+            .merge(("tls.key", tls.key()));
+    }
+
+    let namespace = api::Namespace::new(config.namespace)?;
+
+    let mut rocket = rocket::custom(figment);
+
+    // Requests are refused before any other fairing sees them unless relayed, and the relay starts once launched
+    if let Some((socket, listener, relayed)) = socket {
+        rocket = rocket
+            .attach(socket::Relaying::new(relayed))
+            .attach(AdHoc::on_launch("Socket", move |_| {
+                tokio::spawn(socket.run(listener));
+            }));
+    }
+
+    let rocket = rocket
+        .attach(api::Scoping {})
+        .attach(api::Namespacing(namespace.clone()))
         .attach(api::Authorization {})
-        .manage(auth)
-        .manage(repository)
-        .manage(index)
-        .manage(juicer)
-        .manage(suggester)
+        .attach(api::Versioning {})
+        .attach(cors::Cors::new(config.cors))
+        .manage(services.auth)
+        .manage(services.repository)
+        .manage(services.index)
+        .manage(services.queue)
+        .manage(services.suggester)
+        .manage(services.preferences)
+        .manage(services.keyring)
+        .manage(services.filing)
+        .manage(services.mailer)
+        .manage(services.previews)
+        .manage(services.transcriber)
+        .manage(services.merger)
+        .manage(services.editor)
+        .manage(services.quotas)
+        .manage(services.labels)
+        .manage(services.correspondents)
+        .manage(services.persons)
+        .manage(services.checklists)
+        .manage(services.rules)
+        .manage(services.requests)
+        .manage(services.shares)
+        .manage(services.repositories)
+        .manage(services.reloader)
+        .manage(status)
+        .manage(undo)
+        .manage(reviews)
+        .manage(Uploads::new())
+        .manage(Suggestions::new())
+        .manage(proxy::Proxies(proxies))
+        .manage(api::Profiles(config.scanner))
+        .manage(namespace)
+        .manage(api::graphql_schema())
+        .mount("/api/v1", api::routes())
+        .mount("/api", api::unversioned())
+        // Unversioned paths predating versioning are kept for compatibility
         .mount("/api", api::routes())
-        .mount("/", frontend::Frontend {}))
+        .mount("/", api::shortcuts());
+
+    // Serve the frontend from the same origin, so a single instance is enough to run adacta
+    if config.frontend.enabled {
+        return Ok(rocket.mount("/", frontend::Frontend::new(config.frontend.path)));
+    }
+
+    return Ok(rocket);
 }
@@ -0,0 +1,200 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use rocket::{Request, State};
+use rocket::request::{FromRequest, Outcome};
+
+use super::socket::Origination;
+
+/// Addresses of the reverse proxies whose forwarding headers are trusted.
+pub struct Proxies(pub Vec<IpAddr>);
+
+/// The request as seen by the client in front of the reverse proxies.
+///
+/// The `Forwarded` header and the `X-Forwarded-*` headers are only taken into account if the request has been received
+/// from a trusted proxy, as clients could claim any address otherwise. Without a trusted proxy, the connection itself
+/// is described.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Forwarded {
+    /// Address of the client
+    pub client: Option<IpAddr>,
+
+    /// Scheme used by the client
+    pub scheme: String,
+
+    /// Host name requested by the client, if forwarded by the proxy
+    pub host: Option<String>,
+
+    /// Path prefix the proxy serves adacta below, without trailing slash
+    pub prefix: String,
+}
+
+impl Forwarded {
+    /// Returns the URL the client reaches the given absolute path at.
+    ///
+    /// The URL is relative to the origin of the request unless the proxy forwarded the requested host.
+    pub fn url(&self, path: &str) -> String {
+        return match &self.host {
+            Some(host) => format!("{}://{}{}{}", self.scheme, host, self.prefix, path),
+            None => format!("{}{}", self.prefix, path),
+        };
+    }
+}
+
+/// Parses the elements of a `Forwarded` header into their parameters, one element for each hop.
+fn parse_forwarded(header: &str) -> Vec<Vec<(String, String)>> {
+    return header.split(',')
+        .map(|element| element.split(';')
+            .filter_map(|pair| {
+                let mut pair = pair.splitn(2, '=');
+                let key = pair.next()?.trim().to_lowercase();
+                let value = pair.next()?.trim().trim_matches('"').to_string();
+                return Some((key, value));
+            })
+            .collect())
+        .collect();
+}
+
+/// Picks the hop describing the client from the hops listed by the proxies, the leftmost one being the first.
+///
+/// Each proxy appends the hop it received the request from, but the client may send any hops itself. Therefore, the
+/// list is walked from the right, skipping the trusted proxies, and the first other hop is the client.
+fn client_hop<T>(hops: Vec<T>, node: impl Fn(&T) -> Option<IpAddr>, trusted: &[IpAddr]) -> Option<T> {
+    let mut client = None;
+
+    for hop in hops.into_iter().rev() {
+        let proxy = node(&hop).map_or(false, |node| trusted.contains(&node));
+        client = Some(hop);
+
+        if !proxy {
+            break;
+        }
+    }
+
+    return client;
+}
+
+/// Parses a node of the `for` parameter, which may be a quoted and bracketed IPv6 address or carry a port.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+
+    if let Some(node) = node.strip_prefix('[') {
+        return node.split(']').next()?.parse().ok();
+    }
+
+    return node.rsplitn(2, ':').last()?.parse().ok();
+}
+
+fn forwarded(request: &Request<'_>, trusted: &[IpAddr]) -> Forwarded {
+    let remote = request.remote().map(|remote| remote.ip());
+
+    // Only the proxy in front of the socket is able to connect to it, so the headers of relayed requests are trusted
+    let relayed = request.local_cache(|| Origination { relayed: false }).relayed;
+
+    if !relayed && !remote.map_or(false, |remote| trusted.contains(&remote)) {
+        return Forwarded { client: remote, scheme: String::from("http"), host: None, prefix: String::new() };
+    }
+
+    let headers = request.headers();
+
+    let mut client = headers.get_one("X-Forwarded-For")
+        .and_then(|value| client_hop(value.split(',').collect(), |node| parse_node(node.trim()), trusted))
+        .and_then(|value| parse_node(value.trim()));
+    let mut scheme = headers.get_one("X-Forwarded-Proto").map(String::from);
+    let mut host = headers.get_one("X-Forwarded-Host").map(String::from);
+
+    // The standardized header takes precedence over the de-facto standard ones
+    let element = headers.get_one("Forwarded")
+        .and_then(|header| client_hop(parse_forwarded(header), |element| element.iter()
+            .find(|(key, _)| key == "for")
+            .and_then(|(_, value)| parse_node(value)), trusted));
+
+    for (key, value) in element.unwrap_or_default() {
+        match key.as_str() {
+            "for" => client = parse_node(&value).or(client),
+            "proto" => scheme = Some(value),
+            "host" => host = Some(value),
+            _ => {}
+        }
+    }
+
+    let prefix = headers.get_one("X-Forwarded-Prefix")
+        .map(|prefix| prefix.trim_end_matches('/').to_string())
+        .unwrap_or_default();
+
+    return Forwarded {
+        client: client.or(remote),
+        scheme: scheme.unwrap_or_else(|| String::from("http")),
+        host,
+        prefix,
+    };
+}
+
+#[async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for Forwarded {
+    type Error = ();
+
+    async fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let proxies = request.guard::<State<'_, Proxies>>().await
+            .expect("No Proxies");
+
+        Outcome::Success(forwarded(request, &proxies.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_forwarded() {
+        assert_that!(parse_forwarded("for=192.0.2.60;proto=https;host=\"adacta.example.com\", for=198.51.100.17")).is_equal_to(vec![
+            vec![
+                (String::from("for"), String::from("192.0.2.60")),
+                (String::from("proto"), String::from("https")),
+                (String::from("host"), String::from("adacta.example.com")),
+            ],
+            vec![
+                (String::from("for"), String::from("198.51.100.17")),
+            ],
+        ]);
+    }
+
+    #[test]
+    fn test_client_hop() {
+        let trusted = vec![IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2])];
+        let hops = |hops: &[&'static str]| hops.to_vec();
+        let node = |hop: &&str| parse_node(hop);
+
+        // Hops sent by the client are left of the one appended by the first trusted proxy
+        assert_that!(client_hop(hops(&["192.0.2.1", "198.51.100.17"]), node, &trusted)).is_equal_to(Some("198.51.100.17"));
+        assert_that!(client_hop(hops(&["192.0.2.1", "198.51.100.17", "10.0.0.2"]), node, &trusted)).is_equal_to(Some("198.51.100.17"));
+        assert_that!(client_hop(hops(&["unknown", "10.0.0.1"]), node, &trusted)).is_equal_to(Some("unknown"));
+        assert_that!(client_hop(hops(&["10.0.0.2", "10.0.0.1"]), node, &trusted)).is_equal_to(Some("10.0.0.2"));
+        assert_that!(client_hop(hops(&[]), node, &trusted)).is_none();
+    }
+
+    #[test]
+    fn test_parse_node() {
+        assert_that!(parse_node("192.0.2.60")).is_equal_to(Some(IpAddr::from([192, 0, 2, 60])));
+        assert_that!(parse_node("192.0.2.60:4711")).is_equal_to(Some(IpAddr::from([192, 0, 2, 60])));
+        assert_that!(parse_node("[2001:db8::1]:4711")).is_equal_to(Some("2001:db8::1".parse().unwrap()));
+        assert_that!(parse_node("2001:db8::1")).is_equal_to(Some("2001:db8::1".parse().unwrap()));
+        assert_that!(parse_node("unknown")).is_none();
+    }
+
+    #[test]
+    fn test_url() {
+        let forwarded = Forwarded {
+            client: None,
+            scheme: String::from("https"),
+            host: Some(String::from("adacta.example.com")),
+            prefix: String::from("/adacta"),
+        };
+        assert_that!(forwarded.url("/inbox")).is_equal_to(String::from("https://adacta.example.com/adacta/inbox"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::future::try_join;
+use log::{debug, error, info, warn};
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Status as HttpStatus;
+use rocket::http::uri::Origin;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UnixListener, UnixStream};
+
+use crate::config::Socket as Config;
+use crate::status::Status;
+
+/// Delay after a failed accept, doubled on each further failure up to the maximum
+const BACKOFF_MIN: Duration = Duration::from_millis(10);
+const BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Path requests not received through the socket are routed to, as no route serves it
+const REFUSED_PATH: &str = "/socket/refused";
+
+/// Accepts connections on a unix domain socket and passes them on to the web server.
+///
+/// The web server is not able to listen on a unix socket itself. Instead, it listens on a port of the loopback
+/// interface and all connections to the socket are relayed to this port. As other local users may connect to this port
+/// directly, the web server refuses all requests not received through a relayed connection (see [`Relaying`]).
+pub struct Socket {
+    config: Config,
+
+    /// Address the web server listens on
+    target: SocketAddr,
+
+    /// Local addresses of the connections currently relayed to the web server
+    relayed: Relayed,
+
+    status: Arc<Status>,
+}
+
+/// The local addresses of the connections opened by the relay to the web server.
+///
+/// The web server sees these as the remote address of the requests it receives through the socket.
+#[derive(Debug, Clone, Default)]
+pub struct Relayed(Arc<Mutex<HashSet<SocketAddr>>>);
+
+impl Relayed {
+    fn contains(&self, addr: &SocketAddr) -> bool {
+        return self.0.lock().expect("Relayed poisoned").contains(addr);
+    }
+}
+
+/// Removes a relayed connection once it has been closed.
+struct Registration {
+    relayed: Relayed,
+    addr: SocketAddr,
+}
+
+impl Registration {
+    fn register(relayed: &Relayed, addr: SocketAddr) -> Self {
+        relayed.0.lock().expect("Relayed poisoned").insert(addr);
+        return Self { relayed: relayed.clone(), addr };
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.relayed.0.lock().expect("Relayed poisoned").remove(&self.addr);
+    }
+}
+
+/// Marks requests received through the socket, whose forwarding headers are set by the proxy in front of it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Origination {
+    pub relayed: bool,
+}
+
+/// Refuses requests the web server received without passing the socket.
+///
+/// Requests from other local processes are routed to a path no route serves and answered with `403 Forbidden`.
+pub struct Relaying {
+    relayed: Relayed,
+}
+
+impl Relaying {
+    pub fn new(relayed: Relayed) -> Self {
+        return Self { relayed };
+    }
+}
+
+#[async_trait]
+impl Fairing for Relaying {
+    fn info(&self) -> Info {
+        Info {
+            name: "Relaying",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data) {
+        let relayed = request.remote().map_or(false, |remote| self.relayed.contains(&remote));
+        request.local_cache(|| Origination { relayed });
+
+        if !relayed {
+            warn!("Refusing request not received through the socket from {:?}", request.remote());
+            request.set_uri(Origin::parse(REFUSED_PATH).expect("Invalid refused path"));
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !request.local_cache(|| Origination { relayed: false }).relayed {
+            response.set_status(HttpStatus::Forbidden);
+            response.set_sized_body(0, Cursor::new(""));
+        }
+    }
+}
+
+/// Parses a mode given in octal notation, like `0660`.
+fn parse_mode(mode: &str) -> Result<u32> {
+    return u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .with_context(|| format!("Invalid socket mode: {}", mode));
+}
+
+/// Copies data in both directions until both sides have closed the connection.
+async fn relay(mut inbound: UnixStream, target: SocketAddr, relayed: Relayed) -> Result<()> {
+    let mut outbound = TcpStream::connect(target).await?;
+
+    // Registered before sending anything, so the web server knows the connection by the time it reads the request
+    let _registration = Registration::register(&relayed, outbound.local_addr()?);
+
+    let (mut inbound_read, mut inbound_write) = inbound.split();
+    let (mut outbound_read, mut outbound_write) = outbound.split();
+
+    let request = async {
+        tokio::io::copy(&mut inbound_read, &mut outbound_write).await?;
+        outbound_write.shutdown().await
+    };
+
+    let response = async {
+        tokio::io::copy(&mut outbound_read, &mut inbound_write).await?;
+        inbound_write.shutdown().await
+    };
+
+    try_join(request, response).await?;
+
+    return Ok(());
+}
+
+impl Socket {
+    pub fn from_config(config: Config, target: SocketAddr, relayed: Relayed, status: Arc<Status>) -> Self {
+        return Self { config, target, relayed, status };
+    }
+
+    /// Picks an unused port on the loopback interface for the web server to listen on.
+    ///
+    /// The port is free again once picked, so another process could take it before the web server binds it. The web
+    /// server fails to launch then, and the relay is started after launch only, so no connection is relayed to others.
+    pub fn loopback() -> Result<SocketAddr> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .context("No free port on loopback interface")?;
+        return Ok(listener.local_addr()?);
+    }
+
+    /// Binds the socket, replacing a stale one left behind by a previous run.
+    ///
+    /// Connections are not accepted until the listener is run, so clients wait for the web server to launch.
+    pub fn bind(&self) -> Result<StdUnixListener> {
+        let mode = parse_mode(&self.config.mode)?;
+
+        match std::fs::remove_file(&self.config.path) {
+            Ok(()) => debug!("Removed stale socket {}", self.config.path),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("Failed to remove stale socket {}", self.config.path)),
+        }
+
+        let listener = StdUnixListener::bind(&self.config.path)
+            .with_context(|| format!("Failed to bind socket {}", self.config.path))?;
+        listener.set_nonblocking(true)?;
+
+        std::fs::set_permissions(&self.config.path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set permissions of socket {}", self.config.path))?;
+
+        info!("Listening on socket {}", self.config.path);
+
+        return Ok(listener);
+    }
+
+    pub async fn run(self, listener: StdUnixListener) {
+        let mut listener = match UnixListener::from_std(listener) {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Failed to listen on socket {}: {}", self.config.path, err);
+                self.status.failed("socket", &anyhow::Error::from(err));
+                return;
+            }
+        };
+
+        let mut backoff = BACKOFF_MIN;
+
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    // Errors like running out of file descriptors persist for a while, so retrying right away would spin
+                    error!("Failed to accept connection on socket {}: {}", self.config.path, err);
+                    self.status.failed("socket", &anyhow::Error::from(err));
+
+                    tokio::time::delay_for(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, BACKOFF_MAX);
+                    continue;
+                }
+            };
+
+            backoff = BACKOFF_MIN;
+
+            let target = self.target;
+            let relayed = self.relayed.clone();
+            tokio::spawn(async move {
+                if let Err(err) = relay(stream, target, relayed).await {
+                    debug!("Relaying connection failed: {:#}", err);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_that!(parse_mode("0660").ok()).is_equal_to(Some(0o660));
+        assert_that!(parse_mode("0o600").ok()).is_equal_to(Some(0o600));
+        assert_that!(parse_mode("660").ok()).is_equal_to(Some(0o660));
+        assert_that!(parse_mode("0990").ok()).is_none();
+    }
+
+    #[test]
+    fn test_registration() {
+        let relayed = Relayed::default();
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 4711));
+
+        let registration = Registration::register(&relayed, addr);
+        assert_that!(relayed.contains(&addr)).is_true();
+
+        drop(registration);
+        assert_that!(relayed.contains(&addr)).is_false();
+    }
+}
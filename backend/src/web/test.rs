@@ -9,6 +9,10 @@ struct Server {
     pub index: crate::index::MockIndex,
     pub juicer: crate::juicer::MockJuicer,
     pub suggester: crate::suggester::MockSuggester,
+    pub transcription: Option<crate::config::Transcription>,
+    pub mail: Option<crate::config::Mail>,
+    pub quota: crate::config::Quota,
+    pub repositories: Vec<(String, crate::repository::Repository, crate::index::MockIndex)>,
 }
 
 impl Server {
@@ -16,13 +20,18 @@ impl Server {
         let mut api_keys = HashMap::new();
         api_keys.insert(String::from("test"), String::from("$2y$12$8X8eghlzYFEKYhcOdqIU6OqaC5oACEYpfLzJChXPPIHBO6aRmzXaC")); // "testkey"
 
+        let repository = crate::repository::Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+
         let authenticator = crate::auth::Authenticator::from_config(crate::config::Auth {
+            username: "admin".to_string(),
             passhash: "$2y$12$/luV8edFPQFt7Vc3O9MgReHsFoQUD0Vu4g9nkjFb/fK0ib3HwJ9/G".to_string(), // "pass"
             secret: "my dirty secret".to_string(),
             api_keys,
-        }).await.unwrap();
-
-        let repository = crate::repository::Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+            defaults: HashMap::new(),
+            max_attempts: 3,
+            lockout: 60,
+            webauthn: None,
+        }, repository.path().to_path_buf()).await.unwrap();
 
         let index = crate::index::MockIndex::new();
         let juicer = crate::juicer::MockJuicer::new();
@@ -34,25 +43,174 @@ impl Server {
             index,
             juicer,
             suggester,
+            transcription: None,
+            mail: None,
+            quota: crate::config::Quota::default(),
+            repositories: Vec::new(),
         };
     }
 
     pub async fn client(self) -> rocket::local::asynchronous::Client {
-        let config = crate::config::Web { address: "127.0.0.1".to_string(), port: 0 };
+        let config = crate::config::Web {
+            address: "127.0.0.1".to_string(),
+            port: 0,
+            undo_window: 30,
+            frontend: crate::config::Frontend::default(),
+            cors: crate::config::Cors { origins: vec!["https://app.example.com".to_string()], ..crate::config::Cors::default() },
+            proxies: vec!["10.0.0.1".to_string()],
+            tls: None,
+            socket: None,
+            namespace: None,
+            scanner: vec![("1".to_string(), crate::config::Defaults {
+                labels: vec![crate::proto::model::Label::from("scan")].into_iter().collect(),
+                ..crate::config::Defaults::default()
+            })].into_iter().collect(),
+        };
+
+        let preferences = crate::preferences::Preferences::with_path(self.repository.path().join("preferences")).await.unwrap();
+
+        let keyring = crate::crypto::Keyring::new(vec![
+            crate::config::Domain { name: "medical".to_string(), labels: vec!["medical".to_string()] },
+        ], self.repository.path().join("domains")).await.unwrap();
+
+        let filing = crate::filing::Filing::new(None, self.repository.clone());
+
+        let mailer = crate::mailer::Mailer::new(self.mail);
+
+        let previews = crate::previews::Previews::from_config(crate::config::Previews::default(), &self.repository);
+
+        let transcriber = crate::transcription::Transcriber::new(self.transcription);
+
+        let labels = crate::labels::Labels::load(self.repository.clone()).await.unwrap();
+
+        let correspondents = std::sync::Arc::new(crate::correspondents::Correspondents::load(self.repository.path().join("correspondents.json")).await.unwrap());
+
+        let persons = crate::persons::Persons::load(self.repository.path().join("persons.json")).await.unwrap();
+
+        let checklists = crate::checklists::Checklists::load(self.repository.path().join("checklists.json")).await.unwrap();
+
+        let rules = std::sync::Arc::new(crate::rules::Rules::load(self.repository.clone()).await.unwrap());
 
-        let rocket = crate::web::server(
-            config,
-            self.authenticator,
-            self.repository,
-            Box::new(self.index),
-            Box::new(self.juicer),
-            Box::new(self.suggester),
-        ).unwrap();
+        let requests = crate::requests::Requests::load(self.repository.path().join("requests.json")).await.unwrap();
+
+        let shares = crate::shares::Shares::load(self.repository.path().join("shares.json"), self.repository.path().join("shares.key")).await.unwrap();
+
+        let status = std::sync::Arc::new(crate::status::Status::new());
+
+        let queue = crate::queue::Queue::new(
+            crate::config::Queue { retries: 0, ..crate::config::Queue::default() },
+            self.repository.clone(),
+            std::sync::Arc::new(self.juicer),
+            std::collections::HashMap::new(),
+            rules.clone(),
+            correspondents.clone(),
+            status.clone(),
+        );
+
+        let merger = crate::merge::Merger::new(crate::config::Merge::default(), queue.clone());
+        let editor = crate::editing::Editor::new(crate::config::Editing::default(), queue.clone());
+        let quotas = crate::quota::Quotas::new(self.quota);
+
+        let mut repositories = Vec::new();
+        for (name, repository, index) in self.repositories {
+            let rules = std::sync::Arc::new(crate::rules::Rules::load(repository.clone()).await.unwrap());
+            let correspondents = std::sync::Arc::new(crate::correspondents::Correspondents::load(repository.path().join("correspondents.json")).await.unwrap());
+
+            let queue = crate::queue::Queue::new(
+                crate::config::Queue { retries: 0, ..crate::config::Queue::default() },
+                repository.clone(),
+                std::sync::Arc::new(crate::juicer::MockJuicer::new()),
+                std::collections::HashMap::new(),
+                rules.clone(),
+                correspondents.clone(),
+                status.clone(),
+            );
+
+            repositories.push((name, crate::web::Named {
+                index: std::sync::Arc::new(index),
+                labels: crate::labels::Labels::load(repository.clone()).await.unwrap(),
+                keyring: crate::crypto::Keyring::new(vec![], repository.path().join("domains")).await.unwrap(),
+                filing: crate::filing::Filing::new(None, repository.clone()),
+                previews: crate::previews::Previews::from_config(crate::config::Previews::default(), &repository),
+                rules,
+                correspondents,
+                persons: crate::persons::Persons::load(repository.path().join("persons.json")).await.unwrap(),
+                checklists: crate::checklists::Checklists::load(repository.path().join("checklists.json")).await.unwrap(),
+                merger: crate::merge::Merger::new(crate::config::Merge::default(), queue.clone()),
+                editor: crate::editing::Editor::new(crate::config::Editing::default(), queue.clone()),
+                undo: crate::undo::Undo::new(std::time::Duration::from_secs(30)),
+                reviews: crate::quarantine::Reviews::open(repository.path().join(crate::quarantine::Reviews::FILENAME)),
+                suggestions: crate::suggestions::Suggestions::new(),
+                queue,
+                repository,
+            }));
+        }
+        let repositories = crate::web::Repositories::new(repositories);
+
+        let reloader = std::sync::Arc::new(crate::reload::Reloader::new(None, queue.clone(), rules.clone(), Vec::new(), None, status.clone()));
+
+        let services = crate::web::Services {
+            auth: std::sync::Arc::new(self.authenticator),
+            repository: self.repository,
+            index: std::sync::Arc::new(self.index),
+            queue,
+            suggester: Box::new(self.suggester),
+            preferences,
+            keyring,
+            filing,
+            mailer,
+            previews,
+            transcriber,
+            merger,
+            editor,
+            quotas,
+            labels,
+            correspondents,
+            persons,
+            checklists,
+            rules,
+            requests,
+            shares,
+            repositories,
+            reloader,
+        };
+
+        let rocket = crate::web::server(config, services, status).unwrap();
 
         return rocket::local::asynchronous::Client::untracked(rocket).await.unwrap();
     }
 }
 
+mod acme {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_challenge() {
+        let challenges = crate::web::acme::Challenges::default();
+        challenges.insert("my-token".to_string(), "my-token.my-thumbprint".to_string());
+
+        let client = rocket::local::asynchronous::Client::untracked(crate::web::acme::server("127.0.0.1", 0, challenges)).await.unwrap();
+
+        let response = client.get("/.well-known/acme-challenge/my-token").dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::Ok);
+        assert_that!(response.into_string().await).is_equal_to(Some("my-token.my-thumbprint".to_string()));
+
+        let response = client.get("/.well-known/acme-challenge/other-token").dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_redirect() {
+        let client = rocket::local::asynchronous::Client::untracked(crate::web::acme::server("127.0.0.1", 0, Default::default())).await.unwrap();
+
+        let response = client.get("/archive/some-document?page=2")
+            .header(rocket::http::Header::new("Host", "adacta.example.com"))
+            .dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::PermanentRedirect);
+        assert_that!(response.headers().get_one("Location")).is_equal_to(Some("https://adacta.example.com/archive/some-document?page=2"));
+    }
+}
+
 mod frontend {
     use super::*;
 
@@ -75,6 +233,24 @@ mod frontend {
 
         assert_that!(response_index).is_equal_to(response_root);
     }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let server = Server::new().await;
+        let client = server.client().await;
+
+        // Routes of the frontend are answered with the index page which must be revalidated
+        let response = client.get("/archive/some-document").dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::Ok);
+        assert_that!(response.headers().get_one("Cache-Control")).is_equal_to(Some("no-cache"));
+        assert_that!(response.content_type()).is_equal_to(Some(ContentType::HTML));
+
+        let response = client.get("/missing.js").dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+        let response = client.get("/api/missing").dispatch().await;
+        assert_that!(response.status()).is_equal_to(Status::NotFound);
+    }
 }
 
 mod api {
@@ -131,6 +307,166 @@ mod api {
             assert_that!(response.status()).is_equal_to(Status::BadRequest);
             assert_that!(response.headers().get_one("Authorization")).is_none();
         }
+
+        #[tokio::test]
+        async fn test_login_lockout() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            for _ in 0..3 {
+                let response = client.post("/api/auth/login")
+                    .header(ContentType::JSON)
+                    .body(json_payload!({
+                        "password": "wrong pass",
+                    }))
+                    .dispatch().await;
+                assert_that!(response.status()).is_equal_to(Status::BadRequest);
+            }
+
+            // Even the correct password is rejected while locked
+            let response = client.post("/api/auth/login")
+                .header(ContentType::JSON)
+                .body(json_payload!({
+                    "password": "pass",
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::TooManyRequests);
+            assert_that!(response.headers().get_one("Retry-After")).is_some();
+
+            let response = client.get("/api/admin/status")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["failed_logins"].as_array().map(Vec::len)).is_equal_to(Some(4));
+            assert_that!(response["failed_logins"][0]["locked"].as_bool()).is_equal_to(Some(true));
+        }
+
+        #[tokio::test]
+        async fn test_passkeys_not_configured() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.post("/api/auth/passkeys")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.get("/api/auth/passkeys")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["passkeys"].as_array().map(Vec::len)).is_equal_to(Some(0));
+        }
+
+        #[tokio::test]
+        async fn test_api_tokens() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.post("/api/auth/tokens")
+                .header(ContentType::JSON)
+                .header(api_key())
+                .body(json_payload!({
+                    "name": "scanner",
+                    "scopes": ["admin"],
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().to_string();
+            let token = response["token"].as_str().unwrap().to_string();
+
+            let response = client.get("/api/auth/tokens")
+                .header(Header::new("Authorization", format!("Bearer {}", token)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["tokens"][0]["name"].as_str()).is_equal_to(Some("scanner"));
+
+            let response = client.delete(format!("/api/auth/tokens/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/auth/tokens")
+                .header(Header::new("Authorization", format!("Bearer {}", token)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Unauthorized);
+        }
+
+        #[tokio::test]
+        async fn test_api_token_scopes() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.post("/api/auth/tokens")
+                .header(ContentType::JSON)
+                .header(api_key())
+                .body(json_payload!({
+                    "name": "scanner",
+                    "scopes": ["upload"],
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let token = response["token"].as_str().unwrap().to_string();
+
+            let response = client.get("/api/inbox")
+                .header(Header::new("Authorization", format!("Bearer {}", token)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Forbidden);
+
+            let response = client.get("/api/auth/tokens")
+                .header(Header::new("Authorization", format!("Bearer {}", token)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Forbidden);
+        }
+
+        #[tokio::test]
+        async fn test_sessions() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.post("/api/auth/login")
+                .header(ContentType::JSON)
+                .header(Header::new("User-Agent", "phone"))
+                .body(json_payload!({
+                    "password": "pass",
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Accepted);
+
+            let bearer = response.headers().get_one("Authorization").unwrap().to_string();
+
+            let response = client.get("/api/auth/sessions")
+                .header(Header::new("Authorization", format!("Bearer {}", bearer)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["sessions"].as_array().unwrap()).has_length(1);
+            assert_that!(response["sessions"][0]["user_agent"].as_str()).is_equal_to(Some("phone"));
+            assert_that!(response["sessions"][0]["current"].as_bool()).is_equal_to(Some(true));
+
+            let id = response["sessions"][0]["id"].as_str().unwrap().to_string();
+
+            let response = client.delete(format!("/api/auth/sessions/{}", id))
+                .header(Header::new("Authorization", format!("Bearer {}", bearer)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/auth/sessions")
+                .header(Header::new("Authorization", format!("Bearer {}", bearer)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Unauthorized);
+        }
     }
 
     fn api_key() -> impl Into<Header<'static>> {
@@ -140,6 +476,35 @@ mod api {
         return Header::new("Authorization", format!("Basic {}", basic));
     }
 
+    /// Stages a document with the given plaintext, which lands in the inbox without being juiced.
+    async fn stage(repository: &crate::repository::Repository, text: &str, metadata: crate::meta::Metadata) -> crate::proto::model::DocId {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::proto::model::Kind;
+
+        let staging = repository.stage().await.unwrap();
+        let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+        plaintext.write_all(text.as_bytes()).await.unwrap();
+        plaintext.commit().await.unwrap();
+        metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        return *staging.create().await.unwrap().id();
+    }
+
+    /// Archives the electricity bills of three months, which suggestions for further bills are learned from.
+    async fn archive_bills(repository: &crate::repository::Repository) {
+        use crate::meta::Metadata;
+        use crate::proto::model::Label;
+
+        for month in &["march", "april", "may"] {
+            let id = stage(repository, &format!("Stadtwerke electricity bill for {}", month), Metadata {
+                labels: vec![Label::from("utilities")].into_iter().collect(),
+                correspondent: Some(String::from("Stadtwerke")),
+                ..Metadata::new()
+            }).await;
+            repository.inbox().get(id).await.unwrap().archive().await.unwrap();
+        }
+    }
+
     mod upload {
         use mockall::predicate;
         use rand::RngCore;
@@ -156,6 +521,7 @@ mod api {
                 .times(1)
                 .return_once(|_| Ok(()));
 
+            let repository = server.repository.clone();
             let client = server.client().await;
 
             // Create the document from random data
@@ -170,331 +536,4049 @@ mod api {
 
 
             assert_that!(response.status()).is_equal_to(Status::Ok);
-        }
-    }
-
-    mod inbox {
-        use std::collections::HashSet;
-        use std::iter::FromIterator;
-
-        use chrono::{DateTime, NaiveDateTime, Utc};
-        use futures::{stream, StreamExt};
-        use serde_json::json;
-        use tokio::io::AsyncWriteExt;
-        use tokio::time::Duration;
 
-        use crate::meta::Metadata;
-        use crate::proto::model::{Kind, Label};
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
 
-        use super::*;
+            // The bundle is moved to the inbox as soon as the queued juicer finished
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                while repository.inbox().get(id).await.is_none() {
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+        }
 
         #[tokio::test]
-        async fn test_list() {
-            let server = Server::new().await;
-
-            // Create bundles in inbox (with a short delay between each to have a unique timestamp)
-            let ids = tokio::time::throttle(Duration::from_millis(10),
-                                            stream::iter(0..13usize))
-                .then(|_| async {
-                    let bundle = server.repository.stage().await.unwrap();
-
-                    Metadata {
-                        uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
-                        ..Metadata::new()
-                    }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
-
-                    let bundle = bundle.create().await.unwrap();
-
-                    *bundle.id()
-                }).collect::<Vec<_>>().await;
+        async fn test_upload_quota() {
+            let mut server = Server::new().await;
+            server.quota.user = Some(512);
 
+            let repository = server.repository.clone();
             let client = server.client().await;
 
-            let response = client.get("/api/inbox")
+            // Uploads exceeding the quota are rejected before juicing
+            let response = client.post("/api/upload")
+                .header(ContentType::PDF)
+                .header(api_key())
+                .body([0u8; 1024])
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::InsufficientStorage);
+            assert_that!(repository.staging().list().await.unwrap().len()).is_equal_to(0);
+
+            // Resumable uploads are rejected by their announced length
+            let response = client.post("/api/uploads?filename=scan.pdf&length=1024")
                 .header(api_key())
                 .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::InsufficientStorage);
 
+            let response = client.get("/api/stats/quota")
+                .header(api_key())
+                .dispatch().await;
             assert_that!(response.status()).is_equal_to(Status::Ok);
 
-            assert_json_eq!(response.into_bytes().await.unwrap(), {
-                "count": 13,
-                "docs": ids[0..10].iter().map(|id| json!({
-                    "id": id,
-                    "metadata": {
-                        "archived": (),
-                        "uploaded": "2001-09-09T01:46:40Z",
-                        "pages": 0,
-                        "title": (),
-                        "labels": [],
-                        "properties": {},
-                    }
-                })).collect::<Vec<_>>(),
-            });
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["user"]["used"].as_u64()).is_equal_to(Some(0));
+            assert_that!(response["user"]["limit"].as_u64()).is_equal_to(Some(512));
+            assert_that!(response["repository"]["limit"].is_null()).is_true();
         }
 
         #[tokio::test]
-        async fn test_get() {
+        async fn test_upload_checksum() {
             let mut server = Server::new().await;
 
-            let doc_id = {
-                let staging = server.repository.stage().await.unwrap();
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(1)
+                .return_once(|_| Ok(()));
 
-                staging.write(Kind::Document).await.unwrap()
-                    .write_all(b"").await.unwrap();
+            let repository = server.repository.clone();
+            let client = server.client().await;
 
-                staging.write(Kind::Plaintext).await.unwrap()
-                    .write_all(b"my document plaintext").await.unwrap();
+            // Corrupted uploads are rejected before juicing
+            let response = client.post(format!("/api/upload?sha256={}", "0".repeat(64)))
+                .header(ContentType::PDF)
+                .header(api_key())
+                .body("my document")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+            assert_that!(repository.staging().list().await.unwrap().len()).is_equal_to(0);
 
-                Metadata {
-                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
-                    ..Metadata::new()
-                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let response = client.post("/api/upload?sha256=d6bd37c01a945e0212365a72d72b7cb6cdb3e58fcf115c70e93d32d1028ebd06")
+                .header(ContentType::PDF)
+                .header(api_key())
+                .body("my document")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+        }
 
-                *staging.create().await.unwrap().id()
-            };
+        #[tokio::test]
+        async fn test_upload_languages() {
+            let mut server = Server::new().await;
 
-            server.suggester.expect_guess()
-                .with(mockall::predicate::eq("my document plaintext"))
-                .returning(|_| Ok(HashSet::from_iter(vec![Label::from("suggestion")])));
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(1)
+                .return_once(|_| Ok(()));
 
+            let repository = server.repository.clone();
             let client = server.client().await;
 
-            let response = client.get(format!("/api/inbox/{}", doc_id))
+            let response = client.post("/api/upload?languages=deu;eng")
+                .header(ContentType::PDF)
                 .header(api_key())
+                .body("my document")
                 .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
 
+            let response = client.post("/api/upload?languages=deu%2Bfra")
+                .header(ContentType::PDF)
+                .header(api_key())
+                .body("my document")
+                .dispatch().await;
             assert_that!(response.status()).is_equal_to(Status::Ok);
 
-            assert_json_eq!(response.into_bytes().await.unwrap(), {
-                "id": doc_id.to_string(),
-                "metadata": {
-                    "archived": (),
-                    "uploaded": "2001-09-09T01:46:40Z",
-                    "pages": 0,
-                    "title": (),
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+
+            // The requested languages are passed on to the juicer by the metadata
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                while repository.inbox().get(id).await.is_none() {
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+
+            let metadata = repository.inbox().get(id).await.unwrap().read_metadata().await.unwrap();
+            assert_that!(metadata.languages).is_equal_to(Some(String::from("deu+fra")));
+        }
+
+        #[tokio::test]
+        async fn test_upload_resumable() {
+            let mut server = Server::new().await;
+
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(1)
+                .return_once(|_| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post("/api/uploads?filename=scan.pdf&length=11&sha256=d6bd37c01a945e0212365a72d72b7cb6cdb3e58fcf115c70e93d32d1028ebd06")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+            assert_that!(response["offset"].as_u64()).is_equal_to(Some(0));
+
+            let response = client.patch(format!("/api/uploads/{}?offset=0", id))
+                .header(api_key())
+                .body("my doc")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            // Chunks sent again after a dropped connection are rejected
+            let response = client.patch(format!("/api/uploads/{}?offset=0", id))
+                .header(api_key())
+                .body("my doc")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Conflict);
+
+            let response = client.get(format!("/api/uploads/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["offset"].as_u64()).is_equal_to(Some(6));
+
+            // Chunks exceeding the announced length are discarded
+            let response = client.patch(format!("/api/uploads/{}?offset=6", id))
+                .header(api_key())
+                .body("ument and more")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.patch(format!("/api/uploads/{}?offset=6", id))
+                .header(api_key())
+                .body("ument")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["doc"]["id"].as_str()).is_equal_to(Some(id.to_string().as_str()));
+
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                while repository.inbox().get(id).await.is_none() {
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+
+            let response = client.get(format!("/api/uploads/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_upload_office() {
+            let mut server = Server::new().await;
+
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(1)
+                .return_once(|_| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post("/api/upload?filename=letter.odt")
+                .header(ContentType::new("application", "vnd.oasis.opendocument.text"))
+                .header(api_key())
+                .body("my letter")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+
+            let bundle = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                loop {
+                    if let Some(bundle) = repository.inbox().get(id).await {
+                        return bundle;
+                    }
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+
+            // The original is kept for the juicer to convert it
+            assert_that!(bundle.read(crate::proto::model::Kind::other("original.odt")).await.unwrap().is_some()).is_true();
+        }
+
+        #[tokio::test]
+        async fn test_upload_image() {
+            let mut server = Server::new().await;
+
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(1)
+                .return_once(|_| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post("/api/upload")
+                .header(ContentType::PNG)
+                .header(api_key())
+                .body("my scan")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+
+            let bundle = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                loop {
+                    if let Some(bundle) = repository.inbox().get(id).await {
+                        return bundle;
+                    }
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+
+            // The scan is kept next to the searchable PDF assembled by the juicer
+            assert_that!(bundle.read(crate::proto::model::Kind::other("original.png")).await.unwrap().is_some()).is_true();
+        }
+
+        #[tokio::test]
+        async fn test_scan() {
+            let mut server = Server::new().await;
+
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(1)
+                .return_once(|_| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post("/api/scans?profile=9")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.post("/api/scans?profile=1")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+            assert_that!(response["pages"].as_u64()).is_equal_to(Some(0));
+
+            // Scans without pages can not be completed
+            let response = client.post(format!("/api/scans/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            for page in &["first page", "second page"] {
+                let response = client.post(format!("/api/scans/{}/pages", id))
+                    .header(ContentType::PNG)
+                    .header(api_key())
+                    .body(*page)
+                    .dispatch().await;
+                assert_that!(response.status()).is_equal_to(Status::Ok);
+            }
+
+            // Pages must be images
+            let response = client.post(format!("/api/scans/{}/pages", id))
+                .header(ContentType::PDF)
+                .header(api_key())
+                .body("my doc")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.post(format!("/api/scans/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["pages"].as_u64()).is_equal_to(Some(2));
+            assert_that!(response["doc"]["id"].as_str()).is_equal_to(Some(id.to_string().as_str()));
+
+            let bundle = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                loop {
+                    if let Some(bundle) = repository.inbox().get(id).await {
+                        return bundle;
+                    }
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+
+            // The pages are kept in order for the juicer to assemble them and the profile applied its labels
+            assert_that!(bundle.read(crate::proto::model::Kind::other("scan-0001.png")).await.unwrap().is_some()).is_true();
+            assert_that!(bundle.read(crate::proto::model::Kind::other("scan-0002.png")).await.unwrap().is_some()).is_true();
+            assert_that!(bundle.read_metadata().await.unwrap().labels.contains(&crate::proto::model::Label::from("scan"))).is_true();
+
+            let response = client.post(format!("/api/scans/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_upload_mail() {
+            let mut server = Server::new().await;
+
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(2)
+                .returning(|_| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post("/api/upload")
+                .header(ContentType::new("message", "rfc822"))
+                .header(api_key())
+                .body("From: Biller <billing@example.com>\r
+Subject: Your invoice\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"XXX\"\r
+\r
+--XXX\r
+Content-Type: text/plain; charset=utf-8\r
+\r
+Please find your invoice attached.\r
+--XXX\r
+Content-Type: application/pdf; name=\"invoice.pdf\"\r
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r
+Content-Transfer-Encoding: base64\r
+\r
+JVBERi0xLjQK\r
+--XXX--\r
+")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let docs = response["docs"].as_array().unwrap();
+            assert_that!(docs).has_length(2);
+
+            let body = docs[0]["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+            let attachment = docs[1]["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+
+            assert_that!(docs[0]["metadata"]["title"].as_str()).is_equal_to(Some("Your invoice"));
+            assert_that!(docs[1]["metadata"]["title"].as_str()).is_equal_to(Some("invoice.pdf"));
+
+            let bundle = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                loop {
+                    if let Some(bundle) = repository.inbox().get(attachment).await {
+                        return bundle;
+                    }
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+
+            // The attachment is linked to the mail body and keeps the mail headers
+            let metadata = bundle.read_metadata().await.unwrap();
+            assert_that!(metadata.owner.as_deref()).is_equal_to(Some("test"));
+            assert_that!(metadata.relations.iter().any(|relation| relation.target == body && relation.kind == crate::proto::model::RelationKind::AttachmentOf)).is_true();
+            assert_that!(metadata.properties.get("mail.subject")).is_equal_to(Some(&crate::proto::model::PropertyValue::from("Your invoice")));
+        }
+
+        #[tokio::test]
+        async fn test_upload_unsupported() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.post("/api/upload")
+                .header(ContentType::GIF)
+                .header(api_key())
+                .body("my image")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_upload_requested() {
+            let mut server = Server::new().await;
+
+            server.juicer.expect_extract()
+                .with(predicate::always())
+                .times(1)
+                .return_once(|_| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post("/api/requests")
+                .header(ContentType::JSON)
+                .header(api_key())
+                .body(json_payload!({
+                    "recipient": "Jane",
+                    "message": "Please upload your insurance certificate",
+                    "labels": ["insurance"],
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let token = response["token"].as_str().unwrap().to_string();
+
+            let response = client.get(format!("/api/requests/link/{}", token))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["recipient"].as_str()).is_equal_to(Some("Jane"));
+
+            let mut doc = [0u8; 1024];
+            OsRng.fill_bytes(&mut doc);
+
+            let response = client.post(format!("/api/requests/link/{}", token))
+                .header(ContentType::PDF)
+                .body(doc)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().parse::<crate::proto::model::DocId>().unwrap();
+
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                while repository.inbox().get(id).await.is_none() {
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+
+            let metadata = repository.inbox().get(id).await.unwrap().read_metadata().await.unwrap();
+            assert_that!(metadata.owner).is_equal_to(Some(String::from("test")));
+            assert_that!(metadata.labels.len()).is_equal_to(1);
+
+            // The link can only be used once
+            let response = client.post(format!("/api/requests/link/{}", token))
+                .header(ContentType::PDF)
+                .body(doc)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Gone);
+
+            let response = client.get("/api/requests")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["requests"][0]["document"].as_str()).is_equal_to(Some(id.to_string().as_str()));
+        }
+
+        #[tokio::test]
+        async fn test_upload_requested_quota() {
+            let mut server = Server::new().await;
+            server.quota.user = Some(512);
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post("/api/requests")
+                .header(ContentType::JSON)
+                .header(api_key())
+                .body(json_payload!({
+                    "recipient": "Jane",
+                    "message": "Please upload your insurance certificate",
                     "labels": [],
-                    "properties": {},
+                }))
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let token = response["token"].as_str().unwrap().to_string();
+
+            // Requested documents are accounted to the requester
+            let response = client.post(format!("/api/requests/link/{}", token))
+                .header(ContentType::PDF)
+                .body([0u8; 1024])
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::InsufficientStorage);
+            assert_that!(repository.staging().list().await.unwrap().len()).is_equal_to(0);
+        }
+
+        #[tokio::test]
+        async fn test_status() {
+            use crate::juicer::report::Failure;
+            use crate::meta::Metadata;
+            use crate::proto::model::{DocId, Kind};
+            use crate::queue::Job;
+
+            let server = Server::new().await;
+
+            let stage = || async {
+                let staging = server.repository.stage().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                staging
+            };
+
+            let queued = stage().await;
+            Job::queued_now().save(&queued).await.unwrap();
+
+            let failed = stage().await;
+            Job {
+                attempts: 3,
+                failed: Some(Failure::classify(&anyhow::anyhow!("pdftotext failed"), "Syntax Error: Couldn't read xref table\n")),
+                ..Job::queued_now()
+            }.save(&failed).await.unwrap();
+            let failed = failed.fail().await.unwrap();
+
+            let inboxed = stage().await.create().await.unwrap();
+
+            let client = server.client().await;
+
+            let status = |id: DocId| {
+                let client = &client;
+                async move {
+                    let response = client.get(format!("/api/queue/{}", id))
+                        .header(api_key())
+                        .dispatch().await;
+                    assert_that!(response.status()).is_equal_to(Status::Ok);
+                    serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap()
+                }
+            };
+
+            assert_that!(status(*queued.id()).await["status"].as_str()).is_equal_to(Some("queued"));
+
+            let response = status(*failed.id()).await;
+            assert_that!(response["status"].as_str()).is_equal_to(Some("failed"));
+            assert_that!(response["attempts"].as_u64()).is_equal_to(Some(3));
+            assert_that!(response["failure"]["kind"].as_str()).is_equal_to(Some("corrupted"));
+
+            assert_that!(status(*inboxed.id()).await["status"].as_str()).is_equal_to(Some("inboxed"));
+
+            let response = client.get(format!("/api/queue/{}", DocId::random()))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_failed() {
+            use crate::juicer::report::Failure;
+            use crate::meta::Metadata;
+            use crate::proto::model::Kind;
+            use crate::queue::Job;
+
+            let mut server = Server::new().await;
+            server.juicer.expect_extract()
+                .returning(|_| Ok(()));
+
+            let fail = || async {
+                let staging = server.repository.stage().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                Job {
+                    attempts: 1,
+                    failed: Some(Failure::classify(&anyhow::anyhow!("juicer failed"), "")),
+                    ..Job::queued_now()
+                }.save(&staging).await.unwrap();
+                *staging.fail().await.unwrap().id()
+            };
+
+            let retried = fail().await;
+            let discarded = fail().await;
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.get("/api/queue")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["jobs"].as_array().map(Vec::len)).is_equal_to(Some(2));
+
+            let response = client.post(format!("/api/queue/{}/retry?juicer=unknown", retried))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.post(format!("/api/queue/{}/retry", retried))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                while repository.inbox().get(retried).await.is_none() {
+                    tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                }
+            }).await.unwrap();
+            assert_that!(repository.failed().get(retried).await.is_none()).is_true();
+
+            let response = client.delete(format!("/api/queue/{}", discarded))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(repository.failed().get(discarded).await.is_none()).is_true();
+
+            // Only failed jobs can be discarded
+            let response = client.delete(format!("/api/queue/{}", retried))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod inbox {
+        use std::collections::HashSet;
+        use std::iter::FromIterator;
+
+        use chrono::{DateTime, NaiveDateTime, Utc};
+        use futures::{stream, StreamExt};
+        use serde_json::json;
+        use tokio::io::AsyncWriteExt;
+        use tokio::time::Duration;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{Kind, Label, PropertyValue, Proposal};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_list() {
+            let server = Server::new().await;
+
+            // Create bundles in inbox (with a short delay between each to have a unique timestamp)
+            let ids = tokio::time::throttle(Duration::from_millis(10),
+                                            stream::iter(0..13usize))
+                .then(|_| async {
+                    let bundle = server.repository.stage().await.unwrap();
+
+                    Metadata {
+                        uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                        ..Metadata::new()
+                    }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                    let bundle = bundle.create().await.unwrap();
+
+                    *bundle.id()
+                }).collect::<Vec<_>>().await;
+
+            let client = server.client().await;
+
+            let response = client.get("/api/inbox")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            assert_json_eq!(response.into_bytes().await.unwrap(), {
+                "count": 13,
+                "docs": ids[0..10].iter().map(|id| json!({
+                    "id": id,
+                    "metadata": {
+                        "archived": (),
+                        "uploaded": "2001-09-09T01:46:40Z",
+                        "pages": 0,
+                        "title": (),
+                        "labels": [],
+                        "properties": {},
+                    }
+                })).collect::<Vec<_>>(),
+            });
+        }
+
+        #[tokio::test]
+        async fn test_list_streamed() {
+            let server = Server::new().await;
+
+            let ids = tokio::time::throttle(Duration::from_millis(10),
+                                            stream::iter(0..13usize))
+                .then(|_| async {
+                    let bundle = server.repository.stage().await.unwrap();
+                    Metadata::new().save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                    *bundle.create().await.unwrap().id()
+                }).collect::<Vec<_>>().await;
+
+            let client = server.client().await;
+
+            let response = client.get("/api/inbox")
+                .header(api_key())
+                .header(Header::new("Accept", "application/x-ndjson"))
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.content_type().map(|content_type| content_type.to_string()))
+                .is_equal_to(Some(String::from("application/x-ndjson")));
+
+            // All documents are streamed without pagination, one per line
+            let body = response.into_string().await.unwrap();
+            let streamed = body.lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            assert_that!(streamed).is_equal_to(ids.iter().map(|id| id.to_string()).collect::<Vec<_>>());
+        }
+
+        #[tokio::test]
+        async fn test_list_sorted() {
+            let server = Server::new().await;
+            let repository = &server.repository;
+
+            let ids = stream::iter(0..5u32)
+                .then(|i| async move {
+                    let bundle = repository.stage().await.unwrap();
+
+                    Metadata {
+                        pages: (i * 3) % 5,
+                        ..Metadata::new()
+                    }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                    let bundle = bundle.create().await.unwrap();
+
+                    *bundle.id()
+                }).collect::<Vec<_>>().await;
+
+            let client = server.client().await;
+
+            let response = client.get("/api/inbox?sort=-pages&offset=1&limit=2")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            // Pages are 0, 3, 1, 4, 2 - the second and third document in descending order have 3 and 2 pages
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(5));
+            assert_that!(response["docs"].as_array().unwrap().iter()
+                .map(|doc| doc["id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>())
+                .is_equal_to(vec![ids[1].to_string(), ids[4].to_string()]);
+
+            let response = client.get("/api/inbox?sort=size")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_list_query() {
+            let server = Server::new().await;
+            let repository = &server.repository;
+
+            let ids = stream::iter(0..4usize)
+                .then(|i| async move {
+                    let bundle = repository.stage().await.unwrap();
+
+                    Metadata {
+                        uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                        labels: if i % 2 == 0 { HashSet::from_iter(vec![Label::from("invoice")]) } else { HashSet::new() },
+                        ..Metadata::new()
+                    }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                    let bundle = bundle.create().await.unwrap();
+
+                    *bundle.id()
+                }).collect::<Vec<_>>().await;
+
+            let client = server.client().await;
+
+            let response = client.get("/api/inbox?query=label:invoice")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(2));
+
+            let docs = response["docs"].as_array().unwrap().iter()
+                .map(|doc| doc["id"].as_str().unwrap().to_string())
+                .collect::<HashSet<_>>();
+            assert_that!(docs).is_equal_to(HashSet::from_iter(vec![ids[0].to_string(), ids[2].to_string()]));
+
+            let response = client.get("/api/inbox?query=uploaded:yesterday")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_list_property() {
+            let server = Server::new().await;
+            let repository = &server.repository;
+
+            let ids = stream::iter(vec![Some("9.99 EUR"), Some("100"), Some("250.50 EUR"), None])
+                .then(|total| async move {
+                    let bundle = repository.stage().await.unwrap();
+
+                    let mut metadata = Metadata::new();
+                    if let Some(total) = total {
+                        metadata.properties.insert(String::from("total"), PropertyValue::parse(total));
+                    }
+                    metadata.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                    let bundle = bundle.create().await.unwrap();
+
+                    *bundle.id()
+                }).collect::<Vec<_>>().await;
+
+            let client = server.client().await;
+
+            // Amounts are compared numerically instead of as text
+            let response = client.get("/api/inbox?query=property.total:%3E%3D100&sort=-property.total")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(2));
+            assert_that!(response["docs"].as_array().unwrap().iter()
+                .map(|doc| doc["id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>())
+                .is_equal_to(vec![ids[2].to_string(), ids[1].to_string()]);
+        }
+
+        #[tokio::test]
+        async fn test_list_grouped() {
+            let server = Server::new().await;
+            let repository = &server.repository;
+
+            let ids = stream::iter(vec![Some("Stadtwerke"), None, Some("ACME"), Some("Stadtwerke")])
+                .then(|correspondent| async move {
+                    let bundle = repository.stage().await.unwrap();
+
+                    Metadata {
+                        correspondent: correspondent.map(String::from),
+                        ..Metadata::new()
+                    }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                    let bundle = bundle.create().await.unwrap();
+
+                    *bundle.id()
+                }).collect::<Vec<_>>().await;
+
+            let client = server.client().await;
+
+            let response = client.get("/api/inbox?group=correspondent&sort=uploaded&limit=2")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["groups"]).is_equal_to(json!([
+                { "key": "ACME", "count": 1 },
+                { "key": "Stadtwerke", "count": 2 },
+                { "count": 1 },
+            ]));
+            assert_that!(response["docs"].as_array().unwrap().iter()
+                .map(|doc| doc["id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>())
+                .is_equal_to(vec![ids[2].to_string(), ids[0].to_string()]);
+
+            let response = client.get("/api/inbox?group=color")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_list_owner() {
+            let server = Server::new().await;
+            let repository = &server.repository;
+
+            let ids = stream::iter(vec![Some("test"), Some("other"), None])
+                .then(|owner| async move {
+                    let bundle = repository.stage().await.unwrap();
+
+                    Metadata {
+                        owner: owner.map(String::from),
+                        ..Metadata::new()
+                    }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                    let bundle = bundle.create().await.unwrap();
+
+                    *bundle.id()
+                }).collect::<Vec<_>>().await;
+
+            let client = server.client().await;
+
+            let response = client.get("/api/inbox")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let docs = response["docs"].as_array().unwrap().iter()
+                .map(|doc| doc["id"].as_str().unwrap().to_string())
+                .collect::<HashSet<_>>();
+            assert_that!(docs).is_equal_to(HashSet::from_iter(vec![ids[0].to_string(), ids[2].to_string()]));
+
+            let response = client.get(format!("/api/inbox/{}", ids[1]))
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_get() {
+            let mut server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"").await.unwrap();
+                document.commit().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            server.suggester.expect_guess()
+                .with(mockall::predicate::eq("my document plaintext"))
+                .returning(|_| Ok(HashSet::from_iter(vec![Label::from("suggestion")])));
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/inbox/{}", doc_id))
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            assert_json_eq!(response.into_bytes().await.unwrap(), {
+                "id": doc_id.to_string(),
+                "metadata": {
+                    "archived": (),
+                    "uploaded": "2001-09-09T01:46:40Z",
+                    "pages": 0,
+                    "title": (),
+                    "labels": [],
+                    "properties": {},
+                },
+                "suggestions": ["suggestion"],
+            });
+        }
+
+        #[tokio::test]
+        async fn test_delete() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"").await.unwrap();
+                document.commit().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.delete(format!("/api/inbox/{}", doc_id))
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+        }
+
+        #[tokio::test]
+        async fn test_snooze() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                *staging.create().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let until = Utc::today().naive_utc() + chrono::Duration::days(14);
+
+            let response = client.put(format!("/api/inbox/{}/snooze", doc_id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "until": until }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            // Snoozed documents are hidden from the default listing
+            let response = client.get("/api/inbox")
+                .header(api_key())
+                .dispatch().await;
+            let listing = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(listing["count"]).is_equal_to(json!(0));
+
+            let response = client.get("/api/inbox?snoozed=true")
+                .header(api_key())
+                .dispatch().await;
+            let listing = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(listing["docs"][0]["id"]).is_equal_to(json!(doc_id));
+            assert_that!(listing["docs"][0]["metadata"]["snoozed"]).is_equal_to(json!(until));
+
+            // Snoozing until today or before is rejected
+            let response = client.put(format!("/api/inbox/{}/snooze", doc_id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "until": Utc::today().naive_utc() }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            // Waking the document brings it back right away
+            let response = client.put(format!("/api/inbox/{}/snooze", doc_id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({}))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/inbox")
+                .header(api_key())
+                .dispatch().await;
+            let listing = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(listing["count"]).is_equal_to(json!(1));
+        }
+
+        #[tokio::test]
+        async fn test_proposal() {
+            let mut server = Server::new().await;
+
+            async fn create(repository: &crate::repository::Repository) -> crate::proto::model::DocId {
+                let staging = repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"").await.unwrap();
+                document.commit().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata {
+                    proposal: Some(Proposal {
+                        title: Some("Invoice".to_string()),
+                        labels: HashSet::from_iter(vec![Label::from("invoice")]),
+                        correspondent: Some("ACME".to_string()),
+                        ..Proposal::default()
+                    }),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                return *staging.create().await.unwrap().id();
+            }
+
+            let accepted = create(&server.repository).await;
+            let rejected = create(&server.repository).await;
+
+            server.suggester.expect_train()
+                .returning(|_, _| Ok(()));
+
+            let client = server.client().await;
+
+            let archive = |id| client.post(format!("/api/inbox/{}", id))
+                .header(api_key())
+                .body(json_payload!({
+                    "labels": [],
+                    "properties": {},
+                }));
+
+            // Archiving is refused as long as the proposal is pending
+            let response = archive(accepted).dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Conflict);
+
+            // The proposal is accepted as edited by the reviewer
+            let response = client.post(format!("/api/inbox/{}/proposal", accepted))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({
+                    "title": "Invoice 42",
+                    "labels": [ "invoice" ],
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let doc = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(doc["metadata"]["title"]).is_equal_to(json!("Invoice 42"));
+            assert_that!(doc["metadata"]["labels"]).is_equal_to(json!(["invoice"]));
+            assert_that!(doc["metadata"]["correspondent"]).is_equal_to(json!(null));
+            assert_that!(doc["metadata"]["proposal"]).is_equal_to(json!(null));
+
+            // Accepting again fails as nothing is pending anymore
+            let response = client.post(format!("/api/inbox/{}/proposal", accepted))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({}))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = archive(accepted).dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            // Rejecting drops the proposal without touching the metadata
+            let response = client.delete(format!("/api/inbox/{}/proposal", rejected))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let doc = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(doc["metadata"]["title"]).is_equal_to(json!(null));
+            assert_that!(doc["metadata"]["labels"]).is_equal_to(json!([]));
+            assert_that!(doc["metadata"]["proposal"]).is_equal_to(json!(null));
+
+            let response = archive(rejected).dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+        }
+
+        #[tokio::test]
+        async fn test_archive() {
+            let mut server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"").await.unwrap();
+                document.commit().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            server.suggester.expect_train()
+                .with(mockall::predicate::eq("my document plaintext"),
+                      mockall::predicate::eq(HashSet::from_iter(vec![Label::from("expected")])))
+                .returning(|_, _| Ok(()));
+
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/inbox/{}", doc_id))
+                .header(api_key())
+                .body(json_payload!({
+                    "labels": [ "expected" ],
+                    "properties": {
+                        "title": "My little Test",
+                        "source": "testsuite",
+                    }
+                }))
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+        }
+
+        #[tokio::test]
+        async fn test_archive_encrypted() {
+            let mut server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"my document").await.unwrap();
+                document.commit().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            server.suggester.expect_train()
+                .returning(|_, _| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let archive = || client.post(format!("/api/inbox/{}", doc_id))
+                .header(api_key())
+                .body(json_payload!({
+                    "labels": [ "medical" ],
+                    "properties": {},
+                }));
+
+            // Archiving requires the domain to be unlocked
+            let response = archive().dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Forbidden);
+
+            let response = client.post("/api/domains/medical/unlock")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "passphrase": "secret" }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = archive().dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let bundle = repository.archive().get(doc_id).await.unwrap();
+            assert_that!(tokio::fs::read(bundle.path_of(Kind::Document)).await.unwrap()).is_not_equal_to(b"my document".to_vec());
+
+            let response = client.get(format!("/api/archive/{}/document", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.into_bytes().await).is_equal_to(Some(b"my document".to_vec()));
+
+            client.post("/api/domains/medical/lock")
+                .header(api_key())
+                .dispatch().await;
+
+            let response = client.get(format!("/api/archive/{}/document", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Forbidden);
+        }
+    }
+
+    mod archive {
+        use chrono::{DateTime, NaiveDateTime, Utc};
+        use futures::{stream, StreamExt};
+        use serde_json::json;
+        use tokio::io::AsyncWriteExt;
+
+        use std::str::FromStr;
+
+        use crate::index::SearchResponse;
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+        use crate::proto::query::Query;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_get_bundle() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"").await.unwrap();
+                document.commit().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/archive/{}", doc_id))
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            assert_json_eq!(response.into_bytes().await.unwrap(), {
+                "id": doc_id.to_string(),
+                "metadata": {
+                    "uploaded": "2001-09-09T01:46:40Z",
+                    "archived": (),
+                    "title": (),
+                    "pages": 0,
+                    "labels": [],
+                    "properties": {},
+                }
+            });
+        }
+
+        #[tokio::test]
+        async fn test_get_fragment() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"").await.unwrap();
+                document.commit().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/archive/{}/plaintext", doc_id))
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            assert_that!(response.into_bytes().await).is_some().is_equal_to(b"my document plaintext".to_vec());
+        }
+
+        #[tokio::test]
+        async fn test_send() {
+            let mut server = Server::new().await;
+            server.mail = Some(crate::config::Mail {
+                host: String::from("localhost"),
+                username: String::from("adacta"),
+                password: String::from("secret"),
+                from: String::from("adacta@example.com"),
+                max_size: 4,
+            });
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"%PDF-1.4 my document").await.unwrap();
+                document.commit().await.unwrap();
+
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/archive/{}/send", doc_id))
+                .header(api_key())
+                .body(json_payload!({ "to": "nobody" }))
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            // The document is refused before connecting to the server
+            let response = client.post(format!("/api/archive/{}/send", doc_id))
+                .header(api_key())
+                .body(json_payload!({ "to": "accountant@example.com", "message": "The invoice" }))
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::PayloadTooLarge);
+        }
+
+        #[tokio::test]
+        async fn test_send_disabled() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/archive/{}/send", doc_id))
+                .header(api_key())
+                .body(json_payload!({ "to": "accountant@example.com" }))
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_share() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"%PDF-1.4 my document").await.unwrap();
+                document.commit().await.unwrap();
+
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.post("/api/shares")
+                .header(ContentType::JSON)
+                .header(api_key())
+                .body(json_payload!({ "doc": doc_id }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let id = response["id"].as_str().unwrap().to_string();
+            let link = response["link"].as_str().unwrap().to_string();
+
+            // The link is fetched without any authentication
+            let response = client.get(link.clone())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.into_bytes().await).is_equal_to(Some(b"%PDF-1.4 my document".to_vec()));
+
+            // Tampering with the signature invalidates the link
+            let response = client.get(format!("/api/shares/link/{}/{}", id, "00".repeat(32)))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.get("/api/shares")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["shares"][0]["doc"].as_str()).is_equal_to(Some(doc_id.to_string().as_str()));
+
+            let response = client.delete(format!("/api/shares/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get(link)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_get_fragment_range() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"%PDF-1.4 my document").await.unwrap();
+                document.commit().await.unwrap();
+
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/archive/{}/document", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.headers().get_one("Accept-Ranges")).is_equal_to(Some("bytes"));
+
+            let etag = response.headers().get_one("ETag").unwrap().to_string();
+
+            let response = client.get(format!("/api/archive/{}/document", doc_id))
+                .header(api_key())
+                .header(Header::new("Range", "bytes=9-"))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::PartialContent);
+            assert_that!(response.headers().get_one("Content-Range")).is_equal_to(Some("bytes 9-19/20"));
+            assert_that!(response.into_bytes().await).is_some().is_equal_to(b"my document".to_vec());
+
+            let response = client.get(format!("/api/archive/{}/document", doc_id))
+                .header(api_key())
+                .header(Header::new("Range", "bytes=20-"))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::RangeNotSatisfiable);
+
+            // Ranges of another version of the fragment are ignored
+            let response = client.get(format!("/api/archive/{}/document", doc_id))
+                .header(api_key())
+                .header(Header::new("Range", "bytes=9-"))
+                .header(Header::new("If-Range", "\"outdated\""))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get(format!("/api/archive/{}/document", doc_id))
+                .header(api_key())
+                .header(Header::new("If-None-Match", etag))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotModified);
+        }
+
+        #[tokio::test]
+        async fn test_get_page_sized() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                Metadata {
+                    pages: 1,
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/archive/{}/preview/1?size=100000", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.get(format!("/api/archive/{}/preview/2?size=512", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_get_page_sized_locked() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                Metadata {
+                    pages: 1,
+                    domain: Some(String::from("medical")),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            // Encrypted documents are not rendered unless the domain is unlocked
+            let response = client.get(format!("/api/archive/{}/preview/1?size=512", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Forbidden);
+        }
+
+        #[tokio::test]
+        async fn test_get_page() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut page = staging.write(Kind::Page(1)).await.unwrap();
+                page.write_all(b"first page").await.unwrap();
+                page.commit().await.unwrap();
+
+                let mut page = staging.write(Kind::Page(2)).await.unwrap();
+                page.write_all(b"second page").await.unwrap();
+                page.commit().await.unwrap();
+
+                Metadata {
+                    pages: 2,
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let inboxed = staging.create().await.unwrap();
+                *inboxed.archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/archive/{}/preview/2", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.content_type()).is_equal_to(Some(ContentType::PNG));
+            assert_that!(response.into_bytes().await).is_some().is_equal_to(b"second page".to_vec());
+
+            let response = client.get(format!("/api/archive/{}/preview/3", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.get(format!("/api/archive/{}/preview/0", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_browse() {
+            let server = Server::new().await;
+
+            let docs = vec![
+                ("ACME", vec!["invoice", "tax"], "2021"),
+                ("ACME", vec!["invoice"], "2020"),
+                ("Globex", vec!["invoice", "tax"], "2021"),
+            ];
+
+            for (correspondent, labels, year) in docs {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut metadata = Metadata {
+                    correspondent: Some(correspondent.to_string()),
+                    labels: labels.into_iter().map(crate::proto::model::Label::from).collect(),
+                    ..Metadata::new()
+                };
+                metadata.properties.insert("year".to_string(), crate::proto::model::PropertyValue::parse(year));
+                metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                staging.create().await.unwrap().archive().await.unwrap();
+            }
+
+            let client = server.client().await;
+
+            let browse = |query: &str| client.get(format!("/api/archive/browse?{}", query))
+                .header(api_key());
+
+            let response = browse("").dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(3));
+
+            let response = browse("labels=invoice,tax&correspondent=acme").dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(1));
+
+            let response = browse("property=year%3D2021&group=correspondent&limit=1").dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(2));
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(1));
+            assert_that!(response["groups"]).is_equal_to(json!([
+                { "key": "ACME", "count": 1 },
+                { "key": "Globex", "count": 1 },
+            ]));
+
+            let response = browse("property=year").dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_search() {
+            let mut server = Server::new().await;
+
+            // Create bundles in inbox (with a short delay between each to have a unique timestamp)
+            let ids = stream::iter(0..10usize).then(|_| async {
+                let bundle = server.repository.stage().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    ..Metadata::new()
+                }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let bundle = bundle.create().await.unwrap();
+                let bundle = bundle.archive().await.unwrap();
+
+                *bundle.id()
+            }).collect::<Vec<_>>().await;
+
+            server.index.expect_search()
+                .with(mockall::predicate::eq(Query::from_str("testquery").unwrap()),
+                      mockall::predicate::eq(crate::repository::Listing::default()))
+                .return_once({
+                    let ids = ids.clone();
+                    move |_, _| Ok(SearchResponse {
+                        count: 387,
+                        docs: ids,
+                        snippets: HashMap::new(),
+                    })
+                });
+
+            let client = server.client().await;
+
+            let response = client.get("/api/archive?query=testquery")
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            assert_json_eq!(response.into_bytes().await.unwrap(), {
+                "count": 387,
+                "docs": ids.iter().map(|id| json!({
+                    "id": id,
+                    "metadata": {
+                        "uploaded": "2001-09-09T01:46:40Z",
+                        "archived": (),
+                        "title": (),
+                        "pages": 0,
+                        "labels": [],
+                        "properties": {},
+                    }
+                })).collect::<Vec<_>>(),
+            });
+        }
+
+        #[tokio::test]
+        async fn test_similar() {
+            let mut server = Server::new().await;
+            let repository = &server.repository;
+
+            let ids = stream::iter(vec![None, None, Some("other")]).then(|owner| async move {
+                let bundle = repository.stage().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    owner: owner.map(String::from),
+                    ..Metadata::new()
+                }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let bundle = bundle.create().await.unwrap();
+                let bundle = bundle.archive().await.unwrap();
+
+                *bundle.id()
+            }).collect::<Vec<_>>().await;
+
+            server.index.expect_similar()
+                .with(mockall::predicate::eq(ids[0]),
+                      mockall::predicate::eq(crate::repository::Listing::new(None, None, Some(5))))
+                .return_once({
+                    let ids = ids.clone();
+                    move |_, _| Ok(SearchResponse {
+                        count: 2,
+                        docs: ids[1..].to_vec(),
+                        snippets: HashMap::new(),
+                    })
+                });
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/archive/{}/similar?limit=5", ids[0]))
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            // Documents hidden from the requesting user are dropped
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["docs"][0]["id"].as_str()).is_equal_to(Some(ids[1].to_string().as_str()));
+
+            let response = client.get(format!("/api/archive/{}/similar", DocId::random()))
+                .header(api_key())
+                .dispatch().await;
+
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod trash {
+        use chrono::{DateTime, NaiveDateTime, Utc};
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_delete_restore() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                Metadata {
+                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.delete(format!("/api/inbox/{}", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get(format!("/api/inbox/{}", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.get("/api/trash")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["docs"][0]["id"].as_str()).is_equal_to(Some(doc_id.to_string().as_str()));
+
+            let response = client.post(format!("/api/trash/{}/restore", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get(format!("/api/inbox/{}/plaintext", doc_id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+        }
+    }
+
+    mod quarantine {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::proto::model::Kind;
+        use crate::proto::api::quarantine::ReviewAction;
+        use crate::quarantine::Reviews;
+        use crate::uploads::Upload;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_review() {
+            let server = Server::new().await;
+
+            let purged = {
+                let staging = server.repository.stage().await.unwrap();
+                let mut fragment = staging.write(Kind::other("original.pdf")).await.unwrap();
+                fragment.write_all(b"suspicious").await.unwrap();
+                fragment.commit().await.unwrap();
+                *staging.quarantine("Virus found").await.unwrap().id()
+            };
+
+            let released = {
+                let staging = server.repository.stage().await.unwrap();
+                let mut fragment = staging.write(Kind::other(Upload::FRAGMENT)).await.unwrap();
+                fragment.write_all(b"{}").await.unwrap();
+                fragment.commit().await.unwrap();
+                *staging.quarantine("Upload abandoned").await.unwrap().id()
+            };
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.get("/api/quarantine")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(2));
+
+            let response = client.get(format!("/api/quarantine/{}", purged))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["reason"].as_str()).is_equal_to(Some("Virus found"));
+            assert_that!(response["fragments"][0]["name"].as_str()).is_equal_to(Some("original.pdf"));
+            assert_that!(response["fragments"][0]["size"].as_u64()).is_equal_to(Some(10));
+            assert_that!(response["reviews"][0]["action"].as_str()).is_equal_to(Some("inspected"));
+
+            let response = client.get(format!("/api/quarantine/{}/sample", purged))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.content_type()).is_equal_to(Some(ContentType::ZIP));
+
+            let sample = response.into_bytes().await.unwrap();
+            assert_that!(sample[..4].to_vec()).is_equal_to(b"PK\x03\x04".to_vec());
+            assert_that!(sample.windows(10).any(|window| window == b"suspicious")).is_false();
+
+            let response = client.delete(format!("/api/quarantine/{}", purged))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get(format!("/api/quarantine/{}", purged))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            // Abandoned uploads are released to staging to be continued
+            let response = client.post(format!("/api/quarantine/{}/release", released))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(repository.staging().get(released).await).is_some();
+
+            let reviews = Reviews::open(repository.path().join(Reviews::FILENAME));
+            assert_that!(reviews.of(purged).await.unwrap().iter().map(|review| review.action).collect::<Vec<_>>())
+                .is_equal_to(vec![ReviewAction::Inspected, ReviewAction::Downloaded, ReviewAction::Purged]);
+            assert_that!(reviews.of(released).await.unwrap().len()).is_equal_to(1);
+        }
+    }
+
+    mod sync {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{Kind, Label};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_list_update() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+
+                let mut metadata = Metadata::new();
+                metadata.labels.insert(Label::from("travel"));
+                metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get("/api/sync?labels=travel")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(1));
+            assert_that!(response["docs"][0]["id"].as_str()).is_equal_to(Some(doc_id.to_string().as_str()));
+
+            let revision = response["docs"][0]["revision"].as_str().unwrap().to_string();
+            let metadata = response["docs"][0]["metadata"].clone();
+
+            let response = client.put(format!("/api/sync/{}", doc_id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "base": "outdated", "metadata": metadata }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Conflict);
+
+            let response = client.put(format!("/api/sync/{}", doc_id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "base": revision, "metadata": metadata }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/sync/changes?cursor=0")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["cursor"].as_u64()).is_equal_to(Some(4));
+            assert_that!(response["updated"][0]["id"].as_str()).is_equal_to(Some(doc_id.to_string().as_str()));
+            assert_that!(response["updated"][0]["location"].as_str()).is_equal_to(Some("archive"));
+            assert_that!(response["deleted"].as_array().map(Vec::len)).is_equal_to(Some(0));
+
+            let response = client.get("/api/sync?labels=other")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(0));
+        }
+
+        #[tokio::test]
+        async fn test_owner() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                Metadata {
+                    owner: Some(String::from("other")),
+                    labels: vec![Label::from("travel")].into_iter().collect(),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().archive().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get("/api/sync?labels=travel")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(0));
+
+            let response = client.get("/api/sync/changes?cursor=0")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["updated"].as_array().map(Vec::len)).is_equal_to(Some(0));
+            assert_that!(response["deleted"].as_array().map(Vec::len)).is_equal_to(Some(0));
+
+            let response = client.put(format!("/api/sync/{}", doc_id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({
+                    "base": "any",
+                    "metadata": {
+                        "uploaded": "2020-01-01T00:00:00Z",
+                        "archived": null,
+                        "title": null,
+                        "pages": 0,
+                        "labels": [],
+                        "properties": {},
+                    },
+                }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod changes {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_changes() {
+            let server = Server::new().await;
+
+            let doc_id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                let bundle = staging.create().await.unwrap().archive().await.unwrap();
+
+                let mut metadata = bundle.read_metadata().await.unwrap();
+                metadata.title = Some(String::from("my title"));
+                bundle.write_metadata(&metadata).await.unwrap();
+
+                *bundle.delete().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get("/api/changes")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["cursor"].as_u64()).is_equal_to(Some(5));
+            assert_that!(response["more"].as_bool()).is_equal_to(Some(false));
+
+            let changes = response["changes"].as_array().unwrap();
+            assert_that!(changes.iter().map(|change| change["kind"].as_str().unwrap()).collect::<Vec<_>>())
+                .is_equal_to(vec!["created", "updated", "updated", "trashed"]);
+            assert_that!(changes[0]["id"].as_str()).is_equal_to(Some(doc_id.to_string().as_str()));
+            assert_that!(changes[0]["location"].as_str()).is_equal_to(Some("inbox"));
+            assert_that!(changes[0]["metadata"].is_object()).is_true();
+            assert_that!(changes[1]["location"].as_str()).is_equal_to(Some("archive"));
+            assert_that!(changes[2]["fields"]["title"].as_str()).is_equal_to(Some("my title"));
+
+            let response = client.get("/api/changes?since=4&limit=10")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["changes"].as_array().map(Vec::len)).is_equal_to(Some(1));
+            assert_that!(response["changes"][0]["cursor"].as_u64()).is_equal_to(Some(5));
+        }
+    }
+
+    mod bulk {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind, Label};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_add_label() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let mut ids = Vec::new();
+            for _ in 0..3 {
+                let staging = repository.stage().await.unwrap();
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                ids.push(*staging.create().await.unwrap().id());
+            }
+
+            let client = server.client().await;
+
+            // Nothing is changed if a single document is invalid
+            let response = client.post("/api/bulk")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({
+                    "ids": [ids[0], ids[1], DocId::random()],
+                    "op": "add-label",
+                    "label": "receipt",
+                }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["applied"].as_bool()).is_equal_to(Some(false));
+            assert_that!(response["results"][2]["error"].as_str()).is_some();
+
+            let metadata = repository.inbox().get(ids[0]).await.unwrap().read_metadata().await.unwrap();
+            assert_that!(metadata.labels.contains("receipt")).is_false();
+
+            let response = client.post("/api/bulk")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({
+                    "ids": ids,
+                    "op": "add-label",
+                    "label": "receipt",
+                }).to_string())
+                .dispatch().await;
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["applied"].as_bool()).is_equal_to(Some(true));
+
+            for id in ids {
+                let metadata = repository.inbox().get(id).await.unwrap().read_metadata().await.unwrap();
+                assert_that!(metadata.labels.contains(&Label::from("receipt"))).is_true();
+            }
+        }
+
+        #[tokio::test]
+        async fn test_stream() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let mut ids = Vec::new();
+            for _ in 0..2 {
+                let staging = repository.stage().await.unwrap();
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                ids.push(*staging.create().await.unwrap().id());
+            }
+
+            let client = server.client().await;
+
+            let response = client.post("/api/bulk/stream")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({
+                    "ids": ids,
+                    "op": "add-label",
+                    "label": "receipt",
+                }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = response.into_string().await.unwrap();
+            let events = response.lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect::<Vec<_>>();
+
+            assert_that!(events).has_length(4);
+            assert_that!(events[0]["event"].as_str()).is_equal_to(Some("started"));
+            assert_that!(events[0]["total"].as_u64()).is_equal_to(Some(2));
+            assert_that!(events[1]["event"].as_str()).is_equal_to(Some("processed"));
+            assert_that!(events[2]["done"].as_u64()).is_equal_to(Some(2));
+            assert_that!(events[3]["event"].as_str()).is_equal_to(Some("finished"));
+            assert_that!(events[3]["failed"].as_u64()).is_equal_to(Some(0));
+
+            for id in ids {
+                let metadata = repository.inbox().get(id).await.unwrap().read_metadata().await.unwrap();
+                assert_that!(metadata.labels.contains(&Label::from("receipt"))).is_true();
+            }
+
+            // Nothing is processed if a single document is invalid
+            let response = client.post("/api/bulk/stream")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({
+                    "ids": [DocId::random()],
+                    "op": "delete",
+                }).to_string())
+                .dispatch().await;
+
+            let response = response.into_string().await.unwrap();
+            let events = response.lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect::<Vec<_>>();
+
+            assert_that!(events).has_length(1);
+            assert_that!(events[0]["event"].as_str()).is_equal_to(Some("rejected"));
+        }
+    }
+
+    mod suggestions {
+        use crate::meta::Metadata;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_suggest() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            archive_bills(&repository).await;
+
+            let id = stage(&repository, "Holiday postcard from the beach", Metadata::new()).await;
+            repository.inbox().get(id).await.unwrap().archive().await.unwrap();
+
+            let id = stage(&repository, "Stadtwerke electricity bill for june", Metadata::new()).await;
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/suggestions/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["labels"][0]["value"].as_str()).is_equal_to(Some("utilities"));
+            assert_that!(response["correspondent"]["value"].as_str()).is_equal_to(Some("Stadtwerke"));
+            assert_that!(response["title"].is_null()).is_true();
+        }
+    }
+
+    mod triage {
+        use chrono::{DateTime, NaiveDateTime, Utc};
+
+        use crate::meta::Metadata;
+
+        use super::*;
+
+        fn uploaded(timestamp: i64) -> DateTime<Utc> {
+            return DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), Utc);
+        }
+
+        #[tokio::test]
+        async fn test_session() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            archive_bills(&repository).await;
+
+            let postcard = stage(&repository, "Holiday postcard from the beach", Metadata {
+                uploaded: uploaded(1_000_000_200),
+                ..Metadata::new()
+            }).await;
+            let bill = stage(&repository, "Stadtwerke electricity bill for june", Metadata {
+                uploaded: uploaded(1_000_000_100),
+                ..Metadata::new()
+            }).await;
+
+            let client = server.client().await;
+
+            // The oldest document is served first
+            let response = client.get("/api/triage")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["remaining"].as_u64()).is_equal_to(Some(2));
+            assert_that!(response["next"]["id"]).is_equal_to(serde_json::json!(bill));
+            assert_that!(response["next"]["suggestions"]["labels"][0]["value"].as_str()).is_equal_to(Some("utilities"));
+
+            // Archiving without values accepts the suggestions
+            let response = client.post(format!("/api/triage/{}", bill))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "op": "archive" }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["undo"]["operation"].is_string()).is_true();
+            assert_that!(response["remaining"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["next"]["id"]).is_equal_to(serde_json::json!(postcard));
+
+            let metadata = repository.archive().get(bill).await.unwrap().read_metadata().await.unwrap();
+            assert_that!(metadata.labels.contains("utilities")).is_true();
+            assert_that!(metadata.correspondent).is_equal_to(Some(String::from("Stadtwerke")));
+
+            // Skipping the last document ends the session but leaves the document in the inbox
+            let response = client.post(format!("/api/triage/{}", postcard))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "op": "skip" }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["undo"].is_null()).is_true();
+            assert_that!(response["remaining"].as_u64()).is_equal_to(Some(0));
+            assert_that!(response["next"].is_null()).is_true();
+
+            assert_that!(repository.inbox().get(postcard).await.is_some()).is_true();
+        }
+    }
+
+    mod undo {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_undo_bulk_delete() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let mut ids = Vec::new();
+            for _ in 0..2 {
+                let staging = repository.stage().await.unwrap();
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                ids.push(*staging.create().await.unwrap().id());
+            }
+
+            let client = server.client().await;
+
+            let response = client.post("/api/bulk")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({
+                    "ids": ids,
+                    "op": "delete",
+                }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let operation = response["undo"]["operation"].as_str().unwrap().to_string();
+
+            for id in &ids {
+                assert_that!(repository.inbox().get(*id).await.is_none()).is_true();
+            }
+
+            let response = client.post(format!("/api/undo/{}", operation))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["results"].as_array().map(Vec::len)).is_equal_to(Some(2));
+
+            for id in &ids {
+                assert_that!(repository.inbox().get(*id).await.is_some()).is_true();
+            }
+
+            // Operations can only be reverted once
+            let response = client.post(format!("/api/undo/{}", operation))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_undo_archive() {
+            let mut server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let staging = repository.stage().await.unwrap();
+            let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+            plaintext.write_all(b"my document plaintext").await.unwrap();
+            plaintext.commit().await.unwrap();
+            Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *staging.create().await.unwrap().id();
+
+            server.suggester.expect_train()
+                .returning(|_, _| Ok(()));
+
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/inbox/{}", id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({
+                    "labels": [ "receipt" ],
+                    "properties": {},
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let operation = response["operation"].as_str().unwrap().to_string();
+
+            assert_that!(repository.archive().get(id).await.is_some()).is_true();
+
+            let response = client.post(format!("/api/undo/{}", operation))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let metadata = repository.inbox().get(id).await.unwrap().read_metadata().await.unwrap();
+            assert_that!(metadata.archived).is_none();
+            assert_that!(metadata.labels).is_empty();
+        }
+    }
+
+    mod labels {
+        use crate::meta::Metadata;
+        use crate::proto::model::{Kind, Label};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_define_rename() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let staging = repository.stage().await.unwrap();
+            Metadata {
+                labels: vec![Label::from("finance/tax/2023")].into_iter().collect(),
+                ..Metadata::new()
+            }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *staging.create().await.unwrap().id();
+
+            let client = server.client().await;
+
+            let response = client.put("/api/labels/finance%2Ftax")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r##"{"color": "#ff0000", "description": "Tax returns"}"##)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.put("/api/labels/finance")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"color": "red"}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.get("/api/labels")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["labels"].as_array().map(Vec::len)).is_equal_to(Some(3));
+            assert_that!(response["labels"][1]["label"].as_str()).is_equal_to(Some("finance/tax"));
+            assert_that!(response["labels"][1]["color"].as_str()).is_equal_to(Some("#ff0000"));
+            assert_that!(response["labels"][1]["count"].as_u64()).is_equal_to(Some(1));
+
+            let response = client.post("/api/labels/finance%2Ftax/rename")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"to": "taxes"}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["documents"].as_u64()).is_equal_to(Some(1));
+
+            let metadata = repository.inbox().get(id).await.unwrap().read_metadata().await.unwrap();
+            assert_that!(metadata.labels.contains("taxes/2023")).is_true();
+        }
+    }
+
+    mod correspondents {
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_define_filter() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            for correspondent in vec![Some("ACME Corp"), None] {
+                let staging = repository.stage().await.unwrap();
+                Metadata {
+                    correspondent: correspondent.map(String::from),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                staging.create().await.unwrap();
+            }
+
+            let client = server.client().await;
+
+            let response = client.put("/api/correspondents/ACME%20Corp")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"aliases": ["ACME Corporation"], "labels": ["supplier"]}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/correspondents")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["correspondents"][0]["name"].as_str()).is_equal_to(Some("ACME Corp"));
+            assert_that!(response["correspondents"][0]["aliases"][0].as_str()).is_equal_to(Some("ACME Corporation"));
+
+            let response = client.get("/api/inbox?query=correspondent:%22acme%20corp%22")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(1));
+
+            let response = client.delete("/api/correspondents/ACME%20Corp")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/correspondents/ACME%20Corp")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod checklists {
+        use crate::meta::Metadata;
+        use crate::proto::model::{Kind, Label};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_progress() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let staging = repository.stage().await.unwrap();
+            Metadata {
+                labels: vec![Label::from("payslip")].into_iter().collect(),
+                ..Metadata::new()
+            }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *staging.create().await.unwrap().archive().await.unwrap().id();
+
+            let client = server.client().await;
+
+            let response = client.put("/api/checklists/Taxes")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"items": [{"name": "Payslip", "query": "label:payslip"}, {"name": "Donations", "query": "label:donation"}]}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.put("/api/checklists/Broken")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"items": [{"name": "Payslip", "query": "uploaded:yesterday"}]}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.get("/api/checklists/Taxes/progress")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["arrived"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["missing"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["items"][0]["documents"][0].as_str()).is_equal_to(Some(id.to_string().as_str()));
+            assert_that!(response["items"][1]["documents"].as_array().map(Vec::len)).is_equal_to(Some(0));
+
+            let response = client.delete("/api/checklists/Taxes")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/checklists/Taxes/progress")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod persons {
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_belongs_to() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let staging = repository.stage().await.unwrap();
+            Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *staging.create().await.unwrap().id();
+
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/inbox/{}", id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"labels": [], "properties": {}, "belongs_to": "Alice"}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.put("/api/persons/Alice")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"relationship": "daughter"}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/persons")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["persons"][0]["name"].as_str()).is_equal_to(Some("Alice"));
+            assert_that!(response["persons"][0]["relationship"].as_str()).is_equal_to(Some("daughter"));
+
+            let response = client.post(format!("/api/inbox/{}", id))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"labels": [], "properties": {}, "belongs_to": "Alice"}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let metadata = repository.archive().get(id).await.unwrap().read_metadata().await.unwrap();
+            assert_that!(metadata.belongs_to).is_equal_to(Some(String::from("Alice")));
+
+            let response = client.delete("/api/persons/Alice")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/persons/Alice")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod rules {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_define_remove() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.put("/api/rules/power%20bills")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{
+                    "conditions": [
+                        { "match": "keyword", "field": "text", "keyword": "Stadtwerke" },
+                        { "match": "regex", "field": "text", "pattern": "Invoice (?P<number>\\d+)" }
+                    ],
+                    "labels": ["utilities"],
+                    "title": "Power bill {number}"
+                }"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.put("/api/rules/broken")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"conditions": [{ "match": "regex", "field": "filename", "pattern": "(" }]}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.get("/api/rules")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["rules"].as_array().map(Vec::len)).is_equal_to(Some(1));
+            assert_that!(response["rules"][0]["name"].as_str()).is_equal_to(Some("power bills"));
+
+            let response = client.delete("/api/rules/power%20bills")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.delete("/api/rules/power%20bills")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod stats {
+        use std::str::FromStr;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{Decimal, Kind, Label, PropertyValue};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_stats() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            for (correspondent, amount) in vec![("ACME", "10.00"), ("ACME", "2.50"), ("Other", "1.00")] {
+                let staging = repository.stage().await.unwrap();
+                let mut metadata = Metadata {
+                    correspondent: Some(String::from(correspondent)),
+                    ..Metadata::new()
+                };
+                metadata.properties.insert(String::from("invoice.total"), PropertyValue::amount(Decimal::from_str(amount).unwrap(), Some(String::from("EUR"))).unwrap());
+                metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                staging.create().await.unwrap().archive().await.unwrap();
+            }
+
+            let client = server.client().await;
+
+            let response = client.get("/api/stats?query=correspondent:acme")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["groups"].as_array().map(Vec::len)).is_equal_to(Some(1));
+            assert_that!(response["groups"][0]["count"].as_u64()).is_equal_to(Some(2));
+            assert_that!(response["groups"][0]["totals"][0]["amount"].as_str()).is_equal_to(Some("12.50"));
+            assert_that!(response["groups"][0]["totals"][0]["currency"].as_str()).is_equal_to(Some("EUR"));
+        }
+
+        #[tokio::test]
+        async fn test_calendar() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            for _ in 0..2 {
+                let staging = repository.stage().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                staging.create().await.unwrap().archive().await.unwrap();
+            }
+
+            let client = server.client().await;
+
+            let response = client.get("/api/stats/calendar")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["days"].as_array().map(Vec::len)).is_equal_to(Some(1));
+            assert_that!(response["days"][0]["date"]).is_equal_to(&response["to"]);
+            assert_that!(response["days"][0]["uploaded"].as_u64()).is_equal_to(Some(2));
+            assert_that!(response["days"][0]["dated"].as_u64()).is_equal_to(Some(0));
+
+            let response = client.get("/api/stats/calendar?from=2023-02-01&to=2023-01-01")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+
+        #[tokio::test]
+        async fn test_dashboard() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            for (correspondent, label) in vec![("ACME", "invoice"), ("ACME", "invoice"), ("Other", "tax")] {
+                let staging = repository.stage().await.unwrap();
+                let mut metadata = Metadata {
+                    correspondent: Some(String::from(correspondent)),
+                    pages: 2,
+                    ..Metadata::new()
+                };
+                metadata.labels.insert(Label::from(label));
+                metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                staging.create().await.unwrap().archive().await.unwrap();
+            }
+
+            // Documents in the inbox are not counted, but use storage
+            let staging = repository.stage().await.unwrap();
+            Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            staging.create().await.unwrap();
+
+            let client = server.client().await;
+
+            let response = client.get("/api/stats/dashboard")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["documents"].as_u64()).is_equal_to(Some(3));
+            assert_that!(response["pages"].as_u64()).is_equal_to(Some(6));
+            assert_that!(response["months"][0]["count"].as_u64()).is_equal_to(Some(3));
+            assert_that!(response["labels"][0]["name"].as_str()).is_equal_to(Some("invoice"));
+            assert_that!(response["labels"][0]["count"].as_u64()).is_equal_to(Some(2));
+            assert_that!(response["correspondents"][0]["name"].as_str()).is_equal_to(Some("ACME"));
+            assert_that!(response["usage"]["inbox"].as_u64().unwrap()).is_greater_than(0);
+            assert_that!(response["usage"]["trash"].as_u64()).is_equal_to(Some(0));
+            assert_that!(response["failure_rate"].as_f64()).is_equal_to(Some(0.0));
+        }
+    }
+
+    mod confidence {
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_list() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            for confidence in vec![Some(80), Some(35), Some(50), None] {
+                let staging = repository.stage().await.unwrap();
+                Metadata {
+                    confidence,
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                staging.create().await.unwrap();
+            }
+
+            let client = server.client().await;
+
+            let response = client.get("/api/confidence")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["threshold"].as_u64()).is_equal_to(Some(60));
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(2));
+            assert_that!(response["docs"][0]["metadata"]["confidence"].as_u64()).is_equal_to(Some(35));
+            assert_that!(response["docs"][1]["metadata"]["confidence"].as_u64()).is_equal_to(Some(50));
+
+            let response = client.get("/api/confidence?threshold=40")
+                .header(api_key())
+                .dispatch().await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(1));
+        }
+    }
+
+    mod due {
+        use chrono::{Duration, Utc};
+
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_list_dismiss() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let today = Utc::today().naive_utc();
+
+            let mut ids = Vec::new();
+            for days in vec![-2, 3, 30] {
+                let staging = repository.stage().await.unwrap();
+                Metadata {
+                    due: Some(today + Duration::days(days)),
+                    ..Metadata::new()
+                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                ids.push(*staging.create().await.unwrap().id());
+            }
+
+            let client = server.client().await;
+
+            let response = client.get("/api/due")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(2));
+            assert_that!(response["docs"][0]["id"].as_str()).is_equal_to(Some(ids[0].to_string().as_str()));
+            assert_that!(response["docs"][0]["overdue"].as_bool()).is_equal_to(Some(true));
+            assert_that!(response["docs"][1]["overdue"].as_bool()).is_equal_to(Some(false));
+
+            let response = client.put(format!("/api/due/{}", ids[0]))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/due?days=60")
+                .header(api_key())
+                .dispatch().await;
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["docs"].as_array().map(Vec::len)).is_equal_to(Some(2));
+        }
+    }
+
+    mod resolve {
+        use crate::meta::Metadata;
+        use crate::proto::model::{Kind, PropertyValue};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_resolve() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let staging = repository.stage().await.unwrap();
+            let mut metadata = Metadata::new();
+            metadata.properties.insert(String::from("asn"), PropertyValue::Integer(42));
+            metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *staging.create().await.unwrap().archive().await.unwrap().id();
+
+            let client = server.client().await;
+
+            let response = client.get("/api/resolve/ASN%20000042")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["id"].as_str()).is_equal_to(Some(id.to_string().as_str()));
+
+            let response = client.get(format!("/d/{}", &id.to_string()[..8]))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::SeeOther);
+            assert_that!(response.headers().get_one("Location")).is_equal_to(Some(format!("/archive/{}", id).as_str()));
+
+            let response = client.get("/d/42")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::SeeOther);
+            assert_that!(response.headers().get_one("Location")).is_equal_to(Some("/login?redirect=/d/42"));
+
+            let response = client.get("/api/resolve/43")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod filing {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_sheet_disabled() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.post("/api/filing/sheet")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"query": "label:finance"}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+    }
+
+    mod relations {
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_link_unlink() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let mut ids = Vec::new();
+            for _ in 0..2 {
+                let staging = repository.stage().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+                ids.push(*staging.create().await.unwrap().id());
+            }
+
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/relations/{}", ids[0]))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "kind": "invoice-for", "target": ids[1] }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            // Links to unknown documents and to the document itself are rejected
+            let response = client.post(format!("/api/relations/{}", ids[0]))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "kind": "related", "target": DocId::random() }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.post(format!("/api/relations/{}", ids[0]))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "kind": "related", "target": ids[0] }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.get(format!("/api/relations/{}", ids[1]))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["outgoing"].as_array().map(Vec::len)).is_equal_to(Some(0));
+            assert_that!(response["incoming"][0]["kind"].as_str()).is_equal_to(Some("invoice-for"));
+            assert_that!(response["incoming"][0]["id"].as_str()).is_equal_to(Some(ids[0].to_string().as_str()));
+
+            let response = client.delete(format!("/api/relations/{}/invoice-for/{}", ids[0], ids[1]))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.delete(format!("/api/relations/{}/invoice-for/{}", ids[0], ids[1]))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod merge {
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_merge_invalid() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let staging = repository.stage().await.unwrap();
+            Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *staging.create().await.unwrap().id();
+
+            let client = server.client().await;
+
+            // Merging requires at least two distinct and existing documents
+            let response = client.post("/api/merge")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "ids": [id] }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.post("/api/merge")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "ids": [id, id] }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.post("/api/merge")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "ids": [id, DocId::random()] }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            // The sources are left untouched
+            assert_that!(repository.inbox().get(id).await.is_some()).is_true();
+        }
+    }
+
+    mod pages {
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_edit_invalid() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
+
+            let staging = repository.stage().await.unwrap();
+            Metadata { pages: 2, ..Metadata::new() }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *staging.create().await.unwrap().archive().await.unwrap().id();
+
+            let client = server.client().await;
+
+            // Pages must exist and be rotated by right angles only
+            let payloads = vec![
+                json_payload!({ "pages": [] }),
+                json_payload!({ "pages": [{ "page": 3 }] }),
+                json_payload!({ "pages": [{ "page": 1, "rotate": 45 }] }),
+            ];
+            for payload in payloads {
+                let response = client.post(format!("/api/pages/{}", id))
+                    .header(api_key())
+                    .header(ContentType::JSON)
+                    .body(payload)
+                    .dispatch().await;
+                assert_that!(response.status()).is_equal_to(Status::BadRequest);
+            }
+
+            let response = client.post(format!("/api/pages/{}", DocId::random()))
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({ "pages": [{ "page": 1 }] }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            // The document is left untouched
+            let bundle = repository.archive().get(id).await.unwrap();
+            assert_that!(bundle.versions().await.unwrap()).is_empty();
+        }
+    }
+
+    mod documents {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_replace() {
+            let mut server = Server::new().await;
+
+            let id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"my document").await.unwrap();
+                document.commit().await.unwrap();
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().archive().await.unwrap().id()
+            };
+
+            // The juicer gets the new document as original
+            server.juicer.expect_extract()
+                .times(1)
+                .returning(|bundle| {
+                    assert_eq!(std::fs::read(bundle.path_of(Kind::other("original.pdf"))).unwrap(), b"my signed document");
+                    std::fs::write(bundle.path_of(Kind::Document), b"my signed document").unwrap();
+                    std::fs::write(bundle.path_of(Kind::Plaintext), b"my signed plaintext").unwrap();
+                    Ok(())
+                });
+
+            server.index.expect_index()
+                .times(1)
+                .returning(|_| Ok(()));
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/documents/{}?reason=Signed", id))
+                .header(api_key())
+                .header(ContentType::PDF)
+                .body(b"my signed document")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let bundle = repository.archive().get(id).await.unwrap();
+            assert_that!(bundle.read_plaintext().await.unwrap()).is_equal_to("my signed plaintext".to_string());
+
+            let response = client.get(format!("/api/documents/{}/versions", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let versions: serde_json::Value = serde_json::from_slice(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(versions["versions"][0]["version"]).is_equal_to(serde_json::json!(1));
+            assert_that!(versions["versions"][0]["reason"]).is_equal_to(serde_json::json!("Signed"));
+
+            let response = client.get(format!("/api/documents/{}/versions/1", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.into_bytes().await.unwrap()).is_equal_to(b"my document".to_vec());
+
+            let response = client.get(format!("/api/documents/{}/versions/2", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.post(format!("/api/documents/{}", DocId::random()))
+                .header(api_key())
+                .header(ContentType::PDF)
+                .body(b"my signed document")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod reprocess {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_reprocess() {
+            let mut server = Server::new().await;
+
+            let id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut fragment = staging.write(Kind::other("original.pdf")).await.unwrap();
+                fragment.write_all(b"my original").await.unwrap();
+                fragment.commit().await.unwrap();
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"my document").await.unwrap();
+                document.commit().await.unwrap();
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            // The juicer gets a copy of the original and extracts the text again
+            server.juicer.expect_extract()
+                .times(1)
+                .returning(|bundle| {
+                    assert_eq!(std::fs::read(bundle.path_of(Kind::other("original.pdf"))).unwrap(), b"my original");
+                    std::fs::write(bundle.path_of(Kind::Document), b"my juiced document").unwrap();
+                    std::fs::write(bundle.path_of(Kind::Plaintext), b"my better plaintext").unwrap();
+                    Ok(())
+                });
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/reprocess/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let bundle = repository.inbox().get(id).await.unwrap();
+            assert_that!(bundle.read_plaintext().await.unwrap()).is_equal_to("my better plaintext".to_string());
+            assert_that!(tokio::fs::read(bundle.path_of(Kind::Document)).await.unwrap()).is_equal_to(b"my document".to_vec());
+
+            let response = client.post(format!("/api/reprocess/{}", DocId::random()))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod attachments {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_attach() {
+            let mut server = Server::new().await;
+            server.transcription = Some(crate::config::Transcription {
+                command: vec![
+                    String::from("sh"),
+                    String::from("-c"),
+                    String::from(r#"test -f "$1" && echo 'Cancel the contract by March'"#),
+                    String::from("sh"),
+                ],
+                timeout: 10,
+            });
+
+            let id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/attachments/{}/memo.ogg", id))
+                .header(api_key())
+                .header(ContentType::new("audio", "ogg"))
+                .body(b"OggS my memo".as_ref())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["fragment"].as_str()).is_equal_to(Some("memo.ogg"));
+            assert_that!(response["transcript"].as_str()).is_equal_to(Some("memo.ogg.transcript.txt"));
+
+            let bundle = repository.inbox().get(id).await.unwrap();
+            assert_that!(tokio::fs::read_to_string(bundle.path_of(Kind::other("memo.ogg.transcript.txt"))).await.unwrap())
+                .is_equal_to("Cancel the contract by March".to_string());
+
+            // The audio is served as playable fragment
+            let response = client.get(format!("/api/inbox/{}/memo.ogg", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.content_type()).is_equal_to(Some(ContentType::new("audio", "ogg")));
+            assert_that!(response.into_bytes().await).is_equal_to(Some(b"OggS my memo".to_vec()));
+
+            // Other content and existing fragments are refused
+            let response = client.post(format!("/api/attachments/{}/memo.ogg", id))
+                .header(api_key())
+                .header(ContentType::PDF)
+                .body(b"%PDF-1.7".as_ref())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.post(format!("/api/attachments/{}/metadata", id))
+                .header(api_key())
+                .header(ContentType::new("audio", "ogg"))
+                .body(b"OggS".as_ref())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.post(format!("/api/attachments/{}/memo.ogg", DocId::random()))
+                .header(api_key())
+                .header(ContentType::new("audio", "ogg"))
+                .body(b"OggS".as_ref())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_attach_file() {
+            let mut server = Server::new().await;
+            server.index.expect_index()
+                .times(1)
+                .returning(|_| Ok(()));
+
+            let id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap()
+                    .archive().await.unwrap()
+                    .id()
+            };
+
+            let repository = server.repository.clone();
+            let client = server.client().await;
+
+            let response = client.post(format!("/api/attachments/{}/invoice.xml", id))
+                .header(api_key())
+                .header(ContentType::XML)
+                .body(br#"<?xml version="1.0"?><Invoice><ID>RE-4711</ID></Invoice>"#.as_ref())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["fragment"].as_str()).is_equal_to(Some("invoice.xml"));
+            assert_that!(response["text"].as_str()).is_equal_to(Some("invoice.xml.text.txt"));
+            assert_that!(response["transcript"].is_null()).is_true();
+
+            let bundle = repository.archive().get(id).await.unwrap();
+            assert_that!(tokio::fs::read_to_string(bundle.path_of(Kind::other("invoice.xml.text.txt"))).await.unwrap())
+                .is_equal_to(String::from("RE-4711"));
+
+            // Files without text are stored without being indexed again
+            let response = client.post(format!("/api/attachments/{}/photo.png", id))
+                .header(api_key())
+                .header(ContentType::PNG)
+                .body(b"\x89PNG\r\n\x1a\n".as_ref())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["text"].is_null()).is_true();
+
+            let response = client.post(format!("/api/attachments/{}/document.pdf", id))
+                .header(api_key())
+                .header(ContentType::PDF)
+                .body(b"%PDF-1.7".as_ref())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+        }
+    }
+
+    mod fragments {
+        use tokio::io::AsyncWriteExt;
+
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_fragments() {
+            let server = Server::new().await;
+
+            let id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                let mut fragment = staging.write(Kind::other("juicer.log")).await.unwrap();
+                fragment.write_all(b"my log").await.unwrap();
+                fragment.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap()
+                    .archive().await.unwrap()
+                    .id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/fragments/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            let fragments = response["fragments"].as_array().unwrap();
+            let log = fragments.iter()
+                .find(|fragment| fragment["name"].as_str() == Some("juicer.log"))
+                .unwrap();
+            assert_that!(log["kind"].as_str()).is_equal_to(Some("other"));
+            assert_that!(log["sha256"].as_str().map(str::len)).is_equal_to(Some(64));
+
+            let plaintext = fragments.iter()
+                .find(|fragment| fragment["name"].as_str() == Some("document.txt"))
+                .unwrap();
+            assert_that!(plaintext["kind"].as_str()).is_equal_to(Some("plaintext"));
+
+            let response = client.get(format!("/api/fragments/{}/juicer.log", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.into_bytes().await).is_equal_to(Some(b"my log".to_vec()));
+
+            // Well-known fragments are served by the archive
+            let response = client.get(format!("/api/fragments/{}/document.txt", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.get(format!("/api/fragments/{}/missing.log", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.get(format!("/api/fragments/{}", DocId::random()))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod export {
+        use std::io::Read;
+
+        use tokio::io::AsyncWriteExt;
+
+        use crate::export::{Manifest, MANIFEST};
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_export() {
+            let server = Server::new().await;
+
+            let id = {
+                let staging = server.repository.stage().await.unwrap();
+
+                let mut document = staging.write(Kind::Document).await.unwrap();
+                document.write_all(b"my document").await.unwrap();
+                document.commit().await.unwrap();
+                let mut plaintext = staging.write(Kind::Plaintext).await.unwrap();
+                plaintext.write_all(b"my document plaintext").await.unwrap();
+                plaintext.commit().await.unwrap();
+                Metadata::new().save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+
+                *staging.create().await.unwrap().id()
+            };
+
+            let client = server.client().await;
+
+            let response = client.get(format!("/api/export?ids={}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let data = response.into_bytes().await.unwrap();
+            let mut archive = tar::Archive::new(data.as_slice());
+            let mut entries = archive.entries().unwrap()
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let path = entry.path().unwrap().to_string_lossy().into_owned();
+                    let mut data = Vec::new();
+                    entry.read_to_end(&mut data).unwrap();
+                    (path, data)
+                })
+                .collect::<Vec<_>>();
+
+            let (path, manifest) = entries.remove(0);
+            assert_that!(path.as_str()).is_equal_to(MANIFEST);
+
+            let manifest: Manifest = serde_json::from_slice(&manifest).unwrap();
+            assert_that!(manifest.docs).has_length(1);
+            assert_that!(manifest.docs[0].doc.id).is_equal_to(id);
+
+            assert_that!(entries).contains((format!("{}/document.txt", id), b"my document plaintext".to_vec()));
+
+            let response = client.get(format!("/api/export?ids={}", DocId::random()))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
+
+    mod preferences {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_set_remove() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.put("/api/preferences/sort")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"field": "uploaded", "order": "desc"}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/preferences")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["sort"]["order"].as_str()).is_equal_to(Some("desc"));
+
+            let response = client.delete("/api/preferences/sort")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.delete("/api/preferences/sort")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_settings_import_export() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.put("/api/settings")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(json_payload!({
+                    "version": 1,
+                    "preferences": {
+                        "theme": "dark",
+                    },
+                }))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = client.get("/api/settings")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_json_eq!(response.into_bytes().await.unwrap(), {
+                "version": 1,
+                "preferences": {
+                    "theme": "dark",
                 },
-                "suggestions": ["suggestion"],
             });
         }
+    }
+
+    mod admin {
+        use super::*;
 
         #[tokio::test]
-        async fn test_delete() {
+        async fn test_status() {
             let server = Server::new().await;
+            let client = server.client().await;
 
-            let doc_id = {
-                let staging = server.repository.stage().await.unwrap();
-
-                staging.write(Kind::Document).await.unwrap()
-                    .write_all(b"").await.unwrap();
-
-                staging.write(Kind::Plaintext).await.unwrap()
-                    .write_all(b"my document plaintext").await.unwrap();
-
-                Metadata {
-                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
-                    ..Metadata::new()
-                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let response = client.get("/api/admin/status")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
 
-                *staging.create().await.unwrap().id()
-            };
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["bundles"]["inbox"].as_u64()).is_equal_to(Some(0));
+            assert_that!(response["jobs"]["failed"].as_u64()).is_equal_to(Some(0));
+            assert_that!(response["disk"]["total"].as_u64().unwrap()).is_greater_than(0);
+            assert_that!(response["errors"].as_array().map(Vec::len)).is_equal_to(Some(0));
+        }
 
+        #[tokio::test]
+        async fn test_reload() {
+            let server = Server::new().await;
             let client = server.client().await;
 
-            let response = client.delete(format!("/api/inbox/{}", doc_id))
+            let response = client.post("/api/admin/reload")
                 .header(api_key())
                 .dispatch().await;
-
             assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["reloaded"]).is_equal_to(serde_json::json!(["rules"]));
         }
+    }
+
+    mod graphql {
+        use crate::meta::Metadata;
+        use crate::proto::model::{DocId, Kind, Relation, RelationKind};
+
+        use super::*;
 
         #[tokio::test]
-        async fn test_archive() {
-            let mut server = Server::new().await;
+        async fn test_query() {
+            let server = Server::new().await;
+            let repository = server.repository.clone();
 
-            let doc_id = {
-                let staging = server.repository.stage().await.unwrap();
+            let staging = repository.stage().await.unwrap();
+            let mut metadata = Metadata::new();
+            metadata.title = Some(String::from("Order"));
+            metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let order = *staging.create().await.unwrap().id();
 
-                staging.write(Kind::Document).await.unwrap()
-                    .write_all(b"").await.unwrap();
+            let staging = repository.stage().await.unwrap();
+            let mut metadata = Metadata::new();
+            metadata.title = Some(String::from("Invoice"));
+            metadata.relations.insert(Relation { kind: RelationKind::InvoiceFor, target: order });
+            metadata.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let invoice = *staging.create().await.unwrap().id();
 
-                staging.write(Kind::Plaintext).await.unwrap()
-                    .write_all(b"my document plaintext").await.unwrap();
+            let client = server.client().await;
 
-                Metadata {
-                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
-                    ..Metadata::new()
-                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let query = format!(r#"{{ document(id: "{}") {{ title related {{ kind document {{ id title }} }} }} }}"#, invoice);
+            let response = client.post("/api/graphql")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "query": query }).to_string())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
 
-                *staging.create().await.unwrap().id()
-            };
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["errors"].is_null()).is_true();
+            assert_that!(response["data"]).is_equal_to(serde_json::json!({
+                "document": {
+                    "title": "Invoice",
+                    "related": [
+                        { "kind": "INVOICE_FOR", "document": { "id": order.to_string(), "title": "Order" } },
+                    ],
+                },
+            }));
 
-            server.index.expect_index()
-                .withf(move |bundle| bundle.id() == &doc_id)
-                .returning(|_| Ok(()));
+            // Unknown documents resolve to nothing
+            let query = format!(r#"{{ document(id: "{}") {{ title }} }}"#, DocId::random());
+            let response = client.post("/api/graphql")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "query": query }).to_string())
+                .dispatch().await;
 
-            server.suggester.expect_train()
-                .with(mockall::predicate::eq("my document plaintext"),
-                      mockall::predicate::eq(HashSet::from_iter(vec![Label::from("expected")])))
-                .returning(|_, _| Ok(()));
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["data"]["document"].is_null()).is_true();
+        }
 
+        #[tokio::test]
+        async fn test_limits() {
+            let server = Server::new().await;
             let client = server.client().await;
 
-            let response = client.post(format!("/api/inbox/{}", doc_id))
+            let query = |query: String| client.post("/api/graphql")
                 .header(api_key())
-                .body(json_payload!({
-                    "labels": [ "expected" ],
-                    "properties": {
-                        "title": "My little Test",
-                        "source": "testsuite",
-                    }
-                }))
-                .dispatch().await;
+                .header(ContentType::JSON)
+                .body(serde_json::json!({ "query": query }).to_string())
+                .dispatch();
 
-            assert_that!(response.status()).is_equal_to(Status::Ok);
+            // Following relations back and forth is cut off by the depth
+            let nested = (0..6).fold(String::from("id"), |inner, _| format!("related {{ document {{ {} }} }}", inner));
+            let response = query(format!(r#"{{ document(id: "{}") {{ {} }} }}"#, DocId::random(), nested)).await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["errors"].is_array()).is_true();
+            assert_that!(response["data"].is_null()).is_true();
+
+            // Selecting fields over and over by aliases is cut off by the complexity
+            let aliased = (0..300).map(|index| format!("l{}: labels {{ name }}", index)).collect::<Vec<_>>().join(" ");
+            let response = query(format!("{{ {} }}", aliased)).await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["errors"].is_array()).is_true();
+            assert_that!(response["data"].is_null()).is_true();
+
+            // Queries within the limits are resolved
+            let response = query(String::from("{ labels { name } }")).await;
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["errors"].is_null()).is_true();
         }
     }
 
-    mod archive {
-        use chrono::{DateTime, NaiveDateTime, Utc};
-        use futures::{stream, StreamExt};
-        use serde_json::json;
-        use tokio::io::AsyncWriteExt;
+    mod openapi {
+        use super::*;
 
-        use crate::index::SearchResponse;
-        use crate::meta::Metadata;
-        use crate::proto::model::Kind;
+        #[tokio::test]
+        async fn test_spec() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.get("/api/openapi.json")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["openapi"].as_str()).is_equal_to(Some("3.0.3"));
+            assert_that!(response["servers"][0]["url"].as_str()).is_equal_to(Some("/api/v1"));
 
+            let inbox = &response["paths"]["/inbox/{id}"];
+            assert_that!(inbox["get"]["parameters"][0]["name"].as_str()).is_equal_to(Some("id"));
+            assert_that!(inbox["get"]["responses"]["200"]["content"]["application/json"]["schema"]["$ref"].as_str())
+                .is_equal_to(Some("#/components/schemas/GetResponse"));
+            assert_that!(inbox["delete"].is_object()).is_true();
+
+            let schemas = &response["components"]["schemas"];
+            assert_that!(schemas["DocInfo"].is_object()).is_true();
+            assert_that!(schemas["InboxListResponse"].is_object()).is_true();
+            assert_that!(schemas["LabelsListResponse"].is_object()).is_true();
+        }
+    }
+
+    mod versions {
         use super::*;
 
         #[tokio::test]
-        async fn test_get_bundle() {
+        async fn test_list() {
             let server = Server::new().await;
+            let client = server.client().await;
 
-            let doc_id = {
-                let staging = server.repository.stage().await.unwrap();
+            let response = client.get("/api/versions")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.headers().get_one("Deprecation")).is_none();
 
-                staging.write(Kind::Document).await.unwrap()
-                    .write_all(b"").await.unwrap();
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["current"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["versions"][0]["path"].as_str()).is_equal_to(Some("/api/v1"));
+            assert_that!(response["versions"][0]["deprecated"].as_bool()).is_equal_to(Some(false));
+        }
 
-                staging.write(Kind::Plaintext).await.unwrap()
-                    .write_all(b"my document plaintext").await.unwrap();
+        #[tokio::test]
+        async fn test_versioned() {
+            let server = Server::new().await;
+            let client = server.client().await;
 
-                Metadata {
-                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
-                    ..Metadata::new()
-                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let response = client.get("/api/v1/inbox")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.headers().get_one("API-Version")).is_equal_to(Some("1"));
+            assert_that!(response.headers().get_one("Deprecation")).is_none();
 
-                let inboxed = staging.create().await.unwrap();
-                *inboxed.archive().await.unwrap().id()
-            };
+            // Scopes apply to versioned paths alike
+            let response = client.get("/api/v1/admin/status")
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Unauthorized);
+        }
 
+        #[tokio::test]
+        async fn test_unversioned() {
+            let server = Server::new().await;
             let client = server.client().await;
 
-            let response = client.get(format!("/api/archive/{}", doc_id))
+            let response = client.get("/api/inbox")
                 .header(api_key())
                 .dispatch().await;
-
             assert_that!(response.status()).is_equal_to(Status::Ok);
-
-            assert_json_eq!(response.into_bytes().await.unwrap(), {
-                "id": doc_id.to_string(),
-                "metadata": {
-                    "uploaded": "2001-09-09T01:46:40Z",
-                    "archived": (),
-                    "title": (),
-                    "pages": 0,
-                    "labels": [],
-                    "properties": {},
-                }
-            });
+            assert_that!(response.headers().get_one("API-Version")).is_equal_to(Some("1"));
+            assert_that!(response.headers().get_one("Deprecation")).is_equal_to(Some("true"));
+            assert_that!(response.headers().get_one("Link")).is_equal_to(Some("</api/v1/inbox>; rel=\"successor-version\""));
         }
 
         #[tokio::test]
-        async fn test_get_fragment() {
+        async fn test_unknown_version() {
             let server = Server::new().await;
+            let client = server.client().await;
 
-            let doc_id = {
-                let staging = server.repository.stage().await.unwrap();
+            let response = client.get("/api/v2/inbox")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+    }
 
-                staging.write(Kind::Document).await.unwrap()
-                    .write_all(b"").await.unwrap();
+    mod repositories {
+        use crate::meta::Metadata;
+        use crate::proto::model::Kind;
 
-                staging.write(Kind::Plaintext).await.unwrap()
-                    .write_all(b"my document plaintext").await.unwrap();
+        use super::*;
 
-                Metadata {
-                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
-                    ..Metadata::new()
-                }.save(staging.write(Kind::Metadata).await.unwrap()).await.unwrap();
+        #[tokio::test]
+        async fn test_scoped() {
+            let mut server = Server::new().await;
 
-                let inboxed = staging.create().await.unwrap();
-                *inboxed.archive().await.unwrap().id()
-            };
+            let work = crate::repository::Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+            let bundle = work.stage().await.unwrap();
+            Metadata::new().save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *bundle.create().await.unwrap().id();
 
+            server.repositories.push((String::from("work"), work, crate::index::MockIndex::new()));
             let client = server.client().await;
 
-            let response = client.get(format!("/api/archive/{}/plaintext", doc_id))
+            let response = client.get("/api/v1/repositories")
                 .header(api_key())
                 .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_json_eq!(response.into_bytes().await.unwrap(), { "repositories": ["work"] });
 
+            let response = client.get("/api/v1/repositories/work/inbox")
+                .header(api_key())
+                .dispatch().await;
             assert_that!(response.status()).is_equal_to(Status::Ok);
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["docs"][0]["id"].as_str()).is_equal_to(Some(id.to_string().as_str()));
 
-            assert_that!(response.into_bytes().await).is_some().is_equal_to(b"my document plaintext".to_vec());
+            // The default repository is served unscoped
+            let response = client.get(format!("/api/v1/inbox/{}", id))
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.get("/api/repositories/private/inbox")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
         }
 
         #[tokio::test]
-        async fn test_search() {
+        async fn test_scoped_registers() {
             let mut server = Server::new().await;
 
-            // Create bundles in inbox (with a short delay between each to have a unique timestamp)
-            let ids = stream::iter(0..10usize).then(|_| async {
-                let bundle = server.repository.stage().await.unwrap();
+            let work = crate::repository::Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+            server.repositories.push((String::from("work"), work, crate::index::MockIndex::new()));
+            let client = server.client().await;
 
-                Metadata {
-                    uploaded: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_000_000_000, 0), Utc),
-                    ..Metadata::new()
-                }.save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let response = client.put("/api/repositories/work/correspondents/ACME%20Corp")
+                .header(api_key())
+                .header(ContentType::JSON)
+                .body(r#"{"aliases": [], "labels": []}"#)
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
 
-                let bundle = bundle.create().await.unwrap();
-                let bundle = bundle.archive().await.unwrap();
+            let response = client.get("/api/repositories/work/correspondents/ACME%20Corp")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
 
-                *bundle.id()
-            }).collect::<Vec<_>>().await;
+            // Correspondents are registered for the named repository only
+            let response = client.get("/api/correspondents/ACME%20Corp")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
 
-            server.index.expect_search()
-                .with(mockall::predicate::eq("testquery"))
-                .return_once({
-                    let ids = ids.clone();
-                    move |_| Ok(SearchResponse {
-                        count: 387,
-                        docs: ids,
-                    })
-                });
+            // Shares and document requests hand out links to the default repository only
+            let response = client.get("/api/repositories/work/shares")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+
+            let response = client.get("/api/repositories/work/requests")
+                .header(api_key())
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NotFound);
+        }
+
+        #[tokio::test]
+        async fn test_search() {
+            let mut server = Server::new().await;
+
+            let work = crate::repository::Repository::with_path(tempfile::tempdir().unwrap()).await.unwrap();
+            let bundle = work.stage().await.unwrap();
+            Metadata::new().save(bundle.write(Kind::Metadata).await.unwrap()).await.unwrap();
+            let id = *bundle.create().await.unwrap().archive().await.unwrap().id();
+
+            // Searches are answered by the index of the named repository, the default index is never asked
+            let mut index = crate::index::MockIndex::new();
+            index.expect_search()
+                .times(1)
+                .return_once(move |_, _| Ok(crate::index::SearchResponse {
+                    count: 1,
+                    docs: vec![id],
+                    snippets: HashMap::new(),
+                }));
 
+            server.repositories.push((String::from("work"), work, index));
             let client = server.client().await;
 
-            let response = client.get("/api/archive?query=testquery")
+            let response = client.get("/api/repositories/work/archive?query=invoice")
                 .header(api_key())
                 .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::Ok);
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["count"].as_u64()).is_equal_to(Some(1));
+            assert_that!(response["docs"][0]["id"].as_str()).is_equal_to(Some(id.to_string().as_str()));
+        }
+    }
+
+    mod proxy {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_cors_preflight() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.options("/api/inbox")
+                .header(Header::new("Origin", "https://app.example.com"))
+                .header(Header::new("Access-Control-Request-Method", "GET"))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::NoContent);
+            assert_that!(response.headers().get_one("Access-Control-Allow-Origin")).is_equal_to(Some("https://app.example.com"));
+            assert_that!(response.headers().get_one("Access-Control-Allow-Headers")).is_some();
 
+            let response = client.get("/api/inbox")
+                .header(Header::new("Origin", "https://evil.example.com"))
+                .header(api_key())
+                .dispatch().await;
             assert_that!(response.status()).is_equal_to(Status::Ok);
+            assert_that!(response.headers().get_one("Access-Control-Allow-Origin")).is_none();
+        }
 
-            assert_json_eq!(response.into_bytes().await.unwrap(), {
-                "count": 387,
-                "docs": ids.iter().map(|id| json!({
-                    "id": id,
-                    "metadata": {
-                        "uploaded": "2001-09-09T01:46:40Z",
-                        "archived": (),
-                        "title": (),
-                        "pages": 0,
-                        "labels": [],
-                        "properties": {},
-                    }
-                })).collect::<Vec<_>>(),
-            });
+        #[tokio::test]
+        async fn test_forwarded() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.get("/d/42")
+                .remote("10.0.0.1:4711".parse().unwrap())
+                .header(Header::new("X-Forwarded-Proto", "https"))
+                .header(Header::new("X-Forwarded-Host", "adacta.example.com"))
+                .header(Header::new("X-Forwarded-Prefix", "/adacta/"))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::SeeOther);
+            assert_that!(response.headers().get_one("Location")).is_equal_to(Some("https://adacta.example.com/adacta/login?redirect=/adacta/d/42"));
+
+            // Headers of untrusted peers are ignored
+            let response = client.get("/d/42")
+                .remote("10.0.0.2:4711".parse().unwrap())
+                .header(Header::new("X-Forwarded-Host", "adacta.example.com"))
+                .header(Header::new("X-Forwarded-Prefix", "/adacta/"))
+                .dispatch().await;
+            assert_that!(response.headers().get_one("Location")).is_equal_to(Some("/login?redirect=/d/42"));
+        }
+
+        #[tokio::test]
+        async fn test_forwarded_client() {
+            let server = Server::new().await;
+            let client = server.client().await;
+
+            let response = client.post("/api/auth/login")
+                .remote("10.0.0.1:4711".parse().unwrap())
+                .header(Header::new("Forwarded", "for=192.0.2.60;proto=https"))
+                .header(ContentType::JSON)
+                .body(json_payload!({"password": "wrong"}))
+                .dispatch().await;
+            assert_that!(response.status()).is_equal_to(Status::BadRequest);
+
+            let response = client.get("/api/admin/status")
+                .header(api_key())
+                .dispatch().await;
+
+            let response = serde_json::from_slice::<serde_json::Value>(&response.into_bytes().await.unwrap()).unwrap();
+            assert_that!(response["failed_logins"][0]["address"].as_str()).is_equal_to(Some("192.0.2.60"));
         }
     }
 }
@@ -14,6 +14,11 @@ pub enum Auth {
         username: String,
         password: String,
     },
+
+    /// An API token issued by the server, which is sent as bearer token
+    Token {
+        token: String,
+    },
 }
 
 impl Auth {
@@ -24,6 +29,10 @@ impl Auth {
     pub fn api_key(username: String, password: String) -> Self {
         return Self::ApiKey { username, password };
     }
+
+    pub fn token(token: String) -> Self {
+        return Self::Token { token };
+    }
 }
 
 pub(super) enum Session {
@@ -41,7 +50,7 @@ impl Session {
         match auth {
             Auth::Login { password } => {
                 let response = client.post(&format!("{}/auth/login", base_url))
-                    .json(&AuthRequest { password })
+                    .json(&AuthRequest { password, code: None, assertion: None })
                     .send().await?
                     .error_for_status()?;
 
@@ -55,6 +64,11 @@ impl Session {
             Auth::ApiKey { username, password } => {
                 return Ok(Self::ApiKey { username, password });
             }
+
+            Auth::Token { token } => {
+                // API tokens are never renewed by the server
+                return Ok(Self::Token { token });
+            }
         }
     }
 
@@ -7,7 +7,7 @@ use tokio_util::codec::{BytesCodec, FramedRead, FramedWrite};
 pub use auth::Auth;
 use auth::Session;
 
-use crate::proto::api::{archive, inbox, upload};
+use crate::proto::api::{archive, bulk, inbox, resolve, upload};
 
 pub mod auth;
 
@@ -20,7 +20,7 @@ pub struct Client {
 impl Client {
     pub async fn new(base_url: &str, auth: Auth) -> Result<Self> {
         let base_url = if !base_url.ends_with('/') {
-            Url::parse(&format!("{}/api/", base_url))
+            Url::parse(&format!("{}/api/v1/", base_url))
         } else {
             Url::parse(base_url)
         }?;
@@ -47,13 +47,21 @@ impl Client {
         return Ok(request);
     }
 
-    pub async fn upload(&mut self, r: impl AsyncRead + Send + Sync + 'static) -> Result<upload::UploadResponse> {
+    /// Uploads a document, the server determines the format of documents other than PDFs by the filename.
+    pub async fn upload(&mut self,
+                        r: impl AsyncRead + Send + Sync + 'static,
+                        filename: Option<&str>,
+                        content_type: &str) -> Result<upload::UploadResponse> {
         let request = self.request(Method::POST, "/upload")?;
+        let request = match filename {
+            Some(filename) => request.query(&[("filename", filename)]),
+            None => request,
+        };
 
         let r = FramedRead::new(r, BytesCodec::new());
         let r = Body::wrap_stream(r);
         let request = request.body(r)
-            .header(reqwest::header::CONTENT_TYPE, "application/pdf");
+            .header(reqwest::header::CONTENT_TYPE, content_type);
 
 
         let response = self.session.send(request).await?
@@ -143,4 +151,24 @@ impl Client {
 
         return Ok(response.json().await?);
     }
-}
\ No newline at end of file
+
+    /// Finds a document in the inbox or the archive by its ID or a code printed on paper.
+    pub async fn resolve(&mut self, code: &str) -> Result<resolve::ResolveResponse> {
+        let request = self.request(Method::GET, &format!("/resolve/{}", code))?;
+
+        let response = self.session.send(request).await?
+            .error_for_status()?;
+
+        return Ok(response.json().await?);
+    }
+
+    pub async fn bulk(&mut self, data: &bulk::BulkRequest) -> Result<bulk::BulkResponse> {
+        let request = self.request(Method::POST, "/bulk")?;
+        let request = request.json(data);
+
+        let response = self.session.send(request).await?
+            .error_for_status()?;
+
+        return Ok(response.json().await?);
+    }
+}
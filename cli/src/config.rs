@@ -22,6 +22,12 @@ pub enum Auth {
         #[serde(rename = "password")]
         password: String,
     },
+
+    #[serde(rename = "token")]
+    Token {
+        #[serde(rename = "token")]
+        token: String,
+    },
 }
 
 impl Auth {
@@ -32,6 +38,10 @@ impl Auth {
     pub fn api_key(username: String, password: String) -> Self {
         return Self::ApiKey { username, password };
     }
+
+    pub fn token(token: String) -> Self {
+        return Self::Token { token };
+    }
 }
 
 impl Into<client::Auth> for Auth {
@@ -39,6 +49,7 @@ impl Into<client::Auth> for Auth {
         return match self {
             Self::Login { password } => client::Auth::login(password),
             Self::ApiKey { username, password } => client::Auth::api_key(username, password),
+            Self::Token { token } => client::Auth::token(token),
         };
     }
 }
@@ -89,12 +100,13 @@ impl Config {
 pub fn exec(matches: &clap::ArgMatches<'_>) -> Result<Box<dyn Output>> {
     let target = matches.value_of("target").expect("Target required");
     let username = matches.value_of("username");
-    let password = matches.value_of("password").expect("Password required");
+    let password = matches.value_of("password");
 
-    let auth = if let Some(username) = username {
-        Auth::api_key(username.to_string(), password.to_string())
-    } else {
-        Auth::login(password.to_string())
+    let auth = match (matches.value_of("token"), username, password) {
+        (Some(token), _, _) => Auth::token(token.to_string()),
+        (None, Some(username), Some(password)) => Auth::api_key(username.to_string(), password.to_string()),
+        (None, None, Some(password)) => Auth::login(password.to_string()),
+        (None, _, None) => unreachable!("Password or token required"),
     };
 
     let config = Config::with(target.to_owned(), auth);
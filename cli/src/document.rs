@@ -0,0 +1,84 @@
+use std::io::Write;
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::client::Client;
+use crate::output::{Output, SimpleOutput};
+use crate::proto::api::bulk::{BulkRequest, BulkResponse, Operation};
+use crate::proto::api::resolve::ResolveResponse;
+use crate::proto::model::{DocId, Label};
+
+/// Shows a document from the inbox or the archive.
+pub async fn show(matches: &clap::ArgMatches<'_>, client: &mut Client) -> Result<Box<dyn Output>> {
+    let id = matches.value_of("id").expect("Required ID missing");
+
+    let response = client.resolve(id).await?;
+
+    return Ok(Box::new(response));
+}
+
+/// Adds labels to a document or removes the ones prefixed with a minus.
+pub async fn label(matches: &clap::ArgMatches<'_>, client: &mut Client) -> Result<Box<dyn Output>> {
+    let id = matches.value_of("id").expect("Required ID missing");
+    let id: DocId = client.resolve(id).await?.doc.id;
+
+    let mut results = Vec::new();
+    for label in matches.values_of("labels").expect("Required labels missing") {
+        let operation = match label.strip_prefix('-') {
+            Some(label) => Operation::RemoveLabel { label: Label::from(label) },
+            None => Operation::AddLabel { label: Label::from(label.trim_start_matches('+')) },
+        };
+
+        let response = client.bulk(&BulkRequest { ids: vec![id], operation }).await?;
+        results.push(response);
+    }
+
+    return Ok(Box::new(results));
+}
+
+/// Downloads a fragment of a document from the inbox or the archive.
+pub async fn download(matches: &clap::ArgMatches<'_>, client: &mut Client) -> Result<Box<dyn Output>> {
+    let id = matches.value_of("id").expect("Required ID missing");
+    let kind = matches.value_of("kind").expect("Required kind missing");
+
+    let doc = client.resolve(id).await?.doc;
+    let id = doc.id.to_string();
+
+    let target = matches.value_of("target").map(str::to_string)
+        .unwrap_or_else(|| match kind {
+            "document" => format!("{}.pdf", id),
+            _ => format!("{}.{}", id, kind),
+        });
+
+    let archived = doc.metadata.archived.is_some();
+    match (target.as_str(), archived) {
+        ("-", true) => client.archive_fragment(&id, kind, tokio::io::stdout()).await?,
+        ("-", false) => client.inbox_fragment(&id, kind, tokio::io::stdout()).await?,
+        (target, true) => client.archive_fragment(&id, kind, tokio::fs::File::create(target).await?).await?,
+        (target, false) => client.inbox_fragment(&id, kind, tokio::fs::File::create(target).await?).await?,
+    };
+
+    return Ok(Box::new(()));
+}
+
+impl SimpleOutput for ResolveResponse {
+    fn to_text(&self, w: &mut dyn Write) -> Result<()> {
+        SimpleOutput::to_text(&self.doc, w)?;
+
+        return Ok(());
+    }
+}
+
+impl SimpleOutput for Vec<BulkResponse> {
+    fn to_text(&self, w: &mut dyn Write) -> Result<()> {
+        for result in self.iter().flat_map(|response| response.results.iter()) {
+            match &result.error {
+                Some(error) => writeln!(w, "{} {}: {}", "❌".bright_red(), result.id.to_string().cyan(), error.red())?,
+                None => writeln!(w, "{} {}", "✓".bright_green(), result.id.to_string().cyan())?,
+            }
+        }
+
+        return Ok(());
+    }
+}
\ No newline at end of file
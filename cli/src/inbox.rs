@@ -7,6 +7,7 @@ use colored::Colorize;
 use crate::client::Client;
 use crate::output::{Output, SimpleOutput};
 use crate::proto::api::inbox::{ArchiveRequest, GetResponse, ListResponse};
+use crate::proto::model::PropertyValue;
 
 pub async fn exec(matches: &clap::ArgMatches<'_>, client: &mut Client) -> Result<Box<dyn Output>> {
     return match matches.subcommand() {
@@ -76,12 +77,17 @@ pub async fn archive(matches: &clap::ArgMatches<'_>, client: &mut Client) -> Res
 
     // TODO: Custom typed parsers in clap?
     let properties = matches.values_of("labels")
-        .map(|properties| properties.map(|property| property.split_once('=').unwrap_or((property, ""))).map(|(k, v)| (k.to_string(), v.to_string())).collect())
+        .map(|properties| properties.map(|property| property.split_once('=').unwrap_or((property, ""))).map(|(k, v)| (k.to_string(), PropertyValue::parse(v))).collect())
         .unwrap_or_else(HashMap::default);
 
     let data = ArchiveRequest {
         labels,
         properties,
+        shared: HashSet::default(),
+        correspondent: None,
+        belongs_to: matches.value_of("belongs-to").map(String::from),
+        due: None,
+        date: None,
     };
 
     client.inbox_archive(id, &data).await?;
@@ -16,6 +16,7 @@ mod client;
 mod upload;
 mod inbox;
 mod archive;
+mod document;
 
 #[tokio::main]
 async fn main() {
@@ -63,11 +64,55 @@ async fn main() {
                 .short("p")
                 .help("The login or application key password")
                 .takes_value(true)
-                .required(true)))
+                .required_unless("token"))
+            .arg(Arg::with_name("token")
+                .long("token")
+                .help("An API token issued by the server")
+                .takes_value(true)
+                .conflicts_with_all(&["username", "password"])))
         .subcommand(SubCommand::with_name("upload")
             .about("Uploads a document")
-            .arg(Arg::with_name("pdf")
-                .help("The PDF document to upload")
+            .arg(Arg::with_name("document")
+                .help("The PDF, office document or scan to upload")
+                .takes_value(true)
+                .required(true)))
+        .subcommand(SubCommand::with_name("show")
+            .about("Shows a document from the inbox or the archive")
+            .arg(Arg::with_name("id")
+                .help("The document ID or a code printed on paper")
+                .takes_value(true)
+                .required(true)))
+        .subcommand(SubCommand::with_name("label")
+            .about("Adds labels to a document or removes them")
+            .arg(Arg::with_name("id")
+                .help("The document ID or a code printed on paper")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("labels")
+                .help("The labels to add, labels prefixed with - are removed")
+                .takes_value(true)
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .required(true)))
+        .subcommand(SubCommand::with_name("download")
+            .about("Downloads a fragment of a document from the inbox or the archive")
+            .arg(Arg::with_name("id")
+                .help("The document ID or a code printed on paper")
+                .takes_value(true)
+                .required(true))
+            .arg(Arg::with_name("kind")
+                .help("The fragment kind")
+                .takes_value(true)
+                .default_value("document"))
+            .arg(Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .help("The target filename or - for standard output")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("search")
+            .about("Search for documents in the archive")
+            .arg(Arg::with_name("query")
+                .help("The search query")
                 .takes_value(true)
                 .required(true)))
         .subcommand(SubCommand::with_name("inbox")
@@ -115,7 +160,12 @@ async fn main() {
                 .arg(Arg::with_name("properties")
                     .help("The labels to put on the document")
                     .takes_value(true)
-                    .multiple(true))))
+                    .multiple(true))
+                .arg(Arg::with_name("belongs-to")
+                    .short("b")
+                    .long("belongs-to")
+                    .help("The household member the document belongs to")
+                    .takes_value(true))))
         .subcommand(SubCommand::with_name("archive")
             .about("Access your document archive")
             .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -176,6 +226,10 @@ async fn exec(matches: &clap::ArgMatches<'_>) -> Result<Box<dyn Output>> {
                 ("upload", Some(matches)) => upload::exec(matches, &mut client).await,
                 ("inbox", Some(matches)) => inbox::exec(matches, &mut client).await,
                 ("archive", Some(matches)) => archive::exec(matches, &mut client).await,
+                ("show", Some(matches)) => document::show(matches, &mut client).await,
+                ("label", Some(matches)) => document::label(matches, &mut client).await,
+                ("download", Some(matches)) => document::download(matches, &mut client).await,
+                ("search", Some(matches)) => archive::search(matches, &mut client).await,
 
                 _ => unreachable!()
             }
@@ -9,10 +9,18 @@ use crate::output::{Output, SimpleOutput};
 use crate::proto::api::upload::UploadResponse;
 
 pub async fn exec(matches: &clap::ArgMatches<'_>, client: &mut Client) -> Result<Box<dyn Output>> {
-    let pdf = Path::new(matches.value_of_os("pdf").expect("Document missing"));
-    let pdf = tokio::fs::File::open(pdf).await?;
+    let path = Path::new(matches.value_of_os("document").expect("Document missing"));
 
-    let response = client.upload(pdf).await?;
+    // Formats other than PDF are converted by the server, which determines the format by the filename
+    let filename = path.file_name().map(|filename| filename.to_string_lossy().into_owned());
+    let content_type = match path.extension().map(|extension| extension.to_string_lossy().to_lowercase()) {
+        Some(extension) if extension == "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    };
+
+    let document = tokio::fs::File::open(path).await?;
+
+    let response = client.upload(document, filename.as_deref(), content_type).await?;
 
     return Ok(Box::new(response));
 }
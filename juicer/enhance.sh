@@ -2,9 +2,13 @@
 
 set -xe
 
+# OCR languages requested on upload take precedence over the configured ones
+LANGUAGES="$(jq --raw-output '.languages // empty' 'metadata.json')"
+LANGUAGES="${LANGUAGES:-${OCR_LANGUAGES:-eng+deu}}"
+
 # OCR the original pdf
 ocrmypdf \
-  -l eng+deu \
+  -l "${LANGUAGES}" \
   --rotate-pages \
   --deskew \
   --remove-background \
@@ -16,3 +20,10 @@ ocrmypdf \
 
 # Extract the text of the final pdf file
 pdftotext 'document.pdf' > 'document.txt'
+
+# Rate the OCR by recognizing the words of the pages again, as ocrmypdf does not report the confidence
+mkdir -p 'confidence'
+pdftoppm -r 300 -png 'document.pdf' 'confidence/page'
+ls confidence/page-*.png > 'confidence/list.txt'
+tesseract 'confidence/list.txt' 'document' -l "${LANGUAGES}" tsv
+rm -rf 'confidence'
@@ -2,6 +2,25 @@
 
 set -xe
 
+# Convert office documents to PDF, the original is kept as is
+for ORIGINAL in original.docx original.odt original.xlsx; do
+  if [[ -r "${ORIGINAL}" ]]; then
+    soffice -env:UserInstallation=file:///tmp/libreoffice --headless --convert-to pdf "${ORIGINAL}"
+  fi
+done
+
+# Wrap scanned images into a PDF without text, which gets deskewed and OCRed while enhancing
+for ORIGINAL in original.jpg original.jpeg original.png original.tif original.tiff; do
+  if [[ -r "${ORIGINAL}" ]]; then
+    img2pdf "${ORIGINAL}" -o "original.pdf"
+  fi
+done
+
+# Assemble the pages of scans received page by page in the same way, the zero-padded names keep them in order
+if compgen -G 'scan-*' > /dev/null; then
+  img2pdf scan-* -o "original.pdf"
+fi
+
 # Sanity checks
 if [[ ! -r "original.pdf" ]]; then
     echo "Missing original.pdf" >&2
@@ -25,6 +44,15 @@ fi
 # Extract preview
 pdftoppm 'document.pdf' 'preview' -png -f 1 -singlefile
 
+# Extract a preview per page named by the zero-padded page number and a small thumbnail
+pdftoppm 'document.pdf' 'page' -png -r 100
+for PAGE in page-*.png; do
+  NUMBER="${PAGE#page-}"
+  NUMBER="${NUMBER%.png}"
+  mv "${PAGE}" "$(printf 'preview-%04d.png' "$((10#${NUMBER}))")"
+done
+pdftoppm 'document.pdf' 'thumbnail' -png -f 1 -singlefile -scale-to 256
+
 # Extract additional metadata
 # This splits the pdfinfo output by line on first colon (':'), trims the values, filters for empty values and converts to JSON object
 INFO="$(pdfinfo 'document.pdf' | jq --slurp --raw-input '
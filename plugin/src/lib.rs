@@ -0,0 +1,109 @@
+//! Traits for integrations compiled into custom builds of Adacta.
+//!
+//! Plugins are published as crates depending on this one and implementing any of the traits below. A custom build adds
+//! the crates as dependencies of the backend and registers their plugins in its `plugins.rs`. The traits only use the
+//! types of the `adacta-proto` crate, which is re-exported, so plugins do not depend on the internals of the backend.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+pub use adacta_proto as proto;
+
+use proto::model::{DocId, DocInfo, Label, PropertyValue};
+
+/// A document handed to the backend by a source.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub data: Vec<u8>,
+
+    /// Extension of the document format, either `pdf` or one of the office formats converted by the juicer
+    pub extension: String,
+
+    /// Name of the file the document was ingested from, if any
+    pub filename: Option<String>,
+
+    pub labels: HashSet<Label>,
+    pub properties: HashMap<String, PropertyValue>,
+}
+
+/// Accepts documents for ingestion, which are juiced and land in the inbox like uploaded ones.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    async fn submit(&self, document: Document) -> Result<DocId>;
+}
+
+/// Brings documents into the repository, i.e. by polling another service.
+#[async_trait]
+pub trait Source: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Runs the source for the lifetime of the backend, submitting documents to the sink as they are found.
+    async fn run(&self, sink: Arc<dyn Sink>) -> Result<()>;
+}
+
+/// Metadata a processor extracted from a fragment, which is merged into the metadata of the document.
+#[derive(Debug, Clone, Default)]
+pub struct Enrichment {
+    pub labels: HashSet<Label>,
+    pub properties: HashMap<String, PropertyValue>,
+}
+
+/// Enriches the metadata of archived documents by processing their fragments.
+///
+/// Fragments of documents in encryption domains are never passed to processors.
+#[async_trait]
+pub trait Processor: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Returns true if the fragment with the given filename is processed.
+    fn matches(&self, fragment: &str) -> bool;
+
+    async fn process(&self, doc: &DocInfo, fragment: &str, data: &[u8]) -> Result<Enrichment>;
+}
+
+/// A notification about documents, i.e. about them becoming due.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub subject: String,
+
+    /// The notified documents rendered as plain text
+    pub body: String,
+
+    pub docs: Vec<DocId>,
+}
+
+/// Delivers notifications, i.e. to a chat.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// The plugins compiled into a build.
+#[derive(Default, Clone)]
+pub struct Plugins {
+    pub sources: Vec<Arc<dyn Source>>,
+    pub processors: Vec<Arc<dyn Processor>>,
+    pub notifiers: Vec<Arc<dyn Notifier>>,
+}
+
+impl Plugins {
+    pub fn source(&mut self, source: impl Source + 'static) -> &mut Self {
+        self.sources.push(Arc::new(source));
+        return self;
+    }
+
+    pub fn processor(&mut self, processor: impl Processor + 'static) -> &mut Self {
+        self.processors.push(Arc::new(processor));
+        return self;
+    }
+
+    pub fn notifier(&mut self, notifier: impl Notifier + 'static) -> &mut Self {
+        self.notifiers.push(Arc::new(notifier));
+        return self;
+    }
+}
@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The gRPC service is only generated if requested, as it requires protoc to build
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/adacta.proto")?;
+
+    return Ok(());
+}
@@ -1,63 +1,1627 @@
 use std::collections::{HashMap, HashSet};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::model::*;
 
 pub mod auth {
+    use chrono::{DateTime, Utc};
+
     use super::*;
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
     pub struct AuthRequest {
         pub password: String,
+
+        /// TOTP or recovery code, required if two-factor authentication is enabled
+        #[serde(default)]
+        pub code: Option<String>,
+
+        /// Passkey assertion signing the challenge of the previous attempt, accepted instead of the code
+        #[serde(default)]
+        pub assertion: Option<serde_json::Value>,
+    }
+
+    /// Returned along with the login failing as a second factor is required.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SecondFactorResponse {
+        /// Challenge to be signed by one of the passkeys, if any is registered
+        #[serde(default)]
+        pub challenge: Option<serde_json::Value>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct EnrollResponse {
+        /// The base32 encoded TOTP secret
+        pub secret: String,
+
+        /// Provisioning URI to be shown as QR code
+        pub uri: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct CodeRequest {
+        pub code: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ConfirmResponse {
+        pub recovery_codes: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct RegisterPasskeyResponse {
+        /// Options to create the credential with, as passed to `navigator.credentials.create()`
+        pub options: serde_json::Value,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ConfirmPasskeyRequest {
+        pub name: String,
+
+        /// The credential created by the authenticator
+        pub credential: serde_json::Value,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct PasskeyInfo {
+        pub id: String,
+        pub name: String,
+        pub created: DateTime<Utc>,
+
+        #[serde(default)]
+        pub last_used: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct PasskeysResponse {
+        pub passkeys: Vec<PasskeyInfo>,
+    }
+
+    /// Permissions granted to an API token.
+    #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Scope {
+        /// Upload new documents
+        Upload,
+
+        /// Read single documents and their fragments
+        Read,
+
+        /// List and search documents
+        Search,
+
+        /// Everything else, including all other scopes
+        Admin,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct TokenInfo {
+        pub id: String,
+        pub name: String,
+        pub created: DateTime<Utc>,
+        pub scopes: HashSet<Scope>,
+
+        #[serde(default)]
+        pub last_used: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct TokensResponse {
+        pub tokens: Vec<TokenInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct CreateTokenRequest {
+        pub name: String,
+        pub scopes: HashSet<Scope>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct CreateTokenResponse {
+        #[serde(flatten)]
+        pub info: TokenInfo,
+
+        /// The token - this is the only time it is handed out
+        pub token: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SessionInfo {
+        pub id: String,
+
+        pub created: DateTime<Utc>,
+        pub last_seen: DateTime<Utc>,
+
+        pub user_agent: Option<String>,
+        pub address: Option<String>,
+
+        /// Whether this is the session making the request
+        pub current: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SessionsResponse {
+        pub sessions: Vec<SessionInfo>,
     }
 }
 
 pub mod upload {
     use super::*;
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
     pub struct UploadResponse {
         #[serde(flatten)]
         pub doc: DocInfo,
     }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct UploadMailResponse {
+        /// The rendered mail body, if any, followed by the attachments
+        pub docs: Vec<DocInfo>,
+    }
+
+    /// Progress of a resumable upload
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ResumableInfo {
+        pub id: DocId,
+
+        /// Number of bytes received so far, where the next chunk must start
+        pub offset: u64,
+
+        /// Total size of the document in bytes
+        pub length: u64,
+
+        /// The uploaded document, set once all bytes have been received
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub doc: Option<DocInfo>,
+    }
+}
+
+pub mod scans {
+    use super::*;
+
+    /// Progress of a scan assembled page by page
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ScanInfo {
+        pub id: DocId,
+
+        /// Number of pages received so far
+        pub pages: usize,
+
+        /// The scanned document, set once the scan is complete
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub doc: Option<DocInfo>,
+    }
 }
 
 pub mod inbox {
+    use chrono::NaiveDate;
+
     use super::*;
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct GroupInfo {
+        /// The shared value of the grouped attribute, unset for the group of documents without value
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub key: Option<String>,
+
+        pub count: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "InboxListResponse")]
     pub struct ListResponse {
         pub count: u64,
         pub docs: Vec<DocInfo>,
+
+        /// All groups of the listing in listing order, only set if grouping was requested
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub groups: Vec<GroupInfo>,
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
     pub struct GetResponse {
         #[serde(flatten)]
         pub doc: DocInfo,
         pub suggestions: HashSet<Label>,
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
     pub struct ArchiveRequest {
         pub labels: HashSet<Label>,
-        pub properties: HashMap<String, String>,
+        pub properties: HashMap<String, PropertyValue>,
+
+        /// Users to share the document with
+        #[serde(default)]
+        pub shared: HashSet<String>,
+
+        /// Overrides the correspondent identified while juicing
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub correspondent: Option<String>,
+
+        /// The household member the document belongs to
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub belongs_to: Option<String>,
+
+        /// Overrides the due date taken from e-invoices
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub due: Option<NaiveDate>,
+
+        /// Overrides the date found in the text of the document
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub date: Option<NaiveDate>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SnoozeRequest {
+        /// Day the document re-surfaces in the inbox, unset to wake it right away
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub until: Option<NaiveDate>,
+    }
+
+    /// Snoozed documents which have re-surfaced in the inbox, as notified to the reminder webhook.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ResurfacedResponse {
+        pub docs: Vec<DocInfo>,
     }
 }
 
 pub mod archive {
     use super::*;
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
     pub struct BundleResponse {
         #[serde(flatten)]
         pub doc: DocInfo,
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
     pub struct SearchResponse {
         pub count: u64,
         pub docs: Vec<DocInfo>,
+
+        /// Excerpts of the text showing the matched terms by document, only set for documents matched by free text
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub snippets: HashMap<DocId, Vec<Snippet>>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct BrowseResponse {
+        pub count: u64,
+        pub docs: Vec<DocInfo>,
+
+        /// All groups of the listing in listing order, only set if grouping was requested
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub groups: Vec<super::inbox::GroupInfo>,
+    }
+}
+
+pub mod mail {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SendRequest {
+        /// Address the document is sent to
+        pub to: String,
+
+        /// Message preceding the summary of the document
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub message: Option<String>,
+    }
+}
+
+pub mod trash {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct TrashedDoc {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+        pub trashed: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "TrashListResponse")]
+    pub struct ListResponse {
+        pub count: u64,
+        pub docs: Vec<TrashedDoc>,
+    }
+}
+
+pub mod sync {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SyncDoc {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+
+        /// Revision of the metadata used to detect concurrent modifications
+        pub revision: String,
+
+        pub fragments: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "SyncListResponse")]
+    pub struct ListResponse {
+        pub docs: Vec<SyncDoc>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "SyncUpdateRequest")]
+    pub struct UpdateRequest {
+        /// The revision the update is based on
+        pub base: String,
+
+        pub metadata: Metadata,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct UpdateResponse {
+        pub revision: String,
+    }
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Location {
+        Inbox,
+        Archive,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ChangedDoc {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+
+        pub location: Location,
+
+        /// Whether a preview image is available for the document
+        pub preview: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "SyncChangesResponse")]
+    pub struct ChangesResponse {
+        /// Cursor to pass with the next request
+        pub cursor: u64,
+
+        /// Whether there are more changes after the returned cursor
+        pub more: bool,
+
+        pub updated: Vec<ChangedDoc>,
+        pub deleted: Vec<DocId>,
+    }
+}
+
+pub mod changes {
+    use std::collections::BTreeMap;
+
+    use chrono::{DateTime, Utc};
+    use serde_json::Value;
+
+    use super::*;
+    use super::sync::Location;
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ChangeKind {
+        /// The document has been added or restored from the trash
+        Created,
+        Updated,
+        Trashed,
+        Purged,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "ChangesChange")]
+    pub struct Change {
+        /// Cursor to pass to continue after this change
+        pub cursor: u64,
+        pub time: DateTime<Utc>,
+
+        pub id: DocId,
+        pub kind: ChangeKind,
+
+        /// The location of the document if changed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub location: Option<Location>,
+
+        /// The current metadata of created documents
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub metadata: Option<Metadata>,
+
+        /// The new values of updated metadata fields
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub fields: BTreeMap<String, Value>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "ChangesChangesResponse")]
+    pub struct ChangesResponse {
+        /// Cursor to pass with the next request
+        pub cursor: u64,
+
+        /// Whether there are more changes after the returned cursor
+        pub more: bool,
+
+        pub changes: Vec<Change>,
+    }
+}
+
+pub mod admin {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Bundles {
+        pub staging: u64,
+        pub inbox: u64,
+        pub archive: u64,
+        pub trash: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Jobs {
+        /// Documents currently being ingested
+        pub queued: u64,
+
+        /// Background jobs failed since startup
+        pub failed: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Disk {
+        /// Bytes used by the repository
+        pub used: u64,
+        pub available: u64,
+        pub total: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct IndexInfo {
+        pub last_update: Option<DateTime<Utc>>,
+
+        /// Cursor of the last repository change
+        pub journal_cursor: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ErrorInfo {
+        pub time: DateTime<Utc>,
+        pub source: String,
+        pub message: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct LoginFailureInfo {
+        pub time: DateTime<Utc>,
+        pub address: Option<String>,
+        pub user_agent: Option<String>,
+
+        /// Whether the attempt was rejected due to a lockout
+        pub locked: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct StatusResponse {
+        pub started: DateTime<Utc>,
+        pub bundles: Bundles,
+        pub jobs: Jobs,
+        pub disk: Disk,
+        pub index: IndexInfo,
+        pub last_backup: Option<DateTime<Utc>>,
+        pub errors: Vec<ErrorInfo>,
+        pub failed_logins: Vec<LoginFailureInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct TaskInfo {
+        /// One of `reindex`, `orphans`, `trash`, `backup` or `duplicates`
+        pub task: String,
+
+        /// Seconds between two runs
+        pub interval: u64,
+
+        pub last_run: Option<DateTime<Utc>>,
+
+        /// Milliseconds the last run took
+        pub duration: Option<u64>,
+
+        /// Summary of the last run, if it succeeded
+        pub result: Option<String>,
+
+        /// Error of the last run, if it failed
+        pub error: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct MaintenanceResponse {
+        pub tasks: Vec<TaskInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ReloadResponse {
+        /// The settings which have been reloaded, i.e. `juicer`
+        pub reloaded: Vec<String>,
+    }
+}
+
+pub mod queue {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct FailureInfo {
+        /// Category of the failure, i.e. `corrupted` or `ocr-failed`
+        pub kind: String,
+
+        /// Human readable description of the failure
+        pub message: String,
+
+        pub details: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct JobInfo {
+        pub id: DocId,
+        pub attempts: u32,
+        pub queued: DateTime<Utc>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub failure: Option<FailureInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "QueueListResponse")]
+    pub struct ListResponse {
+        pub jobs: Vec<JobInfo>,
+    }
+
+    /// Processing stage of an uploaded document.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    pub enum BundleStatus {
+        Queued,
+        Juicing,
+        Failed,
+        Inboxed,
+        Archived,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct StatusResponse {
+        pub id: DocId,
+        pub status: BundleStatus,
+
+        /// Number of failed juicing attempts
+        #[serde(default)]
+        pub attempts: u32,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub failure: Option<FailureInfo>,
+    }
+}
+
+pub mod domains {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct DomainInfo {
+        pub name: String,
+        pub labels: Vec<Label>,
+
+        /// Whether the domain is unlocked for the authenticated user
+        pub unlocked: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "DomainsListResponse")]
+    pub struct ListResponse {
+        pub domains: Vec<DomainInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct UnlockRequest {
+        pub passphrase: String,
+    }
+}
+
+pub mod history {
+    use std::collections::BTreeMap;
+
+    use chrono::{DateTime, Utc};
+    use serde_json::Value;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "HistoryChange")]
+    pub struct Change {
+        pub before: Value,
+        pub after: Value,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct HistoryEntry {
+        pub seq: u64,
+        pub time: DateTime<Utc>,
+
+        /// The kind of change, i.e. `archived` or `metadata-updated`
+        pub event: String,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub actor: Option<String>,
+
+        /// Changed metadata fields
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub diff: BTreeMap<String, Change>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct HistoryResponse {
+        pub entries: Vec<HistoryEntry>,
+    }
+}
+
+pub mod bulk {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[serde(tag = "op", rename_all = "kebab-case")]
+    pub enum Operation {
+        Archive,
+        Delete,
+        AddLabel { label: Label },
+        RemoveLabel { label: Label },
+        SetProperty { key: String, value: Option<PropertyValue> },
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct BulkRequest {
+        pub ids: Vec<DocId>,
+
+        #[serde(flatten)]
+        pub operation: Operation,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct DocResult {
+        pub id: DocId,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct BulkResponse {
+        /// Whether the operation has been applied - nothing is changed if any document fails validation
+        pub applied: bool,
+
+        pub results: Vec<DocResult>,
+
+        /// Handle to revert the operation, only present if it has been applied
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub undo: Option<super::undo::UndoInfo>,
+    }
+
+    /// Progress of a bulk operation streamed as newline delimited JSON.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[serde(tag = "event", rename_all = "kebab-case")]
+    pub enum Progress {
+        /// Validation failed for some documents and nothing has been changed
+        Rejected { results: Vec<DocResult> },
+
+        /// All documents passed validation and processing starts
+        Started { total: usize },
+
+        /// A single document has been processed
+        Processed {
+            id: DocId,
+
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            error: Option<String>,
+
+            done: usize,
+            total: usize,
+        },
+
+        /// All documents have been processed
+        Finished {
+            failed: usize,
+
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            undo: Option<super::undo::UndoInfo>,
+        },
+    }
+}
+
+pub mod undo {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct UndoInfo {
+        /// ID of the operation to pass to the undo endpoint
+        pub operation: String,
+
+        /// The point in time after which the operation can not be reverted anymore
+        pub expires: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct UndoResponse {
+        pub results: Vec<super::bulk::DocResult>,
     }
-}
\ No newline at end of file
+}
+
+pub mod revisions {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct RevisionInfo {
+        pub revision: usize,
+
+        /// The point in time the revision was replaced
+        pub replaced: DateTime<Utc>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub actor: Option<String>,
+
+        pub metadata: Metadata,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "RevisionsListResponse")]
+    pub struct ListResponse {
+        pub revisions: Vec<RevisionInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct RevertResponse {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+    }
+}
+
+pub mod labels {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct LabelInfo {
+        pub label: Label,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub color: Option<String>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub description: Option<String>,
+
+        /// Number of documents with the label or a label below it
+        pub count: usize,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "LabelsListResponse")]
+    pub struct ListResponse {
+        pub labels: Vec<LabelInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "LabelsUpdateRequest")]
+    pub struct UpdateRequest {
+        #[serde(default)]
+        pub color: Option<String>,
+
+        #[serde(default)]
+        pub description: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct RenameRequest {
+        pub to: Label,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct RenameResponse {
+        /// Number of documents changed
+        pub documents: usize,
+    }
+}
+
+pub mod persons {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "PersonsListResponse")]
+    pub struct ListResponse {
+        pub persons: Vec<Person>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "PersonsUpdateRequest")]
+    pub struct UpdateRequest {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub relationship: Option<String>,
+    }
+}
+
+pub mod correspondents {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "CorrespondentsListResponse")]
+    pub struct ListResponse {
+        pub correspondents: Vec<Correspondent>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "CorrespondentsUpdateRequest")]
+    pub struct UpdateRequest {
+        #[serde(default)]
+        pub aliases: Vec<String>,
+
+        #[serde(default)]
+        pub labels: HashSet<Label>,
+    }
+}
+
+pub mod checklists {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "ChecklistsListResponse")]
+    pub struct ListResponse {
+        pub checklists: Vec<Checklist>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "ChecklistsUpdateRequest")]
+    pub struct UpdateRequest {
+        pub items: Vec<ExpectedDocument>,
+
+        #[serde(default)]
+        pub date: Option<String>,
+    }
+
+    /// The documents of the year matching an expected document, which is missing if there are none.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ItemProgress {
+        pub name: String,
+        pub documents: Vec<DocId>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ProgressResponse {
+        pub name: String,
+        pub year: i32,
+
+        /// Number of expected documents which have arrived
+        pub arrived: usize,
+
+        /// Number of expected documents which are still missing
+        pub missing: usize,
+
+        pub items: Vec<ItemProgress>,
+    }
+}
+
+pub mod attestations {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    /// A signed manifest of all bundle checksums.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct AttestationInfo {
+        pub name: String,
+        pub created: DateTime<Utc>,
+
+        /// Hex encoded ed25519 public key the manifest is signed with
+        pub key: String,
+
+        /// Hex encoded SHA-256 checksum of the manifest
+        pub digest: String,
+
+        /// Hex encoded ed25519 signature of the manifest
+        pub signature: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "AttestationsListResponse")]
+    pub struct ListResponse {
+        pub attestations: Vec<AttestationInfo>,
+    }
+}
+
+pub mod quarantine {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    /// A bundle set aside for review, i.e. an orphaned or suspicious upload.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct QuarantinedDoc {
+        pub id: DocId,
+        pub quarantined: DateTime<Utc>,
+        pub reason: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "QuarantineListResponse")]
+    pub struct ListResponse {
+        pub count: u64,
+        pub docs: Vec<QuarantinedDoc>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct FragmentInfo {
+        pub name: String,
+        pub size: u64,
+
+        /// Hex encoded SHA-256 checksum, i.e. to look the sample up in malware databases
+        pub sha256: String,
+    }
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ReviewAction {
+        Inspected,
+        Downloaded,
+        Released,
+        Purged,
+    }
+
+    /// An action taken by an administrator reviewing a quarantined bundle.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Review {
+        pub time: DateTime<Utc>,
+        pub actor: String,
+        pub id: DocId,
+        pub action: ReviewAction,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct InspectResponse {
+        #[serde(flatten)]
+        pub doc: QuarantinedDoc,
+
+        pub fragments: Vec<FragmentInfo>,
+
+        /// All review actions taken on the bundle so far, including this inspection
+        pub reviews: Vec<Review>,
+    }
+}
+
+pub mod stats {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    /// Sum of the amounts in a single currency.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Total {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub currency: Option<String>,
+
+        pub amount: Decimal,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Group {
+        /// Documents without correspondent are grouped together
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub correspondent: Option<String>,
+
+        pub year: i32,
+
+        /// Number of documents
+        pub count: usize,
+
+        /// Sums of the amounts of the documents having one, per currency
+        pub totals: Vec<Total>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct StatsResponse {
+        pub groups: Vec<Group>,
+    }
+
+    /// Number of documents per day.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+    pub struct Day {
+        pub date: NaiveDate,
+
+        /// Number of documents uploaded on the day
+        pub uploaded: usize,
+
+        /// Number of documents dated on the day
+        pub dated: usize,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct CalendarResponse {
+        pub from: NaiveDate,
+        pub to: NaiveDate,
+
+        /// Days having documents in order, days without documents are left out
+        pub days: Vec<Day>,
+    }
+
+    /// Number of documents uploaded in a month.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+    pub struct Month {
+        pub year: i32,
+        pub month: u32,
+        pub count: usize,
+    }
+
+    /// Number of documents having a label or correspondent.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+    pub struct Count {
+        pub name: String,
+        pub count: usize,
+    }
+
+    /// Bytes used by the bundles per state.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Usage {
+        pub inbox: u64,
+        pub archive: u64,
+        pub trash: u64,
+        pub quarantine: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct DashboardResponse {
+        /// Number of archived documents
+        pub documents: usize,
+
+        /// Number of pages of the archived documents
+        pub pages: u64,
+
+        /// Archived documents per month of upload in order, months without documents are left out
+        pub months: Vec<Month>,
+
+        /// The most frequent labels of archived documents
+        pub labels: Vec<Count>,
+
+        /// The most frequent correspondents of archived documents
+        pub correspondents: Vec<Count>,
+
+        pub usage: Usage,
+
+        /// Share of ingested documents which ended up in the quarantine
+        pub failure_rate: f64,
+    }
+
+    /// Bytes used and the quota limiting them, unlimited if unset.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Quota {
+        pub used: u64,
+        pub limit: Option<u64>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct QuotaResponse {
+        /// Storage used by the documents of the repository
+        pub repository: Quota,
+
+        /// Storage used by the documents owned by the requesting user
+        pub user: Quota,
+    }
+}
+
+pub mod confidence {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "ConfidenceListResponse")]
+    pub struct ListResponse {
+        /// Confidence in percent the listed documents are below
+        pub threshold: u8,
+
+        /// Documents in the inbox and the archive, least confident first
+        pub docs: Vec<DocInfo>,
+    }
+}
+
+pub mod due {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct DueInfo {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+
+        /// Whether the due date has passed
+        pub overdue: bool,
+
+        /// The last day the document can be cancelled, if it is a contract
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub cancel_by: Option<NaiveDate>,
+
+        /// The last day of the warranty, if the document is a receipt with a warranty
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub warranty_expires: Option<NaiveDate>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "DueListResponse")]
+    pub struct ListResponse {
+        /// Documents ordered by due date
+        pub docs: Vec<DueInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "DueUpdateRequest")]
+    pub struct UpdateRequest {
+        /// The new due date, the due date is cleared if unset
+        #[serde(default)]
+        pub due: Option<NaiveDate>,
+    }
+}
+
+pub mod rules {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "RulesListResponse")]
+    pub struct ListResponse {
+        /// Rules in the order they are applied
+        pub rules: Vec<Rule>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "RulesUpdateRequest")]
+    pub struct UpdateRequest {
+        pub conditions: Vec<Condition>,
+
+        #[serde(default)]
+        pub labels: HashSet<Label>,
+
+        #[serde(default)]
+        pub title: Option<String>,
+
+        #[serde(default)]
+        pub correspondent: Option<String>,
+
+        #[serde(default)]
+        pub properties: HashMap<String, PropertyValue>,
+    }
+}
+
+pub mod suggestions {
+    use super::*;
+
+    /// A proposed value with the estimated probability of it being right.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Suggestion<T> {
+        pub value: T,
+        pub confidence: f64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SuggestionsResponse {
+        /// Proposed labels, most confident first
+        pub labels: Vec<Suggestion<Label>>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub correspondent: Option<Suggestion<String>>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub title: Option<Suggestion<String>>,
+    }
+}
+
+pub mod triage {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use super::suggestions::SuggestionsResponse;
+    use super::undo::UndoInfo;
+
+    /// A document to triage with the suggestions for archiving it.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct TriageItem {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+
+        pub suggestions: SuggestionsResponse,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct NextResponse {
+        /// Number of documents left to triage, including the served one
+        pub remaining: u64,
+
+        /// The document to triage next, unset if all documents have been served
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub next: Option<TriageItem>,
+    }
+
+    /// The decision on a triaged document.
+    ///
+    /// Values not given when archiving are taken from the document and the suggestions, so accepting all suggestions
+    /// requires no more than the operation.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[serde(tag = "op", rename_all = "kebab-case")]
+    pub enum Decision {
+        Archive {
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            title: Option<String>,
+
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            labels: Option<HashSet<Label>>,
+
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            correspondent: Option<String>,
+        },
+        Snooze { until: NaiveDate },
+        Delete,
+        Skip,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct DecisionResponse {
+        /// Reverts the decision, unset if there is nothing to revert
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub undo: Option<UndoInfo>,
+
+        #[serde(flatten)]
+        pub next: NextResponse,
+    }
+}
+
+pub mod resolve {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ResolveResponse {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+    }
+}
+
+pub mod filing {
+    use super::*;
+
+    /// Selects the documents to print labels for, either by ID or by a search query over the archive.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct SheetRequest {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub ids: Option<Vec<DocId>>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub query: Option<String>,
+
+        /// Number of label positions already used on the first sheet
+        #[serde(default)]
+        pub skip: usize,
+    }
+}
+
+pub mod relations {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct RelatedInfo {
+        pub kind: RelationKind,
+
+        #[serde(flatten)]
+        pub doc: DocInfo,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "RelationsListResponse")]
+    pub struct ListResponse {
+        /// Documents linked from the document
+        pub outgoing: Vec<RelatedInfo>,
+
+        /// Documents linking to the document
+        pub incoming: Vec<RelatedInfo>,
+    }
+}
+
+pub mod requests {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct RequestInfo {
+        pub id: String,
+
+        /// Token to be included in the link handed out to the recipient
+        pub token: String,
+
+        pub recipient: String,
+        pub message: String,
+        pub labels: HashSet<Label>,
+
+        pub created: DateTime<Utc>,
+        pub expires: Option<DateTime<Utc>>,
+
+        pub fulfilled: Option<DateTime<Utc>>,
+        pub document: Option<DocId>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "RequestsListResponse")]
+    pub struct ListResponse {
+        pub requests: Vec<RequestInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct CreateRequest {
+        pub recipient: String,
+        pub message: String,
+
+        #[serde(default)]
+        pub labels: HashSet<Label>,
+
+        #[serde(default)]
+        pub expires: Option<DateTime<Utc>>,
+    }
+
+    /// The request as shown to the recipient following the link.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct LinkResponse {
+        pub requester: String,
+        pub recipient: String,
+        pub message: String,
+    }
+}
+
+pub mod shares {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ShareInfo {
+        pub id: String,
+
+        pub doc: DocId,
+
+        /// The shared fragment, the document itself if unset
+        pub fragment: Option<String>,
+
+        pub created: DateTime<Utc>,
+        pub expires: DateTime<Utc>,
+
+        /// Path of the link, which can be fetched without authentication until it expires
+        pub link: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "SharesListResponse")]
+    pub struct ListResponse {
+        pub shares: Vec<ShareInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct CreateRequest {
+        pub doc: DocId,
+
+        #[serde(default)]
+        pub fragment: Option<String>,
+
+        /// Expiry of the link, a week from now if unset
+        #[serde(default)]
+        pub expires: Option<DateTime<Utc>>,
+    }
+}
+
+pub mod versions {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct VersionInfo {
+        pub version: u32,
+
+        /// Mount point of the version
+        pub path: String,
+
+        /// Deprecated versions are still served but will be removed eventually
+        pub deprecated: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct VersionsResponse {
+        /// The version new integrations should use
+        pub current: u32,
+
+        pub versions: Vec<VersionInfo>,
+    }
+}
+
+pub mod merge {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct MergeRequest {
+        /// Documents to merge in the order of their pages in the merged document
+        pub ids: Vec<DocId>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct MergeResponse {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+    }
+}
+
+pub mod attachments {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct AttachResponse {
+        /// Name of the fragment holding the attached file
+        pub fragment: String,
+
+        /// Name of the fragment holding the transcript of attached audio, if transcription is configured
+        pub transcript: Option<String>,
+
+        /// Name of the fragment holding the text extracted from other attachments, if they contain any
+        #[serde(default)]
+        pub text: Option<String>,
+    }
+}
+
+pub mod verify {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct Problem {
+        /// The kind of problem, i.e. `missing-fragment` or `page-count`
+        pub kind: String,
+        pub description: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct VerifyResponse {
+        pub id: DocId,
+        pub ok: bool,
+        pub problems: Vec<Problem>,
+    }
+}
+
+pub mod repositories {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "RepositoriesListResponse")]
+    pub struct ListResponse {
+        /// Names of the repositories served in addition to the default one
+        pub repositories: Vec<String>,
+    }
+}
+
+pub mod warranties {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct WarrantyInfo {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+
+        /// The last day of the warranty
+        pub expires: NaiveDate,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "WarrantiesListResponse")]
+    pub struct ListResponse {
+        /// Documents still under warranty ordered by the end of their warranty
+        pub docs: Vec<WarrantyInfo>,
+    }
+}
+
+pub mod fragments {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "FragmentsFragmentInfo")]
+    pub struct FragmentInfo {
+        pub name: String,
+
+        /// One of `document`, `preview`, `page`, `thumbnail`, `plaintext`, `metadata` or `other`
+        pub kind: String,
+
+        /// Size as stored, which differs from the served size for compressed or encrypted fragments
+        pub size: u64,
+
+        pub modified: DateTime<Utc>,
+
+        /// Hex encoded SHA-256 checksum of the fragment as stored
+        pub sha256: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "FragmentsListResponse")]
+    pub struct ListResponse {
+        pub fragments: Vec<FragmentInfo>,
+    }
+}
+
+pub mod pages {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct PageEdit {
+        /// The page of the current document, counting from one
+        pub page: u32,
+
+        /// Clockwise rotation in degrees, a multiple of 90
+        #[serde(default)]
+        pub rotate: i32,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct EditRequest {
+        /// Pages of the edited document in their new order, leaving out dropped pages
+        pub pages: Vec<PageEdit>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct EditResponse {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+
+        /// The prior version keeping the document as before the edit
+        pub version: usize,
+    }
+}
+
+pub mod documents {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "DocumentsVersionInfo")]
+    pub struct VersionInfo {
+        /// Sequence number of the version, starting at 1
+        pub version: usize,
+
+        /// The point in time the version was replaced
+        pub replaced: DateTime<Utc>,
+
+        /// The user who replaced the version
+        pub actor: Option<String>,
+
+        pub reason: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    #[schemars(rename = "DocumentsVersionsResponse")]
+    pub struct VersionsResponse {
+        /// Prior versions of the document, oldest first
+        pub versions: Vec<VersionInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+    pub struct ReplaceResponse {
+        #[serde(flatten)]
+        pub doc: DocInfo,
+
+        /// The prior version keeping the replaced document
+        pub version: usize,
+    }
+}
@@ -1,2 +1,8 @@
 pub mod api;
 pub mod model;
+pub mod query;
+
+#[cfg(feature = "grpc")]
+pub mod grpc {
+    tonic::include_proto!("adacta");
+}
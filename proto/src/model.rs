@@ -1,12 +1,18 @@
 use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::ffi::OsString;
+use std::fmt;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Error};
 use base58::{FromBase58, ToBase58};
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize, Serializer};
+use chrono::{DateTime, NaiveDate, Utc};
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
@@ -15,6 +21,24 @@ pub struct DocId(Uuid);
 impl DocId {
     pub fn random() -> Self { Self(Uuid::new_v4()) }
 
+    /// Creates a random ID prefixed by the current time, following UUIDv7.
+    ///
+    /// The IDs order by the time they have been created in, even in their encoded form as long as its length does
+    /// not change, which is not before the year 10000.
+    pub fn time_ordered() -> Self {
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time before epoch")
+            .as_millis() as u64;
+
+        // Take the random bits and the variant from a random ID
+        let mut bytes = *Uuid::new_v4().as_bytes();
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = (bytes[6] & 0x0f) | 0x70;
+
+        return Self(Uuid::from_bytes(bytes));
+    }
+
     pub fn to_base58(&self) -> String { self.0.as_bytes().to_base58() }
 }
 
@@ -57,16 +81,75 @@ impl<'de> Deserialize<'de> for DocId {
     }
 }
 
+impl JsonSchema for DocId {
+    fn schema_name() -> String { String::from("DocId") }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // Encoded as base58 string
+        return String::json_schema(gen);
+    }
+}
+
 impl std::fmt::Display for DocId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.to_base58())
     }
 }
 
+/// A document ID qualified by the namespace of the instance holding the document, like `home:5Kd3NBUAdUnhyzenEwVLy9`.
+///
+/// Namespaces address documents unambiguously if multiple instances are synced or viewed together. IDs without a
+/// namespace refer to the instance they are passed to.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct QualifiedId {
+    pub namespace: Option<String>,
+    pub id: DocId,
+}
+
+impl QualifiedId {
+    /// Checks if the name is usable as namespace, which must not be confused with a document ID.
+    pub fn is_valid_namespace(name: &str) -> bool {
+        return !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && DocId::from_str(name).is_err();
+    }
+}
+
+impl FromStr for QualifiedId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s.rfind(':') {
+            Some(i) if QualifiedId::is_valid_namespace(&s[..i]) => Ok(Self {
+                namespace: Some(s[..i].to_string()),
+                id: DocId::from_str(&s[i + 1..])?,
+            }),
+            Some(_) => Err(anyhow!("Invalid namespace")),
+            None => Ok(Self { namespace: None, id: DocId::from_str(s)? }),
+        };
+    }
+}
+
+impl std::fmt::Display for QualifiedId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match &self.namespace {
+            Some(namespace) => write!(f, "{}:{}", namespace, self.id),
+            None => write!(f, "{}", self.id),
+        };
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Kind {
     Document,
     Preview,
+
+    /// Preview of a single page, counting from one
+    Page(u32),
+
+    /// Small preview of the first page
+    Thumbnail,
+
     Plaintext,
     Metadata,
     Other { name: OsString },
@@ -82,6 +165,7 @@ impl<S: Into<String>> From<S> for Kind {
         return match fragment.as_str() {
             "document" => Kind::Document,
             "preview" => Kind::Preview,
+            "thumbnail" => Kind::Thumbnail,
             "plaintext" => Kind::Plaintext,
             "metadata" => Kind::Metadata,
             s => Kind::other(s),
@@ -89,9 +173,47 @@ impl<S: Into<String>> From<S> for Kind {
     }
 }
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq, Deserialize, Serialize)]
+/// A label attached to documents.
+///
+/// Labels form a hierarchy by separating their segments with `/`, i.e. `finance/tax/2023` is below `finance/tax`.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize, JsonSchema)]
 pub struct Label(String);
 
+impl Label {
+    pub const SEPARATOR: char = '/';
+
+    /// Checks that the label consists of non-empty segments.
+    pub fn is_valid(&self) -> bool {
+        return self.0.split(Self::SEPARATOR).all(|segment| !segment.trim().is_empty());
+    }
+
+    /// The label directly above this one in the hierarchy, if any.
+    pub fn parent(&self) -> Option<Label> {
+        return self.0.rfind(Self::SEPARATOR)
+            .map(|i| Label(self.0[..i].to_string()));
+    }
+
+    /// All labels above this one in the hierarchy, nearest first.
+    pub fn ancestors(&self) -> impl Iterator<Item=Label> {
+        return std::iter::successors(self.parent(), Label::parent);
+    }
+
+    /// Checks if the label is the other label or below it in the hierarchy.
+    pub fn is_within(&self, other: &Label) -> bool {
+        return self.0 == other.0
+            || (self.0.starts_with(&other.0) && self.0[other.0.len()..].starts_with(Self::SEPARATOR));
+    }
+
+    /// Moves the label from below `from` to below `to`, if it is within `from`.
+    pub fn rebase(&self, from: &Label, to: &Label) -> Option<Label> {
+        if !self.is_within(from) {
+            return None;
+        }
+
+        return Some(Label(format!("{}{}", to.0, &self.0[from.0.len()..])));
+    }
+}
+
 impl std::fmt::Display for Label {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.0)
@@ -110,7 +232,7 @@ impl Borrow<str> for Label {
     fn borrow(&self) -> &str { &self.0 }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, JsonSchema)]
 pub struct Metadata {
     pub uploaded: DateTime<Utc>,
     pub archived: Option<DateTime<Utc>>,
@@ -120,22 +242,786 @@ pub struct Metadata {
 
     pub labels: HashSet<Label>,
 
-    pub properties: HashMap<String, String>,
+    pub properties: HashMap<String, PropertyValue>,
+
+    /// The user who uploaded the document, documents without owner are visible to everyone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
+    /// Other users the document is shared with
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub shared: HashSet<String>,
+
+    /// Links to other documents
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub relations: HashSet<Relation>,
+
+    /// Name of the correspondent who sent the document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correspondent: Option<String>,
+
+    /// Name of the household member the document belongs to, i.e. whose passport or payslip it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub belongs_to: Option<String>,
+
+    /// Day the document has been issued, i.e. the date of an invoice or a letter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+
+    /// Deadline for acting on the document, i.e. paying a bill
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
+
+    /// Day the document is hidden from the inbox until, i.e. to deal with it after a vacation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snoozed: Option<NaiveDate>,
+
+    /// Name of the file the document was ingested from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+
+    /// The encryption domain the fragments of the document are encrypted for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+
+    /// Language of the document detected from its text, as ISO 639-3 code like `deu`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// OCR languages requested on upload, like `deu+eng`, the languages of the juicer apply if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<String>,
+
+    /// Mean confidence of the OCR in percent, only set for OCRed documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+
+    /// Mean confidence of the OCR per page in percent, unset for pages without recognized words
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub page_confidence: Vec<Option<u8>>,
+
+    /// Name of the juicer of a fallback chain which extracted the document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub juicer: Option<String>,
+
+    /// Metadata proposed by the rules while juicing, which must be accepted before archiving
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposal: Option<Proposal>,
+}
+
+/// Metadata proposed for a document in the inbox, which is not applied until confirmed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Proposal {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Labels added to the existing ones
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub labels: HashSet<Label>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correspondent: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+
+    /// Properties set in addition to the existing ones
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, PropertyValue>,
+}
+
+impl Proposal {
+    pub fn is_empty(&self) -> bool {
+        return self.title.is_none()
+            && self.labels.is_empty()
+            && self.correspondent.is_none()
+            && self.date.is_none()
+            && self.properties.is_empty();
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DocInfo {
     pub id: DocId,
+
+    /// The namespace of the instance holding the document, if configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
     pub metadata: Metadata,
 }
 
+impl DocInfo {
+    /// The ID of the document qualified by its namespace.
+    pub fn qualified_id(&self) -> QualifiedId {
+        return QualifiedId { namespace: self.namespace.clone(), id: self.id };
+    }
+}
+
+/// A short excerpt of the text of a document showing why it matched a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Snippet {
+    pub text: String,
+
+    /// Page the excerpt is taken from, unset for text not belonging to a page like transcripts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+
+    /// The matched terms within the excerpt
+    pub highlights: Vec<Highlight>,
+}
+
+/// A matched term within a snippet, given as offsets in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
 impl<D, M> From<(D, M)> for DocInfo
     where D: Into<DocId>,
           M: Into<Metadata> {
     fn from((id, metadata): (D, M)) -> Self {
         return Self {
             id: id.into(),
+            namespace: None,
             metadata: metadata.into(),
         };
     }
-}
\ No newline at end of file
+}
+
+/// The kind of a link from one document to another.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelationKind {
+    /// The document is an invoice for the target, i.e. an order or a contract
+    InvoiceFor,
+
+    /// The document is a reply to the target
+    ReplyTo,
+
+    /// The document replaces the target
+    Supersedes,
+
+    /// The document was attached to the target, i.e. a mail
+    AttachmentOf,
+
+    /// Any other relation
+    Related,
+}
+
+impl FromStr for RelationKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "invoice-for" => Ok(Self::InvoiceFor),
+            "reply-to" => Ok(Self::ReplyTo),
+            "supersedes" => Ok(Self::Supersedes),
+            "attachment-of" => Ok(Self::AttachmentOf),
+            "related" => Ok(Self::Related),
+            s => Err(anyhow!("Unknown relation: {}", s)),
+        };
+    }
+}
+
+impl fmt::Display for RelationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(match self {
+            Self::InvoiceFor => "invoice-for",
+            Self::ReplyTo => "reply-to",
+            Self::Supersedes => "supersedes",
+            Self::AttachmentOf => "attachment-of",
+            Self::Related => "related",
+        });
+    }
+}
+
+/// A rule setting metadata of documents which match all its conditions when they land in the inbox.
+///
+/// The title and string property values can refer to named capture groups of regex conditions as `{name}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Rule {
+    pub name: String,
+
+    pub conditions: Vec<Condition>,
+
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub labels: HashSet<Label>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correspondent: Option<String>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub properties: HashMap<String, PropertyValue>,
+}
+
+/// A pattern matched against a field of a document.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "match", rename_all = "kebab-case")]
+pub enum Condition {
+    /// Matches if the field contains the keyword, ignoring case
+    Keyword { field: Field, keyword: String },
+
+    /// Matches if the regular expression matches anywhere in the field
+    Regex { field: Field, pattern: String },
+}
+
+/// The fields of a document rules can match against.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Field {
+    /// The extracted plaintext
+    Text,
+
+    /// The name of the file the document was ingested from
+    Filename,
+}
+
+/// A sender of documents, i.e. a company or an authority.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Correspondent {
+    /// The unique name the correspondent is referred to by documents
+    pub name: String,
+
+    /// Other names the correspondent appears as in document texts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+
+    /// Labels added to documents from the correspondent
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub labels: HashSet<Label>,
+}
+
+/// A member of the household documents can belong to.
+///
+/// Persons are independent of the users logging in, as not every member of the household has an account.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Person {
+    /// The unique name the person is referred to by documents
+    pub name: String,
+
+    /// Relationship to the household, like `spouse` or `child`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relationship: Option<String>,
+}
+
+/// A document expected to arrive once a year, i.e. the annual tax statement of the bank.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ExpectedDocument {
+    pub name: String,
+
+    /// Search query matching the document
+    pub query: String,
+}
+
+/// A list of documents expected to arrive every year, i.e. all the papers needed for the tax return.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Checklist {
+    /// The unique name of the checklist
+    pub name: String,
+
+    pub items: Vec<ExpectedDocument>,
+
+    /// Date property the year of a document is taken from, falls back to the upload date
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+
+impl Checklist {
+    /// Checks if the name is usable as path segment and all items have distinct names.
+    pub fn is_valid(&self) -> bool {
+        let names = self.items.iter()
+            .map(|item| item.name.as_str())
+            .collect::<HashSet<_>>();
+
+        return !self.name.trim().is_empty()
+            && self.name.trim() == self.name
+            && !self.name.contains('/')
+            && self.items.iter().all(|item| !item.name.trim().is_empty())
+            && names.len() == self.items.len();
+    }
+}
+
+impl Person {
+    /// Checks if the name is usable as path segment and query value.
+    pub fn is_valid(&self) -> bool {
+        return !self.name.trim().is_empty()
+            && self.name.trim() == self.name
+            && !self.name.contains('/');
+    }
+}
+
+impl Correspondent {
+    /// Checks if the name is usable as path segment and query value.
+    pub fn is_valid(&self) -> bool {
+        return !self.name.trim().is_empty()
+            && self.name.trim() == self.name
+            && !self.name.contains('/');
+    }
+
+    /// Finds the earliest position of the name or any alias in the text.
+    ///
+    /// Names are matched case-insensitive and only as whole words.
+    pub fn find_in(&self, text: &str) -> Option<usize> {
+        let text = text.to_lowercase();
+
+        return std::iter::once(&self.name)
+            .chain(self.aliases.iter())
+            .filter(|name| !name.trim().is_empty())
+            .filter_map(|name| find_word(&text, &name.to_lowercase()))
+            .min();
+    }
+}
+
+/// Finds the first occurrence of the needle in the haystack which is not part of a longer word.
+fn find_word(haystack: &str, needle: &str) -> Option<usize> {
+    let is_boundary = |c: Option<char>| match c {
+        Some(c) => !c.is_alphanumeric(),
+        None => true,
+    };
+
+    return haystack.match_indices(needle)
+        .map(|(i, _)| i)
+        .find(|&i| is_boundary(haystack[..i].chars().next_back())
+            && is_boundary(haystack[i + needle.len()..].chars().next()));
+}
+
+/// A typed link to another document.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Relation {
+    pub kind: RelationKind,
+    pub target: DocId,
+}
+
+/// A decimal number with an exact representation, i.e. for monetary amounts.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Maximum number of fractional digits
+    const MAX_SCALE: u32 = 18;
+
+    fn rescale(&self, scale: u32) -> i128 {
+        return self.mantissa * 10i128.pow(scale - self.scale);
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        return self.mantissa as f64 / 10f64.powi(self.scale as i32);
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self { Self { mantissa: value.into(), scale: 0 } }
+}
+
+impl std::ops::Add for Decimal {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let scale = self.scale.max(other.scale);
+        return Self { mantissa: self.rescale(scale) + other.rescale(scale), scale };
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        return self.rescale(scale).cmp(&other.rescale(scale));
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Decimal {}
+
+impl FromStr for Decimal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+
+        let (integer, fraction) = match digits.find('.') {
+            Some(i) => (&digits[..i], &digits[i + 1..]),
+            None => (digits, ""),
+        };
+
+        if integer.is_empty()
+            || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+            || fraction.len() > Self::MAX_SCALE as usize {
+            return Err(anyhow!("Invalid decimal: {}", s));
+        }
+
+        let mantissa = format!("{}{}", integer, fraction).parse::<i128>()
+            .map_err(|_| anyhow!("Invalid decimal: {}", s))?;
+
+        return Ok(Self {
+            mantissa: if negative { -mantissa } else { mantissa },
+            scale: fraction.len() as u32,
+        });
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+
+        let divisor = 10i128.pow(self.scale);
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let mantissa = self.mantissa.abs();
+
+        return write!(f, "{}{}.{:0width$}", sign, mantissa / divisor, mantissa % divisor, width = self.scale as usize);
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        return Self::from_str(&s).map_err(serde::de::Error::custom);
+    }
+}
+
+impl JsonSchema for Decimal {
+    fn schema_name() -> String { String::from("Decimal") }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // Encoded as string to keep the precision, i.e. `"12.50"`
+        return String::json_schema(gen);
+    }
+}
+
+/// A typed value of a metadata property.
+///
+/// Booleans, integers and strings are stored as plain JSON values. Decimals, dates and locations are stored as objects
+/// to keep them apart from strings, i.e. `{"amount": "12.50", "currency": "EUR"}`, `{"date": "2023-01-01"}` and
+/// `{"lat": "48.137154", "lon": "11.576124"}`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawPropertyValue", into = "RawPropertyValue")]
+pub enum PropertyValue {
+    Boolean(bool),
+    Integer(i64),
+    Decimal { amount: Decimal, currency: Option<String> },
+    Date(NaiveDate),
+    Location { lat: Decimal, lon: Decimal },
+    String(String),
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum RawPropertyValue {
+    Boolean(bool),
+    Integer(i64),
+    Decimal {
+        amount: Decimal,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        currency: Option<String>,
+    },
+    Date { date: NaiveDate },
+    Location { lat: Decimal, lon: Decimal },
+    String(String),
+}
+
+impl JsonSchema for PropertyValue {
+    fn schema_name() -> String { String::from("PropertyValue") }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        return RawPropertyValue::json_schema(gen);
+    }
+}
+
+impl TryFrom<RawPropertyValue> for PropertyValue {
+    type Error = Error;
+
+    fn try_from(raw: RawPropertyValue) -> Result<Self, Self::Error> {
+        return Ok(match raw {
+            RawPropertyValue::Boolean(value) => Self::Boolean(value),
+            RawPropertyValue::Integer(value) => Self::Integer(value),
+            RawPropertyValue::Decimal { amount, currency } => Self::amount(amount, currency)?,
+            RawPropertyValue::Date { date } => Self::Date(date),
+            RawPropertyValue::Location { lat, lon } => Self::location(lat, lon)?,
+            RawPropertyValue::String(value) => Self::String(value),
+        });
+    }
+}
+
+impl From<PropertyValue> for RawPropertyValue {
+    fn from(value: PropertyValue) -> Self {
+        return match value {
+            PropertyValue::Boolean(value) => Self::Boolean(value),
+            PropertyValue::Integer(value) => Self::Integer(value),
+            PropertyValue::Decimal { amount, currency } => Self::Decimal { amount, currency },
+            PropertyValue::Date(date) => Self::Date { date },
+            PropertyValue::Location { lat, lon } => Self::Location { lat, lon },
+            PropertyValue::String(value) => Self::String(value),
+        };
+    }
+}
+
+impl PropertyValue {
+    /// Creates a decimal value with an optional currency given as ISO 4217 code.
+    pub fn amount(amount: Decimal, currency: Option<String>) -> Result<Self, Error> {
+        if let Some(currency) = &currency {
+            if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+                return Err(anyhow!("Invalid currency code: {}", currency));
+            }
+        }
+
+        return Ok(Self::Decimal { amount, currency });
+    }
+
+    /// Creates a location from WGS 84 coordinates in degrees.
+    pub fn location(lat: Decimal, lon: Decimal) -> Result<Self, Error> {
+        if lat < Decimal::from(-90) || lat > Decimal::from(90) || lon < Decimal::from(-180) || lon > Decimal::from(180) {
+            return Err(anyhow!("Invalid coordinates: {},{}", lat, lon));
+        }
+
+        return Ok(Self::Location { lat, lon });
+    }
+
+    /// Parses coordinates given as `<lat>,<lon>` in degrees.
+    pub fn parse_location(s: &str) -> Result<Self, Error> {
+        let (lat, lon) = match s.find(',') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => return Err(anyhow!("Invalid coordinates: {}", s)),
+        };
+
+        return Self::location(Decimal::from_str(lat.trim())?, Decimal::from_str(lon.trim())?);
+    }
+
+    /// Returns the distance to another location in meters, or `None` if any of the values is not a location.
+    pub fn distance(&self, other: &Self) -> Option<f64> {
+        /// Mean radius of the earth in meters
+        const EARTH_RADIUS: f64 = 6_371_000.0;
+
+        return match (self, other) {
+            (Self::Location { lat: lat1, lon: lon1 }, Self::Location { lat: lat2, lon: lon2 }) => {
+                let (lat1, lat2) = (lat1.to_f64().to_radians(), lat2.to_f64().to_radians());
+                let (dlat, dlon) = (lat2 - lat1, (lon2.to_f64() - lon1.to_f64()).to_radians());
+
+                // Haversine formula
+                let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+                Some(2.0 * EARTH_RADIUS * a.sqrt().asin())
+            }
+            _ => None,
+        };
+    }
+
+    /// Infers the type of a value given as text, i.e. on the command line or in a query.
+    ///
+    /// Decimals can be followed by a currency code like `12.50 EUR`. Locations are given as `geo:<lat>,<lon>`. Text
+    /// which does not match any other type is taken as a string.
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "true" => return Self::Boolean(true),
+            "false" => return Self::Boolean(false),
+            _ => {}
+        }
+
+        if let Ok(value) = s.parse::<i64>() {
+            return Self::Integer(value);
+        }
+
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Self::Date(date);
+        }
+
+        if let Some(location) = s.strip_prefix("geo:").and_then(|coordinates| Self::parse_location(coordinates).ok()) {
+            return location;
+        }
+
+        let (amount, currency) = match s.rfind(' ') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        if let Ok(amount) = Decimal::from_str(amount) {
+            if let Ok(value) = Self::amount(amount, currency.map(String::from)) {
+                return value;
+            }
+        }
+
+        return Self::String(s.to_string());
+    }
+
+    /// Compares two values of compatible types.
+    ///
+    /// Integers and decimals are compared numerically, amounts in different currencies are not comparable. Strings are
+    /// compared case-insensitive.
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        return match (self, other) {
+            (Self::Boolean(a), Self::Boolean(b)) => Some(a.cmp(b)),
+            (Self::Integer(a), Self::Integer(b)) => Some(a.cmp(b)),
+            (Self::Integer(a), Self::Decimal { amount: b, .. }) => Some(Decimal::from(*a).cmp(b)),
+            (Self::Decimal { amount: a, .. }, Self::Integer(b)) => Some(a.cmp(&Decimal::from(*b))),
+            (Self::Decimal { amount: a, currency: ca }, Self::Decimal { amount: b, currency: cb }) => {
+                match (ca, cb) {
+                    (Some(ca), Some(cb)) if ca != cb => None,
+                    _ => Some(a.cmp(b)),
+                }
+            }
+            (Self::Date(a), Self::Date(b)) => Some(a.cmp(b)),
+            (Self::Location { .. }, Self::Location { .. }) if self == other => Some(Ordering::Equal),
+            (Self::String(a), Self::String(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::Boolean(value) => write!(f, "{}", value),
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Decimal { amount, currency: Some(currency) } => write!(f, "{} {}", amount, currency),
+            Self::Decimal { amount, currency: None } => write!(f, "{}", amount),
+            Self::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Self::Location { lat, lon } => write!(f, "geo:{},{}", lat, lon),
+            Self::String(value) => f.write_str(value),
+        };
+    }
+}
+
+impl From<String> for PropertyValue {
+    fn from(value: String) -> Self { Self::String(value) }
+}
+
+impl From<&str> for PropertyValue {
+    fn from(value: &str) -> Self { Self::String(value.to_string()) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_label_hierarchy() {
+        let label = Label::from("finance/tax/2023");
+
+        assert_eq!(label.ancestors().collect::<Vec<_>>(), vec![Label::from("finance/tax"), Label::from("finance")]);
+        assert!(label.is_within(&Label::from("finance")));
+        assert!(label.is_within(&label));
+        assert!(!label.is_within(&Label::from("fin")));
+        assert_eq!(label.rebase(&Label::from("finance/tax"), &Label::from("taxes")), Some(Label::from("taxes/2023")));
+        assert_eq!(label.rebase(&Label::from("private"), &Label::from("taxes")), None);
+
+        assert!(!Label::from("finance//2023").is_valid());
+        assert!(!Label::from("").is_valid());
+    }
+
+    #[test]
+    fn test_qualified_id() {
+        let id = DocId::random();
+
+        let qualified = QualifiedId::from_str(&format!("home:{}", id)).unwrap();
+        assert_eq!(qualified, QualifiedId { namespace: Some(String::from("home")), id });
+        assert_eq!(qualified.to_string(), format!("home:{}", id));
+
+        assert_eq!(QualifiedId::from_str(&id.to_string()).unwrap(), QualifiedId { namespace: None, id });
+        assert!(QualifiedId::from_str(&format!("ho/me:{}", id)).is_err());
+        assert!(QualifiedId::from_str("home:invalid").is_err());
+    }
+
+    #[test]
+    fn test_time_ordered_id() {
+        let first = DocId::time_ordered();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = DocId::time_ordered();
+
+        assert!(first < second);
+        assert!(first.to_string() < second.to_string());
+        assert_eq!(first.0.get_version_num(), 7);
+        assert_eq!(DocId::from_str(&first.to_string()).unwrap(), first);
+    }
+
+    #[test]
+    fn test_correspondent_find_in() {
+        let correspondent = Correspondent {
+            name: String::from("ACME"),
+            aliases: vec![String::from("Acme Corporation")],
+            labels: HashSet::new(),
+        };
+
+        assert_eq!(correspondent.find_in("Invoice from acme corporation, ACME"), Some(13));
+        assert_eq!(correspondent.find_in("ACMEville"), None);
+        assert!(!Correspondent { name: String::from("a/b"), ..correspondent.clone() }.is_valid());
+        assert!(correspondent.is_valid());
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!((Decimal::from_str("12.50").unwrap() + Decimal::from_str("0.125").unwrap()).to_string(), "12.625");
+        assert_eq!(Decimal::from_str("12.50").unwrap(), Decimal::from_str("12.5").unwrap());
+        assert!(Decimal::from_str("-1.05").unwrap() < Decimal::from(0));
+        assert_eq!(Decimal::from_str("-1.05").unwrap().to_string(), "-1.05");
+        assert!(Decimal::from_str("1.2.3").is_err());
+        assert!(Decimal::from_str(".5").is_err());
+    }
+
+    #[test]
+    fn test_property_serde() {
+        let values = vec![
+            (PropertyValue::Boolean(true), r#"true"#),
+            (PropertyValue::Integer(42), r#"42"#),
+            (PropertyValue::parse("12.50 EUR"), r#"{"amount":"12.50","currency":"EUR"}"#),
+            (PropertyValue::parse("2023-01-31"), r#"{"date":"2023-01-31"}"#),
+            (PropertyValue::parse("geo:48.137154,11.576124"), r#"{"lat":"48.137154","lon":"11.576124"}"#),
+            (PropertyValue::String(String::from("2023-01-31")), r#""2023-01-31""#),
+        ];
+
+        for (value, json) in values {
+            assert_eq!(serde_json::to_string(&value).unwrap(), json);
+            assert_eq!(serde_json::from_str::<PropertyValue>(json).unwrap(), value);
+        }
+
+        assert!(serde_json::from_str::<PropertyValue>(r#"{"amount":"12.50","currency":"euro"}"#).is_err());
+        assert!(serde_json::from_str::<PropertyValue>(r#"{"amount":"twelve"}"#).is_err());
+        assert!(serde_json::from_str::<PropertyValue>(r#"{"lat":"91","lon":"0"}"#).is_err());
+    }
+
+    #[test]
+    fn test_location() {
+        let munich = PropertyValue::parse("geo:48.137154,11.576124");
+        let berlin = PropertyValue::parse("geo:52.520008,13.404954");
+
+        assert_eq!(munich.to_string(), "geo:48.137154,11.576124");
+        assert_eq!(PropertyValue::parse("geo:north"), PropertyValue::from("geo:north"));
+
+        let distance = munich.distance(&berlin).unwrap();
+        assert!((distance - 504_000.0).abs() < 1_000.0, "distance: {}", distance);
+        assert_eq!(munich.distance(&munich), Some(0.0));
+        assert_eq!(munich.distance(&PropertyValue::Integer(1)), None);
+    }
+
+    #[test]
+    fn test_property_compare() {
+        assert_eq!(PropertyValue::parse("100").compare(&PropertyValue::parse("99.99")), Some(Ordering::Greater));
+        assert_eq!(PropertyValue::parse("1 EUR").compare(&PropertyValue::parse("1 USD")), None);
+        assert_eq!(PropertyValue::parse("2023-01-01").compare(&PropertyValue::parse("2022-12-31")), Some(Ordering::Greater));
+    }
+}
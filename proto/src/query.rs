@@ -0,0 +1,426 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use chrono::NaiveDate;
+
+use crate::model::{Label, PropertyValue};
+
+/// A structured search query.
+///
+/// Queries consist of whitespace separated terms which are all required to match. Each term can be negated by a
+/// leading `-`. Supported terms are:
+/// * `word` and `"quoted phrase"` for free text,
+/// * `label:<label>` to require a label or any label below it in the hierarchy,
+/// * `title:<text>` to search in the title only,
+/// * `correspondent:<name>` to require the document to be from a correspondent,
+/// * `belongs_to:<name>` to require the document to belong to a member of the household,
+/// * `property.<key>:<value>` to require a property value with an optional comparison before the value,
+/// * `near.<key>:<lat>,<lon>,<radius>` to require a location property within a radius given in `m` or `km`,
+/// * `uploaded:<date>`, `archived:<date>`, `date:<date>` and `due:<date>` with an optional comparison (`<`, `<=`, `>`, `>=`)
+///   before the date, where `date` is the day the document has been issued,
+/// * `confidence:<percent>` with an optional comparison to find OCRed documents by the confidence of the OCR.
+///
+/// Values containing whitespace can be quoted like `property.vendor:"acme corp"`. Property values are typed by their
+/// text, i.e. `property.total:>100` compares numerically and `property.due:<2023-01-01` compares dates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Text(String),
+    Phrase(String),
+    Label(Label),
+    Title(String),
+    Correspondent(String),
+    BelongsTo(String),
+    Property { key: String, comparison: Comparison, value: PropertyValue },
+    Near { key: String, center: PropertyValue, radius: u64 },
+    Uploaded(Comparison, NaiveDate),
+    Archived(Comparison, NaiveDate),
+    Dated(Comparison, NaiveDate),
+    Due(Comparison, NaiveDate),
+    Confidence(Comparison, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn matches<T: Ord>(&self, lhs: &T, rhs: &T) -> bool {
+        return match self {
+            Self::Eq => lhs == rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        };
+    }
+
+    fn split(s: &str) -> (Self, &str) {
+        if let Some(s) = s.strip_prefix(">=") { return (Self::Ge, s); }
+        if let Some(s) = s.strip_prefix("<=") { return (Self::Le, s); }
+        if let Some(s) = s.strip_prefix('>') { return (Self::Gt, s); }
+        if let Some(s) = s.strip_prefix('<') { return (Self::Lt, s); }
+        return (Self::Eq, s);
+    }
+}
+
+/// Keys listings can be sorted by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortKey {
+    Uploaded,
+    Title,
+    Pages,
+    Date,
+    Due,
+    Property(String),
+}
+
+/// Sort order of a listing, parsed from the key name with an optional leading `-` for descending order.
+///
+/// Properties are referred to as `property.<key>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Query {
+    pub fn is_empty(&self) -> bool { self.terms.is_empty() }
+
+    /// Adds a required term to the query.
+    pub fn and(mut self, filter: Filter) -> Self {
+        self.terms.push(Term { negated: false, filter });
+        return self;
+    }
+
+    /// Returns all free text terms and phrases which are not negated.
+    pub fn text(&self) -> impl Iterator<Item=&Filter> {
+        return self.terms.iter()
+            .filter(|term| !term.negated)
+            .map(|term| &term.filter)
+            .filter(|filter| matches!(filter, Filter::Text(_) | Filter::Phrase(_)));
+    }
+}
+
+/// Splits the query into tokens while keeping quoted parts together.
+fn tokenize(s: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                token.push(c);
+                quoted = !quoted;
+            }
+
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+
+            c => token.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(anyhow!("Unterminated quote in query"));
+    }
+
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    return Ok(tokens);
+}
+
+fn unquote(s: &str) -> &str {
+    return s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s);
+}
+
+/// Parses a distance in meters given with a unit of `m` or `km`.
+fn parse_radius(s: &str) -> Result<u64> {
+    let (value, factor) = match s.strip_suffix("km") {
+        Some(value) => (value, 1000),
+        None => match s.strip_suffix('m') {
+            Some(value) => (value, 1),
+            None => return Err(anyhow!("Missing unit for radius in query: {}", s)),
+        },
+    };
+
+    return value.parse::<u64>()
+        .map(|value| value * factor)
+        .map_err(|_| anyhow!("Invalid radius in query: {}", s));
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('"') {
+            return Ok(Self::Phrase(unquote(s).to_string()));
+        }
+
+        let (key, value) = match s.find(':') {
+            Some(i) => (&s[..i], unquote(&s[i + 1..])),
+            None => return Ok(Self::Text(s.to_string())),
+        };
+
+        if value.is_empty() {
+            return Err(anyhow!("Missing value for '{}' in query", key));
+        }
+
+        let date = |value: &str| {
+            let (comparison, date) = Comparison::split(value);
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| anyhow!("Invalid date in query: {}", date))?;
+            return Result::<_, Error>::Ok((comparison, date));
+        };
+
+        return match key {
+            "label" => Ok(Self::Label(Label::from(value))),
+            "title" => Ok(Self::Title(value.to_string())),
+            "correspondent" => Ok(Self::Correspondent(value.to_string())),
+            "belongs_to" => Ok(Self::BelongsTo(value.to_string())),
+            "uploaded" => date(value).map(|(c, d)| Self::Uploaded(c, d)),
+            "archived" => date(value).map(|(c, d)| Self::Archived(c, d)),
+            "date" => date(value).map(|(c, d)| Self::Dated(c, d)),
+            "due" => date(value).map(|(c, d)| Self::Due(c, d)),
+            "confidence" => {
+                let (comparison, confidence) = Comparison::split(value);
+                let confidence = confidence.parse()
+                    .map_err(|_| anyhow!("Invalid confidence in query: {}", confidence))?;
+                Ok(Self::Confidence(comparison, confidence))
+            }
+            key => match key.strip_prefix("property.") {
+                Some(property) if !property.is_empty() => {
+                    let (comparison, value) = Comparison::split(value);
+                    Ok(Self::Property {
+                        key: property.to_string(),
+                        comparison,
+                        value: PropertyValue::parse(unquote(value)),
+                    })
+                }
+                _ => match key.strip_prefix("near.") {
+                    Some(property) if !property.is_empty() => {
+                        let (center, radius) = match value.rfind(',') {
+                            Some(i) => (&value[..i], &value[i + 1..]),
+                            None => return Err(anyhow!("Missing radius for '{}' in query", key)),
+                        };
+
+                        Ok(Self::Near {
+                            key: property.to_string(),
+                            center: PropertyValue::parse_location(center)?,
+                            radius: parse_radius(radius)?,
+                        })
+                    }
+                    _ => Ok(Self::Text(s.to_string())),
+                },
+            },
+        };
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let terms = tokenize(s)?.into_iter()
+            .map(|token| -> Result<Term> {
+                return match token.strip_prefix('-') {
+                    Some(token) if !token.is_empty() => Ok(Term { negated: true, filter: token.parse()? }),
+                    _ => Ok(Term { negated: false, filter: token.parse()? }),
+                };
+            })
+            .collect::<Result<_>>()?;
+
+        return Ok(Self { terms });
+    }
+}
+
+impl FromStr for Sort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (descending, key) = match s.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, s),
+        };
+
+        let key = match key {
+            "uploaded" => SortKey::Uploaded,
+            "title" => SortKey::Title,
+            "pages" => SortKey::Pages,
+            "date" => SortKey::Date,
+            "due" => SortKey::Due,
+            key => match key.strip_prefix("property.") {
+                Some(property) if !property.is_empty() => SortKey::Property(property.to_string()),
+                _ => return Err(anyhow!("Unknown sort key: {}", key)),
+            },
+        };
+
+        return Ok(Self { key, descending });
+    }
+}
+
+fn quote(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+    if s.contains(char::is_whitespace) {
+        return write!(f, "\"{}\"", s);
+    } else {
+        return f.write_str(s);
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        return f.write_str(match self {
+            Self::Eq => "",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        });
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Phrase(phrase) => write!(f, "\"{}\"", phrase),
+            Self::Label(label) => { f.write_str("label:")?; quote(f, &label.to_string()) }
+            Self::Title(title) => { f.write_str("title:")?; quote(f, title) }
+            Self::Correspondent(name) => { f.write_str("correspondent:")?; quote(f, name) }
+            Self::BelongsTo(name) => { f.write_str("belongs_to:")?; quote(f, name) }
+            Self::Property { key, comparison, value } => { write!(f, "property.{}:{}", key, comparison)?; quote(f, &value.to_string()) }
+            Self::Near { key, center, radius } => {
+                let center = center.to_string();
+                match radius % 1000 {
+                    0 => write!(f, "near.{}:{},{}km", key, center.trim_start_matches("geo:"), radius / 1000),
+                    _ => write!(f, "near.{}:{},{}m", key, center.trim_start_matches("geo:"), radius),
+                }
+            }
+            Self::Uploaded(comparison, date) => write!(f, "uploaded:{}{}", comparison, date.format("%Y-%m-%d")),
+            Self::Archived(comparison, date) => write!(f, "archived:{}{}", comparison, date.format("%Y-%m-%d")),
+            Self::Dated(comparison, date) => write!(f, "date:{}{}", comparison, date.format("%Y-%m-%d")),
+            Self::Due(comparison, date) => write!(f, "due:{}{}", comparison, date.format("%Y-%m-%d")),
+            Self::Confidence(comparison, confidence) => write!(f, "confidence:{}{}", comparison, confidence),
+        };
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+
+            if term.negated {
+                f.write_str("-")?;
+            }
+
+            write!(f, "{}", term.filter)?;
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let query = Query::from_str(r#"label:invoice uploaded:>2023-01-01 property.vendor:acme "total amount" -label:paid foo"#).unwrap();
+
+        assert_eq!(query.terms, vec![
+            Term { negated: false, filter: Filter::Label(Label::from("invoice")) },
+            Term { negated: false, filter: Filter::Uploaded(Comparison::Gt, NaiveDate::from_ymd(2023, 1, 1)) },
+            Term { negated: false, filter: Filter::Property { key: "vendor".to_string(), comparison: Comparison::Eq, value: PropertyValue::from("acme") } },
+            Term { negated: false, filter: Filter::Phrase("total amount".to_string()) },
+            Term { negated: true, filter: Filter::Label(Label::from("paid")) },
+            Term { negated: false, filter: Filter::Text("foo".to_string()) },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_quoted_value() {
+        let query = Query::from_str(r#"property.vendor:"acme corp""#).unwrap();
+
+        assert_eq!(query.terms, vec![
+            Term { negated: false, filter: Filter::Property { key: "vendor".to_string(), comparison: Comparison::Eq, value: PropertyValue::from("acme corp") } },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Query::from_str(r#""unterminated"#).is_err());
+        assert!(Query::from_str("uploaded:yesterday").is_err());
+        assert!(Query::from_str("date:2021-13-01").is_err());
+        assert!(Query::from_str("label:").is_err());
+        assert!(Query::from_str("confidence:low").is_err());
+    }
+
+    #[test]
+    fn test_parse_sort() {
+        assert_eq!(Sort::from_str("title").unwrap(), Sort { key: SortKey::Title, descending: false });
+        assert_eq!(Sort::from_str("-uploaded").unwrap(), Sort { key: SortKey::Uploaded, descending: true });
+        assert_eq!(Sort::from_str("due").unwrap(), Sort { key: SortKey::Due, descending: false });
+        assert_eq!(Sort::from_str("-date").unwrap(), Sort { key: SortKey::Date, descending: true });
+        assert_eq!(Sort::from_str("-property.total").unwrap(), Sort { key: SortKey::Property("total".to_string()), descending: true });
+        assert!(Sort::from_str("size").is_err());
+        assert!(Sort::from_str("property.").is_err());
+    }
+
+    #[test]
+    fn test_parse_property_comparison() {
+        let query = Query::from_str(r#"property.total:>=100.50 property.due:<2023-01-01 property.paid:true"#).unwrap();
+
+        assert_eq!(query.terms, vec![
+            Term { negated: false, filter: Filter::Property { key: "total".to_string(), comparison: Comparison::Ge, value: PropertyValue::parse("100.50") } },
+            Term { negated: false, filter: Filter::Property { key: "due".to_string(), comparison: Comparison::Lt, value: PropertyValue::Date(NaiveDate::from_ymd(2023, 1, 1)) } },
+            Term { negated: false, filter: Filter::Property { key: "paid".to_string(), comparison: Comparison::Eq, value: PropertyValue::Boolean(true) } },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_near() {
+        let query = Query::from_str(r#"near.photo.location:48.137154,11.576124,25km"#).unwrap();
+
+        assert_eq!(query.terms, vec![
+            Term { negated: false, filter: Filter::Near { key: "photo.location".to_string(), center: PropertyValue::parse("geo:48.137154,11.576124"), radius: 25_000 } },
+        ]);
+
+        assert!(Query::from_str("near.photo.location:48.137154,11.576124").is_err());
+        assert!(Query::from_str("near.photo.location:48.137154,11.576124,25").is_err());
+        assert!(Query::from_str("near.photo.location:91,0,1km").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let s = r#"label:invoice belongs_to:alice archived:<=2020-12-31 date:>=2020-06-01 due:<2021-01-15 confidence:<60 property.vendor:"acme corp" near.location:48.1,11.5,500m -"total amount""#;
+        assert_eq!(Query::from_str(s).unwrap().to_string(), s);
+    }
+}
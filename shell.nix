@@ -14,9 +14,11 @@ in pkgs.mkShell {
     rustChannel.cargo
     pkg-config
     openssl
+    protobuf
     nodejs
   ];
 
   RUST_BACKTRACE = 1;
+  PROTOC = "${pkgs.protobuf}/bin/protoc";
   RUST_SRC = "${rustChannel.rust-src}/lib/rustlib/src/rust";
 }